@@ -0,0 +1,116 @@
+/* Helpers for picking "--name=value" and "--flag" options
+ * out of the extra command-args that modes get passed
+ */
+use std::str::FromStr;
+
+/* Check whether a "--flag" style option was supplied */
+pub fn has_flag(args: &[String], flag: &str) -> bool
+{
+	args.iter().any(|arg| arg == flag)
+}
+
+/* Get the value supplied for a "--name=value" style option
+ * > returns the value for the last occurrence of the option (so later ones override earlier ones)
+ */
+pub fn get_option<'a>(args: &'a [String], name: &str) -> Option<&'a str>
+{
+	let prefix = format!("{}=", name);
+	args.iter()
+	    .rev()
+	    .find(|arg| arg.starts_with(&prefix))
+	    .map(|arg| &arg[prefix.len() ..])
+}
+
+/* Get the values supplied for a "--name=value" style option that can be given several times
+ * > returns the values in the order given
+ */
+pub fn get_options<'a>(args: &'a [String], name: &str) -> Vec<&'a str>
+{
+	let prefix = format!("{}=", name);
+	args.iter()
+	    .filter(|arg| arg.starts_with(&prefix))
+	    .map(|arg| &arg[prefix.len() ..])
+	    .collect()
+}
+
+/* Get and parse the value supplied for a "--name=value" style option
+ * > returns Ok(None) if the option wasn't supplied, or an error message if it couldn't be parsed
+ */
+pub fn parse_option<T: FromStr>(args: &[String], name: &str) -> Result<Option<T>, String>
+{
+	match get_option(args, name) {
+		Some(value) => {
+			match value.parse::<T>() {
+				Ok(x)  => Ok(Some(x)),
+				Err(_) => Err(format!("Invalid value for {0} - '{1}'", name, value))
+			}
+		},
+		None => Ok(None)
+	}
+}
+
+/* Parse a list of (1-based) positions, e.g. "3", "3,7", or "2-5,9"
+ * > returns the positions in the order given (with ranges expanded), or an error message
+ */
+pub fn parse_positions(s: &str) -> Result<Vec<usize>, String>
+{
+	let mut positions = Vec::new();
+	for part in s.split(',').map(|p| p.trim()) {
+		let parse = |x: &str| x.trim().parse::<usize>().map_err(|_| format!("Invalid position - '{}'", part));
+
+		match part.find('-') {
+			Some(dash) => {
+				let (start, end) = (parse(&part[.. dash])?, parse(&part[dash + 1 ..])?);
+				if start > end {
+					return Err(format!("Invalid range - '{}' (start is after the end)", part));
+				}
+				positions.extend(start ..= end);
+			},
+			None => positions.push(parse(part)?)
+		}
+	}
+	Ok(positions)
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn make_args(args: &[&str]) -> Vec<String>
+	{
+		args.iter().map(|s| s.to_string()).collect()
+	}
+
+	#[test]
+	fn test_flags_and_options()
+	{
+		let args = make_args(&["--json", "--max-drift=30", "extra", "--max-drift=45"]);
+
+		assert!(has_flag(&args, "--json"));
+		assert!(!has_flag(&args, "--quiet"));
+
+		assert_eq!(Some("45"), get_option(&args, "--max-drift"));
+		assert_eq!(None,       get_option(&args, "--max"));
+		assert_eq!(vec!["30", "45"], get_options(&args, "--max-drift"));
+		assert!(get_options(&args, "--max").is_empty());
+
+		assert_eq!(Ok(Some(45)), parse_option::<i64>(&args, "--max-drift"));
+		assert_eq!(Ok(None),     parse_option::<i64>(&args, "--other"));
+		assert!(parse_option::<i64>(&make_args(&["--max-drift=abc"]), "--max-drift").is_err());
+	}
+
+	#[test]
+	fn test_parse_positions()
+	{
+		assert_eq!(Ok(vec![3]), parse_positions("3"));
+		assert_eq!(Ok(vec![2, 3, 4, 5, 9]), parse_positions("2-5,9"));
+		assert_eq!(Ok(vec![7, 1]), parse_positions("7, 1"));
+
+		assert_eq!(Err("Invalid position - 'x'".to_string()), parse_positions("1,x"));
+		assert_eq!(Err("Invalid range - '5-2' (start is after the end)".to_string()), parse_positions("5-2"));
+		assert!(parse_positions("").is_err());
+	}
+}
@@ -0,0 +1,533 @@
+/* Find tracks whose files have exactly the same contents
+ *
+ * Reading every byte of a large collection takes a long time, when most files can be told apart
+ * without doing so. So candidates get whittled down in stages, with only the files surviving
+ * each stage going on to the next (more expensive) one:
+ *   1) Size - Files can only be duplicates of others with exactly the same size
+ *   2) Partial hash - Hash of the first and last PARTIAL_BLOCK_SIZE bytes (most files differ in those)
+ *   3) Full hash - Hash of the whole file
+ * A record is kept of how many files were eliminated at each stage, and how much actually got read.
+ */
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use size_estimate::format_size;
+use terminal_text::sanitize;
+
+/* Number of bytes at each end of a file used for the partial hash */
+pub const PARTIAL_BLOCK_SIZE: usize = 64 * 1024;
+
+/* Size of the chunks that whole files get read in (so they never need to be in memory all at once) */
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/* *************************************************** */
+/* Content Providers */
+
+/* Access to the contents of the items being compared (e.g. files on disk) */
+pub trait ContentProvider {
+	/* Number of items */
+	fn len(&self) -> usize;
+
+	/* Are there no items at all? */
+	fn is_empty(&self) -> bool
+	{
+		self.len() == 0
+	}
+
+	/* Size of the given item (in bytes) */
+	fn size(&self, item: usize) -> io::Result<u64>;
+
+	/* Read "len" bytes from the given item, starting at "offset" */
+	fn read_range(&self, item: usize, offset: u64, len: usize) -> io::Result<Vec<u8>>;
+
+	/* Pass the whole item to the callback, a chunk at a time */
+	fn read_all(&self, item: usize, callback: &mut dyn FnMut(&[u8])) -> io::Result<()>;
+}
+
+/* Contents of files on disk */
+pub struct FileContents {
+	pub paths: Vec<PathBuf>,
+}
+
+impl ContentProvider for FileContents {
+	fn len(&self) -> usize
+	{
+		self.paths.len()
+	}
+
+	fn size(&self, item: usize) -> io::Result<u64>
+	{
+		fs::metadata(&self.paths[item]).map(|m| m.len())
+	}
+
+	fn read_range(&self, item: usize, offset: u64, len: usize) -> io::Result<Vec<u8>>
+	{
+		let mut f = File::open(&self.paths[item])?;
+		f.seek(SeekFrom::Start(offset))?;
+
+		let mut buf = Vec::with_capacity(len);
+		f.take(len as u64).read_to_end(&mut buf)?;
+		Ok(buf)
+	}
+
+	fn read_all(&self, item: usize, callback: &mut dyn FnMut(&[u8])) -> io::Result<()>
+	{
+		let mut f = File::open(&self.paths[item])?;
+		let mut buf = vec![0u8; READ_CHUNK_SIZE];
+		loop {
+			match f.read(&mut buf) {
+				Ok(0)  => return Ok(()),
+				Ok(n)  => callback(&buf[.. n]),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e)
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Hashing */
+
+/* 128-bit hash of some content
+ * NOTE: This isn't a cryptographic hash - it only needs to tell apart files that aren't deliberately made to collide
+ */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct ContentDigest(u64, u64);
+
+/* Hashes content fed to it a piece at a time */
+struct ContentHasher {
+	/* Two independent hashes (the second gets a different starting state) */
+	hashers: (DefaultHasher, DefaultHasher),
+	/* Number of bytes hashed */
+	bytes: u64,
+}
+
+impl ContentHasher {
+	fn new() -> ContentHasher
+	{
+		let mut second = DefaultHasher::new();
+		second.write(b"xspf_tools:content_dupes");
+		ContentHasher {
+			hashers: (DefaultHasher::new(), second),
+			bytes: 0,
+		}
+	}
+
+	fn update(&mut self, bytes: &[u8])
+	{
+		self.hashers.0.write(bytes);
+		self.hashers.1.write(bytes);
+		self.bytes += bytes.len() as u64;
+	}
+
+	fn finish(&self) -> ContentDigest
+	{
+		ContentDigest(self.hashers.0.finish(), self.hashers.1.finish())
+	}
+}
+
+/* *************************************************** */
+/* Results */
+
+/* Group of items that have the same contents */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct DupeGroup {
+	/* Size of each of the items (in bytes) */
+	pub size: u64,
+	/* Indices of the items (in order) */
+	pub items: Vec<usize>,
+	/* Were the whole contents compared? (If not, they're only probably duplicates - see DupeOptions.fast) */
+	pub confirmed: bool,
+}
+
+/* How many items were eliminated at each stage, and how much had to be read to do so */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct StageCounts {
+	/* Number of items considered */
+	pub items: usize,
+	/* Items eliminated for having a unique size */
+	pub eliminated_by_size: usize,
+	/* Items eliminated by the partial hash */
+	pub eliminated_by_partial_hash: usize,
+	/* Items eliminated by the full hash */
+	pub eliminated_by_full_hash: usize,
+
+	/* Total size of all the items */
+	pub bytes_total: u64,
+	/* Number of bytes actually read */
+	pub bytes_read: u64,
+}
+
+/* Item that couldn't be read */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct UnreadableItem {
+	pub item: usize,
+	pub error: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct DupesReport {
+	/* Groups of duplicates (in order of their first item) */
+	pub groups: Vec<DupeGroup>,
+	pub counts: StageCounts,
+	/* Items left out because they couldn't be read (at whichever stage that was noticed) */
+	pub unreadable: Vec<UnreadableItem>,
+}
+
+/* Settings for find_dupes() */
+#[derive(Debug)]
+#[derive(Default)]
+pub struct DupeOptions {
+	/* Stop after the partial hash (so items larger than the partially hashed blocks are only probably duplicates) */
+	pub fast: bool,
+}
+
+/* *************************************************** */
+/* Staging */
+
+/* Hash of the first and last PARTIAL_BLOCK_SIZE bytes of the item (which is the whole item, if it's small enough)
+ * > returns the hash, and the number of bytes read
+ */
+fn partial_hash<P: ContentProvider>(contents: &P, item: usize, size: u64) -> io::Result<(ContentDigest, u64)>
+{
+	let mut hasher = ContentHasher::new();
+	if size <= 2 * PARTIAL_BLOCK_SIZE as u64 {
+		hasher.update(&contents.read_range(item, 0, size as usize)?);
+	}
+	else {
+		hasher.update(&contents.read_range(item, 0, PARTIAL_BLOCK_SIZE)?);
+		hasher.update(&contents.read_range(item, size - PARTIAL_BLOCK_SIZE as u64, PARTIAL_BLOCK_SIZE)?);
+	}
+	Ok((hasher.finish(), hasher.bytes))
+}
+
+/* Hash of the whole item
+ * > returns the hash, and the number of bytes read
+ */
+fn full_hash<P: ContentProvider>(contents: &P, item: usize) -> io::Result<(ContentDigest, u64)>
+{
+	let mut hasher = ContentHasher::new();
+	contents.read_all(item, &mut |chunk| hasher.update(chunk))?;
+	Ok((hasher.finish(), hasher.bytes))
+}
+
+/* Size and hash of the whole file at the given path (e.g. for checking that a copy matches the original)
+ * NOTE: The hash is only meant for comparing files during a single run (it may differ between builds)
+ */
+pub fn file_fingerprint(path: &Path) -> io::Result<(u64, String)>
+{
+	let contents = FileContents { paths: vec![path.to_path_buf()] };
+	let (digest, bytes) = full_hash(&contents, 0)?;
+	Ok((bytes, format!("{0:016x}{1:016x}", digest.0, digest.1)))
+}
+
+/* Split each group of candidates up by the given key, dropping any that end up on their own
+ * - key: Works out the key for an item (or fails if it couldn't be read)
+ * > returns the groups that still have more than one item, and the number of items eliminated
+ */
+fn refine_groups<K: Ord>(groups: Vec<Vec<usize>>,
+                         unreadable: &mut Vec<UnreadableItem>,
+                         key: &mut dyn FnMut(usize) -> io::Result<K>)
+	-> (Vec<Vec<usize>>, usize)
+{
+	let mut refined: Vec<Vec<usize>> = Vec::new();
+	let mut eliminated = 0;
+
+	for group in groups.into_iter() {
+		let mut by_key: BTreeMap<K, Vec<usize>> = BTreeMap::new();
+		for item in group.into_iter() {
+			match key(item) {
+				Ok(k)  => by_key.entry(k).or_default().push(item),
+				Err(e) => unreadable.push(UnreadableItem { item: item, error: e.to_string() })
+			}
+		}
+
+		for (_, items) in by_key.into_iter() {
+			if items.len() > 1 {
+				refined.push(items);
+			}
+			else {
+				eliminated += items.len();
+			}
+		}
+	}
+	(refined, eliminated)
+}
+
+/* Find the groups of items with the same contents */
+pub fn find_dupes<P: ContentProvider>(contents: &P, options: &DupeOptions) -> DupesReport
+{
+	let mut counts = StageCounts::default();
+	let mut unreadable: Vec<UnreadableItem> = Vec::new();
+	let mut sizes: Vec<u64> = vec![0; contents.len()];
+
+	/* 1) Size */
+	let all_items = vec![(0 .. contents.len()).collect::<Vec<usize>>()];
+	let (groups, eliminated) = refine_groups(all_items, &mut unreadable, &mut |item| {
+		let size = contents.size(item)?;
+		sizes[item] = size;
+		Ok(size)
+	});
+	counts.items = contents.len() - unreadable.len();
+	counts.bytes_total = sizes.iter().sum();
+	counts.eliminated_by_size = eliminated;
+
+	/* 2) Partial hash */
+	let mut bytes_read = 0;
+	let (groups, eliminated) = refine_groups(groups, &mut unreadable, &mut |item| {
+		let (digest, read) = partial_hash(contents, item, sizes[item])?;
+		bytes_read += read;
+		Ok(digest)
+	});
+	counts.eliminated_by_partial_hash = eliminated;
+
+	/* 3) Full hash - Only needed for items that weren't entirely covered by the partial hash */
+	let partially_hashed = |group: &Vec<usize>| sizes[group[0]] > 2 * PARTIAL_BLOCK_SIZE as u64;
+	let (complete, partial): (Vec<Vec<usize>>, Vec<Vec<usize>>) = groups.into_iter().partition(|g| !partially_hashed(g));
+
+	let mut dupe_groups: Vec<DupeGroup> = Vec::new();
+	let mut add_groups = |groups: Vec<Vec<usize>>, confirmed: bool| {
+		for items in groups.into_iter() {
+			dupe_groups.push(DupeGroup { size: sizes[items[0]], items: items, confirmed: confirmed });
+		}
+	};
+	add_groups(complete, true);
+
+	if options.fast {
+		add_groups(partial, false);
+	}
+	else {
+		let (groups, eliminated) = refine_groups(partial, &mut unreadable, &mut |item| {
+			let (digest, read) = full_hash(contents, item)?;
+			bytes_read += read;
+			Ok(digest)
+		});
+		counts.eliminated_by_full_hash = eliminated;
+		add_groups(groups, true);
+	}
+	counts.bytes_read = bytes_read;
+
+	dupe_groups.sort_by_key(|g| g.items[0]);
+	unreadable.sort_by_key(|u| u.item);
+	DupesReport { groups: dupe_groups, counts: counts, unreadable: unreadable }
+}
+
+/* *************************************************** */
+/* Output */
+
+impl DupesReport {
+	/* Write report as text
+	 * - names: Name to show for each item (e.g. the track's path)
+	 */
+	pub fn write_text(&self, out: &mut dyn Write, names: &[String]) -> io::Result<()>
+	{
+		if self.groups.is_empty() {
+			writeln!(out, "No duplicate tracks found")?;
+		}
+		else {
+			writeln!(out, "Duplicate tracks ({} groups):", self.groups.len())?;
+			for group in self.groups.iter() {
+				let label = if group.confirmed { "duplicates" } else { "probable duplicates" };
+				writeln!(out)?;
+				writeln!(out, "  {0} {1}, {2} each:", group.items.len(), label, format_size(group.size))?;
+				for &item in group.items.iter() {
+					writeln!(out, "    track {0:3} | {1}", item + 1, sanitize(&names[item]))?;
+				}
+			}
+		}
+
+		if !self.unreadable.is_empty() {
+			writeln!(out)?;
+			writeln!(out, "Couldn't read {} tracks (left out):", self.unreadable.len())?;
+			for u in self.unreadable.iter() {
+				writeln!(out, "    track {0:3} | {1} - {2}", u.item + 1, sanitize(&names[u.item]), u.error)?;
+			}
+		}
+
+		let c = &self.counts;
+		let percent_read = if c.bytes_total > 0 { c.bytes_read as f64 * 100.0 / c.bytes_total as f64 } else { 0.0 };
+		writeln!(out)?;
+		writeln!(out, "Compared {0} tracks ({1}):", c.items, format_size(c.bytes_total))?;
+		writeln!(out, "   {} ruled out by size", c.eliminated_by_size)?;
+		writeln!(out, "   {0} ruled out by their first/last {1} KiB", c.eliminated_by_partial_hash, PARTIAL_BLOCK_SIZE / 1024)?;
+		writeln!(out, "   {} ruled out by their full contents", c.eliminated_by_full_hash)?;
+		writeln!(out, "   Read {0} ({1:.1}% of the total)", format_size(c.bytes_read), percent_read)?;
+		Ok(())
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+
+	/* Contents held in memory, keeping track of how much gets read */
+	struct MemoryContents {
+		items: Vec<Vec<u8>>,
+		bytes_read: Cell<u64>,
+	}
+
+	impl MemoryContents {
+		fn new(items: Vec<Vec<u8>>) -> MemoryContents
+		{
+			MemoryContents { items: items, bytes_read: Cell::new(0) }
+		}
+	}
+
+	impl ContentProvider for MemoryContents {
+		fn len(&self) -> usize
+		{
+			self.items.len()
+		}
+
+		fn size(&self, item: usize) -> io::Result<u64>
+		{
+			Ok(self.items[item].len() as u64)
+		}
+
+		fn read_range(&self, item: usize, offset: u64, len: usize) -> io::Result<Vec<u8>>
+		{
+			let bytes = &self.items[item][offset as usize ..];
+			let bytes = &bytes[.. len.min(bytes.len())];
+			self.bytes_read.set(self.bytes_read.get() + bytes.len() as u64);
+			Ok(bytes.to_vec())
+		}
+
+		fn read_all(&self, item: usize, callback: &mut dyn FnMut(&[u8])) -> io::Result<()>
+		{
+			for chunk in self.items[item].chunks(1000) {
+				self.bytes_read.set(self.bytes_read.get() + chunk.len() as u64);
+				callback(chunk);
+			}
+			Ok(())
+		}
+	}
+
+	/* Content that's different for each seed (and position) */
+	fn content(seed: u8, len: usize) -> Vec<u8>
+	{
+		(0 .. len).map(|i| (i % 251) as u8 ^ seed).collect()
+	}
+
+	/* Copy of the content with one byte changed (somewhere in the middle, so that the partial hash misses it) */
+	fn altered_in_middle(content: &[u8]) -> Vec<u8>
+	{
+		let mut altered = content.to_vec();
+		let middle = altered.len() / 2;
+		altered[middle] ^= 0xFF;
+		altered
+	}
+
+	fn group_items(report: &DupesReport) -> Vec<Vec<usize>>
+	{
+		report.groups.iter().map(|g| g.items.clone()).collect()
+	}
+
+	const LARGE: usize = 3 * PARTIAL_BLOCK_SIZE;
+
+	#[test]
+	fn test_true_duplicates_found()
+	{
+		let large = content(1, LARGE);
+		let small = content(2, 5000);
+		let contents = MemoryContents::new(vec![large.clone(), small.clone(), content(3, 5000), large.clone(),
+		                                        small.clone(), Vec::new(), large.clone(), Vec::new()]);
+
+		let report = find_dupes(&contents, &DupeOptions::default());
+		assert_eq!(vec![vec![0, 3, 6], vec![1, 4], vec![5, 7]], group_items(&report));
+		assert!(report.groups.iter().all(|g| g.confirmed));
+		assert_eq!(LARGE as u64, report.groups[0].size);
+
+		/* Even when they're only probable duplicates, true duplicates are never missed */
+		let report = find_dupes(&contents, &DupeOptions { fast: true });
+		assert_eq!(vec![vec![0, 3, 6], vec![1, 4], vec![5, 7]], group_items(&report));
+		assert_eq!(vec![false, true, true], report.groups.iter().map(|g| g.confirmed).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_differing_files_eliminated_early()
+	{
+		let large = content(1, LARGE);
+		let contents = MemoryContents::new(vec![
+			large.clone(),
+			content(1, LARGE + 1),             /* Different size */
+			content(2, LARGE),                 /* Same size, different start */
+			altered_in_middle(&large),         /* Same size, same ends */
+			content(3, 100),                   /* Different size */
+			large.clone(),
+		]);
+
+		let report = find_dupes(&contents, &DupeOptions::default());
+		assert_eq!(vec![vec![0, 5]], group_items(&report));
+		assert_eq!(StageCounts {
+			items: 6,
+			eliminated_by_size: 2,
+			eliminated_by_partial_hash: 1,
+			eliminated_by_full_hash: 1,
+			bytes_total: (5 * LARGE + 1 + 100) as u64,
+			/* Ends of the 4 same-size items, then the whole of the 3 that got through */
+			bytes_read: (4 * 2 * PARTIAL_BLOCK_SIZE + 3 * LARGE) as u64,
+		}, report.counts);
+
+		/* Stopping after the partial hash can't tell the altered copy apart */
+		let report = find_dupes(&contents, &DupeOptions { fast: true });
+		assert_eq!(vec![vec![0, 3, 5]], group_items(&report));
+		assert!(!report.groups[0].confirmed);
+		assert_eq!(0, report.counts.eliminated_by_full_hash);
+	}
+
+	#[test]
+	fn test_bytes_read_accounting()
+	{
+		let large = content(1, LARGE);
+		let cases = vec![
+			vec![large.clone(), content(2, LARGE), large.clone()],
+			vec![content(4, 70_000), content(4, 70_000), content(5, 200)],   /* Small enough to be hashed whole */
+			vec![content(1, 10), content(2, 20)],                            /* Nothing to read */
+		];
+		for items in cases.into_iter() {
+			for &fast in [false, true].iter() {
+				let contents = MemoryContents::new(items.clone());
+				let report = find_dupes(&contents, &DupeOptions { fast: fast });
+				assert_eq!(contents.bytes_read.get(), report.counts.bytes_read);
+				assert_eq!(items.iter().map(|i| i.len() as u64).sum::<u64>(), report.counts.bytes_total);
+			}
+		}
+
+		/* Items hashed whole by the partial hash don't get read again */
+		let contents = MemoryContents::new(vec![content(4, 70_000), content(4, 70_000)]);
+		let report = find_dupes(&contents, &DupeOptions::default());
+		assert_eq!(140_000, report.counts.bytes_read);
+		assert!(report.groups[0].confirmed);
+	}
+
+	#[test]
+	fn test_write_text()
+	{
+		let contents = MemoryContents::new(vec![content(1, LARGE), content(2, 10), content(1, LARGE)]);
+		let names: Vec<String> = vec!["a.mp3".to_string(), "b.mp3".to_string(), "c.mp3".to_string()];
+
+		let mut out: Vec<u8> = Vec::new();
+		find_dupes(&contents, &DupeOptions { fast: true }).write_text(&mut out, &names).unwrap();
+		let text = String::from_utf8(out).unwrap();
+		assert!(text.contains("2 probable duplicates"), "{}", text);
+		assert!(text.contains("track   3 | c.mp3"), "{}", text);
+		assert!(text.contains("1 ruled out by size"), "{}", text);
+	}
+}
@@ -0,0 +1,299 @@
+/* Conversion presets for each type of track
+ *
+ * Different types of tracks warrant different settings (e.g. voice memos don't need 320 kbps stereo,
+ * while violin takes do). So, "--preset=Voice:mono-64k,ViolinLayering:hq" picks one of the built-in
+ * presets for each type, whose ffmpeg args get added to the ones the convert mode would use anyway.
+ * Types without a preset just get converted using those.
+ */
+use std::fmt;
+
+use ffmpeg_command::{self, ArgConflict};
+use track_name_info::{TrackExtension, TrackType};
+
+/* *************************************************** */
+/* Presets */
+
+/* A named set of ffmpeg settings */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Preset {
+	pub name: &'static str,
+	/* Other names it can be given by */
+	pub aliases: &'static [&'static str],
+	pub description: &'static str,
+	/* Args to add to the ffmpeg args */
+	pub args: &'static [&'static str],
+	/* Output format it can only be used with (if it sets the codec) */
+	pub only_for: Option<&'static str>,
+}
+
+impl fmt::Display for Preset {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.name)
+	}
+}
+
+/* Built-in presets */
+pub const PRESETS: [Preset; 4] = [
+	Preset {
+		name: "hq",
+		aliases: &[],
+		description: "High quality (320 kbps)",
+		args: &["-b:a", "320k"],
+		only_for: None,
+	},
+	Preset {
+		name: "standard",
+		aliases: &[],
+		description: "Standard quality (192 kbps)",
+		args: &["-b:a", "192k"],
+		only_for: None,
+	},
+	Preset {
+		name: "mono-64k",
+		aliases: &["voice"],
+		description: "Mono at 64 kbps (fine for speech)",
+		args: &["-ac", "1", "-b:a", "64k"],
+		only_for: None,
+	},
+	Preset {
+		name: "archival-flac",
+		aliases: &[],
+		description: "Lossless, with the smallest files (slowest to encode)",
+		args: &["-c:a", "flac", "-compression_level", "12"],
+		only_for: Some("flac"),
+	},
+];
+
+/* Look up a preset by name (or alias) */
+pub fn find_preset(name: &str) -> Result<&'static Preset, String>
+{
+	let name = name.trim().to_lowercase();
+	match PRESETS.iter().find(|p| p.name == name || p.aliases.contains(&name.as_str())) {
+		Some(preset) => Ok(preset),
+		None => {
+			let names: Vec<&str> = PRESETS.iter().map(|p| p.name).collect();
+			Err(format!("Unknown preset '{0}' (expected one of: {1})", name, names.join(", ")))
+		}
+	}
+}
+
+/* *************************************************** */
+/* Preset Map */
+
+/* Which preset to use for each type of track (as given by "--preset=<type>:<preset>,...") */
+#[derive(Debug)]
+#[derive(Default)]
+pub struct PresetMap {
+	pub entries: Vec<(TrackType, &'static Preset)>,
+}
+
+impl PresetMap {
+	/* Parse "<type>:<preset>,<type>:<preset>,..." (types can be given by name or shortname, e.g. "ViolinLayering" or "VL") */
+	pub fn parse(spec: &str) -> Result<PresetMap, String>
+	{
+		let mut map = PresetMap::default();
+
+		for item in spec.split(',').map(|item| item.trim()) {
+			let mut parts = item.splitn(2, ':');
+			let (type_name, preset_name) = match (parts.next(), parts.next()) {
+				(Some(t), Some(p)) if !t.trim().is_empty() && !p.trim().is_empty() => (t, p),
+				_ => return Err(format!("Expected <type>:<preset> (e.g. Voice:mono-64k), got '{}'", item))
+			};
+
+			let track_type: TrackType = type_name.parse()?;
+			let preset = find_preset(preset_name)?;
+
+			if map.preset_for(&track_type).is_some() {
+				return Err(format!("More than one preset given for {:?}", track_type));
+			}
+			map.entries.push((track_type, preset));
+		}
+		Ok(map)
+	}
+
+	/* Preset to use for tracks of the given type (if any) */
+	pub fn preset_for(&self, track_type: &TrackType) -> Option<&'static Preset>
+	{
+		self.entries.iter().find(|(t, _)| t == track_type).map(|&(_, preset)| preset)
+	}
+
+	/* Check that all the presets can be used with the output format */
+	pub fn check_format(&self, format: &TrackExtension) -> Result<(), String>
+	{
+		for &(ref track_type, preset) in self.entries.iter() {
+			if let Some(only_for) = preset.only_for {
+				if format.to_string() != only_for {
+					return Err(format!("Preset '{0}' (for {1:?}) can only be used when converting to {2}, not {3}",
+					                   preset, track_type, only_for, format));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/* Work out the ffmpeg args for each type with a preset, by adding the preset's args to the base args
+	 * > returns descriptions of all the conflicts, if any of the presets contradict the base args
+	 */
+	pub fn resolve(&self, base_args: &[String]) -> Result<ResolvedPresets, Vec<String>>
+	{
+		let mut resolved = ResolvedPresets::none(base_args);
+		let mut problems = Vec::new();
+
+		for &(track_type, preset) in self.entries.iter() {
+			let preset_args: Vec<String> = preset.args.iter().map(|a| a.to_string()).collect();
+			match ffmpeg_command::merge_args(base_args, &preset_args) {
+				Ok(args) => resolved.by_type.push((track_type, preset, args)),
+				Err(conflicts) => {
+					problems.extend(conflicts.iter().map(|c: &ArgConflict| {
+						format!("Preset '{0}' (for {1:?}) sets {2} {3}, but the other settings give {2} {4}",
+						        preset, track_type, c.option,
+						        c.extra_value.as_ref().map_or("", |v| v.as_str()),
+						        c.base_value.as_ref().map_or("", |v| v.as_str()))
+					}));
+				}
+			}
+		}
+
+		if problems.is_empty() { Ok(resolved) } else { Err(problems) }
+	}
+}
+
+/* ffmpeg args to use for each type of track */
+#[derive(Debug)]
+pub struct ResolvedPresets {
+	/* Args for types without a preset */
+	pub base: Vec<String>,
+	/* Preset and args for each type with one */
+	pub by_type: Vec<(TrackType, &'static Preset, Vec<String>)>,
+}
+
+impl ResolvedPresets {
+	/* No presets - Every type uses the base args */
+	pub fn none(base_args: &[String]) -> ResolvedPresets
+	{
+		ResolvedPresets { base: base_args.to_vec(), by_type: Vec::new() }
+	}
+
+	/* Preset (if any) and args to use for tracks of the given type */
+	pub fn args_for(&self, track_type: &TrackType) -> (Option<&'static Preset>, &[String])
+	{
+		match self.by_type.iter().find(|(t, _, _)| t == track_type) {
+			Some((_, preset, args)) => (Some(preset), args),
+			None                    => (None, &self.base)
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(s: &str) -> Vec<String>
+	{
+		s.split_whitespace().map(|a| a.to_string()).collect()
+	}
+
+	/* (type, preset name) for each entry in the map */
+	fn entries(map: &PresetMap) -> Vec<(TrackType, &'static str)>
+	{
+		map.entries.iter().map(|&(t, p)| (t, p.name)).collect()
+	}
+
+	#[test]
+	fn test_find_preset()
+	{
+		assert_eq!("hq", find_preset("hq").unwrap().name);
+		assert_eq!("mono-64k", find_preset("voice").unwrap().name);
+		assert_eq!("archival-flac", find_preset(" Archival-FLAC ").unwrap().name);
+
+		let e = find_preset("lofi").unwrap_err();
+		assert!(e.contains("'lofi'") && e.contains("hq, standard, mono-64k, archival-flac"), "{}", e);
+	}
+
+	#[test]
+	fn test_parse_map()
+	{
+		let map = PresetMap::parse("Voice:mono-64k,ViolinLayering:hq").unwrap();
+		assert_eq!(vec![(TrackType::Voice, "mono-64k"), (TrackType::ViolinLayering, "hq")], entries(&map));
+
+		/* Shortnames, aliases, and spacing */
+		let map = PresetMap::parse(" V:voice , MS : standard,p:archival-flac").unwrap();
+		assert_eq!(vec![(TrackType::Voice, "mono-64k"), (TrackType::MuseScore, "standard"), (TrackType::Piano, "archival-flac")],
+		           entries(&map));
+
+		assert_eq!(Some("hq"), PresetMap::parse("VL:hq").unwrap().preset_for(&TrackType::ViolinLayering).map(|p| p.name));
+		assert_eq!(None, PresetMap::parse("VL:hq").unwrap().preset_for(&TrackType::Voice));
+	}
+
+	#[test]
+	fn test_parse_map_errors()
+	{
+		let error = |spec: &str| PresetMap::parse(spec).unwrap_err();
+
+		assert!(error("Voice").contains("Expected <type>:<preset>"));
+		assert!(error("Voice:").contains("Expected <type>:<preset>"));
+		assert!(error("").contains("Expected <type>:<preset>"));
+		assert!(error("VL:hq,").contains("Expected <type>:<preset>"));
+		assert!(error("Violin:hq").contains("Unknown track type 'Violin'"));
+		assert!(error("Voice:lofi").contains("Unknown preset 'lofi'"));
+		assert_eq!("More than one preset given for Voice", error("Voice:hq,V:mono-64k"));
+	}
+
+	#[test]
+	fn test_check_format()
+	{
+		let map = PresetMap::parse("Voice:mono-64k,Piano:archival-flac").unwrap();
+		assert!(map.check_format(&TrackExtension::flac).is_ok());
+
+		let e = map.check_format(&TrackExtension::mp3).unwrap_err();
+		assert_eq!("Preset 'archival-flac' (for Piano) can only be used when converting to flac, not mp3", e);
+
+		assert!(PresetMap::parse("Voice:mono-64k").unwrap().check_format(&TrackExtension::ogg).is_ok());
+	}
+
+	#[test]
+	fn test_resolve()
+	{
+		let map = PresetMap::parse("Voice:mono-64k,ViolinLayering:hq").unwrap();
+
+		/* Presets get added to the base args, and types without one just use those */
+		let resolved = map.resolve(&args("-vn")).unwrap();
+		assert_eq!((Some("mono-64k"), args("-vn -ac 1 -b:a 64k")),
+		           { let (p, a) = resolved.args_for(&TrackType::Voice); (p.map(|p| p.name), a.to_vec()) });
+		assert_eq!((Some("hq"), args("-vn -b:a 320k")),
+		           { let (p, a) = resolved.args_for(&TrackType::ViolinLayering); (p.map(|p| p.name), a.to_vec()) });
+		assert_eq!((None, args("-vn")),
+		           { let (p, a) = resolved.args_for(&TrackType::Piano); (p.map(|p| p.name), a.to_vec()) });
+
+		/* Settings the preset agrees with don't get repeated */
+		let resolved = map.resolve(&args("-vn -ac 1")).unwrap();
+		assert_eq!(args("-vn -ac 1 -b:a 64k"), resolved.args_for(&TrackType::Voice).1);
+
+		/* No presets */
+		let resolved = PresetMap::default().resolve(&args("-vn -b:a 192k")).unwrap();
+		assert_eq!((None, &args("-vn -b:a 192k")[..]), resolved.args_for(&TrackType::Voice));
+	}
+
+	#[test]
+	fn test_resolve_conflicts()
+	{
+		/* e.g. "--bitrate=192k" contradicts the bitrate of both presets (but not the flac one) */
+		let map = PresetMap::parse("Voice:mono-64k,ViolinLayering:hq,Piano:archival-flac").unwrap();
+		let problems = map.resolve(&args("-vn -b:a 192k")).unwrap_err();
+		assert_eq!(vec!["Preset 'mono-64k' (for Voice) sets -b:a 64k, but the other settings give -b:a 192k",
+		                "Preset 'hq' (for ViolinLayering) sets -b:a 320k, but the other settings give -b:a 192k"],
+		           problems);
+
+		/* ... unless it's the same (given by another name) */
+		assert!(PresetMap::parse("VL:hq").unwrap().resolve(&args("-vn -ab 320k")).is_ok());
+
+		/* Codec given in the passed-through ffmpeg args */
+		let problems = PresetMap::parse("P:archival-flac").unwrap().resolve(&args("-vn -acodec alac")).unwrap_err();
+		assert_eq!(vec!["Preset 'archival-flac' (for Piano) sets -c:a flac, but the other settings give -c:a alac"], problems);
+	}
+}
@@ -0,0 +1,213 @@
+/* Resuming interrupted converts
+ *
+ * Each file gets written to a temp file alongside its final destination first
+ * (".xspf_tools-partial-<dst_filename>"), and only renamed to its final name once
+ * it's complete. So after an interrupted run, any file with its final name is done,
+ * while any temp files left behind are incomplete (and just get thrown away).
+ */
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/* Prefix for the temp files that outputs get written to, until they're complete */
+pub const PARTIAL_PREFIX: &'static str = ".xspf_tools-partial-";
+
+/* *************************************************** */
+/* Partial Outputs */
+
+/* Path of the temp file that the given destination file gets written to first
+ * (The extension is kept, so that ffmpeg still knows which format to write)
+ */
+pub fn partial_path(dst_path: &Path) -> PathBuf
+{
+	let filename = dst_path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+	dst_path.with_file_name(format!("{0}{1}", PARTIAL_PREFIX, filename))
+}
+
+/* Is the given filename one of the temp files? (the name may include subdirectories - see list_dir()) */
+pub fn is_partial_filename(filename: &str) -> bool
+{
+	filename.rsplit('/').next().unwrap_or(filename).starts_with(PARTIAL_PREFIX)
+}
+
+/* Move a completed temp file to its final destination */
+pub fn finish_partial(dst_path: &Path) -> io::Result<()>
+{
+	fs::rename(partial_path(dst_path), dst_path)
+}
+
+/* Throw away the temp file for the given destination (if there is one) */
+pub fn discard_partial(dst_path: &Path)
+{
+	let _ = fs::remove_file(partial_path(dst_path));
+}
+
+/* *************************************************** */
+/* Resume Planning */
+
+/* State of an output folder, with respect to the files that a run will produce */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ResumePlan {
+	/* Destination filenames that are already complete */
+	pub done: BTreeSet<String>,
+	/* Destination filenames still to be produced (in the planned order) */
+	pub remaining: Vec<String>,
+	/* Temp files left behind by an interrupted run (which need removing) */
+	pub stale_partials: Vec<String>,
+}
+
+impl ResumePlan {
+	/* Is the given destination file already complete? */
+	pub fn is_done(&self, dst_filename: &str) -> bool
+	{
+		self.done.contains(dst_filename)
+	}
+}
+
+/* Work out what's left to do
+ * - listing: Names of the files currently in the output folder
+ * - planned: Destination filenames that the run will produce (as used for the run itself)
+ */
+pub fn plan_resume(listing: &[String], planned: &[String]) -> ResumePlan
+{
+	let existing: BTreeSet<&str> = listing.iter()
+	                                      .map(|f| f.as_str())
+	                                      .filter(|f| !is_partial_filename(f))
+	                                      .collect();
+
+	let (done, remaining): (Vec<&String>, Vec<&String>) = planned.iter().partition(|f| existing.contains(f.as_str()));
+
+	ResumePlan {
+		done: done.into_iter().cloned().collect(),
+		remaining: remaining.into_iter().cloned().collect(),
+		stale_partials: listing.iter().filter(|f| is_partial_filename(f)).cloned().collect(),
+	}
+}
+
+/* Names of the files in the given folder, including any in subdirectories (as "<subdir>/<name>",
+ * matching how destination filenames are given when the naming template has subdirectories)
+ * NOTE: A missing folder counts as empty
+ */
+fn list_dir(dir: &Path) -> io::Result<Vec<String>>
+{
+	fn add_files(dir: &Path, prefix: &str, names: &mut Vec<String>) -> io::Result<()>
+	{
+		for entry in fs::read_dir(dir)? {
+			let entry = entry?;
+			let name = format!("{0}{1}", prefix, entry.file_name().to_string_lossy());
+			let file_type = entry.file_type()?;
+			if file_type.is_file() {
+				names.push(name);
+			}
+			else if file_type.is_dir() {
+				add_files(&entry.path(), &format!("{}/", name), names)?;
+			}
+		}
+		Ok(())
+	}
+
+	let mut names = Vec::new();
+	match add_files(dir, "", &mut names) {
+		Ok(()) => {
+			names.sort();
+			Ok(names)
+		},
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound && !dir.exists() => Ok(Vec::new()),
+		Err(e) => Err(e)
+	}
+}
+
+/* Scan the output folder to see what's left to do, and remove any temp files left behind */
+pub fn prepare_resume(out_dir: &Path, planned: &[String]) -> io::Result<ResumePlan>
+{
+	let plan = plan_resume(&list_dir(out_dir)?, planned);
+	for partial in plan.stale_partials.iter() {
+		fs::remove_file(out_dir.join(partial))?;
+	}
+	Ok(plan)
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	fn names(names: &[&str]) -> Vec<String>
+	{
+		names.iter().map(|s| s.to_string()).collect()
+	}
+
+	#[test]
+	fn test_partial_path()
+	{
+		let partial = partial_path(Path::new("out/Track_01-20170403-VL01_tranquil.mp3"));
+		assert_eq!(Path::new("out/.xspf_tools-partial-Track_01-20170403-VL01_tranquil.mp3"), partial);
+		assert_eq!(Some("mp3".as_ref()), partial.extension());
+		assert!(is_partial_filename(&partial.file_name().unwrap().to_string_lossy()));
+	}
+
+	#[test]
+	fn test_plan_resume()
+	{
+		let planned = names(&["Track_1.mp3", "Track_2.mp3", "Track_3.mp3", "Track_4.mp3"]);
+		let listing = names(&[".xspf_tools-convert.json",
+		                      ".xspf_tools-partial-Track_3.mp3",
+		                      "Track_1.mp3",
+		                      "Track_2.flac",    /* Same track, but a different format - not done */
+		                      "sessions.m3u"]);
+
+		let plan = plan_resume(&listing, &planned);
+		assert_eq!(vec!["Track_1.mp3"], plan.done.iter().collect::<Vec<_>>());
+		assert_eq!(names(&["Track_2.mp3", "Track_3.mp3", "Track_4.mp3"]), plan.remaining);
+		assert_eq!(names(&[".xspf_tools-partial-Track_3.mp3"]), plan.stale_partials);
+		assert!(plan.is_done("Track_1.mp3"));
+		assert!(!plan.is_done("Track_3.mp3"));
+
+		/* Nothing there yet */
+		let plan = plan_resume(&[], &planned);
+		assert!(plan.done.is_empty());
+		assert_eq!(planned, plan.remaining);
+	}
+
+	#[test]
+	fn test_prepare_resume()
+	{
+		/* Half-finished run - 2 done, 1 interrupted part-way through, 1 not started */
+		let dir = TempDir::new("convert-resume");
+		dir.write_file("Track_1-20170403-VL01_tranquil.mp3", b"done");
+		dir.write_file("Track_2-20170403-MS01_sonata.mp3", b"done");
+		dir.write_file(".xspf_tools-partial-Track_3-20170404-VL02_moose.mp3", b"half");
+
+		let planned = names(&["Track_1-20170403-VL01_tranquil.mp3",
+		                      "Track_2-20170403-MS01_sonata.mp3",
+		                      "Track_3-20170404-VL02_moose.mp3",
+		                      "Track_4-20170405-VL03_dawn.mp3"]);
+
+		let plan = prepare_resume(dir.path(), &planned).unwrap();
+		assert_eq!(2, plan.done.len());
+		assert_eq!(names(&["Track_3-20170404-VL02_moose.mp3", "Track_4-20170405-VL03_dawn.mp3"]), plan.remaining);
+		assert_eq!(1, plan.stale_partials.len());
+
+		/* Temp file is gone, and the completed ones are untouched */
+		assert!(!dir.path().join(".xspf_tools-partial-Track_3-20170404-VL02_moose.mp3").exists());
+		assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+
+		/* Files in subdirectories (from naming templates with them) count too */
+		dir.write_file("20170405/VL03_dawn.mp3", b"done");
+		dir.write_file("20170406/.xspf_tools-partial-VL04_dusk.mp3", b"half");
+		let plan = prepare_resume(dir.path(), &names(&["20170405/VL03_dawn.mp3", "20170406/VL04_dusk.mp3"])).unwrap();
+		assert!(plan.is_done("20170405/VL03_dawn.mp3"));
+		assert_eq!(names(&["20170406/VL04_dusk.mp3"]), plan.remaining);
+		assert_eq!(names(&["20170406/.xspf_tools-partial-VL04_dusk.mp3"]), plan.stale_partials);
+		assert!(!dir.path().join("20170406/.xspf_tools-partial-VL04_dusk.mp3").exists());
+
+		/* Missing output folder - nothing done yet */
+		let plan = prepare_resume(&dir.path().join("missing"), &planned).unwrap();
+		assert_eq!(4, plan.remaining.len());
+	}
+}
@@ -0,0 +1,223 @@
+/* Record of how each file in a convert output folder was produced
+ *
+ * This lets an incremental convert figure out which files need to be
+ * redone - either because the source changed, or (with "--settings-diff")
+ * because they were encoded using different ffmpeg settings.
+ */
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_json;
+
+/* Name of the file (in the output folder) that the stamps get saved to */
+pub const STAMPS_FILENAME: &'static str = ".xspf_tools-convert.json";
+
+/* Version of the stamps file format */
+const STAMPS_FORMAT_VERSION: u32 = 1;
+
+/* *************************************************** */
+
+/* What was recorded about a destination file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct ConvertStamp {
+	/* Fingerprint of the ffmpeg settings used (see ffmpeg_command::settings_fingerprint()) */
+	pub fingerprint: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct StampsFile {
+	version: u32,
+	/* Destination filename -> Stamp */
+	entries: BTreeMap<String, ConvertStamp>,
+}
+
+/* Stamps for all the files in an output folder */
+#[derive(Debug)]
+pub struct ConvertStamps {
+	path: PathBuf,
+	entries: BTreeMap<String, ConvertStamp>,
+}
+
+impl ConvertStamps {
+	/* Load the stamps for the given output folder
+	 * (Missing or unreadable stamps are treated as empty - at worst, things get redone)
+	 */
+	pub fn load(out_dir: &Path) -> ConvertStamps
+	{
+		let path = out_dir.join(STAMPS_FILENAME);
+		let entries = fs::read_to_string(&path).ok()
+		                .and_then(|s| serde_json::from_str::<StampsFile>(&s).ok())
+		                .filter(|f| f.version == STAMPS_FORMAT_VERSION)
+		                .map(|f| f.entries)
+		                .unwrap_or_default();
+
+		ConvertStamps {
+			path: path,
+			entries: entries,
+		}
+	}
+
+	/* Fingerprint recorded for the given destination file */
+	pub fn fingerprint_for(&self, dst_filename: &str) -> Option<&str>
+	{
+		self.entries.get(dst_filename).map(|s| s.fingerprint.as_str())
+	}
+
+	/* Record that the given destination file was produced using the given settings */
+	pub fn record(&mut self, dst_filename: &str, fingerprint: &str)
+	{
+		self.entries.insert(dst_filename.to_string(), ConvertStamp { fingerprint: fingerprint.to_string() });
+	}
+
+	/* Write the stamps back to the output folder */
+	pub fn save(&self) -> Result<(), String>
+	{
+		let stamps_file = StampsFile {
+			version: STAMPS_FORMAT_VERSION,
+			entries: self.entries.clone(),
+		};
+		let json = serde_json::to_string_pretty(&stamps_file).map_err(|e| e.to_string())?;
+
+		let temp_path = self.path.with_extension("json.tmp");
+		File::create(&temp_path)
+			.and_then(|mut f| f.write_all(json.as_bytes()))
+			.and_then(|_| fs::rename(&temp_path, &self.path))
+			.map_err(|e| format!("Couldn't save convert stamps to {0:?} - {1}", self.path, e))
+	}
+}
+
+/* *************************************************** */
+/* Incremental Convert Decisions */
+
+/* What to do with a track during an incremental convert */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum ConvertDecision {
+	/* Output exists, and is newer than the source (and made with the same settings, if checked) */
+	UpToDate,
+	/* No output yet */
+	Missing,
+	/* Source was modified after the output was made */
+	SourceNewer,
+	/* Output was made using different settings (or unknown ones) */
+	SettingsChanged,
+}
+
+impl ConvertDecision {
+	/* Does the track need to be (re)converted? */
+	pub fn needs_convert(&self) -> bool
+	{
+		*self != ConvertDecision::UpToDate
+	}
+}
+
+/* Decide whether a track needs to be (re)converted
+ * - src_mtime/dst_mtime: Modification times of the files (None if they don't exist)
+ * - stored_fingerprint: Fingerprint recorded for the destination file (if any)
+ * - current_fingerprint: Fingerprint of the settings for this run
+ * - settings_diff: Whether to redo files made with different settings (i.e. "--settings-diff")
+ */
+pub fn decide(src_mtime: Option<SystemTime>, dst_mtime: Option<SystemTime>,
+              stored_fingerprint: Option<&str>, current_fingerprint: &str,
+              settings_diff: bool)
+	-> ConvertDecision
+{
+	let dst_mtime = match dst_mtime {
+		Some(t) => t,
+		None    => return ConvertDecision::Missing
+	};
+
+	if let Some(src_mtime) = src_mtime {
+		if src_mtime > dst_mtime {
+			return ConvertDecision::SourceNewer;
+		}
+	}
+
+	if settings_diff && stored_fingerprint != Some(current_fingerprint) {
+		return ConvertDecision::SettingsChanged;
+	}
+
+	ConvertDecision::UpToDate
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+	use ffmpeg_command::settings_fingerprint;
+	use test_utils::TempDir;
+
+	#[test]
+	fn test_decide()
+	{
+		let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+		let new = SystemTime::UNIX_EPOCH + Duration::from_secs(2000);
+
+		assert_eq!(ConvertDecision::Missing,         decide(Some(old), None, None, "abc", false));
+		assert_eq!(ConvertDecision::SourceNewer,     decide(Some(new), Some(old), Some("abc"), "abc", true));
+		assert_eq!(ConvertDecision::UpToDate,        decide(Some(old), Some(new), Some("abc"), "abc", true));
+
+		/* Settings only matter when asked */
+		assert_eq!(ConvertDecision::UpToDate,        decide(Some(old), Some(new), Some("abc"), "def", false));
+		assert_eq!(ConvertDecision::SettingsChanged, decide(Some(old), Some(new), Some("abc"), "def", true));
+		assert_eq!(ConvertDecision::SettingsChanged, decide(Some(old), Some(new), None, "def", true));
+	}
+
+	/* Simulate a convert run, returning the tracks that got queued for conversion */
+	fn simulate_run(out_dir: &Path, tracks: &[&str], bitrate: &str) -> Vec<String>
+	{
+		let args = vec!["-vn".to_string(), "-b:a".to_string(), bitrate.to_string()];
+		let fingerprint = settings_fingerprint(&args);
+
+		let src_mtime = Some(SystemTime::UNIX_EPOCH);
+		let mut stamps = ConvertStamps::load(out_dir);
+		let mut queued = Vec::new();
+
+		for track in tracks.iter() {
+			let dst_path = out_dir.join(track);
+			let dst_mtime = fs::metadata(&dst_path).and_then(|m| m.modified()).ok();
+
+			if decide(src_mtime, dst_mtime, stamps.fingerprint_for(track), &fingerprint, true).needs_convert() {
+				/* "Convert" */
+				fs::write(&dst_path, bitrate.as_bytes()).unwrap();
+				stamps.record(track, &fingerprint);
+				queued.push(track.to_string());
+			}
+		}
+
+		stamps.save().unwrap();
+		queued
+	}
+
+	#[test]
+	fn test_settings_diff_runs()
+	{
+		let dir = TempDir::new("convert-settings-diff");
+
+		/* First run - Everything is new */
+		assert_eq!(vec!["Track_01.mp3", "Track_02.mp3"],
+		           simulate_run(dir.path(), &["Track_01.mp3", "Track_02.mp3"], "192k"));
+
+		/* A track added later gets converted using the new settings */
+		assert_eq!(vec!["Track_03.mp3"],
+		           simulate_run(dir.path(), &["Track_01.mp3", "Track_02.mp3", "Track_03.mp3"][2 ..], "256k"));
+
+		/* Second full run with the new settings - Only the ones made with the old settings are redone */
+		assert_eq!(vec!["Track_01.mp3", "Track_02.mp3"],
+		           simulate_run(dir.path(), &["Track_01.mp3", "Track_02.mp3", "Track_03.mp3"], "256k"));
+
+		/* ... and then there's nothing left to do */
+		assert!(simulate_run(dir.path(), &["Track_01.mp3", "Track_02.mp3", "Track_03.mp3"], "256k").is_empty());
+	}
+}
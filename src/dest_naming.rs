@@ -0,0 +1,790 @@
+/* Naming of the files written by copy/convert (and anything else that needs to know what they're called)
+ *
+ * Every track gets a canonical name, built from the info that could be worked out from its filename,
+ * following a template (e.g. "Track_03-20170403-v02b_take.mp3" with the default one - see NameTemplate).
+ * Should two tracks end up with the same name, one of them needs renaming, which can be done in a few
+ * different ways (see CollisionStyle).
+ */
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use track_name_info::{TrackExtension, TrackType};
+use xspf_parser::Track;
+
+/* Name of the report listing any files that had to be renamed (written into the output folder) */
+pub const COLLISIONS_REPORT_FILENAME: &'static str = "collisions.txt";
+
+/* *************************************************** */
+/* Templates */
+
+/* Template that files get named with, unless another is given */
+pub const DEFAULT_TEMPLATE: &'static str = "Track_{track}-{date}-{type}{index}{variant}_{name}.{ext}";
+
+/* Values that can be put into names */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+enum Placeholder {
+	Track,      /* Number of the track in the playlist (1-based, padded to the same width for all tracks) */
+	Date,       /* Session date (YYYYMMDD) */
+	Type,       /* Shortname of the track's type (e.g. "VL") */
+	Index,      /* Index of the take (2 digits) */
+	Variant,    /* Variant letter(s) of the take (e.g. "b" for "v02b") */
+	Name,       /* Name of the piece */
+	Ext,        /* Extension of the output file */
+	Orig,       /* Original filename (minus its extension) */
+}
+
+/* Names of the placeholders, as they're written in templates */
+const PLACEHOLDERS: [(&'static str, Placeholder); 8] = [
+	("track", Placeholder::Track),
+	("date", Placeholder::Date),
+	("type", Placeholder::Type),
+	("index", Placeholder::Index),
+	("variant", Placeholder::Variant),
+	("name", Placeholder::Name),
+	("ext", Placeholder::Ext),
+	("orig", Placeholder::Orig),
+];
+
+/* Characters separating the parts of a name, which go along with any placeholders left empty */
+const SEPARATORS: &'static str = "-_ ./";
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+enum TemplatePart {
+	Text(String),
+	Value(Placeholder),
+}
+
+/* Template for the names of the output files (e.g. "{date}/{type}{index}_{name}.{ext}")
+ *
+ * Templates may contain "/", to put the files into subdirectories of the output folder. They need
+ * to end with ".{ext}", so that converted files get the right extension (and any suffixes added to
+ * colliding names go before it). Placeholders that are left empty for a track (e.g. {date} for tracks
+ * not filed under a date) take the separator following them with them, so that names don't end up
+ * with doubled up "-"s. Tracks whose filenames don't follow the naming rules only get {track}, {name},
+ * {orig}, and {ext} - everything else about them is unknown.
+ */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct NameTemplate {
+	/* Everything before the ".{ext}" at the end */
+	parts: Vec<TemplatePart>,
+}
+
+/* List of the placeholders, for error messages */
+fn supported_placeholders() -> String
+{
+	let names: Vec<String> = PLACEHOLDERS.iter().map(|&(name, _)| format!("{{{}}}", name)).collect();
+	names.join(", ")
+}
+
+impl FromStr for NameTemplate {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<NameTemplate, String>
+	{
+		let mut parts: Vec<TemplatePart> = Vec::new();
+		let mut rest = s;
+		while !rest.is_empty() {
+			match rest.find('{') {
+				Some(0) => {
+					let end = match rest.find('}') {
+						Some(end) => end,
+						None      => return Err(format!("Unclosed placeholder in template '{}'", s))
+					};
+					let name = &rest[1 .. end];
+					match PLACEHOLDERS.iter().find(|&&(n, _)| n == name) {
+						Some(&(_, placeholder)) => parts.push(TemplatePart::Value(placeholder)),
+						None => return Err(format!("Unknown placeholder '{{{0}}}' in template '{1}' (supported placeholders are {2})",
+						                           name, s, supported_placeholders()))
+					}
+					rest = &rest[end + 1 ..];
+				},
+				Some(start) => {
+					parts.push(TemplatePart::Text(rest[.. start].to_string()));
+					rest = &rest[start ..];
+				},
+				None => {
+					parts.push(TemplatePart::Text(rest.to_string()));
+					rest = "";
+				}
+			}
+		}
+
+		/* Split off the extension */
+		let has_extension = match parts.len() {
+			n if n >= 2 => match (&parts[n - 2], &parts[n - 1]) {
+				(&TemplatePart::Text(ref text), &TemplatePart::Value(Placeholder::Ext)) => text.ends_with('.') && (text.len() > 1 || n > 2),
+				_                                                                          => false
+			},
+			_ => false
+		};
+		if !has_extension {
+			return Err(format!("Template '{0}' needs to end with '.{{ext}}' after the rest of the name (e.g. '{1}')", s, DEFAULT_TEMPLATE));
+		}
+		parts.pop();
+		if let Some(TemplatePart::Text(mut text)) = parts.pop() {
+			text.pop();
+			if !text.is_empty() {
+				parts.push(TemplatePart::Text(text));
+			}
+		}
+
+		/* Only one variant position */
+		if parts.iter().filter(|&p| *p == TemplatePart::Value(Placeholder::Variant)).count() > 1 {
+			return Err(format!("{{variant}} can only be used once in template '{}'", s));
+		}
+
+		/* Files need to stay inside the output folder */
+		if s.starts_with('/') || s.contains('\\') || s.split('/').any(|c| c.is_empty() || c == "." || c == "..") {
+			return Err(format!("Template '{}' needs to give a path relative to the output folder (without any empty, '.', or '..' parts)", s));
+		}
+
+		Ok(NameTemplate { parts: parts })
+	}
+}
+
+impl Default for NameTemplate {
+	fn default() -> NameTemplate
+	{
+		DEFAULT_TEMPLATE.parse().unwrap()
+	}
+}
+
+/* *************************************************** */
+/* Canonical Names */
+
+/* Canonical name of a track's file, split up around the variant position
+ * (i.e. where the "b" in "v03b" would go - just after the index)
+ */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct DestinationName {
+	/* Everything up to the variant position (e.g. "Track_03-20170403-v02") */
+	pub head: String,
+	/* Everything after the variant position, apart from the extension (e.g. "_take") */
+	pub tail: String,
+	/* Extension (without the dot) */
+	pub extension: String,
+
+	/* Variant letter(s) the track really has (e.g. "b" for "v02b"), which go at the variant position */
+	pub real_variant: String,
+}
+
+impl DestinationName {
+	/* Canonical name for the given track
+	 * - track_idx: Index of the track in the playlist (0-based)
+	 * - track_index_width: Number of digits to pad the track's number to
+	 * - extension_override: Extension the file will have, if not the same as the track's (e.g. when converting)
+	 * - template: How to put the name together (where {variant} marks the variant position, or the end of the name if it isn't used)
+	 */
+	pub fn for_track(track: &Track,
+	                 track_idx: usize,
+	                 track_index_width: usize,
+	                 extension_override: Option<TrackExtension>,
+	                 template: &NameTemplate)
+		-> DestinationName
+	{
+		let info = track.info();
+		let extension = extension_override.unwrap_or_else(|| info.extn.clone()).to_string();
+
+		/* Only the name can be used as-is for tracks that don't follow our rules */
+		let known = info.track_type != TrackType::UnknownType;
+		let value = |placeholder: Placeholder| -> String {
+			match placeholder {
+				Placeholder::Track   => format!("{0:01$}", track_idx + 1, track_index_width),
+				Placeholder::Date    => track.date.normalised().filter(|_| known).unwrap_or("").to_string(),
+				Placeholder::Type    => if known { info.track_type.shortname_safe() } else { String::new() },
+				Placeholder::Index   => if known { format!("{:02}", info.index) } else { String::new() },
+				Placeholder::Variant => info.variant.clone().filter(|_| known).unwrap_or_default(),
+				Placeholder::Name    => info.name_with_revision(),
+				Placeholder::Ext     => extension.clone(),
+				Placeholder::Orig    => Path::new(&track.filename).file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+			}
+		};
+
+		let mut head = String::new();
+		let mut tail = String::new();
+		let mut real_variant: Option<String> = None;
+		let mut skip_separator = false;
+
+		let mut i = 0;
+		while i < template.parts.len() {
+			match template.parts[i] {
+				TemplatePart::Text(ref text) => {
+					let text = if skip_separator && text.starts_with(|c: char| SEPARATORS.contains(c)) { &text[1 ..] } else { &text[..] };
+					if real_variant.is_some() { tail.push_str(text); } else { head.push_str(text); }
+					skip_separator = false;
+					i += 1;
+				},
+				TemplatePart::Value(_) => {
+					/* Expand the whole run of placeholders, to see if any of them gave anything */
+					let mut any_values = false;
+					while let Some(&TemplatePart::Value(placeholder)) = template.parts.get(i) {
+						let text = value(placeholder);
+						any_values |= !text.is_empty();
+						if placeholder == Placeholder::Variant {
+							real_variant = Some(text);
+						}
+						else if real_variant.is_some() {
+							tail.push_str(&text);
+						}
+						else {
+							head.push_str(&text);
+						}
+						i += 1;
+					}
+
+					let name_so_far = if real_variant.is_some() { head.clone() + &tail } else { head.clone() };
+					skip_separator = !any_values && (name_so_far.is_empty() || name_so_far.ends_with(|c: char| SEPARATORS.contains(c)));
+				}
+			}
+		}
+
+		DestinationName {
+			head: head,
+			tail: tail,
+			extension: extension,
+			real_variant: real_variant.unwrap_or_default(),
+		}
+	}
+
+	/* Filename with the given variant letter(s), and numeric suffix (if any)
+	 * NOTE: Files without an extension don't get a trailing dot either
+	 */
+	fn render(&self, variant: &str, suffix: Option<usize>) -> String
+	{
+		let dot = if self.extension.is_empty() { "" } else { "." };
+		match suffix {
+			Some(n) => format!("{0}{1}{2}_{3}{4}{5}", self.head, variant, self.tail, n, dot, self.extension),
+			None    => format!("{0}{1}{2}{3}{4}", self.head, variant, self.tail, dot, self.extension)
+		}
+	}
+}
+
+impl fmt::Display for DestinationName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.render(&self.real_variant, None))
+	}
+}
+
+/* *************************************************** */
+/* Collisions */
+
+/* How to rename tracks whose canonical names are the same */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub enum CollisionStyle {
+	/* Add "_2", "_3", etc. to the end of the name (before the extension) */
+	#[default]
+	Suffix,
+	/* Put "b", "c", etc. at the variant position (e.g. "v02b_take"), in place of the track's real variant if it has one */
+	Letter,
+	/* Don't rename anything - refuse to go any further instead */
+	Fail,
+}
+
+impl FromStr for CollisionStyle {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<CollisionStyle, String>
+	{
+		match s {
+			"suffix" => Ok(CollisionStyle::Suffix),
+			"letter" => Ok(CollisionStyle::Letter),
+			"fail"   => Ok(CollisionStyle::Fail),
+			_        => Err(format!("Unknown collision style '{}' (expected suffix, letter, or fail)", s))
+		}
+	}
+}
+
+/* A track that was given a different name to its canonical one */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Rename {
+	/* Index of the track (0-based) */
+	pub track_index: usize,
+	/* Canonical name it would have had */
+	pub original: String,
+	/* Name it was given instead */
+	pub renamed: String,
+	/* Name of the file that it collided with (i.e. the one that got to keep the canonical name) */
+	pub counterpart: String,
+}
+
+/* Tracks that would all have had the same name (with CollisionStyle::Fail) */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Collision {
+	pub name: String,
+	/* Indices of the tracks (0-based, in playlist order) */
+	pub track_indices: Vec<usize>,
+}
+
+impl fmt::Display for Collision {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		let numbers: Vec<String> = self.track_indices.iter().map(|i| (i + 1).to_string()).collect();
+		write!(f, "{0} - tracks {1}", self.name, numbers.join(", "))
+	}
+}
+
+/* Names to give each track's file */
+#[derive(Debug)]
+#[derive(Default)]
+pub struct NamingPlan {
+	/* Filename for each track (in playlist order) */
+	pub filenames: Vec<String>,
+	/* Tracks that had to be renamed */
+	pub renames: Vec<Rename>,
+}
+
+impl NamingPlan {
+	/* Subdirectories the files go into (relative to the output folder, parents first) */
+	pub fn subdirectories(&self) -> Vec<String>
+	{
+		let mut dirs: BTreeSet<String> = BTreeSet::new();
+		for filename in self.filenames.iter() {
+			let mut dir = Path::new(filename).parent();
+			while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+				dirs.insert(d.to_string_lossy().into_owned());
+				dir = d.parent();
+			}
+		}
+		dirs.into_iter().collect()
+	}
+	
+	/* Write the list of renamed files (see COLLISIONS_REPORT_FILENAME) */
+	pub fn write_report<W: Write>(&self, out: &mut W) -> io::Result<()>
+	{
+		writeln!(out, "# Files renamed because their names collided with other files")?;
+		writeln!(out, "# <original name>\t<final name>\t<collided with>")?;
+		for rename in self.renames.iter() {
+			writeln!(out, "{0}\t{1}\t{2}", rename.original, rename.renamed, rename.counterpart)?;
+		}
+		Ok(())
+	}
+}
+
+/* Key that names are compared by
+ * NOTE: Names differing only in case count as the same, as they'd still collide on Windows/macOS
+ */
+fn name_key(name: &str) -> String
+{
+	name.to_lowercase()
+}
+
+/* Variant letters that can be given to colliding tracks (the track keeping the canonical name counts as "a") */
+const COLLISION_LETTERS: &'static str = "bcdefghijklmnopqrstuvwxyz";
+
+/* Pick the name for a colliding track
+ * - taken: Names already in use (by key), which the new name gets added to
+ */
+fn collision_name(name: &DestinationName, style: CollisionStyle, taken: &mut HashSet<String>) -> String
+{
+	let mut candidates: Vec<String> = Vec::new();
+	if style == CollisionStyle::Letter {
+		candidates.extend(COLLISION_LETTERS.chars().map(|letter| name.render(&letter.to_string(), None)));
+	}
+
+	/* Numeric suffixes can always be found (and are the fallback, should the letters run out) */
+	let renamed = candidates.into_iter()
+	                        .chain((2 ..).map(|n| name.render(&name.real_variant, Some(n))))
+	                        .find(|candidate| !taken.contains(&name_key(candidate)))
+	                        .unwrap();
+	taken.insert(name_key(&renamed));
+	renamed
+}
+
+/* Work out the names to give each track, renaming any that collide in the given style
+ * > returns the names to use, or all the collisions found (with CollisionStyle::Fail)
+ *
+ * The first track with each name gets to keep it.
+ */
+pub fn plan_names(names: &[DestinationName], style: CollisionStyle) -> Result<NamingPlan, Vec<Collision>>
+{
+	/* Group the tracks sharing each name (groups in order of their first track) */
+	let mut groups: Vec<Vec<usize>> = Vec::new();
+	let mut group_of: HashMap<String, usize> = HashMap::new();
+	for (i, name) in names.iter().enumerate() {
+		let key = name_key(&name.to_string());
+		let group = *group_of.entry(key).or_insert_with(|| {
+			groups.push(Vec::new());
+			groups.len() - 1
+		});
+		groups[group].push(i);
+	}
+
+	if style == CollisionStyle::Fail {
+		let collisions: Vec<Collision> = groups.into_iter()
+			.filter(|group| group.len() > 1)
+			.map(|group| Collision { name: names[group[0]].to_string(), track_indices: group })
+			.collect();
+		return if collisions.is_empty() {
+			Ok(NamingPlan { filenames: names.iter().map(|name| name.to_string()).collect(), renames: Vec::new() })
+		}
+		else {
+			Err(collisions)
+		};
+	}
+
+	/* Renamed tracks mustn't take the canonical name of any other track either */
+	let mut filenames: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+	let mut taken: HashSet<String> = filenames.iter().map(|name| name_key(name)).collect();
+	let mut renames: Vec<Rename> = Vec::new();
+
+	/* Each renamed track collided with the one keeping the name
+	 * NOTE: Tracks get renamed in playlist order, so that free letters/suffixes are handed out in that order too
+	 */
+	let mut renamed: Vec<(usize, usize)> = groups.iter()
+		.filter(|group| group.len() > 1)
+		.flat_map(|group| group.iter().skip(1).map(move |&i| (i, group[0])))
+		.collect();
+	renamed.sort();
+
+	for (i, keeper) in renamed {
+		filenames[i] = collision_name(&names[i], style, &mut taken);
+		renames.push(Rename {
+			track_index: i,
+			original: names[i].to_string(),
+			renamed: filenames[i].clone(),
+			counterpart: filenames[keeper].clone(),
+		});
+	}
+
+	Ok(NamingPlan { filenames: filenames, renames: renames })
+}
+
+/* *************************************************** */
+/* Continued Numbering */
+
+/* What the names of files given the default template start with (before their track number) */
+const TRACK_NUMBER_PREFIX: &'static str = "Track_";
+
+/* Track numbers used by the files already in an output folder (e.g. from copying other playlists into it before),
+ * so that more tracks can be numbered after them (see "--continue-numbering")
+ */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub struct ExistingNumbering {
+	/* Highest track number found (0 if there weren't any) */
+	pub highest: usize,
+	/* Number of digits the widest of those numbers was padded to (0 if there weren't any) */
+	pub width: usize,
+}
+
+/* Track number at the start of a filename, and how many digits it was written with
+ * (e.g. (12, 3) for "Track_012-20170403-v01_take.mp3")
+ */
+fn filename_track_number(filename: &str) -> Option<(usize, usize)>
+{
+	let rest = filename.strip_prefix(TRACK_NUMBER_PREFIX)?;
+	let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+	if digits.is_empty() || !rest[digits.len() ..].starts_with(|c: char| SEPARATORS.contains(c)) {
+		return None;
+	}
+	digits.parse().ok().map(|number| (number, digits.len()))
+}
+
+impl ExistingNumbering {
+	/* Look through the files in the folder for any named "Track_<N>-..." (i.e. with the default template)
+	 * NOTE: A folder that doesn't exist yet just doesn't have any
+	 */
+	pub fn scan(dir: &Path) -> io::Result<ExistingNumbering>
+	{
+		let entries = match fs::read_dir(dir) {
+			Ok(entries) => entries,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(ExistingNumbering::default()),
+			Err(e) => return Err(e)
+		};
+
+		let mut numbering = ExistingNumbering::default();
+		for entry in entries {
+			if let Some((number, width)) = filename_track_number(&entry?.file_name().to_string_lossy()) {
+				numbering.highest = numbering.highest.max(number);
+				numbering.width = numbering.width.max(width);
+			}
+		}
+		Ok(numbering)
+	}
+
+	/* Number of digits to pad the track numbers to, when adding the given number of tracks after the existing ones
+	 * NOTE: This is never narrower than the existing files, but gets wider if the new numbers need more digits
+	 *       (the existing files aren't renamed, so only the new ones get the extra digit)
+	 */
+	pub fn width_for(&self, new_tracks: usize) -> usize
+	{
+		let last = self.highest + new_tracks;
+		let digits = last.to_string().len();
+		digits.max(self.width).max(2)
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn name(head: &str, real_variant: &str) -> DestinationName
+	{
+		DestinationName {
+			head: head.to_string(),
+			tail: "_take".to_string(),
+			extension: "mp3".to_string(),
+			real_variant: real_variant.to_string(),
+		}
+	}
+
+	fn named(path: &str, template: &NameTemplate) -> String
+	{
+		DestinationName::for_track(&Track::from_filepath(path).unwrap(), 0, 2, None, template).to_string()
+	}
+
+	/* Dates are normalised, or left out if the track isn't filed under one */
+	#[test]
+	fn test_for_track()
+	{
+		let template = NameTemplate::default();
+		let canonical = |path: &str| named(path, &template);
+
+		assert_eq!("Track_01-20170403-VL02b_celestial.mp3", canonical("music/20170403/v02b-celestial.mp3"));
+		assert_eq!("Track_01-20170403-VL02b_celestial.mp3", canonical("music/2017-04-03/v02b-celestial.mp3"));
+		assert_eq!("Track_01-20170403-VL02b_celestial.mp3", canonical("music/2017-04-03 Rehearsal/v02b-celestial.mp3"));
+		assert_eq!("Track_01-VL02b_celestial.mp3", canonical("music/misc/v02b-celestial.mp3"));
+
+		/* MuseScore files have the date in their name anyway */
+		assert_eq!("Track_01-20170802-MS02_TouchedByAnAngel.flac", canonical("music/misc/20170802-02-TouchedByAnAngel.flac"));
+
+		/* Names not following the rules are used as-is */
+		assert_eq!("Track_01-intro.wav", canonical("music/20170403/intro.wav"));
+		
+		/* Files without an extension don't get a trailing dot */
+		assert_eq!("Track_01-README", canonical("music/20170403/README"));
+		assert_eq!("Track_01-20170403-VL02_celestial", canonical("music/20170403/v02-celestial"));
+
+		/* Padded to the width given, and converted files get the new extension */
+		let track = Track::from_filepath("music/20170403/p03-etude.mp3").unwrap();
+		let name = DestinationName::for_track(&track, 11, 3, Some(TrackExtension::flac), &template);
+		assert_eq!("Track_012-20170403-P03_etude.flac", name.to_string());
+	}
+
+	#[test]
+	fn test_templates()
+	{
+		let template: NameTemplate = "{date}/{type}{index}{variant}_{name}.{ext}".parse().unwrap();
+		assert_eq!("20170403/VL02b_celestial.mp3", named("music/2017-04-03/v02b-celestial.mp3", &template));
+		assert_eq!("VL02b_celestial.mp3", named("music/misc/v02b-celestial.mp3", &template));
+		assert_eq!("intro.wav", named("music/20170403/intro.wav", &template));
+
+		let template: NameTemplate = "{track} - {orig} [{variant}].{ext}".parse().unwrap();
+		assert_eq!("01 - v02b-celestial [b].mp3", named("music/20170403/v02b-celestial.mp3", &template));
+
+		/* Colliding names get their letter/suffix at the variant position/end of the name, as with the default template */
+		let track = Track::from_filepath("music/20170403/v02-celestial.mp3").unwrap();
+		let template: NameTemplate = "{date}/{name}-{index}{variant}.{ext}".parse().unwrap();
+		let names = vec![DestinationName::for_track(&track, 0, 1, None, &template), DestinationName::for_track(&track, 1, 1, None, &template)];
+		assert_eq!(vec!["20170403/celestial-02.mp3", "20170403/celestial-02b.mp3"],
+		           plan_names(&names, CollisionStyle::Letter).unwrap().filenames);
+		let template: NameTemplate = "{date}/{name}.{ext}".parse().unwrap();
+		let names = vec![DestinationName::for_track(&track, 0, 1, None, &template), DestinationName::for_track(&track, 1, 1, None, &template)];
+		let plan = plan_names(&names, CollisionStyle::Suffix).unwrap();
+		assert_eq!(vec!["20170403/celestial.mp3", "20170403/celestial_2.mp3"], plan.filenames);
+		assert_eq!(vec!["20170403"], plan.subdirectories());
+	}
+
+	#[test]
+	fn test_invalid_templates()
+	{
+		let error = "{date}/{title}.{ext}".parse::<NameTemplate>().unwrap_err();
+		assert!(error.contains("'{title}'"));
+		assert!(error.contains("{track}, {date}, {type}, {index}, {variant}, {name}, {ext}, {orig}"));
+
+		for template in ["{name}", "{name}.mp3", ".{ext}", "{name}.{ext", "{variant}{name}{variant}.{ext}",
+		                     "/music/{name}.{ext}", "../{name}.{ext}", "{date}//{name}.{ext}"] {
+			assert!(template.parse::<NameTemplate>().is_err(), "{}", template);
+		}
+		assert!("{orig}.{ext}".parse::<NameTemplate>().is_ok());
+	}
+
+	#[test]
+	fn test_subdirectories()
+	{
+		let plan = NamingPlan {
+			filenames: vec!["b/c/v01.mp3".to_string(), "v02.mp3".to_string(), "a/v03.mp3".to_string(), "b/v04.mp3".to_string()],
+			renames: Vec::new(),
+		};
+		assert_eq!(vec!["a", "b", "b/c"], plan.subdirectories());
+	}
+
+	#[test]
+	fn test_no_collisions()
+	{
+		let names = vec![name("Track_1-20170403-v01", ""), name("Track_2-20170403-v02", "b")];
+		for style in [CollisionStyle::Suffix, CollisionStyle::Letter, CollisionStyle::Fail] {
+			let plan = plan_names(&names, style).unwrap();
+			assert_eq!(vec!["Track_1-20170403-v01_take.mp3", "Track_2-20170403-v02b_take.mp3"], plan.filenames);
+			assert!(plan.renames.is_empty());
+		}
+	}
+
+	#[test]
+	fn test_suffix_style()
+	{
+		/* Three-way collision (one differing only in case), plus a track already using the first suffix */
+		let mut taken_suffix = name("Track-v01", "");
+		taken_suffix.tail = "_take_2".to_string();
+		let names = vec![name("Track-v01", ""), name("Track-v02", ""), name("TRACK-v01", ""), taken_suffix, name("Track-v01", "")];
+
+		let plan = plan_names(&names, CollisionStyle::Suffix).unwrap();
+		assert_eq!(vec!["Track-v01_take.mp3", "Track-v02_take.mp3", "TRACK-v01_take_3.mp3", "Track-v01_take_2.mp3", "Track-v01_take_4.mp3"],
+		           plan.filenames);
+		assert_eq!(vec![Rename { track_index: 2, original: "TRACK-v01_take.mp3".to_string(), renamed: "TRACK-v01_take_3.mp3".to_string(),
+		                         counterpart: "Track-v01_take.mp3".to_string() },
+		                Rename { track_index: 4, original: "Track-v01_take.mp3".to_string(), renamed: "Track-v01_take_4.mp3".to_string(),
+		                         counterpart: "Track-v01_take.mp3".to_string() }],
+		           plan.renames);
+
+		/* Suffixes keep the real variant */
+		let names = vec![name("Track-v01", "b"), name("Track-v01", "b")];
+		let plan = plan_names(&names, CollisionStyle::Suffix).unwrap();
+		assert_eq!(vec!["Track-v01b_take.mp3", "Track-v01b_take_2.mp3"], plan.filenames);
+	}
+
+	#[test]
+	fn test_letter_style()
+	{
+		/* Three-way collision */
+		let names = vec![name("Track-v01", ""), name("Track-v01", ""), name("Track-v01", "")];
+		let plan = plan_names(&names, CollisionStyle::Letter).unwrap();
+		assert_eq!(vec!["Track-v01_take.mp3", "Track-v01b_take.mp3", "Track-v01c_take.mp3"], plan.filenames);
+		assert_eq!(2, plan.renames.len());
+		assert!(plan.renames.iter().all(|r| r.counterpart == "Track-v01_take.mp3"));
+
+		/* Real variants are part of the name, so different ones don't collide, while the same ones get replaced by free letters */
+		let names = vec![name("Track-v01", "b"), name("Track-v01", ""), name("Track-v01", ""), name("Track-v01", "b")];
+		let plan = plan_names(&names, CollisionStyle::Letter).unwrap();
+		assert_eq!(vec!["Track-v01b_take.mp3", "Track-v01_take.mp3", "Track-v01c_take.mp3", "Track-v01d_take.mp3"], plan.filenames);
+		assert_eq!(vec![2, 3], plan.renames.iter().map(|r| r.track_index).collect::<Vec<_>>());
+		assert_eq!(vec!["Track-v01_take.mp3", "Track-v01b_take.mp3"],
+		           plan.renames.iter().map(|r| r.counterpart.as_str()).collect::<Vec<_>>());
+
+		let names = vec![name("Track-v01", "a"), name("Track-v01", "b")];
+		let plan = plan_names(&names, CollisionStyle::Letter).unwrap();
+		assert_eq!(vec!["Track-v01a_take.mp3", "Track-v01b_take.mp3"], plan.filenames);
+		assert!(plan.renames.is_empty());
+	}
+
+	#[test]
+	fn test_fail_style()
+	{
+		let names = vec![name("Track-v01", ""), name("Track-v02", ""), name("Track-v01", "b"),
+		                 name("Track-v02", ""), name("Track-v01", ""), name("Track-v01", "")];
+		let collisions = plan_names(&names, CollisionStyle::Fail).unwrap_err();
+		assert_eq!(vec![Collision { name: "Track-v01_take.mp3".to_string(), track_indices: vec![0, 4, 5] },
+		                Collision { name: "Track-v02_take.mp3".to_string(), track_indices: vec![1, 3] }],
+		           collisions);
+		assert_eq!("Track-v01_take.mp3 - tracks 1, 5, 6", collisions[0].to_string());
+	}
+
+	/* mp3 and flac renders of the same take (or the same take from two folders) */
+	#[test]
+	fn test_same_stem_renders()
+	{
+		let tracks = [Track::from_filepath("music/20170403/v02-celestial.mp3").unwrap(),
+		              Track::from_filepath("music/20170403/v02-celestial.flac").unwrap(),
+		              Track::from_filepath("backup/20170403/v02-celestial.mp3").unwrap()];
+		let template: NameTemplate = "{date}-{type}{index}{variant}_{name}.{ext}".parse().unwrap();
+		let plan_for = |ext: Option<TrackExtension>, style| {
+			let names: Vec<DestinationName> = tracks.iter().enumerate()
+				.map(|(i, track)| DestinationName::for_track(track, i, 1, ext.clone(), &template))
+				.collect();
+			plan_names(&names, style)
+		};
+
+		/* Copying - only the ones with the same extension collide */
+		let plan = plan_for(None, CollisionStyle::Suffix).unwrap();
+		assert_eq!(vec!["20170403-VL02_celestial.mp3", "20170403-VL02_celestial.flac", "20170403-VL02_celestial_2.mp3"], plan.filenames);
+
+		/* Converting - all of them end up with the same extension */
+		let plan = plan_for(Some(TrackExtension::ogg), CollisionStyle::Suffix).unwrap();
+		assert_eq!(vec!["20170403-VL02_celestial.ogg", "20170403-VL02_celestial_2.ogg", "20170403-VL02_celestial_3.ogg"], plan.filenames);
+		assert_eq!(vec![1, 2], plan.renames.iter().map(|r| r.track_index).collect::<Vec<_>>());
+
+		let collisions = plan_for(Some(TrackExtension::ogg), CollisionStyle::Fail).unwrap_err();
+		assert_eq!(vec![Collision { name: "20170403-VL02_celestial.ogg".to_string(), track_indices: vec![0, 1, 2] }], collisions);
+	}
+
+	#[test]
+	fn test_filename_track_number()
+	{
+		assert_eq!(Some((3, 2)), filename_track_number("Track_03-20170403-v02b_take.mp3"));
+		assert_eq!(Some((12, 3)), filename_track_number("Track_012_intro.flac"));
+		assert_eq!(None, filename_track_number("Track_-20170403-v02b_take.mp3"));
+		assert_eq!(None, filename_track_number("Track_03b-take.mp3"));
+		assert_eq!(None, filename_track_number("v02b_take.mp3"));
+		assert_eq!(None, filename_track_number("playlist.m3u8"));
+	}
+
+	#[test]
+	fn test_existing_numbering()
+	{
+		use test_utils::TempDir;
+
+		let dir = TempDir::new("dest-naming-numbering");
+		assert_eq!(ExistingNumbering::default(), ExistingNumbering::scan(&dir.path().join("new")).unwrap());
+
+		/* Numbers carry on after the highest one there (whatever order they're in) */
+		dir.write_file("Track_01-20170403-v01_tranquil.mp3", b"");
+		dir.write_file("Track_07-20170404-v02_moose.mp3", b"");
+		dir.write_file("Track_03-20170405-v03_dawn.mp3", b"");
+		dir.write_file("phone.m3u8", b"");
+		dir.write_file("collisions.txt", b"");
+		let numbering = ExistingNumbering::scan(dir.path()).unwrap();
+		assert_eq!(ExistingNumbering { highest: 7, width: 2 }, numbering);
+		assert_eq!(2, numbering.width_for(5));
+
+		/* Numbers that need another digit get one (only for the new files) */
+		assert_eq!(3, numbering.width_for(93));
+		assert_eq!(2, numbering.width_for(92));
+
+		/* ... but are never narrower than the existing ones */
+		dir.write_file("Track_008-20170406-v04_dusk.mp3", b"");
+		assert_eq!(ExistingNumbering { highest: 8, width: 3 }, ExistingNumbering::scan(dir.path()).unwrap());
+		assert_eq!(3, ExistingNumbering::scan(dir.path()).unwrap().width_for(1));
+
+		/* Names continue on from the existing ones */
+		let track = Track::from_filepath("music/20170407/v05-noon.mp3").unwrap();
+		let name = DestinationName::for_track(&track, numbering.highest, numbering.width_for(1), None, &NameTemplate::default());
+		assert_eq!("Track_08-20170407-VL05_noon.mp3", name.to_string());
+	}
+
+	#[test]
+	fn test_report()
+	{
+		let names = vec![name("Track-v01", ""), name("Track-v01", "")];
+		let plan = plan_names(&names, CollisionStyle::Suffix).unwrap();
+
+		let mut out: Vec<u8> = Vec::new();
+		plan.write_report(&mut out).unwrap();
+		assert!(String::from_utf8(out).unwrap().ends_with("\nTrack-v01_take.mp3\tTrack-v01_take_2.mp3\tTrack-v01_take.mp3\n"));
+
+		assert_eq!(Ok(CollisionStyle::Letter), "letter".parse());
+		assert!("number".parse::<CollisionStyle>().is_err());
+	}
+}
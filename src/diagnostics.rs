@@ -0,0 +1,170 @@
+/* Collector for the findings/problems that get noticed while processing a playlist
+ *
+ * Checks append entries here instead of printing directly, so that the results
+ * can be displayed as text or exported as JSON in a uniform way.
+ */
+use std::fmt;
+use std::io::{self, Write};
+
+use serde_json::Value;
+
+use terminal_text::sanitize;
+
+/* *************************************************** */
+/* Diagnostic Types */
+
+/* How serious a finding is */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Severity {
+	Info,
+	Warning,
+	Error,
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			Severity::Info    => write!(f, "info"),
+			Severity::Warning => write!(f, "warning"),
+			Severity::Error   => write!(f, "error"),
+		}
+	}
+}
+
+/* A single finding */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Diagnostic {
+	/* How serious it is */
+	pub severity: Severity,
+	/* Short identifier for the kind of problem (e.g. "freshness") */
+	pub category: String,
+
+	/* Index (0-based) of the track this applies to, if it's about a specific track */
+	pub track_index: Option<usize>,
+
+	/* Human-readable description */
+	pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self.track_index {
+			Some(idx) => write!(f, "[{0}] {1} | track {2}: {3}", self.category, self.severity, idx + 1, self.message),
+			None      => write!(f, "[{0}] {1} | {2}", self.category, self.severity, self.message),
+		}
+	}
+}
+
+/* *************************************************** */
+/* Diagnostics Collector */
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Diagnostics {
+	pub entries: Vec<Diagnostic>,
+}
+
+/* JSON Schema for Diagnostics, as output by "verify-order --json" (see json_schema) */
+pub fn report_schema() -> Value
+{
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "report",
+		"type": "object",
+		"properties": {
+			"entries": {
+				"type": "array",
+				"items": {
+					"type": "object",
+					"properties": {
+						"severity": { "enum": ["Info", "Warning", "Error"] },
+						"category": { "type": "string" },
+						"track_index": { "description": "0-based", "type": ["integer", "null"] },
+						"message": { "type": "string" }
+					},
+					"required": ["severity", "category", "track_index", "message"],
+					"additionalProperties": false
+				}
+			}
+		},
+		"required": ["entries"],
+		"additionalProperties": false
+	})
+}
+
+impl Diagnostics {
+	pub fn new() -> Diagnostics
+	{
+		Diagnostics { entries: Vec::new() }
+	}
+
+	/* Add a new entry */
+	pub fn add(&mut self, severity: Severity, category: &str, track_index: Option<usize>, message: String)
+	{
+		self.entries.push(Diagnostic {
+			severity: severity,
+			category: category.to_string(),
+			track_index: track_index,
+			message: message,
+		});
+	}
+
+	/* Shorthand for adding a track-specific warning */
+	pub fn warn_track(&mut self, category: &str, track_index: usize, message: String)
+	{
+		self.add(Severity::Warning, category, Some(track_index), message);
+	}
+
+	/* Utility - Number of entries */
+	pub fn len(&self) -> usize
+	{
+		self.entries.len()
+	}
+
+	/* Utility - Were there any entries at all? */
+	pub fn is_empty(&self) -> bool
+	{
+		self.entries.is_empty()
+	}
+
+	/* Write each entry on its own line to the given output stream */
+	pub fn write_text(&self, out: &mut dyn Write) -> io::Result<()>
+	{
+		for d in self.entries.iter() {
+			writeln!(out, "  {}", sanitize(&d.to_string()))?;
+		}
+		Ok(())
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json;
+
+	/* The JSON output matches its schema */
+	#[test]
+	fn test_report_schema()
+	{
+		use json_schema::{assert_matches_schema, OutputKind};
+
+		let mut diagnostics = Diagnostics::new();
+		assert_matches_schema(OutputKind::Report, &serde_json::to_value(&diagnostics).unwrap());
+
+		diagnostics.add(Severity::Warning, "order", Some(3), "Out of order".to_string());
+		diagnostics.add(Severity::Info, "mixed-locations", None, "Mixed locations".to_string());
+		diagnostics.add(Severity::Error, "freshness", Some(0), "Missing".to_string());
+		assert_matches_schema(OutputKind::Report, &serde_json::to_value(&diagnostics).unwrap());
+	}
+}
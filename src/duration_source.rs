@@ -0,0 +1,312 @@
+/* Where track durations come from
+ *
+ * A track's duration can be known from several places - the <duration> given in the playlist,
+ * or the duration of the file itself as reported by ffprobe. These don't always agree (e.g. when
+ * a file gets re-exported after the playlist was written), so which one gets used is decided by
+ * an explicit priority list, rather than by whichever the mode in question happened to look at.
+ */
+use std::collections::HashMap;
+use std::fmt;
+
+use track_duration::TrackDuration;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* *************************************************** */
+/* Sources */
+
+/* Where a track's duration came from */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationSource {
+	/* <duration> given for the track in the playlist */
+	Playlist,
+	/* Duration of the file, as reported by ffprobe (or the probe cache) */
+	Probed,
+	/* None of the sources had a duration for the track */
+	Unknown,
+}
+
+impl DurationSource {
+	/* Name of the source (as for "--duration-priority") */
+	pub fn name(&self) -> &'static str
+	{
+		match *self {
+			DurationSource::Playlist => "playlist",
+			DurationSource::Probed   => "probed",
+			DurationSource::Unknown  => "unknown",
+		}
+	}
+}
+
+impl fmt::Display for DurationSource {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.name())
+	}
+}
+
+/* *************************************************** */
+/* Priority */
+
+/* Order that the sources get tried in (sources that aren't listed never get used) */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct DurationPriority {
+	pub sources: Vec<DurationSource>,
+}
+
+impl Default for DurationPriority {
+	/* The playlist's durations, falling back on probing the files for tracks without one */
+	fn default() -> DurationPriority
+	{
+		DurationPriority { sources: vec![DurationSource::Playlist, DurationSource::Probed] }
+	}
+}
+
+impl DurationPriority {
+	/* Probed durations first, falling back on the playlist's (i.e. "--prefer-probed") */
+	pub fn prefer_probed() -> DurationPriority
+	{
+		DurationPriority { sources: vec![DurationSource::Probed, DurationSource::Playlist] }
+	}
+
+	/* Parse a comma-separated list of sources, e.g. "probed,playlist" */
+	pub fn parse(s: &str) -> Result<DurationPriority, String>
+	{
+		let mut sources: Vec<DurationSource> = Vec::new();
+		for name in s.split(',').map(|n| n.trim()) {
+			let source = match name {
+				"playlist" => DurationSource::Playlist,
+				"probed"   => DurationSource::Probed,
+				_ => return Err(format!("Unknown duration source '{}' (expected playlist or probed)", name))
+			};
+			if sources.contains(&source) {
+				return Err(format!("Duration source '{}' is listed more than once", name));
+			}
+			sources.push(source);
+		}
+		Ok(DurationPriority { sources: sources })
+	}
+
+	/* Description of the priority, e.g. "playlist > probed" */
+	pub fn describe(&self) -> String
+	{
+		self.sources.iter().map(|s| s.name()).collect::<Vec<_>>().join(" > ")
+	}
+}
+
+/* *************************************************** */
+/* Resolution */
+
+/* Work out the duration of a track, trying each source in order of priority
+ * - probe: Looks up the probed duration of the track (only called if it's needed)
+ *
+ * NOTE: The track's current duration only counts as the playlist's if it came from the playlist
+ *       (i.e. not if it was already resolved from somewhere else).
+ */
+pub fn resolve_duration(track: &Track, priority: &DurationPriority,
+                        probe: &mut dyn FnMut(&Track) -> Option<TrackDuration>)
+	-> (Option<TrackDuration>, DurationSource)
+{
+	for &source in priority.sources.iter() {
+		let duration = match source {
+			DurationSource::Playlist if track.duration_source == DurationSource::Playlist => {
+				track.duration
+			},
+			DurationSource::Probed => probe(track),
+			_ => None
+		};
+		if duration.is_some() {
+			return (duration, source);
+		}
+	}
+	(None, DurationSource::Unknown)
+}
+
+/* Resolve the durations of all the tracks in the playlist (recording where each one came from)
+ * > returns the number of tracks whose durations came from each source, in the order of the priority list
+ */
+pub fn resolve_playlist_durations(xspf: &mut XspfPlaylist, priority: &DurationPriority,
+                                  probe: &mut dyn FnMut(&Track) -> Option<TrackDuration>)
+	-> Vec<(DurationSource, usize)>
+{
+	let mut counts: Vec<(DurationSource, usize)> = priority.sources.iter().map(|&s| (s, 0)).collect();
+
+	for track in xspf.tracks.iter_mut() {
+		let (duration, source) = resolve_duration(track, priority, probe);
+		track.duration = duration;
+		track.duration_source = source;
+
+		if let Some(count) = counts.iter_mut().find(|c| c.0 == source) {
+			count.1 += 1;
+		}
+	}
+	counts
+}
+
+/* Diagnostic category for tracks left without a duration because probing their file failed */
+pub const PROBE_FAILED_CATEGORY: &str = "duration-probe";
+
+/* Note why each track still without a duration couldn't be probed (after resolve_playlist_durations())
+ * - probe_failures: Reason probing failed, for each track path where it did (e.g. "file not found")
+ */
+pub fn note_probe_failures(xspf: &mut XspfPlaylist, probe_failures: &HashMap<String, String>)
+{
+	for (i, track) in xspf.tracks.iter().enumerate() {
+		if track.duration.is_none() {
+			if let Some(reason) = probe_failures.get(&track.path.to_string()) {
+				xspf.diagnostics.warn_track(PROBE_FAILED_CATEGORY, i, format!("Couldn't probe duration - {}", reason));
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use diagnostics::Diagnostics;
+
+	fn make_track(path: &str, playlist_ms: Option<i64>) -> Track
+	{
+		let mut track = Track::from_filepath(path).unwrap();
+		if let Some(ms) = playlist_ms {
+			track.duration = Some(TrackDuration(ms));
+			track.duration_source = DurationSource::Playlist;
+		}
+		track
+	}
+
+	/* Prober returning a fixed duration for each filename */
+	fn prober(durations: &'static [(&'static str, i64)]) -> impl FnMut(&Track) -> Option<TrackDuration>
+	{
+		move |track: &Track| durations.iter().find(|d| d.0 == track.filename).map(|d| TrackDuration(d.1))
+	}
+
+	fn resolve(track: &Track, priority: &str, probed_ms: Option<i64>) -> (Option<i64>, DurationSource)
+	{
+		let priority = DurationPriority::parse(priority).unwrap();
+		let (duration, source) = resolve_duration(track, &priority, &mut |_| probed_ms.map(TrackDuration));
+		(duration.map(|d| d.0), source)
+	}
+
+	#[test]
+	fn test_resolve_duration_priorities()
+	{
+		/* Both sources have a value */
+		let track = make_track("music/20170403/v01-tranquil.mp3", Some(60_000));
+		assert_eq!((Some(60_000), DurationSource::Playlist), resolve(&track, "playlist,probed", Some(61_500)));
+		assert_eq!((Some(61_500), DurationSource::Probed), resolve(&track, "probed,playlist", Some(61_500)));
+		assert_eq!((Some(60_000), DurationSource::Playlist), resolve(&track, "playlist", Some(61_500)));
+		assert_eq!((Some(61_500), DurationSource::Probed), resolve(&track, "probed", Some(61_500)));
+
+		/* Falling back when the preferred source has nothing */
+		assert_eq!((Some(60_000), DurationSource::Playlist), resolve(&track, "probed,playlist", None));
+		assert_eq!((None, DurationSource::Unknown), resolve(&track, "probed", None));
+
+		let unlisted = make_track("music/20170403/v02-moose.mp3", None);
+		assert_eq!((Some(61_500), DurationSource::Probed), resolve(&unlisted, "playlist,probed", Some(61_500)));
+		assert_eq!((None, DurationSource::Unknown), resolve(&unlisted, "playlist", Some(61_500)));
+	}
+
+	#[test]
+	fn test_resolve_duration_probes_lazily()
+	{
+		let track = make_track("music/20170403/v01-tranquil.mp3", Some(60_000));
+		let mut probed = 0;
+		resolve_duration(&track, &DurationPriority::default(), &mut |_| { probed += 1; None });
+		assert_eq!(0, probed);
+
+		resolve_duration(&track, &DurationPriority::prefer_probed(), &mut |_| { probed += 1; None });
+		assert_eq!(1, probed);
+	}
+
+	#[test]
+	fn test_parse_priority()
+	{
+		assert_eq!(DurationPriority::default(), DurationPriority::parse("playlist, probed").unwrap());
+		assert_eq!(DurationPriority::prefer_probed(), DurationPriority::parse("probed,playlist").unwrap());
+		assert_eq!("probed > playlist", DurationPriority::prefer_probed().describe());
+
+		assert!(DurationPriority::parse("playlist,override").is_err());
+		assert!(DurationPriority::parse("probed,probed").unwrap_err().contains("more than once"));
+		assert!(DurationPriority::parse("").is_err());
+	}
+
+	/* Runtime totals follow the priority */
+	#[test]
+	fn test_playlist_runtime_with_priority()
+	{
+		let playlist = || XspfPlaylist {
+			tracks: vec![make_track("music/20170403/v01-tranquil.mp3", Some(60_000)),
+			             make_track("music/20170403/v02-moose.mp3", Some(120_000)),
+			             make_track("music/20170404/v01-dawn.mp3", None)],
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		static PROBED: [(&'static str, i64); 2] = [("v01-tranquil.mp3", 65_000), ("v01-dawn.mp3", 30_000)];
+
+		/* Playlist first - only the track without a duration gets probed */
+		let mut xspf = playlist();
+		let counts = resolve_playlist_durations(&mut xspf, &DurationPriority::default(), &mut prober(&PROBED));
+		assert_eq!(vec![(DurationSource::Playlist, 2), (DurationSource::Probed, 1)], counts);
+		assert_eq!((210_000, 0), (xspf.total_duration().duration.0, xspf.total_duration().uncounted));
+
+		/* Probed first - the probed duration replaces the playlist's where there is one */
+		let mut xspf = playlist();
+		let counts = resolve_playlist_durations(&mut xspf, &DurationPriority::prefer_probed(), &mut prober(&PROBED));
+		assert_eq!(vec![(DurationSource::Probed, 2), (DurationSource::Playlist, 1)], counts);
+		assert_eq!((215_000, 0), (xspf.total_duration().duration.0, xspf.total_duration().uncounted));
+		assert_eq!(vec![DurationSource::Probed, DurationSource::Playlist, DurationSource::Probed],
+		           xspf.tracks.iter().map(|t| t.duration_source).collect::<Vec<_>>());
+
+		/* Playlist only - the unknown track stays unknown */
+		let mut xspf = playlist();
+		resolve_playlist_durations(&mut xspf, &DurationPriority::parse("playlist").unwrap(), &mut prober(&PROBED));
+		assert_eq!((180_000, 1), (xspf.total_duration().duration.0, xspf.total_duration().uncounted));
+		assert_eq!(DurationSource::Unknown, xspf.tracks[2].duration_source);
+	}
+
+	#[test]
+	fn test_note_probe_failures()
+	{
+		let mut xspf = XspfPlaylist {
+			tracks: vec![make_track("music/20170403/v01-tranquil.mp3", Some(60_000)),
+			             make_track("music/20170403/v02-moose.mp3", None),
+			             make_track("music/20170404/v01-dawn.mp3", None),
+			             make_track("music/20170404/v02-dusk.mp3", None)],
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		static PROBED: [(&'static str, i64); 1] = [("v01-dawn.mp3", 30_000)];
+		resolve_playlist_durations(&mut xspf, &DurationPriority::default(), &mut prober(&PROBED));
+
+		let failures: HashMap<String, String> = [("music/20170403/v02-moose.mp3", "file not found"),
+		                                         ("music/20170404/v02-dusk.mp3", "ffprobe failed - Invalid data")]
+			.iter().map(|&(path, reason)| (path.to_string(), reason.to_string())).collect();
+		note_probe_failures(&mut xspf, &failures);
+
+		let noted: Vec<(Option<usize>, &str)> = xspf.diagnostics.entries.iter()
+			.map(|d| (d.track_index, d.message.as_str()))
+			.collect();
+		assert_eq!(vec![(Some(1), "Couldn't probe duration - file not found"),
+		                (Some(3), "Couldn't probe duration - ffprobe failed - Invalid data")],
+		           noted);
+		assert!(xspf.diagnostics.entries.iter().all(|d| d.category == PROBE_FAILED_CATEGORY));
+	}
+}
@@ -0,0 +1,220 @@
+/* Duration verification (for the "verify" mode)
+ *
+ * The <duration> stored in the playlist goes stale whenever a track gets re-rendered after the
+ * playlist was written, which then quietly skews the runtime totals. This compares the stored
+ * durations against those of the files themselves (as probed), flagging any that differ by more
+ * than a tolerance (to allow for the rounding different tools do).
+ */
+use track_duration::TrackDuration;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Default difference (in ms) allowed between the stored and actual durations */
+pub const DEFAULT_TOLERANCE_MS: i64 = 2000;
+
+/* Category for tracks whose stored durations are wrong (see run_outcome) */
+pub const MISMATCH_CATEGORY: &'static str = "duration-mismatch";
+
+/* *************************************************** */
+
+/* Outcome of checking a track */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyStatus {
+	/* Stored duration is within the tolerance of the actual one */
+	Match,
+	/* Stored duration is off by more than the tolerance */
+	Mismatch,
+	/* File doesn't exist */
+	Missing,
+	/* Playlist doesn't give a duration for the track (so there's nothing to check) */
+	NoStoredDuration,
+	/* The file's duration couldn't be found (e.g. ffprobe failed) */
+	ProbeFailed(String),
+}
+
+/* Result of checking a track */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct TrackCheck {
+	/* Index of the track (0-based) */
+	pub track_index: usize,
+	pub filename: String,
+
+	/* Durations (in ms) - from the playlist, and the file */
+	pub stored_ms: Option<i64>,
+	pub actual_ms: Option<i64>,
+
+	pub status: VerifyStatus,
+}
+
+impl TrackCheck {
+	/* Difference between the actual and stored durations (in ms) - positive if the file is longer */
+	pub fn delta_ms(&self) -> Option<i64>
+	{
+		match (self.stored_ms, self.actual_ms) {
+			(Some(stored), Some(actual)) => Some(actual - stored),
+			_                            => None
+		}
+	}
+}
+
+/* Results of checking a whole playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct VerifyReport {
+	/* Difference allowed (in ms) */
+	pub tolerance_ms: i64,
+
+	/* Every track, in playlist order */
+	pub tracks: Vec<TrackCheck>,
+}
+
+impl VerifyReport {
+	/* Number of tracks with the given outcome (ignoring the reasons for probe failures) */
+	pub fn count(&self, status: &VerifyStatus) -> usize
+	{
+		self.tracks.iter()
+		           .filter(|t| match (&t.status, status) {
+		               (&VerifyStatus::ProbeFailed(_), &VerifyStatus::ProbeFailed(_)) => true,
+		               (a, b)                                                       => a == b
+		           })
+		           .count()
+	}
+
+	/* Tracks whose stored durations are wrong */
+	pub fn mismatches(&self) -> Vec<&TrackCheck>
+	{
+		self.tracks.iter().filter(|t| t.status == VerifyStatus::Mismatch).collect()
+	}
+}
+
+/* Check the stored durations of all the tracks in the playlist
+ * - probe: Looks up the actual duration of the track's file (only called for files that exist, and have stored durations)
+ *
+ * NOTE: Only durations given in the playlist get checked - so this should be run before resolving
+ *       any durations from elsewhere (see duration_source).
+ */
+pub fn verify_durations(xspf: &XspfPlaylist, tolerance_ms: i64,
+                        probe: &mut dyn FnMut(&Track) -> Result<TrackDuration, String>)
+	-> VerifyReport
+{
+	let tracks = xspf.tracks.iter().enumerate().map(|(i, track)| {
+		let stored_ms = track.duration.as_ref().map(|d| d.0);
+		let mut actual_ms = None;
+
+		let status = if !track.file_path().exists() {
+			VerifyStatus::Missing
+		}
+		else if let Some(stored) = stored_ms {
+			match probe(track) {
+				Ok(actual) => {
+					actual_ms = Some(actual.0);
+					if (actual.0 - stored).abs() > tolerance_ms {
+						VerifyStatus::Mismatch
+					}
+					else {
+						VerifyStatus::Match
+					}
+				},
+				Err(e) => VerifyStatus::ProbeFailed(e)
+			}
+		}
+		else {
+			VerifyStatus::NoStoredDuration
+		};
+
+		TrackCheck {
+			track_index: i,
+			filename: track.filename.clone(),
+			stored_ms: stored_ms,
+			actual_ms: actual_ms,
+			status: status,
+		}
+	}).collect();
+
+	VerifyReport { tolerance_ms: tolerance_ms, tracks: tracks }
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use diagnostics::Diagnostics;
+	use test_utils::TempDir;
+
+	/* Playlist of files in the dir, with the given stored durations */
+	fn make_playlist(dir: &TempDir, durations: &[Option<i64>]) -> XspfPlaylist
+	{
+		let tracks = durations.iter().enumerate().map(|(i, duration)| {
+			let path = dir.write_file(&format!("20170403/v{:02}-take.mp3", i + 1), b"audio");
+			let mut track = Track::from_path(&path).unwrap();
+			track.duration = duration.map(TrackDuration);
+			track
+		}).collect();
+
+		XspfPlaylist {
+			tracks: tracks,
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_verify_durations()
+	{
+		let dir = TempDir::new("duration-verify");
+		let xspf = make_playlist(&dir, &[Some(61_000), Some(60_000), Some(90_000), None, Some(30_000), Some(45_000)]);
+		fs::remove_file(xspf.tracks[4].file_path()).unwrap();
+
+		/* Actual durations - the file for track 3 has been re-rendered, and track 6 can't be probed */
+		let mut probed: Vec<String> = Vec::new();
+		let report = verify_durations(&xspf, DEFAULT_TOLERANCE_MS, &mut |track: &Track| {
+			probed.push(track.filename.clone());
+			match track.info().index {
+				1 => Ok(TrackDuration(61_400)),
+				2 => Ok(TrackDuration(62_000)),   /* exactly on the tolerance */
+				3 => Ok(TrackDuration(84_500)),
+				_ => Err("ffprobe failed".to_string())
+			}
+		});
+
+		let statuses: Vec<&VerifyStatus> = report.tracks.iter().map(|t| &t.status).collect();
+		assert_eq!(vec![&VerifyStatus::Match, &VerifyStatus::Match, &VerifyStatus::Mismatch, &VerifyStatus::NoStoredDuration,
+		                &VerifyStatus::Missing, &VerifyStatus::ProbeFailed("ffprobe failed".to_string())],
+		           statuses);
+
+		/* Only existing files with stored durations get probed */
+		assert_eq!(vec!["v01-take.mp3", "v02-take.mp3", "v03-take.mp3", "v06-take.mp3"], probed);
+
+		let mismatches = report.mismatches();
+		assert_eq!(1, mismatches.len());
+		assert_eq!((2, Some(90_000), Some(84_500), Some(-5_500)),
+		           (mismatches[0].track_index, mismatches[0].stored_ms, mismatches[0].actual_ms, mismatches[0].delta_ms()));
+
+		assert_eq!((2, 1, 1, 1, 1), (report.count(&VerifyStatus::Match), report.count(&VerifyStatus::Mismatch),
+		                             report.count(&VerifyStatus::Missing), report.count(&VerifyStatus::NoStoredDuration),
+		                             report.count(&VerifyStatus::ProbeFailed(String::new()))));
+	}
+
+	#[test]
+	fn test_tolerance()
+	{
+		let dir = TempDir::new("duration-verify-tolerance");
+		let xspf = make_playlist(&dir, &[Some(60_000)]);
+		let mut probe = |_: &Track| -> Result<TrackDuration, String> { Ok(TrackDuration(60_500)) };
+
+		assert_eq!(VerifyStatus::Match, verify_durations(&xspf, 500, &mut probe).tracks[0].status);
+		assert_eq!(VerifyStatus::Mismatch, verify_durations(&xspf, 499, &mut probe).tracks[0].status);
+	}
+}
@@ -0,0 +1,840 @@
+/* Construction of the argument lists passed to FFMPEG
+ *
+ * Everything that runs ffmpeg builds its arguments here, so that the
+ * argument lists (and any escaping they need) can be tested without
+ * actually running ffmpeg.
+ */
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use track_name_info::TrackExtension;
+
+/* *************************************************** */
+/* Escaping */
+
+/* Escape a value for use as an option value in a filtergraph (e.g. "-filter_complex")
+ * NOTE: Filtergraphs get unescaped twice - once for the graph description itself,
+ *       then once more for each filter's options. So, both levels of escaping are needed.
+ *       See "Notes on filtergraph escaping" in the ffmpeg-filters docs.
+ */
+pub fn escape_filter_value(value: &str) -> String
+{
+	/* Level 1 - Filter option value (special chars: \ ' :) */
+	let mut option_escaped = String::with_capacity(value.len());
+	for c in value.chars() {
+		if c == '\\' || c == '\'' || c == ':' {
+			option_escaped.push('\\');
+		}
+		option_escaped.push(c);
+	}
+
+	/* Level 2 - Filtergraph description (special chars: \ ' [ ] , ;) */
+	let mut graph_escaped = String::with_capacity(option_escaped.len());
+	for c in option_escaped.chars() {
+		match c {
+			'\\' | '\'' | '[' | ']' | ',' | ';' => graph_escaped.push('\\'),
+			_ => {}
+		}
+		graph_escaped.push(c);
+	}
+	graph_escaped
+}
+
+/* *************************************************** */
+/* Conversion */
+
+/* Arguments for converting src_path to dst_path (with the format determined by the extension)
+ * - Input filename needs to come first
+ * - Output filename needs to go last
+ * - overwrite: Whether an existing file at dst_path gets replaced ("-y") or left alone, failing the convert ("-n").
+ *              One or the other always gets given, as otherwise ffmpeg stops to ask (waiting forever on stdin)
+ * NOTE: These are OsStrings so that the source path gets passed through intact, even if it isn't valid UTF-8
+ */
+pub fn convert_args(src_path: &Path, dst_path: &Path, extra_args: &[String], overwrite: bool) -> Vec<OsString>
+{
+	let mut args: Vec<OsString> = Vec::new();
+
+	args.push(OsString::from(if overwrite { "-y" } else { "-n" }));
+	args.push(OsString::from("-i"));
+	args.push(src_path.as_os_str().to_os_string());
+
+	for arg in extra_args {
+		/* Add each standard arg for this conversion operation */
+		args.push(OsString::from(arg));
+	}
+
+	args.push(dst_path.as_os_str().to_os_string());
+	args
+}
+
+/* ------------------------------------------- */
+/* Video Output */
+
+/* Size of the videos made with a waveform (see VideoSource::Waveform) */
+pub const WAVEFORM_VIDEO_SIZE: &'static str = "1280x720";
+
+/* Picture to go along with the audio, when converting to a video format (e.g. for uploading to video sites) */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum VideoSource {
+	/* Still image (e.g. cover art) shown for the whole track */
+	Cover(PathBuf),
+	/* Waveform of the track, drawn as it plays (using the "showwaves" filter) - in the given colour */
+	Waveform(String),
+}
+
+/* Can the format hold video? (i.e. the formats that need a VideoSource when converting) */
+pub fn is_video_format(format: &TrackExtension) -> bool
+{
+	matches!(*format, TrackExtension::mp4 | TrackExtension::mkv)
+}
+
+/* Arguments for converting src_path to a video at dst_path, with the picture coming from the given source
+ * (otherwise the same as convert_args() - extra_args are the audio settings, from build_ffmpeg_args())
+ */
+pub fn video_convert_args(src_path: &Path, dst_path: &Path, source: &VideoSource, extra_args: &[String], overwrite: bool)
+	-> Vec<OsString>
+{
+	let mut args: Vec<OsString> = Vec::new();
+	args.push(OsString::from(if overwrite { "-y" } else { "-n" }));
+
+	let video_args: Vec<String> = match *source {
+		VideoSource::Cover(ref image_path) => {
+			/* Image goes first (looped for as long as the audio lasts) */
+			args.push(OsString::from("-loop"));
+			args.push(OsString::from("1"));
+			args.push(OsString::from("-i"));
+			args.push(image_path.as_os_str().to_os_string());
+			args.push(OsString::from("-i"));
+			args.push(src_path.as_os_str().to_os_string());
+
+			["-map", "0:v", "-map", "1:a", "-c:v", "libx264", "-tune", "stillimage", "-pix_fmt", "yuv420p", "-shortest"]
+				.iter().map(|a| a.to_string()).collect()
+		},
+		VideoSource::Waveform(ref color) => {
+			args.push(OsString::from("-i"));
+			args.push(src_path.as_os_str().to_os_string());
+
+			let filter = format!("[0:a]showwaves=s={size}:mode=line:colors={color},format=yuv420p[v]",
+			                     size=WAVEFORM_VIDEO_SIZE, color=escape_filter_value(color));
+			vec!["-filter_complex".to_string(), filter,
+			     "-map".to_string(), "[v]".to_string(), "-map".to_string(), "0:a".to_string(),
+			     "-c:v".to_string(), "libx264".to_string()]
+		}
+	};
+
+	for arg in video_args.iter().chain(extra_args) {
+		args.push(OsString::from(arg));
+	}
+
+	args.push(dst_path.as_os_str().to_os_string());
+	args
+}
+
+/* ------------------------------------------- */
+/* Encoder Settings */
+
+/* Quality to encode at (as given by "--quality=high|medium|low") */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub enum AudioQuality {
+	High,
+	#[default]
+	Medium,
+	Low,
+}
+
+/* Usage: "high".parse::<AudioQuality>() */
+impl FromStr for AudioQuality {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<AudioQuality, Self::Err>
+	{
+		match s.trim().to_lowercase().as_ref() {
+			"high"   => Ok(AudioQuality::High),
+			"medium" => Ok(AudioQuality::Medium),
+			"low"    => Ok(AudioQuality::Low),
+			_        => Err(format!("Unknown quality '{}' (expected high, medium, or low)", s))
+		}
+	}
+}
+
+impl fmt::Display for AudioQuality {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			AudioQuality::High   => write!(f, "high"),
+			AudioQuality::Medium => write!(f, "medium"),
+			AudioQuality::Low    => write!(f, "low"),
+		}
+	}
+}
+
+/* Encoder to use for an output format, along with the option (and its value) giving the quality
+ * - mp3/ogg: VBR quality scale (lower = better for LAME, higher = better for Vorbis)
+ * - flac: Compression level (it's lossless, so this only trades encoding time for smaller files)
+ * - mp4/mkv: Bitrate of the AAC audio track (the video settings depend on the VideoSource - see video_convert_args())
+ * > returns None for formats that convert doesn't support
+ */
+pub fn encoder_settings(format: &TrackExtension, quality: AudioQuality) -> Option<(&'static str, &'static str, &'static str)>
+{
+	let pick = |high, medium, low| match quality {
+		AudioQuality::High   => high,
+		AudioQuality::Medium => medium,
+		AudioQuality::Low    => low,
+	};
+
+	match *format {
+		TrackExtension::mp3  => Some(("libmp3lame", "-qscale:a", pick("0", "4", "7"))),
+		TrackExtension::ogg  => Some(("libvorbis", "-qscale:a", pick("8", "5", "3"))),
+		TrackExtension::flac => Some(("flac", "-compression_level", pick("8", "5", "0"))),
+		TrackExtension::mp4 |
+		TrackExtension::mkv  => Some(("aac", "-b:a", pick("256k", "192k", "128k"))),
+		_                    => None
+	}
+}
+
+/* Do the args set a bitrate? (which takes the place of the VBR quality setting - see build_ffmpeg_args()) */
+fn sets_bitrate(args: &[String]) -> bool
+{
+	group_options(args).iter().any(|(option, _)| canonical_option(option) == "-b:a")
+}
+
+/* Args for converting to the given format, before the input/output paths get added (see convert_args())
+ * - Video gets dropped for the audio-only formats
+ * - The encoder and quality settings for the format come next
+ * - user_args (e.g. from "--bitrate", presets, and those passed to ffmpeg) go after them, so they can override them
+ *
+ * NOTE: For mp3/ogg, the encoders ignore a bitrate when a quality is given. So when the user args
+ *       give one, the quality setting gets left out instead.
+ */
+pub fn build_ffmpeg_args(format: &TrackExtension, quality: AudioQuality, user_args: &[String]) -> Vec<String>
+{
+	let mut args: Vec<String> = Vec::new();
+	if !is_video_format(format) {
+		/* Only audio feed */
+		args.push("-vn".to_string());
+	}
+
+	if let Some((codec, quality_option, quality_value)) = encoder_settings(format, quality) {
+		args.push("-codec:a".to_string());
+		args.push(codec.to_string());
+
+		if *format == TrackExtension::flac || !sets_bitrate(user_args) {
+			args.push(quality_option.to_string());
+			args.push(quality_value.to_string());
+		}
+	}
+
+	args.extend(user_args.iter().cloned());
+	args
+}
+
+/* ------------------------------------------- */
+/* Fades */
+
+/* Fade-in/out to apply to the start/end of each converted track (in seconds - 0 = no fade) */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub struct FadeOptions {
+	pub fade_in_secs: f64,
+	pub fade_out_secs: f64,
+}
+
+/* Audio filters that apply a set of fades to a single track */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct FadeFilters {
+	/* "afade" filters to apply (in order) */
+	pub filters: Vec<String>,
+	/* Was a fade-out wanted, but left out as the track's duration is unknown? */
+	pub fade_out_skipped: bool,
+}
+
+/* Seconds as a filter option value (e.g. 1.5 -> "1.5", 3.0 -> "3") */
+fn format_secs(secs: f64) -> String
+{
+	let s = format!("{:.3}", secs);
+	match s.trim_end_matches('0').trim_end_matches('.') {
+		""      => "0".to_string(),
+		trimmed => trimmed.to_string()
+	}
+}
+
+/* Build the "afade" filters for a track
+ * - duration_ms: Length of the track (None if unknown)
+ *
+ * The fade-out has to start at a fixed time, so it needs the duration. Without it, only the fade-in gets applied.
+ * For tracks shorter than the two fades put together, both get scaled down proportionally so they don't overlap.
+ */
+pub fn fade_filters(fade: &FadeOptions, duration_ms: Option<i64>) -> FadeFilters
+{
+	let mut fade_in = fade.fade_in_secs.max(0.0);
+	let mut fade_out = fade.fade_out_secs.max(0.0);
+	let duration_secs = duration_ms.map(|ms| ms.max(0) as f64 / 1000.0);
+
+	if let Some(duration) = duration_secs {
+		let total = fade_in + fade_out;
+		if total > duration {
+			let scale = duration / total;
+			fade_in *= scale;
+			fade_out *= scale;
+		}
+	}
+
+	let mut filters = Vec::new();
+	if fade_in > 0.0 {
+		filters.push(format!("afade=t=in:st=0:d={}", format_secs(fade_in)));
+	}
+
+	let mut fade_out_skipped = false;
+	if fade_out > 0.0 {
+		match duration_secs {
+			Some(duration) => {
+				filters.push(format!("afade=t=out:st={0}:d={1}", format_secs(duration - fade_out), format_secs(fade_out)));
+			},
+			None => fade_out_skipped = true
+		}
+	}
+
+	FadeFilters { filters: filters, fade_out_skipped: fade_out_skipped }
+}
+
+/* Add extra audio filters to a list of ffmpeg args, as a single "-af" chain
+ *
+ * ffmpeg only uses the last audio filter option given, so any "-af"/"-filter:a" already present
+ * (e.g. supplied by the user) get merged into one chain, with the extra filters going after them.
+ */
+pub fn with_audio_filters(args: &[String], extra_filters: &[String]) -> Vec<String>
+{
+	if extra_filters.is_empty() {
+		return args.to_vec();
+	}
+
+	let mut result: Vec<String> = Vec::new();
+	let mut chain: Vec<String> = Vec::new();
+
+	let mut i = 0;
+	while i < args.len() {
+		if (args[i] == "-af" || args[i] == "-filter:a") && (i + 1 < args.len()) {
+			chain.push(args[i + 1].clone());
+			i += 2;
+		}
+		else {
+			result.push(args[i].clone());
+			i += 1;
+		}
+	}
+	chain.extend(extra_filters.iter().cloned());
+
+	result.push("-af".to_string());
+	result.push(chain.join(","));
+	result
+}
+
+/* ------------------------------------------- */
+/* Settings Fingerprints */
+
+/* Options that ffmpeg allows to be given several times, where every occurrence counts
+ * and the order they're given in matters (e.g. "-map" determines the order of streams)
+ */
+const REPEATABLE_OPTIONS: [&'static str; 4] = ["-map", "-metadata", "-af", "-filter:a"];
+
+/* Does the given arg look like an option name (instead of a value)? */
+fn is_option_name(arg: &str) -> bool
+{
+	arg.starts_with('-') && arg.len() > 1 && arg.parse::<f64>().is_err()
+}
+
+/* Group args into "<option> <value>" pairs (or lone flags like "-vn", with no value) */
+fn group_options(args: &[String]) -> Vec<(String, Option<String>)>
+{
+	let mut groups = Vec::new();
+
+	let mut i = 0;
+	while i < args.len() {
+		let option = args[i].clone();
+		let value = match args.get(i + 1) {
+			Some(v) if is_option_name(&option) && !is_option_name(v) => Some(v.clone()),
+			_ => None
+		};
+		i += if value.is_some() { 2 } else { 1 };
+
+		groups.push((option, value));
+	}
+	groups
+}
+
+/* Normalise the settings passed to ffmpeg for a conversion, so that equivalent lists compare equal
+ *
+ * The args get grouped into "<option> <value>" pairs (or lone flags like "-vn"). Then:
+ * - Most output options apply to the output file regardless of where they appear, so the order
+ *   of those is irrelevant. They get sorted.
+ * - When such an option is given more than once, ffmpeg uses the last one. So only that one is kept.
+ * - For REPEATABLE_OPTIONS, every occurrence counts, and the order matters. Those are kept as-is,
+ *   in their original relative order (after all the others).
+ *
+ * NOTE: The input/output paths should not be included (see convert_args()), as they aren't settings
+ */
+pub fn normalize_settings(args: &[String]) -> Vec<String>
+{
+	let mut single : Vec<(String, Option<String>)> = Vec::new();
+	let mut repeated : Vec<String> = Vec::new();
+
+	for (option, value) in group_options(args) {
+		if REPEATABLE_OPTIONS.contains(&option.as_str()) {
+			repeated.push(option);
+			repeated.extend(value);
+		}
+		else {
+			/* Last one wins */
+			single.retain(|&(ref o, _)| *o != option);
+			single.push((option, value));
+		}
+	}
+
+	single.sort();
+
+	let mut result : Vec<String> = Vec::new();
+	for (option, value) in single {
+		result.push(option);
+		result.extend(value);
+	}
+	result.extend(repeated);
+	result
+}
+
+/* Stable fingerprint of the settings used for a conversion (see normalize_settings())
+ * NOTE: This uses FNV-1a instead of the std hashers, as their output isn't guaranteed
+ *       to stay the same between Rust versions (and these get saved to disk)
+ */
+pub fn settings_fingerprint(args: &[String]) -> String
+{
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for arg in normalize_settings(args).iter() {
+		/* NUL separator, so that ["-a", "bc"] and ["-ab", "c"] differ */
+		for &byte in arg.as_bytes().iter().chain(&[0u8]) {
+			hash ^= byte as u64;
+			hash = hash.wrapping_mul(0x100000001b3);
+		}
+	}
+	format!("{:016x}", hash)
+}
+
+/* ------------------------------------------- */
+/* Merging */
+
+/* Options that ffmpeg has several names for (alias, standard name) */
+const OPTION_ALIASES: [(&'static str, &'static str); 4] = [
+	("-ab", "-b:a"),
+	("-acodec", "-c:a"),
+	("-codec:a", "-c:a"),
+	("-filter:a", "-af"),
+];
+
+/* Standard name for an option (i.e. with any alias replaced) */
+fn canonical_option(option: &str) -> &str
+{
+	OPTION_ALIASES.iter()
+	              .find(|(alias, _)| *alias == option)
+	              .map_or(option, |&(_, name)| name)
+}
+
+/* An option given different values by two sets of args */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ArgConflict {
+	pub option: String,
+	/* Values given by each set of args (None for lone flags) */
+	pub base_value: Option<String>,
+	pub extra_value: Option<String>,
+}
+
+impl fmt::Display for ArgConflict {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		let show = |value: &Option<String>| value.as_ref().map_or("(flag)".to_string(), |v| v.clone());
+		write!(f, "{0} {1} vs {0} {2}", self.option, show(&self.base_value), show(&self.extra_value))
+	}
+}
+
+/* Add extra args (e.g. from a preset) to a list of ffmpeg args, without repeating or contradicting any of them
+ * - Options already in the base args with the same value (under any of their names) don't get added again
+ * - Options already in the base args with a different value are conflicts (as ffmpeg would just use whichever came last)
+ * - REPEATABLE_OPTIONS get added as-is
+ * > returns the merged args, or all the conflicts found
+ */
+pub fn merge_args(base: &[String], extra: &[String]) -> Result<Vec<String>, Vec<ArgConflict>>
+{
+	let base_options = group_options(base);
+	let mut merged = base.to_vec();
+	let mut conflicts = Vec::new();
+
+	for (option, value) in group_options(extra) {
+		let name = canonical_option(&option);
+		let existing = if REPEATABLE_OPTIONS.contains(&name) {
+			None
+		}
+		else {
+			/* Last one wins, so that's the one that counts */
+			base_options.iter().rev().find(|(o, _)| canonical_option(o) == name)
+		};
+
+		match existing {
+			Some((_, base_value)) if *base_value == value => {},
+			Some((_, base_value)) => {
+				conflicts.push(ArgConflict { option: name.to_string(), base_value: base_value.clone(), extra_value: value });
+			},
+			None => {
+				merged.push(option);
+				merged.extend(value);
+			}
+		}
+	}
+
+	if conflicts.is_empty() { Ok(merged) } else { Err(conflicts) }
+}
+
+/* *************************************************** */
+/* Waveform Images */
+
+/* Default colour used for drawing waveforms */
+pub const DEFAULT_WAVEFORM_COLOR: &'static str = "steelblue";
+
+/* Dimensions (in pixels) of a waveform image */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub struct WaveformSize {
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Default for WaveformSize {
+	fn default() -> WaveformSize
+	{
+		WaveformSize { width: 600, height: 120 }
+	}
+}
+
+/* Usage: "600x120".parse::<WaveformSize>() */
+impl FromStr for WaveformSize {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<WaveformSize, Self::Err>
+	{
+		let mut parts = s.splitn(2, ['x', 'X']);
+		let width = parts.next().and_then(|w| w.trim().parse::<u32>().ok());
+		let height = parts.next().and_then(|h| h.trim().parse::<u32>().ok());
+
+		match (width, height) {
+			(Some(w), Some(h)) if w > 0 && h > 0 => Ok(WaveformSize { width: w, height: h }),
+			_ => Err(format!("Expected size as <width>x<height> (e.g. 600x120), got '{}'", s))
+		}
+	}
+}
+
+impl fmt::Display for WaveformSize {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{0}x{1}", self.width, self.height)
+	}
+}
+
+/* Settings for rendering waveform images */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct WaveformOptions {
+	pub size: WaveformSize,
+	/* Colour of the waveform (any colour ffmpeg understands, e.g. "steelblue" or "0x3060c0") */
+	pub color: String,
+}
+
+impl Default for WaveformOptions {
+	fn default() -> WaveformOptions
+	{
+		WaveformOptions {
+			size: WaveformSize::default(),
+			color: DEFAULT_WAVEFORM_COLOR.to_string(),
+		}
+	}
+}
+
+/* Arguments for rendering a PNG of the waveform of src_path to dst_path, using the "showwavespic" filter */
+pub fn waveform_image_args(src_path: &Path, dst_path: &Path, options: &WaveformOptions) -> Vec<OsString>
+{
+	let filter = format!("showwavespic=s={size}:colors={color}",
+	                     size=escape_filter_value(&options.size.to_string()),
+	                     color=escape_filter_value(&options.color));
+
+	vec![
+		OsString::from("-y"),  /* Overwrite images left over from previous runs */
+		OsString::from("-i"),
+		src_path.as_os_str().to_os_string(),
+		OsString::from("-filter_complex"),
+		OsString::from(filter),
+		OsString::from("-frames:v"),
+		OsString::from("1"),
+		dst_path.as_os_str().to_os_string(),
+	]
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/* Helper - Convert args to strings for easier comparisons */
+	fn as_strings(args: Vec<OsString>) -> Vec<String>
+	{
+		args.into_iter().map(|a| a.to_string_lossy().into_owned()).collect()
+	}
+
+	#[test]
+	fn test_escape_filter_value()
+	{
+		assert_eq!("steelblue", escape_filter_value("steelblue"));
+		assert_eq!("red|0x3060c0", escape_filter_value("red|0x3060c0"));
+
+		assert_eq!(r"a\\:b", escape_filter_value("a:b"));
+		assert_eq!(r"a\,b\;c", escape_filter_value("a,b;c"));
+		assert_eq!(r"\[x\]", escape_filter_value("[x]"));
+		assert_eq!(r"it\\\'s", escape_filter_value("it's"));
+		assert_eq!(r"\\\\", escape_filter_value(r"\"));
+	}
+
+	#[test]
+	fn test_waveform_size()
+	{
+		assert_eq!(Ok(WaveformSize { width: 600, height: 120 }), "600x120".parse::<WaveformSize>());
+		assert_eq!(Ok(WaveformSize { width: 1920, height: 200 }), "1920X200".parse::<WaveformSize>());
+		assert_eq!("640x80", WaveformSize { width: 640, height: 80 }.to_string());
+
+		assert!("600".parse::<WaveformSize>().is_err());
+		assert!("0x120".parse::<WaveformSize>().is_err());
+		assert!("widexhigh".parse::<WaveformSize>().is_err());
+	}
+
+	#[test]
+	fn test_waveform_image_args()
+	{
+		let options = WaveformOptions {
+			size: WaveformSize { width: 800, height: 100 },
+			color: "white:0.5".to_string(),
+		};
+		let args = waveform_image_args(Path::new("in/v01-tranquil.mp3"), Path::new("out/Track_01.png"), &options);
+
+		assert_eq!(vec!["-y", "-i", "in/v01-tranquil.mp3",
+		                "-filter_complex", r"showwavespic=s=800x100:colors=white\\:0.5",
+		                "-frames:v", "1",
+		                "out/Track_01.png"],
+		           as_strings(args));
+	}
+
+	/* Helper - Make a list of args from a string */
+	fn args(s: &str) -> Vec<String>
+	{
+		s.split_whitespace().map(|a| a.to_string()).collect()
+	}
+
+	#[test]
+	fn test_normalize_settings()
+	{
+		/* Order of ordinary options doesn't matter */
+		assert_eq!(normalize_settings(&args("-vn -b:a 192k -ar 44100")),
+		           normalize_settings(&args("-ar 44100 -vn -b:a 192k")));
+		assert_eq!(args("-ar 44100 -b:a 192k -vn"), normalize_settings(&args("-vn -b:a 192k -ar 44100")));
+
+		/* Last one wins */
+		assert_eq!(args("-b:a 256k -vn"), normalize_settings(&args("-vn -b:a 192k -b:a 256k")));
+
+		/* Negative numbers are values, not options */
+		assert_eq!(args("-itsoffset -1.5"), normalize_settings(&args("-itsoffset -1.5")));
+
+		/* Order of repeatable options does matter */
+		assert_eq!(args("-vn -map 0:a:1 -map 0:a:0"), normalize_settings(&args("-map 0:a:1 -vn -map 0:a:0")));
+		assert!(normalize_settings(&args("-map 0:a:1 -map 0:a:0")) != normalize_settings(&args("-map 0:a:0 -map 0:a:1")));
+	}
+
+	#[test]
+	fn test_merge_args()
+	{
+		/* New options get added after the base ones */
+		assert_eq!(Ok(args("-vn -b:a 192k -ac 1")), merge_args(&args("-vn -b:a 192k"), &args("-ac 1")));
+		assert_eq!(Ok(args("-ac 1")), merge_args(&[], &args("-ac 1")));
+		assert_eq!(Ok(args("-vn")), merge_args(&args("-vn"), &[]));
+
+		/* Options already given the same value (under any name) aren't repeated */
+		assert_eq!(Ok(args("-vn -b:a 64k -ac 1")), merge_args(&args("-vn -b:a 64k"), &args("-ac 1 -b:a 64k -vn")));
+		assert_eq!(Ok(args("-ab 64k")), merge_args(&args("-ab 64k"), &args("-b:a 64k")));
+
+		/* Different values conflict (including the last value given, if the base has several) */
+		assert_eq!(Err(vec![ArgConflict { option: "-b:a".to_string(), base_value: Some("192k".to_string()), extra_value: Some("320k".to_string()) }]),
+		           merge_args(&args("-vn -b:a 192k"), &args("-b:a 320k")));
+		assert_eq!(Ok(args("-b:a 320k -b:a 192k")), merge_args(&args("-b:a 320k -b:a 192k"), &args("-b:a 192k")));
+		let conflicts = merge_args(&args("-acodec libmp3lame -ac 2"), &args("-c:a flac -ac 1 -ar 48000")).unwrap_err();
+		assert_eq!(vec!["-c:a libmp3lame vs -c:a flac", "-ac 2 vs -ac 1"],
+		           conflicts.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+
+		/* Repeatable options never conflict */
+		assert_eq!(Ok(args("-map 0:a:0 -map 0:a:1")), merge_args(&args("-map 0:a:0"), &args("-map 0:a:1")));
+	}
+
+	#[test]
+	fn test_settings_fingerprint()
+	{
+		assert_eq!(settings_fingerprint(&args("-vn -b:a 192k")), settings_fingerprint(&args("-b:a 192k -vn")));
+		assert!(settings_fingerprint(&args("-vn -b:a 192k")) != settings_fingerprint(&args("-vn -b:a 256k")));
+
+		/* Stable across runs/versions (FNV-1a of the empty list) */
+		assert_eq!("cbf29ce484222325", settings_fingerprint(&[]));
+		assert_eq!(16, settings_fingerprint(&args("-vn")).len());
+	}
+
+	#[test]
+	fn test_fade_filters()
+	{
+		let fade = FadeOptions { fade_in_secs: 1.5, fade_out_secs: 3.0 };
+
+		let result = fade_filters(&fade, Some(180_000));
+		assert_eq!(vec!["afade=t=in:st=0:d=1.5", "afade=t=out:st=177:d=3"], result.filters);
+		assert!(!result.fade_out_skipped);
+
+		/* Only one of them */
+		let fade_in_only = FadeOptions { fade_in_secs: 2.0, fade_out_secs: 0.0 };
+		assert_eq!(vec!["afade=t=in:st=0:d=2"], fade_filters(&fade_in_only, Some(180_000)).filters);
+	}
+
+	/* Fades on tracks shorter than both fades together get scaled down to fit */
+	#[test]
+	fn test_fade_filters_short_track()
+	{
+		let fade = FadeOptions { fade_in_secs: 1.5, fade_out_secs: 3.0 };
+
+		/* 3s track - 4.5s of fades scaled by 2/3 */
+		let result = fade_filters(&fade, Some(3_000));
+		assert_eq!(vec!["afade=t=in:st=0:d=1", "afade=t=out:st=1:d=2"], result.filters);
+
+		/* Fade-out only, starting right at the beginning */
+		let fade_out_only = FadeOptions { fade_in_secs: 0.0, fade_out_secs: 3.0 };
+		assert_eq!(vec!["afade=t=out:st=0:d=2"], fade_filters(&fade_out_only, Some(2_000)).filters);
+
+		/* Exactly long enough - untouched */
+		let result = fade_filters(&fade, Some(4_500));
+		assert_eq!(vec!["afade=t=in:st=0:d=1.5", "afade=t=out:st=1.5:d=3"], result.filters);
+	}
+
+	/* Without a duration, only the fade-in can be done */
+	#[test]
+	fn test_fade_filters_missing_duration()
+	{
+		let fade = FadeOptions { fade_in_secs: 1.5, fade_out_secs: 3.0 };
+		let result = fade_filters(&fade, None);
+		assert_eq!(vec!["afade=t=in:st=0:d=1.5"], result.filters);
+		assert!(result.fade_out_skipped);
+
+		/* Nothing lost if no fade-out was wanted anyway */
+		let fade_in_only = FadeOptions { fade_in_secs: 1.5, fade_out_secs: 0.0 };
+		assert!(!fade_filters(&fade_in_only, None).fade_out_skipped);
+	}
+
+	#[test]
+	fn test_with_audio_filters()
+	{
+		let fades = vec!["afade=t=in:st=0:d=1".to_string()];
+
+		assert_eq!(args("-vn -b:a 192k -af afade=t=in:st=0:d=1"), with_audio_filters(&args("-vn -b:a 192k"), &fades));
+
+		/* User filters come first, all in the one chain */
+		assert_eq!(args("-vn -af volume=2,highpass=f=80,afade=t=in:st=0:d=1"),
+		           with_audio_filters(&args("-af volume=2 -vn -filter:a highpass=f=80"), &fades));
+
+		/* No extra filters - unchanged */
+		assert_eq!(args("-vn -af volume=2"), with_audio_filters(&args("-vn -af volume=2"), &[]));
+	}
+
+	#[test]
+	fn test_convert_args()
+	{
+		let extra_args = ["-vn".to_string(), "-q:a".to_string(), "2".to_string()];
+		let args = convert_args(Path::new("in/v01-tranquil.flac"), Path::new("out/Track_01.mp3"), &extra_args, false);
+		assert_eq!(vec!["-n", "-i", "in/v01-tranquil.flac", "-vn", "-q:a", "2", "out/Track_01.mp3"],
+		           as_strings(args));
+
+		let args = convert_args(Path::new("in/v01-tranquil.flac"), Path::new("out/Track_01.mp3"), &extra_args, true);
+		assert_eq!("-y", as_strings(args)[0]);
+	}
+
+	#[test]
+	fn test_audio_quality()
+	{
+		assert_eq!(Ok(AudioQuality::High), "high".parse::<AudioQuality>());
+		assert_eq!(Ok(AudioQuality::Low), " LOW ".parse::<AudioQuality>());
+		assert!("best".parse::<AudioQuality>().is_err());
+		assert_eq!(AudioQuality::Medium, AudioQuality::default());
+	}
+
+	#[test]
+	fn test_build_ffmpeg_args()
+	{
+		let no_args: Vec<String> = Vec::new();
+		let args = |s: &str| -> Vec<String> { s.split_whitespace().map(|a| a.to_string()).collect() };
+
+		/* Each format gets its encoder, and the quality setting for it */
+		assert_eq!(args("-vn -codec:a libmp3lame -qscale:a 0"), build_ffmpeg_args(&TrackExtension::mp3, AudioQuality::High, &no_args));
+		assert_eq!(args("-vn -codec:a libmp3lame -qscale:a 4"), build_ffmpeg_args(&TrackExtension::mp3, AudioQuality::Medium, &no_args));
+		assert_eq!(args("-vn -codec:a libmp3lame -qscale:a 7"), build_ffmpeg_args(&TrackExtension::mp3, AudioQuality::Low, &no_args));
+		assert_eq!(args("-vn -codec:a libvorbis -qscale:a 8"), build_ffmpeg_args(&TrackExtension::ogg, AudioQuality::High, &no_args));
+		assert_eq!(args("-vn -codec:a libvorbis -qscale:a 3"), build_ffmpeg_args(&TrackExtension::ogg, AudioQuality::Low, &no_args));
+		assert_eq!(args("-vn -codec:a flac -compression_level 8"), build_ffmpeg_args(&TrackExtension::flac, AudioQuality::High, &no_args));
+		assert_eq!(args("-vn -codec:a flac -compression_level 0"), build_ffmpeg_args(&TrackExtension::flac, AudioQuality::Low, &no_args));
+
+		/* User args go last, so they win */
+		assert_eq!(args("-vn -codec:a libvorbis -qscale:a 5 -ac 1 -qscale:a 6"),
+		           build_ffmpeg_args(&TrackExtension::ogg, AudioQuality::Medium, &args("-ac 1 -qscale:a 6")));
+
+		/* A bitrate takes the place of the quality setting (but not the compression level for flac) */
+		assert_eq!(args("-vn -codec:a libmp3lame -b:a 192k"), build_ffmpeg_args(&TrackExtension::mp3, AudioQuality::High, &args("-b:a 192k")));
+		assert_eq!(args("-vn -codec:a libvorbis -ab 96k"), build_ffmpeg_args(&TrackExtension::ogg, AudioQuality::High, &args("-ab 96k")));
+		assert_eq!(args("-vn -codec:a flac -compression_level 5 -b:a 192k"),
+		           build_ffmpeg_args(&TrackExtension::flac, AudioQuality::Medium, &args("-b:a 192k")));
+
+		/* Video formats keep their video, with AAC audio */
+		assert_eq!(args("-codec:a aac -b:a 192k"), build_ffmpeg_args(&TrackExtension::mp4, AudioQuality::Medium, &no_args));
+		assert_eq!(args("-codec:a aac -b:a 96k"), build_ffmpeg_args(&TrackExtension::mkv, AudioQuality::High, &args("-b:a 96k")));
+
+		/* Unsupported formats only get the user args */
+		assert_eq!(args("-vn -ac 1"), build_ffmpeg_args(&TrackExtension::Unknown("wav".to_string()), AudioQuality::High, &args("-ac 1")));
+	}
+
+	#[test]
+	fn test_video_convert_args()
+	{
+		let audio_args = ["-codec:a".to_string(), "aac".to_string()];
+
+		let cover = VideoSource::Cover(PathBuf::from("art/cover.png"));
+		let args = video_convert_args(Path::new("in/v01-tranquil.flac"), Path::new("out/Track_01.mp4"), &cover, &audio_args, false);
+		assert_eq!(vec!["-n", "-loop", "1", "-i", "art/cover.png", "-i", "in/v01-tranquil.flac",
+		                "-map", "0:v", "-map", "1:a", "-c:v", "libx264", "-tune", "stillimage", "-pix_fmt", "yuv420p", "-shortest",
+		                "-codec:a", "aac", "out/Track_01.mp4"],
+		           as_strings(args));
+
+		let waveform = VideoSource::Waveform("steelblue".to_string());
+		let args = video_convert_args(Path::new("in/v01-tranquil.flac"), Path::new("out/Track_01.mkv"), &waveform, &audio_args, true);
+		assert_eq!(vec!["-y", "-i", "in/v01-tranquil.flac",
+		                "-filter_complex", "[0:a]showwaves=s=1280x720:mode=line:colors=steelblue,format=yuv420p[v]",
+		                "-map", "[v]", "-map", "0:a", "-c:v", "libx264", "-codec:a", "aac", "out/Track_01.mkv"],
+		           as_strings(args));
+
+		assert!(is_video_format(&TrackExtension::mp4));
+		assert!(!is_video_format(&TrackExtension::mp3));
+	}
+}
@@ -0,0 +1,186 @@
+/* Setting the modification times of copied files
+ *
+ * fs::copy() doesn't keep the source file's modification time everywhere (it depends on the platform),
+ * so tools that look at timestamps (e.g. backup tools) would see every copy as a new file. Instead, the
+ * times of copied files get set explicitly - either to match the source file, or (with "--stamp-from-date")
+ * to the session date that the track is filed under.
+ */
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use diagnostics::Diagnostics;
+use track_date::{self, SessionDate};
+use xspf_parser::Track;
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "timestamps";
+
+/* Hour of the day (local time) used for times set from session dates */
+pub const SESSION_HOUR: i64 = 12;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/* *************************************************** */
+
+/* What the copied files' modification times get set to */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum StampMode {
+	/* Same as the source file */
+	PreserveSource,
+	/* Midday (local time) on the track's session date, or the source file's time for tracks without a date */
+	SessionDate,
+}
+
+/* Midday (local time) on the given session date */
+pub fn session_midday(session: &SessionDate) -> SystemTime
+{
+	let local_secs = session.days_since_epoch() * SECS_PER_DAY + SESSION_HOUR * 3600;
+	let secs = local_secs - track_date::local_utc_offset_secs(local_secs);
+
+	if secs >= 0 {
+		UNIX_EPOCH + Duration::from_secs(secs as u64)
+	}
+	else {
+		UNIX_EPOCH - Duration::from_secs((-secs) as u64)
+	}
+}
+
+/* Set the modification time of a file (leaving its access time alone) */
+pub fn set_mtime(path: &Path, mtime: SystemTime) -> io::Result<()>
+{
+	let file = File::options().write(true).open(path)?;
+	file.set_modified(mtime)
+}
+
+/* *************************************************** */
+
+/* Sets the modification times of the files copied during a run, keeping track of any problems */
+pub struct Stamper {
+	pub mode: StampMode,
+
+	/* Number of tracks that got the source file's time, as they didn't have a session date (SessionDate mode only) */
+	pub undated: usize,
+
+	/* Files whose times couldn't be set */
+	pub diagnostics: Diagnostics,
+}
+
+impl Stamper {
+	pub fn new(mode: StampMode) -> Stamper
+	{
+		Stamper {
+			mode: mode,
+			undated: 0,
+			diagnostics: Diagnostics::new(),
+		}
+	}
+
+	/* Set the modification time of a copy of the track's file (at dst_path)
+	 * NOTE: Failures are only recorded (the copy itself is still fine)
+	 */
+	pub fn stamp(&mut self, track_idx: usize, track: &Track, dst_path: &Path)
+	{
+		let session = match self.mode {
+			StampMode::SessionDate    => track.date.session_date(),
+			StampMode::PreserveSource => None,
+		};
+		if self.mode == StampMode::SessionDate && session.is_none() {
+			self.undated += 1;
+		}
+
+		let mtime = match session {
+			Some(session) => Ok(session_midday(&session)),
+			None          => track.file_path().metadata().and_then(|m| m.modified())
+		};
+
+		if let Err(e) = mtime.and_then(|mtime| set_mtime(dst_path, mtime)) {
+			self.diagnostics.warn_track(CATEGORY, track_idx,
+				format!("Couldn't set modification time of '{0}' - {1}", dst_path.display(), e));
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use test_utils::TempDir;
+
+	fn mtime(path: &Path) -> SystemTime
+	{
+		fs::metadata(path).unwrap().modified().unwrap()
+	}
+
+	/* Copy a file from the fixture, and stamp it */
+	fn copy_and_stamp(stamper: &mut Stamper, track_idx: usize, src: &Path, dst: &Path)
+	{
+		let track = Track::from_filepath(&src.to_string_lossy()).unwrap();
+		fs::copy(src, dst).unwrap();
+		stamper.stamp(track_idx, &track, dst);
+	}
+
+	#[test]
+	fn test_preserve_source_mtime()
+	{
+		let dir = TempDir::new("timestamps-preserve");
+		let src = dir.write_file("music/20170403/v01-tranquil.mp3", b"tranquil");
+		let src_mtime = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+		set_mtime(&src, src_mtime).unwrap();
+
+		let dst = dir.path().join("Track_01.mp3");
+		let mut stamper = Stamper::new(StampMode::PreserveSource);
+		copy_and_stamp(&mut stamper, 0, &src, &dst);
+
+		assert_eq!(src_mtime, mtime(&dst));
+		assert!(stamper.diagnostics.is_empty());
+	}
+
+	#[test]
+	fn test_stamp_from_session_date()
+	{
+		let dir = TempDir::new("timestamps-session");
+		let dated = dir.write_file("music/20170403/v01-tranquil.mp3", b"tranquil");
+		let undated = dir.write_file("music/misc/v02-moose.mp3", b"moose");
+		let src_mtime = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+		set_mtime(&dated, src_mtime).unwrap();
+		set_mtime(&undated, src_mtime).unwrap();
+
+		let mut stamper = Stamper::new(StampMode::SessionDate);
+		copy_and_stamp(&mut stamper, 0, &dated, &dir.path().join("Track_01.mp3"));
+		copy_and_stamp(&mut stamper, 1, &undated, &dir.path().join("Track_02.mp3"));
+
+		/* Dated track - midday on the session date (in local time) */
+		let session = SessionDate::new(2017, 4, 3).unwrap();
+		let expected = session_midday(&session);
+		assert_eq!(expected, mtime(&dir.path().join("Track_01.mp3")));
+
+		let secs = expected.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+		let local_secs = secs + track_date::local_utc_offset_secs(secs);
+		assert_eq!((session.days_since_epoch(), SESSION_HOUR * 3600), (local_secs / SECS_PER_DAY, local_secs % SECS_PER_DAY));
+
+		/* Undated track - keeps the source file's time */
+		assert_eq!(src_mtime, mtime(&dir.path().join("Track_02.mp3")));
+		assert_eq!(1, stamper.undated);
+		assert!(stamper.diagnostics.is_empty());
+	}
+
+	#[test]
+	fn test_stamp_failure()
+	{
+		let dir = TempDir::new("timestamps-failure");
+		let track = Track::from_filepath("music/20170403/v01-tranquil.mp3").unwrap();
+
+		let mut stamper = Stamper::new(StampMode::SessionDate);
+		stamper.stamp(3, &track, &dir.path().join("missing.mp3"));
+
+		assert_eq!(1, stamper.diagnostics.len());
+		assert_eq!(Some(3), stamper.diagnostics.entries[0].track_index);
+	}
+}
@@ -0,0 +1,763 @@
+/* Track filter expressions
+ *
+ * A small expression language for selecting tracks, e.g.
+ *     type=VL and duration>2:00 and (date>=2017-06-01 or name~moose)
+ *
+ * Syntax:
+ *   - Comparisons: <field> <op> <value>
+ *       =, !=            Equality (text fields other than paths/names are case insensitive)
+ *       >, >=, <, <=     Ordering (only for durations, dates, and numbers)
+ *       ~                Substring match (case insensitive)
+ *       ~~               Regex match
+ *   - Boolean operators: and, or, not  (in order of increasing precedence), and parentheses
+ *   - Values containing spaces or special characters (e.g. parentheses) need to be quoted
+ *     with '...' or "..."
+ *
+ * Field names are those from TrackField. Values are checked against the kind of field they're
+ * compared against when the expression is parsed, so that mistakes get reported up front
+ * instead of silently matching nothing.
+ */
+extern crate regex;
+use self::regex::Regex;
+
+use std::fmt;
+
+use track_date::SessionDate;
+use track_duration::TrackDuration;
+use track_field::{FieldKind, TrackField};
+use xspf_parser::Track;
+
+/* *************************************************** */
+/* Errors */
+
+/* Problem with a filter expression */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct FilterError {
+	/* Offset (in bytes) of the offending part of the expression */
+	pub position: usize,
+	/* Description of the problem */
+	pub message: String,
+}
+
+impl FilterError {
+	fn new(position: usize, message: String) -> FilterError
+	{
+		FilterError { position: position, message: message }
+	}
+
+	/* Show the error along with the expression, and a marker pointing at the problem */
+	pub fn describe(&self, expr: &str) -> String
+	{
+		let marker_offset = expr[.. self.position.min(expr.len())].chars().count();
+		format!("{0}\n    {1}\n    {2}^", self, expr, " ".repeat(marker_offset))
+	}
+}
+
+impl fmt::Display for FilterError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "Invalid filter expression - {0} (at position {1})", self.message, self.position)
+	}
+}
+
+/* *************************************************** */
+/* Tokens */
+
+/* Comparison Operators */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum CompareOp {
+	Eq,
+	NotEq,
+	Greater,
+	GreaterEq,
+	Less,
+	LessEq,
+	Contains,
+	Regex,
+}
+
+impl CompareOp {
+	fn symbol(&self) -> &'static str
+	{
+		match *self {
+			CompareOp::Eq        => "=",
+			CompareOp::NotEq     => "!=",
+			CompareOp::Greater   => ">",
+			CompareOp::GreaterEq => ">=",
+			CompareOp::Less      => "<",
+			CompareOp::LessEq    => "<=",
+			CompareOp::Contains  => "~",
+			CompareOp::Regex     => "~~",
+		}
+	}
+
+	/* Is this one of the ordering operators? */
+	fn is_ordering(&self) -> bool
+	{
+		elem!(*self, CompareOp::Greater, CompareOp::GreaterEq, CompareOp::Less, CompareOp::LessEq)
+	}
+
+	/* Is this one of the text matching operators? */
+	fn is_matching(&self) -> bool
+	{
+		elem!(*self, CompareOp::Contains, CompareOp::Regex)
+	}
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+enum Token {
+	LParen,
+	RParen,
+	Op(CompareOp),
+	/* Word/value - The flag indicates whether it was quoted (i.e. can never be a keyword) */
+	Word(String, bool),
+}
+
+/* Token, along with where it was found */
+#[derive(Debug)]
+struct Spanned {
+	token: Token,
+	pos: usize,
+}
+
+/* Characters which end an unquoted word */
+fn is_word_terminator(c: char) -> bool
+{
+	c.is_whitespace() || elem!(c, '(', ')', '=', '!', '<', '>', '~', '\'', '"')
+}
+
+/* Split the expression into tokens */
+fn tokenize(expr: &str) -> Result<Vec<Spanned>, FilterError>
+{
+	let mut tokens = Vec::new();
+	let mut chars = expr.char_indices().peekable();
+
+	while let Some(&(pos, c)) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+		}
+		else if c == '(' || c == ')' {
+			chars.next();
+			let token = if c == '(' { Token::LParen } else { Token::RParen };
+			tokens.push(Spanned { token: token, pos: pos });
+		}
+		else if elem!(c, '=', '!', '<', '>', '~') {
+			chars.next();
+			let next = chars.peek().map(|&(_, n)| n);
+
+			let op = match (c, next) {
+				('=', _)         => CompareOp::Eq,
+				('!', Some('=')) => { chars.next(); CompareOp::NotEq },
+				('>', Some('=')) => { chars.next(); CompareOp::GreaterEq },
+				('>', _)         => CompareOp::Greater,
+				('<', Some('=')) => { chars.next(); CompareOp::LessEq },
+				('<', _)         => CompareOp::Less,
+				('~', Some('~')) => { chars.next(); CompareOp::Regex },
+				('~', _)         => CompareOp::Contains,
+				_                => {
+					return Err(FilterError::new(pos, format!("unexpected '{}'", c)));
+				}
+			};
+			tokens.push(Spanned { token: Token::Op(op), pos: pos });
+		}
+		else if c == '\'' || c == '"' {
+			/* Quoted value - Read until the matching quote, handling backslash escapes */
+			chars.next();
+
+			let mut value = String::new();
+			let mut terminated = false;
+
+			while let Some((_, qc)) = chars.next() {
+				if qc == c {
+					terminated = true;
+					break;
+				}
+				else if qc == '\\' {
+					if let Some((_, escaped)) = chars.next() {
+						value.push(escaped);
+					}
+				}
+				else {
+					value.push(qc);
+				}
+			}
+
+			if !terminated {
+				return Err(FilterError::new(pos, "unterminated quoted value".to_string()));
+			}
+			tokens.push(Spanned { token: Token::Word(value, true), pos: pos });
+		}
+		else {
+			/* Bare word */
+			let mut word = String::new();
+			while let Some(&(_, wc)) = chars.peek() {
+				if is_word_terminator(wc) {
+					break;
+				}
+				word.push(wc);
+				chars.next();
+			}
+			tokens.push(Spanned { token: Token::Word(word, false), pos: pos });
+		}
+	}
+
+	Ok(tokens)
+}
+
+/* *************************************************** */
+/* Expression Tree */
+
+/* Value to compare against, already converted to suit the field */
+#[derive(Debug)]
+enum Literal {
+	Text(String),
+	Pattern(Regex),
+	Number(i64),
+	Duration(i64),
+	Date(SessionDate),
+}
+
+#[derive(Debug)]
+struct Comparison {
+	field: TrackField,
+	op: CompareOp,
+	value: Literal,
+}
+
+#[derive(Debug)]
+enum Node {
+	And(Box<Node>, Box<Node>),
+	Or(Box<Node>, Box<Node>),
+	Not(Box<Node>),
+	Compare(Comparison),
+}
+
+/* Parse a duration value: "m:ss", "h:mm:ss", or a plain number of seconds
+ * > returns the number of milliseconds
+ */
+pub fn parse_duration_value(s: &str) -> Option<i64>
+{
+	if s.contains(':') {
+		return TrackDuration::from_timecode(s).ok().map(|d| d.as_millis());
+	}
+
+	if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+		return None;
+	}
+	s.parse::<i64>().ok().map(|secs| secs * 1000)
+}
+
+/* Convert the value text into the literal used for comparing against the field */
+fn make_literal(field: TrackField, op: CompareOp, value: &str, pos: usize) -> Result<Literal, FilterError>
+{
+	if op == CompareOp::Regex {
+		return match Regex::new(value) {
+			Ok(re) => Ok(Literal::Pattern(re)),
+			Err(e) => Err(FilterError::new(pos, format!("invalid regex '{0}' - {1}", value, e)))
+		};
+	}
+
+	if op.is_matching() {
+		/* Substring matches work on the text form of any field */
+		return Ok(Literal::Text(value.to_lowercase()));
+	}
+
+	match field.kind() {
+		FieldKind::Text => {
+			if op.is_ordering() {
+				Err(FilterError::new(pos, format!("operator '{0}' cannot be used with text field '{1}'",
+				                                  op.symbol(), field)))
+			}
+			else {
+				Ok(Literal::Text(value.to_string()))
+			}
+		},
+		FieldKind::Number => {
+			match value.parse::<i64>() {
+				Ok(x)  => Ok(Literal::Number(x)),
+				Err(_) => Err(FilterError::new(pos, format!("expected a number for field '{0}', got '{1}'",
+				                                            field, value)))
+			}
+		},
+		FieldKind::Duration => {
+			match parse_duration_value(value) {
+				Some(ms) => Ok(Literal::Duration(ms)),
+				None     => Err(FilterError::new(pos, format!("expected a duration (e.g. 2:30) for field '{0}', got '{1}'",
+				                                              field, value)))
+			}
+		},
+		FieldKind::Date => {
+			match SessionDate::parse(value) {
+				Some(date) => Ok(Literal::Date(date)),
+				None       => Err(FilterError::new(pos, format!("expected a date (YYYY-MM-DD) for field '{0}', got '{1}'",
+				                                                field, value)))
+			}
+		},
+	}
+}
+
+/* *************************************************** */
+/* Parser */
+
+struct Parser<'a> {
+	tokens: Vec<Spanned>,
+	current: usize,
+	expr: &'a str,
+}
+
+impl<'a> Parser<'a> {
+	/* Position to report for errors about the current token */
+	fn current_pos(&self) -> usize
+	{
+		match self.tokens.get(self.current) {
+			Some(t) => t.pos,
+			None    => self.expr.len()
+		}
+	}
+
+	/* Is the current token the given (unquoted) keyword? */
+	fn at_keyword(&self, keyword: &str) -> bool
+	{
+		match self.tokens.get(self.current) {
+			Some(&Spanned { token: Token::Word(ref w, false), .. }) => w.eq_ignore_ascii_case(keyword),
+			_ => false
+		}
+	}
+
+	/* or_expr := and_expr ("or" and_expr)* */
+	fn parse_or(&mut self) -> Result<Node, FilterError>
+	{
+		let mut node = self.parse_and()?;
+		while self.at_keyword("or") {
+			self.current += 1;
+			let rhs = self.parse_and()?;
+			node = Node::Or(Box::new(node), Box::new(rhs));
+		}
+		Ok(node)
+	}
+
+	/* and_expr := not_expr ("and" not_expr)* */
+	fn parse_and(&mut self) -> Result<Node, FilterError>
+	{
+		let mut node = self.parse_not()?;
+		while self.at_keyword("and") {
+			self.current += 1;
+			let rhs = self.parse_not()?;
+			node = Node::And(Box::new(node), Box::new(rhs));
+		}
+		Ok(node)
+	}
+
+	/* not_expr := "not" not_expr | primary */
+	fn parse_not(&mut self) -> Result<Node, FilterError>
+	{
+		if self.at_keyword("not") {
+			self.current += 1;
+			let inner = self.parse_not()?;
+			Ok(Node::Not(Box::new(inner)))
+		}
+		else {
+			self.parse_primary()
+		}
+	}
+
+	/* primary := "(" or_expr ")" | field op value */
+	fn parse_primary(&mut self) -> Result<Node, FilterError>
+	{
+		let pos = self.current_pos();
+
+		match self.tokens.get(self.current) {
+			Some(&Spanned { token: Token::LParen, .. }) => {
+				self.current += 1;
+				let node = self.parse_or()?;
+
+				match self.tokens.get(self.current) {
+					Some(&Spanned { token: Token::RParen, .. }) => {
+						self.current += 1;
+						Ok(node)
+					},
+					_ => Err(FilterError::new(self.current_pos(), "expected ')'".to_string()))
+				}
+			},
+			Some(&Spanned { token: Token::Word(ref name, false), .. }) => {
+				let field = match TrackField::from_name(name) {
+					Some(f) => f,
+					None    => {
+						return Err(FilterError::new(pos, format!("unknown field '{}'", name)));
+					}
+				};
+				self.current += 1;
+
+				let op = match self.tokens.get(self.current) {
+					Some(&Spanned { token: Token::Op(op), .. }) => op,
+					_ => {
+						return Err(FilterError::new(self.current_pos(),
+						                            format!("expected a comparison operator after '{}'", name)));
+					}
+				};
+				self.current += 1;
+
+				let value_pos = self.current_pos();
+				let value = match self.tokens.get(self.current) {
+					Some(&Spanned { token: Token::Word(ref v, _), .. }) => v.clone(),
+					_ => {
+						return Err(FilterError::new(value_pos,
+						                            format!("expected a value after '{0}{1}'", name, op.symbol())));
+					}
+				};
+				self.current += 1;
+
+				let literal = make_literal(field, op, &value, value_pos)?;
+				Ok(Node::Compare(Comparison { field: field, op: op, value: literal }))
+			},
+			Some(_) => Err(FilterError::new(pos, "expected a field name or '('".to_string())),
+			None    => Err(FilterError::new(pos, "unexpected end of expression".to_string()))
+		}
+	}
+}
+
+/* *************************************************** */
+/* Evaluation */
+
+/* Compare two values with one of the equality/ordering operators */
+fn compare_ordered<T: PartialOrd>(op: CompareOp, lhs: &T, rhs: &T) -> bool
+{
+	match op {
+		CompareOp::Eq        => lhs == rhs,
+		CompareOp::NotEq     => lhs != rhs,
+		CompareOp::Greater   => lhs > rhs,
+		CompareOp::GreaterEq => lhs >= rhs,
+		CompareOp::Less      => lhs < rhs,
+		CompareOp::LessEq    => lhs <= rhs,
+		_                    => false
+	}
+}
+
+impl Comparison {
+	fn matches(&self, track: &Track) -> bool
+	{
+		match self.value {
+			Literal::Pattern(ref re) => {
+				re.is_match(&self.field.value_string(track))
+			},
+			Literal::Text(ref s) if self.op == CompareOp::Contains => {
+				self.field.value_string(track).to_lowercase().contains(s.as_str())
+			},
+			Literal::Text(ref s) => {
+				let equal = match self.field {
+					/* Types can be referred to using either their short or full names */
+					TrackField::Type => {
+						let track_type = &track.info().track_type;
+						track_type.shortname().eq_ignore_ascii_case(s) ||
+						format!("{:?}", track_type).eq_ignore_ascii_case(s)
+					},
+					TrackField::Extension => {
+						track.info().extn.to_string().eq_ignore_ascii_case(s)
+					},
+					/* Playlists can be referred to using either the path they were merged from, or just their filename */
+					TrackField::SourcePlaylist => {
+						match track.source_playlist {
+							Some(ref source) => (source == s) || (source.rsplit(['/', '\\']).next() == Some(s.as_str())),
+							None             => false
+						}
+					},
+					_ => {
+						self.field.value_string(track) == *s
+					}
+				};
+				if self.op == CompareOp::NotEq { !equal } else { equal }
+			},
+			Literal::Number(x) => {
+				match self.field {
+					/* Unrated tracks never match */
+					TrackField::Rating => {
+						match track.rating {
+							Some(rating) => compare_ordered(self.op, &(rating as i64), &x),
+							None         => false
+						}
+					},
+					_ => compare_ordered(self.op, &(track.info().index as i64), &x)
+				}
+			},
+			Literal::Duration(ms) => {
+				/* Tracks with unknown durations never match */
+				match track.duration {
+					Some(ref d) => compare_ordered(self.op, &d.0, &ms),
+					None        => false
+				}
+			},
+			Literal::Date(ref date) => {
+				/* Tracks without a valid date never match */
+				match track.date.session_date() {
+					Some(ref track_date) => compare_ordered(self.op, track_date, date),
+					None                 => false
+				}
+			},
+		}
+	}
+}
+
+impl Node {
+	fn matches(&self, track: &Track) -> bool
+	{
+		match *self {
+			Node::And(ref a, ref b) => a.matches(track) && b.matches(track),
+			Node::Or(ref a, ref b)  => a.matches(track) || b.matches(track),
+			Node::Not(ref a)        => !a.matches(track),
+			Node::Compare(ref c)    => c.matches(track),
+		}
+	}
+}
+
+/* *************************************************** */
+/* Public API */
+
+/* A parsed filter expression */
+#[derive(Debug)]
+pub struct FilterExpr {
+	root: Node,
+}
+
+impl FilterExpr {
+	/* Parse an expression */
+	pub fn parse(expr: &str) -> Result<FilterExpr, FilterError>
+	{
+		let tokens = tokenize(expr)?;
+		if tokens.is_empty() {
+			return Err(FilterError::new(0, "empty filter expression".to_string()));
+		}
+
+		let mut parser = Parser { tokens: tokens, current: 0, expr: expr };
+		let root = parser.parse_or()?;
+
+		/* Everything should have been consumed */
+		if parser.current < parser.tokens.len() {
+			let pos = parser.current_pos();
+			let message = match parser.tokens[parser.current].token {
+				Token::RParen => "unmatched ')'".to_string(),
+				_             => "expected 'and' or 'or'".to_string(),
+			};
+			return Err(FilterError::new(pos, message));
+		}
+
+		Ok(FilterExpr { root: root })
+	}
+
+	/* Does the given track satisfy the expression? */
+	pub fn matches(&self, track: &Track) -> bool
+	{
+		self.root.matches(track)
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn make_track(path: &str, duration_ms: Option<i64>) -> Track
+	{
+		let mut track = Track::from_filepath(path).unwrap();
+		track.duration = duration_ms.map(TrackDuration);
+		track
+	}
+
+	fn matches(expr: &str, track: &Track) -> bool
+	{
+		FilterExpr::parse(expr).unwrap().matches(track)
+	}
+
+	/* Basic comparisons on each kind of field ----------------------------------------- */
+
+	#[test]
+	fn test_text_comparisons()
+	{
+		let t = make_track("/music/20170403/v01-the_last_moose.mp3", Some(150000));
+
+		assert!(matches("type=VL", &t));
+		assert!(matches("type=violinlayering", &t));
+		assert!(!matches("type=MS", &t));
+		assert!(matches("type!=MS", &t));
+		assert!(matches("ext=MP3", &t));
+
+		assert!(matches("name=the_last_moose", &t));
+		assert!(matches("name~MOOSE", &t));
+		assert!(!matches("name~elk", &t));
+		assert!(matches("name~~^the_.*moose$", &t));
+		assert!(matches("filename~~v\\d+", &t));
+	}
+
+	#[test]
+	fn test_duration_comparisons()
+	{
+		let t = make_track("/music/20170403/v01-tranquil.mp3", Some(150000)); /* 2:30 */
+
+		assert!(matches("duration>2:00", &t));
+		assert!(matches("duration>=2:30", &t));
+		assert!(!matches("duration>2:30", &t));
+		assert!(matches("duration<0:02:31", &t));
+		assert!(matches("duration=150", &t));
+
+		/* Unknown durations never match */
+		let u = make_track("/music/20170403/v01-tranquil.mp3", None);
+		assert!(!matches("duration>0:00", &u));
+		assert!(!matches("duration<99:00", &u));
+	}
+
+	#[test]
+	fn test_date_and_number_comparisons()
+	{
+		let t = make_track("/music/20170802/v03-spectral.mp3", None);
+
+		assert!(matches("date>=2017-06-01", &t));
+		assert!(matches("date=20170802", &t));
+		assert!(!matches("date<2017-08-02", &t));
+		assert!(matches("date~201708", &t));
+
+		assert!(matches("index=3", &t));
+		assert!(matches("index>2", &t));
+		assert!(!matches("index>=4", &t));
+
+		/* Undated tracks never match date comparisons */
+		let u = make_track("/music/misc/v03-spectral.mp3", None);
+		assert!(!matches("date>=2017-06-01", &u));
+		assert!(!matches("date<2017-06-01", &u));
+	}
+
+	#[test]
+	fn test_rating_comparisons()
+	{
+		let mut t = make_track("/music/20170802/v03-spectral.mp3", None);
+		t.rating = Some(4);
+
+		assert!(matches("rating>=4", &t));
+		assert!(matches("rating=4", &t));
+		assert!(!matches("rating=5", &t));
+		assert!(matches("stars<5", &t));
+
+		/* Unrated tracks never match */
+		let u = make_track("/music/20170802/v04-spectral.mp3", None);
+		assert!(!matches("rating>=0", &u));
+		assert!(!matches("rating<5", &u));
+
+		assert!(FilterExpr::parse("rating>=four").is_err());
+	}
+
+	/* Boolean operators and precedence ------------------------------------------------ */
+
+	#[test]
+	fn test_precedence()
+	{
+		let t = make_track("/music/20170403/v01-tranquil.mp3", Some(60000));
+
+		/* "and" binds tighter than "or" */
+		assert!(matches("type=MS and index=5 or name=tranquil", &t));
+		assert!(matches("name=tranquil or type=MS and index=5", &t));
+		assert!(!matches("(name=tranquil or type=MS) and index=5", &t));
+
+		/* "not" binds tighter than "and" */
+		assert!(matches("not type=MS and name=tranquil", &t));
+		assert!(!matches("not (type=VL and name=tranquil)", &t));
+		assert!(matches("not not type=VL", &t));
+
+		/* Keywords are case insensitive */
+		assert!(matches("type=VL AND NOT name=foo", &t));
+	}
+
+	#[test]
+	fn test_full_example()
+	{
+		let expr = "type=VL and duration>2:00 and (date>=2017-06-01 or name~moose)";
+
+		let a = make_track("/music/20170403/v05-the_last_moose.mp3", Some(180000));
+		let b = make_track("/music/20170802/v01-tranquil.mp3", Some(180000));
+		let c = make_track("/music/20170403/v01-tranquil.mp3", Some(180000));
+		let d = make_track("/music/20170802/v01-tranquil.mp3", Some(60000));
+
+		assert!(matches(expr, &a));
+		assert!(matches(expr, &b));
+		assert!(!matches(expr, &c));
+		assert!(!matches(expr, &d));
+	}
+
+	/* Quoting ---------------------------------------------------------------------------- */
+
+	#[test]
+	fn test_quoted_values()
+	{
+		let t = make_track("/music/misc/my favourite song (live).mp3", None);
+
+		assert!(matches("name='my favourite song (live)'", &t));
+		assert!(matches("name~\"song (live\"", &t));
+		assert!(matches("name~'it\\'s' or name~favourite", &t));
+
+		/* Quoted keywords are just values */
+		let k = make_track("/music/misc/and.mp3", None);
+		assert!(matches("name='and'", &k));
+	}
+
+	/* Errors ----------------------------------------------------------------------------- */
+
+	fn parse_error(expr: &str) -> FilterError
+	{
+		FilterExpr::parse(expr).unwrap_err()
+	}
+
+	#[test]
+	fn test_syntax_errors()
+	{
+		assert_eq!(0,  parse_error("").position);
+		assert_eq!(0,  parse_error("colour=red").position);
+		assert_eq!(5,  parse_error("name moose").position);
+		assert_eq!(5,  parse_error("name=").position);
+		assert_eq!(18, parse_error("(type=VL or name=b").position);
+		assert_eq!(8,  parse_error("type=VL ) and name=x").position);
+		assert_eq!(8,  parse_error("type=VL name=x").position);
+		assert_eq!(5,  parse_error("name='unterminated").position);
+		assert_eq!(4,  parse_error("name!x").position);
+
+		let e = parse_error("colour=red");
+		assert!(e.message.contains("unknown field 'colour'"));
+	}
+
+	#[test]
+	fn test_type_mismatches()
+	{
+		let e = parse_error("type=VL and duration>abc");
+		assert_eq!(21, e.position);
+		assert!(e.message.contains("expected a duration"));
+		assert!(e.message.contains("'abc'"));
+
+		let e = parse_error("date>=yesterday");
+		assert_eq!(6, e.position);
+		assert!(e.message.contains("expected a date"));
+
+		let e = parse_error("index>three");
+		assert!(e.message.contains("expected a number"));
+
+		let e = parse_error("name>moose");
+		assert_eq!(5, e.position);
+		assert!(e.message.contains("cannot be used with text field 'name'"));
+
+		let e = parse_error("name~~'(unclosed'");
+		assert!(e.message.contains("invalid regex"));
+	}
+
+	#[test]
+	fn test_error_description()
+	{
+		let e = parse_error("type=VL and duration>abc");
+		let description = e.describe("type=VL and duration>abc");
+		let lines: Vec<&str> = description.lines().collect();
+
+		assert_eq!(3, lines.len());
+		assert_eq!("    type=VL and duration>abc", lines[1]);
+		assert_eq!("                         ^", lines[2]);
+	}
+}
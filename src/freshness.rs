@@ -0,0 +1,217 @@
+/* Track "freshness" check
+ *
+ * Compares the modification time of each track's file against the session date
+ * it is filed under, to catch files that were re-exported long after the session
+ * (i.e. the playlist may now be pointing at a changed take), or files which
+ * claim to have been modified before the session even happened.
+ */
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use diagnostics::Diagnostics;
+use track_date::{self, SessionDate};
+use xspf_parser::XspfPlaylist;
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "freshness";
+
+/* Default number of days a file may be modified after its session before getting flagged */
+pub const DEFAULT_MAX_DRIFT_DAYS: i64 = 30;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/* *************************************************** */
+
+/* Outcome of comparing a file's mtime against its session date */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum FreshnessStatus {
+	/* Modified on/after the session, within the allowed drift */
+	Fresh,
+	/* Modified more than max-drift days after the session */
+	PossiblyReexported,
+	/* Modified before the session date even started */
+	SuspiciousBackdate,
+}
+
+/* Compare a file modification time with the session date it's filed under
+ *
+ * - session: The date of the session
+ * - mtime_secs: File modification time (seconds since the unix epoch)
+ * - utc_offset_secs: Offset of the local timezone, used to find local midnight at the start of the session
+ * - max_drift_days: Number of whole days after the session that modifications are still considered normal
+ *
+ * > returns the status, along with the number of whole days between the session start and the mtime
+ *   (negative for backdated files)
+ */
+pub fn classify_mtime(session: &SessionDate, mtime_secs: i64, utc_offset_secs: i64, max_drift_days: i64)
+	-> (FreshnessStatus, i64)
+{
+	let session_start = session.days_since_epoch() * SECS_PER_DAY - utc_offset_secs;
+
+	let delta = mtime_secs - session_start;
+	let drift_days = if delta >= 0 { delta / SECS_PER_DAY } else { -((-delta + SECS_PER_DAY - 1) / SECS_PER_DAY) };
+
+	if delta < 0 {
+		(FreshnessStatus::SuspiciousBackdate, drift_days)
+	}
+	else if drift_days > max_drift_days {
+		(FreshnessStatus::PossiblyReexported, drift_days)
+	}
+	else {
+		(FreshnessStatus::Fresh, drift_days)
+	}
+}
+
+/* *************************************************** */
+
+/* Summary of a freshness check over a whole playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct FreshnessReport {
+	/* Threshold used for the check */
+	pub max_drift_days: i64,
+
+	/* Number of tracks that could be compared */
+	pub checked: usize,
+	/* Number of tracks skipped as they had no parseable date, or the file couldn't be stat'd */
+	pub unchecked: usize,
+
+	/* Problems found */
+	pub diagnostics: Diagnostics,
+}
+
+/* Get the modification time of a file (in seconds since the unix epoch) */
+fn file_mtime_secs(path: &Path) -> Option<i64>
+{
+	let modified = fs::metadata(path).ok()?.modified().ok()?;
+	match modified.duration_since(UNIX_EPOCH) {
+		Ok(d)  => Some(d.as_secs() as i64),
+		Err(e) => Some(-(e.duration().as_secs() as i64)),
+	}
+}
+
+/* Run the freshness check over all tracks in the playlist */
+pub fn check_playlist(xspf: &XspfPlaylist, max_drift_days: i64) -> FreshnessReport
+{
+	let mut report = FreshnessReport {
+		max_drift_days: max_drift_days,
+		checked: 0,
+		unchecked: 0,
+		diagnostics: Diagnostics::new(),
+	};
+
+	for (i, track) in xspf.tracks.iter().enumerate() {
+		let session = track.date.session_date();
+		let mtime = file_mtime_secs(track.file_path());
+
+		if let (Some(session), Some(mtime)) = (session, mtime) {
+			/* Use the timezone offset in effect at the start of the session (DST may differ from now) */
+			let utc_offset = track_date::local_utc_offset_secs(session.days_since_epoch() * SECS_PER_DAY);
+
+			match classify_mtime(&session, mtime, utc_offset, max_drift_days) {
+				(FreshnessStatus::PossiblyReexported, days) => {
+					report.diagnostics.warn_track(CATEGORY, i,
+						format!("'{0}' possibly re-exported - modified {1} days after session {2}",
+						        track.filename, days, session));
+				},
+				(FreshnessStatus::SuspiciousBackdate, _) => {
+					report.diagnostics.warn_track(CATEGORY, i,
+						format!("'{0}' suspicious backdate - modified before session {1}",
+						        track.filename, session));
+				},
+				(FreshnessStatus::Fresh, _) => { /* all good */ }
+			}
+			report.checked += 1;
+		}
+		else {
+			report.unchecked += 1;
+		}
+	}
+
+	report
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use xspf_parser::Track;
+
+	/* 2017-04-03 00:00 UTC */
+	const SESSION_START: i64 = 17259 * SECS_PER_DAY;
+
+	fn session() -> SessionDate
+	{
+		SessionDate::new(2017, 4, 3).unwrap()
+	}
+
+	/* Files modified during/just after the session are fine */
+	#[test]
+	fn test_fresh()
+	{
+		assert_eq!((FreshnessStatus::Fresh, 0),  classify_mtime(&session(), SESSION_START + 3600, 0, 30));
+		assert_eq!((FreshnessStatus::Fresh, 30), classify_mtime(&session(), SESSION_START + 30 * SECS_PER_DAY, 0, 30));
+	}
+
+	/* Files modified long after the session are flagged */
+	#[test]
+	fn test_possibly_reexported()
+	{
+		assert_eq!((FreshnessStatus::PossiblyReexported, 31),
+		           classify_mtime(&session(), SESSION_START + 31 * SECS_PER_DAY, 0, 30));
+		assert_eq!((FreshnessStatus::PossiblyReexported, 2),
+		           classify_mtime(&session(), SESSION_START + 2 * SECS_PER_DAY, 0, 1));
+	}
+
+	/* Files modified before the session are flagged */
+	#[test]
+	fn test_suspicious_backdate()
+	{
+		assert_eq!((FreshnessStatus::SuspiciousBackdate, -1), classify_mtime(&session(), SESSION_START - 1, 0, 30));
+		assert_eq!((FreshnessStatus::SuspiciousBackdate, -2), classify_mtime(&session(), SESSION_START - SECS_PER_DAY - 1, 0, 30));
+	}
+
+	/* Local midnight is used as the start of the session */
+	#[test]
+	fn test_local_midnight()
+	{
+		/* UTC+12: Local midnight happens at 12:00 UTC on the previous day */
+		let utc_plus_12 = 12 * 3600;
+		assert_eq!(FreshnessStatus::Fresh,
+		           classify_mtime(&session(), SESSION_START - 6 * 3600, utc_plus_12, 30).0);
+
+		/* UTC-5: Local midnight happens at 05:00 UTC on the same day */
+		let utc_minus_5 = -5 * 3600;
+		assert_eq!(FreshnessStatus::SuspiciousBackdate,
+		           classify_mtime(&session(), SESSION_START + 4 * 3600, utc_minus_5, 30).0);
+	}
+
+	/* Tracks without dates or without files on disk don't fail the check */
+	#[test]
+	fn test_unchecked_tracks()
+	{
+		let xspf = XspfPlaylist {
+			tracks: vec![
+				Track::from_filepath("/nonexistent/20170403/v01-tranquil.mp3").unwrap(),
+				Track::from_filepath("/nonexistent/misc/v02-celestial.mp3").unwrap(),
+			],
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+
+		let report = check_playlist(&xspf, DEFAULT_MAX_DRIFT_DAYS);
+		assert_eq!(0, report.checked);
+		assert_eq!(2, report.unchecked);
+		assert!(report.diagnostics.is_empty());
+	}
+}
@@ -0,0 +1,404 @@
+/* Deciding what to do about each of a set of destructive actions (e.g. deleting duplicate files)
+ *
+ * Decisions can be made by asking about each one in turn (with the option of applying the same
+ * answer to all the rest), taken from a file of decisions recorded on an earlier run (so that
+ * they can be reviewed first, then applied later), or just be "yes" to everything.
+ */
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use size_estimate::format_size;
+use terminal_text::sanitize;
+use track_date::SessionDate;
+
+/* *************************************************** */
+/* Decisions */
+
+/* What to do with a candidate */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+	Keep,
+	Delete,
+}
+
+impl fmt::Display for Decision {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			Decision::Keep   => write!(f, "keep"),
+			Decision::Delete => write!(f, "delete"),
+		}
+	}
+}
+
+/* A file that's up for deletion */
+#[derive(Debug)]
+pub struct Candidate {
+	pub path: String,
+	pub size: Option<u64>,
+	pub modified: Option<SystemTime>,
+	/* What it conflicts with (e.g. the playlist entry it's a duplicate of) */
+	pub conflicts_with: String,
+}
+
+impl Candidate {
+	/* Candidate for the given file (with its size and modification time, if they can be found) */
+	pub fn for_file(path: &Path, conflicts_with: String) -> Candidate
+	{
+		let metadata = fs::metadata(path).ok();
+		Candidate {
+			path: path.to_string_lossy().into_owned(),
+			size: metadata.as_ref().map(|m| m.len()),
+			modified: metadata.and_then(|m| m.modified().ok()),
+			conflicts_with: conflicts_with,
+		}
+	}
+
+	/* Write the details shown when asking about it */
+	fn describe(&self, out: &mut dyn Write) -> io::Result<()>
+	{
+		writeln!(out, "{}", sanitize(&self.path))?;
+		writeln!(out, "    Size:     {}", self.size.map(format_size).unwrap_or_else(|| "?".to_string()))?;
+		writeln!(out, "    Modified: {}", self.modified.map(format_mtime).unwrap_or_else(|| "?".to_string()))?;
+		writeln!(out, "    Same as:  {}", sanitize(&self.conflicts_with))
+	}
+}
+
+/* Modification time, as "YYYY-MM-DD HH:MM UTC" */
+pub fn format_mtime(mtime: SystemTime) -> String
+{
+	let secs = match mtime.duration_since(UNIX_EPOCH) {
+		Ok(d)  => d.as_secs() as i64,
+		Err(e) => -(e.duration().as_secs() as i64)
+	};
+	let date = SessionDate::from_days_since_epoch(secs.div_euclid(86400));
+	let time_of_day = secs.rem_euclid(86400);
+	format!("{0} {1:02}:{2:02} UTC", date, time_of_day / 3600, (time_of_day % 3600) / 60)
+}
+
+/* *************************************************** */
+/* Prompting */
+
+/* Source of the answers typed in response to prompts */
+pub trait KeyInput {
+	/* Next key pressed (or None once there's no more input) */
+	fn read_key(&mut self) -> io::Result<Option<char>>;
+}
+
+/* Answers typed as lines of text (e.g. on stdin), taking the first character of each line
+ * NOTE: Terminals only pass on what was typed once Enter is pressed, unless put into raw mode
+ *       (which isn't portable), so each key needs to be followed by Enter.
+ */
+pub struct LineInput<R: BufRead> {
+	reader: R,
+}
+
+impl<R: BufRead> LineInput<R> {
+	pub fn new(reader: R) -> LineInput<R>
+	{
+		LineInput { reader: reader }
+	}
+}
+
+impl<R: BufRead> KeyInput for LineInput<R> {
+	fn read_key(&mut self) -> io::Result<Option<char>>
+	{
+		let mut line = String::new();
+		if self.reader.read_line(&mut line)? == 0 {
+			return Ok(None);
+		}
+		/* Blank lines count as an unrecognised key (so that the question gets asked again) */
+		Ok(Some(line.trim().chars().next().unwrap_or(' ')))
+	}
+}
+
+/* Prompt listing the keys that can be pressed */
+const PROMPT: &'static str = "[k]eep, [d]elete, [s]kip all (keep the rest), [a]pply all (delete the rest)? ";
+
+/* Asks what to do about each candidate in turn */
+pub struct Prompter<I: KeyInput, W: Write> {
+	input: I,
+	out: W,
+	/* Decision to make for all the remaining candidates (once "skip all"/"apply all" has been chosen) */
+	apply_all: Option<Decision>,
+}
+
+impl<I: KeyInput, W: Write> Prompter<I, W> {
+	pub fn new(input: I, out: W) -> Prompter<I, W>
+	{
+		Prompter { input: input, out: out, apply_all: None }
+	}
+
+	/* Ask what to do about the candidate (unless the same thing is being done to all of them now) */
+	pub fn decide(&mut self, candidate: &Candidate) -> io::Result<Decision>
+	{
+		writeln!(self.out)?;
+		candidate.describe(&mut self.out)?;
+
+		if let Some(decision) = self.apply_all {
+			writeln!(self.out, "    -> {} (as for all the rest)", decision)?;
+			return Ok(decision);
+		}
+
+		loop {
+			write!(self.out, "{}", PROMPT)?;
+			self.out.flush()?;
+
+			match self.input.read_key()? {
+				Some('k') | Some('K') => return Ok(Decision::Keep),
+				Some('d') | Some('D') => return Ok(Decision::Delete),
+				Some('s') | Some('S') => {
+					self.apply_all = Some(Decision::Keep);
+					return Ok(Decision::Keep);
+				},
+				Some('a') | Some('A') => {
+					self.apply_all = Some(Decision::Delete);
+					return Ok(Decision::Delete);
+				},
+				Some(key) => {
+					writeln!(self.out, "Unrecognised answer {:?}", key)?;
+				},
+				None => {
+					/* Input closed - Play it safe, and keep everything from here on */
+					writeln!(self.out, "\nNo more input - keeping this and all the rest")?;
+					self.apply_all = Some(Decision::Keep);
+					return Ok(Decision::Keep);
+				}
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Recorded Decisions */
+
+/* Decision made about one file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct RecordedDecision {
+	pub path: String,
+	pub decision: Decision,
+}
+
+/* Decisions recorded from earlier runs ("--record"), to be applied by later ones ("--replay") */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct DecisionsFile {
+	pub decisions: Vec<RecordedDecision>,
+}
+
+impl DecisionsFile {
+	/* Load the decisions from the given file */
+	pub fn load(path: &Path) -> Result<DecisionsFile, String>
+	{
+		let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read decisions file {0:?} - {1}", path, e))?;
+		serde_json::from_str(&text).map_err(|e| format!("Invalid decisions file {0:?} - {1}", path, e))
+	}
+
+	/* Load the decisions from the given file, so that more can be added to it (starting afresh if it doesn't exist yet) */
+	pub fn load_for_append(path: &Path) -> Result<DecisionsFile, String>
+	{
+		if path.exists() { DecisionsFile::load(path) } else { Ok(DecisionsFile::default()) }
+	}
+
+	pub fn save(&self, path: &Path) -> io::Result<()>
+	{
+		let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+		fs::write(path, json + "\n")
+	}
+
+	/* Add a decision (replacing any made about the same file before) */
+	pub fn record(&mut self, path: &str, decision: Decision)
+	{
+		self.decisions.retain(|d| d.path != path);
+		self.decisions.push(RecordedDecision { path: path.to_string(), decision: decision });
+	}
+
+	/* Decisions by path, for looking them up while replaying */
+	fn by_path(&self) -> HashMap<String, Decision>
+	{
+		self.decisions.iter().map(|d| (d.path.clone(), d.decision)).collect()
+	}
+}
+
+/* *************************************************** */
+/* Resolving */
+
+/* How the decision gets made for each candidate */
+pub enum Resolver<I: KeyInput, W: Write> {
+	/* Delete everything without asking ("--yes") */
+	DeleteAll,
+	/* Ask about each one ("--interactive") */
+	Interactive(Prompter<I, W>),
+	/* Do what was decided on an earlier run ("--replay") - anything not decided then gets kept */
+	Replay(HashMap<String, Decision>),
+}
+
+impl<I: KeyInput, W: Write> Resolver<I, W> {
+	pub fn replay(decisions: &DecisionsFile) -> Resolver<I, W>
+	{
+		Resolver::Replay(decisions.by_path())
+	}
+
+	/* Decide what to do with the candidate
+	 * > returns the decision, and whether one had actually been made about it (i.e. it wasn't left out of a replayed file)
+	 */
+	pub fn decide(&mut self, candidate: &Candidate) -> io::Result<(Decision, bool)>
+	{
+		match *self {
+			Resolver::DeleteAll                  => Ok((Decision::Delete, true)),
+			Resolver::Interactive(ref mut asker) => asker.decide(candidate).map(|d| (d, true)),
+			Resolver::Replay(ref decisions) => {
+				match decisions.get(&candidate.path) {
+					Some(&decision) => Ok((decision, true)),
+					None            => Ok((Decision::Keep, false))
+				}
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::VecDeque;
+
+	/* Keys to "press", in order (running out counts as the input being closed) */
+	struct ScriptedKeys {
+		keys: VecDeque<char>,
+	}
+
+	impl ScriptedKeys {
+		fn new(keys: &str) -> ScriptedKeys
+		{
+			ScriptedKeys { keys: keys.chars().collect() }
+		}
+	}
+
+	impl KeyInput for ScriptedKeys {
+		fn read_key(&mut self) -> io::Result<Option<char>>
+		{
+			Ok(self.keys.pop_front())
+		}
+	}
+
+	fn candidate(path: &str) -> Candidate
+	{
+		Candidate {
+			path: path.to_string(),
+			size: Some(2048),
+			modified: Some(UNIX_EPOCH + ::std::time::Duration::from_secs(17259 * 86400 + 13 * 3600 + 5 * 60)),
+			conflicts_with: "track 1 (/music/20170403/v01-a.mp3)".to_string(),
+		}
+	}
+
+	/* Decisions made for each candidate, given the keys pressed */
+	fn decide_all(keys: &str, count: usize) -> (Vec<Decision>, String)
+	{
+		let mut out: Vec<u8> = Vec::new();
+		let decisions = {
+			let mut prompter = Prompter::new(ScriptedKeys::new(keys), &mut out);
+			(0 .. count).map(|i| prompter.decide(&candidate(&format!("copy{}.mp3", i))).unwrap()).collect()
+		};
+		(decisions, String::from_utf8(out).unwrap())
+	}
+
+	#[test]
+	fn test_prompt_details()
+	{
+		let (_, text) = decide_all("k", 1);
+		assert!(text.contains("copy0.mp3\n"), "{}", text);
+		assert!(text.contains("Size:     2.0 KB"), "{}", text);
+		assert!(text.contains("Modified: 2017-04-03 13:05 UTC"), "{}", text);
+		assert!(text.contains("Same as:  track 1 (/music/20170403/v01-a.mp3)"), "{}", text);
+		assert!(text.contains(PROMPT), "{}", text);
+	}
+
+	#[test]
+	fn test_keep_and_delete()
+	{
+		let (decisions, _) = decide_all("kDdK", 4);
+		assert_eq!(vec![Decision::Keep, Decision::Delete, Decision::Delete, Decision::Keep], decisions);
+
+		/* Unrecognised keys get asked again */
+		let (decisions, text) = decide_all("x d", 1);
+		assert_eq!(vec![Decision::Delete], decisions);
+		assert_eq!(3, text.matches(PROMPT).count());
+		assert!(text.contains("Unrecognised answer 'x'"), "{}", text);
+	}
+
+	#[test]
+	fn test_apply_all()
+	{
+		/* No more questions get asked once "apply all"/"skip all" has been chosen */
+		let (decisions, text) = decide_all("kaK", 4);
+		assert_eq!(vec![Decision::Keep, Decision::Delete, Decision::Delete, Decision::Delete], decisions);
+		assert_eq!(2, text.matches(PROMPT).count());
+		assert_eq!(2, text.matches("-> delete (as for all the rest)").count());
+
+		let (decisions, _) = decide_all("dsd", 3);
+		assert_eq!(vec![Decision::Delete, Decision::Keep, Decision::Keep], decisions);
+
+		/* Running out of input keeps the rest */
+		let (decisions, text) = decide_all("d", 3);
+		assert_eq!(vec![Decision::Delete, Decision::Keep, Decision::Keep], decisions);
+		assert!(text.contains("No more input"), "{}", text);
+	}
+
+	#[test]
+	fn test_line_input()
+	{
+		let mut input = LineInput::new("d\n  keep\n\n".as_bytes());
+		assert_eq!(Some('d'), input.read_key().unwrap());
+		assert_eq!(Some('k'), input.read_key().unwrap());
+		assert_eq!(Some(' '), input.read_key().unwrap());
+		assert_eq!(None, input.read_key().unwrap());
+	}
+
+	#[test]
+	fn test_record_and_replay()
+	{
+		use test_utils::TempDir;
+
+		let dir = TempDir::new("interactive-decisions");
+		let path = dir.path().join("decisions.json");
+
+		/* Recording (appending to what's already there, with later decisions replacing earlier ones) */
+		let mut decisions = DecisionsFile::load_for_append(&path).unwrap();
+		decisions.record("copy0.mp3", Decision::Delete);
+		decisions.record("copy1.mp3", Decision::Delete);
+		decisions.save(&path).unwrap();
+
+		let mut decisions = DecisionsFile::load_for_append(&path).unwrap();
+		decisions.record("copy1.mp3", Decision::Keep);
+		decisions.save(&path).unwrap();
+
+		let decisions = DecisionsFile::load(&path).unwrap();
+		assert_eq!(vec![RecordedDecision { path: "copy0.mp3".to_string(), decision: Decision::Delete },
+		                RecordedDecision { path: "copy1.mp3".to_string(), decision: Decision::Keep }],
+		           decisions.decisions);
+
+		/* Replaying - Anything not decided on gets kept */
+		let mut resolver: Resolver<ScriptedKeys, Vec<u8>> = Resolver::replay(&decisions);
+		assert_eq!((Decision::Delete, true), resolver.decide(&candidate("copy0.mp3")).unwrap());
+		assert_eq!((Decision::Keep, true), resolver.decide(&candidate("copy1.mp3")).unwrap());
+		assert_eq!((Decision::Keep, false), resolver.decide(&candidate("copy2.mp3")).unwrap());
+
+		assert!(DecisionsFile::load(&dir.path().join("missing.json")).is_err());
+	}
+}
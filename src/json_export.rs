@@ -0,0 +1,318 @@
+/* Playlist export, as output by the "json" mode
+ *
+ * The internal types (XspfPlaylist, Track, etc.) get serialised however suits them - durations as bare
+ * millisecond counts, the filename info as a nested object with enums in their Debug form, and so on.
+ * That's fine for files only this tool reads back (e.g. bundle metadata), but not for other tools,
+ * so the "json" mode writes out this flatter structure instead, with the computed fields (timecodes,
+ * totals) filled in, and a schema version that gets bumped whenever the layout changes.
+ */
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use duration_source::DurationSource;
+use xspf_extension::{self, ExtensionBlock};
+use xspf_parser::{SkippedTrack, Track, XspfPlaylist};
+
+/* Version of the layout below - Bump this whenever fields get changed or removed */
+pub const SCHEMA_VERSION: u32 = 1;
+
+/* *************************************************** */
+/* Export Types */
+
+/* A track in the export */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct JsonTrackExport {
+	pub path: String,
+	pub filename: String,
+	pub date: String,
+
+	/* Duration in ms, and as a "mm:ss" timecode - if known */
+	pub duration_ms: Option<i64>,
+	pub duration: Option<String>,
+	pub duration_source: DurationSource,
+
+	pub title: Option<String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub creator: Option<String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub album: Option<String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub annotation: Option<String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub source_playlist: Option<String>,
+	/* Position in the playlist it was read from (see Track.original_index) */
+	#[serde(default)]
+	pub original_index: usize,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rating: Option<u8>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub properties: BTreeMap<String, String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub extensions: Vec<ExtensionBlock>,
+
+	/* Info worked out from the filename (see FilenameInfoComponents) */
+	pub track_type: String,        /* Shortname of the type, e.g. "VL" */
+	pub index: i32,
+	pub variant: Option<String>,   /* e.g. "b" for "v03b" */
+	pub name: String,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub revision: Option<String>,  /* e.g. "v2" (MuseScore only) */
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub name_date: Option<String>, /* Date in the filename, as "YYYYMMDD" (MuseScore only) */
+	pub extension: String,         /* e.g. "mp3" */
+}
+
+/* The whole playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct JsonPlaylistExport {
+	pub schema_version: u32,
+
+	pub title: Option<String>,
+	pub creator: Option<String>,
+	pub annotation: Option<String>,
+	pub date: Option<String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub extensions: Vec<ExtensionBlock>,
+
+	/* Totals - Tracks without known durations aren't included in the total duration */
+	pub track_count: usize,
+	pub total_duration_ms: i64,
+	pub total_duration: String,
+	pub uncounted: usize,
+
+	pub tracks: Vec<JsonTrackExport>,
+
+	/* Entries in the playlist that couldn't be used as tracks (not included in any of the above) */
+	pub skipped: Vec<SkippedTrack>,
+}
+
+impl JsonTrackExport {
+	pub fn from_track(track: &Track) -> JsonTrackExport
+	{
+		let info = track.info();
+		JsonTrackExport {
+			path: track.path.to_string(),
+			filename: track.filename.clone(),
+			date: track.date.to_string(),
+
+			duration_ms: track.duration.as_ref().map(|d| d.0),
+			duration: track.duration.as_ref().map(|d| d.to_timecode()),
+			duration_source: track.duration_source,
+
+			title: track.title.clone(),
+			creator: track.creator.clone(),
+			album: track.album.clone(),
+			annotation: track.annotation.clone(),
+			source_playlist: track.source_playlist.clone(),
+			original_index: track.original_index,
+			rating: track.rating,
+			properties: track.properties.clone(),
+			extensions: track.extensions.clone(),
+
+			track_type: info.track_type.shortname(),
+			index: info.index,
+			variant: info.variant.clone(),
+			name: info.name.clone(),
+			revision: info.revision.clone(),
+			name_date: info.date.clone(),
+			extension: info.extn.to_string(),
+		}
+	}
+}
+
+impl JsonPlaylistExport {
+	pub fn from_playlist(xspf: &XspfPlaylist) -> JsonPlaylistExport
+	{
+		let total = xspf.total_duration();
+		JsonPlaylistExport {
+			schema_version: SCHEMA_VERSION,
+
+			title: xspf.title.clone(),
+			creator: xspf.creator.clone(),
+			annotation: xspf.annotation.clone(),
+			date: xspf.date.clone(),
+			extensions: xspf.extensions.clone(),
+
+			track_count: xspf.len(),
+			total_duration_ms: total.duration.0,
+			total_duration: total.duration.to_timecode(),
+			uncounted: total.uncounted,
+
+			tracks: xspf.tracks.iter().map(JsonTrackExport::from_track).collect(),
+			skipped: xspf.skipped.clone(),
+		}
+	}
+}
+
+/* *************************************************** */
+/* Schema */
+
+/* JSON Schema for JsonPlaylistExport, as output by the "json" mode (see json_schema) */
+pub fn export_schema() -> Value
+{
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "playlist",
+		"type": "object",
+		"properties": {
+			"schema_version": { "enum": [SCHEMA_VERSION] },
+			"title": { "type": ["string", "null"] },
+			"creator": { "type": ["string", "null"] },
+			"annotation": { "type": ["string", "null"] },
+			"date": { "type": ["string", "null"] },
+			"extensions": xspf_extension::extensions_schema(),
+			"track_count": { "type": "integer" },
+			"total_duration_ms": { "description": "Only counting tracks with known durations", "type": "integer" },
+			"total_duration": { "description": "mm:ss", "type": "string" },
+			"uncounted": { "description": "Number of tracks without known durations", "type": "integer" },
+			"tracks": { "type": "array", "items": track_schema() },
+			"skipped": {
+				"description": "Entries that couldn't be used as tracks (e.g. streams)",
+				"type": "array",
+				"items": {
+					"type": "object",
+					"properties": {
+						"location": { "type": ["string", "null"] },
+						"reason": { "type": "string" }
+					},
+					"required": ["location", "reason"],
+					"additionalProperties": false
+				}
+			}
+		},
+		"required": ["schema_version", "title", "creator", "annotation", "date",
+		             "track_count", "total_duration_ms", "total_duration", "uncounted", "tracks", "skipped"],
+		"additionalProperties": false
+	})
+}
+
+/* JSON Schema for JsonTrackExport */
+fn track_schema() -> Value
+{
+	json!({
+		"type": "object",
+		"properties": {
+			"path": { "type": "string" },
+			"filename": { "type": "string" },
+			"date": { "type": "string" },
+			"duration_ms": { "type": ["integer", "null"] },
+			"duration": { "description": "mm:ss", "type": ["string", "null"] },
+			"duration_source": { "enum": ["playlist", "probed", "unknown"] },
+			"title": { "type": ["string", "null"] },
+			"creator": { "description": "Only present if given in the playlist", "type": "string" },
+			"album": { "description": "Only present if given in the playlist", "type": "string" },
+			"annotation": { "description": "Only present if given in the playlist", "type": "string" },
+			"source_playlist": { "description": "Only present for merged tracks", "type": "string" },
+			"original_index": {
+				"description": "Position of the track's entry in the playlist it was read from (0-based, counting skipped entries)",
+				"type": "integer"
+			},
+			"rating": { "description": "Stars (0-5) - Only present for rated tracks", "enum": [0, 1, 2, 3, 4, 5] },
+			"properties": {
+				"description": "Values from other applications' extensions (only present if there are any)",
+				"type": "object",
+				"additionalProperties": { "type": "string" }
+			},
+			"extensions": xspf_extension::extensions_schema(),
+			"track_type": { "description": "Shortname of the type", "enum": ["?", "VL", "MS", "P", "V"] },
+			"index": { "type": "integer" },
+			"variant": { "type": ["string", "null"] },
+			"name": { "type": "string" },
+			"revision": { "description": "Revision marker after the name, e.g. v2 - Only present for MuseScore tracks with one", "type": "string" },
+			"name_date": { "description": "YYYYMMDD - Only present for MuseScore tracks (which have the date in their filename)", "type": "string" },
+			"extension": { "type": "string" }
+		},
+		"required": ["path", "filename", "date", "duration_ms", "duration", "duration_source", "title", "original_index",
+		             "track_type", "index", "variant", "name", "extension"],
+		"additionalProperties": false
+	})
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json;
+	use diagnostics::Diagnostics;
+	use json_schema::{assert_matches_schema, OutputKind};
+	use track_duration::TrackDuration;
+
+	fn fixture() -> XspfPlaylist
+	{
+		let paths = ["music/20170403/v01b-tranquil.mp3", "music/20170802/20170802-02-TouchedByAnAngel.flac", "music/misc/intro.wav"];
+		let mut xspf = XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: Some("Sessions".to_string()),
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		xspf.tracks[0].duration = Some(TrackDuration(61_000));
+		xspf.tracks[1].duration = Some(TrackDuration(125_500));
+		xspf.tracks[1].rating = Some(4);
+		xspf.tracks[1].album = Some("Angels".to_string());
+		xspf.skipped.push(SkippedTrack { location: Some("http://example.com/stream".to_string()), reason: "Unsupported URI".to_string() });
+		xspf
+	}
+
+	#[test]
+	fn test_export()
+	{
+		let json = serde_json::to_string_pretty(&JsonPlaylistExport::from_playlist(&fixture())).unwrap();
+		let export: JsonPlaylistExport = serde_json::from_str(&json).unwrap();
+
+		assert_eq!(SCHEMA_VERSION, export.schema_version);
+		assert_eq!(Some("Sessions".to_string()), export.title);
+		assert_eq!((3, 186_500, 1), (export.track_count, export.total_duration_ms, export.uncounted));
+		assert_eq!("03:06", export.total_duration);
+
+		let t = &export.tracks[0];
+		assert_eq!("music/20170403/v01b-tranquil.mp3", t.path);
+		assert_eq!((Some(61_000), Some("01:01")), (t.duration_ms, t.duration.as_deref()));
+		assert_eq!(("VL", 1, Some("b"), "tranquil", "mp3"),
+		           (t.track_type.as_str(), t.index, t.variant.as_deref(), t.name.as_str(), t.extension.as_str()));
+
+		let t = &export.tracks[1];
+		assert_eq!(("MS", 2, "TouchedByAnAngel", "flac"), (t.track_type.as_str(), t.index, t.name.as_str(), t.extension.as_str()));
+		assert_eq!((Some(4), Some("Angels"), None), (t.rating, t.album.as_deref(), t.creator.as_deref()));
+		assert_eq!((Some("20170802"), None), (t.name_date.as_deref(), t.revision.as_deref()));
+
+		/* Unknown types/extensions, and missing durations */
+		let t = &export.tracks[2];
+		assert_eq!(("?", "wav"), (t.track_type.as_str(), t.extension.as_str()));
+		assert_eq!(None, t.name_date);
+		assert_eq!((None, None), (t.duration_ms, t.duration.as_deref()));
+		assert_eq!(DurationSource::Unknown, t.duration_source);
+
+		assert_eq!(vec![SkippedTrack { location: Some("http://example.com/stream".to_string()), reason: "Unsupported URI".to_string() }],
+		           export.skipped);
+	}
+
+	#[test]
+	fn test_export_schema()
+	{
+		assert_matches_schema(OutputKind::Playlist, &serde_json::to_value(JsonPlaylistExport::from_playlist(&fixture())).unwrap());
+
+		let empty = XspfPlaylist { tracks: Vec::new(), title: None, creator: None, annotation: None, date: None,
+		                          extensions: Vec::new(), diagnostics: Diagnostics::new(), skipped: Vec::new() };
+		assert_matches_schema(OutputKind::Playlist, &serde_json::to_value(JsonPlaylistExport::from_playlist(&empty)).unwrap());
+	}
+}
@@ -0,0 +1,417 @@
+/* JSON Schemas for the JSON that gets output
+ *
+ * Each kind of JSON output has a (hand-written) schema, kept next to the types that get serialised
+ * for it, so that other tools can check what they're getting (see the "schema" mode).
+ *
+ * The validator here only covers the parts of JSON Schema that those schemas use (types, properties,
+ * required, additionalProperties, items, enum, and oneOf). It's used by the tests to check actual
+ * output against the schemas, so that types can't get changed without their schemas being updated too.
+ */
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{self, Value};
+
+use diagnostics;
+use json_export;
+use playlist_stats;
+use progress_events;
+use xspf_parser;
+
+/* *************************************************** */
+/* Output Kinds */
+
+/* Kinds of JSON output that there are schemas for */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum OutputKind {
+	/* Playlist, as output by the "json" mode (i.e. JsonPlaylistExport) */
+	Playlist,
+	/* Playlist as stored internally (i.e. XspfPlaylist), as written by "verify-order --fix" and in bundles */
+	RawPlaylist,
+	/* Findings from checks (i.e. Diagnostics), as output by "verify-order --json" */
+	Report,
+	/* Totals, as output by "stats --json" */
+	Stats,
+	/* Totals for two windows, as output by "stats --json --compare=..." */
+	StatsComparison,
+	/* Each line of the progress events (see progress_events) */
+	Events,
+}
+
+/* All the kinds (in the order they get listed) */
+pub const OUTPUT_KINDS: [OutputKind; 6] = [
+	OutputKind::Playlist,
+	OutputKind::RawPlaylist,
+	OutputKind::Report,
+	OutputKind::Stats,
+	OutputKind::StatsComparison,
+	OutputKind::Events,
+];
+
+impl OutputKind {
+	/* Name of the kind (as given to the "schema" mode) */
+	pub fn name(&self) -> &'static str
+	{
+		match *self {
+			OutputKind::Playlist        => "playlist",
+			OutputKind::RawPlaylist     => "raw-playlist",
+			OutputKind::Report          => "report",
+			OutputKind::Stats           => "stats",
+			OutputKind::StatsComparison => "stats-compare",
+			OutputKind::Events          => "events",
+		}
+	}
+
+	/* Schema for this kind of output */
+	pub fn schema(&self) -> Value
+	{
+		match *self {
+			OutputKind::Playlist        => json_export::export_schema(),
+			OutputKind::RawPlaylist     => xspf_parser::playlist_schema(),
+			OutputKind::Report          => diagnostics::report_schema(),
+			OutputKind::Stats           => playlist_stats::summary_schema(),
+			OutputKind::StatsComparison => playlist_stats::comparison_schema(),
+			OutputKind::Events          => progress_events::record_schema(),
+		}
+	}
+}
+
+impl fmt::Display for OutputKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.name())
+	}
+}
+
+impl FromStr for OutputKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<OutputKind, String>
+	{
+		match OUTPUT_KINDS.iter().find(|kind| kind.name() == s) {
+			Some(kind) => Ok(*kind),
+			None => {
+				let names: Vec<&str> = OUTPUT_KINDS.iter().map(|kind| kind.name()).collect();
+				Err(format!("Unknown output kind '{0}' (expected one of: {1})", s, names.join(", ")))
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Validation */
+
+/* Schema keywords that the validator knows how to check (or that don't affect validation) */
+#[cfg(test)]
+const SUPPORTED_KEYWORDS: [&'static str; 10] = [
+	"$schema", "title", "description",
+	"type", "properties", "required", "additionalProperties", "items", "enum", "oneOf",
+];
+
+/* Check that a schema only uses keywords that validate() supports
+ * > returns descriptions of the places using anything else
+ * NOTE: Only needed by the tests, to make sure the output schemas can be fully checked
+ */
+#[cfg(test)]
+pub fn check_schema(schema: &Value) -> Result<(), Vec<String>>
+{
+	let mut problems = Vec::new();
+	check_schema_at(schema, "#", &mut problems);
+	if problems.is_empty() { Ok(()) } else { Err(problems) }
+}
+
+#[cfg(test)]
+fn check_schema_at(schema: &Value, path: &str, problems: &mut Vec<String>)
+{
+	let object = match schema.as_object() {
+		Some(object) => object,
+		None => {
+			problems.push(format!("{}: schema isn't an object", path));
+			return;
+		}
+	};
+
+	for (keyword, value) in object.iter() {
+		if !SUPPORTED_KEYWORDS.contains(&keyword.as_str()) {
+			problems.push(format!("{0}: unsupported keyword '{1}'", path, keyword));
+		}
+
+		match (keyword.as_str(), value) {
+			("properties", Value::Object(properties)) => {
+				for (name, property) in properties.iter() {
+					check_schema_at(property, &format!("{0}/properties/{1}", path, name), problems);
+				}
+			},
+			("additionalProperties", Value::Object(_)) | ("items", _) => {
+				check_schema_at(value, &format!("{0}/{1}", path, keyword), problems);
+			},
+			("oneOf", Value::Array(alternatives)) => {
+				for (i, alternative) in alternatives.iter().enumerate() {
+					check_schema_at(alternative, &format!("{0}/oneOf/{1}", path, i), problems);
+				}
+			},
+			_ => {}
+		}
+	}
+}
+
+/* Check a value against a schema
+ * > returns descriptions of everything that doesn't match (with the path to each, e.g. "$.tracks[0].index")
+ */
+pub fn validate(schema: &Value, value: &Value) -> Result<(), Vec<String>>
+{
+	let mut errors = Vec::new();
+	validate_at(schema, value, "$", &mut errors);
+	if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>)
+{
+	/* Type (either a single type name, or a list of them) */
+	match schema.get("type") {
+		Some(Value::String(name)) if !matches_type(value, name) => {
+			errors.push(format!("{0}: expected {1}, found {2}", path, name, type_name(value)));
+			return;
+		},
+		Some(Value::Array(names)) if !names.iter().any(|name| name.as_str().is_some_and(|name| matches_type(value, name))) => {
+			let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+			errors.push(format!("{0}: expected one of {1}, found {2}", path, names.join(" / "), type_name(value)));
+			return;
+		},
+		_ => {}
+	}
+
+	/* Allowed values */
+	if let Some(Value::Array(allowed)) = schema.get("enum") {
+		if !allowed.contains(value) {
+			errors.push(format!("{0}: {1} isn't one of the allowed values", path, value));
+		}
+	}
+
+	/* Objects */
+	if let Value::Object(object) = value {
+		if let Some(Value::Array(required)) = schema.get("required") {
+			for name in required.iter().filter_map(|name| name.as_str()) {
+				if !object.contains_key(name) {
+					errors.push(format!("{0}: missing required property '{1}'", path, name));
+				}
+			}
+		}
+
+		let properties = schema.get("properties").and_then(|p| p.as_object());
+		for (name, property_value) in object.iter() {
+			let property_path = format!("{0}.{1}", path, name);
+			match (properties.and_then(|p| p.get(name)), schema.get("additionalProperties")) {
+				(Some(property_schema), _) => {
+					validate_at(property_schema, property_value, &property_path, errors);
+				},
+				(None, Some(Value::Bool(false))) => {
+					errors.push(format!("{0}: unexpected property '{1}'", path, name));
+				},
+				(None, Some(extra_schema @ Value::Object(_))) => {
+					validate_at(extra_schema, property_value, &property_path, errors);
+				},
+				(None, _) => {}
+			}
+		}
+	}
+
+	/* Arrays */
+	if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+		for (i, item) in items.iter().enumerate() {
+			validate_at(item_schema, item, &format!("{0}[{1}]", path, i), errors);
+		}
+	}
+
+	/* Alternatives - Exactly one of them must match */
+	if let Some(Value::Array(alternatives)) = schema.get("oneOf") {
+		let matching = alternatives.iter()
+			.filter(|alternative| validate(alternative, value).is_ok())
+			.count();
+		if matching != 1 {
+			errors.push(format!("{0}: matches {1} of the {2} alternatives (expected exactly 1)", path, matching, alternatives.len()));
+		}
+	}
+}
+
+/* Does the value have the given (JSON Schema) type? */
+fn matches_type(value: &Value, name: &str) -> bool
+{
+	match name {
+		"null"    => value.is_null(),
+		"boolean" => value.is_boolean(),
+		"integer" => value.is_i64() || value.is_u64(),
+		"number"  => value.is_number(),
+		"string"  => value.is_string(),
+		"array"   => value.is_array(),
+		"object"  => value.is_object(),
+		_         => false
+	}
+}
+
+/* Name of the value's type (for error messages) */
+fn type_name(value: &Value) -> &'static str
+{
+	match *value {
+		Value::Null          => "null",
+		Value::Bool(_)       => "boolean",
+		Value::Number(ref n) => if n.is_f64() { "number" } else { "integer" },
+		Value::String(_)     => "string",
+		Value::Array(_)      => "array",
+		Value::Object(_)     => "object",
+	}
+}
+
+/* Check output text against the schema for its kind of output (each line separately for the events)
+ * > returns descriptions of everything that doesn't match (or couldn't be parsed as JSON)
+ */
+pub fn validate_output(kind: OutputKind, text: &str) -> Result<(), Vec<String>>
+{
+	let schema = kind.schema();
+	let check = |json: &str| -> Result<(), Vec<String>> {
+		match serde_json::from_str::<Value>(json) {
+			Ok(value) => validate(&schema, &value),
+			Err(e) => Err(vec![format!("Invalid JSON - {}", e)])
+		}
+	};
+
+	if kind == OutputKind::Events {
+		let errors: Vec<String> = text.lines().enumerate()
+			.filter(|&(_, line)| !line.trim().is_empty())
+			.filter_map(|(i, line)| check(line).err().map(|errors| (i, errors)))
+			.flat_map(|(i, errors)| errors.into_iter().map(move |e| format!("line {0}: {1}", i + 1, e)))
+			.collect();
+		if errors.is_empty() { Ok(()) } else { Err(errors) }
+	}
+	else {
+		check(text)
+	}
+}
+
+/* Check a serialised value against the schema for its kind of output, panicking with all the mismatches
+ * Helper for the tests checking each kind of output
+ */
+#[cfg(test)]
+pub fn assert_matches_schema(kind: OutputKind, value: &Value)
+{
+	if let Err(errors) = validate(&kind.schema(), value) {
+		panic!("{0} output doesn't match its schema:\n   {1}\n{2}", kind, errors.join("\n   "), value);
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn person_schema() -> Value
+	{
+		json!({
+			"type": "object",
+			"properties": {
+				"name": { "type": "string" },
+				"age": { "type": ["integer", "null"] },
+				"role": { "enum": ["player", "conductor"] },
+				"parts": { "type": "array", "items": { "type": "string" } },
+				"scores": { "type": "object", "additionalProperties": { "type": "number" } },
+			},
+			"required": ["name", "age"],
+			"additionalProperties": false
+		})
+	}
+
+	fn errors(schema: &Value, value: Value) -> Vec<String>
+	{
+		validate(schema, &value).err().unwrap_or_default()
+	}
+
+	#[test]
+	fn test_validate_matching()
+	{
+		let schema = person_schema();
+		assert!(validate(&schema, &json!({ "name": "Ann", "age": 30 })).is_ok());
+		assert!(validate(&schema, &json!({
+			"name": "Ann", "age": null, "role": "player",
+			"parts": ["violin", "viola"], "scores": { "a": 1.5, "b": 2 }
+		})).is_ok());
+	}
+
+	#[test]
+	fn test_validate_mismatches()
+	{
+		let schema = person_schema();
+
+		assert_eq!(vec!["$: expected object, found array"], errors(&schema, json!([])));
+		assert_eq!(vec!["$: missing required property 'age'"], errors(&schema, json!({ "name": "Ann" })));
+		assert_eq!(vec!["$.age: expected one of \"integer\" / \"null\", found number"],
+		           errors(&schema, json!({ "name": "Ann", "age": 30.5 })));
+		assert_eq!(vec!["$.role: \"cellist\" isn't one of the allowed values"],
+		           errors(&schema, json!({ "name": "Ann", "age": 30, "role": "cellist" })));
+		assert_eq!(vec!["$.parts[1]: expected string, found integer"],
+		           errors(&schema, json!({ "name": "Ann", "age": 30, "parts": ["violin", 2] })));
+		assert_eq!(vec!["$.scores.b: expected number, found string"],
+		           errors(&schema, json!({ "name": "Ann", "age": 30, "scores": { "a": 1, "b": "2" } })));
+
+		/* New fields that aren't in the schema get noticed */
+		assert_eq!(vec!["$: unexpected property 'instrument'"],
+		           errors(&schema, json!({ "name": "Ann", "age": 30, "instrument": "violin" })));
+
+		/* Everything gets reported, not just the first problem */
+		assert_eq!(2, errors(&schema, json!({ "age": "30" })).len());
+	}
+
+	#[test]
+	fn test_validate_one_of()
+	{
+		let schema = json!({
+			"oneOf": [
+				{ "type": "string", "enum": ["mp3", "flac"] },
+				{ "type": "object", "properties": { "Unknown": { "type": "string" } }, "required": ["Unknown"] },
+			]
+		});
+		assert!(validate(&schema, &json!("mp3")).is_ok());
+		assert!(validate(&schema, &json!({ "Unknown": "wav" })).is_ok());
+		assert_eq!(vec!["$: matches 0 of the 2 alternatives (expected exactly 1)"], errors(&schema, json!("wav")));
+	}
+
+	#[test]
+	fn test_check_schema()
+	{
+		assert!(check_schema(&person_schema()).is_ok());
+		assert_eq!(Err(vec!["#/properties/age: unsupported keyword 'minimum'".to_string()]),
+		           check_schema(&json!({ "type": "object", "properties": { "age": { "type": "integer", "minimum": 0 } } })));
+
+		/* The validator covers everything used by the output schemas */
+		for kind in OUTPUT_KINDS.iter() {
+			assert_eq!(Ok(()), check_schema(&kind.schema()), "{}", kind);
+		}
+	}
+
+	#[test]
+	fn test_validate_output()
+	{
+		let report = "{ \"entries\": [{ \"severity\": \"Info\", \"category\": \"x\", \"track_index\": null, \"message\": \"\" }] }";
+		assert_eq!(Ok(()), validate_output(OutputKind::Report, report));
+		assert!(validate_output(OutputKind::Report, "{ \"entries\": ").unwrap_err()[0].starts_with("Invalid JSON"));
+
+		/* Events get checked a line at a time */
+		let events = "{\"version\":1,\"seq\":0,\"dropped\":0,\"event\":{\"type\":\"run_started\",\"mode\":\"copy\",\"total_tracks\":1,\"total_bytes\":null}}\n\
+		              \n\
+		              {\"version\":1,\"seq\":1,\"dropped\":0,\"event\":{\"type\":\"run_paused\"}}\n";
+		assert_eq!(Err(vec!["line 3: $.event: matches 0 of the 4 alternatives (expected exactly 1)".to_string()]),
+		           validate_output(OutputKind::Events, events));
+	}
+
+	#[test]
+	fn test_output_kind_names()
+	{
+		for kind in OUTPUT_KINDS.iter() {
+			assert_eq!(Ok(*kind), kind.name().parse::<OutputKind>());
+		}
+		assert!("diff".parse::<OutputKind>().is_err());
+	}
+}
@@ -0,0 +1,161 @@
+/* Library crate for "xspf_tools"
+ *
+ * The parser and track info types can be used by other tools (see the "xspf" module below,
+ * and the re-exported types). Everything else here is what the xspf_tools executable (main.rs)
+ * is built from - it's public so that the executable can get at it, but isn't part of the API
+ * (hence hidden from the docs), and may change at any time.
+ *
+ * NOTE: Nothing in here exits the process - errors get handed back for the caller to deal with.
+ */
+
+//! Parsing of XSPF (and M3U) playlists, and the info encoded in the filenames of the tracks in them.
+//!
+//! ```
+//! extern crate xspf_tools;
+//!
+//! use xspf_tools::xspf;
+//! use xspf_tools::TrackType;
+//!
+//! let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+//! <playlist xmlns="http://xspf.org/ns/0/" version="1">
+//!   <title>Sessions</title>
+//!   <trackList>
+//!     <track><location>file:///music/20170403/v01b-tranquil.mp3</location><duration>61000</duration></track>
+//!     <track><location>file:///music/20170802/20170802-02-TouchedByAnAngel.flac</location></track>
+//!     <track><location>http://example.com/stream</location></track>
+//!   </trackList>
+//! </playlist>"#;
+//!
+//! let playlist = match xspf::parse_str(xml, "sessions.xspf", &xspf::ParseOptions::default()) {
+//!     Ok(playlist) => playlist,
+//!     Err(e) => panic!("Couldn't parse the playlist - {}", e),
+//! };
+//! assert_eq!(2, playlist.len());
+//! assert_eq!(1, playlist.skipped.len());
+//!
+//! let info = playlist.tracks[0].info();
+//! assert_eq!((TrackType::ViolinLayering, 1, "tranquil"), (info.track_type, info.index, info.name.as_str()));
+//! assert_eq!("01:01", playlist.tracks[0].duration.as_ref().unwrap().to_timecode());
+//!
+//! /* Tracks without durations aren't counted in the total */
+//! let total = playlist.total_duration();
+//! assert_eq!((61000, 1), (total.duration.0, total.uncounted));
+//! ```
+
+/* macro_use defines need to happen in the crate root - https://stackoverflow.com/a/39175997/6531515 */
+#[macro_use] extern crate lazy_static;
+#[macro_use] extern crate serde_derive;
+#[macro_use] mod logic_macros;
+
+extern crate serde;
+#[macro_use] extern crate serde_json;
+
+/* Public API */
+pub mod track_duration;  // XXX: Have this as part of xspf_parser?
+pub mod track_name_info; // XXX: Have this as part of xspf_parser
+pub mod track_date;
+pub mod track_path;
+
+pub mod xspf_parser;
+pub mod xspf_extension;
+pub mod m3u_parser;
+pub mod json_export;
+pub mod diagnostics;
+pub mod duration_source;
+
+/* Internals (used by the executable) */
+mod xml_security;
+mod xml_writer;
+#[doc(hidden)] pub mod playlist_input;
+#[doc(hidden)] pub mod parallel_parse;
+#[doc(hidden)] pub mod parallel_copy;
+
+mod track_field;
+#[doc(hidden)] pub mod filter_expr;
+#[doc(hidden)] pub mod track_sampling;
+
+#[doc(hidden)] pub mod command_args;
+#[doc(hidden)] pub mod json_schema;
+#[doc(hidden)] pub mod freshness;
+#[doc(hidden)] pub mod session_gaps;
+#[doc(hidden)] pub mod track_lint;
+#[doc(hidden)] pub mod playlist_order;
+#[doc(hidden)] pub mod track_diff;
+#[doc(hidden)] pub mod split_types;
+#[doc(hidden)] pub mod title_numbering;
+#[doc(hidden)] pub mod practice_playlist;
+#[doc(hidden)] pub mod playlist_merge;
+#[doc(hidden)] pub mod playlist_diff;
+#[doc(hidden)] pub mod playlist_augment;
+#[doc(hidden)] pub mod terminal_width;
+#[doc(hidden)] pub mod terminal_text;
+#[doc(hidden)] pub mod playlist_stats;
+
+mod name_folding;
+#[doc(hidden)] pub mod near_dupes;
+#[doc(hidden)] pub mod content_dupes;
+#[doc(hidden)] pub mod interactive_resolve;
+
+#[doc(hidden)] pub mod media_probe;
+#[doc(hidden)] pub mod probe_cache;
+#[doc(hidden)] pub mod duration_verify;
+
+#[doc(hidden)] pub mod ffmpeg_command;
+#[doc(hidden)] pub mod convert_stamps;
+#[doc(hidden)] pub mod convert_resume;
+#[doc(hidden)] pub mod convert_presets;
+#[doc(hidden)] pub mod track_move;
+#[doc(hidden)] pub mod track_relocate;
+#[doc(hidden)] pub mod file_timestamps;
+#[doc(hidden)] pub mod dest_naming;
+#[doc(hidden)] pub mod nfo_sidecar;
+#[doc(hidden)] pub mod post_command;
+#[doc(hidden)] pub mod size_estimate;
+#[doc(hidden)] pub mod tar_archive;
+#[doc(hidden)] pub mod web_player;
+#[doc(hidden)] pub mod progress_events;
+#[doc(hidden)] pub mod output_file;
+#[doc(hidden)] pub mod output_lock;
+#[doc(hidden)] pub mod run_journal;
+#[doc(hidden)] pub mod run_outcome;
+#[doc(hidden)] pub mod usage_log;
+
+#[cfg(test)] mod test_utils;
+
+/* ********************************************* */
+/* Re-exports */
+
+/// Reading/writing playlists - the main entrypoint for the library.
+///
+/// ```no_run
+/// extern crate xspf_tools;
+/// use xspf_tools::xspf;
+///
+/// match xspf::parse("music/2017-08.xspf", &xspf::ParseOptions::default()) {
+///     Ok(playlist) => {
+///         for track in playlist.tracks.iter() {
+///             println!("{} - {:?}", track.filename, track.duration);
+///         }
+///     },
+///     Err(e) => eprintln!("ERROR: {}", e),
+/// }
+/// ```
+pub mod xspf {
+	pub use xspf_parser::XspfPlaylist as Playlist;
+	pub use xspf_parser::{Track, SkippedTrack, ParseOptions, IoStrategy, XspfDurationTallyResult, DurationStats, TrackFilter};
+	pub use xspf_parser::XspfError as Error;
+	pub use xspf_extension::{ExtensionBlock, ExtensionNode};
+	pub use xspf_parser::parse_xspf as parse;
+	pub use xspf_parser::parse_xspf_str as parse_str;
+	pub use xspf_parser::parse_xspf_reader as parse_reader;
+	pub use xspf_parser::parse_xspf_streaming as parse_streaming;
+
+	pub use m3u_parser::read_m3u_playlist as parse_m3u;
+
+	pub use playlist_order::SortKey;
+	pub use playlist_stats::StatsSummary as PlaylistStats;
+	pub use playlist_stats::TypeStats;
+}
+
+pub use track_duration::TrackDuration;
+pub use track_name_info::{TrackType, TrackExtension, FilenameInfoComponents};
@@ -8,7 +8,7 @@
 
 /// Check if the first argument in any of the following arguments, eg:
 ///
-/// ```.text
+/// ```text
 /// if elem!(my_var, FOO, BAR, BAZ) { ... }
 /// ```
 ///
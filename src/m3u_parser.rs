@@ -0,0 +1,324 @@
+/* Reading M3U playlists
+ *
+ * Plain M3U files are just a list of file locations (one per line). Extended ones ("#EXTM3U") can
+ * also give each entry a duration and title, on an "#EXTINF:<secs>,<title>" line before it.
+ * These get used to fill in details missing from XSPF playlists of the same tracks (see playlist_augment),
+ * or can be read in place of an XSPF playlist for any mode (see read_m3u_playlist()).
+ */
+use std::fs;
+use std::path::Path;
+
+use diagnostics::{Diagnostics, Severity};
+use duration_source::DurationSource;
+use track_duration::TrackDuration;
+use track_path::{LocationForm, PathStyle, TrackPath};
+use xspf_parser::{SkippedTrack, Track, XspfPlaylist};
+
+/* Header line of extended M3U files */
+pub const EXTM3U_HEADER: &'static str = "#EXTM3U";
+
+/* Directive giving the duration and title of the next entry */
+const EXTINF_PREFIX: &'static str = "#EXTINF:";
+
+/* *************************************************** */
+/* Entries */
+
+/* A single file listed in the playlist */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct M3uEntry {
+	/* Location as written in the file (a path, or a "file:" URI) */
+	pub location: String,
+
+	/* Duration (in ms) from "#EXTINF" (if one was given, and it wasn't -1/unknown) */
+	pub duration_ms: Option<i64>,
+	/* Title from "#EXTINF" (if one was given) */
+	pub title: Option<String>,
+
+	/* Line number of the location (1-based) */
+	pub line: usize,
+}
+
+impl M3uEntry {
+	/* Path of the file, with relative locations resolved against the playlist's directory
+	 * NOTE: As with XSPF locations, paths from "file:///" URIs don't keep their leading slash (see TrackPath::from_uri())
+	 */
+	pub fn track_path(&self, base_dir: &TrackPath) -> TrackPath
+	{
+		if LocationForm::classify(&self.location) == Some(LocationForm::AbsoluteUri) {
+			if let Ok(path) = TrackPath::from_uri(&self.location) {
+				return path;
+			}
+		}
+
+		/* Plain paths aren't escaped, but may have been written on Windows */
+		let style = if self.location.contains('\\') { PathStyle::Windows } else { PathStyle::Unix };
+		TrackPath::from_native_bytes(self.location.as_bytes(), style).resolve_against(base_dir)
+	}
+}
+
+/* Contents of an M3U playlist */
+#[derive(Debug)]
+#[derive(Default)]
+pub struct M3uPlaylist {
+	/* Did it start with the "#EXTM3U" header? */
+	pub extended: bool,
+
+	pub entries: Vec<M3uEntry>,
+}
+
+impl M3uPlaylist {
+	/* Convert to a playlist of tracks, with relative locations resolved against the given directory
+	 * (i.e. the M3U's directory). Durations and titles from "#EXTINF" are kept too.
+	 * Entries that don't refer to a file get skipped (and noted).
+	 */
+	pub fn to_playlist(&self, base_dir: &TrackPath) -> XspfPlaylist
+	{
+		let mut tracks: Vec<Track> = Vec::new();
+		let mut diagnostics = Diagnostics::new();
+		let mut skipped: Vec<SkippedTrack> = Vec::new();
+
+		for (index, entry) in self.entries.iter().enumerate() {
+			/* Streams (e.g. "http://...") aren't files */
+			let track = match LocationForm::classify(&entry.location) {
+				Some(_) => Track::from_track_path(entry.track_path(base_dir)),
+				None    => Err("Unsupported URI - Only local files ('file:') can be used")
+			};
+			match track {
+				Ok(mut track) => {
+					if let Some(duration_ms) = entry.duration_ms {
+						track.duration = Some(TrackDuration(duration_ms));
+						track.duration_source = DurationSource::Playlist;
+					}
+					track.title = entry.title.clone();
+					track.original_index = index;
+					tracks.push(track);
+				},
+				Err(e) => {
+					diagnostics.add(Severity::Warning, M3U_ENTRY_CATEGORY, None,
+						format!("line {0}: entry skipped ({1}) - '{2}'", entry.line, e, entry.location));
+					skipped.push(SkippedTrack { location: Some(entry.location.clone()), reason: e.to_string() });
+				}
+			}
+		}
+
+		XspfPlaylist {
+			tracks: tracks,
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: diagnostics,
+			skipped: skipped
+		}
+	}
+}
+
+/* Diagnostic category for M3U entries that couldn't be used as tracks */
+pub const M3U_ENTRY_CATEGORY: &'static str = "m3u-entry";
+
+/* *************************************************** */
+/* Parsing */
+
+/* Parse the value of an "#EXTINF:" line - "<secs>[ <attributes>],<title>"
+ * > returns the duration (unless unknown), and the title (unless empty)
+ */
+fn parse_extinf(value: &str) -> (Option<i64>, Option<String>)
+{
+	/* The title starts after the first comma that isn't inside a quoted attribute value */
+	let mut in_quotes = false;
+	let comma = value.char_indices().find(|&(_, c)| {
+		if c == '"' {
+			in_quotes = !in_quotes;
+		}
+		c == ',' && !in_quotes
+	}).map(|(pos, _)| pos);
+
+	let (info, title) = match comma {
+		Some(pos) => (&value[.. pos], Some(value[pos + 1 ..].trim())),
+		None      => (value, None)
+	};
+
+	/* Duration is the first part of the info (any attributes follow it) */
+	let secs = info.split_whitespace().next().and_then(|s| s.parse::<f64>().ok());
+	let duration_ms = match secs {
+		Some(secs) if secs.is_finite() && secs >= 0.0 => Some((secs * 1000.0).round() as i64),
+		_                                             => None
+	};
+	(duration_ms, title.filter(|t| !t.is_empty()).map(|t| t.to_string()))
+}
+
+/* Parse the contents of an M3U file */
+pub fn parse_m3u(text: &str) -> M3uPlaylist
+{
+	let mut playlist = M3uPlaylist::default();
+	let mut pending_info: Option<(Option<i64>, Option<String>)> = None;
+
+	for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		if line.starts_with('#') {
+			if i == 0 && line == EXTM3U_HEADER {
+				playlist.extended = true;
+			}
+			else if let Some(value) = line.strip_prefix(EXTINF_PREFIX) {
+				pending_info = Some(parse_extinf(value));
+			}
+			/* Any other directives/comments aren't needed */
+			continue;
+		}
+
+		let (duration_ms, title) = pending_info.take().unwrap_or((None, None));
+		playlist.entries.push(M3uEntry {
+			location: line.to_string(),
+			duration_ms: duration_ms,
+			title: title,
+			line: i + 1,
+		});
+	}
+	playlist
+}
+
+/* Read and parse an M3U file */
+pub fn read_m3u(path: &Path) -> Result<M3uPlaylist, String>
+{
+	match fs::read(path) {
+		/* NOTE: M3U files without the "8" may not be UTF-8, but the paths are all that matter from those */
+		Ok(bytes) => Ok(parse_m3u(&String::from_utf8_lossy(&bytes))),
+		Err(e)    => Err(format!("Couldn't read M3U playlist {0:?} - {1}", path, e))
+	}
+}
+
+/* Is the file an M3U playlist? (going by its extension - ".m3u" or ".m3u8") */
+pub fn is_m3u_file(filename: &str) -> bool
+{
+	match Path::new(filename).extension() {
+		Some(extn) => {
+			let extn = extn.to_string_lossy().to_lowercase();
+			extn == "m3u" || extn == "m3u8"
+		},
+		None => false
+	}
+}
+
+/* Read an M3U file as a playlist (see M3uPlaylist::to_playlist())
+ * - rebase: Directory to resolve relative locations against, instead of the M3U's directory
+ */
+pub fn read_m3u_playlist(filename: &str, rebase: Option<&Path>) -> Result<XspfPlaylist, String>
+{
+	let bytes = fs::read(filename).map_err(|e| format!("Couldn't read file - {}", e))?;
+	let m3u = parse_m3u(&String::from_utf8_lossy(&bytes));
+
+	let base_dir = rebase.unwrap_or_else(|| Path::new(filename).parent().unwrap_or(Path::new("")));
+	let base_dir = fs::canonicalize(base_dir).unwrap_or_else(|_| base_dir.to_path_buf());
+	Ok(m3u.to_playlist(&TrackPath::from_native(&base_dir)))
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_extinf()
+	{
+		assert_eq!((Some(61_000), Some("Tranquil".to_string())), parse_extinf("61,Tranquil"));
+		assert_eq!((Some(61_500), Some("A, B".to_string())), parse_extinf("61.5 ,A, B"));
+		assert_eq!((None, Some("Live".to_string())), parse_extinf("-1,Live"));
+		assert_eq!((Some(5_000), None), parse_extinf("5"));
+
+		/* Attributes (which can contain commas) come before the title */
+		assert_eq!((Some(180_000), Some("Song".to_string())),
+		           parse_extinf("180 tvg-name=\"a,b\" group-title=\"x\",Song"));
+	}
+
+	#[test]
+	fn test_parse_m3u()
+	{
+		let m3u = parse_m3u("\u{feff}#EXTM3U\n\n\
+		                     #EXTINF:61,Tranquil\n\
+		                     20170403/v01-tranquil.mp3\n\
+		                     #EXTVLCOPT:network-caching=1000\n\
+		                     file:///music/20170404/v02-stormy%20sea.mp3\n\
+		                     #EXTINF:-1,\n\
+		                     C:\\music\\v03-c.mp3\n");
+		assert!(m3u.extended);
+		assert_eq!(3, m3u.entries.len());
+
+		assert_eq!(M3uEntry { location: "20170403/v01-tranquil.mp3".to_string(), duration_ms: Some(61_000),
+		                      title: Some("Tranquil".to_string()), line: 4 },
+		           m3u.entries[0]);
+		assert_eq!((None, None), (m3u.entries[1].duration_ms, m3u.entries[1].title.clone()));
+		assert_eq!((None, None), (m3u.entries[2].duration_ms, m3u.entries[2].title.clone()));
+
+		/* Plain M3U */
+		let m3u = parse_m3u("a.mp3\r\nb.mp3\r\n");
+		assert!(!m3u.extended);
+		assert_eq!(vec!["a.mp3", "b.mp3"], m3u.entries.iter().map(|e| e.location.as_str()).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_entry_paths()
+	{
+		let base = TrackPath::from_normalized("/home/me/lists");
+		let m3u = parse_m3u("20170403/v01-tranquil.mp3\n\
+		                     /music/v02 b.mp3\n\
+		                     file:///music/v03%20c.mp3\n\
+		                     ..\\music\\v04-d.mp3\n");
+		let paths: Vec<String> = m3u.entries.iter().map(|e| e.track_path(&base).as_str().to_string()).collect();
+		assert_eq!(vec!["/home/me/lists/20170403/v01-tranquil.mp3",
+		                "/music/v02 b.mp3",
+		                "music/v03 c.mp3",  /* no leading slash, as with XSPF locations (see TrackPath::from_uri()) */
+		                "/home/me/lists/../music/v04-d.mp3"],
+		           paths);
+	}
+
+	#[test]
+	fn test_to_playlist()
+	{
+		let base = TrackPath::from_normalized("/home/me/lists");
+		let m3u = parse_m3u("#EXTM3U\n\
+		                     #EXTINF:61,Tranquil\n\
+		                     20170403/v01-tranquil.mp3\n\
+		                     /music/20170404/v02-stormy.mp3\n\
+		                     file:///\n\
+		                     #EXTINF:-1,Radio\n\
+		                     http://example.com/stream.mp3\n");
+		let xspf = m3u.to_playlist(&base);
+		assert_eq!(2, xspf.len());
+
+		assert_eq!("/home/me/lists/20170403/v01-tranquil.mp3", xspf.tracks[0].path.as_str());
+		assert_eq!("20170403", xspf.tracks[0].date.as_str());
+		assert_eq!(Some(61_000), xspf.tracks[0].duration.as_ref().map(|d| d.0));
+		assert_eq!(DurationSource::Playlist, xspf.tracks[0].duration_source);
+		assert_eq!(Some("Tranquil".to_string()), xspf.tracks[0].title);
+
+		assert_eq!("v02-stormy.mp3", xspf.tracks[1].filename);
+		assert_eq!(1, xspf.tracks[1].original_index);
+		assert!(xspf.tracks[1].duration.is_none());
+		assert_eq!(DurationSource::Unknown, xspf.tracks[1].duration_source);
+
+		/* The entry without a filename, and the stream, were skipped */
+		assert_eq!(2, xspf.diagnostics.len());
+		assert!(xspf.diagnostics.entries[0].message.starts_with("line 5:"));
+		assert_eq!(vec![Some("file:///"), Some("http://example.com/stream.mp3")],
+		           xspf.skipped.iter().map(|s| s.location.as_deref()).collect::<Vec<_>>());
+		assert!(xspf.skipped[1].reason.starts_with("Unsupported URI"));
+	}
+
+	#[test]
+	fn test_is_m3u_file()
+	{
+		assert!(is_m3u_file("lists/all.m3u"));
+		assert!(is_m3u_file("all.M3U8"));
+		assert!(!is_m3u_file("all.xspf"));
+		assert!(!is_m3u_file("m3u"));
+	}
+}
@@ -1,35 +1,50 @@
-/* Main entrypoint for "xspf_tools" executable AND 
- * also the implicit crate that it and all its stuff lives in
+/* Main entrypoint for "xspf_tools" executable
+ * All the actual processing lives in the library crate (see lib.rs) - this just handles the command-line
  */
 
 /* macro_use defines need to happen in the crate root - https://stackoverflow.com/a/39175997/6531515 */
-#[macro_use] extern crate lazy_static;
 #[macro_use] extern crate indoc;
-#[macro_use] extern crate serde_derive;
-#[macro_use] mod logic_macros;
 
-extern crate serde;
 extern crate serde_json;
+extern crate ctrlc;
+
+extern crate xspf_tools;
 
 //use serde_json::Error;
 
+use std::collections::HashMap;
 use std::env;
 use std::process;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 //use std::error::Error;
 use std::fs::{self, File};
-use std::io::{self, Write};
-use std::path::Path;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use xspf_tools::{track_duration, track_name_info, track_date, track_path};
+
+use xspf_tools::{xspf_parser, xspf_extension, playlist_input, json_export, parallel_parse, parallel_copy};
+use xspf_tools::{filter_expr, track_sampling};
+
+use xspf_tools::{command_args, diagnostics, json_schema, freshness, session_gaps, track_lint, playlist_order, track_diff,
+                 split_types, title_numbering, practice_playlist, playlist_merge, playlist_diff, m3u_parser, playlist_augment,
+                 terminal_width, terminal_text, playlist_stats};
 
-mod track_duration;  // XXX: Have this as part of xspf_parser?
-mod track_name_info; // XXX: Have this as part of xspf_parser
+use xspf_tools::{near_dupes, content_dupes, interactive_resolve};
 
-mod xspf_parser;
+use xspf_tools::{media_probe, probe_cache, duration_source, duration_verify};
+
+use xspf_tools::{ffmpeg_command, convert_stamps, convert_resume, convert_presets, track_move, file_timestamps,
+                 track_relocate, dest_naming, nfo_sidecar, post_command, size_estimate, tar_archive, web_player, progress_events,
+                 output_file, output_lock, run_journal, run_outcome, usage_log};
 
 /* Aliases */
 use xspf_parser::Track as Track;
+use xspf_parser::XspfPlaylist as XspfPlaylist;
 use track_name_info::TrackExtension as TrackExtension;
+use progress_events::TrackStatus as TrackStatus;
 
 /* ********************************************* */
 
@@ -42,24 +57,387 @@ fn print_usage_info()
                            * help      Prints this text
                            
                            * dump      Prints summary of the important identifying info gained from the playlist
+                                       (including any entries that were skipped, e.g. streams)
+                                       --verbose also shows other applications' <extension> blocks (e.g. VLC's track ids)
                            * runtime   Prints summary of the total running time of the playlist
+                                       (with the average length, shortest/longest tracks, and totals per track type)
+                                       Several playlists can be given at once (runtime <in1.xspf> <in2.xspf> ...),
+                                       which get read in parallel. Options: --jobs=<N> (default 4), --timeout=<secs> (default 30)
+                           
+                           * freshness Flags tracks whose files were modified long after (or before) their session date.
+                                       Options: --max-drift=<days> (default 30), --json
+                           
+                           * gaps      Reports missing take numbers within each session (e.g. have 1,2,3,5 - missing 4),
+                                       and take numbers used by takes with different names. Options: --json
+                           
+                           * check     Checks each track's filename against the naming conventions, flagging tracks of unknown
+                                       type, MuseScore files filed under a different date from the one in their name, take
+                                       number 0, names with characters that can't be used in output filenames, and unknown
+                                       extensions (listed with the track number, filename, and the id of the rule broken).
+                                       Options: --json, --strict (exit with an error code if any tracks are flagged)
+                           
+                           * missing   Lists the tracks whose files no longer exist (e.g. they've been moved or deleted),
+                                       with how many of the tracks that is. Exits with an error code if any are missing.
+                           * relocate  Shows where the missing tracks have moved to under --search-root=<dir> (old -> new
+                                       path for each), listing the candidates for any that turned up in several places.
+                                       Nothing gets changed. Usage: relocate <in.xspf> [<outfile>] --search-root=<dir>
+                           
+                           * stats     Prints the number of tracks and total duration of the playlist, overall and per type,
+                                       session date (most recent first), and file extension.
+                                       Options: --json, --compare=<start>..<end> <start>..<end> (shows the stats for tracks
+                                       from two ranges of session dates side-by-side, along with the change between them,
+                                       e.g. --compare=2017-01-01..2017-06-30 2017-07-01..2017-12-31),
+                                       --by=<day|week|month> (also shows the totals per session date, week, or month -
+                                       weeks are ISO-8601 weeks, labelled like 2017-W31), --week-start=<monday|sunday>
+                                       (with --by=week, use simple non-ISO weeks starting on that day instead - week 1
+                                       is the one containing 1st January, and dates never move into another year)
+                           
+                           * near-dupes Reports tracks in the same folder whose filenames only differ by encoding
+                                       artifacts (spaces vs underscores, case, trailing spaces, accent encoding)
+                                       Options: --json, --renames=<file> (writes suggested renames as <old>TAB<new> lines)
+                           
+                           * dupes     Reports tracks whose files have exactly the same contents (whatever they're called).
+                                       Files are compared by size, then their first/last 64 KiB, then in full, reporting
+                                       how many were ruled out at each stage and how much was read. Options: --json,
+                                       --fast (stop before comparing in full - large files are only probable duplicates),
+                                       --dedupe (deletes all but the first copy of each - lists what would be deleted,
+                                       unless given --yes to delete them all, --interactive to ask about each one, or
+                                       --replay=<file> to do what was decided before), --record=<file> (adds the
+                                       decisions made to <file>, for --replay)
+                           
+                           * verify-order Reports tracks that are out of chronological order (by session date), or out of
+                                       index order within a session. Options: --json, --fix=<file> (writes the re-sorted
+                                       playlist, ordered by date, type, then index, as JSON rather than XSPF;
+                                       undated tracks stay in place)
+                           * sort      Sorts the tracks, writing the sorted playlist to <outfile> (or if that isn't given,
+                                       listing the paths of the sorted tracks). Options: --by=<date|name|duration|type>
+                                       (default: date - by session date, type, then index, with unknown types last)
+                           * shuffle   Writes the tracks in a random order to <outfile> (as XSPF, or just the paths for
+                                       .m3u/.txt outfiles, or if that isn't given). Options: --seed=<N> (the same seed
+                                       always gives the same order - without one, the seed picked gets printed)
                            
                            * list      Writes the file paths of all tracks in the playlist to <outfile>
+                                       (as each track is read, so large playlists start listing straight away)
                            * json      Extracts the useful info out of the file, and dumps to JSON format
-                                       in <outfile> for easier handling
+                                       in <outfile> for easier handling (including totals, and a schema_version)
+                           
+                           * probe     Prints the duration, codec, sample rate, and channels of each track,
+                                       as reported by ffprobe (assuming FFMPEG is on the path).
+                                       Options: --probe-cache=<path>, --no-probe-cache
+                           * verify    Checks the durations stored in the playlist against those of the files (as probed),
+                                       listing any that differ by more than the tolerance, with a summary of how many
+                                       matched/mismatched/were missing/had no stored duration. Fails if any mismatched.
+                                       Options: --tolerance=<secs> (default 2), --json, --probe-cache=<path>, --no-probe-cache
+                           
+                           * cache     Manages the cache of ffprobe results. Usage: cache prune <in.xspf> [--probe-cache=<path>]
+                                       - prune  Drops entries for files that no longer exist
                            
                            * copy      Copies all the files named in the playlist to the nominated folder <outdir>.
+                                       The copies keep the modification times of the source files.
+                                       Options: --stamp-from-date (set them to midday on the session date instead),
+                                       --jobs=<N> (number of files to copy at the same time, default 4 - everything
+                                       else still happens in playlist order), --force (copy every file again, even if
+                                       the copy from an earlier run is up to date - same size and modification time)
+                           
+                           * move      Moves all the files named in the playlist to <outdir> (named as for copy). Each file
+                                       is copied and verified (size + hash), and only then is the source deleted. Files on
+                                       the same filesystem as <outdir> just get renamed. Every step is recorded in
+                                       <outdir>/.xspf_tools-move.json, so running it again resumes an interrupted move.
+                                       Options: --no-delete (copy and verify, but keep the source files)
                            
                            * convert   Similar to copy, but it takes an additional <format> arg (i.e. ''<sub-mode>'')
                                        specifying the output format to convert everything to. Any additional arguments
                                        after that are passed directly to FFMPEG (assuming FFMPEG is on the path).
                                        
-                                       Supported formats: mp3, flac, ogg
+                                       Supported formats: mp3, flac, ogg, and the video formats mp4, mkv (e.g. for
+                                       uploading to video sites - the picture is the image given by --cover=<image>,
+                                       or otherwise a waveform of each track, with AAC audio)
+                                       Options: --quality=<high|medium|low> (default medium - the encoder settings for
+                                       the format, i.e. -qscale:a 0/4/7 for mp3 with libmp3lame, -qscale:a 8/5/3 for ogg with
+                                       libvorbis, or -compression_level 8/5/0 for flac. These come before any args passed
+                                       to ffmpeg, so those can override them), --bitrate=<rate> (e.g. 192k - used in place
+                                       of the quality for mp3/ogg), --incremental (skip tracks whose output is
+                                       newer than the source), --settings-diff (incremental, but also redo tracks that were
+                                       converted using different ffmpeg settings), --fade-in=<secs> / --fade-out=<secs>
+                                       (fade the start/end of each track - fractions allowed, e.g. 1.5; tracks already in
+                                       the output format get re-encoded too, to apply them), --resume-partial (pick up
+                                       an interrupted run - skips tracks whose output is complete, after clearing out
+                                       any half-written temp files), --preset=<type>:<preset>,... (settings for each
+                                       type of track, e.g. --preset=Voice:mono-64k,ViolinLayering:hq - types without one
+                                       use the settings above. Presets: hq (320k), standard (192k), mono-64k or voice
+                                       (mono, 64k), archival-flac (flac output only). Presets contradicting --bitrate or
+                                       the ffmpeg args are refused), --force-transcode (re-encode tracks already in the
+                                       output format too - by default these just get copied, to avoid losing quality),
+                                       --overwrite (redo tracks whose output already exists - by default these are
+                                       skipped as already converted, so re-running an interrupted convert resumes it)
+                           
+                           * sample    Picks a few tracks spread across the playlist's sessions and types (shared out between
+                                       each date + type in proportion to their number of tracks), then lists, copies, or
+                                       converts just those, e.g. to spot-check the quality before converting everything.
+                                       Usage: sample <in.xspf> [<outdir>] [--action=list|copy|convert] [--format=<fmt>]
+                                       Options: --count=<K> (default 5), --seed=<N> (the same seed always picks the same
+                                       tracks), plus the options for the chosen action
+                           
+                           * estimate  Estimates the size of the files that convert would produce, from each track's duration
+                                       and the bitrate (or for flac, from the source file sizes).
+                                       Options: --format=<mp3|ogg|flac> (default mp3), --quality=<high|medium|low> (as for
+                                       convert), --bitrate=<rate> (default: the typical bitrate for the quality, e.g.
+                                       ~165k for mp3 at medium quality), --stat (compare against
+                                       the sizes of the source files), --json
+                           * size      Shows how much space the tracks' files take up (e.g. to check a playlist will fit on
+                                       a device), with the totals per extension, the largest files, and any that couldn't
+                                       be checked. Options: --estimate=<mp3|ogg|flac> (also estimate the size after
+                                       converting, from the durations - assuming 320k for mp3, 256k for ogg, and ~1000k for
+                                       flac, unless --bitrate=<rate> is given)
+                           
+                           * bundle    Writes the (renamed) track files, along with an m3u manifest, JSON metadata, and
+                                       a report, into a single uncompressed tar archive <outfile>
+                           * unbundle  Extracts a bundle. Usage: unbundle <archive.tar> <outdir>
+                                       (Entries with absolute paths or '..' components are refused)
+                           * webplayer Writes a single HTML page <out.html> that lists the tracks and plays them in the
+                                       browser, from the files made by copy/convert (no other files needed).
+                                       Usage: webplayer <in.xspf> <out.html> [--media-base=<url>] [--from-copy-manifest=<m3u>]
+                                       Options: --media-base (URL of the folder with the files, default './'),
+                                       --from-copy-manifest (the .m3u8 written by copy/convert - use the names listed
+                                       in it, e.g. after converting to another format). Also takes the --filter options
+                           
+                           * edit      Makes small changes to a playlist, writing the result as XSPF. Positions start at 1.
+                                       Usage: edit <in.xspf> <out.xspf | --in-place> <command>
+                                       - remove <positions>        e.g. 7, or 2-5,9
+                                       - move <from> <to>          Moves a track so that it ends up at position <to>
+                                       - insert <path> [--at=<pos>] [--probe]
+                                                                   Adds a file (at the end by default). --probe fills in
+                                                                   its duration using ffprobe
+                                       The input is only overwritten when --in-place is given
+                           
+                           * number-titles Prefixes the title of each track with its position (e.g. '01. tranquil'), for players
+                                       that sort by title instead of using the playlist order. Tracks without a title get
+                                       their name from the filename. Writes the result as XSPF.
+                                       Usage: number-titles <in.xspf> <out.xspf | --in-place> [options]
+                                       Options: --start-index=<N> (default 1), --replace-titles (use the name from the
+                                       filename even for tracks with titles), --strip (remove the numbering again)
+                                       Numbering an already numbered playlist renumbers it (rather than numbering twice)
+                           
+                           * write     Writes the playlist back out as XSPF to <out.xspf> (e.g. to tidy up a playlist written
+                                       by another program, or to convert an M3U playlist). Usage: write <in.xspf> <out.xspf>
+                           
+                           * head      Keeps the first tracks of the playlist, writing the result as XSPF to <out.xspf>
+                           * tail      Keeps the last tracks of the playlist, writing the result as XSPF to <out.xspf>
+                                       Usage: head|tail <in.xspf> <out.xspf> [--count=<N> | --duration=<h:mm:ss>]
+                                       Options: --count=<N> (default 10), --duration (keep tracks until their total
+                                       duration reaches this - the track that goes over it is kept too, unless
+                                       --strict-limit is given), --strict-durations (refuse to write anything if tracks
+                                       with unknown durations would be counted, instead of counting them as 0:00)
+                           
+                           * practice  Picks pieces to practice, favouring ones that haven't been played in a while or only
+                                       have a few takes (takes of a piece are matched by name across all the sessions), and
+                                       writes a playlist of the most recent take of each to <out.xspf> (or just lists them).
+                                       Usage: practice <in.xspf> [<out.xspf>] [options]
+                                       Options: --count=<N> and/or --duration=<h:mm:ss> (default: 10 pieces),
+                                       --recency-weight=<w>, --rarity-weight=<w> (default 1 each), --seed=<N> (the same
+                                       seed always picks the same pieces), --filter=<expr>
+                           
+                           * split-types Writes a separate playlist for each type of track in the playlist, named after the
+                                       input and the type (e.g. all.VL.xspf, all.MS.xspf, and all.misc.xspf for unknown types),
+                                       into <outdir> (default: alongside the input). Types without any tracks are skipped.
+                                       Options: --json (also write .json equivalents), --force (overwrite existing files)
+                           
+                           * split     Splits the playlist into parts that each fit within a total duration or number of
+                                       tracks (e.g. for CDs), written as <in>_part1.xspf, <in>_part2.xspf, ... into <outdir>
+                                       (default: alongside the input). Tracks are kept in order, and never split up.
+                                       Usage: split <in.xspf> [<outdir>] --max-duration=<h:mm:ss> | --max-tracks=<N>
+                                       Options: --assume-duration=<h:mm:ss> (count tracks with unknown durations as this,
+                                       instead of 0:00), --force (overwrite existing files)
+                           
+                           * compare   Shows which fields (path, duration, date, name, type, ext) differ between
+                                       two tracks, one from each playlist. Positions start at 1.
+                                       Usage: compare <a.xspf> <b.xspf> <position in a> <position in b>
+                           * diff      Shows what changed between two versions of a playlist - tracks added, removed,
+                                       relocated (matched by filename, e.g. moved to another folder), with a different
+                                       duration, or moved to a different position. Exits with an error code unless
+                                       they're identical. Usage: diff <old.xspf> <new.xspf> [--json]
+                           
+                           * parse-name Shows what gets worked out from each filename given (type, index, variant, name,
+                                       extension, and any revision/date), without needing a playlist - e.g. for checking
+                                       new naming conventions. Usage: parse-name <filename> [<filename> ...] [--json]
+                                       --json prints a JSON array with the details of each (null for names that
+                                       couldn't be parsed, e.g. '..')
+                           
+                           * merge     Merges playlists into one, with the tracks of each in order, writing it as XSPF.
+                                       Usage: merge <out.xspf> <in1.xspf> [<in2.xspf> ...] [options]
+                                       Options: --meta=first|last|concat|drop (default first) - how the playlists' title,
+                                       creator, annotation, and date get combined (concat joins the distinct values
+                                       with '; '). Prints which inputs each final value came from (--json for JSON).
+                                       Each track notes which input it came from (kept when saved, see source_playlist)
+                                       --dedupe - Only keep the first of the tracks with the same path (taking the
+                                       duration from a later one, if the first doesn't have one)
+                           
+                           * usage     Prints the totals kept in a usage log (runs, tracks, bytes, and time per mode).
+                                       Usage: usage --usage-log=<path> [--json]
+                           
+                           * schema    Prints the JSON Schema for a kind of JSON output. Usage: schema <kind> [--validate=<file>]
+                                       Kinds: playlist (json), raw-playlist (verify-order --fix, bundle metadata),
+                                       report (verify-order --json), stats (stats --json),
+                                       stats-compare (stats --json --compare=...), events (each line of --progress-json)
+                                       --validate=<file> checks the output in <file> against the schema instead
+                        
+                        Track selection options (for dump, list, json, runtime, stats, near-dupes, dupes, probe, verify, copy, move, convert,
+                                                 estimate, bundle):
+                           --type=<T>         Only process tracks of the given type - short or full name, e.g. VL or ViolinLayering
+                           --date=<prefix>    Only process tracks whose date starts with the given prefix, e.g. 2017 or 2017-06
+                           --name=<text>      Only process tracks with the given text in their filename (case insensitive)
+                           --filter=<expr>    Only process tracks matching the given expression, e.g.
+                                              --filter='type=VL and duration>2:00 and (date>=2017-06-01 or name~moose)'
+                                              Fields: path, filename, date, duration, type, index, name, ext,
+                                                      source_playlist (input a merged track came from), rating
+                                              Operators: = != > >= < <= ~ (substring) ~~ (regex), and/or/not, ( )
+                           --min-rating=<N>   Only process tracks rated at least N stars (0-5), leaving out unrated ones
+                           --sample=<K>       Only process a sample of K tracks, spread across the sessions/types
+                                              (taken after --filter). --seed=<N> picks a different (repeatable) sample
+                        
+                        Waveform image options (for copy, convert):
+                           --waveform-images      Also render a PNG of each track's waveform (using ffmpeg's showwavespic),
+                                                  named after the copied/converted file
+                           --waveform-size=<WxH>  Size of the images in pixels (default 600x120)
+                           --waveform-color=<c>   Colour of the waveform (default steelblue)
+                        
+                        Sidecar options (for copy, convert):
+                           --nfo                  Also write a '<name>.nfo' metadata file (title, date, track number,
+                                                  duration, genre) next to each output file, for media centers
+                           --genre-map=<T>=<G>    Genre to use for tracks of type T (VL, MS, P, V, misc), e.g.
+                                                  --genre-map=VL=Improvisation. Can be given several times
+                        
+                        Post-command options (for copy, convert):
+                           --post-cmd='<cmd>'     Run a command on each file right after it's been copied/converted, e.g.
+                                                  --post-cmd='beet import -q {dst}'. Placeholders: {src}, {dst}, {name},
+                                                  {date}, {index}, {type} ('{{'/'}}' for literal braces). Each value
+                                                  stays a single argument (spaces included) - the shell isn't used
+                           --post-cmd-shell       Run the command through the shell instead (for pipes, etc.), with the
+                                                  values quoted for it (so don't put quotes around the placeholders)
+                           --post-cmd-fatal       Stop the run when the command fails (instead of just reporting it)
+                        
+                        Progress options (for copy, convert, bundle):
+                           (copy and convert start the line for each track with its number, and the time elapsed -
+                           e.g. [ 37/412 02:15] - with an estimate of the time left for convert, e.g. ETA 21:40)
+                           --progress-json=<fd3|path>  Write machine-readable progress events (newline-delimited JSON)
+                                                       to the given file descriptor or file, leaving stdout/stderr as-is
+                        
+                        Output lock options (for copy, convert, bundle):
+                           --force-unlock  Run even if the output directory is locked by another run that seems to still
+                                           be running (locks from runs that have ended are cleared automatically)
+                        
+                        Path options (for list, and the manifests written by copy, move, convert):
+                           --relative-to=<dir>
+                                           list gives each track's path relative to <dir> (paths on another drive are
+                                           written in full, with a warning)
+                           --relative      As for --relative-to, relative to the output file's directory
+                           --absolute-paths
+                                           List the files in the manifests by their full paths, instead of by name
+                                           (i.e. relative to the manifest, which sits alongside them)
+                        
+                        Output naming options (for copy, move, convert, bundle, webplayer):
+                           --template=<template>
+                                           How to name the files, from the placeholders {track}, {date}, {type},
+                                           {index}, {variant}, {name}, {ext}, and {orig} (the original filename).
+                                           Must end with .{ext}, and may include / to put files into subdirectories
+                                           (default: Track_{track}-{date}-{type}{index}{variant}_{name}.{ext})
+                           --collision-style=<suffix|letter|fail>
+                                           How to name tracks that would get the same filename as another track:
+                                           add _2, _3, ... to the end (suffix - the default), put b, c, ... after the
+                                           index, in place of the track's own variant letter (letter), or list them
+                                           all and stop (fail). Renamed files are listed in collisions.txt
+                           --strict        Same as --collision-style=fail
+                           --continue-numbering
+                                           (copy, convert) Number the tracks after the highest Track_<number>-...
+                                           file already in the output folder, padded to match, and add them to the
+                                           end of its existing manifest
+                        
+                        Playlist parsing options (for all modes):
+                           (M3U playlists - .m3u/.m3u8 - can be given in place of XSPF ones, keeping any #EXTINF details)
+                           (XSPF playlists can be gzip-compressed, e.g. .xspf.gz, or given as - to read them from stdin,
+                           with relative locations resolved against the current directory)
+                           --allow-doctype  Parse playlists containing <!DOCTYPE>/<!ENTITY> declarations or processing
+                                            instructions (these are refused by default, as they have no place in XSPF)
+                           --rebase=<dir>   Resolve tracks with relative locations against <dir>, instead of the playlist's
+                                            directory (e.g. for playlists that have been moved). Absolute locations are
+                                            never changed. Playlists mixing relative and absolute locations get reported
+                           --augment-from=<m3u>
+                                            Fill in missing durations and titles from an (extended) M3U of the same
+                                            tracks, matched by path (or by filename, when only one track has it).
+                                            Where both have a duration/title, the playlist's gets used (and differing
+                                            durations get reported), unless --prefer-augment is given
+                           --io-strategy=<string|buffered|mmap>
+                                            How playlist files get read in (for debugging/comparing them - they all give
+                                            the same result). 'string' reads the whole file first (the default), 'buffered'
+                                            parses straight from the file, and 'mmap' memory-maps it (only in builds with
+                                            the 'mmap' feature). list and runtime read XSPF playlists a track at a time
+                                            instead (unless --sample or --augment-from are given), so this doesn't apply
+                           --profile        Report how long each playlist took to parse (on stderr)
+                           --rating-key=<name>
+                                            Extension property holding each track's rating (default 'rating'), as a
+                                            whole number of stars from 0 to 5. Other values get reported, and are kept
+                                            as plain properties. Ratings show up in dump, json, and stats, and 5-star
+                                            tracks get marked in the manifests written by copy/convert/bundle and in
+                                            the webplayer page
+                        
+                        Location repair options (for missing, probe, verify, dupes, estimate, copy, move, convert, bundle):
+                           --no-repair-encoding
+                                            Use track locations as they are. Otherwise, tracks whose files don't exist,
+                                            but whose locations were escaped twice (e.g. '%2520' for a space) and do
+                                            exist once decoded again, get repaired (and reported)
+                           --search-root=<dir>
+                                            Look for tracks whose files don't exist under <dir> (also for write), by
+                                            filename - preferring one filed under the track's session date when there
+                                            are several. Found tracks get used from there (and reported as relocated);
+                                            ones that could be any of several files are listed, and left as they are
+                        
+                        Output options (for all modes):
+                           --width=<N>  Width to fit tables to (default: the terminal's width, or 120 columns when the
+                                        output isn't a terminal). Can also be set using XSPF_TOOLS_WIDTH. Long names and
+                                        paths get shortened to fit
+                        
+                        Output file options (for modes writing to <outfile>, e.g. list, json, missing):
+                           --overwrite-output  Replace the output file if it already exists (by default, the run stops
+                                               with an error saying what's there instead)
+                           --mkdirs            Create any folders leading up to the output file that don't exist yet
+                        
+                        Duration options (for runtime, json, stats, split-types, split, estimate, practice):
+                           --duration-priority=<sources>  Order to take track durations from, e.g. 'probed,playlist'
+                                                          (default 'playlist,probed'). Sources: playlist (the playlist's
+                                                          <duration>), probed (ffprobe, via the probe cache). Files only
+                                                          get probed when the sources before it have no duration
+                           --prefer-probed                Same as --duration-priority=probed,playlist
+                        
+                        Journal options (for all modes):
+                           --journal=<path>  Append a record of what the run did (the mode and its args, each file
+                                             copied/converted/written, any problems, and a summary) to <path>, as
+                                             newline-delimited JSON. Rotated to <path>.1 once it exceeds 10 MB
+                        
+                        Summary options (for all modes):
+                           Every run ends with a count of each kind of problem that happened (on stderr), then a status
+                           line: OK (exit code 0), OK with warnings (exit code 0), or FAILED. Failed runs exit with
+                           1 (bad usage, or anything else that stopped the run), 2 (the playlist couldn't be read),
+                           or 3 (some tracks couldn't be copied/converted/moved). Runs without any problems only get
+                           the status line
+                           --quiet               Don't print anything at the end when the run was fully successful
+                                                 (copy and convert also leave out the lines for tracks that went fine)
+                           --report=<file.json>  Also write the summary to <file.json> (the problem counts, status,
+                                                 exit code, and for copy/convert/move, the number of tracks that
+                                                 succeeded/failed/were skipped, with the files that failed)
+                        
+                        Usage log options (for all modes):
+                           --usage-log=<path>  Add this run to the running totals kept in <path> (runs, tracks, bytes
+                                               copied/converted, and time taken, per mode) - see the usage mode
+                        
+                        Probe cache options (for modes that run ffprobe):
+                           --probe-cache=<path>  Location of the cache of ffprobe results
+                                                 (default: <in>.probe-cache.json alongside the playlist)
+                           --no-probe-cache      Always run ffprobe, without reading or updating the cache
                   "
                   );
 	println!("{}", s);
 	
-	let program_name = env::args().nth(0).unwrap(); /* This is safe, as this is *always* in args */
+	let program_name = env::args().next().unwrap(); /* This is safe, as this is *always* in args */
 	let current_dir  = env::current_dir().unwrap();
 	
 	println!("\n[{0:}] running from {1:?}", program_name, current_dir.display())
@@ -73,6 +451,7 @@ fn print_usage_info()
  *       Doing it this way means that functions that don't need all the args can be passed to the same
  *       basic handler function.
  */
+#[allow(clippy::enum_variant_names)] /* The "In"/"Out" say which of the filenames each kind of mode takes */
 enum XspfProcessingModeFunc {
 	/* Only takes an input filename - Output filename is not used / causes an error if defined */
 	InOnly(fn(in_file: &str)),
@@ -85,12 +464,15 @@ enum XspfProcessingModeFunc {
 	
 	/* InOut with Mode and additional arguments */
 	InOutModeWithArgs(fn(in_file: &str, out_file: &str, mode: &str, args: &Vec<String>)),
+	
+	/* Two input files (e.g. for comparing playlists) with additional arguments (optional) - there's no output file */
+	InInWithArgs(fn(in_file_a: &str, in_file_b: &str, args: &Vec<String>)),
 }
 
 /* --------------------------------------------- */
 
 /* Extract the vector of args to pass to the (sub)-command being run (e.g. FFMPEG arguments) */
-fn extract_command_args_list(program_args: &Vec<String>, start_index: usize) -> Box<Vec<String>>
+fn extract_command_args_list(program_args: &Vec<String>, start_index: usize) -> Vec<String>
 {
 	// 0 = program name, 1 = program mode, 2 = first mode-related arg
 	assert!(start_index >= 2);
@@ -106,23 +488,52 @@ fn extract_command_args_list(program_args: &Vec<String>, start_index: usize) ->
 			Vec::new()
 		};
 	
-	return Box::new(command_args);
+	return command_args;
 }
 
-/* Handle the "out_file" parameter to determine if we're writing to stdout or a named file */
-// FIXME: Handle errors with not being able to open the file
-fn get_output_stream(out_file: Option<&String>) -> Box<dyn Write>
+/* Options for writing to an output file (for all modes with one)
+ * NOTE: This isn't just "--overwrite", as convert already uses that for letting ffmpeg replace converted tracks
+ */
+const OUTPUT_FILE_OPTIONS: [&'static str; 2] = ["--overwrite-output", "--mkdirs"];
+
+/* Handle the "out_file" parameter to determine if we're writing to stdout or a named file
+ * NOTE: "--overwrite-output" and "--mkdirs" get looked up from the full command line (so they can go anywhere in it)
+ */
+fn get_output_stream(out_file: Option<&String>) -> io::Result<Box<dyn Write>>
 {
-	let out_writer = match out_file {
-		Some(x) => {
-			let path = Path::new(x);
-			Box::new(File::create(&path).unwrap()) as Box<dyn Write>
-		},
-		None => {
-			Box::new(io::stdout()) as Box<dyn Write>
-		},
+	let args: Vec<String> = env::args().collect();
+	let options = output_file::OutputOptions {
+		overwrite: command_args::has_flag(&args, OUTPUT_FILE_OPTIONS[0]),
+		mkdirs: command_args::has_flag(&args, OUTPUT_FILE_OPTIONS[1]),
 	};
-	out_writer
+	output_file::open_output(out_file.map(Path::new), options)
+}
+
+/* Get the stream to write output to (see get_output_stream())
+ * ! This function will terminate the process if the output file couldn't be opened
+ *   (e.g. it already exists, and "--overwrite-output" wasn't given)
+ */
+fn open_output_stream(out_file: Option<&String>) -> Box<dyn Write>
+{
+	match get_output_stream(out_file) {
+		Ok(out) => out,
+		Err(e) => {
+			eprintln!("ERROR: Couldn't open output file - {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Flush whatever's still buffered for the output stream (see open_output_stream()), once the mode has written everything
+ * NOTE: The output file is only written to when the buffer fills up (or gets flushed), so a full disk may only show up here.
+ *       This counts as a failure of the run, as the output is incomplete.
+ */
+fn finish_output_stream(out: &mut dyn Write)
+{
+	if let Err(why) = out.flush() {
+		eprintln!("ERROR: Couldn't write output - {}", why);
+		run_outcome::record_failure(run_outcome::OUTPUT_FAILED_CATEGORY);
+	}
 }
 
 /* Ensure output directory exists
@@ -137,52 +548,120 @@ fn ensure_output_directory_exists(out_dir: &str) -> &Path
 		match fs::create_dir(dst_path_root) {
 			Ok(_) => {
 				println!("   Created new destination folder - {0:?}\n",
-				         dst_path_root.canonicalize().unwrap());  // XXX: how could this go wrong?
+				         dst_path_root.canonicalize().unwrap_or_else(|_| dst_path_root.to_path_buf()));
 			}
 			Err(e) => {
-				eprintln!("   Could not create destination folder - {0:?}",
-				          dst_path_root.canonicalize().unwrap()); // XXX: how could this go wrong?
+				/* NOTE: The folder doesn't exist, so there's no canonical path for it */
+				eprintln!("   Could not create destination folder - {0:?}", dst_path_root);
 				eprintln!("   {:?}", e);
 				
 				/* There's no way we can recover from this */
-				process::exit(1);
+				exit_run(run_outcome::EXIT_FAILED);
 			}
 		}
 	}
 	dst_path_root
 }
 
-/* Write manifest of the set of files copied to <out_path>/<playlist_filename>.m3u */
-fn write_copied_files_manifest(input_playlist_filename: &str, out_path: &str, dest_filenames: &Vec<String>)
+/* Get the name of the playlist file (minus extension), for naming things generated from it */
+fn playlist_file_stem(input_playlist_filename: &str) -> String
+{
+	match Path::new(input_playlist_filename).file_stem() {
+		Some(n) => n.to_string_lossy().into_owned(),
+		None    => input_playlist_filename.to_string()
+	}
+}
+
+/* "#EXTINF" lines marking the favorite (i.e. 5-star) tracks in the manifest, keyed by their output filename
+ * - filenames: Output filename for each track in the playlist
+ */
+fn favorite_track_markers(xspf: &XspfPlaylist, filenames: &[String]) -> HashMap<String, String>
+{
+	xspf.tracks.iter().zip(filenames.iter())
+		.filter(|(track, _)| track.rating == Some(xspf_parser::MAX_RATING))
+		.map(|(track, filename)| {
+			let secs = track.duration.as_ref().map_or(-1, |d| d.0 / 1000);
+			(filename.clone(), format!("#EXTINF:{0},★ {1}", secs, nfo_sidecar::pretty_name(track).replace(['\r', '\n'], " ")))
+		})
+		.collect()
+}
+
+/* Generate the contents of the manifest (i.e. m3u playlist) for a set of copied files
+ * - favorites: Markers for the favorite tracks (see favorite_track_markers())
+ * - absolute_dir: Directory to give the full paths of the files in (the output directory), instead of just their
+ *                 filenames (i.e. paths relative to the manifest, which sits alongside them)
+ */
+fn format_copied_files_manifest(dest_filenames: &Vec<String>, favorites: &HashMap<String, String>,
+                                absolute_dir: Option<&track_path::TrackPath>) -> String
+{
+	/* Required Header for m3u files */
+	let mut manifest = String::from("#EXTM3U\n\n");
+	
+	/* Rest of file */
+	manifest.push_str(&format_manifest_entries(dest_filenames, favorites, absolute_dir));
+	manifest
+}
+
+/* Generate the lines listing each of the copied files in the manifest (i.e. everything after the header)
+ * Helper for format_copied_files_manifest(), and for adding to an existing manifest
+ */
+fn format_manifest_entries(dest_filenames: &Vec<String>, favorites: &HashMap<String, String>,
+                           absolute_dir: Option<&track_path::TrackPath>) -> String
+{
+	let mut manifest = String::new();
+	for filename in dest_filenames.iter() {
+		if let Some(marker) = favorites.get(filename) {
+			manifest.push_str(marker);
+			manifest.push('\n');
+		}
+		match absolute_dir {
+			Some(dir) => manifest.push_str(&dir.join(filename).native().to_string_lossy()),
+			None      => manifest.push_str(filename),
+		}
+		manifest.push('\n');
+	}
+	manifest
+}
+
+/* Write manifest of the set of files copied to <out_path>/<playlist_filename>.m3u
+ * - append: Add the files to the end of the manifest if there's one already (e.g. with "--continue-numbering"),
+ *           instead of replacing it
+ * NOTE: The files are listed relative to the manifest, unless "--absolute-paths" was given
+ */
+fn write_copied_files_manifest(input_playlist_filename: &str, out_path: &str, dest_filenames: &Vec<String>,
+                               favorites: &HashMap<String, String>, args: &[String], append: bool)
 {
-	let playlist_filestem = Path::new(input_playlist_filename).file_stem();
-	let playlist_filename = match playlist_filestem {
-								Some(n) => n.to_str().unwrap(),
-								None    => input_playlist_filename
-							};
+	let playlist_filename = playlist_file_stem(input_playlist_filename);
 	let manifest_path = Path::new(out_path).join(format!("{playlist}.m3u8", playlist=playlist_filename));
-	println!("\nWriting manifest of copied files to {0}", manifest_path.display());
 	
-	match File::create(&manifest_path) {
-		Ok(mut f) => {
-			/* Required Header for m3u files */
-			match writeln!(f, "#EXTM3U\n") {
-				Err(why) => {
-					eprintln!("ERROR: Problem encountered while writing manifest file - {}", why);
-					return;
-				}
-				_ => { /* keep going */}
+	let absolute_dir = if command_args::has_flag(args, PATH_OPTIONS[2]) {
+		Some(absolute_track_path(&track_path::TrackPath::from_native(Path::new(out_path))))
+	}
+	else {
+		None
+	};
+	
+	/* Only the new entries get added to an existing manifest (on a line of their own, even if it didn't end with one) */
+	let existing = if append { fs::read(&manifest_path).ok() } else { None };
+	let (result, contents) = match existing {
+		Some(existing) => {
+			println!("\nAdding copied files to the existing manifest {0}", manifest_path.display());
+			let mut entries = format_manifest_entries(dest_filenames, favorites, absolute_dir.as_ref());
+			if !existing.is_empty() && !existing.ends_with(b"\n") {
+				entries.insert(0, '\n');
 			}
-			
-			/* Rest of file */
-			for filename in dest_filenames.iter() {
-				match writeln!(f, "{}", filename) {
-					Err(why) => {
-						eprintln!("ERROR: Problem encountered while writing manifest file - {}", why);
-						break;
-					}
-					_ => { /* keep going */ }
-				}
+			(fs::OpenOptions::new().append(true).open(&manifest_path), entries)
+		},
+		None => {
+			println!("\nWriting manifest of copied files to {0}", manifest_path.display());
+			(File::create(&manifest_path), format_copied_files_manifest(dest_filenames, favorites, absolute_dir.as_ref()))
+		}
+	};
+	
+	match result {
+		Ok(mut f) => {
+			if let Err(why) = f.write_all(contents.as_bytes()) {
+				eprintln!("ERROR: Problem encountered while writing manifest file - {}", why);
 			}
 		},
 		Err(why) => {
@@ -192,400 +671,4916 @@ fn write_copied_files_manifest(input_playlist_filename: &str, out_path: &str, de
 	}
 }
 
-/* --------------------------------------------- */
+/* Options controlling how playlists get parsed
+ * (i.e. "--allow-doctype", "--rebase=<dir>", "--io-strategy=<name>", "--profile", "--rating-key=<name>")
+ */
+const PARSE_OPTIONS: [&'static str; 5] = ["--allow-doctype", "--rebase", "--io-strategy", "--profile", "--rating-key"];
 
-/* Debug mode showing summary of most salient information about the contents of the playlist */
-fn dump_output_mode(in_file: &str)
+/* Parse options supplied on the command line
+ * NOTE: These apply to every mode, so they're looked up from the full command line,
+ *       instead of being passed down through each mode's args
+ */
+fn playlist_parse_options() -> xspf_parser::ParseOptions
 {
-	if let Some(xspf) = xspf_parser::parse_xspf(in_file) {
-		println!("{0} Tracks:", xspf.len());
-		for (i, track) in xspf.tracks.iter().enumerate() {
-			println!("  {0} | filename = '{1}', date = {2}, duration = {3:?}",
-			         i, track.filename, track.date, track.duration);
-			println!("        Info: {:?}", track.info);
+	let args: Vec<String> = env::args().collect();
+	let rebase = command_args::get_option(&args, PARSE_OPTIONS[1]).map(PathBuf::from);
+	if let Some(ref dir) = rebase {
+		if !dir.is_dir() {
+			eprintln!("ERROR: Directory to rebase relative track locations onto doesn't exist - {:?}", dir);
+			exit_run(run_outcome::EXIT_FAILED);
 		}
 	}
+	
+	let io_strategy = match command_args::get_option(&args, PARSE_OPTIONS[2]).map(|name| name.parse()) {
+		Some(Ok(strategy)) => strategy,
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		None => xspf_parser::IoStrategy::default()
+	};
+	if io_strategy == xspf_parser::IoStrategy::Mmap && !cfg!(feature = "mmap") {
+		eprintln!("ERROR: --io-strategy=mmap isn't available in this build (rebuild with \"--features mmap\")");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+	
+	let rating_key = command_args::get_option(&args, PARSE_OPTIONS[4]).map(|k| k.to_string());
+	if rating_key.as_ref().is_some_and(|k| k.trim().is_empty()) {
+		eprintln!("ERROR: --rating-key needs the name of the extension property holding the ratings");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+	
+	xspf_parser::ParseOptions {
+		allow_doctype: command_args::has_flag(&args, PARSE_OPTIONS[0]),
+		rebase: rebase,
+		io_strategy: io_strategy,
+		rating_key: rating_key,
+	}
 }
 
+/* Options for filling in missing track details from an M3U of the same tracks (i.e. "--augment-from=<m3u>") */
+const AUGMENT_OPTIONS: [&'static str; 2] = ["--augment-from", "--prefer-augment"];
 
-/* ................................ */
-
-/* Extract filenames for all tracks from the playlist */
-fn list_output_mode(in_file: &str, out_file: Option<&String>)
+/* Fill in missing durations/titles from the "--augment-from=<m3u>" playlist (if one was given)
+ * NOTE: Like the parse options, this applies to every mode, so it's looked up from the full command line.
+ * ! This function will terminate the process if the M3U couldn't be read
+ */
+fn apply_augment_options(xspf: &mut XspfPlaylist)
 {
-	println!("List in='{0}', out={1:?}", in_file, out_file);
-	if let Some(xspf) = xspf_parser::parse_xspf(in_file) {
-		/* Get output stream to write to */
-		let mut out : Box<dyn Write> = get_output_stream(out_file);
-		
-		/* Write out the full filepath for each track to separate lines in the output stream */
-		for track in xspf.tracks.iter() {
-			match writeln!(out, "{0}", track.path) {
-				Err(why) => {
-					eprintln!("ERROR: {}", why);
-					break;
-				},
-				_ => { /* continue */}
-			}
+	let args: Vec<String> = env::args().collect();
+	let m3u_file = match command_args::get_option(&args, AUGMENT_OPTIONS[0]) {
+		Some(m3u_file) => m3u_file,
+		None           => return
+	};
+	let m3u = match m3u_parser::read_m3u(Path::new(m3u_file)) {
+		Ok(m3u) => m3u,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
 		}
+	};
+	
+	/* Relative paths in the M3U are relative to the folder it's in */
+	let m3u_dir = Path::new(m3u_file).parent().unwrap_or(Path::new(""));
+	let base_dir = fs::canonicalize(m3u_dir).unwrap_or_else(|_| m3u_dir.to_path_buf());
+	
+	let prefer_augment = command_args::has_flag(&args, AUGMENT_OPTIONS[1]);
+	let report = playlist_augment::augment_playlist(xspf, &m3u, &track_path::TrackPath::from_native(&base_dir), prefer_augment);
+	
+	let description = format!("{0} tracks augmented from {1} ({2} of {3} entries matched, {4} matched nothing, {5} ambiguous, {6} duration conflicts)",
+	                          report.augmented, m3u_file, report.matched, m3u.entries.len(),
+	                          report.unmatched.len(), report.ambiguous.len(), report.conflicts.len());
+	eprintln!("{}", description);
+	run_journal::record_action("augmented", description);
+	
+	if !report.diagnostics.is_empty() {
+		eprintln!("WARNING: Problems found while matching up {}:", m3u_file);
+		let _ = report.diagnostics.write_text(&mut io::stderr());
+		run_outcome::record_diagnostics(&report.diagnostics);
 	}
 }
 
-/* ................................ */
-
-/* Extract all the relevant info from playlist, and dump it into a JSON file for further processing */
-fn json_output_mode(in_file: &str, out_file: Option<&String>)
+/* Make sure that writing to out_file won't overwrite the input playlist (which needs an explicit --in-place)
+ * ! This function will terminate the process if they're the same file
+ */
+fn check_not_overwriting_input(in_file: &str, out_file: &str)
 {
-	println!("JSON in='{0}', out={1:?}", in_file, out_file);
-	if let Some(xspf) = xspf_parser::parse_xspf(in_file) {
-		/* Get output stream to write to */
-		let mut out : Box<dyn Write> = get_output_stream(out_file);
-		
-		/* Serialise XSPF to a JSON string */
-		// FIXME: Warn when we cannot serialise
-		match serde_json::to_string_pretty(&xspf) {
-			Ok(j) => {
-				/* Write entire json string to output */
-				match writeln!(out, "{}", j) {
-					Err(why) => {
-						eprintln!("ERROR: Couldn't write JSON output - {}", why);
-					},
-					_ => { /* continue */}
-				}
-			},
-			
-			// FIXME: handle specific cases?
-			Err(e) => {
-				eprintln!("Couldn't convert to playlist data to JSON - {:?}", e);
-				process::exit(1);
-			}
-		}
+	let same_file = match (fs::canonicalize(in_file), fs::canonicalize(out_file)) {
+		(Ok(a), Ok(b)) => a == b,
+		_              => false
+	};
+	if same_file {
+		eprintln!("ERROR: Refusing to overwrite the input playlist {:?} - use --in-place to do that", in_file);
+		exit_run(run_outcome::EXIT_FAILED);
 	}
 }
 
-/* ................................ */
-
-/* Compute and display summary of total playing time of playlist */
-fn total_duration_mode(in_file: &str)
+/* Write the playlist out as XSPF
+ * ! This function will terminate the process if it couldn't be written
+ */
+fn save_playlist(xspf: &XspfPlaylist, out_file: &str)
 {
-	println!("Total Duration Summary:");
-	if let Some(xspf) = xspf_parser::parse_xspf(in_file) {
-		/* Compute duration */
-		let result = xspf.total_duration();
-		
-		println!("    Total Duration:  {:?} (mm:ss)", result.duration);
-		println!("    Num Tracks:      {}", xspf.len());
-		// TODO: include an average length estimate?
-		
-		if result.uncounted > 0 {
-			println!("");
-			println!("    Skipped Tracks:  {}", result.uncounted);
-			println!("                     (Tracks may skipped if no duration data was found in the playlist)");
+	match xspf.save_xspf(Path::new(out_file)) {
+		Ok(_)  => {
+			println!("Wrote {0} tracks to {1}", xspf.len(), out_file);
+			run_journal::record_action("wrote", format!("{0} tracks -> {1}", xspf.len(), out_file));
+		},
+		Err(e) => {
+			eprintln!("ERROR: Couldn't write playlist to {0:?} - {1}", out_file, e);
+			exit_run(run_outcome::EXIT_FAILED);
 		}
 	}
 }
 
-/* --------------------------------------------- */
+/* Title of the playlist itself (i.e. without the " - <filename>" added when parsing), for writing it out again */
+fn playlist_own_title(xspf: &XspfPlaylist, in_file: &str) -> Option<String>
+{
+	let title_suffix = format!(" - {}", in_file);
+	xspf.title.as_ref().map(|t| t.trim_end_matches(title_suffix.as_str()).to_string())
+}
 
-/* Get output filename for copying or converting a track
- * Helper for copy_files_mode() and convert_files_mode()
+/* Parse the playlist, reporting any problems noticed along the way
+ * NOTE: M3U playlists (".m3u"/".m3u8") can be given instead of XSPF ones (see m3u_parser::read_m3u_playlist())
+ * > returns None if it couldn't be parsed at all (which gets reported, and makes the run fail - see run_outcome)
  */
-fn track_get_destination_filename(track: &Track, 
-                                  track_idx: usize, 
-                                  track_index_width: usize, 
-                                  extension_override: Option<TrackExtension>)
-	-> String
+fn load_playlist(in_file: &str) -> Option<XspfPlaylist>
 {
-	/* Determine what the new file's extension should be */
-	let extension = match extension_override {
-		Some(s) => s,
-		None => track.info.extn.clone()
+	let options = playlist_parse_options();
+	let start_time = std::time::Instant::now();
+	let parsed = if m3u_parser::is_m3u_file(in_file) {
+		m3u_parser::read_m3u_playlist(in_file, options.rebase.as_deref())
+	}
+	else {
+		xspf_parser::parse_xspf(in_file, &options).map_err(|e| e.to_string())
+	};
+	let mut xspf = match parsed {
+		Ok(xspf) => Some(xspf),
+		Err(e) => {
+			eprintln!("ERROR: Couldn't load playlist {0:?} - {1}", in_file, e);
+			run_outcome::record_failure(run_outcome::UNREADABLE_PLAYLIST_CATEGORY);
+			None
+		}
 	};
 	
-	/* Return result of the following expression - which should be a string */
-	if track.info.track_type == track_name_info::TrackType::UnknownType {
-		/* Just use as-is, since it doesn't follow our rules */
-		format!("Track_{track_idx:0tixw$}-{fname}.{ext:?}",
-			track_idx=track_idx + 1,
-			tixw=track_index_width,
-			fname=track.info.name, /* info.name will has everything in it already */
-			ext=extension)
-		.to_string()
+	if command_args::has_flag(&env::args().collect::<Vec<String>>(), PARSE_OPTIONS[3]) {
+		let elapsed = start_time.elapsed();
+		eprintln!("PROFILE: Parsed {0} in {1:.3} ms (io strategy: {2})",
+		          in_file, elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 * 1e-6, options.io_strategy.name());
 	}
-	else {
-		/* Reformat the name, using the info we've learned about it */
-		format!("Track_{track_idx:0tixw$}-{date}-{tt}{index:02}_{name}.{ext:?}",
-			track_idx=track_idx + 1,
-			tixw=track_index_width,
-			date=track.date,
-			tt=track.info.track_type.shortname_safe(),
-			index=track.info.index,
-			name=track.info.name,
-			ext=extension)
-		.to_string()
+	if let Some(ref mut xspf) = xspf {
+		if !xspf.diagnostics.is_empty() {
+			eprintln!("WARNING: Problems found while reading playlist:");
+			let _ = xspf.diagnostics.write_text(&mut io::stderr());
+			run_outcome::record_diagnostics(&xspf.diagnostics);
+		}
+		apply_augment_options(xspf);
+		usage_log::add_tracks(xspf.len());
 	}
+	xspf
 }
 
-/* ................................ */
+/* Can the playlist be read a track at a time (see stream_playlist()), instead of all at once?
+ * Not for M3U playlists, or when any of the options given need all the tracks at once (e.g. "--sample", "--augment-from")
+ */
+fn can_stream_playlist(in_file: &str, args: &[String]) -> bool
+{
+	!m3u_parser::is_m3u_file(in_file) &&
+	command_args::get_option(args, "--sample").is_none() &&
+	command_args::get_option(args, AUGMENT_OPTIONS[0]).is_none()
+}
 
-/* Copy a track from src_path to dst_path
- * Helper for copy_files_mode()
- * > returns success of the copy operation
+/* Read the playlist a track at a time, handing each track to "on_track" as soon as it's been read in,
+ * without building the whole document tree first (see xspf_parser::parse_xspf_streaming())
+ * > returns the rest of the playlist (with no tracks), or None if it couldn't be read (which gets reported)
+ * NOTE: Only for playlists where can_stream_playlist() says so. Any problems found get reported at the end.
  */
-fn copy_track(src_path: &str, dst_path: &str) -> bool
+fn stream_playlist(in_file: &str, on_track: &mut dyn FnMut(Track)) -> Option<XspfPlaylist>
 {
-	match fs::copy(src_path, dst_path) {
-		Ok(_)  => {
-			println!("   Copied {src} => <outdir>/{dst}", 
-			         src=src_path, dst=dst_path);
-			
-			/* Report success */
-			return true;
-		},
+	let options = playlist_parse_options();
+	let start_time = std::time::Instant::now();
+	let mut count = 0;
+	let parsed = xspf_parser::parse_xspf_streaming(in_file, &options, |track| {
+		count += 1;
+		on_track(track);
+	});
+	let xspf = match parsed {
+		Ok(xspf) => xspf,
 		Err(e) => {
-			eprintln!("! ERROR: Couldn't copy {src} => <ourdir>/{dst}!",
-			          src=src_path, dst=dst_path);
-			eprintln!("  Reason: {}", e);
-			
-			/* XXX: Should we stop instead? We don't have any other way to keep going otherwise! */
-			//process::exit(1);
-			
-			/* Report failure */
-			return false;
+			eprintln!("ERROR: Couldn't load playlist {0:?} - {1}", in_file, e);
+			run_outcome::record_failure(run_outcome::UNREADABLE_PLAYLIST_CATEGORY);
+			return None;
 		}
+	};
+	
+	if command_args::has_flag(&env::args().collect::<Vec<String>>(), PARSE_OPTIONS[3]) {
+		let elapsed = start_time.elapsed();
+		eprintln!("PROFILE: Parsed {0} in {1:.3} ms (streamed)",
+		          in_file, elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 * 1e-6);
 	}
+	if !xspf.diagnostics.is_empty() {
+		eprintln!("WARNING: Problems found while reading playlist:");
+		let _ = xspf.diagnostics.write_text(&mut io::stderr());
+		run_outcome::record_diagnostics(&xspf.diagnostics);
+	}
+	usage_log::add_tracks(count);
+	Some(xspf)
 }
 
-/* Convert a track from one format to another, based on the filename extensions
- * Helper for convert_files_mode()
- * > returns success of the convert operation
+/* Load the whole playlist, reading it a track at a time where possible (see can_stream_playlist()),
+ * which saves building the document tree for large playlists
  */
-fn convert_track(src_path: &str, dst_path: &str, ffmpeg_args: &Vec<String>) -> bool
+fn load_playlist_streamed(in_file: &str, args: &[String]) -> Option<XspfPlaylist>
 {
-	/* Add the file paths to the args to pass to FFMPEG...
-	 * - Input filename needs to come first
-	 * - Output filename needs to go last
-	 */
-	let mut ffmpeg_args_for_file: Vec<String> = Vec::new();
-	
-	for arg in ffmpeg_args {
-		/* Add each standard arg for this conversion operation */
-		ffmpeg_args_for_file.push(arg.to_string());
-	}
-	
-	ffmpeg_args_for_file.insert(0, "-i".to_string());
-	ffmpeg_args_for_file.insert(1, src_path.to_string());
-	
-	ffmpeg_args_for_file.push(dst_path.to_string());
-	
-	/* Invoke ffmpeg to convert this file... */
-	println!("   Converting {src_path:?} -> {dst_path:?}...",
-	         src_path = src_path, dst_path = dst_path);
-	// {
-	// 	println!("      Args = {ffmpeg_args:?}\n", ffmpeg_args = ffmpeg_args_for_file); // debug only
-	// }
-	
-	let ffmpeg_convert_command
-		= Command::new("ffmpeg")
-			.args(ffmpeg_args_for_file)
-			.output()
-			.expect("Failed to find and run ffmpeg");
-			
-	if ffmpeg_convert_command.status.success() {
-		println!("     Success for {dst_path:?}\n\n",
-		         dst_path = dst_path);
-		
-		/* Report success */
-		return true;
+	if can_stream_playlist(in_file, args) {
+		let mut tracks: Vec<Track> = Vec::new();
+		let xspf = stream_playlist(in_file, &mut |track| tracks.push(track));
+		xspf.map(|mut xspf| {
+			xspf.tracks = tracks;
+			xspf
+		})
 	}
 	else {
-		eprintln!("     ERROR: Conversion failed for {src_path:?} -> {dst_path:?}!\n\n",
-		          src_path = src_path, dst_path = dst_path);
-		
-		eprintln!("StdError Output ==============================================");
-		io::stderr().write_all(&ffmpeg_convert_command.stderr).unwrap();
-		eprintln!("==============================================================\n\n\n");
-		/* Don't abort... try to carry on... */
-		
-		/* Report failure */
-		return false;
+		load_playlist(in_file)
 	}
 }
 
-/* ................................ */
+/* Options for repairing track locations (for modes that access the tracks' files) */
+const LOCATION_REPAIR_OPTIONS: [&'static str; 2] = ["--no-repair-encoding", "--search-root"];
 
-/* Copy all files listed in playlist to a single folder */
-fn copy_files_mode(in_file: &str, out_path: Option<&String>)
+/* Index of the files under "--search-root=<dir>" (if given) - only scanned once per run, however many playlists get loaded */
+static SEARCH_INDEX: OnceLock<Option<track_relocate::FileIndex>> = OnceLock::new();
+
+/* Get the index of the files under "--search-root=<dir>", scanning it if that hasn't been done yet
+ * > returns None if no search root was given
+ */
+fn search_index() -> Option<&'static track_relocate::FileIndex>
 {
-	if let Some(out) = out_path {
-		println!("Copy Files infile='{0}', outdir={1:?}", in_file, out_path);
-		if let Some(xspf) = xspf_parser::parse_xspf(in_file) {
-			/* Ensure outdir exists */
-			let _dst_path_root = ensure_output_directory_exists(out);
-			
-			/* Compute track index width - number of digits of padding to display before the number */
-			let track_index_width = xspf.track_index_width();
-			
-			/* Loop over tracks copying them to the folder */
-			let mut dest_filenames : Vec<String> = Vec::new();
-			
-			for (track_idx, track) in xspf.tracks.iter().enumerate() {
-				/* Construct filename for copied file - it needs to have enough metadata to figure out what's going on */
-				let dst_filename = track_get_destination_filename(track, track_idx, track_index_width, None);
-				
-				/* Construct paths to actually perform the copying to/from */
-				let src_path = &track.path;
-				let dst_path = Path::new(out).join(dst_filename.to_string())
-				                             .into_os_string().into_string().unwrap();
-				
-				/* Perform the copy operation */
-				if copy_track(src_path, &dst_path) {
-					/* Success - Note this as one of the successful files */
-					dest_filenames.push(dst_filename);
-				}
+	SEARCH_INDEX.get_or_init(|| {
+		let args: Vec<String> = env::args().collect();
+		let root = command_args::get_option(&args, LOCATION_REPAIR_OPTIONS[1])?;
+		match track_relocate::FileIndex::build(Path::new(root)) {
+			Ok(index) => Some(index),
+			Err(e) => {
+				eprintln!("ERROR: Couldn't read the search root {0:?} - {1}", root, e);
+				exit_run(run_outcome::EXIT_FAILED);
 			}
-			
-			/* Dump list of copied files to <out_path>/<playlist_filename>.m3u
-			 * (i.e. a playable playlist, that also acts as a manifest of the set of files copied)
-			 */
-			write_copied_files_manifest(in_file, out, &dest_filenames);
 		}
-	}
-	else {
-		eprintln!("ERROR: The third argument should specify the directory to copy the source files to");
-		process::exit(1);
-	}
+	}).as_ref()
 }
 
-/* ................................ */
-
-/* Similar to copy, but converts all the files to the specified format using FFMPEG */
+/* Repair any locations that were escaped twice (i.e. where the files only exist once the locations get decoded again),
+ * unless "--no-repair-encoding" was given
+ */
+fn repair_double_encoding(xspf: &mut XspfPlaylist)
+{
+	if !command_args::has_flag(&env::args().collect::<Vec<String>>(), LOCATION_REPAIR_OPTIONS[0]) {
+		let first_new = xspf.diagnostics.len();
+		if xspf.repair_double_encoded_locations() > 0 {
+			let repairs = diagnostics::Diagnostics { entries: xspf.diagnostics.entries[first_new ..].to_vec() };
+			eprintln!("WARNING: Repaired track locations that were escaped twice (use --no-repair-encoding to keep them as they are):");
+			let _ = repairs.write_text(&mut io::stderr());
+			run_outcome::record_diagnostics(&repairs);
+		}
+	}
+}
+
+/* Look for the tracks whose files don't exist under "--search-root=<dir>" (if given), moving any found to where they are now
+ * (reporting each, along with any that turned up in several places - which are left as they are)
+ */
+fn relocate_from_search_root(xspf: &mut XspfPlaylist)
+{
+	if let Some(index) = search_index() {
+		let first_new = xspf.diagnostics.len();
+		track_relocate::relocate_missing(xspf, index);
+		if xspf.diagnostics.len() > first_new {
+			let found = diagnostics::Diagnostics { entries: xspf.diagnostics.entries[first_new ..].to_vec() };
+			eprintln!("WARNING: Looked for missing tracks under {:?}:", index.root);
+			let _ = found.write_text(&mut io::stderr());
+			run_outcome::record_diagnostics(&found);
+		}
+	}
+}
+
+/* Load the playlist for a mode that accesses the tracks' files, repairing the locations of any that can't be found
+ * (see repair_double_encoding() and relocate_from_search_root())
+ * NOTE: This checks which files exist, so it's kept out of load_playlist() (i.e. out of modes that only parse).
+ *       Like the parse options, the options for this are looked up from the full command line.
+ */
+fn load_playlist_for_files(in_file: &str) -> Option<XspfPlaylist>
+{
+	let mut xspf = load_playlist(in_file)?;
+	repair_double_encoding(&mut xspf);
+	relocate_from_search_root(&mut xspf);
+	Some(xspf)
+}
+
+/* Options for recording a journal of what the run did (for all modes) */
+const JOURNAL_OPTIONS: [&'static str; 1] = ["--journal"];
+
+/* Start recording the journal, if "--journal=<path>" was given
+ * NOTE: Like the parse options, this applies to every mode, so it's looked up from the full command line.
+ *       Failing to open it only gets a warning, as the journal isn't needed for the run itself.
+ */
+fn open_journal()
+{
+	let args: Vec<String> = env::args().collect();
+	if let Some(path) = command_args::get_option(&args, JOURNAL_OPTIONS[0]) {
+		match run_journal::Journal::open(Path::new(path), run_journal::DEFAULT_MAX_JOURNAL_BYTES) {
+			Ok(journal) => run_journal::install(journal),
+			Err(e)      => eprintln!("WARNING: Couldn't open journal {0:?} - {1} (continuing without it)", path, e)
+		}
+	}
+}
+
+/* Options for the summary of problems given at the end of the run (for all modes) */
+const SUMMARY_OPTIONS: [&'static str; 2] = ["--quiet", "--report"];
+
+/* End the run with the given exit code, after printing the summary of any problems noticed
+ * (or only the status line if there weren't any, or nothing at all with "--quiet"),
+ * and writing it to the "--report=<file.json>" file (if given)
+ * NOTE: Runs that had errors always exit with an error code, even if the mode itself finished
+ *       (see run_outcome for what each code means).
+ *       Use this instead of process::exit(), so that runs which stop early still get summarised.
+ */
+fn exit_run(exit_code: i32) -> !
+{
+	let args: Vec<String> = env::args().collect();
+	let quiet = command_args::has_flag(&args, SUMMARY_OPTIONS[0]);
+	let report_path = command_args::get_option(&args, SUMMARY_OPTIONS[1]).map(Path::new);
+	process::exit(run_outcome::finish(exit_code, quiet, report_path))
+}
+
+/* Options for keeping running totals of how the tool gets used (for all modes) */
+const USAGE_LOG_OPTIONS: [&'static str; 1] = ["--usage-log"];
+
+/* Path of the usage log, if "--usage-log=<path>" was given (with any leading "~" expanded) */
+fn usage_log_path() -> Option<PathBuf>
+{
+	let args: Vec<String> = env::args().collect();
+	command_args::get_option(&args, USAGE_LOG_OPTIONS[0]).map(usage_log::expand_home)
+}
+
+/* Record the totals for a run over the tracks in a playlist (copy, convert, bundle) */
+fn record_run_summary(summary: progress_events::RunSummary)
+{
+	usage_log::add_bytes(summary.bytes);
+	run_journal::record(run_journal::JournalEntry::Summary(summary));
+}
+
+/* Options for selecting which tracks get processed by a mode
+ * (i.e. "--type=<T>", "--date=<prefix>", "--name=<text>", "--filter=<expr>", "--min-rating=<N>", "--sample=<K> --seed=<N>")
+ */
+const TRACK_FILTER_OPTIONS: [&'static str; 7] = ["--type", "--date", "--name", "--filter", "--min-rating", "--sample", "--seed"];
+
+/* Get the simple track criteria given (i.e. "--type=<T>", "--date=<prefix>", "--name=<text>")
+ * ! This function will terminate the process if the options are invalid
+ */
+fn get_track_filter(args: &[String]) -> xspf_parser::TrackFilter
+{
+	let track_type = match command_args::get_option(args, "--type").map(|name| name.parse::<track_name_info::TrackType>()) {
+		None                  => None,
+		Some(Ok(track_type))  => Some(track_type),
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	xspf_parser::TrackFilter {
+		track_type: track_type,
+		date_prefix: command_args::get_option(args, "--date").map(|s| s.to_string()),
+		name_contains: command_args::get_option(args, "--name").map(|s| s.to_string()),
+	}
+}
+
+/* Track selection options that can be checked one track at a time (i.e. everything but "--sample") */
+struct TrackSelection {
+	filter: xspf_parser::TrackFilter,
+	expr: Option<filter_expr::FilterExpr>,
+	min_rating: Option<u8>,
+}
+
+impl TrackSelection {
+	/* Does the track meet all the criteria?
+	 * NOTE: Unrated tracks never meet a minimum rating
+	 */
+	fn matches(&self, track: &Track) -> bool
+	{
+		self.filter.matches(track) &&
+		self.expr.as_ref().is_none_or(|expr| expr.matches(track)) &&
+		self.min_rating.is_none_or(|min_rating| track.rating.is_some_and(|rating| rating >= min_rating))
+	}
+}
+
+/* Get the track selection options given (apart from "--sample" - see apply_track_filter_options())
+ * ! This function will terminate the process if the options are invalid
+ */
+fn get_track_selection(args: &[String]) -> TrackSelection
+{
+	let filter = get_track_filter(args);
+	
+	let expr = command_args::get_option(args, "--filter").map(|expr| {
+		match filter_expr::FilterExpr::parse(expr) {
+			Ok(filter) => filter,
+			Err(e) => {
+				eprintln!("ERROR: {}", e.describe(expr));
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	});
+	
+	let min_rating = match command_args::parse_option::<u8>(args, "--min-rating") {
+		Ok(Some(min_rating)) if min_rating <= xspf_parser::MAX_RATING => Some(min_rating),
+		Ok(None) => None,
+		Ok(Some(_)) | Err(_) => {
+			eprintln!("ERROR: --min-rating expects a number of stars from 0 to {}", xspf_parser::MAX_RATING);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	TrackSelection { filter: filter, expr: expr, min_rating: min_rating }
+}
+
+/* Apply the track selection options supplied to the tracks in the playlist
+ * (Sampling happens after filtering, so that the sample is taken from the tracks that match)
+ * ! This function will terminate the process if the options are invalid
+ */
+fn apply_track_filter_options(xspf: &mut XspfPlaylist, args: &[String])
+{
+	let selection = get_track_selection(args);
+	xspf.tracks.retain(|track| selection.matches(track));
+	
+	match (command_args::parse_option::<usize>(args, "--sample"), command_args::parse_option::<u64>(args, "--seed")) {
+		(Ok(Some(count)), Ok(seed)) => {
+			let picked = track_sampling::select_sample(&xspf.tracks, count, seed.unwrap_or(0));
+			let sampled: Vec<Track> = xspf.tracks.drain(..)
+			                                     .enumerate()
+			                                     .filter(|&(i, _)| picked.binary_search(&i).is_ok())
+			                                     .map(|(_, track)| track)
+			                                     .collect();
+			xspf.tracks = sampled;
+		},
+		(Ok(None), Ok(_)) => { /* not sampling */ },
+		(Err(e), _) | (_, Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Options for rendering waveform images of each track when exporting (copy/convert) */
+const WAVEFORM_OPTIONS: [&'static str; 3] = ["--waveform-images", "--waveform-size", "--waveform-color"];
+
+/* Diagnostic category for problems generating waveform images */
+const WAVEFORM_CATEGORY: &'static str = "waveform";
+
+/* Get the settings for rendering waveform images (if they were requested)
+ * ! This function will terminate the process if the options are invalid
+ */
+fn get_waveform_options(args: &[String]) -> Option<ffmpeg_command::WaveformOptions>
+{
+	if !command_args::has_flag(args, "--waveform-images") {
+		return None;
+	}
+	
+	let mut options = ffmpeg_command::WaveformOptions::default();
+	match command_args::parse_option::<ffmpeg_command::WaveformSize>(args, "--waveform-size") {
+		Ok(Some(size)) => options.size = size,
+		Ok(None)       => { /* use default */ },
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+	if let Some(color) = command_args::get_option(args, "--waveform-color") {
+		options.color = color.to_string();
+	}
+	Some(options)
+}
+
+/* Options for writing .nfo sidecars next to each exported file (copy/convert) */
+const NFO_OPTIONS: [&'static str; 2] = ["--nfo", "--genre-map"];
+
+/* Get the writer for .nfo sidecars (if they were requested), with any "--genre-map=<type>=<genre>" overrides applied
+ * ! This function will terminate the process if the options are invalid
+ */
+fn get_nfo_writer(args: &[String]) -> Option<nfo_sidecar::SidecarWriter>
+{
+	if !command_args::has_flag(args, "--nfo") {
+		return None;
+	}
+	
+	let mut genres = nfo_sidecar::GenreMap::default();
+	for mapping in command_args::get_options(args, "--genre-map") {
+		if let Err(e) = genres.apply_mapping(mapping) {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+	Some(nfo_sidecar::SidecarWriter::new(genres))
+}
+
+/* Options for running a command on each exported file (copy/convert) */
+const POST_CMD_OPTIONS: [&'static str; 3] = ["--post-cmd", "--post-cmd-shell", "--post-cmd-fatal"];
+
+/* Get the runner for the "--post-cmd=<template>" command (if one was given)
+ * ! This function will terminate the process if the command template is invalid
+ */
+fn get_post_command_runner(args: &[String]) -> Option<post_command::PostCommandRunner>
+{
+	let template = match command_args::get_option(args, "--post-cmd") {
+		Some(template) => template,
+		None => {
+			if command_args::has_flag(args, "--post-cmd-shell") || command_args::has_flag(args, "--post-cmd-fatal") {
+				eprintln!("WARNING: --post-cmd-shell/--post-cmd-fatal have no effect without --post-cmd");
+			}
+			return None;
+		}
+	};
+	
+	match post_command::PostCommand::parse(template, command_args::has_flag(args, "--post-cmd-shell")) {
+		Ok(command) => Some(post_command::PostCommandRunner::new(command, command_args::has_flag(args, "--post-cmd-fatal"))),
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Options controlling how convert_files_mode() runs */
+const CONVERT_OPTIONS: [&'static str; 11] = ["--bitrate", "--quality", "--cover", "--incremental", "--settings-diff", "--fade-in",
+                                              "--fade-out", "--resume-partial", "--preset", "--force-transcode", "--overwrite"];
+
+/* Get the quality to encode at ("--quality=high|medium|low", default medium)
+ * ! This function will terminate the process if the quality is invalid
+ */
+fn get_audio_quality(args: &[String]) -> ffmpeg_command::AudioQuality
+{
+	match command_args::get_option(args, "--quality").map(|q| q.parse::<ffmpeg_command::AudioQuality>()) {
+		Some(Ok(quality)) => quality,
+		Some(Err(e)) => {
+			eprintln!("ERROR: --quality - {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		None => ffmpeg_command::AudioQuality::default()
+	}
+}
+
+/* Get the picture to use when converting to a video format ("--cover=<image>", or a waveform of each track otherwise)
+ * > returns None for the audio-only formats
+ * ! This function will terminate the process if a cover is given for an audio-only format, or doesn't exist
+ */
+fn get_video_source(args: &[String], export_format: &TrackExtension) -> Option<ffmpeg_command::VideoSource>
+{
+	let cover = command_args::get_option(args, "--cover");
+	
+	if !ffmpeg_command::is_video_format(export_format) {
+		if cover.is_some() {
+			eprintln!("ERROR: --cover can only be used when converting to a video format (mp4 or mkv), not {}",
+			          export_format);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+		return None;
+	}
+	
+	match cover {
+		Some(image) if !Path::new(image).is_file() => {
+			eprintln!("ERROR: --cover - Couldn't find image '{}'", image);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Some(image) => Some(ffmpeg_command::VideoSource::Cover(PathBuf::from(image))),
+		None => {
+			let color = command_args::get_option(args, "--waveform-color").unwrap_or(ffmpeg_command::DEFAULT_WAVEFORM_COLOR);
+			Some(ffmpeg_command::VideoSource::Waveform(color.to_string()))
+		}
+	}
+}
+
+/* Print the encoder and quality used for the conversion (once, before any tracks get converted) */
+fn print_encoder_settings(export_format: &TrackExtension, quality: ffmpeg_command::AudioQuality, base_args: &[String],
+                          video_source: Option<&ffmpeg_command::VideoSource>)
+{
+	if let Some((codec, _, _)) = ffmpeg_command::encoder_settings(export_format, quality) {
+		println!("Encoding with {0} ({1} quality) - ffmpeg args: {2}", codec, quality, base_args.join(" "));
+	}
+	match video_source {
+		Some(&ffmpeg_command::VideoSource::Cover(ref image_path)) => println!("Video: {} (still image)", image_path.display()),
+		Some(&ffmpeg_command::VideoSource::Waveform(_))           => println!("Video: waveform of each track ({})", ffmpeg_command::WAVEFORM_VIDEO_SIZE),
+		None => {}
+	}
+}
+
+/* Diagnostic category for problems applying fades */
+const FADE_CATEGORY: &'static str = "fade";
+
+/* Get the fades to apply to converted tracks ("--fade-in=<secs>", "--fade-out=<secs>")
+ * > returns None if neither was given
+ * ! This function will terminate the process if the options are invalid
+ */
+fn get_fade_options(args: &[String]) -> Option<ffmpeg_command::FadeOptions>
+{
+	let get_secs = |name: &str| -> f64 {
+		match command_args::parse_option::<f64>(args, name) {
+			Ok(Some(secs)) if secs.is_finite() && secs >= 0.0 => secs,
+			Ok(None) => 0.0,
+			_ => {
+				eprintln!("ERROR: {} should be a number of seconds (e.g. 1.5)", name);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	};
+	
+	let fade = ffmpeg_command::FadeOptions {
+		fade_in_secs: get_secs("--fade-in"),
+		fade_out_secs: get_secs("--fade-out"),
+	};
+	if fade.fade_in_secs > 0.0 || fade.fade_out_secs > 0.0 { Some(fade) } else { None }
+}
+
+/* Get the ffmpeg args to use for each type of track - the settings for the output format and quality
+ * (see ffmpeg_command::build_ffmpeg_args()), followed by the user's args, with the presets given by
+ * "--preset=<type>:<preset>,..." added to those (see convert_presets)
+ * ! This function will terminate the process if the presets are invalid, or contradict the user's args
+ */
+fn get_convert_presets(args: &[String], export_format: &TrackExtension, quality: ffmpeg_command::AudioQuality, user_args: &[String])
+	-> convert_presets::ResolvedPresets
+{
+	/* Presets only get checked against the user's settings, as they can override the ones for the format */
+	let mut presets = match command_args::get_option(args, "--preset") {
+		Some(spec) => resolve_convert_presets(spec, export_format, user_args),
+		None       => convert_presets::ResolvedPresets::none(user_args)
+	};
+	
+	/* Settings for the format go first */
+	presets.base = ffmpeg_command::build_ffmpeg_args(export_format, quality, &presets.base);
+	for entry in presets.by_type.iter_mut() {
+		entry.2 = ffmpeg_command::build_ffmpeg_args(export_format, quality, &entry.2);
+	}
+	presets
+}
+
+/* Parse the presets given by "--preset=<type>:<preset>,...", and add their args to the user's args
+ * ! This function will terminate the process if the presets are invalid, or contradict the other settings
+ */
+fn resolve_convert_presets(spec: &str, export_format: &TrackExtension, user_args: &[String]) -> convert_presets::ResolvedPresets
+{
+	let map = match convert_presets::PresetMap::parse(spec).and_then(|map| map.check_format(export_format).map(|_| map)) {
+		Ok(map) => map,
+		Err(e) => {
+			eprintln!("ERROR: --preset - {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	match map.resolve(user_args) {
+		Ok(resolved) => resolved,
+		Err(conflicts) => {
+			eprintln!("ERROR: --preset contradicts the other settings (from --bitrate, or the args passed to ffmpeg):");
+			for conflict in conflicts.iter() {
+				eprintln!("   {}", conflict);
+			}
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Fingerprint of the settings used to convert a track (see convert_stamps)
+ * Fades depend on each track's duration, so only the requested lengths count as settings
+ */
+fn convert_settings_fingerprint(ffmpeg_args: &[String], fade_options: Option<&ffmpeg_command::FadeOptions>,
+                                video_source: Option<&ffmpeg_command::VideoSource>)
+	-> String
+{
+	let mut fingerprint_args = ffmpeg_args.to_vec();
+	if let Some(fade) = fade_options {
+		fingerprint_args.push(format!("--fade-in={}", fade.fade_in_secs));
+		fingerprint_args.push(format!("--fade-out={}", fade.fade_out_secs));
+	}
+	match video_source {
+		Some(&ffmpeg_command::VideoSource::Cover(ref image_path)) => fingerprint_args.push(format!("--cover={}", image_path.display())),
+		Some(&ffmpeg_command::VideoSource::Waveform(ref color))   => fingerprint_args.push(format!("--waveform-video={}", color)),
+		None => {}
+	}
+	ffmpeg_command::settings_fingerprint(&fingerprint_args)
+}
+
+/* Report how many tracks were converted with each preset (if any were given) */
+fn print_presets_report(presets: &convert_presets::ResolvedPresets, uses: &[(Option<&'static str>, usize)])
+{
+	if presets.by_type.is_empty() {
+		return;
+	}
+	
+	println!("\nPresets used:");
+	for &(preset, count) in uses.iter() {
+		let types: Vec<String> = presets.by_type.iter()
+			.filter(|&&(_, p, _)| Some(p.name) == preset)
+			.map(|&(t, _, _)| format!("{:?}", t))
+			.collect();
+		match preset {
+			Some(name) => println!("   {0:<15} {1:>5} tracks ({2})", name, count, types.join(", ")),
+			None       => println!("   {0:<15} {1:>5} tracks", "(base settings)", count),
+		}
+	}
+}
+
+/* Options for writing machine-readable progress events (copy/convert/bundle) */
+const PROGRESS_OPTIONS: [&'static str; 1] = ["--progress-json"];
+
+/* Set up progress reporting for a long-running mode
+ * Events only get written somewhere if "--progress-json=fd<N>|<path>" was given
+ * ! This function will terminate the process if the events output couldn't be opened
+ */
+fn open_progress_reporter(args: &[String]) -> progress_events::ProgressReporter
+{
+	let sink = match command_args::get_option(args, "--progress-json") {
+		Some(target) => {
+			match progress_events::ProgressSink::open(target) {
+				Ok(sink) => Some(sink),
+				Err(e) => {
+					eprintln!("ERROR: {}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		},
+		None => None
+	};
+	progress_events::ProgressReporter::new(sink)
+}
+
+/* How the lines printed for each track of the playlist look (copy, convert)
+ * - show_eta: Include an estimate of the time left (for modes where tracks take a while, i.e. convert)
+ * NOTE: With "--quiet", only the lines about problems get printed
+ */
+fn track_line_options(xspf: &XspfPlaylist, args: &[String], show_eta: bool) -> progress_events::TrackLineOptions
+{
+	progress_events::TrackLineOptions {
+		index_width: xspf.track_index_width(),
+		show_eta: show_eta,
+		quiet: command_args::has_flag(args, SUMMARY_OPTIONS[0]),
+	}
+}
+
+/* Total size of the source files for all tracks (if all of them exist) */
+fn playlist_total_bytes(xspf: &XspfPlaylist) -> Option<u64>
+{
+	progress_events::total_file_size(xspf.tracks.iter().map(|t| t.file_path()))
+}
+
+/* Options for the lock on the output directory (copy/convert/bundle) */
+const LOCK_OPTIONS: [&'static str; 1] = ["--force-unlock"];
+
+/* Lock the output directory for the rest of the run, so other runs can't write into it at the same time
+ * The lock gets released when the returned object is dropped, or if the run gets interrupted with Ctrl+C
+ * ! This function will terminate the process if another run holds the lock
+ */
+fn lock_output_directory(dir: &Path, args: &[String]) -> output_lock::OutputLock
+{
+	let force = command_args::has_flag(args, "--force-unlock");
+	match output_lock::OutputLock::acquire(dir, force, &output_lock::SystemProcessChecker) {
+		Ok(lock) => {
+			let (lock_path, lock_info) = (lock.path().to_path_buf(), lock.info());
+			let result = ctrlc::set_handler(move || {
+				output_lock::remove_lock_file(&lock_path, &lock_info);
+				eprintln!("\nInterrupted - Output directory lock released");
+				process::exit(130);
+			});
+			if let Err(e) = result {
+				eprintln!("WARNING: Couldn't set up Ctrl+C handling (lock may be left behind if interrupted) - {}", e);
+			}
+			lock
+		},
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Options controlling how track paths get written - relative to a directory by list (i.e. "--relative-to=<dir>",
+ * or "--relative" for the output file's own directory), or in full in the manifests written by copy/move/convert
+ * (i.e. "--absolute-paths")
+ */
+const PATH_OPTIONS: [&'static str; 3] = ["--relative-to", "--relative", "--absolute-paths"];
+
+/* Absolute version of the path (relative ones are taken to be relative to the current directory) */
+fn absolute_track_path(path: &track_path::TrackPath) -> track_path::TrackPath
+{
+	if path.is_absolute() {
+		return path.clone();
+	}
+	match env::current_dir() {
+		Ok(dir) => path.resolve_against(&track_path::TrackPath::from_native(&dir)),
+		Err(_)  => path.clone()
+	}
+}
+
+/* Writes out track paths, relative to a base directory when one was asked for */
+struct PathWriter {
+	/* Directory to give paths relative to (None = give them as they are) */
+	base: Option<track_path::TrackPath>,
+	/* Number of paths that couldn't be made relative (so were given in full) */
+	not_relative: usize,
+}
+
+impl PathWriter {
+	/* Set up from "--relative-to=<dir>" or "--relative" (for the directory that out_file is in,
+	 * or the current directory when writing to stdout)
+	 */
+	fn new(args: &[String], out_file: Option<&String>) -> PathWriter
+	{
+		let base = match command_args::get_option(args, PATH_OPTIONS[0]) {
+			Some(dir) => Some(PathBuf::from(dir)),
+			None if command_args::has_flag(args, PATH_OPTIONS[1]) => {
+				Some(out_file.and_then(|f| Path::new(f).parent()).map(Path::to_path_buf).unwrap_or_default())
+			},
+			None => None
+		};
+		
+		PathWriter {
+			base: base.map(|dir| absolute_track_path(&track_path::TrackPath::from_native(&dir))),
+			not_relative: 0,
+		}
+	}
+	
+	/* Text to write for the path */
+	fn format(&mut self, path: &track_path::TrackPath) -> String
+	{
+		match self.base {
+			Some(ref base) => {
+				match absolute_track_path(path).relative_to(base) {
+					Some(relative) => relative,
+					None => {
+						self.not_relative += 1;
+						path.to_string()
+					}
+				}
+			},
+			None => path.to_string()
+		}
+	}
+	
+	/* Warn about any paths that had to be given in full */
+	fn report(&self)
+	{
+		if let Some(ref base) = self.base {
+			if self.not_relative > 0 {
+				eprintln!("WARNING: {0} paths couldn't be made relative to '{1}' (they're on a different drive or share), so were written in full",
+				          self.not_relative, terminal_text::sanitize(base.as_str()));
+			}
+		}
+	}
+}
+
+/* Options controlling how text output gets laid out (i.e. "--width=<N>") */
+const OUTPUT_OPTIONS: [&'static str; 1] = ["--width"];
+
+/* Width to fit tables to (from "--width", the environment, or the terminal)
+ * NOTE: Like the parse options, this applies to every mode, so it's looked up from the full command line.
+ * ! This function will terminate the process if the given width is invalid
+ */
+fn output_width() -> usize
+{
+	let args: Vec<String> = env::args().collect();
+	match terminal_width::output_width(command_args::get_option(&args, OUTPUT_OPTIONS[0])) {
+		Ok(width) => width,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Is the given command-arg one of the options handled by this tool (instead of being for FFMPEG)? */
+fn is_export_option(arg: &str) -> bool
+{
+	TRACK_FILTER_OPTIONS.iter()
+		.chain(WAVEFORM_OPTIONS.iter())
+		.chain(NFO_OPTIONS.iter())
+		.chain(POST_CMD_OPTIONS.iter())
+		.chain(CONVERT_OPTIONS.iter())
+		.chain(PROGRESS_OPTIONS.iter())
+		.chain(LOCK_OPTIONS.iter())
+		.chain(PARSE_OPTIONS.iter())
+		.chain(LOCATION_REPAIR_OPTIONS.iter())
+		.chain(AUGMENT_OPTIONS.iter())
+		.chain(PROBE_CACHE_OPTIONS.iter())
+		.chain(JOURNAL_OPTIONS.iter())
+		.chain(SUMMARY_OPTIONS.iter())
+		.chain(USAGE_LOG_OPTIONS.iter())
+		.chain(OUTPUT_FILE_OPTIONS.iter())
+		.chain(NAMING_OPTIONS.iter())
+		.chain(OUTPUT_OPTIONS.iter())
+		.chain(PATH_OPTIONS.iter())
+		.chain(DURATION_OPTIONS.iter())
+		.any(|name| arg == *name || arg.starts_with(&format!("{}=", name)))
+}
+
+/* Modification time of a file (if it exists) */
+fn file_mtime(path: &Path) -> Option<std::time::SystemTime>
+{
+	fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/* Size of a file (if it exists) */
+fn file_size(path: &Path) -> Option<u64>
+{
+	fs::metadata(path).map(|m| m.len()).ok()
+}
+
+/* --------------------------------------------- */
+
+/* Print out other applications' <extension> blocks (for "dump --verbose"), with their contents indented under them */
+fn print_extensions(extensions: &[xspf_extension::ExtensionBlock], indent: &str)
+{
+	for extension in extensions.iter() {
+		println!("{0}Extension: {1}", indent, terminal_text::sanitize(&extension.application));
+		for line in extension.describe() {
+			println!("{0}  {1}", indent, terminal_text::sanitize(&line));
+		}
+	}
+}
+
+/* Debug mode showing summary of most salient information about the contents of the playlist */
+fn dump_output_mode(in_file: &str)
+{
+	if let Some(mut xspf) = load_playlist(in_file) {
+		/* NOTE: Like the parse options, the track selection options are looked up from the full command line */
+		let args: Vec<String> = env::args().collect();
+		apply_track_filter_options(&mut xspf, &args);
+		
+		let width = output_width();
+		let verbose = command_args::has_flag(&args, "--verbose");
+		
+		if let Some(ref title) = xspf.title {
+			println!("Title: '{0}'", terminal_text::sanitize(title));
+		}
+		if verbose {
+			print_extensions(&xspf.extensions, "  ");
+		}
+		println!("{0} Tracks:", xspf.len());
+		for (i, track) in xspf.tracks.iter().enumerate() {
+			/* Long filenames get shortened, so that each track's line fits */
+			let filename = terminal_text::sanitize(&track.filename);
+			let prefix = format!("  {0} | filename = '", i);
+			let suffix = format!("', date = {0}, duration = {1:?}", track.date, track.duration);
+			let columns = [terminal_width::Column::fixed(prefix.len()),
+			               terminal_width::Column::flexible(20, filename.chars().count(), 0),
+			               terminal_width::Column::fixed(suffix.len())];
+			let widths = terminal_width::allocate_widths(&columns, width, 0);
+			
+			println!("{0}{1}{2}", prefix, track_diff::truncate_middle(&filename, widths[1]), suffix);
+			if track.original_index != i {
+				println!("        Original Index: {0}", track.original_index);
+			}
+			println!("        Name: '{0}'", terminal_text::sanitize(track.display_name()));
+			println!("        Info: {0}", terminal_text::sanitize(&format!("{:?}", track.info())));
+			for &(label, detail) in [("Creator", &track.creator), ("Album", &track.album), ("Annotation", &track.annotation)].iter() {
+				if let Some(ref value) = *detail {
+					println!("        {0}: '{1}'", label, terminal_text::sanitize(value));
+				}
+			}
+			if let Some(ref source_playlist) = track.source_playlist {
+				println!("        Source Playlist: '{0}'", terminal_text::sanitize(source_playlist));
+			}
+			if let Some(rating) = track.rating {
+				println!("        Rating: {0}", xspf_parser::rating_stars(rating));
+			}
+			for (key, value) in track.properties.iter() {
+				println!("        Property: {0} = '{1}'", terminal_text::sanitize(key), terminal_text::sanitize(value));
+			}
+			if verbose {
+				print_extensions(&track.extensions, "        ");
+			}
+		}
+		
+		if !xspf.skipped.is_empty() {
+			println!("{0} Skipped Entries:", xspf.skipped.len());
+			for skipped in xspf.skipped.iter() {
+				let location = skipped.location.as_ref().map_or("<none>".to_string(), |l| format!("'{}'", l));
+				println!("  location = {0} - {1}", terminal_text::sanitize(&location), skipped.reason);
+			}
+		}
+	}
+}
+
+
+/* ................................ */
+
+/* Extract filenames for all tracks from the playlist */
+fn list_output_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	println!("List in='{0}', out={1:?}", in_file, out_file);
+	
+	/* Paths get written out as the tracks are read in where possible, so that large playlists start listing straight away
+	 * NOTE: The output only gets opened once there's something to write (or the playlist has been read), as with the other modes
+	 */
+	let mut paths = PathWriter::new(args, out_file);
+	
+	if can_stream_playlist(in_file, args) {
+		let selection = get_track_selection(args);
+		let mut out : Option<Box<dyn Write>> = None;
+		let mut failed = false;
+		
+		let xspf = stream_playlist(in_file, &mut |track| {
+			if failed || !selection.matches(&track) {
+				return;
+			}
+			let out = out.get_or_insert_with(|| open_output_stream(out_file));
+			if let Err(why) = writeln!(out, "{0}", paths.format(&track.path)) {
+				eprintln!("ERROR: {}", why);
+				failed = true;
+			}
+		});
+		if xspf.is_some() && out.is_none() {
+			out = Some(open_output_stream(out_file));
+		}
+		if let Some(ref mut out) = out {
+			finish_output_stream(out);
+		}
+		paths.report();
+		return;
+	}
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		/* Write out the filepath for each track (in full, or relative to the requested directory) to separate lines in the output stream */
+		for track in xspf.tracks.iter() {
+			match writeln!(out, "{0}", paths.format(&track.path)) {
+				Err(why) => {
+					eprintln!("ERROR: {}", why);
+					break;
+				},
+				_ => { /* continue */}
+			}
+		}
+		finish_output_stream(&mut out);
+		paths.report();
+	}
+}
+
+/* ................................ */
+
+/* Extract all the relevant info from playlist, and dump it into a JSON file for further processing */
+fn json_output_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	println!("JSON in='{0}', out={1:?}", in_file, out_file);
+	if let Some(mut xspf) = load_playlist(in_file) {
+		resolve_durations(&mut xspf, in_file);
+		apply_track_filter_options(&mut xspf, args);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		/* Serialise the export form of the playlist (with the computed fields filled in) to a JSON string */
+		let export = json_export::JsonPlaylistExport::from_playlist(&xspf);
+		match serde_json::to_string_pretty(&export) {
+			Ok(j) => {
+				/* Write entire json string to output */
+				match writeln!(out, "{}", j) {
+					Err(why) => {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					},
+					_ => { /* continue */}
+				}
+				finish_output_stream(&mut out);
+			},
+			
+			// FIXME: handle specific cases?
+			Err(e) => {
+				eprintln!("Couldn't convert to playlist data to JSON - {:?}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+}
+
+/* ................................ */
+
+/* Compute and display summary of total playing time of playlist */
+fn total_duration_mode(in_file: &str)
+{
+	println!("Total Duration Summary:");
+	let args: Vec<String> = env::args().collect();
+	if let Some(mut xspf) = load_playlist_streamed(in_file, &args) {
+		let mut sources = resolve_durations(&mut xspf, in_file);
+		
+		/* Why tracks didn't get durations (by path, as filtering moves the tracks around) */
+		let unknown_reasons: HashMap<String, String> = xspf.diagnostics.entries.iter()
+			.filter(|d| d.category == duration_source::PROBE_FAILED_CATEGORY)
+			.filter_map(|d| d.track_index.map(|i| (xspf.tracks[i].path.to_string(), d.message.clone())))
+			.collect();
+		
+		/* NOTE: Filtering happens after the durations are known (so they can be filtered on),
+		 *       so the number of durations from each source needs redoing for the tracks left
+		 */
+		apply_track_filter_options(&mut xspf, &args);
+		for source in sources.iter_mut() {
+			source.1 = xspf.tracks.iter().filter(|track| track.duration_source == source.0).count();
+		}
+		
+		/* Compute duration */
+		let stats = xspf.duration_stats();
+		let result = &stats.total;
+		
+		println!("    Total Duration:  {:?} ([h:]mm:ss)", result.duration);
+		println!("    Num Tracks:      {}", xspf.len());
+		
+		let sources: Vec<String> = sources.iter().map(|&(source, count)| format!("{0} {1}", count, source)).collect();
+		println!("    Durations From:  {}", sources.join(", "));
+		
+		if let Some(average) = stats.average() {
+			println!("    Average Length:  {:?}", average);
+		}
+		for &(label, index) in [("Shortest Track:", stats.shortest), ("Longest Track: ", stats.longest)].iter() {
+			if let Some(track) = index.map(|i| &xspf.tracks[i]) {
+				println!("    {0}  {1:?} - {2}", label, track.duration.as_ref().unwrap(), terminal_text::sanitize(&track.filename));
+			}
+		}
+		
+		if stats.per_type.len() > 1 {
+			println!();
+			println!("    By Type:");
+			for tally in stats.per_type.iter() {
+				println!("        {0:<16} {1:?} ({2} tracks)", format!("{:?}:", tally.track_type), tally.duration, tally.counted + tally.uncounted);
+			}
+		}
+		
+		if result.uncounted > 0 {
+			println!();
+			println!("    Skipped Tracks:  {}", result.uncounted);
+			println!("                     (Tracks get skipped if none of the duration sources had a duration for them)");
+			for track in xspf.tracks.iter().filter(|track| track.duration.is_none()) {
+				let reason = unknown_reasons.get(&track.path.to_string())
+				                            .map_or("No duration given in the playlist", |reason| reason.as_str());
+				println!("        {0} - {1}", terminal_text::sanitize(&track.filename), terminal_text::sanitize(reason));
+			}
+		}
+	}
+}
+
+/* Runtime summary for several playlists at once ("runtime <in1.xspf> <in2.xspf> ... [--jobs=N] [--timeout=secs]")
+ * Playlists that couldn't be read are reported, without stopping the rest from being counted
+ */
+fn multi_total_duration_mode(args: &Vec<String>)
+{
+	let in_files: Vec<String> = args[2 ..].iter().filter(|a| !a.starts_with("--")).cloned().collect();
+	let options = &args[2 ..];
+	
+	if command_args::get_option(options, AUGMENT_OPTIONS[0]).is_some() {
+		eprintln!("WARNING: --augment-from only applies when reading a single playlist (ignored)");
+	}
+	
+	let max_workers = command_args::parse_option::<usize>(options, "--jobs");
+	let timeout_secs = command_args::parse_option::<u64>(options, "--timeout");
+	let (max_workers, timeout_secs) = match (max_workers, timeout_secs) {
+		(Ok(j), Ok(t)) => (j.unwrap_or(parallel_parse::DEFAULT_MAX_WORKERS),
+		                   t.unwrap_or(parallel_parse::DEFAULT_TIMEOUT_SECS)),
+		(Err(e), _) | (_, Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	let mut results = parallel_parse::parse_playlists(&in_files, max_workers,
+	                                              std::time::Duration::from_secs(timeout_secs),
+	                                              playlist_parse_options());
+	
+	println!("Total Duration Summary ({} playlists):", in_files.len());
+	
+	let mut total = track_duration::TrackDuration(0);
+	let mut total_tracks = 0;
+	let mut total_uncounted = 0;
+	let mut failures = 0;
+	
+	for &mut (ref in_file, ref mut result) in results.iter_mut() {
+		match *result {
+			Ok(ref mut xspf) => {
+				resolve_durations(xspf, in_file);
+				apply_track_filter_options(xspf, options);
+				let result = xspf.total_duration();
+				println!("    {0:?} ([h:]mm:ss)  {1:4} tracks  {2}", result.duration, xspf.len(), in_file);
+				
+				total += result.duration;
+				total_tracks += xspf.len();
+				usage_log::add_tracks(xspf.len());
+				total_uncounted += result.uncounted;
+			},
+			Err(ref e) => {
+				eprintln!("    ERROR: {0} - {1}", in_file, e);
+				run_outcome::record_failure(run_outcome::UNREADABLE_PLAYLIST_CATEGORY);
+				failures += 1;
+			}
+		}
+	}
+	
+	println!();
+	println!("    Total Duration:  {:?} ([h:]mm:ss)", total);
+	println!("    Num Tracks:      {}", total_tracks);
+	if total_uncounted > 0 {
+		println!("    Skipped Tracks:  {}  (no duration data found in the playlist)", total_uncounted);
+	}
+	if failures > 0 {
+		println!("    Failed Files:    {}", failures);
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+}
+
+/* ................................ */
+
+/* Write the freshness report as text
+ * Helper for freshness_mode()
+ */
+fn write_freshness_report(out: &mut dyn Write, report: &freshness::FreshnessReport) -> io::Result<()>
+{
+	writeln!(out, "Freshness Check (max drift = {} days):", report.max_drift_days)?;
+	if report.diagnostics.is_empty() {
+		writeln!(out, "  No problems found")?;
+	}
+	else {
+		report.diagnostics.write_text(out)?;
+	}
+	
+	writeln!(out)?;
+	writeln!(out, "    Checked:    {}", report.checked)?;
+	writeln!(out, "    Unchecked:  {}  (no parseable session date, or file not found)", report.unchecked)?;
+	writeln!(out, "    Flagged:    {}", report.diagnostics.len())?;
+	Ok(())
+}
+
+/* Check file modification times against the session dates of the tracks */
+fn freshness_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let max_drift = match command_args::parse_option::<i64>(args, "--max-drift") {
+		Ok(value) => value.unwrap_or(freshness::DEFAULT_MAX_DRIFT_DAYS),
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	if let Some(xspf) = load_playlist(in_file) {
+		let report = freshness::check_playlist(&xspf, max_drift);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&report) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert freshness report to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else if let Err(why) = write_freshness_report(&mut out, &report) {
+			eprintln!("ERROR: Couldn't write freshness report - {}", why);
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* ................................ */
+
+/* Write the gap check results as text
+ * Helper for gaps_mode()
+ */
+fn write_gaps_report(out: &mut dyn Write, report: &session_gaps::GapsReport) -> io::Result<()>
+{
+	writeln!(out, "Session Gap Check:")?;
+	if report.diagnostics.is_empty() {
+		writeln!(out, "  No problems found")?;
+	}
+	else {
+		report.diagnostics.write_text(out)?;
+	}
+	
+	writeln!(out)?;
+	writeln!(out, "    Sessions:   {}", report.sessions)?;
+	writeln!(out, "    Unchecked:  {}  (no take number in the filename)", report.unchecked)?;
+	writeln!(out, "    Flagged:    {}", report.diagnostics.len())?;
+	Ok(())
+}
+
+/* Check the take numbers in each session for gaps and duplicates */
+fn gaps_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(xspf) = load_playlist(in_file) {
+		let report = session_gaps::check_playlist(&xspf);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&report) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert gap check results to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else if let Err(why) = write_gaps_report(&mut out, &report) {
+			eprintln!("ERROR: Couldn't write gap check results - {}", why);
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* ................................ */
+
+/* Write the problems found with the filenames, with a summary of how many tracks were flagged
+ * Helper for check_mode()
+ */
+fn write_lint_report(out: &mut dyn Write, report: &track_lint::LintReport) -> io::Result<()>
+{
+	writeln!(out, "Filename Check:")?;
+	if report.diagnostics.is_empty() {
+		writeln!(out, "  No problems found")?;
+	}
+	else {
+		report.diagnostics.write_text(out)?;
+	}
+	
+	writeln!(out)?;
+	writeln!(out, "    Tracks:     {}", report.tracks)?;
+	writeln!(out, "    Flagged:    {0}  ({1} problems)", report.flagged_tracks, report.diagnostics.len())?;
+	Ok(())
+}
+
+/* Check each track's filename against the naming conventions */
+fn check_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(xspf) = load_playlist(in_file) {
+		let report = track_lint::check_playlist(&xspf);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&report) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert filename check results to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else if let Err(why) = write_lint_report(&mut out, &report) {
+			eprintln!("ERROR: Couldn't write filename check results - {}", why);
+		}
+		finish_output_stream(&mut out);
+		
+		/* With --strict, anything flagged counts as a failure (e.g. for checking playlists in scripts) */
+		if command_args::has_flag(args, "--strict") {
+			for d in report.diagnostics.entries.iter() {
+				run_outcome::record_failure(&d.category);
+			}
+		}
+		else {
+			run_outcome::record_diagnostics(&report.diagnostics);
+		}
+	}
+}
+
+/* ................................ */
+
+/* Write the list of tracks whose files are missing, with the number of them
+ * Helper for missing_files_mode()
+ */
+fn write_missing_files_report(out: &mut dyn Write, missing: &[(usize, &Track)], total: usize) -> io::Result<()>
+{
+	writeln!(out, "Missing Files:")?;
+	for &(track_idx, track) in missing.iter() {
+		writeln!(out, "    {0:>4}. {1}", track_idx + 1, terminal_text::sanitize(&track.filename))?;
+		writeln!(out, "          {0}", terminal_text::sanitize(&track.file_path().display().to_string()))?;
+	}
+	if !missing.is_empty() {
+		writeln!(out)?;
+	}
+	writeln!(out, "{0} of {1} tracks missing", missing.len(), total)
+}
+
+/* Report the tracks whose files no longer exist (e.g. they've been moved or deleted since the playlist was made)
+ * NOTE: Each one counts as a failure, so the run exits with an error code when any are missing (for scripts to check)
+ */
+fn missing_files_mode(in_file: &str, out_file: Option<&String>)
+{
+	if let Some(xspf) = load_playlist_for_files(in_file) {
+		let missing: Vec<(usize, &Track)> = xspf.tracks.iter()
+			.enumerate()
+			.filter(|&(_, track)| !track.file_path().exists())
+			.collect();
+		for _ in missing.iter() {
+			run_outcome::record_failure(run_outcome::MISSING_FILE_CATEGORY);
+		}
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if let Err(why) = write_missing_files_report(&mut out, &missing, xspf.tracks.len()) {
+			eprintln!("ERROR: Couldn't write missing files report - {}", why);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* ................................ */
+
+/* Write the list of missing tracks, and where each of them was found (if anywhere)
+ * Helper for relocate_mode()
+ */
+fn write_relocation_report(out: &mut dyn Write, relocations: &[track_relocate::TrackRelocation], total: usize,
+                           index: &track_relocate::FileIndex) -> io::Result<()>
+{
+	let (mut relocated, mut ambiguous) = (0, 0);
+	
+	writeln!(out, "Relocations:")?;
+	for r in relocations.iter() {
+		let filename = terminal_text::sanitize(&r.filename);
+		match r.relocation {
+			track_relocate::Relocation::Found(ref path) => {
+				writeln!(out, "    {0:>4}. {1}", r.track_index + 1, filename)?;
+				writeln!(out, "          {0}", terminal_text::sanitize(&r.old_path))?;
+				writeln!(out, "       -> {0}", terminal_text::sanitize(&path.display().to_string()))?;
+				relocated += 1;
+			},
+			track_relocate::Relocation::Ambiguous(ref paths) => {
+				writeln!(out, "    {0:>4}. {1} - ambiguous, could be any of these (left as it is):", r.track_index + 1, filename)?;
+				for path in paths.iter() {
+					writeln!(out, "        ? {0}", terminal_text::sanitize(&path.display().to_string()))?;
+				}
+				ambiguous += 1;
+			},
+			track_relocate::Relocation::NotFound => {
+				writeln!(out, "    {0:>4}. {1} - not found", r.track_index + 1, filename)?;
+			}
+		}
+	}
+	if !relocations.is_empty() {
+		writeln!(out)?;
+	}
+	writeln!(out, "{0} of {1} tracks missing - {2} relocated, {3} ambiguous, {4} not found (searched {5} files under {6:?})",
+	         relocations.len(), total, relocated, ambiguous, relocations.len() - relocated - ambiguous,
+	         index.file_count, index.root)
+}
+
+/* Show where the tracks whose files no longer exist can be found under "--search-root=<dir>" (without changing anything)
+ * NOTE: Ambiguous tracks, and those that couldn't be found, count as failures (as they'd still be missing)
+ */
+fn relocate_mode(in_file: &str, out_file: Option<&String>)
+{
+	let index = match search_index() {
+		Some(index) => index,
+		None => {
+			eprintln!("ERROR: You need to supply the folder to search - relocate <in.xspf> [<outfile>] --search-root=<dir>");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		repair_double_encoding(&mut xspf);
+		let relocations = track_relocate::relocate_missing(&mut xspf, index);
+		for r in relocations.iter() {
+			if let track_relocate::Relocation::Found(_) = r.relocation {
+				continue;
+			}
+			run_outcome::record_failure(run_outcome::MISSING_FILE_CATEGORY);
+		}
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if let Err(why) = write_relocation_report(&mut out, &relocations, xspf.tracks.len(), index) {
+			eprintln!("ERROR: Couldn't write relocation report - {}", why);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* ................................ */
+
+/* Write the size estimate as text
+ * Helper for estimate_mode()
+ */
+fn write_size_estimate(out: &mut dyn Write, estimate: &size_estimate::SizeEstimate) -> io::Result<()>
+{
+	match estimate.bitrate_kbps {
+		Some(kbps) => writeln!(out, "Size Estimate (converting to {0} @ {1} kbit/s):", estimate.format, kbps)?,
+		None       => writeln!(out, "Size Estimate (converting to {0}, based on source sizes):", estimate.format)?,
+	}
+	
+	for t in estimate.tracks.iter() {
+		let duration = match t.duration_ms {
+			Some(ms) if t.duration_assumed => format!("~{} (assumed)", track_duration::TrackDuration(ms).to_timecode()),
+			Some(ms)                       => track_duration::TrackDuration(ms).to_timecode(),
+			None                           => "?".to_string()
+		};
+		let estimated = t.estimated_bytes.map(size_estimate::format_size).unwrap_or("?".to_string());
+		
+		match t.source_bytes {
+			Some(size) => writeln!(out, "  {0:3} | {1} | {2} | est. {3} (source {4})",
+			                       t.track_index + 1, t.filename, duration, estimated, size_estimate::format_size(size))?,
+			None       => writeln!(out, "  {0:3} | {1} | {2} | est. {3}",
+			                       t.track_index + 1, t.filename, duration, estimated)?,
+		}
+	}
+	
+	writeln!(out)?;
+	writeln!(out, "    Estimated Total:  {0} ({1} bytes)", size_estimate::format_size(estimate.total_bytes), estimate.total_bytes)?;
+	if let Some(source_total) = estimate.source_total_bytes {
+		writeln!(out, "    Source Total:     {0} ({1} bytes)", size_estimate::format_size(source_total), source_total)?;
+	}
+	if estimate.uncertain > 0 {
+		writeln!(out, "    Uncertain:        {} tracks (unknown duration - estimated using the average of the known ones)",
+		         estimate.uncertain)?;
+	}
+	if estimate.unestimated > 0 {
+		writeln!(out, "    Unestimated:      {} tracks (not enough info - not included in the total)", estimate.unestimated)?;
+	}
+	Ok(())
+}
+
+/* Estimate the size of the files that converting the playlist would produce */
+fn estimate_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let format_name = command_args::get_option(args, "--format").unwrap_or("mp3");
+	let format = match format_name.parse::<TrackExtension>() {
+		Ok(f @ TrackExtension::mp3) | Ok(f @ TrackExtension::ogg) | Ok(f @ TrackExtension::flac) => f,
+		_ => {
+			eprintln!("ERROR: Unsupported output format - '{}' (expected mp3, ogg, or flac)", format_name);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	let bitrate = match command_args::get_option(args, "--bitrate").map(size_estimate::parse_bitrate_kbps) {
+		Some(Ok(kbps)) => Some(kbps),
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		/* Same quality as convert would use */
+		None => size_estimate::quality_bitrate_kbps(&format, get_audio_quality(args))
+	};
+	
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		resolve_durations(&mut xspf, in_file);
+		apply_track_filter_options(&mut xspf, args);
+		let estimate = size_estimate::estimate_playlist(&xspf, &format, bitrate, command_args::has_flag(args, "--stat"));
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&estimate) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert size estimate to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else if let Err(why) = write_size_estimate(&mut out, &estimate) {
+			eprintln!("ERROR: Couldn't write size estimate - {}", why);
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* How much space the playlist's files take up (e.g. to check it'll fit on a device before copying it there),
+ * along with a rough estimate of their size once converted if "--estimate=<format>" is given
+ * NOTE: Files that couldn't be checked count as failures (as copying them would fail too)
+ */
+fn size_mode(in_file: &str)
+{
+	/* NOTE: Like the parse options, the options for this are looked up from the full command line */
+	let args: Vec<String> = env::args().collect();
+	
+	let estimate_format = command_args::get_option(&args, "--estimate").map(|format_name| {
+		match format_name.parse::<TrackExtension>() {
+			Ok(f @ TrackExtension::mp3) | Ok(f @ TrackExtension::ogg) | Ok(f @ TrackExtension::flac) => f,
+			_ => {
+				eprintln!("ERROR: Unsupported format for --estimate - '{}' (expected mp3, ogg, or flac)", format_name);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	});
+	let bitrate = match command_args::get_option(&args, "--bitrate").map(size_estimate::parse_bitrate_kbps) {
+		Some(Ok(kbps)) => Some(kbps),
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		None => None
+	};
+	
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		/* Durations are only needed for the estimate */
+		if estimate_format.is_some() {
+			resolve_durations(&mut xspf, in_file);
+		}
+		apply_track_filter_options(&mut xspf, &args);
+		let usage = xspf.disk_usage();
+		
+		println!("Disk Usage:");
+		println!("    Total Size:      {0} ({1} bytes, {2} files)", size_estimate::format_size(usage.total_bytes), usage.total_bytes, usage.files);
+		
+		if !usage.by_extension.is_empty() {
+			println!();
+			println!("    By Extension:");
+			for e in usage.by_extension.iter() {
+				let extension = if e.extension.is_empty() { "(none)" } else { e.extension.as_str() };
+				println!("        {0:<10} {1:>10}  ({2} files)", extension, size_estimate::format_size(e.bytes), e.files);
+			}
+		}
+		
+		if !usage.largest.is_empty() {
+			println!();
+			println!("    Largest Files:");
+			for f in usage.largest.iter() {
+				println!("        {0:>10}  {1:3} | {2}", size_estimate::format_size(f.bytes), f.track_index + 1, terminal_text::sanitize(&f.path));
+			}
+		}
+		
+		if !usage.unreadable.is_empty() {
+			println!();
+			println!("    Couldn't Check:  {} files (not included in the total)", usage.unreadable.len());
+			for f in usage.unreadable.iter() {
+				println!("        {0:3} | {1} - {2}", f.track_index + 1, terminal_text::sanitize(&f.path), f.reason);
+				run_outcome::record_failure(run_outcome::MISSING_FILE_CATEGORY);
+			}
+		}
+		
+		if let Some(format) = estimate_format {
+			/* NOTE: Only unsupported formats give None, and those have been refused already */
+			if let Some(estimate) = size_estimate::estimate_converted_size(&xspf, &format, bitrate) {
+				println!();
+				println!("    ESTIMATE after converting to {0} (assuming {1} kbit/s throughout - the actual size depends on the material):",
+				         estimate.format, estimate.bitrate_kbps);
+				println!("    Estimated Size:  ~{0} ({1} bytes)", size_estimate::format_size(estimate.total_bytes), estimate.total_bytes);
+				if estimate.unknown_durations > 0 {
+					println!("    Unknown:         {} tracks (no duration data - not included in the estimate)", estimate.unknown_durations);
+				}
+			}
+		}
+	}
+}
+
+/* ................................ */
+
+/* Format a change in a count for the stats tables, e.g. "+3", "-1", "0" */
+fn format_count_delta(delta: i64) -> String
+{
+	if delta > 0 { format!("+{}", delta) } else { delta.to_string() }
+}
+
+/* Format a change in duration for the stats tables, e.g. "+01:30", "-00:45" */
+fn format_duration_delta(delta_ms: i64) -> String
+{
+	let timecode = track_duration::TrackDuration(delta_ms.abs()).to_timecode();
+	match delta_ms {
+		d if d > 0 => format!("+{}", timecode),
+		d if d < 0 => format!("-{}", timecode),
+		_          => timecode,
+	}
+}
+
+/* Write the totals for one group of tracks (e.g. a session date) as a row of the stats tables
+ * NOTE: Durations are marked as partial when some of the tracks have no duration data
+ */
+fn write_stats_row(out: &mut dyn Write, label: &str, stats: &playlist_stats::TypeStats) -> io::Result<()>
+{
+	let unknown = if stats.uncounted > 0 { format!(" (+{} unknown)", stats.uncounted) } else { String::new() };
+	let rating = stats.average_rating.map(|r| format!(" (rating {0:.2} from {1})", r, stats.rated)).unwrap_or_default();
+	writeln!(out, "    {0:<10} {1:>6}  {2:>10}{3}{4}", label, stats.tracks, track_duration::TrackDuration(stats.duration_ms).to_timecode(), unknown, rating)
+}
+
+/* Write the stats for a single set of tracks as text
+ * Helper for stats_mode()
+ */
+fn write_stats_summary(out: &mut dyn Write, summary: &playlist_stats::StatsSummary, period: Option<playlist_stats::Period>) -> io::Result<()>
+{
+	writeln!(out, "Playlist Stats:")?;
+	writeln!(out, "    Tracks:          {}", summary.tracks)?;
+	writeln!(out, "    Total Duration:  {}", track_duration::TrackDuration(summary.duration_ms).to_timecode())?;
+	if summary.uncounted > 0 {
+		writeln!(out, "    Unknown:         {} tracks (no duration data - not included in the total)", summary.uncounted)?;
+	}
+	if let (Some(first), Some(last)) = (summary.first_date.as_ref(), summary.last_date.as_ref()) {
+		writeln!(out, "    Sessions:        {0} to {1}", first, last)?;
+	}
+	if let Some(average) = summary.average_rating {
+		writeln!(out, "    Average Rating:  {0:.2} ({1} rated tracks)", average, summary.rated)?;
+	}
+
+	writeln!(out)?;
+	writeln!(out, "    {0:<10} {1:>6}  {2:>10}", "Type", "Tracks", "Duration")?;
+	for name in playlist_stats::type_names(&[summary]).iter() {
+		write_stats_row(out, name, &summary.by_type[name])?;
+	}
+
+	/* Most recent sessions first */
+	if !summary.by_date.is_empty() {
+		writeln!(out)?;
+		writeln!(out, "    {0:<10} {1:>6}  {2:>10}", "Date", "Tracks", "Duration")?;
+		for (date, d) in summary.by_date.iter().rev() {
+			write_stats_row(out, date, d)?;
+		}
+		let undated = summary.undated();
+		if undated > 0 {
+			writeln!(out, "    {0:<10} {1:>6}", "(undated)", undated)?;
+		}
+	}
+
+	writeln!(out)?;
+	writeln!(out, "    {0:<10} {1:>6}  {2:>10}", "Extension", "Tracks", "Duration")?;
+	for (extn, e) in summary.by_extension.iter() {
+		write_stats_row(out, extn, e)?;
+	}
+
+	if let Some(period) = period {
+		writeln!(out)?;
+		writeln!(out, "    {0:<10} {1:>6}  {2:>10}", period.heading(), "Tracks", "Duration")?;
+		for (label, p) in summary.by_period.iter() {
+			write_stats_row(out, label, p)?;
+		}
+	}
+	Ok(())
+}
+
+/* Write the stats for two date windows side-by-side (with the change between them) as text
+ * Helper for stats_mode()
+ */
+fn write_stats_comparison(out: &mut dyn Write, comparison: &playlist_stats::StatsComparison) -> io::Result<()>
+{
+	let (before, after, delta) = (&comparison.before, &comparison.after, &comparison.delta);
+	let timecode = |ms: i64| track_duration::TrackDuration(ms).to_timecode();
+
+	writeln!(out, "Playlist Stats Comparison:")?;
+	writeln!(out, "    {0:<18} {1:>22}  {2:>22}  {3:>10}", "", comparison.before_window, comparison.after_window, "Change")?;
+	writeln!(out, "    {0:<18} {1:>22}  {2:>22}  {3:>10}", "Tracks",
+	         before.tracks, after.tracks, format_count_delta(delta.tracks))?;
+	writeln!(out, "    {0:<18} {1:>22}  {2:>22}  {3:>10}", "Total Duration",
+	         timecode(before.duration_ms), timecode(after.duration_ms), format_duration_delta(delta.duration_ms))?;
+	if before.uncounted > 0 || after.uncounted > 0 {
+		writeln!(out, "    {0:<18} {1:>22}  {2:>22}  {3:>10}", "Unknown Duration",
+		         before.uncounted, after.uncounted, format_count_delta(delta.uncounted))?;
+	}
+
+	let none = playlist_stats::TypeStats::default();
+	for name in playlist_stats::type_names(&[before, after]).iter() {
+		let b = before.by_type.get(name).unwrap_or(&none);
+		let a = after.by_type.get(name).unwrap_or(&none);
+		let d = &delta.by_type[name];
+
+		writeln!(out)?;
+		writeln!(out, "    {0:<18} {1:>22}  {2:>22}  {3:>10}", format!("{} Tracks", name),
+		         b.tracks, a.tracks, format_count_delta(d.tracks))?;
+		writeln!(out, "    {0:<18} {1:>22}  {2:>22}  {3:>10}", format!("{} Duration", name),
+		         timecode(b.duration_ms), timecode(a.duration_ms), format_duration_delta(d.duration_ms))?;
+	}
+	Ok(())
+}
+
+/* Get the two date windows for "--compare=<start>..<end> <start>..<end>"
+ * ! This function will terminate the process if the windows are missing/invalid
+ */
+fn get_compare_windows(args: &[String]) -> Option<(playlist_stats::DateWindow, playlist_stats::DateWindow)>
+{
+	let index = args.iter().position(|a| a.starts_with("--compare="))?;
+	let first = &args[index]["--compare=".len() ..];
+	let second = match args.get(index + 1).filter(|a| !a.starts_with("--")) {
+		Some(second) => second,
+		None => {
+			eprintln!("ERROR: --compare needs two date ranges, e.g. --compare=2017-01-01..2017-06-30 2017-07-01..2017-12-31");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+
+	match (playlist_stats::DateWindow::parse(first), playlist_stats::DateWindow::parse(second)) {
+		(Ok(a), Ok(b)) => Some((a, b)),
+		(Err(e), _) | (_, Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Period to break the stats down by ("--by", with "--week-start"), if any
+ * ! This function will terminate the process if either is invalid
+ */
+fn get_stats_period(args: &[String]) -> Option<playlist_stats::Period>
+{
+	let week_start = command_args::get_option(args, "--week-start");
+	let by = match command_args::get_option(args, "--by") {
+		Some(by) => by,
+		None => {
+			if week_start.is_some() {
+				eprintln!("ERROR: --week-start needs --by=week");
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+			return None;
+		}
+	};
+
+	match playlist_stats::Period::parse(by, week_start) {
+		Ok(period) => Some(period),
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Summary stats (number of tracks, total duration, per type) for the playlist,
+ * or with --compare, for two date windows side-by-side
+ */
+fn stats_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	/* Check the date ranges/grouping before doing anything else */
+	let windows = get_compare_windows(args);
+	let period = get_stats_period(args);
+	let as_json = command_args::has_flag(args, "--json");
+
+	if windows.is_some() && period.is_some() {
+		eprintln!("ERROR: --by can't be used with --compare");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+
+	if let Some(mut xspf) = load_playlist(in_file) {
+		resolve_durations(&mut xspf, in_file);
+		apply_track_filter_options(&mut xspf, args);
+
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+
+		let result = match windows {
+			Some((first, second)) => {
+				let before = playlist_stats::StatsSummary::from_tracks(xspf.tracks.iter().filter(|t| first.contains(t)));
+				let after = playlist_stats::StatsSummary::from_tracks(xspf.tracks.iter().filter(|t| second.contains(t)));
+				let comparison = playlist_stats::StatsComparison {
+					delta: playlist_stats::compute_delta(&before, &after),
+					before_window: first.label(),
+					before: before,
+					after_window: second.label(),
+					after: after,
+				};
+
+				if as_json {
+					serde_json::to_string_pretty(&comparison).map(|j| writeln!(out, "{}", j))
+				}
+				else {
+					Ok(write_stats_comparison(&mut out, &comparison))
+				}
+			},
+			None => {
+				let mut summary = xspf.stats();
+				if let Some(period) = period {
+					summary.group_by(xspf.tracks.iter(), period);
+				}
+
+				if as_json {
+					serde_json::to_string_pretty(&summary).map(|j| writeln!(out, "{}", j))
+				}
+				else {
+					Ok(write_stats_summary(&mut out, &summary, period))
+				}
+			}
+		};
+
+		match result {
+			Ok(Ok(())) => {},
+			Ok(Err(why)) => {
+				eprintln!("ERROR: Couldn't write stats - {}", why);
+			},
+			Err(e) => {
+				eprintln!("Couldn't convert stats to JSON - {:?}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* ................................ */
+
+/* Report tracks that are out of date/index order, optionally writing out the fixed order */
+fn verify_order_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(mut xspf) = load_playlist(in_file) {
+		let diagnostics = playlist_order::check_playlist(&xspf);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&diagnostics) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert order check results to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else {
+			let result = writeln!(out, "Playlist Order Check:").and_then(|_| {
+				if diagnostics.is_empty() {
+					writeln!(out, "  No problems found")
+				}
+				else {
+					diagnostics.write_text(&mut out)
+				}
+			});
+			if let Err(why) = result {
+				eprintln!("ERROR: Couldn't write order check results - {}", why);
+			}
+		}
+		finish_output_stream(&mut out);
+		
+		/* Re-sorted playlist - written as a JSON dump of the playlist (not as XSPF) */
+		if let Some(fix_file) = command_args::get_option(args, "--fix") {
+			let order = playlist_order::sorted_order(&xspf);
+			playlist_order::reorder_tracks(&mut xspf, &order);
+			
+			let result = serde_json::to_string_pretty(&xspf)
+			                 .map_err(|e| e.to_string())
+			                 .and_then(|j| fs::write(fix_file, j).map_err(|e| e.to_string()));
+			match result {
+				Ok(_)    => {
+					println!("\nWrote re-sorted playlist (as JSON) to {}", fix_file);
+					run_journal::record_action("wrote", format!("re-sorted playlist -> {}", fix_file));
+				},
+				Err(why) => eprintln!("ERROR: Couldn't write re-sorted playlist (as JSON) to {0:?} - {1}", fix_file, why)
+			}
+		}
+	}
+}
+
+/* Sort the tracks (by date, name, duration, or type), writing the sorted playlist to out_file,
+ * or if that isn't given, listing the paths of the sorted tracks (like the "list" mode)
+ */
+fn sort_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let key = match command_args::get_option(args, "--by").map_or(Ok(playlist_order::SortKey::Date), |by| by.parse()) {
+		Ok(key) => key,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	if let Some(out_file) = out_file {
+		check_not_overwriting_input(in_file, out_file);
+	}
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		if key == playlist_order::SortKey::Duration {
+			resolve_durations(&mut xspf, in_file);
+		}
+		
+		/* Only the playlist's own title should get written out again (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		let xspf = xspf.sorted(key);
+		
+		match out_file {
+			Some(out_file) => {
+				save_playlist(&xspf, out_file);
+			},
+			None => {
+				let stdout = io::stdout();
+				let mut out = stdout.lock();
+				for track in xspf.tracks.iter() {
+					if let Err(why) = writeln!(out, "{0}", track.path) {
+						eprintln!("ERROR: {}", why);
+						break;
+					}
+				}
+			}
+		}
+	}
+}
+
+/* Is the file a plain list of paths, rather than a playlist? (".m3u" or ".txt") */
+fn is_path_list_file(filename: &str) -> bool
+{
+	let extn = Path::new(filename).extension().map(|e| e.to_string_lossy().to_lowercase());
+	matches!(extn.as_deref(), Some("m3u") | Some("txt"))
+}
+
+/* Write the playlist's tracks in a random order (repeatable with "--seed=<N>")
+ * - As XSPF, or for ".m3u"/".txt" outfiles (or stdout), just the track paths (as for list)
+ */
+fn shuffle_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let seed = match command_args::parse_option::<u64>(args, "--seed") {
+		Ok(Some(seed)) => seed,
+		Ok(None) => {
+			/* Report the seed picked, so the same order can be made again
+			 * (on stderr, so that it doesn't end up amongst the paths when they're written to stdout)
+			 */
+			let seed = track_sampling::random_seed();
+			eprintln!("Shuffling with seed {0} (use --seed={0} to repeat this order)", seed);
+			seed
+		},
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	if let Some(out_file) = out_file {
+		check_not_overwriting_input(in_file, out_file);
+	}
+
+	if let Some(mut xspf) = load_playlist(in_file) {
+		/* Only the playlist's own title should get written out again (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		let xspf = xspf.shuffled(seed);
+
+		match out_file {
+			Some(out_file) if !is_path_list_file(out_file) => {
+				save_playlist(&xspf, out_file);
+			},
+			_ => {
+				let mut paths = PathWriter::new(args, out_file);
+				let mut out : Box<dyn Write> = open_output_stream(out_file);
+				for track in xspf.tracks.iter() {
+					if let Err(why) = writeln!(out, "{0}", paths.format(&track.path)) {
+						eprintln!("ERROR: {}", why);
+						exit_run(run_outcome::EXIT_FAILED);
+					}
+				}
+				finish_output_stream(&mut out);
+				paths.report();
+			}
+		}
+	}
+}
+
+/* ................................ */
+
+/* Options for the cache of ffprobe results (for modes that run ffprobe) */
+const PROBE_CACHE_OPTIONS: [&'static str; 2] = ["--probe-cache", "--no-probe-cache"];
+
+/* Open the probe cache to use for the given playlist, as specified by the command-args
+ * > returns None if the cache has been disabled
+ */
+fn open_probe_cache(in_file: &str, args: &[String]) -> Option<probe_cache::ProbeCache>
+{
+	if command_args::has_flag(args, "--no-probe-cache") {
+		return None;
+	}
+	
+	let cache_path = match command_args::get_option(args, "--probe-cache") {
+		Some(path) => Path::new(path).to_path_buf(),
+		None       => probe_cache::ProbeCache::default_path_for(in_file)
+	};
+	Some(probe_cache::ProbeCache::load(&cache_path))
+}
+
+/* Options controlling where track durations come from (for modes that use durations) */
+const DURATION_OPTIONS: [&'static str; 2] = ["--duration-priority", "--prefer-probed"];
+
+/* Order to try the sources of track durations in, as given on the command line
+ * NOTE: Like the parse options, this is looked up from the full command line (so it also works for "runtime")
+ * ! This function will terminate the process if the priority is invalid
+ */
+fn duration_priority() -> duration_source::DurationPriority
+{
+	let args: Vec<String> = env::args().collect();
+	match (command_args::get_option(&args, DURATION_OPTIONS[0]), command_args::has_flag(&args, DURATION_OPTIONS[1])) {
+		(None, false) => duration_source::DurationPriority::default(),
+		(None, true)  => duration_source::DurationPriority::prefer_probed(),
+		(Some(priority), false) => {
+			match duration_source::DurationPriority::parse(priority) {
+				Ok(priority) => priority,
+				Err(e) => {
+					eprintln!("ERROR: {}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		},
+		(Some(_), true) => {
+			eprintln!("ERROR: Only one of --duration-priority or --prefer-probed should be given");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Work out the durations of the playlist's tracks from the sources given by duration_priority()
+ * (probing the files - via the probe cache - for tracks where the higher priority sources have nothing)
+ * > returns the number of tracks whose durations came from each source
+ */
+fn resolve_durations(xspf: &mut XspfPlaylist, in_file: &str) -> Vec<(duration_source::DurationSource, usize)>
+{
+	let args: Vec<String> = env::args().collect();
+	let priority = duration_priority();
+	let prober = media_probe::FfprobeProber;
+	
+	/* The cache only gets opened once something actually needs probing */
+	let mut cache: Option<Option<probe_cache::ProbeCache>> = None;
+	let mut failures: HashMap<String, String> = HashMap::new();
+	
+	let counts = duration_source::resolve_playlist_durations(xspf, &priority, &mut |track| {
+		/* No point running ffprobe on files that aren't there */
+		if !track.file_path().is_file() {
+			failures.insert(track.path.to_string(), "file not found".to_string());
+			return None;
+		}
+		
+		let result = match *cache.get_or_insert_with(|| open_probe_cache(in_file, &args)) {
+			Some(ref cache) => cache.probe(track.file_path(), &prober),
+			None            => media_probe::Prober::probe(&prober, track.file_path())
+		};
+		match result {
+			Ok(info) => {
+				if info.duration_ms.is_none() {
+					failures.insert(track.path.to_string(), "no duration reported for the file".to_string());
+				}
+				info.duration_ms.map(track_duration::TrackDuration)
+			},
+			Err(e) => {
+				failures.insert(track.path.to_string(), e);
+				None
+			}
+		}
+	});
+	
+	if let Some(cache) = cache {
+		save_probe_cache(cache.as_ref());
+	}
+	if !failures.is_empty() {
+		eprintln!("WARNING: Couldn't probe the durations of {0} tracks in {1} (duration priority: {2})",
+		          failures.len(), in_file, priority.describe());
+		duration_source::note_probe_failures(xspf, &failures);
+	}
+	counts
+}
+
+/* Write the probe cache back to disk (if one was in use) */
+fn save_probe_cache(cache: Option<&probe_cache::ProbeCache>)
+{
+	if let Some(cache) = cache {
+		if let Err(e) = cache.save() {
+			eprintln!("WARNING: {}", e);
+		}
+	}
+}
+
+/* Print the technical details of each track, as reported by ffprobe */
+fn probe_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		
+		let cache = open_probe_cache(in_file, args);
+		let prober = media_probe::FfprobeProber;
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		for (i, track) in xspf.tracks.iter().enumerate() {
+			let result = match cache {
+				Some(ref cache) => cache.probe(track.file_path(), &prober),
+				None            => media_probe::Prober::probe(&prober, track.file_path())
+			};
+			
+			let line = match result {
+				Ok(info) => {
+					format!("  {0} | {1} | duration = {2:?} ms, codec = {3:?}, sample rate = {4:?}, channels = {5:?}",
+					        i, terminal_text::sanitize(track.path.as_str()), info.duration_ms, info.codec, info.sample_rate, info.channels)
+				},
+				Err(e) => {
+					format!("  {0} | {1} | ERROR: {2}", i, terminal_text::sanitize(track.path.as_str()), e)
+				}
+			};
+			
+			if let Err(why) = writeln!(out, "{}", line) {
+				eprintln!("ERROR: {}", why);
+				break;
+			}
+		}
+		finish_output_stream(&mut out);
+		
+		save_probe_cache(cache.as_ref());
+	}
+}
+
+/* Write the duration check results as text
+ * Helper for verify_mode()
+ */
+fn write_verify_report(out: &mut dyn Write, report: &duration_verify::VerifyReport) -> io::Result<()>
+{
+	writeln!(out, "Duration Check (tolerance = {:.1} secs):", report.tolerance_ms as f64 / 1000.0)?;
+	
+	let mismatches = report.mismatches();
+	if mismatches.is_empty() {
+		writeln!(out, "  All stored durations match")?;
+	}
+	for t in mismatches.iter() {
+		let stored = t.stored_ms.map_or("?".to_string(), |ms| track_duration::TrackDuration(ms).to_timecode());
+		let actual = t.actual_ms.map_or("?".to_string(), |ms| track_duration::TrackDuration(ms).to_timecode());
+		let delta = t.delta_ms().unwrap_or(0);
+		writeln!(out, "  {0:3} | {1} | stored {2}, actual {3} ({4}{5:.1} secs)",
+		         t.track_index + 1, terminal_text::sanitize(&t.filename), stored, actual,
+		         if delta >= 0 { "+" } else { "-" }, delta.abs() as f64 / 1000.0)?;
+	}
+	for t in report.tracks.iter() {
+		if let duration_verify::VerifyStatus::ProbeFailed(ref e) = t.status {
+			writeln!(out, "  {0:3} | {1} | couldn't get the file's duration - {2}",
+			         t.track_index + 1, terminal_text::sanitize(&t.filename), e)?;
+		}
+	}
+	
+	writeln!(out)?;
+	writeln!(out, "    Matched:      {}", report.count(&duration_verify::VerifyStatus::Match))?;
+	writeln!(out, "    Mismatched:   {}", mismatches.len())?;
+	writeln!(out, "    Missing:      {}  (file not found)", report.count(&duration_verify::VerifyStatus::Missing))?;
+	writeln!(out, "    No Duration:  {}  (none stored in the playlist)", report.count(&duration_verify::VerifyStatus::NoStoredDuration))?;
+	writeln!(out, "    Unprobed:     {}  (couldn't get the file's duration)", report.count(&duration_verify::VerifyStatus::ProbeFailed(String::new())))?;
+	Ok(())
+}
+
+/* Check the durations stored in the playlist against the files themselves (as probed)
+ * Any mismatches make the run fail, so that scripts can check for them.
+ */
+fn verify_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let tolerance_ms = match command_args::parse_option::<f64>(args, "--tolerance") {
+		Ok(Some(secs)) if secs >= 0.0 => (secs * 1000.0).round() as i64,
+		Ok(Some(_)) => {
+			eprintln!("ERROR: --tolerance can't be negative");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Ok(None) => duration_verify::DEFAULT_TOLERANCE_MS,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		
+		let cache = open_probe_cache(in_file, args);
+		let prober = media_probe::FfprobeProber;
+		let report = duration_verify::verify_durations(&xspf, tolerance_ms, &mut |track: &Track| {
+			let info = match cache {
+				Some(ref cache) => cache.probe(track.file_path(), &prober),
+				None            => media_probe::Prober::probe(&prober, track.file_path())
+			}?;
+			info.duration_ms.map(track_duration::TrackDuration)
+			                .ok_or_else(|| "no duration reported for the file".to_string())
+		});
+		save_probe_cache(cache.as_ref());
+		
+		for _ in report.mismatches() {
+			run_outcome::record_failure(duration_verify::MISMATCH_CATEGORY);
+		}
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&report) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert duration check results to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else if let Err(why) = write_verify_report(&mut out, &report) {
+			eprintln!("ERROR: Couldn't write duration check results - {}", why);
+		}
+		finish_output_stream(&mut out);
+	}
+}
+
+/* "cache <command> <in.xspf> [options]" - Maintenance of the probe cache */
+fn cache_command(args: &Vec<String>)
+{
+	let command = args.get(2).map(|s| s.as_str());
+	let in_file = match args.get(3) {
+		Some(f) => f,
+		None => {
+			eprintln!("ERROR: You need to supply a .xspf filename, whose probe cache should be used");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	let options = extract_command_args_list(args, 4);
+	
+	/* Only the probe cache options apply here, so a mistyped one mustn't go unnoticed (and end up pruning the default cache) */
+	let is_cache_option = |arg: &String| PROBE_CACHE_OPTIONS.iter().any(|name| arg == name || arg.starts_with(&format!("{}=", name)));
+	if let Some(unknown) = options.iter().find(|arg| !is_cache_option(arg)) {
+		eprintln!("ERROR: Unrecognised option for the cache command - {}", unknown);
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+	
+	match command {
+		Some("prune") => {
+			if let Some(cache) = open_probe_cache(in_file, &options) {
+				let removed = cache.prune();
+				println!("Pruned {0} entries from probe cache ({1} remaining)", removed, cache.len());
+				save_probe_cache(Some(&cache));
+			}
+		},
+		other => {
+			eprintln!("ERROR: Unrecognised cache command - {:?}", other);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* ................................ */
+
+/* Report tracks whose filenames only differ by encoding artifacts (spaces vs underscores, case, etc.) */
+fn near_dupes_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(mut xspf) = load_playlist(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		let report = near_dupes::find_near_dupes(&xspf);
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&report) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert near-duplicates report to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else if let Err(why) = report.write_text(&mut out) {
+			eprintln!("ERROR: Couldn't write near-duplicates report - {}", why);
+		}
+		finish_output_stream(&mut out);
+		
+		/* Suggested renames */
+		if let Some(renames_file) = command_args::get_option(args, "--renames") {
+			let result = File::create(renames_file).and_then(|mut f| report.write_renames(&xspf, &mut f));
+			match result {
+				Ok(_)    => println!("\nWrote suggested renames to {}", renames_file),
+				Err(why) => eprintln!("ERROR: Couldn't write suggested renames to {0:?} - {1}", renames_file, why)
+			}
+		}
+	}
+}
+
+/* ................................ */
+
+/* Report tracks whose files have exactly the same contents (see content_dupes) */
+fn dupes_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		
+		/* The same file listed several times isn't a duplicate of itself */
+		let mut seen: std::collections::HashSet<PathBuf> = std::collections::HashSet::new();
+		let mut track_indices: Vec<usize> = Vec::new();
+		for (track_idx, track) in xspf.tracks.iter().enumerate() {
+			if seen.insert(track.file_path().to_path_buf()) {
+				track_indices.push(track_idx);
+			}
+		}
+		
+		let contents = content_dupes::FileContents {
+			paths: track_indices.iter().map(|&i| xspf.tracks[i].file_path().to_path_buf()).collect(),
+		};
+		let options = content_dupes::DupeOptions { fast: command_args::has_flag(args, "--fast") };
+		
+		/* Check that deleting duplicates can go ahead before spending time finding them */
+		let dedupe = command_args::has_flag(args, "--dedupe");
+		if dedupe {
+			if options.fast {
+				eprintln!("ERROR: --dedupe only deletes files confirmed to be duplicates, so can't be used with --fast");
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+			if command_args::has_flag(args, "--interactive") && command_args::get_option(args, "--replay").is_none() && !io::stdin().is_terminal() {
+				eprintln!("ERROR: --interactive needs to be run from a terminal. Use --yes to delete all the duplicates without asking");
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		let mut report = content_dupes::find_dupes(&contents, &options);
+		
+		/* Refer to the tracks by their index in the playlist */
+		for group in report.groups.iter_mut() {
+			for item in group.items.iter_mut() {
+				*item = track_indices[*item];
+			}
+		}
+		for unreadable in report.unreadable.iter_mut() {
+			unreadable.item = track_indices[unreadable.item];
+		}
+		
+		/* Get output stream to write to */
+		let mut out : Box<dyn Write> = open_output_stream(out_file);
+		
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&report) {
+				Ok(j) => {
+					if let Err(why) = writeln!(out, "{}", j) {
+						eprintln!("ERROR: Couldn't write JSON output - {}", why);
+					}
+				},
+				Err(e) => {
+					eprintln!("Couldn't convert duplicates report to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else {
+			let names: Vec<String> = xspf.tracks.iter().map(|t| t.path.to_string()).collect();
+			if let Err(why) = report.write_text(&mut out, &names) {
+				eprintln!("ERROR: Couldn't write duplicates report - {}", why);
+			}
+		}
+		finish_output_stream(&mut out);
+		
+		if dedupe {
+			dedupe_files(&xspf, &report, args);
+		}
+	}
+}
+
+/* Delete the extra copies of duplicate files (keeping the first copy listed in the playlist)
+ * deciding which to delete with "--yes" (all of them), "--interactive" (asking about each one),
+ * or "--replay=<file>" (as recorded by "--record=<file>" on an earlier run)
+ * Helper for dupes_mode()
+ * ! This function will terminate the process if the decisions file can't be read
+ */
+fn dedupe_files(xspf: &XspfPlaylist, report: &content_dupes::DupesReport, args: &Vec<String>)
+{
+	use interactive_resolve::{Candidate, Decision, DecisionsFile, LineInput, Prompter, Resolver};
+	
+	let candidates: Vec<Candidate> = report.groups.iter()
+		.filter(|group| group.confirmed)
+		.flat_map(|group| {
+			let original = &xspf.tracks[group.items[0]];
+			let conflicts_with = format!("track {0} ({1})", group.items[0] + 1, original.path);
+			group.items[1..].iter().map(move |&track_idx| Candidate::for_file(xspf.tracks[track_idx].file_path(), conflicts_with.clone()))
+		})
+		.collect();
+	if candidates.is_empty() {
+		println!("\nNo duplicate files to delete");
+		return;
+	}
+	
+	let stdin = io::stdin();
+	let mut resolver = if let Some(replay_file) = command_args::get_option(args, "--replay") {
+		match DecisionsFile::load(Path::new(replay_file)) {
+			Ok(decisions) => Resolver::replay(&decisions),
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+	else if command_args::has_flag(args, "--interactive") {
+		println!("\nDeciding what to do with {} duplicate files (type a letter, then Enter):", candidates.len());
+		Resolver::Interactive(Prompter::new(LineInput::new(stdin.lock()), io::stdout()))
+	}
+	else if command_args::has_flag(args, "--yes") {
+		Resolver::DeleteAll
+	}
+	else {
+		println!("\n{} duplicate files would be deleted (use --interactive to choose which, or --yes to delete them all):", candidates.len());
+		for candidate in candidates.iter() {
+			println!("   {}", terminal_text::sanitize(&candidate.path));
+		}
+		return;
+	};
+	
+	/* Decisions to add to (if recording them) */
+	let record_file = command_args::get_option(args, "--record").map(PathBuf::from);
+	let mut recorded = match record_file {
+		Some(ref path) => {
+			match DecisionsFile::load_for_append(path) {
+				Ok(decisions) => Some(decisions),
+				Err(e) => {
+					eprintln!("ERROR: {}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		},
+		None => None
+	};
+	
+	let mut deleted = 0;
+	let mut kept = 0;
+	for candidate in candidates.iter() {
+		let decision = match resolver.decide(candidate) {
+			Ok((decision, true)) => decision,
+			Ok((decision, false)) => {
+				eprintln!("WARNING: No decision recorded for {} - keeping it", terminal_text::sanitize(&candidate.path));
+				decision
+			},
+			Err(e) => {
+				eprintln!("ERROR: Couldn't read answer - {}", e);
+				break;
+			}
+		};
+		if let Some(ref mut decisions) = recorded {
+			decisions.record(&candidate.path, decision);
+		}
+		
+		match decision {
+			Decision::Keep => kept += 1,
+			Decision::Delete => {
+				match fs::remove_file(&candidate.path) {
+					Ok(_) => {
+						run_journal::record_action("deleted", format!("{0} (same as {1})", candidate.path, candidate.conflicts_with));
+						deleted += 1;
+					},
+					Err(e) => {
+						eprintln!("ERROR: Couldn't delete {0} - {1}", terminal_text::sanitize(&candidate.path), e);
+						kept += 1;
+					}
+				}
+			}
+		}
+	}
+	
+	if let (Some(path), Some(decisions)) = (record_file, recorded) {
+		if let Err(e) = decisions.save(&path) {
+			eprintln!("ERROR: Couldn't write decisions file {0:?} - {1}", path, e);
+		}
+	}
+	
+	println!("\nDeleted {0} duplicate files, kept {1}", deleted, kept);
+	if deleted > 0 {
+		println!("NOTE: The playlist still lists the deleted files - remove them from it before using it again");
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Options for naming the output files (i.e. "--template=<template>"), and dealing with tracks
+ * that would be given the same output filename (i.e. "--collision-style=<style>", or "--strict" as
+ * shorthand for "--collision-style=fail"), along with numbering them after any files already in
+ * the output folder (i.e. "--continue-numbering", for copy/convert)
+ */
+const NAMING_OPTIONS: [&'static str; 4] = ["--collision-style", "--template", "--strict", "--continue-numbering"];
+
+/* Find the highest track number already used in the output folder, if "--continue-numbering" was given
+ * (so that the new files can be numbered after it)
+ * Helper for copy_files_mode() and convert_files_mode()
+ * ! This function will terminate the process if the output folder can't be read
+ */
+fn get_continued_numbering(out_path: &str, args: &Vec<String>) -> Option<dest_naming::ExistingNumbering>
+{
+	if !command_args::has_flag(args, NAMING_OPTIONS[3]) {
+		return None;
+	}
+	match dest_naming::ExistingNumbering::scan(Path::new(out_path)) {
+		Ok(numbering) => {
+			if numbering.highest > 0 {
+				println!("Continuing the track numbering after {0} (the highest already in {1:?})", numbering.highest, out_path);
+			}
+			Some(numbering)
+		},
+		Err(e) => {
+			eprintln!("ERROR: Couldn't look for existing tracks in {0:?} (for --continue-numbering) - {1}", out_path, e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Get output filenames for copying or converting all tracks (see dest_naming), following the template given by "--template",
+ * and renaming any that collide (in the style given by "--collision-style")
+ * - continue_after: Track numbers already used in the output folder, to number the tracks after (see get_continued_numbering())
+ * Helper for copy_files_mode(), move_files_mode(), convert_files_mode(), bundle_mode(), and webplayer_mode()
+ * ! This function will terminate the process if the template or style are invalid, or names collide with "--collision-style=fail"/"--strict"
+ */
+fn plan_destination_filenames(xspf: &XspfPlaylist, extension_override: Option<TrackExtension>, args: &Vec<String>,
+                              continue_after: Option<dest_naming::ExistingNumbering>)
+	-> dest_naming::NamingPlan
+{
+	let strict = command_args::has_flag(args, NAMING_OPTIONS[2]);
+	let style = match command_args::get_option(args, NAMING_OPTIONS[0]).map(|name| name.parse()) {
+		Some(Ok(dest_naming::CollisionStyle::Fail)) => dest_naming::CollisionStyle::Fail,
+		Some(Ok(_)) if strict => {
+			eprintln!("ERROR: --strict can only be used with --collision-style=fail (it stops on any collisions, instead of renaming)");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Some(Ok(style)) => style,
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		None if strict => dest_naming::CollisionStyle::Fail,
+		None => dest_naming::CollisionStyle::default()
+	};
+	let template = match command_args::get_option(args, NAMING_OPTIONS[1]).map(|template| template.parse()) {
+		Some(Ok(template)) => template,
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		None => dest_naming::NameTemplate::default()
+	};
+	
+	let (first_number, track_index_width) = match continue_after {
+		Some(existing) => (existing.highest, existing.width_for(xspf.len())),
+		None           => (0, xspf.track_index_width())
+	};
+	let names: Vec<dest_naming::DestinationName> = xspf.tracks.iter().enumerate()
+		.map(|(track_idx, track)| dest_naming::DestinationName::for_track(track, first_number + track_idx, track_index_width,
+		                                                                  extension_override.clone(), &template))
+		.collect();
+	
+	match dest_naming::plan_names(&names, style) {
+		Ok(plan) => plan,
+		Err(collisions) => {
+			eprintln!("ERROR: {} output filenames are used by more than one track (and --collision-style=fail or --strict was given):", collisions.len());
+			for collision in collisions.iter() {
+				eprintln!("   {}", terminal_text::sanitize(&collision.to_string()));
+				for &track_idx in collision.track_indices.iter() {
+					eprintln!("      {0:>4}: {1}", track_idx + 1, terminal_text::sanitize(&xspf.tracks[track_idx].path.to_string()));
+				}
+			}
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Create any subdirectories of the output folder that the files go into (i.e. when the template has them)
+ * Helper for copy_files_mode(), move_files_mode(), and convert_files_mode()
+ * ! This function will terminate the process if any of them can't be created
+ */
+fn create_destination_subdirectories(out_path: &str, plan: &dest_naming::NamingPlan)
+{
+	for dir in plan.subdirectories() {
+		let dir_path = output_file_path(out_path, &dir);
+		if let Err(e) = fs::create_dir_all(&dir_path) {
+			eprintln!("ERROR: Couldn't create output folder {0:?} - {1}", dir_path, e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Write the report of tracks that had to be renamed into the output folder (if any were)
+ * Helper for copy_files_mode() and convert_files_mode()
+ */
+fn write_collisions_report(out_path: &str, plan: &dest_naming::NamingPlan)
+{
+	if plan.renames.is_empty() {
+		return;
+	}
+	
+	let report_path = output_file_path(out_path, dest_naming::COLLISIONS_REPORT_FILENAME);
+	println!("{0} tracks were renamed, as their names collided with other tracks (see {1})\n", plan.renames.len(), report_path);
+	
+	let result = File::create(&report_path).and_then(|mut f| plan.write_report(&mut f));
+	if let Err(why) = result {
+		eprintln!("ERROR: Couldn't write report of renamed tracks to {0:?} - {1}", report_path, why);
+	}
+	for rename in plan.renames.iter() {
+		run_journal::record_action("renamed", format!("{0} -> {1}", rename.original, rename.renamed));
+	}
+}
+
+/* Path of a file to write into the output directory (see track_path) */
+fn output_file_path(out_dir: &str, filename: &str) -> String
+{
+	let dir = track_path::TrackPath::from_native(Path::new(out_dir));
+	dir.join(filename).native().to_string_lossy().into_owned()
+}
+
+/* ................................ */
+
+/* Copy a track's file to dst_path
+ * (via a temp file, so that dst_path only appears once it's complete),
+ * then set the modification time of the copy (see file_timestamps)
+ * Helper for convert_files_mode()
+ * > returns success of the copy operation
+ */
+fn copy_track(track_idx: usize, track: &Track, dst_path: &str, stamper: &mut file_timestamps::Stamper,
+              progress: &progress_events::ProgressReporter)
+	-> bool
+{
+	let copy_result = parallel_copy::copy_file(track.file_path(), Path::new(dst_path));
+	finish_track_copy(track_idx, track, dst_path, copy_result, stamper, progress)
+}
+
+/* Report how copying a track's file to dst_path went, setting the modification time of the copy if it worked
+ * Helper for copy_track() and copy_files_mode()
+ * > returns success of the copy operation
+ */
+fn finish_track_copy(track_idx: usize, track: &Track, dst_path: &str, copy_result: io::Result<()>,
+                     stamper: &mut file_timestamps::Stamper, progress: &progress_events::ProgressReporter)
+	-> bool
+{
+	let src_path = track.file_path();
+	match copy_result {
+		Ok(_)  => {
+			stamper.stamp(track_idx, track, Path::new(dst_path));
+			progress.print_track(track_idx, &format!("Copied {src} => <outdir>/{dst} {orig}",
+			                     src=terminal_text::sanitize(&src_path.display().to_string()), dst=terminal_text::sanitize(dst_path),
+			                     orig=original_position_note(track)));
+			run_journal::record_action("copied", format!("{0} -> {1}", src_path.display(), dst_path));
+			
+			/* Report success */
+			return true;
+		},
+		Err(e) => {
+			progress.print_track_error(track_idx, &format!("! ERROR: Couldn't copy {src} => <outdir>/{dst}!",
+			                           src=terminal_text::sanitize(&src_path.display().to_string()), dst=terminal_text::sanitize(dst_path)));
+			eprintln!("  Reason: {}", e);
+			run_journal::record_action("copy failed", format!("{0} -> {1} - {2}", src_path.display(), dst_path, e));
+			run_outcome::record_failure(run_outcome::COPY_FAILED_CATEGORY);
+			
+			/* XXX: Should we stop instead? We don't have any other way to keep going otherwise! */
+			//exit_run(run_outcome::EXIT_FAILED);
+			
+			/* Report failure */
+			return false;
+		}
+	}
+}
+
+/* Where the track was in the playlist it was read from (1-based, as players show it), for the lines about each track
+ * (so they can be matched up with the original playlist, even after it's been filtered or sorted)
+ */
+fn original_position_note(track: &Track) -> String
+{
+	format!("(#{} in the playlist)", track.original_index + 1)
+}
+
+/* Convert a track from one format to another, based on the filename extensions
+ * (via a temp file, so that dst_path only appears once it's complete)
+ * Helper for convert_files_mode()
+ * - video_source: Picture to go with the audio, when converting to a video format (None for audio-only formats)
+ * - overwrite: Tell ffmpeg to overwrite existing files (i.e. "--overwrite") instead of refusing to
+ * > returns success of the convert operation
+ */
+fn convert_track(track_idx: usize, track: &Track, dst_path: &str, ffmpeg_args: &Vec<String>,
+                 video_source: Option<&ffmpeg_command::VideoSource>, overwrite: bool,
+                 progress: &progress_events::ProgressReporter)
+	-> bool
+{
+	let src_path = track.file_path();
+	
+	/* Clear out any temp file left behind by an earlier run (otherwise ffmpeg refuses to overwrite it) */
+	convert_resume::discard_partial(Path::new(dst_path));
+	let partial_path = convert_resume::partial_path(Path::new(dst_path));
+	let ffmpeg_args_for_file = match video_source {
+		Some(source) => ffmpeg_command::video_convert_args(src_path, &partial_path, source, ffmpeg_args, overwrite),
+		None         => ffmpeg_command::convert_args(src_path, &partial_path, ffmpeg_args, overwrite)
+	};
+	
+	/* Invoke ffmpeg to convert this file... */
+	progress.print_track(track_idx, &format!("Converting {src_path:?} -> {dst_path:?} {orig}...",
+	                     src_path = src_path.display().to_string(), dst_path = dst_path, orig = original_position_note(track)));
+	// {
+	// 	println!("      Args = {ffmpeg_args:?}\n", ffmpeg_args = ffmpeg_args_for_file); // debug only
+	// }
+	
+	let ffmpeg_convert_command
+		= Command::new("ffmpeg")
+			.args(ffmpeg_args_for_file)
+			.stdin(Stdio::null())
+			.output()
+			.expect("Failed to find and run ffmpeg");
+			
+	if ffmpeg_convert_command.status.success() {
+		if let Err(e) = convert_resume::finish_partial(Path::new(dst_path)) {
+			progress.print_track_error(track_idx, &format!("ERROR: Couldn't move finished conversion into place at {dst_path:?} - {e}\n\n",
+			                           dst_path = dst_path, e = e));
+			convert_resume::discard_partial(Path::new(dst_path));
+			run_journal::record_action("convert failed", format!("{0} -> {1} - {2}", src_path.display(), dst_path, e));
+			run_outcome::record_failure(run_outcome::CONVERT_FAILED_CATEGORY);
+			return false;
+		}
+		
+		progress.print_track(track_idx, &format!("Success for {dst_path:?}\n\n", dst_path = dst_path));
+		run_journal::record_action("converted", format!("{0} -> {1}", src_path.display(), dst_path));
+		
+		/* Report success */
+		return true;
+	}
+	else {
+		convert_resume::discard_partial(Path::new(dst_path));
+		progress.print_track_error(track_idx, &format!("ERROR: Conversion failed for {src_path:?} -> {dst_path:?}!\n\n",
+		                           src_path = src_path.display().to_string(), dst_path = dst_path));
+		run_journal::record_action("convert failed", format!("{0} -> {1}", src_path.display(), dst_path));
+		run_outcome::record_failure(run_outcome::CONVERT_FAILED_CATEGORY);
+		
+		eprintln!("StdError Output ==============================================");
+		io::stderr().write_all(&ffmpeg_convert_command.stderr).unwrap();
+		eprintln!("==============================================================\n\n\n");
+		/* Don't abort... try to carry on... */
+		
+		/* Report failure */
+		return false;
+	}
+}
+
+/* Render an image of the waveform of a track, named after its destination file (i.e. <outdir>/<dst_name>.png)
+ * Helper for copy_files_mode() and convert_files_mode()
+ * > returns the filename of the image generated (for the caller to report)
+ */
+fn export_waveform_image(src_path: &Path, out_path: &str, dst_filename: &str,
+                         options: &ffmpeg_command::WaveformOptions)
+	-> Result<String, String>
+{
+	let image_filename = match Path::new(dst_filename).file_stem() {
+		Some(stem) => format!("{}.png", stem.to_string_lossy()),
+		None       => format!("{}.png", dst_filename)
+	};
+	let image_path = Path::new(out_path).join(&image_filename);
+	
+	let output = Command::new("ffmpeg")
+	                .args(ffmpeg_command::waveform_image_args(src_path, &image_path, options))
+	                .output()
+	                .map_err(|e| format!("Couldn't run ffmpeg - {}", e))?;
+	
+	if output.status.success() {
+		Ok(image_filename)
+	}
+	else {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		Err(format!("Couldn't render waveform image for '{0}' - {1}",
+		            src_path.display(), stderr.trim().lines().last().unwrap_or("ffmpeg failed")))
+	}
+}
+
+/* Print summary of the waveform images generated (and any that failed)
+ * Helper for copy_files_mode() and convert_files_mode()
+ */
+fn print_waveform_images_report(images: &Vec<String>, problems: &diagnostics::Diagnostics)
+{
+	println!("\nGenerated {} waveform images:", images.len());
+	for image in images.iter() {
+		println!("   {}", image);
+	}
+	
+	if !problems.is_empty() {
+		println!("\nSkipped {} waveform images:", problems.len());
+		let _ = problems.write_text(&mut io::stdout());
+	}
+}
+
+/* Report how many .nfo sidecars got written, and any that couldn't be
+ * Helper for copy_files_mode() and convert_files_mode()
+ */
+fn print_nfo_report(nfo_writer: &nfo_sidecar::SidecarWriter)
+{
+	println!("\nWrote {} .nfo sidecars", nfo_writer.written);
+	if !nfo_writer.diagnostics.is_empty() {
+		eprintln!("\nWARNING: Couldn't write {} .nfo sidecars:", nfo_writer.diagnostics.len());
+		let _ = nfo_writer.diagnostics.write_text(&mut io::stderr());
+		run_outcome::record_diagnostics(&nfo_writer.diagnostics);
+	}
+}
+
+/* Report how the "--post-cmd" command went for the exported files
+ * Helper for copy_files_mode() and convert_files_mode()
+ */
+fn print_post_command_report(runner: &post_command::PostCommandRunner)
+{
+	println!("\nPost-command succeeded for {0} tracks ({1})", runner.succeeded, runner.command.template);
+	if !runner.diagnostics.is_empty() {
+		eprintln!("\nWARNING: Post-command failed for {} tracks:", runner.diagnostics.len());
+		let _ = runner.diagnostics.write_text(&mut io::stderr());
+		run_outcome::record_diagnostics(&runner.diagnostics);
+	}
+	if runner.aborted() {
+		eprintln!("ERROR: Stopped after the post-command failed (--post-cmd-fatal) - the remaining tracks weren't processed");
+	}
+}
+
+/* Report tracks whose copies kept the source file's time (with "--stamp-from-date"), and any times that couldn't be set
+ * Helper for copy_files_mode() and convert_files_mode()
+ */
+fn print_timestamps_report(stamper: &file_timestamps::Stamper)
+{
+	if stamper.undated > 0 {
+		println!("\n{} tracks have no session date, so their copies kept the source file's modification time", stamper.undated);
+	}
+	if !stamper.diagnostics.is_empty() {
+		eprintln!("\nWARNING: Couldn't set the modification times of {} files:", stamper.diagnostics.len());
+		let _ = stamper.diagnostics.write_text(&mut io::stderr());
+		run_outcome::record_diagnostics(&stamper.diagnostics);
+	}
+}
+
+/* ................................ */
+
+/* Copy all files listed in playlist to a single folder */
+fn copy_files_mode(in_file: &str, out_path: Option<&String>, args: &Vec<String>)
+{
+	if let Some(out) = out_path {
+		println!("Copy Files infile='{0}', outdir={1:?}", in_file, out_path);
+		if let Some(mut xspf) = load_playlist_for_files(in_file) {
+			apply_track_filter_options(&mut xspf, args);
+			let waveform_options = get_waveform_options(args);
+			let mut nfo_writer = get_nfo_writer(args);
+			let mut post_cmd = get_post_command_runner(args);
+			let mut progress = open_progress_reporter(args);
+			let copy_jobs = match command_args::parse_option::<usize>(args, "--jobs") {
+				Ok(jobs) => jobs.unwrap_or(parallel_copy::DEFAULT_COPY_JOBS),
+				Err(e) => {
+					eprintln!("ERROR: {}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			};
+			
+			/* Copies get the source file's modification time (or the session's date) */
+			let stamp_from_date = command_args::has_flag(args, "--stamp-from-date");
+			let mut stamper = file_timestamps::Stamper::new(if stamp_from_date {
+				file_timestamps::StampMode::SessionDate
+			}
+			else {
+				file_timestamps::StampMode::PreserveSource
+			});
+			
+			/* Files already copied by an earlier run get left alone (unless "--force" is given)
+			 * NOTE: Copies stamped with the session date can only be compared by size
+			 */
+			let up_to_date_check = if command_args::has_flag(args, "--force") {
+				parallel_copy::UpToDateCheck::Never
+			}
+			else if stamp_from_date {
+				parallel_copy::UpToDateCheck::Size
+			}
+			else {
+				parallel_copy::UpToDateCheck::SizeAndTime
+			};
+			
+			/* Work out what to name each file (before anything gets written, in case the names collide) */
+			let continue_numbering = command_args::has_flag(args, NAMING_OPTIONS[3]);
+			let naming = plan_destination_filenames(&xspf, None, args, get_continued_numbering(out, args));
+			
+			/* Ensure outdir exists, and that nothing else is writing into it */
+			let dst_path_root = ensure_output_directory_exists(out);
+			let _lock = lock_output_directory(dst_path_root, args);
+			write_collisions_report(out, &naming);
+			create_destination_subdirectories(out, &naming);
+			
+			/* Work out where each track gets copied to/from
+			 * (filenames need to have enough metadata to figure out what's going on)
+			 */
+			let jobs: Vec<parallel_copy::CopyJob> = xspf.tracks.iter().enumerate()
+				.map(|(track_idx, track)| parallel_copy::CopyJob {
+					track_index: track_idx,
+					src: track.file_path().to_path_buf(),
+					dst: PathBuf::from(output_file_path(out, &naming.filenames[track_idx])),
+					up_to_date_check: up_to_date_check,
+				})
+				.collect();
+			
+			/* Copy the tracks to the folder (several at a time), handling each one in playlist order once it's copied */
+			let mut dest_filenames : Vec<String> = Vec::new();
+			let mut results = run_outcome::TrackResults::default();
+			let mut waveform_images : Vec<String> = Vec::new();
+			let mut waveform_problems = diagnostics::Diagnostics::new();
+			
+			progress.set_line_options(track_line_options(&xspf, args, false));
+			progress.run_started("copy", xspf.len(), playlist_total_bytes(&xspf));
+			
+			parallel_copy::copy_files(&jobs, copy_jobs, |job, copy_result| {
+				let track_idx = job.track_index;
+				let track = &xspf.tracks[track_idx];
+				let dst_filename = naming.filenames[track_idx].clone();
+				let src_path = track.file_path();
+				let dst_path = job.dst.to_string_lossy().into_owned();
+				
+				progress.track_started(track_idx, &src_path.to_string_lossy(), &dst_path);
+				
+				/* Files that were already there just need listing in the manifest */
+				let copy_result = match copy_result {
+					Ok(parallel_copy::CopyOutcome::UpToDate) => {
+						progress.print_track(track_idx, &format!("Skipped (up to date) <outdir>/{}", terminal_text::sanitize(&dst_filename)));
+						run_journal::record_action("up to date", dst_path);
+						dest_filenames.push(dst_filename);
+						results.skipped += 1;
+						progress.track_finished(track_idx, TrackStatus::Skipped, None);
+						return true;
+					},
+					result => result.map(|_| ())
+				};
+				
+				/* Report how the copy went */
+				if finish_track_copy(track_idx, track, &dst_path, copy_result, &mut stamper, &progress) {
+					/* Write metadata sidecar to go alongside it */
+					if let Some(ref mut nfo_writer) = nfo_writer {
+						nfo_writer.write(track_idx, track, Path::new(&dst_path));
+					}
+					
+					/* Render waveform image to go alongside it */
+					if let Some(ref options) = waveform_options {
+						match export_waveform_image(src_path, out, &dst_filename, options) {
+							Ok(image) => {
+								progress.print_track(track_idx, &format!("Rendered waveform => <outdir>/{}", image));
+								waveform_images.push(image);
+							},
+							Err(e)    => waveform_problems.warn_track(WAVEFORM_CATEGORY, track_idx, e)
+						}
+					}
+					
+					/* Success - Note this as one of the successful files */
+					dest_filenames.push(dst_filename);
+					results.ok += 1;
+					progress.track_finished(track_idx, TrackStatus::Ok, file_size(Path::new(&dst_path)));
+					
+					/* Run the user's command on it (stopping any more copies if it says so) */
+					if let Some(ref mut post_cmd) = post_cmd {
+						return post_cmd.run(track_idx, &post_command::TrackVars::for_track(track, track_idx, &dst_path));
+					}
+				}
+				else {
+					results.add_failed(src_path);
+					progress.track_finished(track_idx, TrackStatus::Failed, None);
+				}
+				true
+			});
+			
+			/* Dump list of copied files to <out_path>/<playlist_filename>.m3u
+			 * (i.e. a playable playlist, that also acts as a manifest of the set of files copied)
+			 */
+			write_copied_files_manifest(in_file, out, &dest_filenames, &favorite_track_markers(&xspf, &naming.filenames), args,
+			                            continue_numbering);
+			let summary = progress.run_finished();
+			println!("\nCopied {0} of {1} tracks ({2} already up to date, {3} failed)",
+			         summary.succeeded, xspf.len(), summary.skipped, summary.failed);
+			record_run_summary(summary);
+			run_outcome::record_track_results(results);
+			
+			print_timestamps_report(&stamper);
+			
+			if let Some(ref nfo_writer) = nfo_writer {
+				print_nfo_report(nfo_writer);
+			}
+			
+			if waveform_options.is_some() {
+				print_waveform_images_report(&waveform_images, &waveform_problems);
+				run_outcome::record_diagnostics(&waveform_problems);
+			}
+			
+			if let Some(ref post_cmd) = post_cmd {
+				print_post_command_report(post_cmd);
+				if post_cmd.aborted() {
+					drop(_lock);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+	}
+	else {
+		eprintln!("ERROR: The third argument should specify the directory to copy the source files to");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+}
+
+/* ................................ */
+
+/* Print what an earlier (interrupted) move got through, for the files this run will produce
+ * Helper for move_files_mode()
+ */
+fn print_move_resume_summary(journal: &track_move::MoveJournal, filenames: &[String], delete_sources: bool)
+{
+	let states = [track_move::MoveState::Copied, track_move::MoveState::Verified, track_move::MoveState::SourceDeleted];
+	let counts: Vec<(track_move::MoveState, usize)> = states.iter()
+		.map(|&state| (state, filenames.iter().filter(|f| journal.get(f).map(|r| r.state) == Some(state)).count()))
+		.filter(|&(_, count)| count > 0)
+		.collect();
+	
+	if !counts.is_empty() {
+		let described: Vec<String> = counts.iter().map(|(state, count)| format!("{0} {1}", count, state)).collect();
+		println!("Resuming an earlier move (from {0:?}): {1}", journal.path(), described.join(", "));
+		if delete_sources && counts.iter().any(|(state, _)| *state != track_move::MoveState::SourceDeleted) {
+			println!("   Tracks that were copied/verified get checked again before their source files are deleted");
+		}
+	}
+}
+
+/* Move the files named in the playlist to the nominated folder (renamed as for copy_files_mode())
+ * Each file is copied, verified, and only then has its source deleted - with every step recorded
+ * in a journal, so that an interrupted run can be picked up again (see track_move)
+ */
+fn move_files_mode(in_file: &str, out_path: Option<&String>, args: &Vec<String>)
+{
+	let out = match out_path {
+		Some(out) => out,
+		None => {
+			eprintln!("ERROR: The third argument should specify the directory to move the source files to");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	let options = track_move::MoveOptions {
+		delete_sources: !command_args::has_flag(args, "--no-delete"),
+	};
+	
+	println!("Move Files infile='{0}', outdir={1:?}", in_file, out);
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		let mut progress = open_progress_reporter(args);
+		
+		/* Work out what to name each file (before anything gets written, in case the names collide) */
+		let naming = plan_destination_filenames(&xspf, None, args, None);
+		
+		/* Ensure outdir exists, and that nothing else is writing into it */
+		let dst_path_root = ensure_output_directory_exists(out);
+		let _lock = lock_output_directory(dst_path_root, args);
+		write_collisions_report(out, &naming);
+		create_destination_subdirectories(out, &naming);
+		
+		let mut journal = match track_move::MoveJournal::load(dst_path_root) {
+			Ok(journal) => journal,
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		};
+		print_move_resume_summary(&journal, &naming.filenames, options.delete_sources);
+		
+		/* Report how the files still to do will get moved (renaming only works within a filesystem)
+		 * NOTE: Files started on by an earlier run carry on being moved the same way
+		 */
+		let methods: Vec<track_move::MoveMethod> = xspf.tracks.iter().zip(naming.filenames.iter())
+			.filter_map(|(track, filename)| {
+				match journal.get(filename).filter(|r| Path::new(&r.source) == track.file_path()) {
+					Some(record) if record.state.is_finished(options.delete_sources) => None,
+					Some(record) => Some(record.method),
+					None => Some(track_move::choose_method(track.file_path(), Path::new(&output_file_path(out, filename)), &options))
+				}
+			})
+			.collect();
+		let renames = methods.iter().filter(|&&m| m == track_move::MoveMethod::Rename).count();
+		if !options.delete_sources {
+			println!("Dry move (--no-delete) - Files get copied and verified, but the source files are kept");
+		}
+		else if renames > 0 {
+			println!("{0} of the {1} tracks to move are on the same filesystem as {2:?} - these just get renamed",
+			         renames, methods.len(), out);
+		}
+		if methods.len() > renames {
+			println!("{0} of the {1} tracks to move are on a different filesystem - these get copied and verified (size + hash){2}",
+			         methods.len() - renames, methods.len(),
+			         if options.delete_sources { ", before their source files are deleted" } else { "" });
+		}
+		
+		/* Loop over tracks moving them to the folder */
+		let mut dest_filenames : Vec<String> = Vec::new();
+		let (mut moved, mut kept, mut already_done) = (0, 0, 0);
+		let mut results = run_outcome::TrackResults::default();
+		
+		progress.run_started("move", xspf.len(), playlist_total_bytes(&xspf));
+		
+		for (track_idx, track) in xspf.tracks.iter().enumerate() {
+			let dst_filename = naming.filenames[track_idx].clone();
+			let src_path = track.file_path();
+			let dst_path = output_file_path(out, &dst_filename);
+			let size = file_size(src_path);
+			
+			progress.track_started(track_idx, &src_path.to_string_lossy(), &dst_path);
+			
+			let src_display = src_path.display().to_string();
+			let src_name = terminal_text::sanitize(&src_display);
+			match track_move::move_file(&mut journal, &dst_filename, src_path, Path::new(&dst_path), &options) {
+				Ok(outcome) => {
+					if outcome.resumed_from.is_some_and(|state| state.is_finished(options.delete_sources)) {
+						println!("   Already done - {0:?}", dst_filename);
+						already_done += 1;
+						progress.track_finished(track_idx, TrackStatus::Skipped, None);
+					}
+					else {
+						let (action, verb) = match (outcome.state, outcome.method) {
+							(track_move::MoveState::Verified, _) => ("copied", "Copied (source kept)"),
+							(_, track_move::MoveMethod::Rename)  => ("moved", "Renamed"),
+							(_, track_move::MoveMethod::Copy)    => ("moved", "Moved"),
+						};
+						println!("   {verb} {src} => <outdir>/{dst}", verb=verb, src=src_name, dst=terminal_text::sanitize(&dst_filename));
+						run_journal::record_action(action, format!("{0} -> {1}", src_path.display(), dst_path));
+						if outcome.state == track_move::MoveState::Verified { kept += 1; } else { moved += 1; }
+						progress.track_finished(track_idx, TrackStatus::Ok, size.or_else(|| file_size(Path::new(&dst_path))));
+					}
+					dest_filenames.push(dst_filename);
+				},
+				Err(e) => {
+					eprintln!("! ERROR: Couldn't move {0} - {1}", src_name, terminal_text::sanitize(&e));
+					run_journal::record_action("move failed", format!("{0} -> {1} - {2}", src_path.display(), dst_path, e));
+					run_outcome::record_failure(run_outcome::MOVE_FAILED_CATEGORY);
+					results.add_failed(src_path);
+					progress.track_finished(track_idx, TrackStatus::Failed, None);
+				}
+			}
+		}
+		
+		/* Dump list of moved files to <out_path>/<playlist_filename>.m3u (as for copy) */
+		write_copied_files_manifest(in_file, out, &dest_filenames, &favorite_track_markers(&xspf, &naming.filenames), args, false);
+		record_run_summary(progress.run_finished());
+		
+		println!("\nMoved {0} tracks, copied {1} (sources kept), {2} already done, {3} failed", moved, kept, already_done, results.failed);
+		let failed = results.failed;
+		results.ok = moved + kept;
+		results.skipped = already_done;
+		run_outcome::record_track_results(results);
+		if failed > 0 {
+			println!("   Source files of the tracks that failed were left alone. Run again to retry them.");
+			drop(_lock);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* ................................ */
+
+/* Similar to copy, but converts all the files to the specified format using FFMPEG */
 fn convert_files_mode(in_file: &str, out_path: &str, convert_mode: &str, args: &Vec<String>)
 {
-	println!("Convert Files infile='{0}', outdir={1:}", in_file, out_path);
+	println!("Convert Files infile='{0}', outdir={1:}", in_file, out_path);
+	
+	/* Check that FFMPEG works/is available... */
+	let ffmpeg_testrun_result = Command::new("ffmpeg").arg("-version")
+									.output()
+									.expect("Failed to find and run ffmpeg");
+	
+	if !ffmpeg_testrun_result.status.success() {
+		eprintln!("Aborting: ffmpeg returned abnormal status from test run");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+	
+	/* Determine what format to convert to */
+	let export_format: TrackExtension = match convert_mode.parse::<TrackExtension>() {
+		/* Supported Formats - Audio only */
+		Ok(f @ TrackExtension::mp3) | Ok(f @ TrackExtension::flac) | Ok(f @ TrackExtension::ogg) => f,
+		
+		/* Video formats - With a still image or waveform for the picture (see get_video_source()) */
+		Ok(f @ TrackExtension::mp4) | Ok(f @ TrackExtension::mkv) => f,
+		
+		/* Unsupported formats - Unknown Extensions */
+		Ok(TrackExtension::Unknown(ext)) => {
+			eprintln!("Error: Unsupported/unknown output format ({0:?})", ext);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Ok(TrackExtension::None) => {
+			eprintln!("Error: No output format given");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Ok(t) => {
+			eprintln!("Error: Unsupported output format ({0:?})", t);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		
+		/* Parsing Error - Invalid argument */
+		_ => {
+			eprintln!("Error: Parsing error for convert_mode argument");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	/* Quality to encode at - This picks the settings for the format, instead of being passed to ffmpeg */
+	let quality = get_audio_quality(args);
+	
+	/* Picture for the video formats */
+	let video_source = get_video_source(args, &export_format);
+	
+	/* Add additional args the user specified on the command-line to also get passed along
+	 * (i.e. allowing for customising the behaviour + tweaking it without recompiling).
+	 * These go after the settings for the format, so they can override them (see ffmpeg_command::build_ffmpeg_args())
+	 */
+	let mut user_ffmpeg_args: Vec<String> = Vec::new();
+	if let Some(bitrate) = command_args::get_option(args, "--bitrate") {
+		user_ffmpeg_args.push("-b:a".to_string());
+		user_ffmpeg_args.push(bitrate.to_string());
+	}
+	for arg in args.iter().filter(|arg| !is_export_option(arg)) {
+		user_ffmpeg_args.push(arg.to_string());
+	}
+	
+	/* Incremental mode - Only redo tracks that have changed since the last run
+	 * (i.e. source modified after the output was made, or with "--settings-diff", made with different settings)
+	 */
+	let settings_diff = command_args::has_flag(args, "--settings-diff");
+	let incremental = settings_diff || command_args::has_flag(args, "--incremental");
+	
+	/* Re-encode tracks that are already in the output format, instead of just copying them */
+	let force_transcode = command_args::has_flag(args, "--force-transcode");
+	
+	/* Redo tracks whose output already exists (from an earlier run), instead of skipping them */
+	let overwrite = command_args::has_flag(args, "--overwrite");
+	
+	/* Presets for each type of track - Checked now, before anything gets converted */
+	let presets = get_convert_presets(args, &export_format, quality, &user_ffmpeg_args);
+	print_encoder_settings(&export_format, quality, &presets.base, video_source.as_ref());
+	let mut preset_uses: Vec<(Option<&'static str>, usize)> = Vec::new();
+	
+	/* Fades - The filters depend on each track's duration, so they get added for each track */
+	let fade_options = get_fade_options(args);
+	
+	/* Parse XSPF Playlist... */
+	if let Some(mut xspf) = load_playlist_for_files(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		let waveform_options = get_waveform_options(args);
+		let mut nfo_writer = get_nfo_writer(args);
+		let mut post_cmd = get_post_command_runner(args);
+		let mut progress = open_progress_reporter(args);
+		
+		/* Construct filenames for converted files - they need to have enough metadata to figure out what's going on
+		 * (worked out before anything gets written, in case the names collide)
+		 */
+		let continue_numbering = command_args::has_flag(args, NAMING_OPTIONS[3]);
+		let naming = plan_destination_filenames(&xspf, Some(export_format.clone()), args, get_continued_numbering(out_path, args));
+		
+		/* Ensure outdir exists, and that nothing else is writing into it */
+		let dst_path_root = ensure_output_directory_exists(out_path);
+		let _lock = lock_output_directory(dst_path_root, args);
+		write_collisions_report(out_path, &naming);
+		create_destination_subdirectories(out_path, &naming);
+		
+		/* Record of the settings used for each file in the output folder */
+		let mut stamps = convert_stamps::ConvertStamps::load(Path::new(out_path));
+		
+		let dst_filenames: Vec<String> = naming.filenames;
+		
+		/* Resume an interrupted run - Skip tracks whose output was completed, and throw away any partial ones */
+		let resume_plan = if command_args::has_flag(args, "--resume-partial") {
+			match convert_resume::prepare_resume(Path::new(out_path), &dst_filenames) {
+				Ok(plan) => {
+					println!("Resuming: {0} already done, {1} to do, {2} temp files cleaned up\n",
+					         plan.done.len(), plan.remaining.len(), plan.stale_partials.len());
+					for partial in plan.stale_partials.iter() {
+						run_journal::record_action("removed partial", partial.clone());
+					}
+					Some(plan)
+				},
+				Err(e) => {
+					eprintln!("ERROR: Couldn't check {0:?} for completed outputs - {1}", out_path, e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+		else {
+			None
+		};
+		
+		/* Loop over tracks copying them to the folder */
+		let mut dest_filenames : Vec<String> = Vec::new();
+		let mut waveform_images : Vec<String> = Vec::new();
+		let mut waveform_problems = diagnostics::Diagnostics::new();
+		let mut fade_problems = diagnostics::Diagnostics::new();
+		
+		/* Files that only get copied keep the source file's modification time */
+		let mut stamper = file_timestamps::Stamper::new(file_timestamps::StampMode::PreserveSource);
+		
+		/* Durations only need probing for fade-outs, when the playlist doesn't have them */
+		let probe_cache = match fade_options {
+			Some(ref fade) if fade.fade_out_secs > 0.0 => open_probe_cache(in_file, args),
+			_                                          => None
+		};
+		
+		progress.set_line_options(track_line_options(&xspf, args, true));
+		progress.run_started("convert", xspf.len(), playlist_total_bytes(&xspf));
+		
+		/* Number of tracks converted, copied as they were (i.e. already in the output format), skipped (as they
+		 * were done by an earlier run), and that failed
+		 */
+		let (mut num_converted, mut num_copied, mut num_skipped) = (0, 0, 0);
+		let mut results = run_outcome::TrackResults::default();
+		
+		for (track_idx, track) in xspf.tracks.iter().enumerate() {
+			let dst_filename = dst_filenames[track_idx].clone();
+			
+			/* Construct paths to actually perform the copying to/from */
+			let src_path = track.file_path();
+			let dst_path = output_file_path(out_path, &dst_filename);
+			
+			progress.track_started(track_idx, &src_path.to_string_lossy(), &dst_path);
+			
+			/* Skip if completed by the interrupted run being resumed */
+			if resume_plan.as_ref().is_some_and(|plan| plan.is_done(&dst_filename)) {
+				progress.print_track(track_idx, &format!("Already done - {0:?}", dst_filename));
+				dest_filenames.push(dst_filename);
+				num_skipped += 1;
+				progress.track_finished(track_idx, TrackStatus::Skipped, None);
+				continue;
+			}
+			
+			/* Skip if already converted by an earlier run (unless asked to redo these)
+			 * NOTE: Incremental runs decide this for themselves below, based on whether the source has changed since
+			 */
+			if !overwrite && !incremental && file_size(Path::new(&dst_path)).is_some_and(|size| size > 0) {
+				progress.print_track(track_idx, &format!("Already converted - {0:?}", dst_filename));
+				run_journal::record_action("already converted", dst_path.clone());
+				dest_filenames.push(dst_filename);
+				num_skipped += 1;
+				progress.track_finished(track_idx, TrackStatus::Skipped, None);
+				continue;
+			}
+			
+			/* Do not convert if the file is already in the desired format, as converting files multiple times
+			 * will cause quality loss each time this happens.
+			 * (Unless fades were requested - these can only be applied by re-encoding - or re-encoding was asked for)
+			 */
+			let needs_conversion = (track.info().extn != export_format) || fade_options.is_some() || force_transcode;
+			
+			/* Settings for this type of track */
+			let (preset, track_base_args) = presets.args_for(&track.info().track_type);
+			let settings_fingerprint = convert_settings_fingerprint(track_base_args, fade_options.as_ref(), video_source.as_ref());
+			
+			/* Skip if unchanged since the last run
+			 * (ffmpeg settings don't matter for files that just get copied)
+			 */
+			if incremental {
+				let decision = convert_stamps::decide(file_mtime(src_path), file_mtime(Path::new(&dst_path)),
+				                                      stamps.fingerprint_for(&dst_filename), &settings_fingerprint,
+				                                      settings_diff && needs_conversion);
+				if decision.needs_convert() {
+					progress.print_track(track_idx, &format!("Redoing {0:?} ({1:?})", dst_filename, decision));
+				}
+				else {
+					progress.print_track(track_idx, &format!("Up to date - {0:?}", dst_filename));
+					dest_filenames.push(dst_filename);
+					num_skipped += 1;
+					progress.track_finished(track_idx, TrackStatus::Skipped, None);
+					continue;
+				}
+			}
+			
+			/* Convert or copy this track */
+			let success = if needs_conversion {
+				/* Add the fades for this track */
+				let track_ffmpeg_args = match fade_options {
+					Some(ref fade) => {
+						let duration_ms = match track.duration {
+							Some(ref d) => Some(d.0),
+							None if fade.fade_out_secs > 0.0 => {
+								let prober = media_probe::FfprobeProber;
+								let result = match probe_cache {
+									Some(ref cache) => cache.probe(src_path, &prober),
+									None            => media_probe::Prober::probe(&prober, src_path)
+								};
+								result.ok().and_then(|info| info.duration_ms)
+							},
+							None => None
+						};
+						
+						let fades = ffmpeg_command::fade_filters(fade, duration_ms);
+						if fades.fade_out_skipped {
+							fade_problems.warn_track(FADE_CATEGORY, track_idx,
+								format!("duration of '{}' is unknown (and couldn't be probed) - converted without fade-out", track.filename));
+						}
+						ffmpeg_command::with_audio_filters(track_base_args, &fades.filters)
+					},
+					None => track_base_args.to_vec()
+				};
+				
+				/* Perform convert operation */
+				if let Some(preset) = preset {
+					progress.print_track(track_idx, &format!("Using preset '{0}' for {1:?} track", preset, track.info().track_type));
+				}
+				let converted = convert_track(track_idx, track, &dst_path, &track_ffmpeg_args, video_source.as_ref(), overwrite, &progress);
+				if converted {
+					stamps.record(&dst_filename, &settings_fingerprint);
+					
+					let name = preset.map(|p| p.name);
+					match preset_uses.iter_mut().find(|&&mut (p, _)| p == name) {
+						Some(&mut (_, ref mut count)) => *count += 1,
+						None => preset_uses.push((name, 1))
+					}
+					if let Some(name) = name {
+						run_journal::record_action("preset", format!("{0} -> {1}", name, dst_path));
+					}
+				}
+				converted
+			}
+			else {
+				/* Just perform copy operation */
+				copy_track(track_idx, track, &dst_path, &mut stamper, &progress)
+			};
+			
+			if success {
+				if needs_conversion { num_converted += 1; } else { num_copied += 1; }
+				
+				/* Write metadata sidecar to go alongside it */
+				if let Some(ref mut nfo_writer) = nfo_writer {
+					nfo_writer.write(track_idx, track, Path::new(&dst_path));
+				}
+				
+				/* Render waveform image to go alongside it */
+				if let Some(ref options) = waveform_options {
+					match export_waveform_image(src_path, out_path, &dst_filename, options) {
+						Ok(image) => {
+							progress.print_track(track_idx, &format!("Rendered waveform => <outdir>/{}", image));
+							waveform_images.push(image);
+						},
+						Err(e)    => waveform_problems.warn_track(WAVEFORM_CATEGORY, track_idx, e)
+					}
+				}
+				
+				/* Success - Note this as one of the successful files */
+				dest_filenames.push(dst_filename);
+				progress.track_finished(track_idx, TrackStatus::Ok, file_size(Path::new(&dst_path)));
+				
+				/* Run the user's command on it */
+				if let Some(ref mut post_cmd) = post_cmd {
+					if !post_cmd.run(track_idx, &post_command::TrackVars::for_track(track, track_idx, &dst_path)) {
+						break;
+					}
+				}
+			}
+			else {
+				results.add_failed(src_path);
+				progress.track_finished(track_idx, TrackStatus::Failed, None);
+			}
+		}
+		
+		/* Dump list of copied files to <out_path>/<playlist_filename>.m3u
+		 * (i.e. a playable playlist, that also acts as a manifest of the set of files copied)
+		 */
+		write_copied_files_manifest(in_file, out_path, &dest_filenames, &favorite_track_markers(&xspf, &dst_filenames), args,
+		                            continue_numbering);
+		record_run_summary(progress.run_finished());
+		
+		println!("\nConverted {0}, copied {1} (already {2:?}), skipped {3} (already done), {4} failed - of {5} tracks",
+		         num_converted, num_copied, export_format, num_skipped, results.failed, xspf.len());
+		results.ok = num_converted + num_copied;
+		results.skipped = num_skipped;
+		run_outcome::record_track_results(results);
+		
+		if let Err(e) = stamps.save() {
+			eprintln!("WARNING: {}", e);
+		}
+		
+		print_timestamps_report(&stamper);
+		print_presets_report(&presets, &preset_uses);
+		
+		if let Some(ref nfo_writer) = nfo_writer {
+			print_nfo_report(nfo_writer);
+		}
+		
+		if waveform_options.is_some() {
+			print_waveform_images_report(&waveform_images, &waveform_problems);
+			run_outcome::record_diagnostics(&waveform_problems);
+		}
+		
+		save_probe_cache(probe_cache.as_ref());
+		if !fade_problems.is_empty() {
+			eprintln!("\nWARNING: {} tracks were converted without fade-outs:", fade_problems.len());
+			let _ = fade_problems.write_text(&mut io::stderr());
+			run_outcome::record_diagnostics(&fade_problems);
+		}
+		
+		if let Some(ref post_cmd) = post_cmd {
+			print_post_command_report(post_cmd);
+			if post_cmd.aborted() {
+				drop(_lock);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+}
+
+/* ................................ */
+
+/* Options only used by sample_mode() (i.e. not passed on to the mode that processes the sample) */
+const SAMPLE_MODE_OPTIONS: [&'static str; 3] = ["--count", "--action", "--format"];
+
+/* List, copy, or convert a small sample of tracks spread across the playlist's sessions/types,
+ * e.g. for spot-checking the quality before converting everything
+ * (This just runs the list/copy/convert mode with "--sample=<count>" added to its track selection options)
+ */
+fn sample_mode(in_file: &str, out_path: Option<&String>, args: &Vec<String>)
+{
+	let count = match command_args::parse_option::<usize>(args, "--count") {
+		Ok(Some(0)) => {
+			eprintln!("ERROR: --count must be at least 1");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Ok(count) => count.unwrap_or(track_sampling::DEFAULT_SAMPLE_COUNT),
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	/* Pass everything else along to the mode doing the work */
+	let mut mode_args: Vec<String> = args.iter()
+	                                     .filter(|arg| !SAMPLE_MODE_OPTIONS.iter().any(|opt| arg.starts_with(&format!("{}=", opt))))
+	                                     .cloned()
+	                                     .collect();
+	mode_args.push(format!("--sample={}", count));
+	
+	match command_args::get_option(args, "--action").unwrap_or("list") {
+		"list" => {
+			list_output_mode(in_file, out_path, &mode_args);
+		},
+		"copy" => {
+			copy_files_mode(in_file, out_path, &mode_args);
+		},
+		"convert" => {
+			match out_path {
+				Some(out) => {
+					let format = command_args::get_option(args, "--format").unwrap_or("mp3");
+					convert_files_mode(in_file, out, format, &mode_args);
+				},
+				None => {
+					eprintln!("ERROR: The third argument should specify the directory to convert the sampled tracks into");
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		},
+		action => {
+			eprintln!("ERROR: Unknown action for sample - '{}' (expected list, copy, or convert)", action);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Write the archive for bundle_mode()
+ * - filenames: Name to give each track's file in the archive (see plan_destination_filenames())
+ * > returns the number of tracks that couldn't be included
+ */
+fn write_bundle(in_file: &str, xspf: &XspfPlaylist, filenames: &[String], out_file: &str,
+                progress: &mut progress_events::ProgressReporter)
+	-> Result<usize, tar_archive::TarError>
+{
+	let mut writer = tar_archive::TarWriter::new(io::BufWriter::new(File::create(out_file)?));
+	
+	let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+	              .map(|d| d.as_secs())
+	              .unwrap_or(0);
+	let playlist_name = playlist_file_stem(in_file);
+	
+	/* Track files - Renamed in the same way as for copy_files_mode() */
+	let mut dest_filenames : Vec<String> = Vec::new();
+	let mut report_lines : Vec<String> = Vec::new();
+	let mut skipped_lines : Vec<String> = Vec::new();
+	let mut total_bytes : u64 = 0;
+	
+	for (track_idx, track) in xspf.tracks.iter().enumerate() {
+		let dst_filename = filenames[track_idx].clone();
+		progress.track_started(track_idx, track.path.as_str(), &dst_filename);
+		
+		/* Check that the file can be included, before anything gets written for it */
+		let size = match fs::metadata(track.file_path()) {
+			Ok(metadata) => metadata.len(),
+			Err(e) => {
+				eprintln!("   [{0}/{1}] ! Skipped {2} - {3}", track_idx + 1, xspf.len(), terminal_text::sanitize(track.path.as_str()), e);
+				skipped_lines.push(format!("  {0} - {1}", track.path, e));
+				run_journal::record_action("skipped", format!("{0} - {1}", track.path, e));
+				run_outcome::record_warning(run_outcome::SKIPPED_CATEGORY);
+				progress.track_finished(track_idx, TrackStatus::Skipped, None);
+				continue;
+			}
+		};
+		if size > tar_archive::MAX_ENTRY_SIZE {
+			eprintln!("   [{0}/{1}] ! Skipped {2} - Too large ({3} bytes, limit is {4})",
+			          track_idx + 1, xspf.len(), terminal_text::sanitize(track.path.as_str()), size, tar_archive::MAX_ENTRY_SIZE);
+			skipped_lines.push(format!("  {0} - Too large ({1} bytes)", track.path, size));
+			run_journal::record_action("skipped", format!("{0} - Too large ({1} bytes)", track.path, size));
+			run_outcome::record_warning(run_outcome::SKIPPED_CATEGORY);
+			progress.track_finished(track_idx, TrackStatus::Skipped, None);
+			continue;
+		}
+		
+		let added = writer.append_file(&dst_filename, track.file_path())?;
+		println!("   [{0}/{1}] Added {2} ({3} bytes)", track_idx + 1, xspf.len(), terminal_text::sanitize(&dst_filename), added);
+		run_journal::record_action("added", format!("{0} -> {1}:{2}", track.path, out_file, dst_filename));
+		progress.track_finished(track_idx, TrackStatus::Ok, Some(added));
+		
+		report_lines.push(format!("  {0}  <= {1} ({2} bytes)", dst_filename, track.path, added));
+		total_bytes += added;
+		dest_filenames.push(dst_filename);
+	}
+	
+	/* Manifest */
+	let manifest_name = format!("{}.m3u8", playlist_name);
+	writer.append_data(&manifest_name, format_copied_files_manifest(&dest_filenames, &favorite_track_markers(xspf, filenames), None).as_bytes(), now)?;
+	println!("   Added {}", manifest_name);
+	
+	/* Metadata */
+	let json_name = format!("{}.json", playlist_name);
+	let json = serde_json::to_string_pretty(xspf).map_err(io::Error::other)?;
+	writer.append_data(&json_name, json.as_bytes(), now)?;
+	println!("   Added {}", json_name);
+	
+	/* Report */
+	let mut report = format!("Bundle of '{0}'\n\nIncluded {1} of {2} tracks ({3} bytes):\n",
+	                         in_file, dest_filenames.len(), xspf.len(), total_bytes);
+	for line in report_lines.iter() {
+		report.push_str(line);
+		report.push('\n');
+	}
+	if !skipped_lines.is_empty() {
+		report.push_str(&format!("\nSkipped {} tracks:\n", skipped_lines.len()));
+		for line in skipped_lines.iter() {
+			report.push_str(line);
+			report.push('\n');
+		}
+	}
+	
+	let report_name = format!("{}-report.txt", playlist_name);
+	writer.append_data(&report_name, report.as_bytes(), now)?;
+	println!("   Added {}", report_name);
+	
+	writer.finish()?;
+	
+	println!("\nBundled {0} tracks ({1} bytes) into {2}", dest_filenames.len(), total_bytes, out_file);
+	Ok(skipped_lines.len())
+}
+
+/* Bundle the (renamed) track files, along with a manifest, metadata, and report into a single tar archive */
+fn bundle_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	if let Some(out) = out_file {
+		println!("Bundle infile='{0}', out={1:?}", in_file, out);
+		if let Some(mut xspf) = load_playlist_for_files(in_file) {
+			apply_track_filter_options(&mut xspf, args);
+			let naming = plan_destination_filenames(&xspf, None, args, None);
+			
+			/* Make sure nothing else is writing into the same folder */
+			let out_dir = match Path::new(out).parent() {
+				Some(dir) if !dir.as_os_str().is_empty() => dir,
+				_                                        => Path::new(".")
+			};
+			let _lock = lock_output_directory(out_dir, args);
+			
+			let mut progress = open_progress_reporter(args);
+			progress.run_started("bundle", xspf.len(), playlist_total_bytes(&xspf));
+			
+			let result = write_bundle(in_file, &xspf, &naming.filenames, out, &mut progress);
+			record_run_summary(progress.run_finished());
+			
+			match result {
+				Ok(0) => { /* all good */ },
+				Ok(skipped) => {
+					eprintln!("\nWARNING: {} tracks could not be included (see report)", skipped);
+				},
+				Err(e) => {
+					eprintln!("ERROR: Couldn't write bundle - {}", e);
+					exit_run(run_outcome::EXIT_FAILED);
+				}
+			}
+		}
+	}
+	else {
+		eprintln!("ERROR: The third argument should specify the archive (.tar) to write");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+}
+
+/* "unbundle <archive.tar> <outdir>" - Extract an archive created by bundle_mode() */
+fn unbundle_command(args: &Vec<String>)
+{
+	let (archive, out_dir) = match (args.get(2), args.get(3)) {
+		(Some(a), Some(o)) => (a, o),
+		_ => {
+			eprintln!("ERROR: Usage - unbundle <archive.tar> <outdir>");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	println!("Unbundle archive='{0}', outdir={1:?}", archive, out_dir);
+	
+	let mut input = match File::open(archive) {
+		Ok(f)  => io::BufReader::new(f),
+		Err(e) => {
+			eprintln!("ERROR: Couldn't open archive {0:?} - {1}", archive, e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	let dst_path_root = ensure_output_directory_exists(out_dir);
+	
+	let result = tar_archive::extract_all(&mut input, dst_path_root, &mut |idx, header| {
+		println!("   [{0}] Extracting {1} ({2} bytes)", idx + 1, terminal_text::sanitize(&header.name), header.size);
+		run_journal::record_action("extracted", format!("{0}:{1} -> {2}", archive, header.name, out_dir));
+	});
+	match result {
+		Ok(entries) => {
+			let total_bytes : u64 = entries.iter().map(|e| e.size).sum();
+			println!("\nExtracted {0} entries ({1} bytes) to {2}", entries.len(), total_bytes, out_dir);
+		},
+		Err(e) => {
+			eprintln!("ERROR: Couldn't extract archive - {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Write a single-file HTML page for playing the playlist in a browser, from the files made by copy/convert
+ * ! This function will terminate the process if the options are invalid, or the page couldn't be written
+ */
+fn webplayer_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let out_file = match out_file {
+		Some(out_file) => out_file,
+		None => {
+			eprintln!("ERROR: You need to supply the output filename - webplayer <in.xspf> <out.html> [options]");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	check_not_overwriting_input(in_file, out_file);
+	
+	let media_base = command_args::get_option(args, "--media-base").unwrap_or("./");
+	let manifest = command_args::get_option(args, "--from-copy-manifest").map(|path| {
+		match m3u_parser::read_m3u(Path::new(path)) {
+			Ok(manifest) => manifest,
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	});
+	
+	println!("Web Player in='{0}', out='{1}', media={2:?}", in_file, out_file, media_base);
+	if let Some(mut xspf) = load_playlist(in_file) {
+		apply_track_filter_options(&mut xspf, args);
+		resolve_durations(&mut xspf, in_file);
+		
+		/* Files are named as copy names them (numbered after any filtering, so the same filter needs to be used),
+		 * unless the manifest says what they actually ended up as (e.g. with a different extension from convert)
+		 */
+		let expected: Vec<String> = plan_destination_filenames(&xspf, None, args, None).filenames;
+		let filenames: Vec<Option<String>> = match manifest {
+			Some(ref manifest) => web_player::map_manifest_names(&expected, manifest),
+			None               => expected.into_iter().map(Some).collect()
+		};
+		
+		let mut missing: Vec<String> = Vec::new();
+		let mut tracks: Vec<web_player::PlayerTrack> = Vec::new();
+		for (track_idx, (track, filename)) in xspf.tracks.iter().zip(filenames.iter()).enumerate() {
+			match *filename {
+				Some(ref filename) => {
+					tracks.push(web_player::PlayerTrack {
+						number: track_idx + 1,
+						title: nfo_sidecar::pretty_name(track),
+						date: track.date.session_date().map(|d| d.to_string()),
+						duration: track.duration.as_ref().map(|d| d.to_timecode()),
+						rating: track.rating,
+						url: web_player::media_url(media_base, filename),
+					});
+				},
+				None => missing.push(format!("{0}. {1}", track_idx + 1, track.filename))
+			}
+		}
+		if !missing.is_empty() {
+			eprintln!("WARNING: {} tracks aren't listed in the copy manifest (e.g. they failed to copy), so were left out:", missing.len());
+			for name in missing.iter() {
+				eprintln!("   {}", terminal_text::sanitize(name));
+			}
+		}
+		
+		let data = web_player::PlayerData {
+			title: web_player::page_title(playlist_own_title(&xspf, in_file).as_deref(), in_file),
+			tracks: tracks,
+		};
+		let result = web_player::player_html(&data)
+			.and_then(|html| fs::write(out_file, html).map_err(|e| format!("Couldn't write {0:?} - {1}", out_file, e)));
+		match result {
+			Ok(_) => {
+				println!("Wrote player for {0} tracks to {1}", data.tracks.len(), out_file);
+				run_journal::record_action("wrote", format!("web player ({0} tracks) -> {1}", data.tracks.len(), out_file));
+			},
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Apply one of the "edit" subcommands to the playlist
+ * Helper for edit_command()
+ * > returns a description of what was done
+ */
+fn apply_playlist_edit(xspf: &mut XspfPlaylist, in_file: &str, command: &str, params: &[&String], options: &[String])
+	-> Result<String, String>
+{
+	match (command, params) {
+		("remove", &[positions]) => {
+			let positions = command_args::parse_positions(positions)?;
+			let removed = xspf.remove_tracks(&positions).map_err(|e| e.to_string())?;
+			
+			let names: Vec<&str> = removed.iter().map(|t| t.filename.as_str()).collect();
+			Ok(format!("Removed {0} tracks - {1}", removed.len(), names.join(", ")))
+		},
+		("move", &[from, to]) => {
+			let from = from.parse::<usize>().map_err(|_| format!("Invalid position - '{}'", from))?;
+			let to = to.parse::<usize>().map_err(|_| format!("Invalid position - '{}'", to))?;
+			xspf.move_track(from, to).map_err(|e| e.to_string())?;
+			
+			Ok(format!("Moved track {0} to position {1} - {2}", from, to, xspf.tracks[to - 1].filename))
+		},
+		("insert", &[path]) => {
+			let position = match command_args::parse_option::<usize>(options, "--at")? {
+				Some(position) => position,
+				None           => xspf.len() + 1
+			};
+			
+			let mut track = Track::from_path(Path::new(path))?;
+			if command_args::has_flag(options, "--probe") {
+				let cache = open_probe_cache(in_file, options);
+				let prober = media_probe::FfprobeProber;
+				let result = match cache {
+					Some(ref cache) => cache.probe(track.file_path(), &prober),
+					None            => media_probe::Prober::probe(&prober, track.file_path())
+				};
+				match result {
+					Ok(info) => {
+						track.duration = info.duration_ms.map(track_duration::TrackDuration);
+						track.duration_source = duration_source::DurationSource::Probed;
+					},
+					Err(e)   => eprintln!("WARNING: Couldn't probe duration of {0:?} - {1}", path, e)
+				}
+				save_probe_cache(cache.as_ref());
+			}
+			
+			let filename = track.filename.clone();
+			xspf.insert_track(position, track).map_err(|e| e.to_string())?;
+			Ok(format!("Inserted {0} at position {1}", filename, position))
+		},
+		_ => {
+			Err(format!("Invalid edit command - expected 'remove <positions>', 'move <from> <to>', or 'insert <path> [--at=<pos>]' (got {0:?} with {1} args)",
+			            command, params.len()))
+		}
+	}
+}
+
+/* "edit <in.xspf> <out.xspf|--in-place> <command> <...>" - Make small changes to a playlist */
+fn edit_command(args: &Vec<String>)
+{
+	let in_file = match args.get(2) {
+		Some(f) => f,
+		None => {
+			eprintln!("ERROR: You need to supply a .xspf filename to edit as the second argument");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	let options = extract_command_args_list(args, 3);
+	let (flags, positional): (Vec<&String>, Vec<&String>) = options.iter().partition(|a| a.starts_with("--"));
+	let flags: Vec<String> = flags.into_iter().cloned().collect();
+	
+	/* Output file is only skipped when editing in-place */
+	let in_place = command_args::has_flag(&flags, "--in-place");
+	let (out_file, positional) = if in_place {
+		(in_file, &positional[..])
+	}
+	else {
+		match positional.split_first() {
+			Some((out_file, rest)) => (*out_file, rest),
+			None => {
+				eprintln!("ERROR: You need to supply the output filename (or --in-place to overwrite the input)");
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	};
+	
+	if !in_place {
+		check_not_overwriting_input(in_file, out_file);
+	}
+	
+	let (command, params) = match positional.split_first() {
+		Some((command, params)) => (command.as_str(), params),
+		None => {
+			eprintln!("ERROR: You need to supply an edit command (remove, move, insert)");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		/* Only the playlist's own title should get written out again (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		
+		match apply_playlist_edit(&mut xspf, in_file, command, params, &flags) {
+			Ok(description) => {
+				println!("{}", terminal_text::sanitize(&description));
+				run_journal::record_action("edited", format!("{0} - {1}", in_file, description));
+			},
+			Err(e) => {
+				eprintln!("ERROR: {}", terminal_text::sanitize(&e));
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		
+		save_playlist(&xspf, out_file);
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Prefix the titles of the tracks with their position (e.g. "01. tranquil"), for players that sort by title
+ * instead of using the playlist order (or with "--strip", remove those prefixes again)
+ */
+fn number_titles_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let start_index = match command_args::parse_option::<usize>(args, "--start-index") {
+		Ok(start_index) => start_index.unwrap_or(1),
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	/* Output file is only skipped when writing in-place */
+	let out_file = match (out_file, command_args::has_flag(args, "--in-place")) {
+		(Some(out_file), false) => {
+			check_not_overwriting_input(in_file, out_file);
+			out_file.as_str()
+		},
+		(None, true) => in_file,
+		(Some(_), true) => {
+			eprintln!("ERROR: Only one of the output filename or --in-place should be given");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		(None, false) => {
+			eprintln!("ERROR: You need to supply the output filename (or --in-place to overwrite the input)");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		/* Only the playlist's own title should get written out again (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		
+		let description = if command_args::has_flag(args, "--strip") {
+			let changed = title_numbering::strip_titles(&mut xspf.tracks);
+			format!("Removed numbering from {} track titles", changed)
+		}
+		else {
+			let width = title_numbering::number_width(xspf.len(), start_index, xspf.track_index_width());
+			title_numbering::number_titles(&mut xspf.tracks, start_index, width, command_args::has_flag(args, "--replace-titles"));
+			format!("Numbered {0} track titles (starting from {1:02$})", xspf.len(), start_index, width)
+		};
+		println!("{}", description);
+		run_journal::record_action("edited", format!("{0} - {1}", in_file, description));
+		
+		save_playlist(&xspf, out_file);
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Number of tracks kept by head/tail when neither --count nor --duration is given */
+const DEFAULT_TRUNCATE_COUNT: usize = 10;
+
+/* Keep only the first (or with from_end, the last) few tracks of the playlist, by number or total duration
+ * ! This function will terminate the process if the options are invalid
+ */
+fn truncate_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>, from_end: bool)
+{
+	let mode = if from_end { "tail" } else { "head" };
+	let count = match command_args::parse_option::<usize>(args, "--count") {
+		Ok(count) => count,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	let limit_ms = command_args::get_option(args, "--duration").map(|value| {
+		match filter_expr::parse_duration_value(value) {
+			Some(ms) => ms,
+			None => {
+				eprintln!("ERROR: Invalid value for --duration - '{}' (expected m:ss, h:mm:ss, or seconds)", value);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	});
+	if count.is_some() && limit_ms.is_some() {
+		eprintln!("ERROR: Only one of --count or --duration should be given");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+	
+	let out_file = match out_file {
+		Some(out_file) => out_file,
+		None => {
+			eprintln!("ERROR: You need to supply the output filename - {} <in.xspf> <out.xspf> [options]", mode);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	check_not_overwriting_input(in_file, out_file);
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		/* Only the playlist's own title should get written out again (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		let total = xspf.len();
+		
+		let description = match limit_ms {
+			Some(limit_ms) => {
+				resolve_durations(&mut xspf, in_file);
+				
+				let include_crossing = !command_args::has_flag(args, "--strict-limit");
+				let cut = if from_end {
+					xspf.tail_by_duration(limit_ms, include_crossing)
+				}
+				else {
+					xspf.head_by_duration(limit_ms, include_crossing)
+				};
+				
+				/* Unknown durations count as zero, unless that isn't acceptable */
+				if !cut.unknown.is_empty() {
+					let positions: Vec<String> = cut.unknown.iter().map(|i| (i + 1).to_string()).collect();
+					if command_args::has_flag(args, "--strict-durations") {
+						eprintln!("ERROR: {0} tracks have unknown durations (positions {1}) - nothing written",
+						          cut.unknown.len(), positions.join(", "));
+						exit_run(run_outcome::EXIT_FAILED);
+					}
+					eprintln!("WARNING: {0} tracks have unknown durations, and were counted as 0:00 (positions {1})",
+					          cut.unknown.len(), positions.join(", "));
+				}
+				
+				format!("Kept the {0} {1} of {2} tracks ({3}, limit {4})",
+				        if from_end { "last" } else { "first" }, cut.kept, total,
+				        track_duration::TrackDuration(cut.total_ms).to_timecode(), track_duration::TrackDuration(limit_ms).to_timecode())
+			},
+			None => {
+				let count = count.unwrap_or(DEFAULT_TRUNCATE_COUNT);
+				if from_end { xspf.tail_by_count(count); } else { xspf.head_by_count(count); }
+				format!("Kept the {0} {1} of {2} tracks", if from_end { "last" } else { "first" }, xspf.len(), total)
+			}
+		};
+		println!("{}", description);
+		run_journal::record_action("edited", format!("{0} - {1}", in_file, description));
+		
+		save_playlist(&xspf, out_file);
+	}
+}
+
+/* Write the playlist back out as XSPF, tidied up (e.g. locations re-escaped the standard way),
+ * or converted from an M3U playlist
+ */
+fn write_mode(in_file: &str, out_file: Option<&String>)
+{
+	let out_file = match out_file {
+		Some(out_file) => out_file,
+		None => {
+			eprintln!("ERROR: You need to supply the output filename - write <in.xspf> <out.xspf>");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	check_not_overwriting_input(in_file, out_file);
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		relocate_from_search_root(&mut xspf);
+		
+		/* Only the playlist's own title should get written out again (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		save_playlist(&xspf, out_file);
+	}
+}
+
+/* Keep only the first few tracks of the playlist (see truncate_mode()) */
+fn head_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	truncate_mode(in_file, out_file, args, false);
+}
+
+/* Keep only the last few tracks of the playlist (see truncate_mode()) */
+fn tail_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	truncate_mode(in_file, out_file, args, true);
+}
+
+/* --------------------------------------------- */
+
+/* Parse a weight for practice_mode() (a non-negative number, default 1)
+ * ! This function will terminate the process if the weight is invalid
+ */
+fn get_practice_weight(args: &[String], option: &str) -> f64
+{
+	match command_args::parse_option::<f64>(args, option) {
+		Ok(None) => 1.0,
+		Ok(Some(weight)) if weight >= 0.0 && weight.is_finite() => weight,
+		Ok(Some(weight)) => {
+			eprintln!("ERROR: Invalid value for {0} - {1} (expected a number >= 0)", option, weight);
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* Pick pieces to practice, favouring ones that haven't been played in a while (or only have a few takes),
+ * and write a playlist of the most recent take of each (or just list them if no output file is given)
+ */
+fn practice_mode(in_file: &str, out_file: Option<&String>, args: &Vec<String>)
+{
+	let count = match command_args::parse_option::<usize>(args, "--count") {
+		Ok(count) => count,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	let max_duration_ms = command_args::get_option(args, "--duration").map(|value| {
+		match filter_expr::parse_duration_value(value) {
+			Some(ms) => ms,
+			None => {
+				eprintln!("ERROR: Invalid value for --duration - '{}' (expected m:ss, h:mm:ss, or seconds)", value);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	});
+	let limit = practice_playlist::PracticeLimit {
+		count: if count.is_none() && max_duration_ms.is_none() { Some(practice_playlist::DEFAULT_PRACTICE_COUNT) } else { count },
+		max_duration_ms: max_duration_ms,
+	};
+
+	let weights = practice_playlist::PracticeWeights {
+		recency: get_practice_weight(args, "--recency-weight"),
+		rarity: get_practice_weight(args, "--rarity-weight"),
+	};
+	let seed = match command_args::parse_option::<u64>(args, "--seed") {
+		Ok(seed) => seed.unwrap_or(0),
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+
+	if let Some(out_file) = out_file {
+		check_not_overwriting_input(in_file, out_file);
+	}
+
+	if let Some(mut xspf) = load_playlist(in_file) {
+		resolve_durations(&mut xspf, in_file);
+		apply_track_filter_options(&mut xspf, args);
+
+		/* Recency is measured up to today (in local time) */
+		let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+		              .map(|d| d.as_secs() as i64)
+		              .unwrap_or(0);
+		let today = (now + track_date::local_utc_offset_secs(now)) / 86400;
+
+		let pieces = practice_playlist::group_pieces(&xspf.tracks);
+		let scores = practice_playlist::score_pieces(&pieces, today, &weights);
+		let durations: Vec<Option<i64>> = pieces.iter().map(|p| xspf.tracks[p.latest].duration.as_ref().map(|d| d.0)).collect();
+		let picked = practice_playlist::select_pieces(&scores, &durations, &limit, seed);
+
+		let total_ms: i64 = picked.iter().filter_map(|&p| durations[p]).sum();
+		println!("Picked {0} of {1} pieces ({2}, seed {3}):", picked.len(), pieces.len(),
+		         track_duration::TrackDuration(total_ms).to_timecode(), seed);
+		
+		/* Piece names get shortened if there isn't room for them */
+		let names: Vec<String> = pieces.iter().map(|p| terminal_text::sanitize(&p.name).into_owned()).collect();
+		let name_width = picked.iter().map(|&p| names[p].chars().count()).max().unwrap_or(0).max(5);
+		let columns = [terminal_width::Column::fixed(3),
+		               terminal_width::Column::flexible(12, name_width, 0),
+		               terminal_width::Column::fixed(10),
+		               terminal_width::Column::fixed(5),
+		               terminal_width::Column::fixed(5),
+		               terminal_width::Column::fixed(8)];
+		let w = terminal_width::allocate_widths(&columns, output_width() - 3, 2);
+		
+		println!("   {0:>1$}  {2:<3$}  {4:<5$}  {6:>7$}  {8:>9$}  {10:>11$}",
+		         "#", w[0], "Piece", w[1], "Last Take", w[2], "Takes", w[3], "Score", w[4], "Duration", w[5]);
+		for (i, &p) in picked.iter().enumerate() {
+			let piece = &pieces[p];
+			let last_date = piece.last_date.as_ref().map(|d| d.to_string()).unwrap_or_else(|| "?".to_string());
+			let duration = durations[p].map(|ms| track_duration::TrackDuration(ms).to_timecode()).unwrap_or_else(|| "?".to_string());
+			println!("   {0:>1$}  {2:<3$}  {4:<5$}  {6:>7$}  {8:>9$.2}  {10:>11$}",
+			         i + 1, w[0], terminal_width::truncate_end(&names[p], w[1]), w[1], last_date, w[2],
+			         piece.takes, w[3], scores[p], w[4], duration, w[5]);
+		}
+
+		if let Some(out_file) = out_file {
+			/* Take out the chosen takes, in the order they were picked */
+			let mut tracks: Vec<Option<Track>> = xspf.tracks.drain(..).map(Some).collect();
+			let practice = XspfPlaylist {
+				tracks: picked.iter().filter_map(|&p| tracks[pieces[p].latest].take()).collect(),
+				title: Some(format!("Practice - {}", playlist_own_title(&xspf, in_file).unwrap_or_else(|| playlist_file_stem(in_file)))),
+				creator: None,
+				annotation: None,
+				date: None,
+				extensions: Vec::new(),
+				diagnostics: diagnostics::Diagnostics::new(),
+				skipped: Vec::new(),
+			};
+			save_playlist(&practice, out_file);
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Write separate playlists for each type of track, e.g. "all.VL.xspf", "all.MS.xspf", "all.misc.xspf"
+ * (into <outdir> if given, otherwise alongside the input playlist)
+ */
+fn split_types_mode(in_file: &str, out_dir: Option<&String>, args: &Vec<String>)
+{
+	let write_json = command_args::has_flag(args, "--json");
+	let force = command_args::has_flag(args, "--force");
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		resolve_durations(&mut xspf, in_file);
+		
+		let dst_path_root = match out_dir {
+			Some(out_dir) => ensure_output_directory_exists(out_dir).to_path_buf(),
+			None          => Path::new(in_file).parent().unwrap_or(Path::new("")).to_path_buf()
+		};
+		let stem = playlist_file_stem(in_file);
+		
+		let title = playlist_own_title(&xspf, in_file);
+		let splits = split_types::split_by_type(xspf.tracks, title.as_deref());
+		
+		match split_types::write_splits(&splits, &dst_path_root, &stem, write_json, force) {
+			Ok(written) => {
+				println!("Wrote {0} playlists ({1} types):", written.len(), splits.len());
+				
+				/* Paths get shortened (in the middle) if there isn't room for them */
+				let paths: Vec<String> = written.iter()
+				                                .map(|f| terminal_text::sanitize(&f.path.display().to_string()).into_owned())
+				                                .collect();
+				let path_width = paths.iter().map(|p| p.chars().count()).max().unwrap_or(0).max(4);
+				let columns = [terminal_width::Column::flexible(20, path_width, 0),
+				               terminal_width::Column::fixed(6),
+				               terminal_width::Column::fixed(10)];
+				let w = terminal_width::allocate_widths(&columns, output_width() - 3, 2);
+				
+				println!("   {0:<1$}  {2:>3$}  {4:>5$}", "File", w[0], "Tracks", w[1], "Duration", w[2]);
+				for (file, path) in written.iter().zip(paths.iter()) {
+					let unknown = if file.uncounted > 0 { format!(" (+{} unknown)", file.uncounted) } else { String::new() };
+					println!("   {0:<1$}  {2:>3$}  {4:>5$}{6}", track_diff::truncate_middle(path, w[0]), w[0],
+					         file.tracks, w[1], file.duration.to_timecode(), w[2], unknown);
+					run_journal::record_action("wrote", format!("{0} tracks -> {1}", file.tracks, file.path.display()));
+				}
+			},
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Parse a duration option for split_mode() (m:ss, h:mm:ss, or seconds)
+ * ! This function will terminate the process if the duration is invalid
+ */
+fn get_split_duration(args: &[String], option: &str) -> Option<i64>
+{
+	command_args::get_option(args, option).map(|value| {
+		match filter_expr::parse_duration_value(value) {
+			Some(ms) => ms,
+			None => {
+				eprintln!("ERROR: Invalid value for {0} - '{1}' (expected m:ss, h:mm:ss, or seconds)", option, value);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	})
+}
+
+/* Write the playlist out in parts that each fit within a total duration ("--max-duration") or number of tracks ("--max-tracks"),
+ * e.g. for burning to CDs - "all.xspf" -> "all_part1.xspf", "all_part2.xspf", ...
+ * (into <outdir> if given, otherwise alongside the input playlist)
+ */
+fn split_mode(in_file: &str, out_dir: Option<&String>, args: &Vec<String>)
+{
+	let max_ms = get_split_duration(args, "--max-duration");
+	let assumed_ms = get_split_duration(args, "--assume-duration");
+	let max_tracks = match command_args::parse_option::<usize>(args, "--max-tracks") {
+		Ok(Some(0)) => {
+			eprintln!("ERROR: Invalid value for --max-tracks - 0 (expected at least 1)");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		Ok(max_tracks) => max_tracks,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+	match (max_ms, max_tracks) {
+		(Some(0), _) => {
+			eprintln!("ERROR: Invalid value for --max-duration - it needs to be longer than 0:00");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		(Some(_), Some(_)) | (None, None) => {
+			eprintln!("ERROR: One of --max-duration or --max-tracks needs to be given - split <in.xspf> [<outdir>] [options]");
+			exit_run(run_outcome::EXIT_FAILED);
+		},
+		_ => {}
+	}
+	let force = command_args::has_flag(args, "--force");
+	
+	if let Some(mut xspf) = load_playlist(in_file) {
+		/* Only the playlist's own title should go into the parts' titles (not the " - <filename>" added when parsing) */
+		xspf.title = playlist_own_title(&xspf, in_file);
+		
+		let parts = match max_ms {
+			Some(max_ms) => {
+				resolve_durations(&mut xspf, in_file);
+				
+				/* Unknown durations count as zero, unless another duration is given for them */
+				let unknown: Vec<String> = xspf.tracks.iter().enumerate()
+				                               .filter(|&(_, t)| t.duration.is_none())
+				                               .map(|(i, _)| (i + 1).to_string())
+				                               .collect();
+				if !unknown.is_empty() {
+					let counted_as = track_duration::TrackDuration(assumed_ms.unwrap_or(0)).to_timecode();
+					eprintln!("WARNING: {0} tracks have unknown durations, and were counted as {1} (positions {2})",
+					          unknown.len(), counted_as, unknown.join(", "));
+				}
+				
+				let max = track_duration::TrackDuration(max_ms);
+				let parts = xspf.split_by_duration_assuming(max, track_duration::TrackDuration(assumed_ms.unwrap_or(0)));
+				for (i, part) in parts.iter().enumerate() {
+					if part.total_duration().duration > max {
+						eprintln!("WARNING: Part {0} is longer than {1}, as '{2}' is too long to fit in a part by itself",
+						          i + 1, max.to_timecode(), terminal_text::sanitize(&part.tracks[0].filename));
+					}
+				}
+				parts
+			},
+			None => xspf.split_by_count(max_tracks.unwrap_or(1))
+		};
+		if parts.is_empty() {
+			println!("No tracks to split");
+			return;
+		}
+		
+		/* Files are named "<stem>_part<N>.xspf" (with the numbers padded, so that they sort in order) */
+		let dst_path_root = match out_dir {
+			Some(out_dir) => ensure_output_directory_exists(out_dir).to_path_buf(),
+			None          => Path::new(in_file).parent().unwrap_or(Path::new("")).to_path_buf()
+		};
+		let stem = playlist_file_stem(in_file);
+		let width = parts.len().to_string().len();
+		let paths: Vec<PathBuf> = (1 ..= parts.len())
+		                             .map(|n| dst_path_root.join(format!("{0}_part{1:02$}.xspf", stem, n, width)))
+		                             .collect();
+		
+		/* Check for existing files first, so that it's all or nothing */
+		if !force {
+			let existing: Vec<String> = paths.iter().filter(|p| p.exists()).map(|p| p.display().to_string()).collect();
+			if !existing.is_empty() {
+				eprintln!("ERROR: Output files already exist (use --force to overwrite them) - {}", existing.join(", "));
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		for (part, path) in parts.iter().zip(paths.iter()) {
+			if let Err(e) = part.save_xspf(path) {
+				eprintln!("ERROR: Couldn't write {0:?} - {1}", path, e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		
+		println!("Split {0} tracks into {1} parts:", xspf.len(), parts.len());
+		
+		/* Paths get shortened (in the middle) if there isn't room for them */
+		let names: Vec<String> = paths.iter()
+		                              .map(|p| terminal_text::sanitize(&p.display().to_string()).into_owned())
+		                              .collect();
+		let path_width = names.iter().map(|p| p.chars().count()).max().unwrap_or(0).max(4);
+		let columns = [terminal_width::Column::flexible(20, path_width, 0),
+		               terminal_width::Column::fixed(6),
+		               terminal_width::Column::fixed(10)];
+		let w = terminal_width::allocate_widths(&columns, output_width() - 3, 2);
+		
+		println!("   {0:<1$}  {2:>3$}  {4:>5$}", "File", w[0], "Tracks", w[1], "Duration", w[2]);
+		for ((part, path), name) in parts.iter().zip(paths.iter()).zip(names.iter()) {
+			let tally = part.total_duration();
+			let unknown = if tally.uncounted > 0 { format!(" (+{} unknown)", tally.uncounted) } else { String::new() };
+			println!("   {0:<1$}  {2:>3$}  {4:>5$}{6}", track_diff::truncate_middle(name, w[0]), w[0],
+			         part.len(), w[1], tally.duration.to_timecode(), w[2], unknown);
+			run_journal::record_action("wrote", format!("{0} tracks -> {1}", part.len(), path.display()));
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Write how the metadata of a merged playlist was arrived at
+ * Helper for merge_command()
+ */
+fn write_merge_provenance(out: &mut dyn Write, in_files: &[String], provenance: &playlist_merge::MergeProvenance) -> io::Result<()>
+{
+	writeln!(out, "Inputs:")?;
+	for (i, (in_file, tracks)) in in_files.iter().zip(provenance.track_counts.iter()).enumerate() {
+		writeln!(out, "   [{0}] {1} ({2} tracks)", i + 1, in_file, tracks)?;
+	}
+
+	writeln!(out, "Metadata:")?;
+	for field in provenance.fields.iter() {
+		let value = match field.value {
+			Some(ref value) => format!("{:?}", value),
+			None            => "-".to_string()
+		};
+		let sources: Vec<String> = field.sources.iter().map(|i| format!("[{}]", i + 1)).collect();
+		let origin = if sources.is_empty() { "no value".to_string() } else { format!("from {}", sources.join(", ")) };
+		writeln!(out, "   {0:<10}  {1}  ({2}, {3})", field.field, value, field.strategy, origin)?;
+	}
+	
+	if provenance.duplicates > 0 {
+		writeln!(out, "Left out {} tracks already in the merged playlist (--dedupe)", provenance.duplicates)?;
+	}
+	Ok(())
+}
+
+/* Merge several playlists into one, with the tracks in order, and the playlist metadata
+ * (title, creator, annotation, date) combined as given by "--meta"
+ */
+fn merge_command(args: &Vec<String>)
+{
+	let files: Vec<String> = args[2 ..].iter().filter(|a| !a.starts_with("--")).cloned().collect();
+	let (out_file, in_files) = match files.split_first() {
+		Some((out_file, in_files)) if !in_files.is_empty() => (out_file, in_files),
+		_ => {
+			eprintln!("ERROR: Usage - merge <out.xspf> <in1.xspf> [<in2.xspf> ...] [--meta=first|last|concat|drop] [--dedupe]");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+
+	let strategy = match command_args::get_option(args, "--meta").map(playlist_merge::MetaStrategy::parse) {
+		None              => playlist_merge::MetaStrategy::First,
+		Some(Ok(strategy)) => strategy,
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
+
+	let mut playlists: Vec<XspfPlaylist> = Vec::new();
+	for in_file in in_files.iter() {
+		check_not_overwriting_input(in_file, out_file);
+		match load_playlist(in_file) {
+			Some(mut xspf) => {
+				/* Only the playlist's own title should get merged (not the " - <filename>" added when parsing) */
+				xspf.title = playlist_own_title(&xspf, in_file);
+				playlists.push(xspf);
+			},
+			None => {
+				/* Already reported */
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+
+	let dedupe = command_args::has_flag(args, "--dedupe");
+	let (merged, provenance) = XspfPlaylist::merge(playlists, in_files, strategy, dedupe);
+
+	if command_args::has_flag(args, "--json") {
+		match serde_json::to_string_pretty(&provenance) {
+			Ok(j) => println!("{}", j),
+			Err(e) => {
+				eprintln!("Couldn't convert merge provenance to JSON - {:?}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+	else if let Err(why) = write_merge_provenance(&mut io::stdout(), in_files, &provenance) {
+		eprintln!("ERROR: Couldn't write merge report - {}", why);
+	}
+
+	save_playlist(&merged, out_file);
+}
+
+/* --------------------------------------------- */
+
+/* Write the totals from the usage log as a table (modes with the most time spent on them first)
+ * Helper for usage_command()
+ */
+fn write_usage_stats(out: &mut dyn Write, stats: &usage_log::UsageStats) -> io::Result<()>
+{
+	let format_ms = |ms: u64| if ms < 1000 { format!("{}ms", ms) } else { output_lock::format_age(ms / 1000) };
+	let row = |out: &mut dyn Write, mode: &str, usage: &usage_log::ModeUsage| {
+		writeln!(out, "   {0:<12}  {1:>6}  {2:>8}  {3:>10}  {4:>9}  {5:>9}",
+		         mode, usage.runs, usage.tracks, size_estimate::format_size(usage.bytes),
+		         format_ms(usage.wall_ms), format_ms(usage.wall_ms / usage.runs.max(1)))
+	};
 	
-	/* Check that FFMPEG works/is available... */
-	let ffmpeg_testrun_result = Command::new("ffmpeg").arg("-version")
-									.output()
-									.expect("Failed to find and run ffmpeg");
+	if stats.modes.is_empty() {
+		writeln!(out, "No runs recorded yet")?;
+		return Ok(());
+	}
 	
-	if !ffmpeg_testrun_result.status.success() {
-		eprintln!("Aborting: ffmpeg returned abnormal status from test run");
-		process::exit(1);
+	writeln!(out, "Usage ({} runs):", stats.totals().runs)?;
+	writeln!(out, "   {0:<12}  {1:>6}  {2:>8}  {3:>10}  {4:>9}  {5:>9}", "Mode", "Runs", "Tracks", "Bytes", "Time", "Per Run")?;
+	for (mode, usage) in stats.modes_by_time() {
+		row(out, &terminal_text::sanitize(mode), usage)?;
 	}
+	row(out, "(total)", &stats.totals())
+}
+
+/* Print the running totals kept in the usage log ("--usage-log=<path>") */
+fn usage_command(args: &Vec<String>)
+{
+	let path = match usage_log_path() {
+		Some(path) => path,
+		None => {
+			eprintln!("ERROR: Usage - usage --usage-log=<path> [--json]");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
 	
-	/* Determine what mode to use, and set the initial arguments for that mode */
-	let export_format: TrackExtension; /* = TrackExtension::Placeholder; */
-	let mut ffmpeg_args: Vec<String> = Vec::new();
+	let stats = match usage_log::load(&path) {
+		Ok(stats) => stats,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
 	
-	match convert_mode.parse::<TrackExtension>() {
-		/* Supported Formats */
-		// XXX: Only audio ones initially, since that's easier than generating visuals for those without them
-		Ok(TrackExtension::mp3) => {
-			export_format = TrackExtension::mp3;
-			
-			ffmpeg_args.push("-vn".to_string()); // Only audio feed
-			//ffmpeg_args.push(format!("-acodec={:?}", export_format).to_string());
-			
-			// TODO: Audio quality
-		},
-		Ok(TrackExtension::flac) => {
-			export_format = TrackExtension::flac;
-			
-			ffmpeg_args.push("-vn".to_string()); // Only audio feed
-			//ffmpeg_args.push(format!("-acodec={:?}", export_format).to_string());
-		},
-		Ok(TrackExtension::ogg) => {
-			export_format = TrackExtension::ogg;
-			
-			ffmpeg_args.push("-vn".to_string()); // Only audio feed
-			//ffmpeg_args.push(format!("-acodec={:?}", export_format).to_string());
-		},
-		
-		/* Unsupported formats - All video formats and Unknown Extensions */
-		Ok(TrackExtension::Unknown(ext)) => {
-			eprintln!("Error: Unsupported/unknown output format ({0:?})", ext);
-			process::exit(1);
-		},
-		Ok(t) => {
-			eprintln!("Error: Cannot export to video format ({0:?})", t);
-			process::exit(1);
+	if command_args::has_flag(args, "--json") {
+		match serde_json::to_string_pretty(&stats) {
+			Ok(j) => println!("{}", j),
+			Err(e) => {
+				eprintln!("Couldn't convert usage stats to JSON - {:?}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+	}
+	else if let Err(why) = write_usage_stats(&mut io::stdout(), &stats) {
+		eprintln!("ERROR: Couldn't write usage stats - {}", why);
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Print the JSON Schema for the named kind of JSON output (see json_schema),
+ * or check a file against it ("--validate=<file>")
+ */
+fn schema_command(args: &Vec<String>)
+{
+	let kind: json_schema::OutputKind = match args.get(2).map(|name| name.parse()) {
+		Some(Ok(kind)) => kind,
+		Some(Err(e)) => {
+			eprintln!("ERROR: {}", e);
+			exit_run(run_outcome::EXIT_FAILED);
 		},
-		
-		/* Parsing Error - Invalid argument */
-		_ => {
-			eprintln!("Error: Parsing error for convert_mode argument");
-			process::exit(1);
+		None => {
+			let names: Vec<&str> = json_schema::OUTPUT_KINDS.iter().map(|kind| kind.name()).collect();
+			eprintln!("ERROR: Usage - schema <{}>", names.join("|"));
+			exit_run(run_outcome::EXIT_FAILED);
 		}
+	};
+	
+	/* Check some output against the schema, instead of printing it */
+	if let Some(path) = command_args::get_option(args, "--validate") {
+		let text = match fs::read_to_string(path) {
+			Ok(text) => text,
+			Err(e) => {
+				eprintln!("ERROR: Couldn't read {0} - {1}", path, e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		};
+		match json_schema::validate_output(kind, &text) {
+			Ok(_) => println!("{0} matches the {1} schema", terminal_text::sanitize(path), kind),
+			Err(errors) => {
+				eprintln!("{0} doesn't match the {1} schema:", terminal_text::sanitize(path), kind);
+				for error in errors.iter() {
+					eprintln!("   {}", terminal_text::sanitize(error));
+				}
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		return;
 	}
 	
-	/* Add additional args the user specified on the command-line to also get passed along
-	 * (i.e. allowing for customising the behaviour + tweaking it without recompiling)
-	 */
-	// XXX: Reconsider this if we want to provide options for the behaviour of this mode (that do not get sent to FFMPEG!)
-	for arg in args {
-		ffmpeg_args.push(arg.to_string());
+	match serde_json::to_string_pretty(&kind.schema()) {
+		Ok(j) => println!("{}", j),
+		Err(e) => {
+			eprintln!("Couldn't convert schema to JSON - {:?}", e);
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Look up the track at the given (1-based) position in the playlist, for compare_command()
+ * ! This function will terminate the process if the position is invalid
+ */
+fn get_track_at<'a>(xspf: &'a XspfPlaylist, in_file: &str, position: &str) -> &'a Track
+{
+	let track = position.parse::<usize>().ok()
+	                    .filter(|&p| p >= 1)
+	                    .and_then(|p| xspf.tracks.get(p - 1));
+	match track {
+		Some(track) => track,
+		None => {
+			eprintln!("ERROR: Invalid position '{0}' for {1:?} (playlist has {2} tracks)", position, in_file, xspf.len());
+			exit_run(run_outcome::EXIT_FAILED);
+		}
 	}
+}
+
+/* "compare <a.xspf> <b.xspf> <pos_a> <pos_b>" - Show which fields differ between a track in each playlist */
+fn compare_command(args: &Vec<String>)
+{
+	let (file_a, file_b, pos_a, pos_b) = match (args.get(2), args.get(3), args.get(4), args.get(5)) {
+		(Some(fa), Some(fb), Some(pa), Some(pb)) => (fa, fb, pa, pb),
+		_ => {
+			eprintln!("ERROR: Usage - compare <a.xspf> <b.xspf> <position in a> <position in b>");
+			exit_run(run_outcome::EXIT_FAILED);
+		}
+	};
 	
-	/* Parse XSPF Playlist... */
-	if let Some(xspf) = xspf_parser::parse_xspf(in_file) {
-		/* Ensure outdir exists */
-		let _dst_path_root = ensure_output_directory_exists(out_path);
+	if let (Some(xspf_a), Some(xspf_b)) = (load_playlist(file_a), load_playlist(file_b)) {
+		let track_a = get_track_at(&xspf_a, file_a, pos_a);
+		let track_b = get_track_at(&xspf_b, file_b, pos_b);
 		
-		/* Compute track index width - number of digits of padding to display before the number */
-		let track_index_width = xspf.track_index_width();
+		println!("a: track {0} of {1} - {2}", pos_a, file_a, terminal_text::sanitize(&track_a.filename));
+		println!("b: track {0} of {1} - {2}", pos_b, file_b, terminal_text::sanitize(&track_b.filename));
 		
-		/* Loop over tracks copying them to the folder */
-		let mut dest_filenames : Vec<String> = Vec::new();
+		let changes = track_diff::track_field_diff(track_a, track_b);
+		if changes.is_empty() {
+			println!("\nTracks are identical");
+		}
+		else {
+			println!("\n~ changed ({} fields)", changes.len());
+			let _ = track_diff::write_field_changes(&mut io::stdout(), &changes, "    ");
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Write what changed between the playlists as text
+ * Helper for diff_mode()
+ */
+fn write_playlist_diff(out: &mut dyn Write, diff: &playlist_diff::PlaylistDiff) -> io::Result<()>
+{
+	if diff.is_empty() {
+		return writeln!(out, "Playlists are identical");
+	}
+	
+	let duration = |ms: Option<i64>| ms.map(|ms| track_duration::TrackDuration(ms).to_timecode()).unwrap_or("(none)".to_string());
+	
+	if !diff.added.is_empty() {
+		writeln!(out, "+ added ({})", diff.added.len())?;
+		for t in diff.added.iter() {
+			writeln!(out, "    {0:3} | {1}", t.index + 1, terminal_text::sanitize(&t.path))?;
+		}
+	}
+	if !diff.removed.is_empty() {
+		writeln!(out, "- removed ({})", diff.removed.len())?;
+		for t in diff.removed.iter() {
+			writeln!(out, "    {0:3} | {1}", t.index + 1, terminal_text::sanitize(&t.path))?;
+		}
+	}
+	if !diff.relocated.is_empty() {
+		writeln!(out, "> relocated ({})", diff.relocated.len())?;
+		for t in diff.relocated.iter() {
+			writeln!(out, "    {0:3} -> {1:3} | {2} -> {3}", t.old_index + 1, t.new_index + 1,
+			         terminal_text::sanitize(&t.old_path), terminal_text::sanitize(&t.new_path))?;
+		}
+	}
+	if !diff.duration_changed.is_empty() {
+		writeln!(out, "~ duration changed ({})", diff.duration_changed.len())?;
+		for c in diff.duration_changed.iter() {
+			writeln!(out, "    {0:3} -> {1:3} | {2} | {3} -> {4}", c.track.old_index + 1, c.track.new_index + 1,
+			         terminal_text::sanitize(&c.track.new_path), duration(c.old_duration_ms), duration(c.new_duration_ms))?;
+		}
+	}
+	if !diff.reordered.is_empty() {
+		writeln!(out, "^ moved position ({})", diff.reordered.len())?;
+		for t in diff.reordered.iter() {
+			writeln!(out, "    {0:3} -> {1:3} | {2}", t.old_index + 1, t.new_index + 1, terminal_text::sanitize(&t.new_path))?;
+		}
+	}
+	Ok(())
+}
+
+/* Show what changed between two versions of a playlist (e.g. an archived copy, and the one that's since been edited)
+ * NOTE: Any differences count as a failure, so the exit code says whether they're identical
+ */
+fn diff_mode(old_file: &str, new_file: &str, args: &Vec<String>)
+{
+	if let (Some(old), Some(new)) = (load_playlist(old_file), load_playlist(new_file)) {
+		let diff = old.diff(&new);
 		
-		for (track_idx, track) in xspf.tracks.iter().enumerate() {
-			/* Construct filename for copied file - it needs to have enough metadata to figure out what's going on */
-			let dst_filename = track_get_destination_filename(track, track_idx, track_index_width, Some(export_format.clone()));
-			
-			/* Construct paths to actually perform the copying to/from */
-			let src_path = &track.path;
-			let dst_path = Path::new(out_path).join(dst_filename.to_string())
-			                                  .into_os_string().into_string().unwrap();
-			
-			/* Convert or copy this track:
-			 * Do not convert if the file is already in the desired format, as converting files multiple times
-			 * will cause quality loss each time this happens.
-			 */
-			if track.info.extn == export_format {
-				/* Just perform copy operation */
-				if copy_track(src_path, &dst_path) {
-					/* Success - Note this as one of the successful files */
-					dest_filenames.push(dst_filename);
+		if command_args::has_flag(args, "--json") {
+			match serde_json::to_string_pretty(&diff) {
+				Ok(j) => println!("{}", j),
+				Err(e) => {
+					eprintln!("Couldn't convert playlist diff to JSON - {:?}", e);
+					exit_run(run_outcome::EXIT_FAILED);
 				}
 			}
-			else {
-				/* Perform convert operation */
-				if convert_track(src_path, &dst_path, &ffmpeg_args) {
-					/* Success - Note this as one of the successful files */
-					dest_filenames.push(dst_filename);
-				}
+		}
+		else {
+			println!("Comparing {0} ({1} tracks) -> {2} ({3} tracks):", old_file, old.len(), new_file, new.len());
+			if let Err(why) = write_playlist_diff(&mut io::stdout(), &diff) {
+				eprintln!("ERROR: Couldn't write playlist diff - {}", why);
+				exit_run(run_outcome::EXIT_FAILED);
 			}
 		}
 		
-		/* Dump list of copied files to <out_path>/<playlist_filename>.m3u
-		 * (i.e. a playable playlist, that also acts as a manifest of the set of files copied)
-		 */
-		write_copied_files_manifest(in_file, out_path, &dest_filenames);
+		if !diff.is_empty() {
+			run_outcome::record_failure(run_outcome::PLAYLISTS_DIFFER_CATEGORY);
+		}
+	}
+}
+
+/* --------------------------------------------- */
+
+/* Show what gets worked out from each of the given filenames (type, index, variant, name, and extension),
+ * without needing a playlist - e.g. for checking how new names get read by the naming rules
+ */
+fn parse_name_command(args: &Vec<String>)
+{
+	let names: Vec<&String> = args[2 ..].iter().filter(|a| !a.starts_with("--")).collect();
+	if names.is_empty() {
+		eprintln!("ERROR: Usage - parse-name <filename> [<filename> ...] [--json]");
+		exit_run(run_outcome::EXIT_FAILED);
+	}
+	
+	/* Names that can't be parsed get reported (and left as null in the JSON, so the rest still line up with the names) */
+	let results: Vec<Result<track_name_info::FilenameInfoComponents, String>> =
+		names.iter().map(|name| track_name_info::FilenameInfoComponents::new(name)).collect();
+	for e in results.iter().filter_map(|r| r.as_ref().err()) {
+		eprintln!("ERROR: {}", terminal_text::sanitize(e));
+		run_outcome::record_failure(run_outcome::UNPARSABLE_NAME_CATEGORY);
+	}
+	
+	if command_args::has_flag(args, "--json") {
+		let infos: Vec<Option<&track_name_info::FilenameInfoComponents>> = results.iter().map(|r| r.as_ref().ok()).collect();
+		match serde_json::to_string_pretty(&infos) {
+			Ok(json) => println!("{}", json),
+			Err(e) => {
+				eprintln!("ERROR: Couldn't serialise the results - {}", e);
+				exit_run(run_outcome::EXIT_FAILED);
+			}
+		}
+		return;
+	}
+	
+	let name_width = names.iter().map(|name| terminal_text::sanitize(name).chars().count()).max().unwrap_or(0);
+	for (name, result) in names.iter().zip(results.iter()) {
+		if let Ok(ref info) = *result {
+			let mut line = format!("{0:<1$}  type={2:<4} index={3:<3} variant={4:<4} name='{5}'  ext={6}",
+			                       terminal_text::sanitize(name), name_width, info.track_type.shortname(), info.index,
+			                       info.variant.as_deref().unwrap_or("-"),
+			                       terminal_text::sanitize(&info.name), info.extn);
+			if let Some(ref revision) = info.revision {
+				line.push_str(&format!("  revision={}", terminal_text::sanitize(revision)));
+			}
+			if let Some(ref date) = info.date {
+				line.push_str(&format!("  date={}", date));
+			}
+			println!("{}", line);
+		}
 	}
 }
 
 /* --------------------------------------------- */
 
+/* Warn if the input file doesn't look like a playlist (going by its extension)
+ * NOTE: "-" reads from stdin, which doesn't have an extension to check
+ */
+fn warn_if_not_playlist_file(in_file: &str)
+{
+	let is_xspf = in_file.ends_with(".xspf") || in_file.ends_with(".xspf.gz");
+	if !is_xspf && !m3u_parser::is_m3u_file(in_file) && !playlist_input::is_stdin(in_file) {
+		println!("WARNING: Input file should have the '.xspf' extension (or '.xspf.gz', '.m3u'/'.m3u8')");
+	}
+}
+
 fn handle_xspf_processing_mode(args: &Vec<String>, processing_func: XspfProcessingModeFunc)
 {
 	let in_file_option = args.get(2);
-	let out_file_option = args.get(3);
+	
+	/* The output file is optional, so any "--options" may start immediately after the input file */
+	let out_file_option = args.get(3).filter(|x| !x.starts_with("--"));
+	let options_start_index = if out_file_option.is_some() { 4 } else { 3 };
 	
 	match in_file_option {
 		Some(in_file) => {
-			if in_file.ends_with(".xspf") == false {
-				println!("WARNING: Input file should have the '.xspf' extension");
-			}
+			warn_if_not_playlist_file(in_file);
 			
 			match processing_func {
 				XspfProcessingModeFunc::InOnly(func) => {
@@ -602,26 +5597,52 @@ fn handle_xspf_processing_mode(args: &Vec<String>, processing_func: XspfProcessi
 				},
 				XspfProcessingModeFunc::InOutWithArgs(func) => {
 					/* Input File + Optional Output File + Optional args  */
-					let command_args = extract_command_args_list(args, 4);
+					let command_args = extract_command_args_list(args, options_start_index);
 					
 					/* Run the command */
 					func(in_file, out_file_option, &command_args);
 				},
 				XspfProcessingModeFunc::InOutModeWithArgs(func) => {
 					/* Input File + Mandatory Output File/Directory + Mandatory Mode + Optional Args */
-					let out_path = out_file_option.expect("Output file/directory must be supplied as the 3rd argument to the program");
-					let mode_arg = args.get(4).expect("Mode argument must be supplied as the 4th argument to the program");
+					let (out_path, mode_arg) = match (out_file_option, args.get(4)) {
+						(Some(out_path), Some(mode_arg)) => (out_path, mode_arg),
+						(None, _) => {
+							eprintln!("ERROR: Output file/directory must be supplied as the 3rd argument to the program");
+							exit_run(run_outcome::EXIT_FAILED);
+						},
+						(Some(_), None) => {
+							eprintln!("ERROR: Mode argument must be supplied as the 4th argument to the program");
+							exit_run(run_outcome::EXIT_FAILED);
+						}
+					};
 					
 					let command_args = extract_command_args_list(args, 5);
 					
 					/* Run the command */
 					func(in_file, out_path, mode_arg, &command_args);
+				},
+				XspfProcessingModeFunc::InInWithArgs(func) => {
+					/* Two Input Files + Optional Args (the second input takes the place of the output file) */
+					let in_file_b = match out_file_option {
+						Some(in_file_b) => in_file_b,
+						None => {
+							eprintln!("ERROR: A second .xspf filename must be supplied as the 3rd argument to the program");
+							exit_run(run_outcome::EXIT_FAILED);
+						}
+					};
+					warn_if_not_playlist_file(in_file_b);
+					
+					let command_args = extract_command_args_list(args, options_start_index);
+					
+					/* Run the command */
+					func(in_file, in_file_b, &command_args);
 				}
 			}
 		},
 		None => {
 			println!("ERROR: You need to supply a .xspf filename as the second argument\n");
 			print_usage_info();
+			exit_run(run_outcome::EXIT_FAILED);
 		}
 	}
 }
@@ -634,6 +5655,12 @@ fn main()
 	let args: Vec<String> = env::args().collect();
 	
 	if let Some(mode) = args.get(1) {
+		open_journal();
+		run_journal::record(run_journal::JournalEntry::ModeStarted { mode: mode.clone(), args: args.clone() });
+		let start_time = std::time::Instant::now();
+		let mut recorded = true;
+		let mut usage_error = false;
+		
 		/* A mode string was supplied - Process it!
 		 *
 		 * XXX: It would've been nice to handle the unsupplied case here too,
@@ -647,19 +5674,155 @@ fn main()
 			},
 			
 			"list" => {
-				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOut(list_output_mode));
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(list_output_mode));
 			},
 			
 			"json" => {
-				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOut(json_output_mode));
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(json_output_mode));
 			},
 			
 			"runtime" => {
-				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOnly(total_duration_mode));
+				if args.iter().skip(2).filter(|a| !a.starts_with("--")).count() > 1 {
+					multi_total_duration_mode(&args);
+				}
+				else {
+					handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOnly(total_duration_mode));
+				}
+			},
+			
+			"freshness" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(freshness_mode));
+			},
+			
+			"gaps" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(gaps_mode));
+			},
+			
+			"check" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(check_mode));
+			},
+			
+			"missing" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOut(missing_files_mode));
+			},
+			"relocate" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOut(relocate_mode));
+			},
+			
+			"stats" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(stats_mode));
+			},
+			
+			"verify-order" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(verify_order_mode));
+			},
+			"sort" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(sort_mode));
+			},
+			
+			"shuffle" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(shuffle_mode));
+			},
+			
+			"near-dupes" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(near_dupes_mode));
+			},
+			"dupes" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(dupes_mode));
+			},
+			
+			"probe" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(probe_mode));
+			},
+			"verify" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(verify_mode));
+			},
+			
+			"cache" => {
+				cache_command(&args);
+			},
+			
+			"bundle" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(bundle_mode));
+			},
+			
+			"unbundle" => {
+				unbundle_command(&args);
+			},
+			"webplayer" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(webplayer_mode));
+			},
+			
+			"edit" => {
+				edit_command(&args);
+			},
+			
+			"number-titles" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(number_titles_mode));
+			},
+			
+			"write" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOut(write_mode));
+			},
+			
+			"head" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(head_mode));
+			},
+			"tail" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(tail_mode));
+			},
+			
+			"practice" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(practice_mode));
+			},
+			
+			"split-types" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(split_types_mode));
+			},
+			"split" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(split_mode));
+			},
+			
+			"compare" => {
+				compare_command(&args);
+			},
+			"diff" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InInWithArgs(diff_mode));
+			},
+			
+			"parse-name" => {
+				parse_name_command(&args);
+			},
+			
+			"merge" => {
+				merge_command(&args);
+			},
+			
+			"usage" => {
+				usage_command(&args);
+				recorded = false;
+			},
+			
+			"schema" => {
+				schema_command(&args);
 			},
 			
 			"copy" => {
-				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOut(copy_files_mode));
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(copy_files_mode));
+			},
+			"move" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(move_files_mode));
+			},
+			
+			"sample" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(sample_mode));
+			},
+			
+			"estimate" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOutWithArgs(estimate_mode));
+			},
+			"size" => {
+				handle_xspf_processing_mode(&args, XspfProcessingModeFunc::InOnly(size_mode));
 			},
 			
 			"convert" => {
@@ -673,12 +5836,33 @@ fn main()
 			arg => {
 				println!("Unrecognised option: '{0:?}'", arg);
 				print_usage_info();
+				recorded = false;
+				usage_error = true;
 			},
 		}
+		
+		run_journal::record(run_journal::JournalEntry::ModeFinished {
+			mode: mode.clone(),
+			elapsed_ms: start_time.elapsed().as_millis() as u64,
+		});
+		
+		/* Count the run (apart from looking at the counts themselves, or mistyped modes) */
+		if let (true, Some(path)) = (recorded, usage_log_path()) {
+			usage_log::record_run(&path, mode, start_time.elapsed());
+		}
+		
+		/* Summarise how it went (apart from just showing the usage info) */
+		if recorded && mode != "help" {
+			exit_run(run_outcome::EXIT_OK);
+		}
+		else if usage_error {
+			process::exit(run_outcome::EXIT_FAILED);
+		}
 	}
 	else {
 		/* No mode arg at all - i.e. user really doesn't know what they're doing */
 		/* XXX: ideally, this would have been included above, instead of in here... */
 		print_usage_info();
+		process::exit(run_outcome::EXIT_FAILED);
 	}
 }
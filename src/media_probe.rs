@@ -0,0 +1,134 @@
+/* Probing media files for their technical details (duration, codec, etc.)
+ *
+ * This shells out to "ffprobe" (part of FFMPEG), in the same way that the
+ * convert mode relies on "ffmpeg" being on the path.
+ */
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::{self, Value};
+
+/* Details about a media file, as reported by the prober */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct ProbeInfo {
+	/* Duration of the file (in ms) */
+	pub duration_ms: Option<i64>,
+	/* Name of the codec used for the (first) audio stream */
+	pub codec: Option<String>,
+	/* Sample rate (in Hz) of the audio stream */
+	pub sample_rate: Option<u32>,
+	/* Number of channels in the audio stream */
+	pub channels: Option<u32>,
+}
+
+/* Something which can look up the details of media files
+ * (NOTE: This is a trait so that tests can substitute their own implementations)
+ */
+pub trait Prober {
+	fn probe(&self, path: &Path) -> Result<ProbeInfo, String>;
+}
+
+/* *************************************************** */
+
+/* Prober using the "ffprobe" tool */
+pub struct FfprobeProber;
+
+impl Prober for FfprobeProber {
+	fn probe(&self, path: &Path) -> Result<ProbeInfo, String>
+	{
+		let output = Command::new("ffprobe")
+		                .args(["-v", "error",
+		                       "-print_format", "json",
+		                       "-show_entries", "format=duration:stream=codec_type,codec_name,sample_rate,channels"])
+		                .arg(path)
+		                .output()
+		                .map_err(|e| format!("Couldn't run ffprobe - {}", e))?;
+
+		if !output.status.success() {
+			let stderr = String::from_utf8_lossy(&output.stderr);
+			return Err(format!("ffprobe failed - {}", stderr.trim()));
+		}
+
+		parse_ffprobe_output(&String::from_utf8_lossy(&output.stdout))
+	}
+}
+
+/* Extract the interesting info from ffprobe's JSON output */
+pub fn parse_ffprobe_output(json: &str) -> Result<ProbeInfo, String>
+{
+	let root: Value = serde_json::from_str(json)
+	                    .map_err(|e| format!("Couldn't understand ffprobe output - {}", e))?;
+
+	/* Duration is given as a string with the number of seconds (e.g. "123.456000") */
+	let duration_ms = root.get("format")
+	                      .and_then(|f| f.get("duration"))
+	                      .and_then(|d| d.as_str())
+	                      .and_then(|d| d.parse::<f64>().ok())
+	                      .map(|secs| (secs * 1000.0).round() as i64);
+
+	/* Use the first audio stream for the rest */
+	let audio_stream = root.get("streams")
+	                       .and_then(|s| s.as_array())
+	                       .and_then(|streams| {
+	                           streams.iter().find(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some("audio"))
+	                       });
+
+	let (codec, sample_rate, channels) = match audio_stream {
+		Some(stream) => {
+			let codec = stream.get("codec_name").and_then(|c| c.as_str()).map(|c| c.to_string());
+			let sample_rate = stream.get("sample_rate")
+			                        .and_then(|r| r.as_str())
+			                        .and_then(|r| r.parse::<u32>().ok());
+			let channels = stream.get("channels").and_then(|c| c.as_u64()).map(|c| c as u32);
+			(codec, sample_rate, channels)
+		},
+		None => (None, None, None)
+	};
+
+	Ok(ProbeInfo {
+		duration_ms: duration_ms,
+		codec: codec,
+		sample_rate: sample_rate,
+		channels: channels,
+	})
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_ffprobe_output()
+	{
+		let json = r#"{
+			"programs": [],
+			"streams": [
+				{ "codec_name": "png", "codec_type": "video" },
+				{ "codec_name": "mp3", "codec_type": "audio", "sample_rate": "44100", "channels": 2 }
+			],
+			"format": { "duration": "123.456000" }
+		}"#;
+
+		let info = parse_ffprobe_output(json).unwrap();
+		assert_eq!(Some(123456), info.duration_ms);
+		assert_eq!(Some("mp3".to_string()), info.codec);
+		assert_eq!(Some(44100), info.sample_rate);
+		assert_eq!(Some(2), info.channels);
+	}
+
+	#[test]
+	fn test_parse_ffprobe_output_missing_fields()
+	{
+		let info = parse_ffprobe_output(r#"{ "streams": [], "format": {} }"#).unwrap();
+		assert_eq!(None, info.duration_ms);
+		assert_eq!(None, info.codec);
+
+		assert!(parse_ffprobe_output("not json").is_err());
+	}
+}
@@ -0,0 +1,131 @@
+/* Folding of names, so that ones differing only by "encoding artifacts" compare equal
+ *
+ * Over the years, files have been re-exported by various tools which don't agree on
+ * things like whether to use spaces or underscores, or how accented letters get encoded.
+ * Anything comparing names for equality (near-duplicate checks, grouping takes of the
+ * same piece, searching) should go through fold_name() so that they all agree.
+ */
+
+/* *************************************************** */
+/* Unicode Composition */
+
+/* Precomposed forms of Latin letters with a combining mark
+ * (combining mark, base letters, composed letters - matched up by position)
+ *
+ * NOTE: This only covers the Latin-1 Supplement and Latin Extended-A ranges, which is
+ *       all that track names have needed so far. It isn't a complete NFC implementation.
+ */
+const COMPOSITIONS: [(char, &'static str, &'static str); 13] = [
+	('\u{0300}', "AEIOUaeiou",                "ÀÈÌÒÙàèìòù"),                  /* grave */
+	('\u{0301}', "AEIOUYaeiouyCcLlNnRrSsZz",  "ÁÉÍÓÚÝáéíóúýĆćĹĺŃńŔŕŚśŹź"),    /* acute */
+	('\u{0302}', "AEIOUaeiouCcGgHhJjSsWwYy",  "ÂÊÎÔÛâêîôûĈĉĜĝĤĥĴĵŜŝŴŵŶŷ"),    /* circumflex */
+	('\u{0303}', "ANOanoIiUu",                "ÃÑÕãñõĨĩŨũ"),                  /* tilde */
+	('\u{0304}', "AaEeIiOoUu",                "ĀāĒēĪīŌōŪū"),                  /* macron */
+	('\u{0306}', "AaEeGgIiOoUu",              "ĂăĔĕĞğĬĭŎŏŬŭ"),                /* breve */
+	('\u{0307}', "CcEeGgIZz",                 "ĊċĖėĠġİŻż"),                   /* dot above */
+	('\u{0308}', "AEIOUaeiouyY",              "ÄËÏÖÜäëïöüÿŸ"),                /* diaeresis */
+	('\u{030A}', "AaUu",                      "ÅåŮů"),                        /* ring above */
+	('\u{030B}', "OoUu",                      "ŐőŰű"),                        /* double acute */
+	('\u{030C}', "CcDdEeLlNnRrSsTtZz",        "ČčĎďĚěĽľŇňŘřŠšŤťŽž"),          /* caron */
+	('\u{0327}', "CcGgKkLlNnRrSsTt",          "ÇçĢģĶķĻļŅņŖŗŞşŢţ"),            /* cedilla */
+	('\u{0328}', "AaEeIiUu",                  "ĄąĘęĮįŲų"),                    /* ogonek */
+];
+
+/* Look up the precomposed form of base + combining mark (if there is one) */
+fn compose_pair(base: char, mark: char) -> Option<char>
+{
+	let &(_, bases, composed) = COMPOSITIONS.iter().find(|&&(m, _, _)| m == mark)?;
+	let pos = bases.chars().position(|b| b == base)?;
+	composed.chars().nth(pos)
+}
+
+/* Convert decomposed letters (e.g. "e" + U+0301) into their precomposed forms (e.g. "é"),
+ * as per Unicode Normalization Form C (for the letters covered by COMPOSITIONS)
+ */
+pub fn compose_nfc(s: &str) -> String
+{
+	let mut result = String::with_capacity(s.len());
+	let mut pending: Option<char> = None;
+
+	for c in s.chars() {
+		if let Some(base) = pending {
+			if let Some(composed) = compose_pair(base, c) {
+				pending = Some(composed);
+				continue;
+			}
+			result.push(base);
+		}
+		pending = Some(c);
+	}
+	result.extend(pending);
+	result
+}
+
+/* *************************************************** */
+/* Folding */
+
+/* Fold a name (e.g. a filename minus extension) into a form where encoding artifacts are removed:
+ * - Unicode composition (NFC)
+ * - Underscores treated as spaces
+ * - Case folded
+ * - Trailing whitespace stripped
+ */
+pub fn fold_name(name: &str) -> String
+{
+	compose_nfc(name).replace('_', " ")
+	                 .to_lowercase()
+	                 .trim_end()
+	                 .to_string()
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_compose_nfc()
+	{
+		assert_eq!("caf\u{E9}", compose_nfc("cafe\u{0301}"));
+		assert_eq!("\u{C5}ngstr\u{F6}m", compose_nfc("A\u{030A}ngstro\u{0308}m"));
+		assert_eq!("\u{160}ostakovi\u{10D}", compose_nfc("S\u{030C}ostakovic\u{030C}"));
+
+		/* Already composed, or nothing to compose with */
+		assert_eq!("caf\u{E9}", compose_nfc("caf\u{E9}"));
+		assert_eq!("x\u{0301}", compose_nfc("x\u{0301}"));
+		assert_eq!("\u{0301}", compose_nfc("\u{0301}"));
+		assert_eq!("", compose_nfc(""));
+	}
+
+	#[test]
+	fn test_fold_space_vs_underscore()
+	{
+		assert_eq!(fold_name("v03-winds_of_flutter"), fold_name("v03-winds of flutter"));
+	}
+
+	#[test]
+	fn test_fold_nfc_vs_nfd()
+	{
+		assert_eq!(fold_name("v02-caf\u{E9}_au_lait"), fold_name("v02-cafe\u{0301}_au_lait"));
+	}
+
+	#[test]
+	fn test_fold_case()
+	{
+		assert_eq!(fold_name("v01-Tranquil_Moose"), fold_name("v01-tranquil_moose"));
+		assert_eq!(fold_name("v02-\u{C9}t\u{E9}"), fold_name("v02-\u{E9}t\u{E9}"));
+	}
+
+	#[test]
+	fn test_fold_trailing_space()
+	{
+		assert_eq!(fold_name("v04-celestial"), fold_name("v04-celestial "));
+		assert_eq!(fold_name("v04-celestial"), fold_name("v04-celestial_\t"));
+
+		/* Leading/inner whitespace is significant */
+		assert!(fold_name("v04-celestial") != fold_name(" v04-celestial"));
+		assert!(fold_name("v04-celestial") != fold_name("v04-celes tial"));
+	}
+}
@@ -0,0 +1,274 @@
+/* Check for tracks whose filenames differ only by encoding artifacts
+ *
+ * e.g. "v03-winds_of_flutter.mp3" vs "v03-winds of flutter.mp3", or names that only
+ * differ by a trailing space or how an accented letter was encoded. These are usually
+ * the same recording, exported several times over the years.
+ */
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+use name_folding::fold_name;
+use terminal_text::sanitize;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* *************************************************** */
+
+/* A track that's part of a cluster */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct NearDupeMember {
+	/* Index (0-based) of the track in the playlist */
+	pub track_index: usize,
+	/* Raw filename of the track */
+	pub filename: String,
+	/* Range of bytes in the filename which differ from the first member of the cluster */
+	pub diff_start: usize,
+	pub diff_end: usize,
+}
+
+/* Group of tracks (in the same folder) whose names are equal once folded */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct NearDupeCluster {
+	/* Folder containing the tracks */
+	pub directory: String,
+	/* The folded name that all the members share */
+	pub folded_name: String,
+	pub members: Vec<NearDupeMember>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct NearDupesReport {
+	pub clusters: Vec<NearDupeCluster>,
+}
+
+/* *************************************************** */
+
+/* Find the range of bytes in "a" which differ from "b"
+ * (i.e. everything except the common prefix and suffix, adjusted so it doesn't split any chars)
+ * > returns (start, end) byte offsets into "a"
+ */
+pub fn differing_range(a: &str, b: &str) -> (usize, usize)
+{
+	let (ab, bb) = (a.as_bytes(), b.as_bytes());
+
+	let mut prefix = ab.iter().zip(bb.iter()).take_while(|&(x, y)| x == y).count();
+	while !a.is_char_boundary(prefix) {
+		prefix -= 1;
+	}
+
+	let max_suffix = ab.len().min(bb.len()) - prefix;
+	let mut suffix = ab.iter().rev().zip(bb.iter().rev()).take(max_suffix).take_while(|&(x, y)| x == y).count();
+	while !a.is_char_boundary(ab.len() - suffix) || !b.is_char_boundary(bb.len() - suffix) {
+		suffix -= 1;
+	}
+
+	(prefix, ab.len() - suffix)
+}
+
+/* Filename with the differing range highlighted (e.g. "v03-winds[_of_]flutter.mp3") */
+pub fn highlight_range(filename: &str, start: usize, end: usize) -> String
+{
+	format!("{0}[{1}]{2}", &filename[.. start], &filename[start .. end], &filename[end ..])
+}
+
+/* Filename of the track, minus the extension */
+fn track_stem(track: &Track) -> String
+{
+	match Path::new(&track.filename).file_stem() {
+		Some(stem) => stem.to_string_lossy().into_owned(),
+		None       => track.filename.clone()
+	}
+}
+
+/* Folder containing the track */
+fn track_directory(track: &Track) -> String
+{
+	track.path.parent()
+}
+
+/* Find all clusters of near-duplicate tracks in the playlist
+ * NOTE: Only tracks in the same folder are compared, as the same name (e.g. "v01-improv")
+ *       gets used for different recordings on different days. Tracks with identical
+ *       filenames (i.e. the same file listed twice) don't count as near-duplicates.
+ */
+pub fn find_near_dupes(xspf: &XspfPlaylist) -> NearDupesReport
+{
+	/* (Folder, Folded Name) -> Track Indices */
+	let mut groups: BTreeMap<(String, String), Vec<usize>> = BTreeMap::new();
+	for (i, track) in xspf.tracks.iter().enumerate() {
+		let key = (track_directory(track), fold_name(&track_stem(track)));
+		groups.entry(key).or_default().push(i);
+	}
+
+	let mut clusters = Vec::new();
+	for ((directory, folded_name), indices) in groups.into_iter() {
+		let first = &xspf.tracks[indices[0]].filename;
+		if indices.iter().all(|&i| xspf.tracks[i].filename == *first) {
+			continue;
+		}
+
+		let members = indices.iter().map(|&i| {
+			let filename = &xspf.tracks[i].filename;
+			let (start, end) = if i == indices[0] {
+				/* Highlight where the first differs from the next different one */
+				let other = indices.iter()
+				                   .map(|&j| &xspf.tracks[j].filename)
+				                   .find(|f| *f != first)
+				                   .unwrap();  /* Safe - checked above that there's at least one */
+				differing_range(filename, other)
+			}
+			else {
+				differing_range(filename, first)
+			};
+
+			NearDupeMember {
+				track_index: i,
+				filename: filename.clone(),
+				diff_start: start,
+				diff_end: end,
+			}
+		}).collect();
+
+		clusters.push(NearDupeCluster {
+			directory: directory,
+			folded_name: folded_name,
+			members: members,
+		});
+	}
+
+	/* Report in playlist order */
+	clusters.sort_by_key(|c| c.members[0].track_index);
+	NearDupesReport { clusters: clusters }
+}
+
+/* *************************************************** */
+/* Output */
+
+impl NearDupesReport {
+	/* Write report as text */
+	pub fn write_text(&self, out: &mut dyn Write) -> io::Result<()>
+	{
+		if self.clusters.is_empty() {
+			writeln!(out, "No near-duplicate filenames found")?;
+			return Ok(());
+		}
+
+		writeln!(out, "Near-duplicate filenames ({} clusters):", self.clusters.len())?;
+		for cluster in self.clusters.iter() {
+			writeln!(out)?;
+			writeln!(out, "  '{0}' in {1:?}", sanitize(&cluster.folded_name), cluster.directory)?;
+			for m in cluster.members.iter() {
+				writeln!(out, "    track {0:3} | {1}  (bytes {2}..{3})",
+				         m.track_index + 1, sanitize(&highlight_range(&m.filename, m.diff_start, m.diff_end)),
+				         m.diff_start, m.diff_end)?;
+			}
+		}
+		Ok(())
+	}
+
+	/* Write suggested renames, as tab-separated "<old path>\t<new path>" lines
+	 * The first member of each cluster is taken as the canonical name, so everything else
+	 * gets renamed to match it.
+	 */
+	pub fn write_renames(&self, xspf: &XspfPlaylist, out: &mut dyn Write) -> io::Result<()>
+	{
+		writeln!(out, "# Suggested renames for near-duplicate filenames (<old path><TAB><new path>)")?;
+		writeln!(out, "# NOTE: The new names already exist - check which copy should be kept first!")?;
+
+		for cluster in self.clusters.iter() {
+			let canonical = &cluster.members[0].filename;
+			for m in cluster.members.iter().skip(1).filter(|m| m.filename != *canonical) {
+				let old_path = &xspf.tracks[m.track_index].path;
+				let new_path = old_path.with_file_name(canonical);
+				writeln!(out, "{0}\t{1}", old_path, new_path)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use diagnostics::Diagnostics;
+
+	fn make_playlist(paths: &[&str]) -> XspfPlaylist
+	{
+		XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_differing_range()
+	{
+		assert_eq!((9, 13), differing_range("v03-winds_of_flutter.mp3", "v03-winds of flutter.mp3"));
+		assert_eq!((13, 14), differing_range("v04-celestial .mp3", "v04-celestial.mp3"));
+		assert_eq!((13, 13), differing_range("v04-celestial.mp3", "v04-celestial .mp3"));
+
+		/* Doesn't split multi-byte chars (NFC "é" vs NFD "e" + U+0301) */
+		assert_eq!((7, 9), differing_range("v02-caf\u{E9}.mp3", "v02-cafe\u{0301}.mp3"));
+		assert_eq!((7, 10), differing_range("v02-cafe\u{0301}.mp3", "v02-caf\u{E9}.mp3"));
+
+		assert_eq!("v03-winds[_of_]flutter.mp3", highlight_range("v03-winds_of_flutter.mp3", 9, 13));
+	}
+
+	#[test]
+	fn test_find_near_dupes()
+	{
+		let xspf = make_playlist(&[
+			"/music/20170403/v03-winds_of_flutter.mp3",
+			"/music/20170403/v01-tranquil.mp3",
+			"/music/20170403/v03-winds of flutter.mp3",
+			"/music/20170404/v03-winds of flutter.mp3",   /* different day - not a dupe */
+			"/music/20170404/v01-tranquil.mp3",           /* different day - not a dupe */
+			"/music/20170404/v01-tranquil.mp3",           /* exact same file - not a near-dupe */
+			"/music/20170405/v02-Caf%C3%A9.mp3",
+			"/music/20170405/v02-cafe%CC%81 .mp3",
+		]);
+
+		let report = find_near_dupes(&xspf);
+		assert_eq!(2, report.clusters.len());
+
+		let winds = &report.clusters[0];
+		assert_eq!("v03-winds of flutter", winds.folded_name);
+		assert_eq!(vec![0, 2], winds.members.iter().map(|m| m.track_index).collect::<Vec<_>>());
+		assert_eq!((9, 13), (winds.members[1].diff_start, winds.members[1].diff_end));
+
+		let cafe = &report.clusters[1];
+		assert_eq!("v02-caf\u{E9}", cafe.folded_name);
+		assert_eq!(vec![6, 7], cafe.members.iter().map(|m| m.track_index).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_write_renames()
+	{
+		let xspf = make_playlist(&[
+			"/music/20170403/v03-winds_of_flutter.mp3",
+			"/music/20170403/v03-winds of flutter.mp3",
+		]);
+		let report = find_near_dupes(&xspf);
+
+		let mut out = Vec::new();
+		report.write_renames(&xspf, &mut out).unwrap();
+		let text = String::from_utf8(out).unwrap();
+
+		assert!(text.ends_with("/music/20170403/v03-winds of flutter.mp3\t/music/20170403/v03-winds_of_flutter.mp3\n"));
+	}
+}
@@ -0,0 +1,252 @@
+/* Writing .nfo metadata sidecars for exported tracks
+ *
+ * Media centers (e.g. Jellyfin, Kodi) pick up "<name>.nfo" files sitting next to media files, and use the
+ * details in them instead of guessing from the filename. With "--nfo", copy/convert write one of these
+ * alongside each output file, so the tracks show up with their proper names, dates, and genres.
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use diagnostics::Diagnostics;
+use split_types;
+use track_duration::TrackDuration;
+use xml_writer::XmlWriter;
+use xspf_parser::Track;
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "nfo";
+
+/* Extension used for the sidecar files */
+pub const NFO_EXTENSION: &'static str = "nfo";
+
+/* Genres used for each type of track (by type tag - see split_types::type_tag())
+ * Tracks of unknown type ("misc") don't get a genre, unless one is mapped for them
+ */
+const DEFAULT_GENRES: [(&'static str, &'static str); 4] = [
+	("VL", "Violin Layering"),
+	("MS", "MuseScore"),
+	("P",  "Piano"),
+	("V",  "Voice"),
+];
+
+/* All the type tags that genres can be mapped for */
+const TYPE_TAGS: [&'static str; 5] = ["VL", "MS", "P", "V", split_types::MISC_TAG];
+
+/* *************************************************** */
+/* Genre Mapping */
+
+/* Genre to use for each type of track */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct GenreMap {
+	genres: BTreeMap<String, String>,
+}
+
+impl Default for GenreMap {
+	fn default() -> GenreMap
+	{
+		GenreMap {
+			genres: DEFAULT_GENRES.iter().map(|&(tag, genre)| (tag.to_string(), genre.to_string())).collect(),
+		}
+	}
+}
+
+impl GenreMap {
+	/* Override the genre for a type, from a "<type>=<genre>" mapping (e.g. "VL=Improvisation")
+	 * An empty genre (e.g. "MS=") leaves the genre out for that type
+	 */
+	pub fn apply_mapping(&mut self, mapping: &str) -> Result<(), String>
+	{
+		let (tag, genre) = match mapping.find('=') {
+			Some(pos) => (mapping[.. pos].trim(), mapping[pos + 1 ..].trim()),
+			None      => return Err(format!("Invalid genre mapping - '{}' (expected <type>=<genre>)", mapping))
+		};
+		if !TYPE_TAGS.contains(&tag) {
+			return Err(format!("Unknown track type '{0}' in genre mapping (expected one of: {1})", tag, TYPE_TAGS.join(", ")));
+		}
+
+		if genre.is_empty() {
+			self.genres.remove(tag);
+		}
+		else {
+			self.genres.insert(tag.to_string(), genre.to_string());
+		}
+		Ok(())
+	}
+
+	/* Genre for a track (if its type has one) */
+	pub fn genre_for(&self, track: &Track) -> Option<&str>
+	{
+		self.genres.get(&split_types::type_tag(&track.info().track_type)).map(|s| s.as_str())
+	}
+}
+
+/* *************************************************** */
+/* Sidecar Documents */
+
+/* Path of the sidecar for an output file (i.e. with its extension replaced by ".nfo") */
+pub fn sidecar_path(dst_path: &Path) -> PathBuf
+{
+	dst_path.with_extension(NFO_EXTENSION)
+}
+
+/* Name to show for a track - its title from the playlist, or the name part of its filename */
+pub fn pretty_name(track: &Track) -> String
+{
+	match track.title {
+		Some(ref title) if !title.trim().is_empty() => title.clone(),
+		_                                           => track.info().name.clone()
+	}
+}
+
+/* Generate the .nfo document for a track
+ * - track_number: Position of the track in the output (1-based)
+ */
+pub fn nfo_document(track: &Track, track_number: usize, genres: &GenreMap) -> String
+{
+	let mut xml = XmlWriter::new();
+	xml.start("musicvideo", &[]);
+
+	/* NOTE: Paths from "file:///" URIs lose their leading slash (see Track::to_uri()), so the full path gets used where possible */
+	let source = fs::canonicalize(track.file_path()).unwrap_or_else(|_| track.file_path().to_path_buf());
+	xml.comment(&format!("Source: {}", source.display()));
+
+	xml.text_element("title", &pretty_name(track));
+	if let Some(session) = track.date.session_date() {
+		xml.text_element("premiered", &session.to_string());
+		xml.text_element("year", &session.year.to_string());
+	}
+	xml.text_element("track", &track_number.to_string());
+	if let Some(TrackDuration(ms)) = track.duration {
+		xml.text_element("durationinseconds", &((ms.max(0) + 500) / 1000).to_string());
+	}
+	if let Some(genre) = genres.genre_for(track) {
+		xml.text_element("genre", genre);
+	}
+
+	xml.finish()
+}
+
+/* *************************************************** */
+/* Writer */
+
+/* Writes the sidecars for the files exported during a run, keeping track of any problems */
+pub struct SidecarWriter {
+	pub genres: GenreMap,
+
+	/* Number of sidecars written */
+	pub written: usize,
+
+	/* Sidecars that couldn't be written */
+	pub diagnostics: Diagnostics,
+}
+
+impl SidecarWriter {
+	pub fn new(genres: GenreMap) -> SidecarWriter
+	{
+		SidecarWriter {
+			genres: genres,
+			written: 0,
+			diagnostics: Diagnostics::new(),
+		}
+	}
+
+	/* Write the sidecar for the output file of a track (at dst_path)
+	 * NOTE: Failures are only recorded (the output file itself is still fine)
+	 */
+	pub fn write(&mut self, track_idx: usize, track: &Track, dst_path: &Path)
+	{
+		let path = sidecar_path(dst_path);
+		match fs::write(&path, nfo_document(track, track_idx + 1, &self.genres)) {
+			Ok(_)  => self.written += 1,
+			Err(e) => self.diagnostics.warn_track(CATEGORY, track_idx,
+			                                      format!("Couldn't write '{0}' - {1}", path.display(), e))
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	fn fixture_track(path: &str) -> Track
+	{
+		let mut track = Track::from_filepath(path).unwrap();
+		track.duration = Some(TrackDuration(61_400));
+		track
+	}
+
+	#[test]
+	fn test_nfo_document()
+	{
+		let track = fixture_track("/music/20170403/v01-tranquil.mp3");
+		assert_eq!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		            <musicvideo>\n\
+		            \t<!-- Source: /music/20170403/v01-tranquil.mp3 -->\n\
+		            \t<title>tranquil</title>\n\
+		            \t<premiered>2017-04-03</premiered>\n\
+		            \t<year>2017</year>\n\
+		            \t<track>3</track>\n\
+		            \t<durationinseconds>61</durationinseconds>\n\
+		            \t<genre>Violin Layering</genre>\n\
+		            </musicvideo>\n",
+		           nfo_document(&track, 3, &GenreMap::default()));
+
+		/* Unknown types have no genre, and undated tracks no dates */
+		let track = Track::from_filepath("/music/misc/notes.mp3").unwrap();
+		let xml = nfo_document(&track, 1, &GenreMap::default());
+		assert!(xml.contains("\t<title>notes</title>\n\t<track>1</track>\n</musicvideo>"));
+	}
+
+	#[test]
+	fn test_genre_mapping()
+	{
+		let track = fixture_track("/music/20170403/v01-tranquil.mp3");
+		let mut genres = GenreMap::default();
+		genres.apply_mapping("VL=Improvisation").unwrap();
+		genres.apply_mapping("misc = Other").unwrap();
+		assert_eq!(Some("Improvisation"), genres.genre_for(&track));
+		assert_eq!(Some("Other"), genres.genre_for(&Track::from_filepath("/music/misc/notes.mp3").unwrap()));
+		assert!(nfo_document(&track, 1, &genres).contains("<genre>Improvisation</genre>"));
+
+		/* Later mappings win, and empty ones remove the genre */
+		genres.apply_mapping("VL=").unwrap();
+		assert_eq!(None, genres.genre_for(&track));
+
+		assert!(genres.apply_mapping("XX=Jazz").is_err());
+		assert!(genres.apply_mapping("Jazz").is_err());
+	}
+
+	#[test]
+	fn test_escaping()
+	{
+		let mut track = fixture_track("/music/20170404/v02-a&b--c.flac");
+		let xml = nfo_document(&track, 1, &GenreMap::default());
+		assert!(xml.contains("<!-- Source: /music/20170404/v02-a&b- -c.flac -->"));
+		assert!(xml.contains("<title>a&amp;b--c</title>"));
+
+		track.title = Some("Rock & <Roll>".to_string());
+		assert!(nfo_document(&track, 1, &GenreMap::default()).contains("<title>Rock &amp; &lt;Roll&gt;</title>"));
+	}
+
+	#[test]
+	fn test_sidecar_writer()
+	{
+		let dir = TempDir::new("nfo-sidecar");
+		let track = fixture_track("/music/20170403/v01-tranquil.mp3");
+		let mut writer = SidecarWriter::new(GenreMap::default());
+
+		writer.write(0, &track, &dir.path().join("Track_01-20170403-VL01_tranquil.mp3"));
+		let written = fs::read_to_string(dir.path().join("Track_01-20170403-VL01_tranquil.nfo")).unwrap();
+		assert!(written.contains("<track>1</track>"));
+
+		writer.write(4, &track, &dir.path().join("missing/Track_05.mp3"));
+		assert_eq!(1, writer.written);
+		assert_eq!(Some(4), writer.diagnostics.entries[0].track_index);
+	}
+}
@@ -0,0 +1,161 @@
+/* Opening the file a mode writes its output to (e.g. the paths from "list", or the JSON from "json")
+ *
+ * A mistyped output path (e.g. the playlist itself, instead of "<name>.txt") used to silently replace
+ * whatever was there, and a path in a folder that doesn't exist yet would crash the run. So now:
+ *  - Existing files are left alone (with an error describing what would have been replaced), unless
+ *    replacing them was asked for ("--overwrite-output")
+ *  - Missing folders are an error, unless creating them was asked for ("--mkdirs")
+ *
+ * Files get written through a buffer, as most modes write their output a line at a time.
+ */
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use interactive_resolve::format_mtime;
+use size_estimate::format_size;
+
+/* How to deal with what is (or isn't) at the output path already */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+pub struct OutputOptions {
+	/* Replace the file if it already exists */
+	pub overwrite: bool,
+	/* Create any folders leading up to the file that don't exist yet */
+	pub mkdirs: bool,
+}
+
+/* Description of the existing file at the given path (for saying what would get replaced) */
+fn describe_existing(path: &Path) -> String
+{
+	match fs::metadata(path) {
+		Ok(metadata) => {
+			let modified = metadata.modified().ok().map(format_mtime).unwrap_or_else(|| "?".to_string());
+			format!("{0:?} already exists ({1}, modified {2})", path, format_size(metadata.len()), modified)
+		},
+		Err(_) => format!("{:?} already exists", path)
+	}
+}
+
+/* Open the given file for writing (through a buffer)
+ * > returns an error (without touching anything) if the file already exists and "overwrite" isn't set,
+ *   or if the folder it goes in doesn't exist and "mkdirs" isn't set
+ */
+pub fn create_output_file(path: &Path, options: OutputOptions) -> io::Result<BufWriter<File>>
+{
+	if path.is_dir() {
+		return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} is a folder, not a file", path)));
+	}
+
+	if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+		if !parent.is_dir() {
+			if !options.mkdirs {
+				return Err(io::Error::new(io::ErrorKind::NotFound,
+					format!("folder {:?} doesn't exist - use --mkdirs to create it", parent)));
+			}
+			fs::create_dir_all(parent)?;
+		}
+	}
+
+	/* NOTE: "create_new" checks for an existing file as part of creating it, so nothing can sneak in between */
+	let file = if options.overwrite {
+			File::create(path)
+		}
+		else {
+			OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| {
+				if e.kind() == io::ErrorKind::AlreadyExists {
+					io::Error::new(io::ErrorKind::AlreadyExists,
+						format!("{} - use --overwrite-output to replace it", describe_existing(path)))
+				}
+				else {
+					e
+				}
+			})
+		};
+	file.map(BufWriter::new)
+}
+
+/* Stream to write output to - the given file (see create_output_file()), or stdout if there isn't one */
+pub fn open_output(out_file: Option<&Path>, options: OutputOptions) -> io::Result<Box<dyn Write>>
+{
+	match out_file {
+		Some(path) => Ok(Box::new(create_output_file(path, options)?)),
+		None       => Ok(Box::new(io::stdout()))
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	#[test]
+	fn test_create_new()
+	{
+		let dir = TempDir::new("output-new");
+		let path = dir.path().join("tracks.txt");
+
+		let mut out = create_output_file(&path, OutputOptions::default()).unwrap();
+		writeln!(out, "/music/20170403/v01-tranquil.mp3").unwrap();
+		drop(out);
+		assert_eq!("/music/20170403/v01-tranquil.mp3\n", fs::read_to_string(&path).unwrap());
+	}
+
+	#[test]
+	fn test_refuse_overwrite()
+	{
+		let dir = TempDir::new("output-existing");
+		let path = dir.write_file("sessions.xspf", b"<playlist/>");
+
+		let e = create_output_file(&path, OutputOptions::default()).err().unwrap();
+		assert_eq!(io::ErrorKind::AlreadyExists, e.kind());
+		assert!(e.to_string().contains("sessions.xspf"));
+		assert!(e.to_string().contains("11 B"));
+		assert!(e.to_string().contains("--overwrite-output"));
+
+		/* ... leaving it as it was */
+		assert_eq!("<playlist/>", fs::read_to_string(&path).unwrap());
+
+		/* Unless asked to */
+		let options = OutputOptions { overwrite: true, ..OutputOptions::default() };
+		let mut out = create_output_file(&path, options).unwrap();
+		write!(out, "replaced").unwrap();
+		out.flush().unwrap();
+		assert_eq!("replaced", fs::read_to_string(&path).unwrap());
+
+		/* Folders never get replaced */
+		let e = create_output_file(dir.path(), options).err().unwrap();
+		assert_eq!(io::ErrorKind::AlreadyExists, e.kind());
+	}
+
+	#[test]
+	fn test_missing_folder()
+	{
+		let dir = TempDir::new("output-mkdirs");
+		let path = dir.path().join("reports/2017/tracks.txt");
+
+		let e = create_output_file(&path, OutputOptions::default()).err().unwrap();
+		assert_eq!(io::ErrorKind::NotFound, e.kind());
+		assert!(e.to_string().contains("--mkdirs"));
+		assert!(!dir.path().join("reports").exists());
+
+		let options = OutputOptions { mkdirs: true, ..OutputOptions::default() };
+		create_output_file(&path, options).unwrap();
+		assert!(path.is_file());
+	}
+
+	/* Write errors for the buffered output can be held back until it gets flushed */
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_flush_failure()
+	{
+		let options = OutputOptions { overwrite: true, ..OutputOptions::default() };
+		let mut out = create_output_file(Path::new("/dev/full"), options).unwrap();
+		writeln!(out, "/music/20170403/v01-tranquil.mp3").unwrap();
+		assert!(out.flush().is_err());
+	}
+}
@@ -0,0 +1,363 @@
+/* Advisory lock on an output directory
+ *
+ * Prevents two runs (e.g. a scheduled sync and a manual run) from writing into the same
+ * output directory (and its manifest) at the same time. The lock is a file in the directory,
+ * recording the PID of the process holding it, and when it started.
+ *
+ * Locks left behind by processes that are no longer running (e.g. after a crash) are
+ * considered stale, and get replaced automatically.
+ */
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+/* Name of the lock file (in the output directory) */
+pub const LOCK_FILENAME: &'static str = ".xspf_tools.lock";
+
+/* Used to give each temp file written while taking a lock a unique name (for locks taken from several threads) */
+static TEMP_LOCK_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/* *************************************************** */
+/* Process Liveness */
+
+/* Something which can tell whether a process is still running
+ * (NOTE: This is a trait so that tests can substitute their own implementations)
+ */
+pub trait ProcessChecker {
+	fn is_running(&self, pid: u32) -> bool;
+}
+
+/* Checks the processes actually running on this machine */
+pub struct SystemProcessChecker;
+
+#[cfg(unix)]
+impl ProcessChecker for SystemProcessChecker {
+	fn is_running(&self, pid: u32) -> bool
+	{
+		use std::process::{Command, Stdio};
+
+		/* Linux (and friends) - Every running process has an entry here */
+		let proc_dir = Path::new("/proc");
+		if proc_dir.join("self").exists() {
+			return proc_dir.join(pid.to_string()).exists();
+		}
+
+		/* Elsewhere - Signal 0 checks that the process exists, without affecting it */
+		Command::new("kill").arg("-0").arg(pid.to_string())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.status()
+			.map(|status| status.success())
+			.unwrap_or(true)  /* Can't tell - Assume it's still running, to be safe */
+	}
+}
+
+#[cfg(not(unix))]
+impl ProcessChecker for SystemProcessChecker {
+	fn is_running(&self, pid: u32) -> bool
+	{
+		use std::process::Command;
+
+		match Command::new("tasklist").args(&["/FI", &format!("PID eq {}", pid), "/NH"]).output() {
+			Ok(output) => String::from_utf8_lossy(&output.stdout).split_whitespace().any(|w| w == pid.to_string()),
+			Err(_)     => true  /* Can't tell - Assume it's still running, to be safe */
+		}
+	}
+}
+
+/* *************************************************** */
+/* Lock File */
+
+/* Contents of the lock file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub struct LockInfo {
+	/* Process holding the lock */
+	pub pid: u32,
+	/* When the lock was taken (seconds since the unix epoch) */
+	pub started: u64,
+}
+
+/* Problems taking the lock */
+#[derive(Debug)]
+pub enum LockError {
+	/* Another process that's still running holds the lock */
+	Held { info: LockInfo, age_secs: u64 },
+	/* Lock file couldn't be created/checked */
+	IoError(io::Error),
+}
+
+impl fmt::Display for LockError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			LockError::Held { ref info, age_secs } => {
+				write!(f, "Output directory is in use by another run (PID {0}, started {1} ago) - \
+				           use --force-unlock if it isn't really running",
+				       info.pid, format_age(age_secs))
+			},
+			LockError::IoError(ref e) => write!(f, "Couldn't lock output directory - {}", e),
+		}
+	}
+}
+
+impl From<io::Error> for LockError {
+	fn from(e: io::Error) -> LockError
+	{
+		LockError::IoError(e)
+	}
+}
+
+/* Human-readable version of a time span, e.g. "2h 5m", "3m 10s", "42s" */
+pub fn format_age(secs: u64) -> String
+{
+	match secs {
+		0 ..= 59    => format!("{}s", secs),
+		60 ..= 3599 => format!("{0}m {1}s", secs / 60, secs % 60),
+		_           => format!("{0}h {1}m", secs / 3600, (secs % 3600) / 60),
+	}
+}
+
+fn now_secs() -> u64
+{
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/* Lock held on an output directory - The lock gets released when this is dropped */
+#[derive(Debug)]
+pub struct OutputLock {
+	path: PathBuf,
+	info: LockInfo,
+}
+
+impl OutputLock {
+	/* Take the lock on the given directory (for this process)
+	 * - force: Take over the lock, even if the process holding it seems to be running (i.e. "--force-unlock")
+	 */
+	pub fn acquire(dir: &Path, force: bool, checker: &dyn ProcessChecker) -> Result<OutputLock, LockError>
+	{
+		let info = LockInfo { pid: process::id(), started: now_secs() };
+		OutputLock::acquire_as(dir, info, force, checker)
+	}
+
+	/* Implementation of acquire(), with the details to record supplied (so tests can pretend to be other processes) */
+	pub fn acquire_as(dir: &Path, info: LockInfo, force: bool, checker: &dyn ProcessChecker) -> Result<OutputLock, LockError>
+	{
+		OutputLock::acquire_file_as(dir.join(LOCK_FILENAME), info, force, checker)
+	}
+
+	/* Take the lock using the given lock file, instead of one for a whole directory
+	 * (e.g. for guarding updates to a single file that several runs share)
+	 */
+	pub fn acquire_file(path: &Path, force: bool, checker: &dyn ProcessChecker) -> Result<OutputLock, LockError>
+	{
+		let info = LockInfo { pid: process::id(), started: now_secs() };
+		OutputLock::acquire_file_as(path.to_path_buf(), info, force, checker)
+	}
+
+	fn acquire_file_as(path: PathBuf, info: LockInfo, force: bool, checker: &dyn ProcessChecker) -> Result<OutputLock, LockError>
+	{
+		let contents = serde_json::to_string(&info).map_err(io::Error::other)?;
+
+		/* Try twice - The second time is after clearing out a stale lock */
+		for _ in 0 .. 2 {
+			match create_lock_file(&path, &contents) {
+				Ok(_) => {
+					return Ok(OutputLock { path: path, info: info });
+				},
+				Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+					/* Check whether the existing lock is still valid
+					 * NOTE: Unreadable locks (e.g. a crash mid-write) are treated as stale
+					 */
+					let existing = fs::read_to_string(&path).ok()
+					                 .and_then(|s| serde_json::from_str::<LockInfo>(&s).ok());
+					if let Some(existing) = existing {
+						if !force && checker.is_running(existing.pid) {
+							let age_secs = info.started.saturating_sub(existing.started);
+							return Err(LockError::Held { info: existing, age_secs: age_secs });
+						}
+					}
+
+					eprintln!("WARNING: Removing stale lock {:?}", path);
+					match fs::remove_file(&path) {
+						Ok(_) => {},
+						Err(ref e) if e.kind() == io::ErrorKind::NotFound => { /* someone else beat us to it */ },
+						Err(e) => return Err(LockError::IoError(e))
+					}
+				},
+				Err(e) => return Err(LockError::IoError(e))
+			}
+		}
+
+		Err(LockError::IoError(io::Error::new(io::ErrorKind::AlreadyExists,
+		                                      format!("Lock file {:?} keeps getting recreated", path))))
+	}
+
+	/* Path to the lock file */
+	pub fn path(&self) -> &Path
+	{
+		&self.path
+	}
+
+	/* Details recorded in the lock file */
+	pub fn info(&self) -> LockInfo
+	{
+		self.info
+	}
+}
+
+impl Drop for OutputLock {
+	fn drop(&mut self)
+	{
+		remove_lock_file(&self.path, &self.info);
+	}
+}
+
+/* Create a lock file with the given contents, failing if one already exists
+ * NOTE: The contents get written to a temp file first, which then gets linked into place (failing if the
+ *       lock exists, so only one process can win). That way, no-one ever sees a half-written lock (which
+ *       would look stale). Filesystems without hard links just create the file directly instead.
+ */
+fn create_lock_file(path: &Path, contents: &str) -> io::Result<()>
+{
+	let mut temp_name = path.as_os_str().to_os_string();
+	temp_name.push(format!(".{0}-{1}.tmp", process::id(), TEMP_LOCK_COUNTER.fetch_add(1, Ordering::SeqCst)));
+	let temp_path = PathBuf::from(temp_name);
+
+	fs::write(&temp_path, contents)?;
+	let linked = fs::hard_link(&temp_path, path);
+	let _ = fs::remove_file(&temp_path);
+
+	match linked {
+		Err(ref e) if e.kind() != io::ErrorKind::AlreadyExists => {
+			/* NOTE: create_new() fails if the file exists, so only one process can win */
+			let mut f = OpenOptions::new().write(true).create_new(true).open(path)?;
+			f.write_all(contents.as_bytes())
+		},
+		result => result
+	}
+}
+
+/* Remove a lock file, but only if it's still the one described by "info"
+ * (i.e. it wasn't forcibly taken over by another run in the meantime)
+ * NOTE: This is separate from OutputLock so that it can also be used from the Ctrl+C handler
+ */
+pub fn remove_lock_file(path: &Path, info: &LockInfo)
+{
+	let current = fs::read_to_string(path).ok()
+	                .and_then(|s| serde_json::from_str::<LockInfo>(&s).ok());
+	if current.as_ref() == Some(info) {
+		let _ = fs::remove_file(path);
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+	use test_utils::TempDir;
+
+	/* Pretends that only the given processes are running */
+	struct FakeProcesses(HashSet<u32>);
+
+	impl ProcessChecker for FakeProcesses {
+		fn is_running(&self, pid: u32) -> bool
+		{
+			self.0.contains(&pid)
+		}
+	}
+
+	fn running(pids: &[u32]) -> FakeProcesses
+	{
+		FakeProcesses(pids.iter().cloned().collect())
+	}
+
+	#[test]
+	fn test_acquire_release()
+	{
+		let dir = TempDir::new("output-lock");
+		let lock_path = dir.path().join(LOCK_FILENAME);
+
+		let lock = OutputLock::acquire_as(dir.path(), LockInfo { pid: 100, started: 1000 }, false, &running(&[100])).unwrap();
+		assert!(lock_path.exists());
+		assert!(fs::read_to_string(&lock_path).unwrap().contains("\"pid\":100"));
+
+		drop(lock);
+		assert!(!lock_path.exists());
+	}
+
+	/* Another live process holding the lock blocks us, and gets named in the error */
+	#[test]
+	fn test_contention()
+	{
+		let dir = TempDir::new("output-lock-contention");
+		let checker = running(&[100, 200]);
+
+		let _held = OutputLock::acquire_as(dir.path(), LockInfo { pid: 100, started: 1000 }, false, &checker).unwrap();
+
+		match OutputLock::acquire_as(dir.path(), LockInfo { pid: 200, started: 1190 }, false, &checker) {
+			Err(ref e @ LockError::Held { .. }) => {
+				let msg = e.to_string();
+				assert!(msg.contains("PID 100"), "{}", msg);
+				assert!(msg.contains("started 3m 10s ago"), "{}", msg);
+			},
+			other => panic!("Expected lock to be held - {:?}", other),
+		}
+
+		/* Forcing takes it over */
+		let forced = OutputLock::acquire_as(dir.path(), LockInfo { pid: 200, started: 1190 }, true, &checker).unwrap();
+		assert!(fs::read_to_string(forced.path()).unwrap().contains("\"pid\":200"));
+	}
+
+	/* Locks from processes that are no longer running get replaced */
+	#[test]
+	fn test_stale_lock()
+	{
+		let dir = TempDir::new("output-lock-stale");
+
+		/* Left behind by a crashed run */
+		let crashed = OutputLock::acquire_as(dir.path(), LockInfo { pid: 100, started: 1000 }, false, &running(&[100])).unwrap();
+		::std::mem::forget(crashed);
+
+		let lock = OutputLock::acquire_as(dir.path(), LockInfo { pid: 200, started: 5000 }, false, &running(&[200])).unwrap();
+		assert!(fs::read_to_string(lock.path()).unwrap().contains("\"pid\":200"));
+
+		/* Corrupt lock files count as stale too */
+		drop(lock);
+		dir.write_file(LOCK_FILENAME, b"{\"pid\":");
+		assert!(OutputLock::acquire_as(dir.path(), LockInfo { pid: 300, started: 6000 }, false, &running(&[300])).is_ok());
+	}
+
+	/* A lock that was taken over isn't removed by the original owner */
+	#[test]
+	fn test_release_after_takeover()
+	{
+		let dir = TempDir::new("output-lock-takeover");
+		let checker = running(&[100, 200]);
+
+		let original = OutputLock::acquire_as(dir.path(), LockInfo { pid: 100, started: 1000 }, false, &checker).unwrap();
+		let forced = OutputLock::acquire_as(dir.path(), LockInfo { pid: 200, started: 2000 }, true, &checker).unwrap();
+
+		drop(original);
+		assert!(forced.path().exists());
+	}
+
+	#[test]
+	fn test_format_age()
+	{
+		assert_eq!("42s", format_age(42));
+		assert_eq!("3m 10s", format_age(190));
+		assert_eq!("2h 5m", format_age(7500));
+	}
+}
@@ -0,0 +1,311 @@
+/* Copying several track files at once (for copy mode)
+ *
+ * Copies to slow drives (e.g. USB sticks) spend most of their time waiting on the drive,
+ * so up to N of them get run at the same time. The results are handed back in playlist order
+ * (whatever order the copies actually finish in), so that everything done with them afterwards
+ * (reporting, sidecars, the manifest, etc.) happens in the same order as when copying one at a time.
+ *
+ * Files already copied by an earlier run (i.e. with the same size, and modification time) can be skipped.
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use convert_resume;
+
+/* Default number of files to copy at the same time */
+pub const DEFAULT_COPY_JOBS: usize = 4;
+
+/* Differences in modification times that get ignored when checking if a copy is up to date
+ * (FAT filesystems - as found on most USB sticks - only store times to the nearest 2 seconds)
+ */
+const MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+/* How to tell if a file copied by an earlier run can be left as it is */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum UpToDateCheck {
+	/* Always copy the file again */
+	Never,
+	/* The copy is the same size (for copies that don't keep the source file's modification time) */
+	Size,
+	/* The copy is the same size, and has the same modification time */
+	SizeAndTime,
+}
+
+/* A file to copy */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct CopyJob {
+	/* Index of the track (0-based) */
+	pub track_index: usize,
+	/* File to copy */
+	pub src: PathBuf,
+	/* Where the copy goes */
+	pub dst: PathBuf,
+	/* When an existing copy can be kept */
+	pub up_to_date_check: UpToDateCheck,
+}
+
+/* What happened to a file */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum CopyOutcome {
+	Copied,
+	/* Skipped, as the copy from an earlier run is up to date */
+	UpToDate,
+}
+
+/* Copy a file to dst_path (via a temp file, so that dst_path only appears once it's complete)
+ * Nothing is left behind if the copy fails.
+ */
+pub fn copy_file(src_path: &Path, dst_path: &Path) -> io::Result<()>
+{
+	let result = fs::copy(src_path, convert_resume::partial_path(dst_path))
+	                .and_then(|_| convert_resume::finish_partial(dst_path));
+	if result.is_err() {
+		convert_resume::discard_partial(dst_path);
+	}
+	result
+}
+
+/* Is the existing file at dst_path an up-to-date copy of src_path? */
+pub fn is_up_to_date(src_path: &Path, dst_path: &Path, check: UpToDateCheck) -> bool
+{
+	let (src, dst) = match (fs::metadata(src_path), fs::metadata(dst_path)) {
+		(Ok(src), Ok(dst)) => (src, dst),
+		_                  => return false
+	};
+	if !dst.is_file() || src.len() != dst.len() {
+		return false;
+	}
+
+	match check {
+		UpToDateCheck::Never       => false,
+		UpToDateCheck::Size        => true,
+		UpToDateCheck::SizeAndTime => {
+			match (src.modified(), dst.modified()) {
+				(Ok(a), Ok(b)) => mtime_difference(a, b) <= MTIME_TOLERANCE,
+				_              => false
+			}
+		}
+	}
+}
+
+/* Difference between two times (whichever is later) */
+fn mtime_difference(a: SystemTime, b: SystemTime) -> Duration
+{
+	a.duration_since(b).or_else(|_| b.duration_since(a)).unwrap_or_default()
+}
+
+/* Carry out a job - copying the file, unless an up-to-date copy is already there */
+pub fn run_job(job: &CopyJob) -> io::Result<CopyOutcome>
+{
+	if is_up_to_date(&job.src, &job.dst, job.up_to_date_check) {
+		Ok(CopyOutcome::UpToDate)
+	}
+	else {
+		copy_file(&job.src, &job.dst).map(|_| CopyOutcome::Copied)
+	}
+}
+
+/* Copy all the files, using up to max_workers threads (see copy_files_with())
+ * > returns the number of jobs whose results were handed back
+ */
+pub fn copy_files<F>(jobs: &[CopyJob], max_workers: usize, on_result: F) -> usize
+	where F: FnMut(&CopyJob, io::Result<CopyOutcome>) -> bool
+{
+	copy_files_with(jobs, max_workers, run_job, on_result)
+}
+
+/* Implementation of copy_files(), with the function carrying out each job supplied (so tests can substitute their own)
+ *
+ * Each result gets passed to on_result in job order, as soon as it (and all the ones before it) are done.
+ * Failed copies don't stop the rest. Returning false from on_result does though - no more copies get started,
+ * and while any copies already under way get to finish, their results aren't handed back.
+ * > returns the number of jobs whose results were handed back
+ */
+pub fn copy_files_with<F>(jobs: &[CopyJob], max_workers: usize, run: fn(&CopyJob) -> io::Result<CopyOutcome>,
+                          mut on_result: F)
+	-> usize
+	where F: FnMut(&CopyJob, io::Result<CopyOutcome>) -> bool
+{
+	let max_workers = max_workers.max(1).min(jobs.len());
+	let next_job = AtomicUsize::new(0);
+	let stopped = AtomicBool::new(false);
+	let mut handled = 0;
+
+	thread::scope(|scope| {
+		let (sender, receiver) = mpsc::channel::<(usize, io::Result<CopyOutcome>)>();
+		for _ in 0 .. max_workers {
+			let sender = sender.clone();
+			let (next_job, stopped) = (&next_job, &stopped);
+			scope.spawn(move || {
+				while !stopped.load(Ordering::SeqCst) {
+					let index = next_job.fetch_add(1, Ordering::SeqCst);
+					let job = match jobs.get(index) {
+						Some(job) => job,
+						None      => break
+					};
+					/* Ignore send failures - That just means we stopped waiting for results */
+					if sender.send((index, run(job))).is_err() {
+						break;
+					}
+				}
+			});
+		}
+		drop(sender);
+
+		/* Results that finished before the ones ahead of them */
+		let mut waiting: BTreeMap<usize, io::Result<CopyOutcome>> = BTreeMap::new();
+		for (index, result) in receiver.iter() {
+			waiting.insert(index, result);
+			while let Some(result) = waiting.remove(&handled) {
+				let keep_going = on_result(&jobs[handled], result);
+				handled += 1;
+				if !keep_going {
+					stopped.store(true, Ordering::SeqCst);
+					return;
+				}
+			}
+		}
+	});
+	handled
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	fn make_jobs(dir: &TempDir, count: usize) -> Vec<CopyJob>
+	{
+		(0 .. count).map(|i| CopyJob {
+			track_index: i,
+			src: dir.write_file(&format!("src/{}.mp3", i), format!("track {}", i).as_bytes()),
+			dst: dir.path().join(format!("out/Track_{:02}.mp3", i + 1)),
+			up_to_date_check: UpToDateCheck::Never,
+		}).collect()
+	}
+
+	#[test]
+	fn test_copy_files()
+	{
+		let dir = TempDir::new("parallel-copy");
+		let jobs = make_jobs(&dir, 10);
+		fs::remove_file(&jobs[3].src).unwrap();
+		fs::create_dir_all(dir.path().join("out")).unwrap();
+
+		let mut order: Vec<(usize, bool)> = Vec::new();
+		let handled = copy_files(&jobs, 3, |job, result| {
+			order.push((job.track_index, result.is_ok()));
+			true
+		});
+
+		/* Results come back in order, and the failure didn't stop the rest */
+		assert_eq!(10, handled);
+		assert_eq!((0 .. 10).map(|i| (i, i != 3)).collect::<Vec<_>>(), order);
+		for (i, job) in jobs.iter().enumerate().filter(|&(i, _)| i != 3) {
+			assert_eq!(format!("track {}", i), fs::read_to_string(&job.dst).unwrap());
+		}
+		assert!(!jobs[3].dst.exists());
+		assert!(!convert_resume::partial_path(&jobs[3].dst).exists());
+	}
+
+	/* Copy where the earlier tracks take longest */
+	fn slow_start_copy(job: &CopyJob) -> io::Result<CopyOutcome>
+	{
+		if job.src.ends_with("0.mp3") {
+			thread::sleep(Duration::from_millis(200));
+		}
+		run_job(job)
+	}
+
+	#[test]
+	fn test_copy_files_ordering()
+	{
+		let dir = TempDir::new("parallel-copy-order");
+		let jobs = make_jobs(&dir, 6);
+		fs::create_dir_all(dir.path().join("out")).unwrap();
+
+		let mut order: Vec<usize> = Vec::new();
+		copy_files_with(&jobs, 4, slow_start_copy, |job, result| {
+			assert!(result.is_ok());
+			order.push(job.track_index);
+			true
+		});
+		assert_eq!(vec![0, 1, 2, 3, 4, 5], order);
+	}
+
+	#[test]
+	fn test_copy_files_stop()
+	{
+		let dir = TempDir::new("parallel-copy-stop");
+		let jobs = make_jobs(&dir, 20);
+		fs::create_dir_all(dir.path().join("out")).unwrap();
+
+		let mut seen: Vec<usize> = Vec::new();
+		let handled = copy_files(&jobs, 2, |job, _| {
+			seen.push(job.track_index);
+			job.track_index < 4
+		});
+		assert_eq!(5, handled);
+		assert_eq!(vec![0, 1, 2, 3, 4], seen);
+		assert!(jobs[.. 5].iter().all(|job| job.dst.exists()));
+	}
+
+	#[test]
+	fn test_up_to_date()
+	{
+		let dir = TempDir::new("parallel-copy-up-to-date");
+		let mut jobs = make_jobs(&dir, 3);
+		fs::create_dir_all(dir.path().join("out")).unwrap();
+		copy_files(&jobs, 2, |_, result| result.is_ok());
+
+		/* Copies keeping the source's time (as copy mode does by default) */
+		let src_mtime = fs::metadata(&jobs[0].src).unwrap().modified().unwrap();
+		for job in jobs.iter() {
+			fs::File::options().write(true).open(&job.dst).unwrap().set_modified(src_mtime).unwrap();
+		}
+		/* Different size (same time) */
+		fs::write(&jobs[1].dst, b"old").unwrap();
+		fs::File::options().write(true).open(&jobs[1].dst).unwrap().set_modified(src_mtime).unwrap();
+		/* Same size, different time */
+		fs::File::options().write(true).open(&jobs[2].dst).unwrap()
+			.set_modified(src_mtime - Duration::from_secs(3600)).unwrap();
+
+		assert!(is_up_to_date(&jobs[0].src, &jobs[0].dst, UpToDateCheck::SizeAndTime));
+		assert!(!is_up_to_date(&jobs[1].src, &jobs[1].dst, UpToDateCheck::SizeAndTime));
+		assert!(!is_up_to_date(&jobs[2].src, &jobs[2].dst, UpToDateCheck::SizeAndTime));
+		assert!(is_up_to_date(&jobs[2].src, &jobs[2].dst, UpToDateCheck::Size));
+		assert!(!is_up_to_date(&jobs[0].src, &jobs[0].dst, UpToDateCheck::Never));
+		assert!(!is_up_to_date(&jobs[0].src, &dir.path().join("out/missing.mp3"), UpToDateCheck::Size));
+
+		for job in jobs.iter_mut() {
+			job.up_to_date_check = UpToDateCheck::SizeAndTime;
+		}
+		let mut outcomes: Vec<CopyOutcome> = Vec::new();
+		copy_files(&jobs, 2, |_, result| {
+			outcomes.push(result.unwrap());
+			true
+		});
+		assert_eq!(vec![CopyOutcome::UpToDate, CopyOutcome::Copied, CopyOutcome::Copied], outcomes);
+		assert_eq!("track 1", fs::read_to_string(&jobs[1].dst).unwrap());
+	}
+
+	#[test]
+	fn test_copy_files_empty()
+	{
+		assert_eq!(0, copy_files(&[], DEFAULT_COPY_JOBS, |_, _| true));
+	}
+}
@@ -0,0 +1,179 @@
+/* Parsing several playlists at once (for modes that take multiple input playlists)
+ *
+ * Each playlist gets parsed on its own worker thread, with up to N running at a time.
+ * A parse that takes too long (e.g. reading from a hung network share) gets reported
+ * as a timeout for that file, instead of stalling the whole batch.
+ */
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use xspf_parser::{self, ParseOptions, XspfPlaylist, XspfError};
+
+/* Default number of playlists to parse at the same time */
+pub const DEFAULT_MAX_WORKERS: usize = 4;
+
+/* Default time allowed for parsing each playlist */
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/* Result of parsing one of the input playlists */
+pub type ParseResult = (String, Result<XspfPlaylist, XspfError>);
+
+/* Parse all the given playlists, using up to max_workers threads
+ * > returns the results in the same order as the input paths
+ */
+pub fn parse_playlists(paths: &[String], max_workers: usize, timeout: Duration, options: ParseOptions) -> Vec<ParseResult>
+{
+	parse_playlists_with(paths, max_workers, timeout, options, xspf_parser::parse_xspf)
+}
+
+/* Implementation of parse_playlists(), with the parsing function supplied (so tests can substitute their own)
+ * NOTE: Threads for parses that time out are abandoned (there's no way to cancel a blocked read).
+ *       They don't count towards max_workers after that, and their results are ignored if they ever finish.
+ */
+pub fn parse_playlists_with(paths: &[String], max_workers: usize, timeout: Duration, options: ParseOptions,
+                            parse: fn(&str, &ParseOptions) -> Result<XspfPlaylist, XspfError>)
+	-> Vec<ParseResult>
+{
+	let max_workers = max_workers.max(1);
+	let (sender, receiver) = mpsc::channel::<(usize, Result<XspfPlaylist, XspfError>)>();
+
+	let mut results: Vec<Option<Result<XspfPlaylist, XspfError>>> = paths.iter().map(|_| None).collect();
+	let mut in_flight: Vec<(usize, Instant)> = Vec::new();  /* (index, deadline) */
+	let mut next_index = 0;
+
+	while next_index < paths.len() || !in_flight.is_empty() {
+		/* Start more workers */
+		while next_index < paths.len() && in_flight.len() < max_workers {
+			let index = next_index;
+			let path = paths[index].clone();
+			let sender = sender.clone();
+			let options = options.clone();
+
+			thread::spawn(move || {
+				/* Ignore send failures - That just means we stopped waiting for this one */
+				let _ = sender.send((index, parse(&path, &options)));
+			});
+
+			in_flight.push((index, Instant::now() + timeout));
+			next_index += 1;
+		}
+
+		/* Wait for the next result, or the earliest deadline */
+		let earliest_deadline = in_flight.iter().map(|&(_, deadline)| deadline).min().unwrap();  /* Safe - something was just started, or is still in flight */
+		let wait = earliest_deadline.saturating_duration_since(Instant::now());
+
+		match receiver.recv_timeout(wait) {
+			Ok((index, result)) => {
+				/* Only accept results for parses we're still waiting on */
+				if let Some(pos) = in_flight.iter().position(|&(i, _)| i == index) {
+					in_flight.remove(pos);
+					results[index] = Some(result);
+				}
+			},
+			Err(_) => {
+				/* Give up on anything that has run out of time */
+				let now = Instant::now();
+				for &(index, _) in in_flight.iter().filter(|&&(_, deadline)| deadline <= now) {
+					results[index] = Some(Err(XspfError::Timeout(timeout)));
+				}
+				in_flight.retain(|&(_, deadline)| deadline > now);
+			}
+		}
+	}
+
+	paths.iter()
+	     .cloned()
+	     .zip(results.into_iter().map(|r| r.unwrap()))  /* Safe - every index gets a result before the loop ends */
+	     .collect()
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json;
+	use test_utils::TempDir;
+
+	/* Write some fixture playlists, returning their paths (the 3rd one is malformed) */
+	fn write_fixtures(dir: &TempDir) -> Vec<String>
+	{
+		let playlist = |name: &str, tracks: &[&str]| -> String {
+			let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\">\n<title>{}</title>\n<trackList>\n", name);
+			for t in tracks.iter() {
+				xml.push_str(&format!("<track><location>file:///music/{}</location><duration>60000</duration></track>\n", t));
+			}
+			xml.push_str("</trackList>\n</playlist>\n");
+			xml
+		};
+
+		let files = [
+			("a.xspf", playlist("A", &["20170403/v01-tranquil.mp3"])),
+			("b.xspf", playlist("B", &["20170404/v01-moose.mp3", "20170404/v02-celestial.flac"])),
+			("c.xspf", "<playlist><trackList><track></trackList></playlist>".to_string()),
+			("d.xspf", playlist("D", &[])),
+			("e.xspf", playlist("E", &["20170406/v03-winds_of_flutter.mp3"])),
+		];
+
+		files.iter()
+		     .map(|&(name, ref contents)| dir.write_file(name, contents.as_bytes()).to_string_lossy().into_owned())
+		     .collect()
+	}
+
+	#[test]
+	fn test_parse_playlists()
+	{
+		let dir = TempDir::new("parallel-parse");
+		let paths = write_fixtures(&dir);
+
+		let results = parse_playlists(&paths, 2, Duration::from_secs(DEFAULT_TIMEOUT_SECS), ParseOptions::default());
+
+		/* Ordering */
+		assert_eq!(paths, results.iter().map(|r| r.0.clone()).collect::<Vec<_>>());
+
+		/* Error isolation */
+		match results[2].1 {
+			Err(XspfError::XmlParseError(_)) => {},
+			ref other => panic!("Expected XML error, got {:?}", other),
+		}
+		assert!(results.iter().enumerate().all(|(i, r)| i == 2 || r.1.is_ok()));
+
+		/* Same results as parsing one after the other */
+		for &(ref path, ref result) in results.iter().filter(|r| r.1.is_ok()) {
+			let serial = xspf_parser::parse_xspf(path, &ParseOptions::default()).unwrap();
+			assert_eq!(serde_json::to_string(&serial).unwrap(),
+			           serde_json::to_string(result.as_ref().unwrap()).unwrap());
+		}
+		assert_eq!(2, results[1].1.as_ref().unwrap().len());
+	}
+
+	/* Parser which hangs on one particular file */
+	fn hanging_parse(path: &str, options: &ParseOptions) -> Result<XspfPlaylist, XspfError>
+	{
+		if path.ends_with("b.xspf") {
+			thread::sleep(Duration::from_secs(5));
+		}
+		xspf_parser::parse_xspf(path, options)
+	}
+
+	#[test]
+	fn test_parse_playlists_timeout()
+	{
+		let dir = TempDir::new("parallel-parse-timeout");
+		let paths = write_fixtures(&dir);
+
+		let start = Instant::now();
+		let results = parse_playlists_with(&paths, 1, Duration::from_millis(200), ParseOptions::default(), hanging_parse);
+
+		/* The hung one doesn't stall the rest (even with only one worker) */
+		assert!(start.elapsed() < Duration::from_secs(4));
+		match results[1].1 {
+			Err(XspfError::Timeout(_)) => {},
+			ref other => panic!("Expected timeout, got {:?}", other),
+		}
+		assert!(results[0].1.is_ok());
+		assert!(results[4].1.is_ok());
+	}
+}
@@ -0,0 +1,349 @@
+/* Filling in missing track details from another playlist of the same tracks
+ *
+ * Some playlists only give the tracks' locations, while an (extended) M3U of the same set has their
+ * durations and titles too. With "--augment-from=<playlist.m3u>", the M3U's entries get matched up with
+ * the tracks (by path, or failing that, by filename), and fill in whatever the tracks are missing.
+ *
+ * Filename matches are only used when they're unambiguous - if several tracks (e.g. in different
+ * session folders) have the same filename, the entry gets reported instead of guessing which it was.
+ */
+use std::collections::HashMap;
+
+use diagnostics::{Diagnostics, Severity};
+use duration_source::DurationSource;
+use m3u_parser::{M3uEntry, M3uPlaylist};
+use track_duration::TrackDuration;
+use track_path::TrackPath;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "augment";
+
+/* Durations closer than this count as the same
+ * (M3U durations are often rounded to whole seconds)
+ */
+pub const DURATION_TOLERANCE_MS: i64 = 1000;
+
+/* *************************************************** */
+/* Matching */
+
+/* How an entry got matched to a track */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum MatchKind {
+	/* Same path */
+	Path,
+	/* Same filename (and no other track has it) */
+	Filename,
+}
+
+/* Result of trying to match an entry to a track */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum EntryMatch {
+	Matched(usize, MatchKind),
+	/* No track has the same path or filename */
+	Unmatched,
+	/* No track has the same path, and several have the same filename (indices of those tracks) */
+	Ambiguous(Vec<usize>),
+}
+
+/* Key for comparing paths
+ * Paths from "file:///" URIs don't keep their leading slash (see TrackPath::from_uri()), and relative ones may
+ * include "." or "..", so these get evened out here.
+ */
+fn path_key(path: &TrackPath) -> String
+{
+	let mut parts: Vec<&str> = Vec::new();
+	for part in path.as_str().split('/') {
+		match part {
+			"" | "." => { /* skip */ },
+			".."     => {
+				if parts.last().is_none_or(|p| *p == "..") {
+					parts.push(part);
+				}
+				else {
+					parts.pop();
+				}
+			},
+			_ => parts.push(part),
+		}
+	}
+	parts.join("/")
+}
+
+/* Match each entry to a track
+ * - entry_paths: Path of each entry (see M3uEntry::track_path())
+ */
+pub fn match_entries(tracks: &[Track], entry_paths: &[TrackPath]) -> Vec<EntryMatch>
+{
+	let mut by_path: HashMap<String, usize> = HashMap::new();
+	let mut by_filename: HashMap<String, Vec<usize>> = HashMap::new();
+	for (i, track) in tracks.iter().enumerate() {
+		by_path.entry(path_key(&track.path)).or_insert(i);
+		by_filename.entry(track.filename.clone()).or_default().push(i);
+	}
+
+	entry_paths.iter().map(|path| {
+		if let Some(&i) = by_path.get(&path_key(path)) {
+			return EntryMatch::Matched(i, MatchKind::Path);
+		}
+		match path.file_name().and_then(|name| by_filename.get(&name)) {
+			Some(candidates) if candidates.len() == 1 => EntryMatch::Matched(candidates[0], MatchKind::Filename),
+			Some(candidates)                          => EntryMatch::Ambiguous(candidates.clone()),
+			None                                      => EntryMatch::Unmatched
+		}
+	}).collect()
+}
+
+/* *************************************************** */
+/* Merging */
+
+/* Track where both sources gave a duration, and they differ */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct DurationConflict {
+	pub track_index: usize,
+
+	/* Durations (in ms) given by the playlist, and by the M3U */
+	pub playlist_ms: i64,
+	pub augment_ms: i64,
+
+	/* Was the M3U's duration the one used? */
+	pub used_augment: bool,
+}
+
+/* Summary of what got filled in */
+#[derive(Debug)]
+#[derive(Default)]
+pub struct AugmentReport {
+	/* Number of entries that were matched to a track */
+	pub matched: usize,
+	/* Number of tracks that had details filled in (or replaced) */
+	pub augmented: usize,
+
+	/* Entries (indices) that didn't match any track */
+	pub unmatched: Vec<usize>,
+	/* Entries (indices) that could have been one of several tracks */
+	pub ambiguous: Vec<usize>,
+
+	pub conflicts: Vec<DurationConflict>,
+
+	/* Description of the problems above (for reporting them) */
+	pub diagnostics: Diagnostics,
+}
+
+/* Fill in the details of a track from an entry
+ * NOTE: Durations filled in this way count as the playlist's own (see duration_source::resolve_duration())
+ * - prefer_augment: Use the entry's details even when the track has its own
+ * > returns whether anything changed, and the (playlist, M3U) durations if they conflict
+ */
+fn merge_entry(track: &mut Track, entry: &M3uEntry, prefer_augment: bool) -> (bool, Option<(i64, i64)>)
+{
+	let mut changed = false;
+	let mut conflict = None;
+
+	if let Some(augment_ms) = entry.duration_ms {
+		match track.duration {
+			None => {
+				track.duration = Some(TrackDuration(augment_ms));
+				track.duration_source = DurationSource::Playlist;
+				changed = true;
+			},
+			Some(TrackDuration(playlist_ms)) if (playlist_ms - augment_ms).abs() >= DURATION_TOLERANCE_MS => {
+				conflict = Some((playlist_ms, augment_ms));
+				if prefer_augment {
+					track.duration = Some(TrackDuration(augment_ms));
+					track.duration_source = DurationSource::Playlist;
+					changed = true;
+				}
+			},
+			Some(_) => { /* same */ }
+		}
+	}
+
+	if let Some(ref title) = entry.title {
+		let has_title = track.title.as_ref().is_some_and(|t| !t.trim().is_empty());
+		if (!has_title || prefer_augment) && track.title.as_ref() != Some(title) {
+			track.title = Some(title.clone());
+			changed = true;
+		}
+	}
+
+	(changed, conflict)
+}
+
+/* Fill in missing durations and titles of the playlist's tracks from the M3U's entries
+ * - base_dir: Directory to resolve the M3U's relative paths against (i.e. the one it's in)
+ * - prefer_augment: Where both have a duration/title, use the M3U's (instead of keeping the playlist's)
+ */
+pub fn augment_playlist(xspf: &mut XspfPlaylist, m3u: &M3uPlaylist, base_dir: &TrackPath, prefer_augment: bool) -> AugmentReport
+{
+	let entry_paths: Vec<TrackPath> = m3u.entries.iter().map(|e| e.track_path(base_dir)).collect();
+	let matches = match_entries(&xspf.tracks, &entry_paths);
+
+	let mut report = AugmentReport::default();
+	let mut augmented = vec![false; xspf.tracks.len()];
+
+	for (entry_idx, (entry, entry_match)) in m3u.entries.iter().zip(matches.iter()).enumerate() {
+		match *entry_match {
+			EntryMatch::Matched(track_idx, _) => {
+				report.matched += 1;
+
+				let (changed, conflict) = merge_entry(&mut xspf.tracks[track_idx], entry, prefer_augment);
+				augmented[track_idx] |= changed;
+
+				if let Some((playlist_ms, augment_ms)) = conflict {
+					report.diagnostics.warn_track(CATEGORY, track_idx,
+						format!("durations differ - {0} in the playlist, {1} in the M3U (line {2}) - used the {3}'s",
+						        TrackDuration(playlist_ms).to_timecode(), TrackDuration(augment_ms).to_timecode(), entry.line,
+						        if prefer_augment { "M3U" } else { "playlist" }));
+					report.conflicts.push(DurationConflict {
+						track_index: track_idx,
+						playlist_ms: playlist_ms,
+						augment_ms: augment_ms,
+						used_augment: prefer_augment,
+					});
+				}
+			},
+			EntryMatch::Ambiguous(ref candidates) => {
+				let positions: Vec<String> = candidates.iter().map(|i| (i + 1).to_string()).collect();
+				report.diagnostics.add(Severity::Warning, CATEGORY, None,
+					format!("M3U entry '{0}' (line {1}) could be any of the tracks at positions {2} (same filename) - skipped",
+					        entry.location, entry.line, positions.join(", ")));
+				report.ambiguous.push(entry_idx);
+			},
+			EntryMatch::Unmatched => {
+				report.unmatched.push(entry_idx);
+			},
+		}
+	}
+
+	report.augmented = augmented.iter().filter(|&&a| a).count();
+	report
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use m3u_parser::parse_m3u;
+
+	fn make_playlist(paths: &[&str]) -> XspfPlaylist
+	{
+		XspfPlaylist {
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			tracks: paths.iter().map(|p| Track::from_track_path(TrackPath::from_normalized(p)).unwrap()).collect(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_path_key()
+	{
+		assert_eq!("music/20170403/v01-a.mp3", path_key(&TrackPath::from_normalized("/music/20170403/v01-a.mp3")));
+		assert_eq!("music/20170403/v01-a.mp3", path_key(&TrackPath::from_normalized("music/./20170403/v01-a.mp3")));
+		assert_eq!("music/20170403/v01-a.mp3", path_key(&TrackPath::from_normalized("/lists/../music/20170403/v01-a.mp3")));
+		assert_eq!("../music/v01-a.mp3", path_key(&TrackPath::from_normalized("../music/v01-a.mp3")));
+	}
+
+	#[test]
+	fn test_match_entries()
+	{
+		let xspf = make_playlist(&["/music/20170403/v01-a.mp3",
+		                           "/music/20170403/v02-b.mp3",
+		                           "/music/20170404/v02-b.mp3",
+		                           "/music/20170404/v03-c.mp3"]);
+		let paths: Vec<TrackPath> = ["/music/20170403/v01-a.mp3",     /* by path */
+		                             "/music/20170404/v02-b.mp3",     /* by path (even though the filename is shared) */
+		                             "/elsewhere/v03-c.mp3",          /* by filename */
+		                             "/elsewhere/v02-b.mp3",          /* filename is in two folders */
+		                             "/music/20170405/v09-z.mp3"]     /* nothing */
+			.iter().map(|p| TrackPath::from_normalized(p)).collect();
+
+		assert_eq!(vec![EntryMatch::Matched(0, MatchKind::Path),
+		                EntryMatch::Matched(2, MatchKind::Path),
+		                EntryMatch::Matched(3, MatchKind::Filename),
+		                EntryMatch::Ambiguous(vec![1, 2]),
+		                EntryMatch::Unmatched],
+		           match_entries(&xspf.tracks, &paths));
+	}
+
+	#[test]
+	fn test_augment_playlist()
+	{
+		let mut xspf = make_playlist(&["music/20170403/v01-a.mp3",
+		                               "music/20170403/v02-b.mp3",
+		                               "music/20170404/v02-b.mp3",
+		                               "music/20170404/v03-c.mp3"]);
+		xspf.tracks[3].duration = Some(TrackDuration(90_000));
+		xspf.tracks[3].title = Some("Own Title".to_string());
+
+		let m3u = parse_m3u("#EXTM3U\n\
+		                     #EXTINF:61,Alpha\n\
+		                     file:///music/20170403/v01-a.mp3\n\
+		                     #EXTINF:30,Bravo\n\
+		                     ../../elsewhere/v02-b.mp3\n\
+		                     #EXTINF:95,Charlie\n\
+		                     20170404/v03-c.mp3\n\
+		                     #EXTINF:10,Nobody\n\
+		                     v09-z.mp3\n");
+		let base = TrackPath::from_normalized("/music");
+		let report = augment_playlist(&mut xspf, &m3u, &base, false);
+
+		/* Missing details get filled in */
+		assert_eq!(Some(61_000), xspf.tracks[0].duration.as_ref().map(|d| d.0));
+		assert_eq!(DurationSource::Playlist, xspf.tracks[0].duration_source);
+		assert_eq!(Some("Alpha".to_string()), xspf.tracks[0].title);
+
+		/* Ambiguous filenames don't get guessed */
+		assert!(xspf.tracks[1 ..= 2].iter().all(|t| t.duration.is_none() && t.title.is_none()));
+		assert_eq!(vec![1], report.ambiguous);
+		assert!(report.diagnostics.entries.iter().any(|d| d.message.contains("positions 2, 3")));
+
+		/* The playlist's own details win by default */
+		assert_eq!(Some(90_000), xspf.tracks[3].duration.as_ref().map(|d| d.0));
+		assert_eq!(Some("Own Title".to_string()), xspf.tracks[3].title);
+		assert_eq!(vec![DurationConflict { track_index: 3, playlist_ms: 90_000, augment_ms: 95_000,
+		                                   used_augment: false }],
+		           report.conflicts);
+
+		assert_eq!((2, 1), (report.matched, report.augmented));
+		assert_eq!(vec![3], report.unmatched);
+	}
+
+	#[test]
+	fn test_prefer_augment()
+	{
+		let mut xspf = make_playlist(&["music/20170404/v03-c.mp3", "music/20170404/v04-d.mp3"]);
+		xspf.tracks[0].duration = Some(TrackDuration(90_000));
+		xspf.tracks[0].title = Some("Own Title".to_string());
+		xspf.tracks[1].duration = Some(TrackDuration(60_400));
+
+		let m3u = parse_m3u("#EXTM3U\n\
+		                     #EXTINF:95,Charlie\n\
+		                     v03-c.mp3\n\
+		                     #EXTINF:60,\n\
+		                     v04-d.mp3\n");
+		let report = augment_playlist(&mut xspf, &m3u, &TrackPath::from_normalized("/music/20170404"), true);
+
+		assert_eq!(Some(95_000), xspf.tracks[0].duration.as_ref().map(|d| d.0));
+		assert_eq!(Some("Charlie".to_string()), xspf.tracks[0].title);
+		assert!(report.conflicts[0].used_augment);
+
+		/* Differences from rounding to whole seconds aren't conflicts */
+		assert_eq!(Some(60_400), xspf.tracks[1].duration.as_ref().map(|d| d.0));
+		assert_eq!(1, report.conflicts.len());
+		assert_eq!(1, report.augmented);
+	}
+}
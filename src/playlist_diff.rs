@@ -0,0 +1,296 @@
+/* Comparing two versions of a playlist (e.g. an archived copy, and the one that's since been edited in VLC)
+ *
+ * Tracks get matched up between the two by their path first. Any left over then get matched by their
+ * filename (folded - see name_folding::fold_name()), so a track whose file was moved to another folder
+ * shows up as relocated, rather than as one track removed and another added. Tracks listed several times
+ * get matched up in the order they're listed.
+ *
+ * Tracks that were matched up can also have moved position. Everything shifting along because a track was
+ * added or removed before them doesn't count though - only the fewest tracks that would need moving to get
+ * from the old order to the new one get reported (i.e. those outside the longest run of matched tracks that
+ * kept the same order).
+ */
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use name_folding::fold_name;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* *************************************************** */
+/* Types */
+
+/* A track that's only in one of the playlists */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct DiffTrack {
+	/* Index (0-based) of the track in the playlist it's in */
+	pub index: usize,
+	pub path: String,
+}
+
+/* A track that's in both playlists */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct MatchedTrack {
+	/* Index (0-based) of the track in the old playlist */
+	pub old_index: usize,
+	/* Index (0-based) of the track in the new playlist */
+	pub new_index: usize,
+
+	pub old_path: String,
+	pub new_path: String,
+}
+
+/* A track whose stored duration is different in the new playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct DurationChange {
+	pub track: MatchedTrack,
+
+	/* Durations (in ms), if the playlists have them */
+	pub old_duration_ms: Option<i64>,
+	pub new_duration_ms: Option<i64>,
+}
+
+/* Everything that changed between two versions of a playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct PlaylistDiff {
+	/* Tracks only in the new playlist */
+	pub added: Vec<DiffTrack>,
+	/* Tracks only in the old playlist */
+	pub removed: Vec<DiffTrack>,
+
+	/* Tracks matched up by their filename, whose path changed (e.g. the file was moved to another folder) */
+	pub relocated: Vec<MatchedTrack>,
+	/* Tracks whose stored duration changed */
+	pub duration_changed: Vec<DurationChange>,
+	/* Tracks that moved position (relative to the others) */
+	pub reordered: Vec<MatchedTrack>,
+}
+
+impl PlaylistDiff {
+	/* Are the playlists the same (as far as their tracks go)? */
+	pub fn is_empty(&self) -> bool
+	{
+		self.added.is_empty() && self.removed.is_empty() && self.relocated.is_empty() &&
+		self.duration_changed.is_empty() && self.reordered.is_empty()
+	}
+}
+
+/* *************************************************** */
+/* Matching */
+
+/* Match up the tracks that haven't been matched yet and have the same key (in the order they're listed)
+ * - old_matched/new_matched: Index of the track each one has been matched with (in the other playlist)
+ */
+fn match_by<F>(old: &[Track], new: &[Track], old_matched: &mut [Option<usize>], new_matched: &mut [Option<usize>], key: F)
+	where F: Fn(&Track) -> String
+{
+	let mut unmatched: HashMap<String, VecDeque<usize>> = HashMap::new();
+	for (j, track) in new.iter().enumerate() {
+		if new_matched[j].is_none() {
+			unmatched.entry(key(track)).or_default().push_back(j);
+		}
+	}
+
+	for (i, track) in old.iter().enumerate() {
+		if old_matched[i].is_some() {
+			continue;
+		}
+		if let Some(j) = unmatched.get_mut(&key(track)).and_then(|indices| indices.pop_front()) {
+			old_matched[i] = Some(j);
+			new_matched[j] = Some(i);
+		}
+	}
+}
+
+/* Positions (in "values") of the longest run of values that keeps increasing (skipping any in between)
+ * NOTE: The values are assumed to all be different (as they're indices of different tracks)
+ */
+fn longest_increasing_run(values: &[usize]) -> Vec<usize>
+{
+	/* tails[k] = position of the smallest value that a run of k + 1 values can end with (so far) */
+	let mut tails: Vec<usize> = Vec::new();
+	let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+
+	for (pos, &value) in values.iter().enumerate() {
+		let k = match tails.binary_search_by(|&t| values[t].cmp(&value)) {
+			Ok(k) | Err(k) => k
+		};
+		if k > 0 {
+			previous[pos] = Some(tails[k - 1]);
+		}
+		if k == tails.len() {
+			tails.push(pos);
+		}
+		else {
+			tails[k] = pos;
+		}
+	}
+
+	let mut run = Vec::new();
+	let mut next = tails.last().cloned();
+	while let Some(pos) = next {
+		run.push(pos);
+		next = previous[pos];
+	}
+	run.reverse();
+	run
+}
+
+impl XspfPlaylist {
+	/* What changed between this playlist and a newer version of it ("other") */
+	pub fn diff(&self, other: &XspfPlaylist) -> PlaylistDiff
+	{
+		let (old, new) = (&self.tracks, &other.tracks);
+		let mut old_matched: Vec<Option<usize>> = vec![None; old.len()];
+		let mut new_matched: Vec<Option<usize>> = vec![None; new.len()];
+
+		/* By path, then by filename for those left over */
+		match_by(old, new, &mut old_matched, &mut new_matched, |t| t.path.to_string());
+		match_by(old, new, &mut old_matched, &mut new_matched, |t| fold_name(&t.filename));
+
+		/* Matched tracks (in the old order), and which of them kept their order */
+		let pairs: Vec<(usize, usize)> = old_matched.iter().enumerate()
+			.filter_map(|(i, matched)| matched.map(|j| (i, j)))
+			.collect();
+		let new_order: Vec<usize> = pairs.iter().map(|&(_, j)| j).collect();
+		let in_order: HashSet<usize> = longest_increasing_run(&new_order).into_iter().collect();
+
+		let mut diff = PlaylistDiff {
+			added: new_matched.iter().enumerate()
+				.filter(|&(_, matched)| matched.is_none())
+				.map(|(j, _)| DiffTrack { index: j, path: new[j].path.to_string() })
+				.collect(),
+			removed: old_matched.iter().enumerate()
+				.filter(|&(_, matched)| matched.is_none())
+				.map(|(i, _)| DiffTrack { index: i, path: old[i].path.to_string() })
+				.collect(),
+			relocated: Vec::new(),
+			duration_changed: Vec::new(),
+			reordered: Vec::new(),
+		};
+
+		for (pos, &(i, j)) in pairs.iter().enumerate() {
+			let matched = MatchedTrack {
+				old_index: i,
+				new_index: j,
+				old_path: old[i].path.to_string(),
+				new_path: new[j].path.to_string(),
+			};
+
+			if matched.old_path != matched.new_path {
+				diff.relocated.push(matched.clone());
+			}
+			if old[i].duration != new[j].duration {
+				diff.duration_changed.push(DurationChange {
+					track: matched.clone(),
+					old_duration_ms: old[i].duration.map(|d| d.0),
+					new_duration_ms: new[j].duration.map(|d| d.0),
+				});
+			}
+			if !in_order.contains(&pos) {
+				diff.reordered.push(matched);
+			}
+		}
+		diff
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use diagnostics::Diagnostics;
+	use track_duration::TrackDuration;
+
+	fn playlist(paths: &[&str]) -> XspfPlaylist
+	{
+		XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_longest_increasing_run()
+	{
+		assert_eq!(vec![0, 2, 3], longest_increasing_run(&[1, 3, 2, 4]));
+		assert_eq!(vec![0, 1, 2], longest_increasing_run(&[0, 1, 2]));
+		assert_eq!(1, longest_increasing_run(&[2, 1, 0]).len());
+		assert!(longest_increasing_run(&[]).is_empty());
+	}
+
+	#[test]
+	fn test_identical()
+	{
+		let paths = ["/music/20170403/v01-tranquil.mp3", "/music/20170403/v02-celestial.mp3"];
+		let diff = playlist(&paths).diff(&playlist(&paths));
+		assert!(diff.is_empty());
+	}
+
+	#[test]
+	fn test_diff()
+	{
+		let mut old = playlist(&["/music/20170403/v01-tranquil.mp3",
+		                         "/music/20170403/v02-celestial.mp3",
+		                         "/music/20170403/v03-moose.mp3",
+		                         "/music/20170404/v01-dawn.mp3",
+		                         "/music/20170404/v02-dusk.mp3"]);
+		let mut new = playlist(&["/music/20170405/v01-new.mp3",
+		                         "/music/20170403/v01-tranquil.mp3",
+		                         "/music/20170403/v03-moose.mp3",
+		                         "/music/20170403/v02-celestial.mp3",
+		                         "/archive/20170404/v01-dawn.mp3"]);
+		old.tracks[3].duration = Some(TrackDuration(60_000));
+		new.tracks[4].duration = Some(TrackDuration(61_000));
+
+		let diff = old.diff(&new);
+		assert!(!diff.is_empty());
+		assert_eq!(vec![(0, "/music/20170405/v01-new.mp3")],
+		           diff.added.iter().map(|t| (t.index, t.path.as_str())).collect::<Vec<_>>());
+		assert_eq!(vec![(4, "/music/20170404/v02-dusk.mp3")],
+		           diff.removed.iter().map(|t| (t.index, t.path.as_str())).collect::<Vec<_>>());
+
+		/* Moved to another folder - so matched by filename */
+		assert_eq!(vec![(3, 4)], diff.relocated.iter().map(|t| (t.old_index, t.new_index)).collect::<Vec<_>>());
+		assert_eq!("/archive/20170404/v01-dawn.mp3", diff.relocated[0].new_path);
+
+		assert_eq!(1, diff.duration_changed.len());
+		assert_eq!((Some(60_000), Some(61_000)), (diff.duration_changed[0].old_duration_ms, diff.duration_changed[0].new_duration_ms));
+
+		/* Only celestial moved - everything else just shifted along for the added track */
+		assert_eq!(vec![(1, 3)], diff.reordered.iter().map(|t| (t.old_index, t.new_index)).collect::<Vec<_>>());
+	}
+
+	/* Filenames get matched up despite encoding artifacts, and repeated tracks in the order they're listed */
+	#[test]
+	fn test_diff_matching()
+	{
+		let old = playlist(&["/music/20170403/v01-tranquil.mp3",
+		                     "/music/20170404/v02 dusk.mp3",
+		                     "/music/20170403/v01-tranquil.mp3"]);
+		let new = playlist(&["/music/20170403/v01-tranquil.mp3",
+		                     "/backup/20170404/V02_Dusk.mp3"]);
+
+		let diff = old.diff(&new);
+		assert_eq!(vec![(1, 1)], diff.relocated.iter().map(|t| (t.old_index, t.new_index)).collect::<Vec<_>>());
+		assert_eq!(vec![2], diff.removed.iter().map(|t| t.index).collect::<Vec<_>>());
+		assert!(diff.added.is_empty());
+		assert!(diff.reordered.is_empty());
+	}
+}
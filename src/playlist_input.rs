@@ -0,0 +1,129 @@
+/* Playlist input - Where the playlists being parsed get read from
+ *
+ * Playlists usually come from a file, but can also be piped in on stdin (given as "-", e.g. when they're
+ * generated on the fly by another tool). Either way, they can be gzip-compressed (e.g. old playlists archived
+ * as ".xspf.gz") - these get decompressed as they're read, so the parser only ever sees the document itself.
+ */
+extern crate flate2;
+use self::flate2::read::MultiGzDecoder;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/* Filename meaning "read the playlist from stdin" */
+pub const STDIN_NAME: &'static str = "-";
+
+/* Name used for stdin in place of the filename (e.g. in the playlist's title) */
+const STDIN_DISPLAY_NAME: &'static str = "<stdin>";
+
+/* Bytes that gzip streams start with */
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/* *************************************************** */
+
+/* Does the filename mean stdin? */
+pub fn is_stdin(filename: &str) -> bool
+{
+	filename == STDIN_NAME
+}
+
+/* Name of the input to show (e.g. in the playlist's title) */
+pub fn display_name(filename: &str) -> &str
+{
+	if is_stdin(filename) { STDIN_DISPLAY_NAME } else { filename }
+}
+
+/* Is the file named as being gzip-compressed? (e.g. "sessions.xspf.gz") */
+pub fn has_gzip_extension(filename: &str) -> bool
+{
+	Path::new(filename).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/* Open the playlist for reading - stdin for "-", otherwise the file, decompressing it if needed */
+pub fn open(filename: &str) -> io::Result<Box<dyn Read>>
+{
+	if is_stdin(filename) {
+		decompressed(io::stdin(), false)
+	}
+	else {
+		decompressed(File::open(filename)?, has_gzip_extension(filename))
+	}
+}
+
+/* Read the source through a gzip decoder if it's compressed (i.e. it starts with the gzip magic bytes,
+ * or "gzipped" says it is), or just as it is otherwise
+ * NOTE: The first bytes have to be read to check them, so they get put back in front of the rest
+ */
+pub fn decompressed<R: Read + 'static>(mut source: R, gzipped: bool) -> io::Result<Box<dyn Read>>
+{
+	let mut start: Vec<u8> = Vec::with_capacity(GZIP_MAGIC.len());
+	(&mut source).take(GZIP_MAGIC.len() as u64).read_to_end(&mut start)?;
+
+	let gzipped = gzipped || start[..] == GZIP_MAGIC[..];
+	let reader = io::Cursor::new(start).chain(source);
+	if gzipped {
+		Ok(Box::new(MultiGzDecoder::new(reader)))
+	}
+	else {
+		Ok(Box::new(reader))
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::flate2::write::GzEncoder;
+	use super::flate2::Compression;
+	use std::io::Write;
+
+	fn gzip(data: &[u8]) -> Vec<u8>
+	{
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(data).unwrap();
+		encoder.finish().unwrap()
+	}
+
+	fn read_all(mut reader: Box<dyn Read>) -> io::Result<String>
+	{
+		let mut contents = String::new();
+		reader.read_to_string(&mut contents)?;
+		Ok(contents)
+	}
+
+	#[test]
+	fn test_decompressed()
+	{
+		let xml = "<playlist xmlns=\"http://xspf.org/ns/0/\"/>";
+
+		/* Plain documents get passed through as they are (including the bytes read to check them) */
+		assert_eq!(xml, read_all(decompressed(io::Cursor::new(xml.as_bytes().to_vec()), false).unwrap()).unwrap());
+
+		/* Compressed ones get decompressed, whether they're named as such or not */
+		let compressed = gzip(xml.as_bytes());
+		assert_eq!(xml, read_all(decompressed(io::Cursor::new(compressed.clone()), false).unwrap()).unwrap());
+		assert_eq!(xml, read_all(decompressed(io::Cursor::new(compressed), true).unwrap()).unwrap());
+
+		/* ... but ones named as compressed that aren't can't be read */
+		assert!(read_all(decompressed(io::Cursor::new(xml.as_bytes().to_vec()), true).unwrap()).is_err());
+
+		/* Inputs shorter than the magic bytes are still fine */
+		assert_eq!("", read_all(decompressed(io::Cursor::new(Vec::new()), false).unwrap()).unwrap());
+		assert_eq!("x", read_all(decompressed(io::Cursor::new(b"x".to_vec()), false).unwrap()).unwrap());
+	}
+
+	#[test]
+	fn test_names()
+	{
+		assert!(is_stdin("-"));
+		assert!(!is_stdin("-.xspf"));
+		assert_eq!(("<stdin>", "sessions.xspf"), (display_name("-"), display_name("sessions.xspf")));
+
+		assert!(has_gzip_extension("archive/2017-08.xspf.gz"));
+		assert!(has_gzip_extension("2017-08.xspf.GZ"));
+		assert!(!has_gzip_extension("2017-08.xspf"));
+	}
+}
@@ -0,0 +1,408 @@
+/* Merging several playlists into one
+ *
+ * The tracks just get concatenated (in the order the playlists were given). The metadata about
+ * the playlists themselves (title, creator, annotation, date) can conflict though, so how each
+ * of those gets combined is controlled by a MetaStrategy, with a record of how each final value
+ * was arrived at (i.e. which strategy, and which of the playlists it came from).
+ *
+ * Each track also keeps a note of which playlist it came from (Track.source_playlist), so that
+ * this isn't lost once they're all in the one playlist.
+ *
+ * Tracks in more than one of the playlists (i.e. with the same path) can optionally be kept only once,
+ * where they first appear (e.g. for a "best of" list drawn from monthly playlists that overlap).
+ */
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use diagnostics::Diagnostics;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Separator used when concatenating values */
+pub const CONCAT_SEPARATOR: &'static str = "; ";
+
+/* *************************************************** */
+/* Strategies */
+
+/* How conflicting metadata values get combined */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetaStrategy {
+	/* Use the value from the first playlist that has one */
+	First,
+	/* Use the value from the last playlist that has one */
+	Last,
+	/* Join all the distinct values, in order */
+	Concat,
+	/* Leave the field out of the merged playlist */
+	Drop,
+}
+
+impl MetaStrategy {
+	/* Parse a strategy name, as given for "--meta" */
+	pub fn parse(s: &str) -> Result<MetaStrategy, String>
+	{
+		match s {
+			"first"  => Ok(MetaStrategy::First),
+			"last"   => Ok(MetaStrategy::Last),
+			"concat" => Ok(MetaStrategy::Concat),
+			"drop"   => Ok(MetaStrategy::Drop),
+			_        => Err(format!("Unknown metadata strategy '{}' (expected first, last, concat, or drop)", s))
+		}
+	}
+
+	/* Name of the strategy (as for "--meta") */
+	pub fn name(&self) -> &'static str
+	{
+		match *self {
+			MetaStrategy::First  => "first",
+			MetaStrategy::Last   => "last",
+			MetaStrategy::Concat => "concat",
+			MetaStrategy::Drop   => "drop",
+		}
+	}
+}
+
+impl fmt::Display for MetaStrategy {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.name())
+	}
+}
+
+/* *************************************************** */
+/* Field Resolution */
+
+/* Combined value for a field, and where it came from */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ResolvedField {
+	pub value: Option<String>,
+	/* Indices of the inputs that contributed to the value */
+	pub sources: Vec<usize>,
+}
+
+/* Combine the values of a field from each input (in order), using the given strategy
+ * (Missing values, and ones that are only whitespace, are ignored)
+ */
+pub fn resolve_field(values: &[Option<&str>], strategy: MetaStrategy) -> ResolvedField
+{
+	let present: Vec<(usize, &str)> = values.iter()
+	                                        .enumerate()
+	                                        .filter_map(|(i, v)| v.map(|v| (i, v.trim())))
+	                                        .filter(|&(_, v)| !v.is_empty())
+	                                        .collect();
+
+	let single = |entry: Option<&(usize, &str)>| match entry {
+		Some(&(i, v)) => ResolvedField { value: Some(v.to_string()), sources: vec![i] },
+		None          => ResolvedField { value: None, sources: Vec::new() },
+	};
+
+	match strategy {
+		MetaStrategy::First => single(present.first()),
+		MetaStrategy::Last  => single(present.last()),
+		MetaStrategy::Drop  => ResolvedField { value: None, sources: Vec::new() },
+		MetaStrategy::Concat => {
+			/* Each distinct value only appears once (with its first source) */
+			let mut distinct: Vec<(usize, &str)> = Vec::new();
+			for &(i, v) in present.iter() {
+				if !distinct.iter().any(|&(_, d)| d == v) {
+					distinct.push((i, v));
+				}
+			}
+
+			let joined: Vec<&str> = distinct.iter().map(|&(_, v)| v).collect();
+			ResolvedField {
+				value: if joined.is_empty() { None } else { Some(joined.join(CONCAT_SEPARATOR)) },
+				sources: distinct.iter().map(|&(i, _)| i).collect(),
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Merging */
+
+/* How one metadata field of the merged playlist was arrived at */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct FieldProvenance {
+	pub field: String,
+	pub strategy: MetaStrategy,
+	pub value: Option<String>,
+	/* Indices of the inputs that the value came from */
+	pub sources: Vec<usize>,
+}
+
+/* Record of how a merged playlist was put together */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct MergeProvenance {
+	/* Number of tracks taken from each input */
+	pub track_counts: Vec<usize>,
+	/* How each metadata field was combined (title, creator, annotation, date) */
+	pub fields: Vec<FieldProvenance>,
+	/* Number of tracks left out for already being in the merged playlist (only when de-duplicating) */
+	pub duplicates: usize,
+}
+
+impl XspfPlaylist {
+	/* Merge the playlists into one, with their tracks in order, and the metadata combined using the given strategy
+	 * - names: Name of each playlist (e.g. the path it was read from), noted as the source of each of its tracks.
+	 *          Tracks that already have a source (i.e. from an earlier merge) keep it.
+	 * - dedupe: Only keep the first of the tracks with the same path. If it doesn't have a duration,
+	 *           it gets one from the later copies (if any of them have one).
+	 */
+	pub fn merge(playlists: Vec<XspfPlaylist>, names: &[String], strategy: MetaStrategy, dedupe: bool)
+		-> (XspfPlaylist, MergeProvenance)
+	{
+		let mut fields: Vec<FieldProvenance> = Vec::new();
+		let mut resolve = |field: &str, get: &dyn Fn(&XspfPlaylist) -> Option<&str>| -> Option<String> {
+			let values: Vec<Option<&str>> = playlists.iter().map(get).collect();
+			let resolved = resolve_field(&values, strategy);
+			fields.push(FieldProvenance {
+				field: field.to_string(),
+				strategy: strategy,
+				value: resolved.value.clone(),
+				sources: resolved.sources,
+			});
+			resolved.value
+		};
+
+		let title = resolve("title", &|p| p.title.as_deref());
+		let creator = resolve("creator", &|p| p.creator.as_deref());
+		let annotation = resolve("annotation", &|p| p.annotation.as_deref());
+		let date = resolve("date", &|p| p.date.as_deref());
+
+		let track_counts = playlists.iter().map(|p| p.len()).collect();
+		let mut tracks: Vec<Track> = Vec::new();
+		let mut first_seen: HashMap<PathBuf, usize> = HashMap::new();
+		let mut duplicates = 0;
+		for (playlist, name) in playlists.into_iter().zip(names.iter()) {
+			for mut track in playlist.tracks.into_iter() {
+				if dedupe {
+					if let Some(&index) = first_seen.get(track.file_path()) {
+						if tracks[index].duration.is_none() {
+							tracks[index].duration = track.duration;
+						}
+						duplicates += 1;
+						continue;
+					}
+					first_seen.insert(track.file_path().to_path_buf(), tracks.len());
+				}
+				
+				track.source_playlist.get_or_insert_with(|| name.clone());
+				tracks.push(track);
+			}
+		}
+
+		let merged = XspfPlaylist {
+			tracks: tracks,
+			title: title,
+			creator: creator,
+			annotation: annotation,
+			date: date,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		(merged, MergeProvenance { track_counts: track_counts, fields: fields, duplicates: duplicates })
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn resolve(values: &[Option<&str>], strategy: MetaStrategy) -> (Option<String>, Vec<usize>)
+	{
+		let resolved = resolve_field(values, strategy);
+		(resolved.value, resolved.sources)
+	}
+
+	#[test]
+	fn test_resolve_field()
+	{
+		let values = [None, Some("Sessions"), Some("  "), Some("Practice"), Some("Sessions")];
+
+		assert_eq!((Some("Sessions".to_string()), vec![1]), resolve(&values, MetaStrategy::First));
+		assert_eq!((Some("Sessions".to_string()), vec![4]), resolve(&values, MetaStrategy::Last));
+		assert_eq!((Some("Sessions; Practice".to_string()), vec![1, 3]), resolve(&values, MetaStrategy::Concat));
+		assert_eq!((None, vec![]), resolve(&values, MetaStrategy::Drop));
+
+		/* Surrounding whitespace doesn't make values distinct */
+		assert_eq!((Some("A; B".to_string()), vec![0, 2]),
+		           resolve(&[Some("A"), Some(" A "), Some("B")], MetaStrategy::Concat));
+	}
+
+	#[test]
+	fn test_resolve_field_empty()
+	{
+		for &strategy in [MetaStrategy::First, MetaStrategy::Last, MetaStrategy::Concat, MetaStrategy::Drop].iter() {
+			assert_eq!((None, vec![]), resolve(&[], strategy));
+			assert_eq!((None, vec![]), resolve(&[None, Some(""), None], strategy));
+		}
+	}
+
+	#[test]
+	fn test_resolve_field_identical()
+	{
+		let values = [Some("Aligorith"), Some("Aligorith"), Some("Aligorith")];
+		for &strategy in [MetaStrategy::First, MetaStrategy::Last, MetaStrategy::Concat].iter() {
+			let (value, _) = resolve(&values, strategy);
+			assert_eq!(Some("Aligorith".to_string()), value);
+		}
+		assert_eq!(vec![0], resolve(&values, MetaStrategy::Concat).1);
+		assert_eq!((None, vec![]), resolve(&values, MetaStrategy::Drop));
+	}
+
+	#[test]
+	fn test_merge()
+	{
+		let playlist = |title: Option<&str>, creator: Option<&str>, paths: &[&str]| XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: title.map(|t| t.to_string()),
+			creator: creator.map(|c| c.to_string()),
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		let inputs = || vec![playlist(Some("April"), Some("Aligorith"), &["music/20170403/v01-tranquil.mp3"]),
+		                     playlist(None, Some("Aligorith"), &[]),
+		                     playlist(Some("May"), None, &["music/20170502/v01-dawn.mp3", "music/20170502/v02-dusk.mp3"])];
+
+		let names = vec!["2017-04.xspf".to_string(), "empty.xspf".to_string(), "2017-05.xspf".to_string()];
+		let (merged, provenance) = XspfPlaylist::merge(inputs(), &names, MetaStrategy::Concat, false);
+		assert_eq!(vec!["v01-tranquil.mp3", "v01-dawn.mp3", "v02-dusk.mp3"],
+		           merged.tracks.iter().map(|t| t.filename.as_str()).collect::<Vec<_>>());
+		assert_eq!(Some("April; May".to_string()), merged.title);
+		assert_eq!(Some("Aligorith".to_string()), merged.creator);
+		assert_eq!(None, merged.annotation);
+
+		assert_eq!(vec![1, 0, 2], provenance.track_counts);
+		assert_eq!(vec!["title", "creator", "annotation", "date"],
+		           provenance.fields.iter().map(|f| f.field.as_str()).collect::<Vec<_>>());
+		assert_eq!(vec![0, 2], provenance.fields[0].sources);
+		assert!(provenance.fields.iter().all(|f| f.strategy == MetaStrategy::Concat));
+
+		/* Tracks are the same whatever the strategy */
+		let (merged, _) = XspfPlaylist::merge(inputs(), &names, MetaStrategy::Last, false);
+		assert_eq!((Some("May"), 3), (merged.title.as_deref(), merged.len()));
+	}
+
+	/* Tracks in several of the playlists only appear once (where they were first seen), when de-duplicating */
+	#[test]
+	fn test_merge_dedupe()
+	{
+		use track_duration::TrackDuration;
+		
+		let track = |path: &str, duration: Option<i64>| {
+			let mut track = Track::from_filepath(path).unwrap();
+			track.duration = duration.map(TrackDuration);
+			track
+		};
+		let playlist = |title: &str, tracks: Vec<Track>| XspfPlaylist {
+			tracks: tracks,
+			title: Some(title.to_string()),
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		let inputs = || vec![
+			playlist("March", vec![track("/music/20170301/v01-dawn.mp3", None),
+			                       track("/music/20170302/v02-dusk.mp3", Some(60_000))]),
+			playlist("April", vec![track("/music/20170403/v01-tranquil.mp3", Some(61_000)),
+			                       track("/music/20170301/v01-dawn.mp3", Some(45_000)),
+			                       track("/music/20170302/v02-dusk.mp3", Some(75_000))]),
+			playlist("May", vec![track("/music/20170301/v01-dawn.mp3", Some(50_000)),
+			                     track("/music/20170403/v01-tranquil.mp3", None)]),
+		];
+		let names = vec!["2017-03.xspf".to_string(), "2017-04.xspf".to_string(), "2017-05.xspf".to_string()];
+		
+		let (merged, provenance) = XspfPlaylist::merge(inputs(), &names, MetaStrategy::Concat, true);
+		assert_eq!(vec![("v01-dawn.mp3", Some(45_000), "2017-03.xspf"),
+		                ("v02-dusk.mp3", Some(60_000), "2017-03.xspf"),
+		                ("v01-tranquil.mp3", Some(61_000), "2017-04.xspf")],
+		           merged.tracks.iter()
+		                        .map(|t| (t.filename.as_str(), t.duration.map(|d| d.0), t.source_playlist.as_deref().unwrap()))
+		                        .collect::<Vec<_>>());
+		assert_eq!(Some("March; April; May".to_string()), merged.title);
+		assert_eq!((vec![2, 3, 2], 4), (provenance.track_counts, provenance.duplicates));
+		
+		/* Otherwise everything is kept */
+		let (merged, provenance) = XspfPlaylist::merge(inputs(), &names, MetaStrategy::Concat, false);
+		assert_eq!((7, 0), (merged.len(), provenance.duplicates));
+		assert_eq!(None, merged.tracks[0].duration);
+	}
+
+	/* Each track records which playlist it came from, which survives being saved and loaded again */
+	#[test]
+	fn test_merge_track_sources()
+	{
+		use filter_expr::FilterExpr;
+		use serde_json;
+		use test_utils::TempDir;
+		use xspf_parser::{parse_xspf, ParseOptions};
+
+		let dir = TempDir::new("merge-sources");
+		let march = dir.write_file("2017-03.xspf",
+			b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			  <track><location>file:///music/20170301/v01-dawn.mp3</location></track>\
+			  <track><location>file:///music/undated/v02-dusk.mp3</location></track>\
+			  </trackList></playlist>");
+		let april = dir.write_file("2017-04.xspf",
+			b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			  <track><location>file:///music/20170403/v01-tranquil.mp3</location></track>\
+			  </trackList></playlist>");
+
+		let paths = vec![march.to_str().unwrap().to_string(), april.to_str().unwrap().to_string()];
+		let inputs = paths.iter().map(|p| parse_xspf(p, &ParseOptions::default()).unwrap()).collect();
+		assert!(parse_xspf(&paths[0], &ParseOptions::default()).unwrap().tracks.iter().all(|t| t.source_playlist.is_none()));
+
+		let (merged, _) = XspfPlaylist::merge(inputs, &paths, MetaStrategy::First, false);
+		let sources = |xspf: &XspfPlaylist| -> Vec<Option<String>> { xspf.tracks.iter().map(|t| t.source_playlist.clone()).collect() };
+		assert_eq!(vec![Some(paths[0].clone()), Some(paths[0].clone()), Some(paths[1].clone())], sources(&merged));
+
+		/* Filtering by the playlist's filename (or its full path) */
+		let filter = FilterExpr::parse("source_playlist=2017-03.xspf").unwrap();
+		assert_eq!(vec!["v01-dawn.mp3", "v02-dusk.mp3"],
+		           merged.tracks.iter().filter(|t| filter.matches(t)).map(|t| t.filename.as_str()).collect::<Vec<_>>());
+		let filter = FilterExpr::parse(&format!("source_playlist!='{}'", paths[0])).unwrap();
+		assert_eq!(1, merged.tracks.iter().filter(|t| filter.matches(t)).count());
+
+		/* Round-trip through the XSPF writer/parser */
+		let merged_path = dir.write_file("2017.xspf", merged.to_xspf_string().as_bytes());
+		let reloaded = parse_xspf(merged_path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+		assert_eq!(sources(&merged), sources(&reloaded));
+
+		/* Merging again keeps the original sources (instead of the intermediate playlist) */
+		let (remerged, _) = XspfPlaylist::merge(vec![reloaded], &["2017.xspf".to_string()], MetaStrategy::First, false);
+		assert_eq!(sources(&merged), sources(&remerged));
+
+		/* Only tracks with a source have it in their JSON */
+		let json = serde_json::to_value(&merged.tracks).unwrap();
+		assert_eq!(paths[1], json[2]["source_playlist"]);
+		let unmerged = serde_json::to_value(Track::from_filepath("music/20170403/v01-tranquil.mp3").unwrap()).unwrap();
+		assert!(unmerged.get("source_playlist").is_none());
+	}
+
+	#[test]
+	fn test_parse_strategy()
+	{
+		for name in ["first", "last", "concat", "drop"].iter() {
+			assert_eq!(*name, MetaStrategy::parse(name).unwrap().name());
+		}
+		assert!(MetaStrategy::parse("newest").is_err());
+	}
+}
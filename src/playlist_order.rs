@@ -0,0 +1,427 @@
+/* Playlist order consistency check
+ *
+ * Playlists are expected to list sessions chronologically, and the takes within
+ * each session by their index. Manual edits have a habit of scrambling this,
+ * so this check points out where the order breaks, and can compute the fixed order.
+ *
+ * The tracks can also be sorted by other keys (see SortKey), for the "sort" mode,
+ * or shuffled (repeatably, for a given seed), for the "shuffle" mode.
+ */
+use std::str::FromStr;
+
+use diagnostics::Diagnostics;
+use track_date::SessionDate;
+use track_name_info::TrackType;
+use track_sampling::SampleRng;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "order";
+
+/* *************************************************** */
+/* Sort Keys */
+
+/* Position of each track type when ordering tracks within a session
+ * (Unknown types go last, as they don't follow the naming conventions anyway)
+ */
+pub fn type_rank(track_type: &TrackType) -> u8
+{
+	match *track_type {
+		TrackType::ViolinLayering => 0,
+		TrackType::MuseScore      => 1,
+		TrackType::Piano          => 2,
+		TrackType::Voice          => 3,
+		TrackType::UnknownType    => 4,
+	}
+}
+
+/* Key that tracks get ordered by - (date, type, index)
+ * > returns None for tracks without a parseable date (these stay where they are)
+ */
+fn sort_key(track: &Track) -> Option<(SessionDate, u8, i32)>
+{
+	track.date.session_date().map(|date| (date, type_rank(&track.info().track_type), track.info().index))
+}
+
+/* Short description of a track for findings - "track <pos> '<filename>'" */
+fn describe(track: &Track, idx: usize) -> String
+{
+	format!("track {0} '{1}'", idx + 1, track.filename)
+}
+
+/* *************************************************** */
+/* Check */
+
+/* Check that the tracks are in chronological/index order
+ *
+ * Reports places where the date goes backwards compared to the previous dated track,
+ * and where the index goes backwards within a run of same-date/same-type tracks.
+ * Tracks without a parseable date are ignored entirely.
+ */
+pub fn check_playlist(xspf: &XspfPlaylist) -> Diagnostics
+{
+	let mut diagnostics = Diagnostics::new();
+	let mut prev: Option<(usize, (SessionDate, u8, i32))> = None;
+
+	for (i, track) in xspf.tracks.iter().enumerate() {
+		let key = match sort_key(track) {
+			Some(key) => key,
+			None      => continue
+		};
+
+		if let Some((prev_idx, prev_key)) = prev {
+			let prev_track = &xspf.tracks[prev_idx];
+
+			if key.0 < prev_key.0 {
+				diagnostics.warn_track(CATEGORY, i,
+					format!("date goes backwards - {0} ({1}) comes after {2} ({3})",
+					        describe(track, i), key.0, describe(prev_track, prev_idx), prev_key.0));
+			}
+			else if (key.0 == prev_key.0) && (key.1 == prev_key.1) && (key.2 < prev_key.2) {
+				diagnostics.warn_track(CATEGORY, i,
+					format!("index goes backwards - {0} (#{1}) comes after {2} (#{3})",
+					        describe(track, i), key.2, describe(prev_track, prev_idx), prev_key.2));
+			}
+		}
+
+		prev = Some((i, key));
+	}
+
+	diagnostics
+}
+
+/* *************************************************** */
+/* Fix */
+
+/* Compute the fixed order of the tracks
+ *
+ * Dated tracks get stable-sorted by (date, type, index), and placed back into the slots
+ * that dated tracks occupied. Undated tracks keep their positions.
+ *
+ * > returns the indices of the tracks in their new order
+ */
+pub fn sorted_order(xspf: &XspfPlaylist) -> Vec<usize>
+{
+	let keys: Vec<_> = xspf.tracks.iter().map(sort_key).collect();
+
+	let slots: Vec<usize> = (0 .. keys.len()).filter(|&i| keys[i].is_some()).collect();
+	let mut dated = slots.clone();
+	dated.sort_by_key(|&i| keys[i]);  /* NOTE: sort_by_key() is stable */
+
+	let mut order: Vec<usize> = (0 .. keys.len()).collect();
+	for (&slot, &idx) in slots.iter().zip(dated.iter()) {
+		order[slot] = idx;
+	}
+	order
+}
+
+/* Rearrange the tracks in the playlist into the given order (as returned by sorted_order()) */
+pub fn reorder_tracks(xspf: &mut XspfPlaylist, order: &[usize])
+{
+	let mut tracks: Vec<Option<Track>> = xspf.tracks.drain(..).map(Some).collect();
+	xspf.tracks = order.iter()
+	                   .map(|&i| tracks[i].take().unwrap())  /* Safe - order is a permutation */
+	                   .collect();
+}
+
+/* *************************************************** */
+/* Sorting */
+
+/* What to sort the tracks by (for the "sort" mode)
+ * NOTE: All of these are stable - tracks that compare the same keep their original order
+ */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum SortKey {
+	/* Canonical order - by session date (directory), then type, then index. Unknown types go last, in their original order */
+	Date,
+	/* Descriptive name from the filename (ignoring case) */
+	Name,
+	/* Shortest first. Tracks without known durations go last */
+	Duration,
+	/* By type only (in the same order as for Date) */
+	TrackType,
+}
+
+impl SortKey {
+	pub fn name(&self) -> &'static str
+	{
+		match *self {
+			SortKey::Date      => "date",
+			SortKey::Name      => "name",
+			SortKey::Duration  => "duration",
+			SortKey::TrackType => "type",
+		}
+	}
+}
+
+impl FromStr for SortKey {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<SortKey, String>
+	{
+		match s {
+			"date"     => Ok(SortKey::Date),
+			"name"     => Ok(SortKey::Name),
+			"duration" => Ok(SortKey::Duration),
+			"type"     => Ok(SortKey::TrackType),
+			_          => Err(format!("Unknown sort key '{}' (expected date, name, duration, or type)", s))
+		}
+	}
+}
+
+/* Compute the order of the tracks when sorted by the given key
+ * > returns the indices of the tracks in their new order (see reorder_tracks())
+ */
+pub fn order_by(xspf: &XspfPlaylist, key: SortKey) -> Vec<usize>
+{
+	let mut order: Vec<usize> = (0 .. xspf.len()).collect();
+	let tracks = &xspf.tracks;
+
+	/* NOTE: sort_by_key()/sort_by_cached_key() are stable */
+	match key {
+		SortKey::Date => {
+			order.sort_by_key(|&i| {
+				let info = tracks[i].info();
+				match info.track_type {
+					TrackType::UnknownType => (true, "", 0, 0),
+					ref track_type         => (false, tracks[i].date.as_str(), type_rank(track_type), info.index)
+				}
+			});
+		},
+		SortKey::Name => {
+			order.sort_by_cached_key(|&i| tracks[i].info().name.to_lowercase());
+		},
+		SortKey::Duration => {
+			order.sort_by_key(|&i| match tracks[i].duration {
+				Some(ref d) => (false, d.0),
+				None        => (true, 0)
+			});
+		},
+		SortKey::TrackType => {
+			order.sort_by_key(|&i| type_rank(&tracks[i].info().track_type));
+		},
+	}
+	order
+}
+
+impl XspfPlaylist {
+	/* Playlist with the tracks sorted by the given key (see SortKey) */
+	pub fn sorted(mut self, key: SortKey) -> XspfPlaylist
+	{
+		let order = order_by(&self, key);
+		reorder_tracks(&mut self, &order);
+		self
+	}
+
+	/* Playlist with the tracks in a random order - the same seed always gives the same order */
+	pub fn shuffled(mut self, seed: u64) -> XspfPlaylist
+	{
+		let mut order: Vec<usize> = (0 .. self.tracks.len()).collect();
+		SampleRng::new(seed).shuffle(&mut order);
+		reorder_tracks(&mut self, &order);
+		self
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use track_duration::TrackDuration;
+
+	fn make_playlist(paths: &[&str]) -> XspfPlaylist
+	{
+		XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+
+	fn filenames(xspf: &XspfPlaylist) -> Vec<&str>
+	{
+		xspf.tracks.iter().map(|t| t.filename.as_str()).collect()
+	}
+
+	#[test]
+	fn test_out_of_order()
+	{
+		let xspf = make_playlist(&[
+			"/music/20170403/v01-tranquil.mp3",
+			"/music/20170403/v03-winds_of_flutter.mp3",
+			"/music/20170403/v02-celestial.mp3",          /* index goes backwards */
+			"/music/20170403/20170403-01-sonata.mp3",     /* different type - starts a new run */
+			"/music/20170401/v01-moose.mp3",              /* date goes backwards */
+			"/music/20170405/v02-dawn.mp3",
+		]);
+
+		let diagnostics = check_playlist(&xspf);
+		assert_eq!(2, diagnostics.len());
+
+		let index_finding = &diagnostics.entries[0];
+		assert_eq!(Some(2), index_finding.track_index);
+		assert!(index_finding.message.contains("track 3 'v02-celestial.mp3'"));
+		assert!(index_finding.message.contains("track 2 'v03-winds_of_flutter.mp3'"));
+
+		let date_finding = &diagnostics.entries[1];
+		assert_eq!(Some(4), date_finding.track_index);
+		assert!(date_finding.message.contains("track 5 'v01-moose.mp3' (2017-04-01)"));
+		assert!(date_finding.message.contains("track 4 '20170403-01-sonata.mp3' (2017-04-03)"));
+
+		assert!(diagnostics.entries.iter().all(|d| d.category == CATEGORY));
+	}
+
+	#[test]
+	fn test_fix_order()
+	{
+		let mut xspf = make_playlist(&[
+			"/music/20170405/v02-dawn.mp3",
+			"/music/20170403/20170403-01-sonata.mp3",
+			"/music/20170403/v03-winds_of_flutter.mp3",
+			"/music/20170403/v01-tranquil.mp3",
+			"/music/20170401/v01-moose.mp3",
+		]);
+
+		let order = sorted_order(&xspf);
+		reorder_tracks(&mut xspf, &order);
+
+		assert_eq!(vec!["v01-moose.mp3", "v01-tranquil.mp3", "v03-winds_of_flutter.mp3", "20170403-01-sonata.mp3", "v02-dawn.mp3"],
+		           filenames(&xspf));
+		assert!(check_playlist(&xspf).is_empty());
+	}
+
+	/* Undated tracks don't trigger anything, and stay put */
+	#[test]
+	fn test_undated_tracks()
+	{
+		let mut xspf = make_playlist(&[
+			"/music/20170403/v02-celestial.mp3",
+			"/music/misc/intro.mp3",
+			"/music/20170403/v01-tranquil.mp3",
+			"/music/misc/outro.mp3",
+		]);
+
+		let diagnostics = check_playlist(&xspf);
+		assert_eq!(1, diagnostics.len());
+		assert_eq!(Some(2), diagnostics.entries[0].track_index);
+
+		let order = sorted_order(&xspf);
+		assert_eq!(vec![2, 1, 0, 3], order);
+
+		reorder_tracks(&mut xspf, &order);
+		assert_eq!(vec!["v01-tranquil.mp3", "intro.mp3", "v02-celestial.mp3", "outro.mp3"], filenames(&xspf));
+
+		/* All undated - nothing to report, nothing moves */
+		let xspf = make_playlist(&["/music/misc/outro.mp3", "/music/misc/intro.mp3"]);
+		assert!(check_playlist(&xspf).is_empty());
+		assert_eq!(vec![0, 1], sorted_order(&xspf));
+	}
+
+	#[test]
+	fn test_sort_by_date()
+	{
+		let xspf = make_playlist(&[
+			"/music/misc/outro.mp3",
+			"/music/20170405/v02-dawn.mp3",
+			"/music/20170403/20170403-01-sonata.mp3",
+			"/music/20170403/v03-winds_of_flutter.mp3",
+			"/music/misc/intro.mp3",
+			"/music/20170403/v01-tranquil.mp3",
+			"/music/20170403/v01b-tranquil.mp3",          /* same date/type/index as v01 - stays after it */
+			"/music/20170401/v01-moose.mp3",
+		]);
+
+		let sorted = xspf.sorted(SortKey::Date);
+		assert_eq!(vec!["v01-moose.mp3", "v01-tranquil.mp3", "v01b-tranquil.mp3", "v03-winds_of_flutter.mp3",
+		                "20170403-01-sonata.mp3", "v02-dawn.mp3",
+		                "outro.mp3", "intro.mp3"],
+		           filenames(&sorted));
+	}
+
+	#[test]
+	fn test_sort_by_other_keys()
+	{
+		let paths = [
+			"/music/20170403/v02-Celestial.mp3",
+			"/music/20170403/20170403-01-sonata.mp3",
+			"/music/20170401/v01-moose.mp3",
+			"/music/20170402/v01-celestial.mp3",
+			"/music/20170401/20170401-02-ballad.mp3",
+		];
+
+		/* Names compare ignoring case - ties keep their original order */
+		let sorted = make_playlist(&paths).sorted(SortKey::Name);
+		assert_eq!(vec!["20170401-02-ballad.mp3", "v02-Celestial.mp3", "v01-celestial.mp3", "v01-moose.mp3", "20170403-01-sonata.mp3"],
+		           filenames(&sorted));
+
+		/* Type only - otherwise in the original order */
+		let sorted = make_playlist(&paths).sorted(SortKey::TrackType);
+		assert_eq!(vec!["v02-Celestial.mp3", "v01-moose.mp3", "v01-celestial.mp3", "20170403-01-sonata.mp3", "20170401-02-ballad.mp3"],
+		           filenames(&sorted));
+	}
+
+	/* Tracks without durations go last (instead of panicking) */
+	#[test]
+	fn test_sort_by_duration()
+	{
+		let mut xspf = make_playlist(&[
+			"/music/20170403/v01-tranquil.mp3",
+			"/music/20170403/v02-celestial.mp3",
+			"/music/20170403/v03-winds_of_flutter.mp3",
+			"/music/20170403/v04-dawn.mp3",
+			"/music/20170403/v05-moose.mp3",
+		]);
+		for (track, duration) in xspf.tracks.iter_mut().zip(vec![None, Some(90_000), Some(61_000), None, Some(90_000)]) {
+			track.duration = duration.map(TrackDuration);
+		}
+
+		let sorted = xspf.sorted(SortKey::Duration);
+		assert_eq!(vec!["v03-winds_of_flutter.mp3", "v02-celestial.mp3", "v05-moose.mp3", "v01-tranquil.mp3", "v04-dawn.mp3"],
+		           filenames(&sorted));
+
+		/* Nothing to sort */
+		assert!(make_playlist(&[]).sorted(SortKey::Duration).is_empty());
+	}
+
+	#[test]
+	fn test_shuffled()
+	{
+		let paths: Vec<String> = (1 .. 11).map(|i| format!("/music/20170403/v{:02}-take.mp3", i)).collect();
+		let make = || make_playlist(&paths.iter().map(|p| p.as_str()).collect::<Vec<_>>());
+		let order_for = |seed: u64| -> Vec<String> {
+			make().shuffled(seed).tracks.iter().map(|t| t.filename.clone()).collect()
+		};
+
+		/* Same seed, same order */
+		assert_eq!(order_for(42), order_for(42));
+
+		/* Different seeds give different orders (almost always - there are 10! of them) */
+		assert_ne!(order_for(42), order_for(43));
+		assert!((1 .. 5).any(|seed| order_for(seed) != order_for(0)));
+
+		/* All the tracks are still there */
+		let mut shuffled = order_for(7);
+		shuffled.sort();
+		assert_eq!(filenames(&make()), shuffled.iter().map(|f| f.as_str()).collect::<Vec<_>>());
+
+		assert!(make_playlist(&[]).shuffled(1).is_empty());
+	}
+
+	#[test]
+	fn test_sort_key_names()
+	{
+		for key in [SortKey::Date, SortKey::Name, SortKey::Duration, SortKey::TrackType] {
+			assert_eq!(Ok(key), key.name().parse());
+		}
+		assert!("length".parse::<SortKey>().is_err());
+	}
+}
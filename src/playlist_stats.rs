@@ -0,0 +1,775 @@
+/* Summary statistics for the tracks in a playlist
+ *
+ * Totals (number of tracks, and their total duration), overall and per type of track, session date,
+ * and file extension, along with ways of comparing them between two periods of time (e.g. first vs
+ * second half of a year), and of breaking them down by day/week/month.
+ */
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use filter_expr::FilterExpr;
+use track_date::{SessionDate, WeekScheme, WeekStart};
+use track_name_info::TrackType;
+use xspf_parser::Track;
+
+/* Order that the types get listed in (same as for playlist_order) */
+const TYPE_ORDER: [TrackType; 5] = [
+	TrackType::ViolinLayering,
+	TrackType::MuseScore,
+	TrackType::Piano,
+	TrackType::Voice,
+	TrackType::UnknownType,
+];
+
+/* *************************************************** */
+/* Summary */
+
+/* Totals for one group of tracks (e.g. one type of track, or one session date) */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct TypeStats {
+	pub tracks: usize,
+	/* Total duration of the tracks with known durations */
+	pub duration_ms: i64,
+	/* Number of tracks whose duration is unknown */
+	pub uncounted: usize,
+
+	/* Number of rated tracks, and their average rating (unrated tracks aren't included) */
+	pub rated: usize,
+	pub average_rating: Option<f64>,
+}
+
+impl TypeStats {
+	/* Compute the totals for the given tracks */
+	fn from_tracks(tracks: &[&Track]) -> TypeStats
+	{
+		let mut stats = TypeStats::default();
+		let mut ratings = RatingTally::default();
+
+		for track in tracks.iter() {
+			stats.tracks += 1;
+			match track.duration {
+				Some(d) => stats.duration_ms += d.as_millis(),
+				None    => stats.uncounted += 1,
+			}
+			if let Some(rating) = track.rating {
+				ratings.add(rating);
+			}
+		}
+
+		stats.rated = ratings.rated;
+		stats.average_rating = ratings.average();
+		stats
+	}
+}
+
+/* Totals for each group of tracks, as given by the key (tracks without a key aren't included) */
+fn group_stats<F>(tracks: &[&Track], key: F) -> BTreeMap<String, TypeStats>
+	where F: Fn(&Track) -> Option<String>
+{
+	let mut groups: BTreeMap<String, Vec<&Track>> = BTreeMap::new();
+	for &track in tracks.iter() {
+		if let Some(key) = key(track) {
+			groups.entry(key).or_default().push(track);
+		}
+	}
+
+	groups.into_iter()
+	      .map(|(key, group)| (key, TypeStats::from_tracks(&group)))
+	      .collect()
+}
+
+/* Ratings of the tracks from one session date */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct RatingStats {
+	pub rated: usize,
+	pub average_rating: f64,
+}
+
+/* Running total of ratings, for working out their average */
+#[derive(Debug)]
+#[derive(Default)]
+struct RatingTally {
+	rated: usize,
+	stars: u64,
+}
+
+impl RatingTally {
+	fn add(&mut self, rating: u8)
+	{
+		self.rated += 1;
+		self.stars += rating as u64;
+	}
+
+	/* Average rating (to 2 decimal places), if any were rated */
+	fn average(&self) -> Option<f64>
+	{
+		if self.rated > 0 {
+			Some((self.stars as f64 / self.rated as f64 * 100.0).round() / 100.0)
+		}
+		else {
+			None
+		}
+	}
+}
+
+/* Totals for a set of tracks */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct StatsSummary {
+	pub tracks: usize,
+	pub duration_ms: i64,
+	pub uncounted: usize,
+
+	/* Range of session dates covered (for tracks with parseable dates) */
+	pub first_date: Option<String>,
+	pub last_date: Option<String>,
+
+	/* Number of rated tracks, and their average rating */
+	pub rated: usize,
+	pub average_rating: Option<f64>,
+
+	/* Totals per type of track (keyed by the type's shortname, e.g. "VL") */
+	pub by_type: BTreeMap<String, TypeStats>,
+
+	/* Totals per session date (undated tracks aren't included) */
+	#[serde(default)]
+	pub by_date: BTreeMap<String, TypeStats>,
+
+	/* Totals per file extension (e.g. "mp3") */
+	#[serde(default)]
+	pub by_extension: BTreeMap<String, TypeStats>,
+
+	/* Ratings per session date (only for dates with rated tracks) */
+	pub ratings_by_date: BTreeMap<String, RatingStats>,
+
+	/* Totals per day/week/month, when grouping (see Period - undated tracks aren't included) */
+	#[serde(default)]
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub by_period: BTreeMap<String, TypeStats>,
+}
+
+impl StatsSummary {
+	/* Compute the totals for the given tracks */
+	pub fn from_tracks<'a, I>(tracks: I) -> StatsSummary
+		where I: Iterator<Item = &'a Track>
+	{
+		let tracks: Vec<&Track> = tracks.collect();
+		let mut summary = StatsSummary::default();
+		let mut dates: Vec<SessionDate> = Vec::new();
+
+		let mut ratings = RatingTally::default();
+		let mut ratings_by_type: BTreeMap<String, RatingTally> = BTreeMap::new();
+		let mut ratings_by_date: BTreeMap<String, RatingTally> = BTreeMap::new();
+
+		for &track in tracks.iter() {
+			let type_stats = summary.by_type.entry(track.info().track_type.shortname()).or_default();
+
+			summary.tracks += 1;
+			type_stats.tracks += 1;
+			match track.duration {
+				Some(ref d) => {
+					summary.duration_ms += d.0;
+					type_stats.duration_ms += d.0;
+				},
+				None => {
+					summary.uncounted += 1;
+					type_stats.uncounted += 1;
+				}
+			}
+
+			let date = track.date.session_date();
+			if let Some(rating) = track.rating {
+				ratings.add(rating);
+				ratings_by_type.entry(track.info().track_type.shortname()).or_default().add(rating);
+				if let Some(ref date) = date {
+					ratings_by_date.entry(date.to_string()).or_default().add(rating);
+				}
+			}
+
+			dates.extend(date);
+		}
+
+		summary.rated = ratings.rated;
+		summary.average_rating = ratings.average();
+		for (name, tally) in ratings_by_type.iter() {
+			if let Some(type_stats) = summary.by_type.get_mut(name) {
+				type_stats.rated = tally.rated;
+				type_stats.average_rating = tally.average();
+			}
+		}
+		summary.ratings_by_date = ratings_by_date.iter()
+			.filter_map(|(date, tally)| {
+				tally.average().map(|average| (date.clone(), RatingStats { rated: tally.rated, average_rating: average }))
+			})
+			.collect();
+
+		summary.first_date = dates.iter().min().map(|d| d.to_string());
+		summary.last_date = dates.iter().max().map(|d| d.to_string());
+
+		summary.by_date = group_stats(&tracks, |t| t.date.session_date().map(|d| d.to_string()));
+		summary.by_extension = group_stats(&tracks, |t| Some(t.info().extn.to_string()));
+		summary
+	}
+
+	/* Number of tracks that aren't included in the totals per session date */
+	pub fn undated(&self) -> usize
+	{
+		self.tracks - self.by_date.values().map(|d| d.tracks).sum::<usize>()
+	}
+
+	/* Fill in the totals per period, for the same tracks as the summary was computed from */
+	pub fn group_by<'a, I>(&mut self, tracks: I, period: Period)
+		where I: Iterator<Item = &'a Track>
+	{
+		let tracks: Vec<&Track> = tracks.collect();
+		self.by_period = group_stats(&tracks, |t| t.date.session_date().map(|d| period.label(&d)));
+	}
+}
+
+/* Names of the types in the given summaries (in the standard type order) */
+pub fn type_names(summaries: &[&StatsSummary]) -> Vec<String>
+{
+	TYPE_ORDER.iter()
+	          .map(|t| t.shortname())
+	          .filter(|name| summaries.iter().any(|s| s.by_type.contains_key(name)))
+	          .collect()
+}
+
+/* *************************************************** */
+/* Periods */
+
+/* Length of time that tracks get grouped by (i.e. "--by") */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Period {
+	/* Session dates, e.g. "2017-08-02" */
+	Day,
+	/* Weeks, e.g. "2017-W31" (ISO-8601 weeks, unless another week start was requested) */
+	Week(WeekScheme),
+	/* Calendar months, e.g. "2017-08" */
+	Month,
+}
+
+impl Period {
+	/* Parse the "--by" value, along with the "--week-start" (if given) */
+	pub fn parse(by: &str, week_start: Option<&str>) -> Result<Period, String>
+	{
+		let scheme = match week_start {
+			Some(start) => {
+				match WeekStart::parse(start) {
+					Some(start) => WeekScheme::Simple(start),
+					None        => return Err(format!("Invalid week start '{}' - expected monday or sunday", start))
+				}
+			},
+			None => WeekScheme::Iso
+		};
+
+		let period = match by.to_lowercase().as_str() {
+			"day" | "date" => Period::Day,
+			"week"         => Period::Week(scheme),
+			"month"        => Period::Month,
+			_              => return Err(format!("Invalid grouping '{}' - expected day, week, or month", by))
+		};
+		if week_start.is_some() && period != Period::Week(scheme) {
+			return Err(format!("--week-start only applies when grouping by week (not '{}')", by));
+		}
+		Ok(period)
+	}
+
+	/* Label for the period that the date falls in (these sort in date order) */
+	pub fn label(&self, date: &SessionDate) -> String
+	{
+		match *self {
+			Period::Day          => date.to_string(),
+			Period::Week(scheme) => date.week(scheme).to_string(),
+			Period::Month        => format!("{0:04}-{1:02}", date.year, date.month),
+		}
+	}
+
+	/* Heading for the labels (when listing them) */
+	pub fn heading(&self) -> &'static str
+	{
+		match *self {
+			Period::Day     => "Date",
+			Period::Week(_) => "Week",
+			Period::Month   => "Month",
+		}
+	}
+}
+
+/* *************************************************** */
+/* Date Windows */
+
+/* A range of session dates (inclusive at both ends), e.g. "2017-01-01..2017-06-30" */
+#[derive(Debug)]
+pub struct DateWindow {
+	pub start: SessionDate,
+	pub end: SessionDate,
+
+	/* Filter selecting the tracks in the window */
+	filter: FilterExpr,
+}
+
+impl DateWindow {
+	/* Parse a window given as "<start>..<end>" (dates as YYYY-MM-DD or YYYYMMDD) */
+	pub fn parse(s: &str) -> Result<DateWindow, String>
+	{
+		let (start, end) = match s.find("..") {
+			Some(pos) => (&s[.. pos], &s[pos + 2 ..]),
+			None      => return Err(format!("Invalid date range '{}' - expected <start>..<end>, e.g. 2017-01-01..2017-06-30", s))
+		};
+
+		let parse_date = |d: &str| -> Result<SessionDate, String> {
+			SessionDate::parse(d.trim()).ok_or_else(|| format!("Invalid date range '{0}' - '{1}' isn't a valid date", s, d))
+		};
+		let start = parse_date(start)?;
+		let end = parse_date(end)?;
+		if start > end {
+			return Err(format!("Invalid date range '{0}' - the start ({1}) is after the end ({2})", s, start, end));
+		}
+
+		let filter = FilterExpr::parse(&format!("date>={0} and date<={1}", start, end))
+		                        .map_err(|e| e.to_string())?;
+		Ok(DateWindow { start: start, end: end, filter: filter })
+	}
+
+	/* Is the track's session date in this window? (Tracks without a valid date never are) */
+	pub fn contains(&self, track: &Track) -> bool
+	{
+		self.filter.matches(track)
+	}
+
+	/* Description of the window, e.g. "2017-01-01..2017-06-30" */
+	pub fn label(&self) -> String
+	{
+		format!("{0}..{1}", self.start, self.end)
+	}
+}
+
+/* *************************************************** */
+/* Comparison */
+
+/* Change in the totals for one type of track */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct TypeDelta {
+	pub tracks: i64,
+	pub duration_ms: i64,
+}
+
+/* Change in the totals between two summaries ("after" minus "before") */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct StatsDelta {
+	pub tracks: i64,
+	pub duration_ms: i64,
+	pub uncounted: i64,
+
+	/* Changes per type, for all types present in either summary
+	 * (types missing from one of them count as having no tracks there)
+	 */
+	pub by_type: BTreeMap<String, TypeDelta>,
+}
+
+/* Compute the change between two summaries */
+pub fn compute_delta(before: &StatsSummary, after: &StatsSummary) -> StatsDelta
+{
+	let none = TypeStats::default();
+
+	let by_type = before.by_type.keys().chain(after.by_type.keys())
+		.map(|name| {
+			let b = before.by_type.get(name).unwrap_or(&none);
+			let a = after.by_type.get(name).unwrap_or(&none);
+			(name.clone(), TypeDelta {
+				tracks: a.tracks as i64 - b.tracks as i64,
+				duration_ms: a.duration_ms - b.duration_ms,
+			})
+		})
+		.collect();
+
+	StatsDelta {
+		tracks: after.tracks as i64 - before.tracks as i64,
+		duration_ms: after.duration_ms - before.duration_ms,
+		uncounted: after.uncounted as i64 - before.uncounted as i64,
+		by_type: by_type,
+	}
+}
+
+/* Summaries of two windows, and the change between them (for JSON output) */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct StatsComparison {
+	pub before_window: String,
+	pub before: StatsSummary,
+
+	pub after_window: String,
+	pub after: StatsSummary,
+
+	pub delta: StatsDelta,
+}
+
+/* *************************************************** */
+/* Schemas (see json_schema) */
+
+/* JSON Schema for StatsSummary, as output by "stats --json" */
+pub fn summary_schema() -> Value
+{
+	let mut schema = summary_schema_body();
+	schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+	schema["title"] = json!("stats");
+	schema
+}
+
+/* JSON Schema for StatsComparison, as output by "stats --json --compare=..." */
+pub fn comparison_schema() -> Value
+{
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "stats-compare",
+		"type": "object",
+		"properties": {
+			"before_window": { "type": "string" },
+			"before": summary_schema_body(),
+			"after_window": { "type": "string" },
+			"after": summary_schema_body(),
+			"delta": {
+				"type": "object",
+				"properties": {
+					"tracks": { "type": "integer" },
+					"duration_ms": { "type": "integer" },
+					"uncounted": { "type": "integer" },
+					"by_type": {
+						"type": "object",
+						"additionalProperties": {
+							"type": "object",
+							"properties": {
+								"tracks": { "type": "integer" },
+								"duration_ms": { "type": "integer" }
+							},
+							"required": ["tracks", "duration_ms"],
+							"additionalProperties": false
+						}
+					}
+				},
+				"required": ["tracks", "duration_ms", "uncounted", "by_type"],
+				"additionalProperties": false
+			}
+		},
+		"required": ["before_window", "before", "after_window", "after", "delta"],
+		"additionalProperties": false
+	})
+}
+
+/* Schema for StatsSummary (wherever it appears) */
+fn summary_schema_body() -> Value
+{
+	json!({
+		"type": "object",
+		"properties": {
+			"tracks": { "type": "integer" },
+			"duration_ms": { "type": "integer" },
+			"uncounted": { "type": "integer" },
+			"first_date": { "type": ["string", "null"] },
+			"last_date": { "type": ["string", "null"] },
+			"rated": { "type": "integer" },
+			"average_rating": { "description": "Only counting rated tracks", "type": ["number", "null"] },
+			"by_type": {
+				"description": "Keyed by the type's shortname (e.g. VL)",
+				"type": "object",
+				"additionalProperties": type_stats_schema()
+			},
+			"by_date": {
+				"description": "Keyed by session date (YYYY-MM-DD) - undated tracks aren't included",
+				"type": "object",
+				"additionalProperties": type_stats_schema()
+			},
+			"by_extension": {
+				"description": "Keyed by file extension (e.g. mp3)",
+				"type": "object",
+				"additionalProperties": type_stats_schema()
+			},
+			"ratings_by_date": {
+				"description": "Keyed by session date (YYYY-MM-DD) - only dates with rated tracks",
+				"type": "object",
+				"additionalProperties": {
+					"type": "object",
+					"properties": {
+						"rated": { "type": "integer" },
+						"average_rating": { "type": "number" }
+					},
+					"required": ["rated", "average_rating"],
+					"additionalProperties": false
+				}
+			},
+			"by_period": {
+				"description": "Only with --by. Keyed by day (YYYY-MM-DD), week (YYYY-Www), or month (YYYY-MM)",
+				"type": "object",
+				"additionalProperties": type_stats_schema()
+			}
+		},
+		"required": ["tracks", "duration_ms", "uncounted", "first_date", "last_date", "rated", "average_rating", "by_type",
+		             "by_date", "by_extension", "ratings_by_date"],
+		"additionalProperties": false
+	})
+}
+
+/* Schema for TypeStats (used for types, dates, extensions, and periods) */
+fn type_stats_schema() -> Value
+{
+	json!({
+		"type": "object",
+		"properties": {
+			"tracks": { "type": "integer" },
+			"duration_ms": { "type": "integer" },
+			"uncounted": { "type": "integer" },
+			"rated": { "type": "integer" },
+			"average_rating": { "type": ["number", "null"] }
+		},
+		"required": ["tracks", "duration_ms", "uncounted", "rated", "average_rating"],
+		"additionalProperties": false
+	})
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json;
+	use track_duration::TrackDuration;
+
+	fn make_tracks(tracks: &[(&str, Option<i64>)]) -> Vec<Track>
+	{
+		tracks.iter()
+		      .map(|&(path, duration_ms)| {
+		          let mut track = Track::from_filepath(path).unwrap();
+		          track.duration = duration_ms.map(TrackDuration);
+		          track
+		      })
+		      .collect()
+	}
+
+	fn fixture() -> Vec<Track>
+	{
+		make_tracks(&[
+			("music/20170403/v01-tranquil.mp3",       Some(60_000)),
+			("music/20170403/20170403-01-sonata.mp3", Some(120_000)),
+			("music/20170610/v01-moose.mp3",          None),
+			("music/20170802/v01-celestial.mp3",      Some(90_000)),
+			("music/20170802/v02-dawn.mp3",           Some(30_000)),
+			("music/misc/intro.mp3",                  Some(10_000)),
+		])
+	}
+
+	#[test]
+	fn test_summary()
+	{
+		let tracks = fixture();
+		let summary = StatsSummary::from_tracks(tracks.iter());
+
+		assert_eq!((6, 310_000, 1), (summary.tracks, summary.duration_ms, summary.uncounted));
+		assert_eq!(Some("2017-04-03".to_string()), summary.first_date);
+		assert_eq!(Some("2017-08-02".to_string()), summary.last_date);
+
+		assert_eq!(TypeStats { tracks: 4, duration_ms: 180_000, uncounted: 1, rated: 0, average_rating: None }, summary.by_type["VL"]);
+		assert_eq!(1, summary.by_type["MS"].tracks);
+		assert_eq!(vec!["VL", "MS", "?"], type_names(&[&summary]));
+	}
+
+	#[test]
+	fn test_by_date_and_extension()
+	{
+		let mut tracks = fixture();
+		tracks.extend(make_tracks(&[
+			("music/20170802/v03-dusk.flac", None),
+			("music/20170610/v02-lake.FLAC", Some(45_000)),
+		]));
+		tracks[3].rating = Some(4);
+		let summary = StatsSummary::from_tracks(tracks.iter());
+
+		/* Per session date - with partial durations where some are unknown */
+		assert_eq!(vec!["2017-04-03", "2017-06-10", "2017-08-02"], summary.by_date.keys().collect::<Vec<_>>());
+		assert_eq!(TypeStats { tracks: 2, duration_ms: 180_000, uncounted: 0, rated: 0, average_rating: None }, summary.by_date["2017-04-03"]);
+		assert_eq!(TypeStats { tracks: 2, duration_ms: 45_000, uncounted: 1, rated: 0, average_rating: None }, summary.by_date["2017-06-10"]);
+		assert_eq!(TypeStats { tracks: 3, duration_ms: 120_000, uncounted: 1, rated: 1, average_rating: Some(4.0) }, summary.by_date["2017-08-02"]);
+		assert_eq!(1, summary.undated());
+
+		/* Per extension (regardless of case) */
+		assert_eq!(vec!["flac", "mp3"], summary.by_extension.keys().collect::<Vec<_>>());
+		assert_eq!((2, 45_000, 1), (summary.by_extension["flac"].tracks, summary.by_extension["flac"].duration_ms,
+		                            summary.by_extension["flac"].uncounted));
+		assert_eq!((6, 310_000, 1), (summary.by_extension["mp3"].tracks, summary.by_extension["mp3"].duration_ms,
+		                             summary.by_extension["mp3"].uncounted));
+
+		/* Nothing to group */
+		let empty = StatsSummary::from_tracks(Vec::<Track>::new().iter());
+		assert!(empty.by_date.is_empty() && empty.by_extension.is_empty());
+		assert_eq!(0, empty.undated());
+	}
+
+	#[test]
+	fn test_rating_averages()
+	{
+		let mut tracks = fixture();
+		for (track, rating) in tracks.iter_mut().zip([Some(5), Some(2), None, Some(4), Some(4), Some(3)].iter()) {
+			track.rating = *rating;
+		}
+		let summary = StatsSummary::from_tracks(tracks.iter());
+
+		/* Unrated tracks don't count towards the averages */
+		assert_eq!((5, Some(3.6)), (summary.rated, summary.average_rating));
+		assert_eq!((3, Some(4.33)), (summary.by_type["VL"].rated, summary.by_type["VL"].average_rating));
+		assert_eq!((1, Some(2.0)), (summary.by_type["MS"].rated, summary.by_type["MS"].average_rating));
+
+		/* Per date (undated tracks only count towards the totals) */
+		assert_eq!(RatingStats { rated: 2, average_rating: 3.5 }, summary.ratings_by_date["2017-04-03"]);
+		assert_eq!(RatingStats { rated: 2, average_rating: 4.0 }, summary.ratings_by_date["2017-08-02"]);
+		assert_eq!(2, summary.ratings_by_date.len());
+
+		/* No ratings at all */
+		let unrated = StatsSummary::from_tracks(fixture().iter());
+		assert_eq!((0, None), (unrated.rated, unrated.average_rating));
+		assert!(unrated.ratings_by_date.is_empty());
+	}
+
+	/* The JSON output matches its schemas */
+	#[test]
+	fn test_stats_schema()
+	{
+		use json_schema::{assert_matches_schema, OutputKind};
+
+		let tracks = fixture();
+		let summary = StatsSummary::from_tracks(tracks.iter());
+		assert_matches_schema(OutputKind::Stats, &serde_json::to_value(&summary).unwrap());
+		assert_matches_schema(OutputKind::Stats, &serde_json::to_value(StatsSummary::default()).unwrap());
+
+		let mut rated = fixture();
+		rated[0].rating = Some(5);
+		assert_matches_schema(OutputKind::Stats, &serde_json::to_value(StatsSummary::from_tracks(rated.iter())).unwrap());
+
+		let mut grouped = StatsSummary::from_tracks(tracks.iter());
+		grouped.group_by(tracks.iter(), Period::Week(WeekScheme::Iso));
+		assert_matches_schema(OutputKind::Stats, &serde_json::to_value(&grouped).unwrap());
+
+		let first_half = DateWindow::parse("2017-01-01..2017-06-30").unwrap();
+		let second_half = DateWindow::parse("2017-07-01..2017-12-31").unwrap();
+		let before = StatsSummary::from_tracks(tracks.iter().filter(|t| first_half.contains(t)));
+		let after = StatsSummary::from_tracks(tracks.iter().filter(|t| second_half.contains(t)));
+		let comparison = StatsComparison {
+			delta: compute_delta(&before, &after),
+			before_window: first_half.label(),
+			before: before,
+			after_window: second_half.label(),
+			after: after,
+		};
+		assert_matches_schema(OutputKind::StatsComparison, &serde_json::to_value(&comparison).unwrap());
+	}
+
+	#[test]
+	fn test_group_by_period()
+	{
+		let mut tracks = fixture();
+		tracks.extend(make_tracks(&[
+			("music/20151228/v01-north.mp3", Some(5_000)),
+			("music/20160101/v01-south.mp3", Some(7_000)),
+			("music/20170801/v03-dusk.mp3",  None),
+		]));
+
+		let period_labels = |period| {
+			let mut summary = StatsSummary::from_tracks(tracks.iter());
+			summary.group_by(tracks.iter(), period);
+			summary.by_period.keys().cloned().collect::<Vec<String>>()
+		};
+
+		/* ISO weeks - the start of 2016 belongs to the last week of 2015 */
+		let mut summary = StatsSummary::from_tracks(tracks.iter());
+		summary.group_by(tracks.iter(), Period::Week(WeekScheme::Iso));
+		assert_eq!(vec!["2015-W53", "2017-W14", "2017-W23", "2017-W31"], summary.by_period.keys().collect::<Vec<_>>());
+		assert_eq!((2, 12_000), (summary.by_period["2015-W53"].tracks, summary.by_period["2015-W53"].duration_ms));
+		assert_eq!((3, 120_000, 1), (summary.by_period["2017-W31"].tracks, summary.by_period["2017-W31"].duration_ms,
+		                              summary.by_period["2017-W31"].uncounted));
+
+		/* Undated tracks aren't in any period */
+		assert_eq!(8, summary.by_period.values().map(|p| p.tracks).sum::<usize>());
+
+		/* Simple weeks keep the dates in their own years */
+		assert_eq!(vec!["2015-W53", "2016-W01", "2017-W15", "2017-W24", "2017-W32"],
+		           period_labels(Period::Week(WeekScheme::Simple(WeekStart::Monday))));
+		assert_eq!(vec!["2015-W53", "2016-W01", "2017-W14", "2017-W23", "2017-W31"],
+		           period_labels(Period::Week(WeekScheme::Simple(WeekStart::Sunday))));
+
+		assert_eq!(vec!["2015-12", "2016-01", "2017-04", "2017-06", "2017-08"], period_labels(Period::Month));
+		assert_eq!(6, period_labels(Period::Day).len());
+
+		/* Not grouped - nothing to add */
+		assert!(StatsSummary::from_tracks(tracks.iter()).by_period.is_empty());
+	}
+
+	#[test]
+	fn test_parse_period()
+	{
+		assert_eq!(Ok(Period::Week(WeekScheme::Iso)), Period::parse("week", None));
+		assert_eq!(Ok(Period::Week(WeekScheme::Simple(WeekStart::Sunday))), Period::parse("Week", Some("sunday")));
+		assert_eq!(Ok(Period::Day), Period::parse("day", None));
+		assert_eq!(Ok(Period::Month), Period::parse("month", None));
+
+		assert!(Period::parse("fortnight", None).is_err());
+		assert!(Period::parse("week", Some("friday")).unwrap_err().contains("friday"));
+		assert!(Period::parse("month", Some("monday")).unwrap_err().contains("only applies"));
+	}
+
+	#[test]
+	fn test_date_window()
+	{
+		let tracks = fixture();
+		let window = DateWindow::parse("2017-01-01..20170610").unwrap();
+		assert_eq!("2017-01-01..2017-06-10", window.label());
+
+		/* Inclusive at both ends, and undated tracks are never included */
+		let included: Vec<&str> = tracks.iter().filter(|t| window.contains(t)).map(|t| t.filename.as_str()).collect();
+		assert_eq!(vec!["v01-tranquil.mp3", "20170403-01-sonata.mp3", "v01-moose.mp3"], included);
+
+		assert!(DateWindow::parse("2017-01-01").is_err());
+		assert!(DateWindow::parse("2017-01-01..later").is_err());
+		assert!(DateWindow::parse("2017-07-01..2017-06-30").unwrap_err().contains("after the end"));
+	}
+
+	#[test]
+	fn test_compute_delta()
+	{
+		let tracks = fixture();
+		let first_half = DateWindow::parse("2017-01-01..2017-06-30").unwrap();
+		let second_half = DateWindow::parse("2017-07-01..2017-12-31").unwrap();
+
+		let before = StatsSummary::from_tracks(tracks.iter().filter(|t| first_half.contains(t)));
+		let after = StatsSummary::from_tracks(tracks.iter().filter(|t| second_half.contains(t)));
+		let delta = compute_delta(&before, &after);
+
+		assert_eq!((-1, -60_000, -1), (delta.tracks, delta.duration_ms, delta.uncounted));
+		assert_eq!(TypeDelta { tracks: 0, duration_ms: 60_000 }, delta.by_type["VL"]);
+
+		/* Only in the first window - drops to nothing */
+		assert_eq!(TypeDelta { tracks: -1, duration_ms: -120_000 }, delta.by_type["MS"]);
+		assert_eq!(2, delta.by_type.len());
+
+		/* Only in the second window (reversing the comparison) */
+		let reversed = compute_delta(&after, &before);
+		assert_eq!(TypeDelta { tracks: 1, duration_ms: 120_000 }, reversed.by_type["MS"]);
+
+		/* Identical - no change */
+		let same = compute_delta(&before, &before);
+		assert_eq!((0, 0), (same.tracks, same.duration_ms));
+		assert!(same.by_type.values().all(|d| *d == TypeDelta { tracks: 0, duration_ms: 0 }));
+	}
+}
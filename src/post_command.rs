@@ -0,0 +1,472 @@
+/* Running a user-supplied command on each exported track
+ *
+ * With "--post-cmd='<template>'", copy/convert run the given command right after each track's output file
+ * has been written (e.g. to import it into a library, or sync it elsewhere), instead of leaving that until
+ * the whole run is done. Details of the track get substituted into the template's placeholders.
+ *
+ * The template gets split into arguments first, and the placeholders substituted within each one, so the
+ * values always end up as single arguments (even with spaces or quotes in them). Only "--post-cmd-shell"
+ * runs it through the shell instead (with the values quoted for it).
+ */
+use std::process::Command;
+
+use diagnostics::Diagnostics;
+use split_types;
+use terminal_text::sanitize;
+use xspf_parser::Track;
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "post-cmd";
+
+/* *************************************************** */
+/* Placeholders */
+
+/* Details of a track that can be substituted into the command */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Placeholder {
+	/* Path of the source file */
+	Src,
+	/* Path of the output file */
+	Dst,
+	/* Name part of the track's filename */
+	Name,
+	/* Session date (YYYY-MM-DD), or the date folder's name if it isn't a date */
+	Date,
+	/* Position of the track in the run (1-based) */
+	Index,
+	/* Type of the track (VL, MS, P, V, misc) */
+	Type,
+}
+
+/* Names of all the placeholders, as written in templates (without the braces) */
+const PLACEHOLDERS: [(&'static str, Placeholder); 6] = [
+	("src",   Placeholder::Src),
+	("dst",   Placeholder::Dst),
+	("name",  Placeholder::Name),
+	("date",  Placeholder::Date),
+	("index", Placeholder::Index),
+	("type",  Placeholder::Type),
+];
+
+/* Values for the placeholders, for one track */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct TrackVars {
+	pub src: String,
+	pub dst: String,
+	pub name: String,
+	pub date: String,
+	pub index: usize,
+	pub track_type: String,
+}
+
+impl TrackVars {
+	/* Values for a track that has just been written to dst_path
+	 * - track_idx: Position of the track in the run (0-based)
+	 */
+	pub fn for_track(track: &Track, track_idx: usize, dst_path: &str) -> TrackVars
+	{
+		TrackVars {
+			src: track.file_path().to_string_lossy().into_owned(),
+			dst: dst_path.to_string(),
+			name: track.info().name.clone(),
+			date: match track.date.session_date() {
+				Some(session) => session.to_string(),
+				None          => track.date.raw().to_string()
+			},
+			index: track_idx + 1,
+			track_type: split_types::type_tag(&track.info().track_type),
+		}
+	}
+
+	/* Value to substitute for a placeholder */
+	pub fn value(&self, placeholder: Placeholder) -> String
+	{
+		match placeholder {
+			Placeholder::Src   => self.src.clone(),
+			Placeholder::Dst   => self.dst.clone(),
+			Placeholder::Name  => self.name.clone(),
+			Placeholder::Date  => self.date.clone(),
+			Placeholder::Index => self.index.to_string(),
+			Placeholder::Type  => self.track_type.clone(),
+		}
+	}
+}
+
+/* *************************************************** */
+/* Templates */
+
+/* Piece of a template argument */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+enum Part {
+	Text(String),
+	Value(Placeholder),
+}
+
+/* Split a template into arguments, at (unquoted) whitespace
+ * Single quotes keep everything inside as-is, double quotes allow \" and \\ inside, and elsewhere a backslash
+ * keeps the next character as-is (e.g. "\ " for a space that doesn't split)
+ */
+fn split_args(template: &str) -> Result<Vec<String>, String>
+{
+	let mut args = Vec::new();
+	let mut current: Option<String> = None;
+	let mut chars = template.chars();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'\'' => {
+				let arg = current.get_or_insert_with(String::new);
+				loop {
+					match chars.next() {
+						Some('\'') => break,
+						Some(c)    => arg.push(c),
+						None       => return Err(format!("Unterminated ' quote in command - {}", template))
+					}
+				}
+			},
+			'"' => {
+				let arg = current.get_or_insert_with(String::new);
+				loop {
+					match chars.next() {
+						Some('"')  => break,
+						Some('\\') => {
+							match chars.next() {
+								Some(c) if c == '"' || c == '\\' => arg.push(c),
+								Some(c) => { arg.push('\\'); arg.push(c); },
+								None    => return Err(format!("Unterminated \" quote in command - {}", template))
+							}
+						},
+						Some(c) => arg.push(c),
+						None    => return Err(format!("Unterminated \" quote in command - {}", template))
+					}
+				}
+			},
+			'\\' => {
+				match chars.next() {
+					Some(c) => current.get_or_insert_with(String::new).push(c),
+					None    => return Err(format!("Command ends with a lone '\\' - {}", template))
+				}
+			},
+			c if c.is_whitespace() => {
+				if let Some(arg) = current.take() {
+					args.push(arg);
+				}
+			},
+			c => current.get_or_insert_with(String::new).push(c),
+		}
+	}
+	if let Some(arg) = current {
+		args.push(arg);
+	}
+	Ok(args)
+}
+
+/* Split an argument into text and placeholders ("{{" and "}}" give literal braces) */
+fn parse_parts(arg: &str) -> Result<Vec<Part>, String>
+{
+	let mut parts = Vec::new();
+	let mut text = String::new();
+	let mut rest = arg;
+
+	while let Some(c) = rest.chars().next() {
+		if rest.starts_with("{{") || rest.starts_with("}}") {
+			text.push(c);
+			rest = &rest[2 ..];
+		}
+		else if c == '{' {
+			let end = match rest.find('}') {
+				Some(end) => end,
+				None      => return Err(format!("Unclosed placeholder in command argument '{}'", arg))
+			};
+			let name = &rest[1 .. end];
+			match PLACEHOLDERS.iter().find(|&&(n, _)| n == name) {
+				Some(&(_, placeholder)) => {
+					if !text.is_empty() {
+						parts.push(Part::Text(text.clone()));
+						text.clear();
+					}
+					parts.push(Part::Value(placeholder));
+				},
+				None => {
+					let names: Vec<String> = PLACEHOLDERS.iter().map(|&(n, _)| format!("{{{}}}", n)).collect();
+					return Err(format!("Unknown placeholder '{{{0}}}' in command (expected one of: {1})", name, names.join(", ")));
+				}
+			}
+			rest = &rest[end + 1 ..];
+		}
+		else if c == '}' {
+			return Err(format!("Unmatched '}}' in command argument '{}' (use '}}}}' for a literal one)", arg));
+		}
+		else {
+			text.push(c);
+			rest = &rest[c.len_utf8() ..];
+		}
+	}
+	if !text.is_empty() {
+		parts.push(Part::Text(text));
+	}
+	Ok(parts)
+}
+
+/* Quote a value so the shell takes it as-is (as a single word) */
+#[cfg(not(windows))]
+fn shell_quote(value: &str) -> String
+{
+	format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(value: &str) -> String
+{
+	format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/* Program and initial arguments for running a command line through the shell */
+#[cfg(not(windows))]
+const SHELL: [&'static str; 2] = ["sh", "-c"];
+
+#[cfg(windows)]
+const SHELL: [&'static str; 2] = ["cmd", "/C"];
+
+/* Command to run for each track, with placeholders for its details */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct PostCommand {
+	/* Template as given */
+	pub template: String,
+	/* Run through the shell (instead of running the program directly) */
+	pub shell: bool,
+
+	/* Arguments (including the program) - or with shell, just the whole command line */
+	args: Vec<Vec<Part>>,
+}
+
+impl PostCommand {
+	/* Parse a command template (e.g. "beet import -q {dst}") */
+	pub fn parse(template: &str, shell: bool) -> Result<PostCommand, String>
+	{
+		let args = if shell {
+			vec![parse_parts(template)?]
+		}
+		else {
+			split_args(template)?.iter().map(|arg| parse_parts(arg)).collect::<Result<Vec<_>, _>>()?
+		};
+		if args.is_empty() || args[0].is_empty() {
+			return Err("No command given for --post-cmd".to_string());
+		}
+
+		Ok(PostCommand {
+			template: template.to_string(),
+			shell: shell,
+			args: args,
+		})
+	}
+
+	/* Arguments to run for a track (starting with the program) */
+	pub fn argv(&self, vars: &TrackVars) -> Vec<String>
+	{
+		let substitute = |parts: &Vec<Part>, quote: bool| -> String {
+			parts.iter().map(|part| match *part {
+				Part::Text(ref text)         => text.clone(),
+				Part::Value(placeholder) if quote => shell_quote(&vars.value(placeholder)),
+				Part::Value(placeholder)     => vars.value(placeholder),
+			}).collect()
+		};
+
+		if self.shell {
+			let mut argv: Vec<String> = SHELL.iter().map(|s| s.to_string()).collect();
+			argv.push(substitute(&self.args[0], true));
+			argv
+		}
+		else {
+			self.args.iter().map(|arg| substitute(arg, false)).collect()
+		}
+	}
+}
+
+/* *************************************************** */
+/* Runner */
+
+/* Runs the command for the files exported during a run, keeping track of how it went */
+pub struct PostCommandRunner {
+	pub command: PostCommand,
+	/* Stop the run when the command fails (instead of just reporting it) */
+	pub fatal: bool,
+
+	/* Number of tracks the command succeeded for */
+	pub succeeded: usize,
+
+	/* Tracks the command failed for */
+	pub diagnostics: Diagnostics,
+}
+
+impl PostCommandRunner {
+	pub fn new(command: PostCommand, fatal: bool) -> PostCommandRunner
+	{
+		PostCommandRunner {
+			command: command,
+			fatal: fatal,
+			succeeded: 0,
+			diagnostics: Diagnostics::new(),
+		}
+	}
+
+	/* Run the command for a track
+	 * > returns whether the run should carry on (i.e. false if it failed, and failures are fatal)
+	 */
+	pub fn run(&mut self, track_idx: usize, vars: &TrackVars) -> bool
+	{
+		let argv = self.command.argv(vars);
+		let result = Command::new(&argv[0]).args(&argv[1 ..]).output();
+
+		let failure = match result {
+			Ok(ref output) => {
+				/* Show what it printed, under the track it was for */
+				let stdout = String::from_utf8_lossy(&output.stdout);
+				let stderr = String::from_utf8_lossy(&output.stderr);
+				for line in stdout.lines().chain(stderr.lines()).filter(|l| !l.trim().is_empty()) {
+					println!("   | {}", sanitize(line));
+				}
+
+				if output.status.success() {
+					None
+				}
+				else {
+					let status = match output.status.code() {
+						Some(code) => format!("exit status {}", code),
+						None       => "killed by a signal".to_string()
+					};
+					match stderr.trim().lines().last().or_else(|| stdout.trim().lines().last()) {
+						Some(last_line) => Some(format!("'{0}' failed ({1}) - {2}", argv[0], status, last_line)),
+						None            => Some(format!("'{0}' failed ({1})", argv[0], status))
+					}
+				}
+			},
+			Err(e) => Some(format!("Couldn't run '{0}' - {1}", argv[0], e))
+		};
+
+		match failure {
+			None => {
+				println!("   Post-command done => {}", argv[0]);
+				self.succeeded += 1;
+				true
+			},
+			Some(message) => {
+				self.diagnostics.warn_track(CATEGORY, track_idx, message);
+				!self.fatal
+			}
+		}
+	}
+
+	/* Was the run stopped by a failure? */
+	pub fn aborted(&self) -> bool
+	{
+		self.fatal && !self.diagnostics.is_empty()
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fixture_vars() -> TrackVars
+	{
+		TrackVars {
+			src: "/music/20170403/v01-tranquil.mp3".to_string(),
+			dst: "/out dir/Track_01-20170403-VL01_tranquil.mp3".to_string(),
+			name: "tranquil".to_string(),
+			date: "2017-04-03".to_string(),
+			index: 1,
+			track_type: "VL".to_string(),
+		}
+	}
+
+	#[test]
+	fn test_split_args()
+	{
+		assert_eq!(vec!["mytool", "{dst}", "{name}"], split_args("  mytool {dst}\t{name} ").unwrap());
+		assert_eq!(vec!["a b", "it's", "x\\y", "c\"d", "e f"],
+		           split_args("'a b' \"it's\" \"x\\y\" \"c\\\"d\" e\\ f").unwrap());
+		assert_eq!(vec!["--to=/some place/{name}"], split_args("--to='/some place/'{name}").unwrap());
+
+		assert!(split_args("mytool 'oops").is_err());
+		assert!(split_args("mytool \"oops").is_err());
+	}
+
+	#[test]
+	fn test_substitution()
+	{
+		let vars = fixture_vars();
+
+		/* Paths with spaces stay as single arguments */
+		let command = PostCommand::parse("mytool {dst} {name} {date}", false).unwrap();
+		assert_eq!(vec!["mytool", "/out dir/Track_01-20170403-VL01_tranquil.mp3", "tranquil", "2017-04-03"],
+		           command.argv(&vars));
+
+		/* Placeholders next to text (and each other) */
+		let command = PostCommand::parse("tag --title={name}-{index}{type}.done {{raw}}", false).unwrap();
+		assert_eq!(vec!["tag", "--title=tranquil-1VL.done", "{raw}"], command.argv(&vars));
+
+		/* Values don't get split again */
+		let mut spaced = fixture_vars();
+		spaced.name = "two words".to_string();
+		assert_eq!(vec!["echo", "two words"], PostCommand::parse("echo {name}", false).unwrap().argv(&spaced));
+	}
+
+	#[test]
+	fn test_parse_errors()
+	{
+		let e = PostCommand::parse("mytool {dest}", false).unwrap_err();
+		assert!(e.contains("Unknown placeholder '{dest}'"), "{}", e);
+		assert!(e.contains("{dst}"), "{}", e);
+
+		assert!(PostCommand::parse("mytool {dst", false).is_err());
+		assert!(PostCommand::parse("mytool dst}", false).is_err());
+		assert!(PostCommand::parse("   ", false).is_err());
+		assert!(PostCommand::parse("{bogus}", true).is_err());
+	}
+
+	#[cfg(not(windows))]
+	#[test]
+	fn test_shell_quoting()
+	{
+		let mut vars = fixture_vars();
+		vars.name = "it's; rm -rf /".to_string();
+		let command = PostCommand::parse("echo {name} | wc -c", true).unwrap();
+		assert_eq!(vec!["sh", "-c", "echo 'it'\\''s; rm -rf /' | wc -c"], command.argv(&vars));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_runner()
+	{
+		let vars = fixture_vars();
+
+		let mut runner = PostCommandRunner::new(PostCommand::parse("test -n {dst}", false).unwrap(), false);
+		assert!(runner.run(0, &vars));
+		assert_eq!(1, runner.succeeded);
+
+		/* Failures get recorded, and only stop the run when fatal */
+		let mut runner = PostCommandRunner::new(PostCommand::parse("sh -c 'echo bad >&2; exit 3'", false).unwrap(), false);
+		assert!(runner.run(2, &vars));
+		assert_eq!(Some(2), runner.diagnostics.entries[0].track_index);
+		assert!(runner.diagnostics.entries[0].message.contains("exit status 3) - bad"));
+		assert!(!runner.aborted());
+
+		runner.fatal = true;
+		assert!(!runner.run(3, &vars));
+		assert!(runner.aborted());
+
+		let mut runner = PostCommandRunner::new(PostCommand::parse("/nonexistent/tool {dst}", false).unwrap(), false);
+		assert!(runner.run(0, &vars));
+		assert!(runner.diagnostics.entries[0].message.starts_with("Couldn't run"));
+	}
+}
@@ -0,0 +1,307 @@
+/* Generating practice playlists, favouring pieces that haven't been played in a while (or much)
+ *
+ * The takes of each piece (i.e. tracks with the same name, across all the sessions) get grouped
+ * together, and each piece gets a score from how long it's been since it was last played and how
+ * few takes there are of it. Pieces are then picked at random, with probabilities in proportion to
+ * their scores, and the most recent take of each gets used. The random choices are driven by a seed,
+ * so the same seed always gives the same playlist.
+ */
+use std::collections::HashMap;
+
+use name_folding::fold_name;
+use track_date::SessionDate;
+use track_sampling::SampleRng;
+use xspf_parser::Track;
+
+/* Number of pieces picked when neither a count nor a duration is given */
+pub const DEFAULT_PRACTICE_COUNT: usize = 10;
+
+/* Score that every piece gets regardless, so that recently/often played pieces still get a (small) chance */
+const BASE_SCORE: f64 = 0.1;
+
+/* Days since the last take at which a piece counts as "half forgotten" (recency factor of 0.5) */
+const RECENCY_HALF_DAYS: f64 = 30.0;
+
+/* Recency factor for pieces without any dated takes - as if they were last played RECENCY_HALF_DAYS ago */
+const NEUTRAL_RECENCY: f64 = 0.5;
+
+/* *************************************************** */
+/* Pieces */
+
+/* All the takes of a piece */
+#[derive(Debug)]
+pub struct Piece {
+	/* Name of the piece (as for the most recent take) */
+	pub name: String,
+	/* Index of the most recent take (in the playlist) */
+	pub latest: usize,
+	/* Number of takes of the piece */
+	pub takes: usize,
+	/* Session date of the most recent take (None if none of the takes have a valid date) */
+	pub last_date: Option<SessionDate>,
+}
+
+/* Group the tracks into pieces, by their (folded) names
+ * > returns the pieces in order of first appearance
+ *
+ * NOTE: Where takes can't be told apart by their dates, the later one in the playlist counts as the most recent
+ */
+pub fn group_pieces(tracks: &[Track]) -> Vec<Piece>
+{
+	let mut pieces: Vec<Piece> = Vec::new();
+	let mut lookup: HashMap<String, usize> = HashMap::new();
+
+	for (i, track) in tracks.iter().enumerate() {
+		let date = track.date.session_date();
+		let key = fold_name(&track.info().name);
+
+		match lookup.get(&key) {
+			Some(&p) => {
+				let piece = &mut pieces[p];
+				piece.takes += 1;
+				if date >= piece.last_date {
+					piece.name = track.info().name.clone();
+					piece.latest = i;
+					piece.last_date = date;
+				}
+			},
+			None => {
+				lookup.insert(key, pieces.len());
+				pieces.push(Piece {
+					name: track.info().name.clone(),
+					latest: i,
+					takes: 1,
+					last_date: date,
+				});
+			}
+		}
+	}
+	pieces
+}
+
+/* *************************************************** */
+/* Scoring */
+
+/* How much each factor counts towards a piece's score */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub struct PracticeWeights {
+	/* Weight for how long it's been since the piece was last played */
+	pub recency: f64,
+	/* Weight for how few takes there are of the piece */
+	pub rarity: f64,
+}
+
+impl Default for PracticeWeights {
+	fn default() -> PracticeWeights
+	{
+		PracticeWeights { recency: 1.0, rarity: 1.0 }
+	}
+}
+
+/* Recency factor (0 = played today, approaching 1 for pieces that haven't been played in ages) */
+pub fn recency_factor(days_since: Option<i64>) -> f64
+{
+	match days_since {
+		Some(days) => {
+			let days = days.max(0) as f64;
+			days / (days + RECENCY_HALF_DAYS)
+		},
+		None => NEUTRAL_RECENCY
+	}
+}
+
+/* Rarity factor (1 for pieces with a single take, getting smaller the more takes there are) */
+pub fn rarity_factor(takes: usize) -> f64
+{
+	1.0 / takes.max(1) as f64
+}
+
+/* Score each piece, as of the given day (as days since the epoch)
+ * > returns the score for each piece (always positive)
+ */
+pub fn score_pieces(pieces: &[Piece], today_days: i64, weights: &PracticeWeights) -> Vec<f64>
+{
+	pieces.iter()
+	      .map(|piece| {
+	          let days_since = piece.last_date.as_ref().map(|d| today_days - d.days_since_epoch());
+	          BASE_SCORE
+	              + weights.recency * recency_factor(days_since)
+	              + weights.rarity * rarity_factor(piece.takes)
+	      })
+	      .collect()
+}
+
+/* *************************************************** */
+/* Selection */
+
+/* How long the practice playlist should be (stopping at whichever limit is reached first) */
+#[derive(Debug)]
+pub struct PracticeLimit {
+	/* Maximum number of pieces */
+	pub count: Option<usize>,
+	/* Maximum total duration (in ms) */
+	pub max_duration_ms: Option<i64>,
+}
+
+/* Pick pieces at random, with probabilities in proportion to their scores
+ * - scores: Score of each piece
+ * - durations: Duration of each piece (i.e. of the take that will be used)
+ *
+ * When there's a maximum duration, only pieces that still fit in the remaining time get considered,
+ * so shorter pieces may get picked after a longer one no longer fits. Pieces with unknown durations
+ * are never picked then (as there's no way of telling whether they fit).
+ *
+ * > returns the indices of the pieces picked (in the order they were picked)
+ */
+pub fn select_pieces(scores: &[f64], durations: &[Option<i64>], limit: &PracticeLimit, seed: u64) -> Vec<usize>
+{
+	let mut rng = SampleRng::new(seed);
+	let mut candidates: Vec<usize> = (0 .. scores.len()).collect();
+	let mut picked: Vec<usize> = Vec::new();
+	let mut total_ms: i64 = 0;
+
+	while limit.count.is_none_or(|count| picked.len() < count) {
+		if let Some(max_duration_ms) = limit.max_duration_ms {
+			candidates.retain(|&i| durations[i].is_some_and(|d| total_ms + d <= max_duration_ms));
+		}
+		if candidates.is_empty() {
+			break;
+		}
+
+		/* Weighted pick - walk along until the cumulative score passes the random point */
+		let total_score: f64 = candidates.iter().map(|&i| scores[i]).sum();
+		let mut point = rng.next_f64() * total_score;
+		let mut chosen = candidates.len() - 1;
+		for (c, &i) in candidates.iter().enumerate() {
+			if point < scores[i] {
+				chosen = c;
+				break;
+			}
+			point -= scores[i];
+		}
+
+		let piece = candidates.remove(chosen);
+		total_ms += durations[piece].unwrap_or(0);
+		picked.push(piece);
+	}
+	picked
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn make_tracks(paths: &[&str]) -> Vec<Track>
+	{
+		paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect()
+	}
+
+	#[test]
+	fn test_group_pieces()
+	{
+		let tracks = make_tracks(&["music/20170403/v01-tranquil.mp3",
+		                           "music/20170610/v01-Moose.mp3",
+		                           "music/20170802/v02-tranquil.mp3",
+		                           "music/20170404/v01-moose.mp3",
+		                           "music/misc/intro.mp3"]);
+		let pieces = group_pieces(&tracks);
+		assert_eq!(3, pieces.len());
+
+		/* Most recent take of each (regardless of its position, or the case of its name) */
+		assert_eq!(("tranquil", 2, 2), (pieces[0].name.as_str(), pieces[0].latest, pieces[0].takes));
+		assert_eq!(("Moose", 1, 2), (pieces[1].name.as_str(), pieces[1].latest, pieces[1].takes));
+		assert_eq!(SessionDate::new(2017, 8, 2), pieces[0].last_date);
+
+		/* Undated */
+		assert_eq!((4, 1, None), (pieces[2].latest, pieces[2].takes, pieces[2].last_date));
+	}
+
+	#[test]
+	fn test_score_pieces()
+	{
+		let tracks = make_tracks(&["music/20170403/v01-tranquil.mp3",
+		                           "music/20170404/v01-tranquil.mp3",
+		                           "music/20170403/v02-moose.mp3",
+		                           "music/20170502/v01-dawn.mp3",
+		                           "music/misc/intro.mp3"]);
+		let pieces = group_pieces(&tracks);
+		let today = SessionDate::new(2017, 5, 3).unwrap().days_since_epoch();
+		let weights = PracticeWeights::default();
+
+		/* Longer since the last take = higher score, and fewer takes = higher score */
+		let recency_only = score_pieces(&pieces, today, &PracticeWeights { recency: 1.0, rarity: 0.0 });
+		assert!(recency_only[1] > recency_only[0]);
+		assert!(recency_only[0] > recency_only[2]);
+
+		let scores = score_pieces(&pieces, today, &weights);
+		assert!(scores[1] > scores[0]);
+
+		/* Undated - neutral recency (same as being last played RECENCY_HALF_DAYS ago) */
+		assert_eq!(NEUTRAL_RECENCY, recency_factor(Some(30)));
+		assert_eq!(BASE_SCORE + NEUTRAL_RECENCY + 1.0, scores[3]);
+
+		/* No weights - everything equally likely */
+		let flat = score_pieces(&pieces, today, &PracticeWeights { recency: 0.0, rarity: 0.0 });
+		assert!(flat.iter().all(|&s| s == BASE_SCORE));
+	}
+
+	#[test]
+	fn test_select_pieces_count()
+	{
+		let scores = [1.0, 0.2, 3.0, 0.5, 1.5];
+		let durations = [None; 5];
+		let limit = PracticeLimit { count: Some(3), max_duration_ms: None };
+
+		let picked = select_pieces(&scores, &durations, &limit, 7);
+		assert_eq!(3, picked.len());
+		let mut unique = picked.clone();
+		unique.sort();
+		unique.dedup();
+		assert_eq!(3, unique.len());
+
+		/* Same seed = same result, while other seeds give other results */
+		assert_eq!(picked, select_pieces(&scores, &durations, &limit, 7));
+		assert!((0 .. 20).any(|seed| select_pieces(&scores, &durations, &limit, seed) != picked));
+
+		/* Higher scores tend to get picked first */
+		let one = PracticeLimit { count: Some(1), max_duration_ms: None };
+		let firsts: Vec<usize> = (0 .. 200).map(|seed| select_pieces(&scores, &durations, &one, seed)[0]).collect();
+		let count_of = |i: usize| firsts.iter().filter(|&&f| f == i).count();
+		assert!(count_of(2) > count_of(0));
+		assert!(count_of(0) > count_of(1));
+
+		/* Asking for more than there are takes everything */
+		let all = PracticeLimit { count: Some(10), max_duration_ms: None };
+		assert_eq!(5, select_pieces(&scores, &durations, &all, 0).len());
+	}
+
+	#[test]
+	fn test_select_pieces_duration()
+	{
+		let scores = [1.0, 1.0, 1.0, 1.0, 5.0];
+		let durations = [Some(10 * 60_000), Some(20 * 60_000), Some(5 * 60_000), Some(15 * 60_000), None];
+		let limit = PracticeLimit { count: None, max_duration_ms: Some(30 * 60_000) };
+
+		for seed in 0 .. 50 {
+			let picked = select_pieces(&scores, &durations, &limit, seed);
+			let total: i64 = picked.iter().map(|&i| durations[i].unwrap()).sum();
+			assert!(total <= 30 * 60_000);
+
+			/* Unknown duration never gets picked, and there's no room left for anything else that's left */
+			assert!(!picked.contains(&4));
+			assert!((0 .. 4).filter(|i| !picked.contains(i)).all(|i| total + durations[i].unwrap() > 30 * 60_000));
+		}
+
+		/* Count and duration together - whichever is reached first */
+		let both = PracticeLimit { count: Some(1), max_duration_ms: Some(30 * 60_000) };
+		assert_eq!(1, select_pieces(&scores, &durations, &both, 3).len());
+
+		/* Nothing fits */
+		let tiny = PracticeLimit { count: None, max_duration_ms: Some(1000) };
+		assert!(select_pieces(&scores, &durations, &tiny, 3).is_empty());
+	}
+}
@@ -0,0 +1,346 @@
+/* On-disk cache of media probe results
+ *
+ * Probing files with ffprobe is slow, and the same files tend to get probed
+ * over and over again across runs. So, results get remembered in a JSON file,
+ * keyed by the canonical path of each file. Entries are only reused if the
+ * size and modification time of the file still match what was recorded.
+ */
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use serde_json;
+
+use media_probe::{ProbeInfo, Prober};
+
+/* Version of the cache file format */
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+/* *************************************************** */
+
+/* Details identifying the exact version of a file that was probed */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+struct FileStamp {
+	size: u64,
+	mtime_secs: i64,
+	mtime_nanos: u32,
+}
+
+impl FileStamp {
+	/* Get the stamp for the given file (if it exists) */
+	fn for_file(path: &Path) -> Option<FileStamp>
+	{
+		let metadata = fs::metadata(path).ok()?;
+		let (mtime_secs, mtime_nanos) = match metadata.modified().ok()?.duration_since(UNIX_EPOCH) {
+			Ok(d)  => (d.as_secs() as i64, d.subsec_nanos()),
+			Err(e) => (-(e.duration().as_secs() as i64), e.duration().subsec_nanos()),
+		};
+
+		Some(FileStamp {
+			size: metadata.len(),
+			mtime_secs: mtime_secs,
+			mtime_nanos: mtime_nanos,
+		})
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+struct CacheEntry {
+	stamp: FileStamp,
+	info: ProbeInfo,
+}
+
+/* Layout of the cache file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct CacheFile {
+	version: u32,
+	entries: BTreeMap<String, CacheEntry>,
+}
+
+/* *************************************************** */
+
+/* Cache of probe results
+ * NOTE: Entries are protected by a mutex, so that the cache can be shared between threads
+ */
+pub struct ProbeCache {
+	/* Where the cache gets saved to */
+	path: PathBuf,
+	/* Canonical Path -> Entry */
+	entries: Mutex<BTreeMap<String, CacheEntry>>,
+	/* Have there been any changes that need saving? */
+	dirty: Mutex<bool>,
+}
+
+impl ProbeCache {
+	/* Get the default location of the cache file for the given playlist
+	 * (i.e. "<playlist_dir>/<playlist_name>.probe-cache.json")
+	 */
+	pub fn default_path_for(playlist_filename: &str) -> PathBuf
+	{
+		let playlist_path = Path::new(playlist_filename);
+		let stem = match playlist_path.file_stem() {
+			Some(s) => s.to_string_lossy().into_owned(),
+			None    => "playlist".to_string()
+		};
+		playlist_path.with_file_name(format!("{}.probe-cache.json", stem))
+	}
+
+	/* Load the cache from the given path
+	 * If the file doesn't exist, an empty cache is created. If it's corrupt,
+	 * a warning is printed and it gets discarded (replaced with an empty cache)
+	 */
+	pub fn load(path: &Path) -> ProbeCache
+	{
+		let mut entries = BTreeMap::new();
+
+		if path.exists() {
+			let parsed = fs::read_to_string(path)
+			               .map_err(|e| e.to_string())
+			               .and_then(|s| serde_json::from_str::<CacheFile>(&s).map_err(|e| e.to_string()));
+			match parsed {
+				Ok(ref cache_file) if cache_file.version != CACHE_FORMAT_VERSION => {
+					eprintln!("WARNING: Discarding probe cache {0:?} - Unsupported version ({1})",
+					          path, cache_file.version);
+				},
+				Ok(cache_file) => {
+					entries = cache_file.entries;
+				},
+				Err(e) => {
+					eprintln!("WARNING: Discarding corrupt probe cache {0:?} - {1}", path, e);
+				}
+			}
+		}
+
+		ProbeCache {
+			path: path.to_path_buf(),
+			entries: Mutex::new(entries),
+			dirty: Mutex::new(false),
+		}
+	}
+
+	/* Key used for looking up the file */
+	fn key_for(path: &Path) -> String
+	{
+		match path.canonicalize() {
+			Ok(p)  => p.to_string_lossy().into_owned(),
+			Err(_) => path.to_string_lossy().into_owned()
+		}
+	}
+
+	/* Get the details of a file, from the cache if they're still valid, or by probing it otherwise */
+	pub fn probe(&self, path: &Path, prober: &dyn Prober) -> Result<ProbeInfo, String>
+	{
+		let key = ProbeCache::key_for(path);
+		let stamp = FileStamp::for_file(path);
+
+		/* Cache hit? */
+		if let Some(ref stamp) = stamp {
+			let entries = self.entries.lock().unwrap();
+			if let Some(entry) = entries.get(&key) {
+				if entry.stamp == *stamp {
+					return Ok(entry.info.clone());
+				}
+			}
+		}
+
+		/* Cache miss - Probe file (without holding the lock, as this is slow) and store the result */
+		let info = prober.probe(path)?;
+
+		if let Some(stamp) = stamp {
+			let mut entries = self.entries.lock().unwrap();
+			entries.insert(key, CacheEntry { stamp: stamp, info: info.clone() });
+			*self.dirty.lock().unwrap() = true;
+		}
+
+		Ok(info)
+	}
+
+	/* Remove entries for files that no longer exist
+	 * > returns the number of entries removed
+	 */
+	pub fn prune(&self) -> usize
+	{
+		let mut entries = self.entries.lock().unwrap();
+		let old_len = entries.len();
+
+		entries.retain(|key, _| Path::new(key).exists());
+
+		let removed = old_len - entries.len();
+		if removed > 0 {
+			*self.dirty.lock().unwrap() = true;
+		}
+		removed
+	}
+
+	/* Number of entries in the cache */
+	pub fn len(&self) -> usize
+	{
+		self.entries.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.entries.lock().unwrap().is_empty()
+	}
+
+	/* Write the cache back to disk (if anything changed)
+	 * NOTE: This is written to a temp file first, then renamed, so that an interrupted
+	 *       write doesn't leave a corrupt cache behind
+	 */
+	pub fn save(&self) -> Result<(), String>
+	{
+		let mut dirty = self.dirty.lock().unwrap();
+		if !*dirty {
+			return Ok(());
+		}
+
+		let cache_file = CacheFile {
+			version: CACHE_FORMAT_VERSION,
+			entries: self.entries.lock().unwrap().clone(),
+		};
+		let json = serde_json::to_string_pretty(&cache_file).map_err(|e| e.to_string())?;
+
+		let temp_path = self.path.with_extension("json.tmp");
+		File::create(&temp_path)
+			.and_then(|mut f| f.write_all(json.as_bytes()))
+			.and_then(|_| fs::rename(&temp_path, &self.path))
+			.map_err(|e| format!("Couldn't save probe cache to {0:?} - {1}", self.path, e))?;
+
+		*dirty = false;
+		Ok(())
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+	use std::time::{Duration, SystemTime};
+	use test_utils::TempDir;
+
+	/* Prober that counts how many times it gets called */
+	struct CountingProber {
+		count: Cell<usize>,
+	}
+
+	impl CountingProber {
+		fn new() -> CountingProber
+		{
+			CountingProber { count: Cell::new(0) }
+		}
+	}
+
+	impl Prober for CountingProber {
+		fn probe(&self, _path: &Path) -> Result<ProbeInfo, String>
+		{
+			self.count.set(self.count.get() + 1);
+			Ok(ProbeInfo {
+				duration_ms: Some(1000 * self.count.get() as i64),
+				codec: Some("mp3".to_string()),
+				sample_rate: Some(44100),
+				channels: Some(2),
+			})
+		}
+	}
+
+	#[test]
+	fn test_cache_hit_and_miss()
+	{
+		let dir = TempDir::new("probe-cache-hit");
+		let track = dir.write_file("20170403/v01-tranquil.mp3", b"audio data");
+		let track = track.as_path();
+		let cache_path = dir.path().join("cache.json");
+
+		let prober = CountingProber::new();
+
+		/* First run - miss, then hit */
+		{
+			let cache = ProbeCache::load(&cache_path);
+			assert_eq!(Some(1000), cache.probe(track, &prober).unwrap().duration_ms);
+			assert_eq!(Some(1000), cache.probe(track, &prober).unwrap().duration_ms);
+			assert_eq!(1, prober.count.get());
+
+			cache.save().unwrap();
+		}
+
+		/* Second run - results remembered from last time */
+		{
+			let cache = ProbeCache::load(&cache_path);
+			assert_eq!(1, cache.len());
+			assert_eq!(Some(1000), cache.probe(track, &prober).unwrap().duration_ms);
+			assert_eq!(1, prober.count.get());
+		}
+	}
+
+	#[test]
+	fn test_cache_invalidated_on_mtime_change()
+	{
+		let dir = TempDir::new("probe-cache-mtime");
+		let track_path = dir.write_file("20170403/v01-tranquil.mp3", b"audio data");
+		let track = track_path.as_path();
+
+		let prober = CountingProber::new();
+		let cache = ProbeCache::load(&dir.path().join("cache.json"));
+
+		cache.probe(track, &prober).unwrap();
+		assert_eq!(1, prober.count.get());
+
+		/* Touch the file (same size, different mtime) */
+		let f = File::options().write(true).open(&track_path).unwrap();
+		f.set_modified(SystemTime::now() + Duration::from_secs(3600)).unwrap();
+
+		assert_eq!(Some(2000), cache.probe(track, &prober).unwrap().duration_ms);
+		assert_eq!(2, prober.count.get());
+
+		/* ... and now it's remembered again */
+		cache.probe(track, &prober).unwrap();
+		assert_eq!(2, prober.count.get());
+	}
+
+	#[test]
+	fn test_corrupt_cache_discarded()
+	{
+		let dir = TempDir::new("probe-cache-corrupt");
+		let cache_path = dir.write_file("cache.json", b"{ this is not json");
+
+		let cache = ProbeCache::load(&cache_path);
+		assert_eq!(0, cache.len());
+	}
+
+	#[test]
+	fn test_prune()
+	{
+		let dir = TempDir::new("probe-cache-prune");
+		let a = dir.write_file("a.mp3", b"a");
+		let b = dir.write_file("b.mp3", b"b");
+
+		let prober = CountingProber::new();
+		let cache = ProbeCache::load(&dir.path().join("cache.json"));
+		cache.probe(&a, &prober).unwrap();
+		cache.probe(&b, &prober).unwrap();
+		assert_eq!(2, cache.len());
+
+		fs::remove_file(&b).unwrap();
+		assert_eq!(1, cache.prune());
+		assert_eq!(1, cache.len());
+	}
+
+	#[test]
+	fn test_default_path()
+	{
+		assert_eq!(Path::new("/music/lists/best.probe-cache.json"),
+		           ProbeCache::default_path_for("/music/lists/best.xspf").as_path());
+	}
+}
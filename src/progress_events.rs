@@ -0,0 +1,780 @@
+/* Machine-readable progress events
+ *
+ * Long-running modes (copy, convert, bundle) can report their progress as newline-delimited
+ * JSON on a side channel (a file, or an inherited file descriptor), for use by GUI wrappers etc.
+ * stdout/stderr stay human-oriented.
+ *
+ * Events are written by a background thread, so a slow consumer never holds up the actual work.
+ * If the consumer falls too far behind, the oldest unwritten events get dropped, and the number
+ * dropped so far is included with each event that does get written.
+ *
+ * The same reporter also prefixes the lines printed for each track on stdout with how far along
+ * the run is (e.g. "[ 37/412 02:15 ETA 21:40]"), so that long runs can be followed by eye.
+ */
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use serde_json::{self, Value};
+
+use track_duration::TrackDuration;
+
+/* Version of the event format - Bump when making incompatible changes to the events */
+pub const EVENTS_FORMAT_VERSION: u32 = 1;
+
+/* Default number of events that can be waiting to be written before the oldest get dropped */
+pub const DEFAULT_BUFFER_SIZE: usize = 256;
+
+/* *************************************************** */
+/* Event Types */
+
+/* Outcome of processing a track */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackStatus {
+	/* Copied/converted/added successfully */
+	Ok,
+	/* Tried, but failed */
+	Failed,
+	/* Not processed (e.g. already up to date, or can't be included) */
+	Skipped,
+}
+
+/* Totals for a whole run */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub struct RunSummary {
+	pub total_tracks: usize,
+	pub succeeded: usize,
+	pub failed: usize,
+	pub skipped: usize,
+
+	/* Total size of the files produced */
+	pub bytes: u64,
+	/* Time taken for the whole run */
+	pub elapsed_ms: u64,
+
+	/* Number of events dropped (before this summary) as the consumer wasn't keeping up */
+	pub dropped_events: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProgressEvent {
+	RunStarted {
+		/* Name of the mode being run (e.g. "copy") */
+		mode: String,
+		total_tracks: usize,
+		/* Total size of the source files (if all of them could be checked) */
+		total_bytes: Option<u64>,
+	},
+	TrackStarted {
+		/* Index (0-based) of the track in the playlist */
+		track_index: usize,
+		src: String,
+		dst: String,
+	},
+	TrackFinished {
+		track_index: usize,
+		status: TrackStatus,
+		elapsed_ms: u64,
+		/* Size of the file produced */
+		bytes: Option<u64>,
+	},
+	RunFinished {
+		summary: RunSummary,
+	},
+}
+
+/* A line in the events output */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ProgressRecord {
+	/* Format version (i.e. EVENTS_FORMAT_VERSION) */
+	pub version: u32,
+	/* Sequence number of the event - Gaps indicate dropped events */
+	pub seq: u64,
+	/* Number of events dropped so far */
+	pub dropped: u64,
+
+	pub event: ProgressEvent,
+}
+
+/* JSON Schema for ProgressRecord (i.e. each line of the events output - see json_schema) */
+pub fn record_schema() -> Value
+{
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "events",
+		"type": "object",
+		"properties": {
+			"version": { "type": "integer" },
+			"seq": { "type": "integer" },
+			"dropped": { "type": "integer" },
+			"event": {
+				"oneOf": [
+					event_schema("run_started", json!({
+						"mode": { "type": "string" },
+						"total_tracks": { "type": "integer" },
+						"total_bytes": { "type": ["integer", "null"] }
+					})),
+					event_schema("track_started", json!({
+						"track_index": { "description": "0-based", "type": "integer" },
+						"src": { "type": "string" },
+						"dst": { "type": "string" }
+					})),
+					event_schema("track_finished", json!({
+						"track_index": { "description": "0-based", "type": "integer" },
+						"status": { "enum": ["ok", "failed", "skipped"] },
+						"elapsed_ms": { "type": "integer" },
+						"bytes": { "type": ["integer", "null"] }
+					})),
+					event_schema("run_finished", json!({
+						"summary": {
+							"type": "object",
+							"properties": {
+								"total_tracks": { "type": "integer" },
+								"succeeded": { "type": "integer" },
+								"failed": { "type": "integer" },
+								"skipped": { "type": "integer" },
+								"bytes": { "type": "integer" },
+								"elapsed_ms": { "type": "integer" },
+								"dropped_events": { "type": "integer" }
+							},
+							"required": ["total_tracks", "succeeded", "failed", "skipped", "bytes", "elapsed_ms", "dropped_events"],
+							"additionalProperties": false
+						}
+					}))
+				]
+			}
+		},
+		"required": ["version", "seq", "dropped", "event"],
+		"additionalProperties": false
+	})
+}
+
+/* Schema for one type of ProgressEvent, with the given fields (all of which are required) */
+fn event_schema(event_type: &str, fields: Value) -> Value
+{
+	let mut properties = fields;
+	let mut required = vec![json!("type")];
+	if let Value::Object(ref mut fields) = properties {
+		required.extend(fields.keys().map(|name| json!(name)));
+		fields.insert("type".to_string(), json!({ "enum": [event_type] }));
+	}
+
+	json!({
+		"type": "object",
+		"properties": properties,
+		"required": required,
+		"additionalProperties": false
+	})
+}
+
+/* *************************************************** */
+/* Event Sink */
+
+/* Events waiting to be written */
+struct EventQueue {
+	events: VecDeque<(u64, ProgressEvent)>,
+	capacity: usize,
+	dropped: u64,
+	closed: bool,
+}
+
+/* Destination that events get written to (by a background thread) */
+pub struct ProgressSink {
+	shared: Arc<(Mutex<EventQueue>, Condvar)>,
+	writer: Option<JoinHandle<()>>,
+	next_seq: u64,
+}
+
+impl ProgressSink {
+	/* Start writing events to the given output stream
+	 * - capacity: Max number of events waiting to be written, before the oldest start getting dropped
+	 */
+	pub fn new(out: Box<dyn Write + Send>, capacity: usize) -> ProgressSink
+	{
+		let queue = EventQueue {
+			events: VecDeque::new(),
+			capacity: capacity.max(1),
+			dropped: 0,
+			closed: false,
+		};
+		let shared = Arc::new((Mutex::new(queue), Condvar::new()));
+
+		let thread_shared = shared.clone();
+		let writer = thread::spawn(move || write_events(out, &thread_shared));
+
+		ProgressSink {
+			shared: shared,
+			writer: Some(writer),
+			next_seq: 0,
+		}
+	}
+
+	/* Open the sink specified by a "--progress-json=<target>" option
+	 * - target: Either "fd<N>" for an (already open) file descriptor, or a file path
+	 */
+	pub fn open(target: &str) -> Result<ProgressSink, String>
+	{
+		let out: Box<dyn Write + Send> = match target.strip_prefix("fd").and_then(|n| n.parse::<i32>().ok()) {
+			Some(fd) => Box::new(open_file_descriptor(fd)?),
+			None => {
+				let file = File::create(target)
+				               .map_err(|e| format!("Couldn't create progress events file {0:?} - {1}", target, e))?;
+				Box::new(io::BufWriter::new(file))
+			}
+		};
+		Ok(ProgressSink::new(out, DEFAULT_BUFFER_SIZE))
+	}
+
+	/* Queue an event to be written, dropping the oldest waiting event if the buffer is full */
+	pub fn emit(&mut self, event: ProgressEvent)
+	{
+		let (lock, cvar) = &*self.shared;
+		let mut queue = lock.lock().unwrap();
+
+		if queue.events.len() >= queue.capacity {
+			queue.events.pop_front();
+			queue.dropped += 1;
+		}
+		queue.events.push_back((self.next_seq, event));
+		self.next_seq += 1;
+
+		cvar.notify_one();
+	}
+
+	/* Number of events dropped so far */
+	pub fn dropped(&self) -> u64
+	{
+		self.shared.0.lock().unwrap().dropped
+	}
+
+	/* Wait for all the queued events to be written */
+	pub fn finish(mut self)
+	{
+		self.close();
+	}
+
+	fn close(&mut self)
+	{
+		if let Some(writer) = self.writer.take() {
+			{
+				let (lock, cvar) = &*self.shared;
+				lock.lock().unwrap().closed = true;
+				cvar.notify_one();
+			}
+			let _ = writer.join();
+		}
+	}
+}
+
+impl Drop for ProgressSink {
+	fn drop(&mut self)
+	{
+		self.close();
+	}
+}
+
+/* Background thread for ProgressSink - Writes events until the sink is closed and everything is written */
+fn write_events(mut out: Box<dyn Write + Send>, shared: &(Mutex<EventQueue>, Condvar))
+{
+	let (lock, cvar) = shared;
+	let mut failed = false;
+
+	loop {
+		let (seq, event, dropped) = {
+			let mut queue = lock.lock().unwrap();
+			while queue.events.is_empty() && !queue.closed {
+				queue = cvar.wait(queue).unwrap();
+			}
+
+			match queue.events.pop_front() {
+				Some((seq, event)) => (seq, event, queue.dropped),
+				None               => break  /* closed, and nothing left to write */
+			}
+		};
+
+		/* After a write error, just keep draining the queue (so the main work isn't affected) */
+		if failed {
+			continue;
+		}
+
+		let record = ProgressRecord {
+			version: EVENTS_FORMAT_VERSION,
+			seq: seq,
+			dropped: dropped,
+			event: event,
+		};
+		let result = serde_json::to_string(&record)
+		                 .map_err(io::Error::other)
+		                 .and_then(|line| writeln!(out, "{}", line))
+		                 .and_then(|_| out.flush());
+		if let Err(e) = result {
+			eprintln!("WARNING: Couldn't write progress events - {}", e);
+			failed = true;
+		}
+	}
+}
+
+/* Writer for an inherited file descriptor (e.g. "fd3", as set up by the calling process) */
+#[cfg(unix)]
+fn open_file_descriptor(fd: i32) -> Result<File, String>
+{
+	use std::os::unix::io::FromRawFd;
+
+	if fd <= 2 {
+		return Err(format!("Progress events can't be written to fd{} (use stdout/stderr for human-readable output)", fd));
+	}
+
+	/* Check that it's actually open, before taking ownership of it */
+	let file = unsafe { File::from_raw_fd(fd) };
+	match file.metadata() {
+		Ok(_)  => Ok(file),
+		Err(e) => {
+			::std::mem::forget(file);  /* Not ours to close */
+			Err(format!("Couldn't use fd{0} for progress events - {1}", fd, e))
+		}
+	}
+}
+
+#[cfg(not(unix))]
+fn open_file_descriptor(fd: i32) -> Result<File, String>
+{
+	Err(format!("Writing progress events to a file descriptor (fd{}) is only supported on Unix - use a file path instead", fd))
+}
+
+/* *************************************************** */
+/* Progress Reporter */
+
+/* How the lines printed for each track look (see ProgressReporter::track_line()) */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+pub struct TrackLineOptions {
+	/* Width of the track numbers (see XspfPlaylist::track_index_width()) */
+	pub index_width: usize,
+	/* Include an estimate of the time left (once there's a track to base it on) */
+	pub show_eta: bool,
+	/* Only print lines about problems (i.e. "--quiet") */
+	pub quiet: bool,
+}
+
+/* Helper for modes to report their progress
+ * Keeps track of the timings and totals, and forwards the events to the sink (if there is one)
+ */
+pub struct ProgressReporter {
+	sink: Option<ProgressSink>,
+	line_options: TrackLineOptions,
+
+	run_start: Instant,
+	track_start: Instant,
+	summary: RunSummary,
+
+	/* Time spent on the tracks that actually got processed (i.e. not skipped), for the ETA */
+	worked_ms: u64,
+	worked_tracks: usize,
+}
+
+impl ProgressReporter {
+	pub fn new(sink: Option<ProgressSink>) -> ProgressReporter
+	{
+		ProgressReporter {
+			sink: sink,
+			line_options: TrackLineOptions::default(),
+			run_start: Instant::now(),
+			track_start: Instant::now(),
+			summary: RunSummary::default(),
+			worked_ms: 0,
+			worked_tracks: 0,
+		}
+	}
+
+	/* Set how the lines printed for each track look */
+	pub fn set_line_options(&mut self, options: TrackLineOptions)
+	{
+		self.line_options = options;
+	}
+
+	fn emit(&mut self, event: ProgressEvent)
+	{
+		if let Some(ref mut sink) = self.sink {
+			sink.emit(event);
+		}
+	}
+
+	/* Start of the run */
+	pub fn run_started(&mut self, mode: &str, total_tracks: usize, total_bytes: Option<u64>)
+	{
+		self.run_start = Instant::now();
+		self.summary.total_tracks = total_tracks;
+
+		self.emit(ProgressEvent::RunStarted {
+			mode: mode.to_string(),
+			total_tracks: total_tracks,
+			total_bytes: total_bytes,
+		});
+	}
+
+	/* Start of processing a track */
+	pub fn track_started(&mut self, track_index: usize, src: &str, dst: &str)
+	{
+		self.track_start = Instant::now();
+
+		self.emit(ProgressEvent::TrackStarted {
+			track_index: track_index,
+			src: src.to_string(),
+			dst: dst.to_string(),
+		});
+	}
+
+	/* End of processing a track (timed from the last track_started()) */
+	pub fn track_finished(&mut self, track_index: usize, status: TrackStatus, bytes: Option<u64>)
+	{
+		match status {
+			TrackStatus::Ok      => self.summary.succeeded += 1,
+			TrackStatus::Failed  => self.summary.failed += 1,
+			TrackStatus::Skipped => self.summary.skipped += 1,
+		}
+		if status == TrackStatus::Ok {
+			self.summary.bytes += bytes.unwrap_or(0);
+		}
+
+		let elapsed_ms = elapsed_ms(self.track_start);
+		if status != TrackStatus::Skipped {
+			self.worked_ms += elapsed_ms;
+			self.worked_tracks += 1;
+		}
+		self.emit(ProgressEvent::TrackFinished {
+			track_index: track_index,
+			status: status,
+			elapsed_ms: elapsed_ms,
+			bytes: bytes,
+		});
+	}
+
+	/* Estimate of the time left (in ms), from the average time taken by the tracks processed so far */
+	pub fn eta_ms(&self) -> Option<u64>
+	{
+		let finished = self.summary.succeeded + self.summary.failed + self.summary.skipped;
+		estimate_remaining_ms(self.worked_ms, self.worked_tracks, self.summary.total_tracks.saturating_sub(finished))
+	}
+
+	/* Line about the given track, prefixed with how far along the run is
+	 * (e.g. "[ 37/412 02:15 ETA 21:40] Converting ...")
+	 */
+	pub fn track_line(&self, track_index: usize, message: &str) -> String
+	{
+		let eta = if self.line_options.show_eta { self.eta_ms() } else { None };
+		format_track_line(track_index, self.summary.total_tracks, self.line_options.index_width,
+		                  elapsed_ms(self.run_start), eta, message)
+	}
+
+	/* Print a line about how the track went (on stdout), unless only problems are wanted */
+	pub fn print_track(&self, track_index: usize, message: &str)
+	{
+		if !self.line_options.quiet {
+			println!("{}", self.track_line(track_index, message));
+		}
+	}
+
+	/* Print a line about a problem with the track (on stderr) - even with "quiet" */
+	pub fn print_track_error(&self, track_index: usize, message: &str)
+	{
+		eprintln!("{}", self.track_line(track_index, message));
+	}
+
+	/* End of the run - Waits for all the events to be written
+	 * > returns the summary of the run
+	 */
+	pub fn run_finished(mut self) -> RunSummary
+	{
+		self.summary.elapsed_ms = elapsed_ms(self.run_start);
+		self.summary.dropped_events = self.sink.as_ref().map(|s| s.dropped()).unwrap_or(0);
+
+		let summary = self.summary.clone();
+		self.emit(ProgressEvent::RunFinished { summary: summary.clone() });
+
+		if let Some(sink) = self.sink.take() {
+			sink.finish();
+		}
+		summary
+	}
+}
+
+/* Estimate of the time needed for the tracks that are left, at the average time taken per track so far
+ * > returns None until there's been a track to base it on
+ */
+fn estimate_remaining_ms(worked_ms: u64, worked_tracks: usize, remaining_tracks: usize) -> Option<u64>
+{
+	if worked_tracks == 0 {
+		None
+	}
+	else {
+		Some(worked_ms * remaining_tracks as u64 / worked_tracks as u64)
+	}
+}
+
+/* Line prefixed with the track number (1-based) out of the total, the time elapsed, and the time left (if known) */
+fn format_track_line(track_index: usize, total_tracks: usize, index_width: usize,
+                     elapsed_ms: u64, eta_ms: Option<u64>, message: &str)
+	-> String
+{
+	let timecode = |ms: u64| TrackDuration(ms as i64).to_timecode();
+	let eta = match eta_ms {
+		Some(ms) => format!(" ETA {}", timecode(ms)),
+		None     => String::new()
+	};
+	format!("[{0:>width$}/{1:>width$} {2}{3}] {4}",
+	        track_index + 1, total_tracks, timecode(elapsed_ms), eta, message, width = index_width)
+}
+
+/* Milliseconds since the given time */
+fn elapsed_ms(since: Instant) -> u64
+{
+	let elapsed = since.elapsed();
+	elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64
+}
+
+/* Total size of the given files
+ * > returns None if any of them couldn't be checked
+ */
+pub fn total_file_size<'a, I>(paths: I) -> Option<u64>
+	where I: Iterator<Item = &'a Path>
+{
+	let mut total = 0;
+	for path in paths {
+		total += fs::metadata(path).ok()?.len();
+	}
+	Some(total)
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	/* Read back the events written to a file */
+	fn read_records(path: &Path) -> Vec<ProgressRecord>
+	{
+		fs::read_to_string(path).unwrap()
+		  .lines()
+		  .map(|line| serde_json::from_str::<ProgressRecord>(line).unwrap())
+		  .collect()
+	}
+
+	/* Copy the fixture files to dst_dir (like copy mode does), reporting progress
+	 * > returns the filenames that got copied (i.e. the run report), and the summary
+	 */
+	fn simulate_copy(sources: &[&Path], dst_dir: &Path, progress: ProgressReporter) -> (Vec<String>, RunSummary)
+	{
+		let mut progress = progress;
+		progress.run_started("copy", sources.len(), total_file_size(sources.iter().cloned()));
+
+		let mut copied = Vec::new();
+		for (i, src) in sources.iter().enumerate() {
+			let dst_filename = format!("Track_{0:02}-{1}", i + 1, src.file_name().unwrap().to_string_lossy());
+			let dst_path = dst_dir.join(&dst_filename);
+			progress.track_started(i, &src.to_string_lossy(), &dst_path.to_string_lossy());
+
+			match fs::copy(src, &dst_path) {
+				Ok(bytes) => {
+					progress.track_finished(i, TrackStatus::Ok, Some(bytes));
+					copied.push(dst_filename);
+				},
+				Err(_) => {
+					progress.track_finished(i, TrackStatus::Failed, None);
+				}
+			}
+		}
+
+		let summary = progress.run_finished();
+		(copied, summary)
+	}
+
+	#[test]
+	fn test_copy_events()
+	{
+		let dir = TempDir::new("progress-events");
+		let a = dir.write_file("music/20170403/v01-tranquil.mp3", b"tranquil");
+		let b = dir.write_file("music/20170404/v02-celestial.flac", b"celestial!");
+		let missing = dir.path().join("music/20170405/v03-missing.mp3");
+		let out_dir = dir.path().join("out");
+		fs::create_dir_all(&out_dir).unwrap();
+
+		let events_path = dir.path().join("events.jsonl");
+		let sink = ProgressSink::open(&events_path.to_string_lossy()).unwrap();
+
+		let sources = [a.as_path(), missing.as_path(), b.as_path()];
+		let (copied, summary) = simulate_copy(&sources, &out_dir, ProgressReporter::new(Some(sink)));
+
+		let records = read_records(&events_path);
+		assert_eq!(8, records.len());
+		assert!(records.iter().all(|r| r.version == EVENTS_FORMAT_VERSION && r.dropped == 0));
+		assert_eq!((0 .. 8).collect::<Vec<u64>>(), records.iter().map(|r| r.seq).collect::<Vec<_>>());
+
+		/* Event sequence */
+		match records[0].event {
+			ProgressEvent::RunStarted { ref mode, total_tracks: 3, total_bytes: None } => assert_eq!("copy", mode),
+			ref other => panic!("Unexpected first event - {:?}", other),
+		}
+		for (i, pair) in records[1 .. 7].chunks(2).enumerate() {
+			match (&pair[0].event, &pair[1].event) {
+				(&ProgressEvent::TrackStarted { track_index: s, .. }, &ProgressEvent::TrackFinished { track_index: f, .. }) => {
+					assert_eq!((i, i), (s, f));
+				},
+				other => panic!("Unexpected track events - {:?}", other),
+			}
+		}
+		match records[4].event {
+			ProgressEvent::TrackFinished { status: TrackStatus::Failed, bytes: None, .. } => {},
+			ref other => panic!("Expected failed track - {:?}", other),
+		}
+
+		/* Final summary is consistent with what actually got copied */
+		match records[7].event {
+			ProgressEvent::RunFinished { summary: ref written } => assert_eq!(summary, *written),
+			ref other => panic!("Unexpected last event - {:?}", other),
+		}
+		assert_eq!(vec!["Track_01-v01-tranquil.mp3", "Track_03-v02-celestial.flac"], copied);
+		assert_eq!((3, 2, 1, 0), (summary.total_tracks, summary.succeeded, summary.failed, summary.skipped));
+		assert_eq!(copied.iter().map(|f| fs::metadata(out_dir.join(f)).unwrap().len()).sum::<u64>(), summary.bytes);
+	}
+
+	#[test]
+	fn test_track_line()
+	{
+		assert_eq!("[ 37/412 02:15] Copied a.mp3", format_track_line(36, 412, 3, 135_000, None, "Copied a.mp3"));
+		assert_eq!("[  9/412 00:00 ETA 1:01:01] Converting b.flac",
+		           format_track_line(8, 412, 3, 400, Some(3_661_000), "Converting b.flac"));
+		assert_eq!("[ 1/12 00:05] x", format_track_line(0, 12, 2, 5_000, None, "x"));
+
+		/* Tracks that are wider than the width given still fit */
+		assert_eq!("[1000/1000 00:00] x", format_track_line(999, 1000, 3, 0, None, "x"));
+	}
+
+	#[test]
+	fn test_estimate_remaining()
+	{
+		assert_eq!(None, estimate_remaining_ms(0, 0, 10));
+		assert_eq!(Some(30_000), estimate_remaining_ms(12_000, 4, 10));
+		assert_eq!(Some(0), estimate_remaining_ms(12_000, 4, 0));
+
+		/* Skipped tracks don't count towards the average */
+		let mut progress = ProgressReporter::new(None);
+		progress.set_line_options(TrackLineOptions { index_width: 2, show_eta: true, quiet: true });
+		progress.run_started("convert", 3, None);
+		assert_eq!(None, progress.eta_ms());
+		progress.track_started(0, "a", "b");
+		progress.track_finished(0, TrackStatus::Skipped, None);
+		assert_eq!(None, progress.eta_ms());
+		assert!(progress.track_line(1, "x").starts_with("[ 2/ 3 00:00] "));
+		progress.track_started(1, "a", "b");
+		progress.track_finished(1, TrackStatus::Ok, None);
+		assert!(progress.eta_ms().is_some());
+		assert!(progress.track_line(2, "x").contains(" ETA "));
+	}
+
+	/* Every event written matches the schema for the events output */
+	#[test]
+	fn test_events_schema()
+	{
+		use json_schema::{assert_matches_schema, OutputKind};
+
+		let dir = TempDir::new("progress-events-schema");
+		let a = dir.write_file("music/20170403/v01-tranquil.mp3", b"tranquil");
+		let missing = dir.path().join("music/20170405/v03-missing.mp3");
+		let out_dir = dir.path().join("out");
+		fs::create_dir_all(&out_dir).unwrap();
+
+		let events_path = dir.path().join("events.jsonl");
+		let sink = ProgressSink::open(&events_path.to_string_lossy()).unwrap();
+		simulate_copy(&[a.as_path(), missing.as_path()], &out_dir, ProgressReporter::new(Some(sink)));
+
+		let lines: Vec<Value> = fs::read_to_string(&events_path).unwrap()
+		  .lines()
+		  .map(|line| serde_json::from_str(line).unwrap())
+		  .collect();
+		assert_eq!(6, lines.len());
+		for line in lines.iter() {
+			assert_matches_schema(OutputKind::Events, line);
+		}
+
+		/* Known total size (which the copy above doesn't have, as one of the files is missing) */
+		let record = ProgressRecord {
+			version: EVENTS_FORMAT_VERSION,
+			seq: 0,
+			dropped: 0,
+			event: ProgressEvent::RunStarted { mode: "convert".to_string(), total_tracks: 1, total_bytes: Some(8) },
+		};
+		assert_matches_schema(OutputKind::Events, &serde_json::to_value(&record).unwrap());
+	}
+
+	/* Consumer that doesn't read anything until it's allowed to */
+	struct StalledWriter {
+		gate: Arc<Mutex<()>>,
+		lines: Arc<Mutex<Vec<u8>>>,
+	}
+
+	impl Write for StalledWriter {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+		{
+			let _wait = self.gate.lock().unwrap();
+			self.lines.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()>
+		{
+			Ok(())
+		}
+	}
+
+	/* A slow consumer doesn't block, and the oldest events get dropped instead */
+	#[test]
+	fn test_slow_consumer()
+	{
+		let gate = Arc::new(Mutex::new(()));
+		let lines = Arc::new(Mutex::new(Vec::new()));
+		let writer = StalledWriter { gate: gate.clone(), lines: lines.clone() };
+
+		let stall = gate.lock().unwrap();
+		let mut sink = ProgressSink::new(Box::new(writer), 4);
+
+		let start = Instant::now();
+		for i in 0 .. 100 {
+			sink.emit(ProgressEvent::TrackStarted { track_index: i, src: String::new(), dst: String::new() });
+		}
+		assert!(elapsed_ms(start) < 1000);
+		assert!(sink.dropped() >= 95);  /* At most one may already be in the writer's hands */
+
+		drop(stall);
+		sink.finish();
+
+		let text = String::from_utf8(lines.lock().unwrap().clone()).unwrap();
+		let records: Vec<ProgressRecord> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+		assert!(records.len() <= 5);
+
+		/* The newest ones survive */
+		let last = records.last().unwrap();
+		assert_eq!(99, last.seq);
+		assert_eq!(100 - records.len() as u64, last.dropped);
+	}
+}
@@ -0,0 +1,414 @@
+/* Run journal - A durable record of what each run did
+ *
+ * With "--journal=<path>", every mode appends timestamped entries (the mode and its args,
+ * each significant action, each diagnostic, and summaries at the end) to the given file,
+ * as newline-delimited JSON. Each entry is flushed as soon as it's written, so that everything
+ * up to the point of a crash/power loss is preserved.
+ *
+ * The journal is purely a side record - problems writing it never stop the run. Instead, the
+ * first failure gets reported as a warning, and the journal is switched off for the rest of the run.
+ */
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use diagnostics::{Diagnostic, Diagnostics};
+use progress_events::RunSummary;
+use run_outcome::OutcomeReport;
+
+/* Size (in bytes) a journal file can grow to before it gets rotated (to "<path>.1") */
+pub const DEFAULT_MAX_JOURNAL_BYTES: u64 = 10 * 1024 * 1024;
+
+/* *************************************************** */
+/* Entry Types */
+
+/* Things that get recorded in the journal */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalEntry {
+	/* A mode started running */
+	ModeStarted {
+		mode: String,
+		/* Full command line */
+		args: Vec<String>,
+	},
+	/* Something significant was done (e.g. action = "copied", detail = "<src> -> <dst>") */
+	Action {
+		action: String,
+		detail: String,
+	},
+	/* A problem/finding was noticed */
+	Diagnostic(Diagnostic),
+	/* Totals for a run over the tracks in a playlist (copy, convert, bundle) */
+	Summary(RunSummary),
+	/* Problems noticed during the run (by category), and how it ended (see run_outcome) */
+	Outcome(OutcomeReport),
+	/* The mode finished (without terminating early) */
+	ModeFinished {
+		mode: String,
+		elapsed_ms: u64,
+	},
+}
+
+/* A line in the journal */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct JournalRecord {
+	/* Position in the sequence of entries written by this run (starting from 0) */
+	pub seq: u64,
+	/* When the entry was written (milliseconds since the unix epoch) */
+	pub timestamp_ms: u64,
+	/* Process that wrote it (for telling runs apart) */
+	pub pid: u32,
+
+	pub entry: JournalEntry,
+}
+
+fn now_ms() -> u64
+{
+	SystemTime::now().duration_since(UNIX_EPOCH)
+	                 .map(|d| d.as_secs() * 1000 + d.subsec_millis() as u64)
+	                 .unwrap_or(0)
+}
+
+/* *************************************************** */
+/* Journal Writer */
+
+/* Append-only writer for journal entries */
+pub struct Journal {
+	/* Where entries get written (None once the journal has been switched off after an error) */
+	writer: Option<Box<dyn Write + Send>>,
+
+	/* File being written to (None if not writing to a file, in which case there's no rotation) */
+	path: Option<PathBuf>,
+	/* Size the file is allowed to reach before being rotated */
+	max_bytes: u64,
+	/* Current size of the file */
+	written_bytes: u64,
+
+	/* Number of entries written so far */
+	seq: u64,
+}
+
+impl Journal {
+	/* Journal writing to the given stream (without any rotation) */
+	pub fn new(writer: Box<dyn Write + Send>) -> Journal
+	{
+		Journal {
+			writer: Some(writer),
+			path: None,
+			max_bytes: u64::MAX,
+			written_bytes: 0,
+			seq: 0,
+		}
+	}
+
+	/* Journal appending to the given file, which gets rotated once it exceeds max_bytes */
+	pub fn open(path: &Path, max_bytes: u64) -> io::Result<Journal>
+	{
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		let existing_bytes = file.metadata()?.len();
+
+		let mut journal = Journal::new(Box::new(file));
+		journal.path = Some(path.to_path_buf());
+		journal.max_bytes = max_bytes;
+		journal.written_bytes = existing_bytes;
+		Ok(journal)
+	}
+
+	/* Path that a full journal file gets moved to - "<path>.1" */
+	pub fn rotated_path(path: &Path) -> PathBuf
+	{
+		let mut rotated: OsString = path.as_os_str().to_os_string();
+		rotated.push(".1");
+		PathBuf::from(rotated)
+	}
+
+	/* Append an entry (flushing it straight away) */
+	pub fn record(&mut self, entry: JournalEntry)
+	{
+		if self.writer.is_none() {
+			return;
+		}
+
+		let record = JournalRecord {
+			seq: self.seq,
+			timestamp_ms: now_ms(),
+			pid: ::std::process::id(),
+			entry: entry,
+		};
+		let mut line = match serde_json::to_string(&record) {
+			Ok(line) => line,
+			Err(e)   => return self.disable(io::Error::other(e))
+		};
+		line.push('\n');
+
+		match self.write_line(&line) {
+			Ok(_)  => self.seq += 1,
+			Err(e) => self.disable(e)
+		}
+	}
+
+	/* Record each of the given diagnostics */
+	pub fn record_diagnostics(&mut self, diagnostics: &Diagnostics)
+	{
+		for diagnostic in diagnostics.entries.iter() {
+			self.record(JournalEntry::Diagnostic(diagnostic.clone()));
+		}
+	}
+
+	fn write_line(&mut self, line: &str) -> io::Result<()>
+	{
+		let line_bytes = line.len() as u64;
+		if (self.written_bytes > 0) && (self.written_bytes + line_bytes > self.max_bytes) {
+			self.rotate()?;
+		}
+
+		if let Some(ref mut writer) = self.writer {
+			writer.write_all(line.as_bytes())?;
+			writer.flush()?;
+		}
+		self.written_bytes += line_bytes;
+		Ok(())
+	}
+
+	/* Move the current file out of the way (replacing any older one), and start a new one */
+	fn rotate(&mut self) -> io::Result<()>
+	{
+		if let Some(path) = self.path.clone() {
+			self.writer = None;  /* Close the current file first */
+			fs::rename(&path, Journal::rotated_path(&path))?;
+
+			let file = File::create(&path)?;
+			self.writer = Some(Box::new(file));
+			self.written_bytes = 0;
+		}
+		Ok(())
+	}
+
+	/* Switch off the journal after an error, with a one-off warning */
+	fn disable(&mut self, e: io::Error)
+	{
+		let target = match self.path {
+			Some(ref path) => format!("{:?}", path),
+			None           => "journal".to_string()
+		};
+		eprintln!("WARNING: Couldn't write to {0} - {1} (journal disabled for the rest of this run)", target, e);
+		self.writer = None;
+	}
+}
+
+/* ------------------------------------------- */
+/* Shared Journal
+ *
+ * Every mode records into the same journal (if one was opened), so it lives here instead of being
+ * passed down through all of them.
+ */
+
+lazy_static! {
+	static ref ACTIVE_JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+}
+
+/* Make the given journal the one that record() writes to */
+pub fn install(journal: Journal)
+{
+	if let Ok(mut active) = ACTIVE_JOURNAL.lock() {
+		*active = Some(journal);
+	}
+}
+
+/* Record an entry in the shared journal (if there is one) */
+pub fn record(entry: JournalEntry)
+{
+	if let Ok(mut active) = ACTIVE_JOURNAL.lock() {
+		if let Some(ref mut journal) = *active {
+			journal.record(entry);
+		}
+	}
+}
+
+/* Record an action in the shared journal (if there is one) */
+pub fn record_action(action: &str, detail: String)
+{
+	record(JournalEntry::Action { action: action.to_string(), detail: detail });
+}
+
+/* Record diagnostics in the shared journal (if there is one) */
+pub fn record_diagnostics(diagnostics: &Diagnostics)
+{
+	if let Ok(mut active) = ACTIVE_JOURNAL.lock() {
+		if let Some(ref mut journal) = *active {
+			journal.record_diagnostics(diagnostics);
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::{Arc, Mutex};
+	use diagnostics::Severity;
+	use run_outcome::{RunOutcome, COPY_FAILED_CATEGORY, EXIT_OK};
+	use test_utils::TempDir;
+
+	/* Writer that keeps everything written, and counts the flushes */
+	#[derive(Clone)]
+	struct CountingWriter {
+		data: Arc<Mutex<Vec<u8>>>,
+		flushes: Arc<Mutex<usize>>,
+		/* Fail all writes after this many bytes */
+		fail_after: Option<usize>,
+	}
+
+	impl CountingWriter {
+		fn new() -> CountingWriter
+		{
+			CountingWriter { data: Arc::new(Mutex::new(Vec::new())), flushes: Arc::new(Mutex::new(0)), fail_after: None }
+		}
+
+		fn lines(&self) -> Vec<serde_json::Value>
+		{
+			let data = self.data.lock().unwrap();
+			String::from_utf8_lossy(&data).lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+		}
+
+		fn flushes(&self) -> usize
+		{
+			*self.flushes.lock().unwrap()
+		}
+	}
+
+	impl Write for CountingWriter {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+		{
+			let mut data = self.data.lock().unwrap();
+			if let Some(limit) = self.fail_after {
+				if data.len() + buf.len() > limit {
+					return Err(io::Error::other("disk full"));
+				}
+			}
+			data.extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()>
+		{
+			*self.flushes.lock().unwrap() += 1;
+			Ok(())
+		}
+	}
+
+	/* Entries for a copy of two tracks (one of which fails), as recorded by copy_files_mode() */
+	#[test]
+	fn test_copy_run_entries()
+	{
+		let writer = CountingWriter::new();
+		let mut journal = Journal::new(Box::new(writer.clone()));
+
+		journal.record(JournalEntry::ModeStarted {
+			mode: "copy".to_string(),
+			args: vec!["xspf_tools".to_string(), "copy".to_string(), "in.xspf".to_string(), "out".to_string()],
+		});
+		journal.record(JournalEntry::Action { action: "copied".to_string(), detail: "a.mp3 -> out/Track_1.mp3".to_string() });
+		journal.record(JournalEntry::Action { action: "copy failed".to_string(), detail: "b.mp3 -> out/Track_2.mp3".to_string() });
+
+		let mut problems = Diagnostics::new();
+		problems.warn_track("waveform", 0, "ffmpeg failed".to_string());
+		journal.record_diagnostics(&problems);
+
+		journal.record(JournalEntry::Summary(RunSummary { total_tracks: 2, succeeded: 1, failed: 1, ..RunSummary::default() }));
+		journal.record(JournalEntry::ModeFinished { mode: "copy".to_string(), elapsed_ms: 5 });
+
+		let mut outcome = RunOutcome::default();
+		outcome.add_diagnostics(&problems);
+		outcome.add(Severity::Error, COPY_FAILED_CATEGORY);
+		journal.record(JournalEntry::Outcome(outcome.report(EXIT_OK)));
+
+		let lines = writer.lines();
+		let types: Vec<&str> = lines.iter().map(|l| l["entry"]["type"].as_str().unwrap()).collect();
+		assert_eq!(vec!["mode_started", "action", "action", "diagnostic", "summary", "mode_finished", "outcome"], types);
+
+		let seqs: Vec<u64> = lines.iter().map(|l| l["seq"].as_u64().unwrap()).collect();
+		assert_eq!(vec![0, 1, 2, 3, 4, 5, 6], seqs);
+		assert!(lines.iter().all(|l| l["timestamp_ms"].as_u64().unwrap() > 0));
+
+		assert_eq!("out", lines[0]["entry"]["args"][3]);
+		assert_eq!("copied", lines[1]["entry"]["action"]);
+		assert_eq!("waveform", lines[3]["entry"]["category"]);
+		assert_eq!(1, lines[4]["entry"]["failed"]);
+		assert_eq!("failed", lines[6]["entry"]["status"]);
+		assert_eq!(3, lines[6]["entry"]["exit_code"]);
+		assert_eq!(COPY_FAILED_CATEGORY, lines[6]["entry"]["categories"][1]["category"]);
+	}
+
+	/* Every entry gets flushed as soon as it's written */
+	#[test]
+	fn test_flush_per_entry()
+	{
+		let writer = CountingWriter::new();
+		let mut journal = Journal::new(Box::new(writer.clone()));
+
+		for i in 0 .. 3 {
+			journal.record(JournalEntry::Action { action: "copied".to_string(), detail: format!("{}", i) });
+			assert_eq!(i + 1, writer.flushes());
+			assert_eq!(i + 1, writer.lines().len());
+		}
+	}
+
+	/* Write errors switch the journal off, without stopping anything */
+	#[test]
+	fn test_write_failure()
+	{
+		let mut writer = CountingWriter::new();
+		writer.fail_after = Some(200);
+		let mut journal = Journal::new(Box::new(writer.clone()));
+
+		for i in 0 .. 10 {
+			journal.record(JournalEntry::Action { action: "copied".to_string(), detail: format!("track {}", i) });
+		}
+		assert!(journal.writer.is_none());  /* switched off */
+		assert!(!writer.lines().is_empty());
+		assert!(writer.lines().len() < 10);
+	}
+
+	#[test]
+	fn test_rotation()
+	{
+		let dir = TempDir::new("run-journal");
+		let path = dir.path().join("run.journal");
+		let rotated = Journal::rotated_path(&path);
+		assert_eq!(dir.path().join("run.journal.1"), rotated);
+
+		let entry = || JournalEntry::Action { action: "copied".to_string(), detail: "x".repeat(100) };
+
+		/* Room for 2 entries per file */
+		let mut journal = Journal::open(&path, 500).unwrap();
+		journal.record(entry());
+		journal.record(entry());
+		assert!(!rotated.exists());
+
+		journal.record(entry());
+		assert!(rotated.exists());
+		assert_eq!(2, fs::read_to_string(&rotated).unwrap().lines().count());
+		assert_eq!(1, fs::read_to_string(&path).unwrap().lines().count());
+
+		/* Reopening carries on from the existing size */
+		drop(journal);
+		let mut journal = Journal::open(&path, 500).unwrap();
+		journal.record(entry());
+		assert_eq!(2, fs::read_to_string(&path).unwrap().lines().count());
+		journal.record(entry());
+		assert_eq!(1, fs::read_to_string(&path).unwrap().lines().count());
+		assert_eq!(2, fs::read_to_string(&rotated).unwrap().lines().count());
+	}
+}
@@ -0,0 +1,502 @@
+/* Run outcome - A tally of the problems noticed during a run, summarised at the end of every mode
+ *
+ * Problems get counted by category as they're reported (the diagnostics noticed by each check, along with
+ * failures such as tracks that couldn't be copied), so every mode ends with the same summary block without
+ * having to keep its own counts: one line per category that occurred, then the overall status, and what
+ * the exit code means. The same summary gets recorded in the journal (if there is one), and written to the
+ * "--report" file (if one was asked for), so that scripts can tell what went wrong without parsing the output.
+ */
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde_json;
+
+use diagnostics::{Diagnostics, Severity};
+use run_journal::{self, JournalEntry};
+use terminal_text::sanitize;
+
+/* Exit codes (as explained by the status line) */
+pub const EXIT_OK: i32 = 0;
+/* Bad usage (e.g. missing/invalid arguments), or anything else that stopped the run */
+pub const EXIT_FAILED: i32 = 1;
+/* The playlist couldn't be read at all */
+pub const EXIT_UNREADABLE_PLAYLIST: i32 = 2;
+/* The run finished, but some of the tracks couldn't be copied/converted/moved */
+pub const EXIT_TRACKS_FAILED: i32 = 3;
+
+/* Categories for failures while working on the playlist/tracks (as opposed to findings from the checks) */
+pub const UNREADABLE_PLAYLIST_CATEGORY: &'static str = "unreadable-playlist";
+pub const COPY_FAILED_CATEGORY: &'static str = "copy-failed";
+pub const CONVERT_FAILED_CATEGORY: &'static str = "convert-failed";
+pub const MOVE_FAILED_CATEGORY: &'static str = "move-failed";
+pub const SKIPPED_CATEGORY: &'static str = "skipped";
+pub const MISSING_FILE_CATEGORY: &'static str = "missing-file";
+pub const UNPARSABLE_NAME_CATEGORY: &'static str = "unparsable-name";
+pub const PLAYLISTS_DIFFER_CATEGORY: &'static str = "playlists-differ";
+pub const OUTPUT_FAILED_CATEGORY: &'static str = "output-failed";
+
+/* Categories of failures for individual tracks (i.e. EXIT_TRACKS_FAILED) */
+const TRACK_FAILED_CATEGORIES: [&'static str; 3] = [COPY_FAILED_CATEGORY, CONVERT_FAILED_CATEGORY, MOVE_FAILED_CATEGORY];
+
+/* Meaning of an exit code (for the status line) */
+pub fn exit_code_meaning(exit_code: i32) -> &'static str
+{
+	match exit_code {
+		EXIT_OK                  => "everything succeeded",
+		EXIT_UNREADABLE_PLAYLIST => "the playlist couldn't be read",
+		EXIT_TRACKS_FAILED       => "some tracks failed, see the errors above",
+		_                        => "something failed, see the errors above",
+	}
+}
+
+/* *************************************************** */
+/* Tally */
+
+/* Number of problems of one category */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct CategoryCount {
+	pub category: String,
+	/* Most serious severity seen for this category */
+	pub severity: Severity,
+	pub count: usize,
+}
+
+/* How the tracks of a copy/convert/move run went */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub struct TrackResults {
+	pub ok: usize,
+	pub failed: usize,
+	/* Tracks left alone (e.g. already up to date, or done by an earlier run) */
+	pub skipped: usize,
+
+	/* Source files of the tracks that failed (in playlist order) */
+	pub failed_files: Vec<String>,
+}
+
+impl TrackResults {
+	/* Note a track that failed */
+	pub fn add_failed(&mut self, src_path: &Path)
+	{
+		self.failed += 1;
+		self.failed_files.push(src_path.display().to_string());
+	}
+}
+
+/* Problems noticed so far (by category, in the order they first happened) */
+#[derive(Debug)]
+#[derive(Default)]
+pub struct RunOutcome {
+	categories: Vec<CategoryCount>,
+	tracks: Option<TrackResults>,
+}
+
+impl RunOutcome {
+	/* Count a problem of the given category */
+	pub fn add(&mut self, severity: Severity, category: &str)
+	{
+		match self.categories.iter_mut().find(|c| c.category == category) {
+			Some(entry) => {
+				entry.count += 1;
+				if severity_rank(severity) > severity_rank(entry.severity) {
+					entry.severity = severity;
+				}
+			},
+			None => {
+				self.categories.push(CategoryCount { category: category.to_string(), severity: severity, count: 1 });
+			}
+		}
+	}
+
+	/* Count all the given diagnostics */
+	pub fn add_diagnostics(&mut self, diagnostics: &Diagnostics)
+	{
+		for d in diagnostics.entries.iter() {
+			self.add(d.severity, &d.category);
+		}
+	}
+
+	/* Set the results for the tracks of a copy/convert/move run */
+	pub fn set_track_results(&mut self, results: TrackResults)
+	{
+		self.tracks = Some(results);
+	}
+
+	/* Summary of the run, which is ending with the given exit code
+	 * NOTE: Runs with errors always count as failed (even if the mode itself finished normally),
+	 *       and failures get the exit code for the most serious problem (see failure_exit_code())
+	 */
+	pub fn report(&self, exit_code: i32) -> OutcomeReport
+	{
+		let errors = self.count_of(Severity::Error);
+		let exit_code = if (errors > 0 && exit_code == EXIT_OK) || exit_code == EXIT_FAILED {
+			self.failure_exit_code()
+		}
+		else {
+			exit_code
+		};
+
+		let status = if exit_code != EXIT_OK {
+			RunStatus::Failed
+		}
+		else if self.categories.is_empty() {
+			RunStatus::Ok
+		}
+		else {
+			RunStatus::OkWithWarnings
+		};
+
+		OutcomeReport {
+			categories: self.categories.clone(),
+			status: status,
+			exit_code: exit_code,
+			tracks: self.tracks.clone(),
+		}
+	}
+
+	/* Exit code for a failed run - an unreadable playlist, then tracks that failed, before anything else */
+	fn failure_exit_code(&self) -> i32
+	{
+		let has_error = |category: &str| {
+			self.categories.iter().any(|c| c.category == category && c.severity == Severity::Error)
+		};
+
+		if has_error(UNREADABLE_PLAYLIST_CATEGORY) {
+			EXIT_UNREADABLE_PLAYLIST
+		}
+		else if TRACK_FAILED_CATEGORIES.iter().any(|category| has_error(category)) {
+			EXIT_TRACKS_FAILED
+		}
+		else {
+			EXIT_FAILED
+		}
+	}
+
+	/* Total number of problems with the given severity */
+	fn count_of(&self, severity: Severity) -> usize
+	{
+		self.categories.iter().filter(|c| c.severity == severity).map(|c| c.count).sum()
+	}
+}
+
+/* Order of severities, from least to most serious */
+fn severity_rank(severity: Severity) -> u32
+{
+	match severity {
+		Severity::Info    => 0,
+		Severity::Warning => 1,
+		Severity::Error   => 2,
+	}
+}
+
+/* *************************************************** */
+/* Report */
+
+/* How the run went overall */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+	/* Nothing went wrong */
+	Ok,
+	/* Finished, but some problems were noticed along the way */
+	OkWithWarnings,
+	/* Something failed (or the mode had to stop early) */
+	Failed,
+}
+
+/* Summary of a run (as printed at the end, and recorded in the journal) */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct OutcomeReport {
+	pub categories: Vec<CategoryCount>,
+	pub status: RunStatus,
+	pub exit_code: i32,
+
+	/* How the tracks went (only for copy/convert/move runs) */
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tracks: Option<TrackResults>,
+}
+
+impl OutcomeReport {
+	/* Did the run succeed, without any problems? */
+	pub fn is_clean(&self) -> bool
+	{
+		self.status == RunStatus::Ok
+	}
+
+	/* Status line, e.g. "Status: FAILED (exit code 1 - something failed, see the errors above)" */
+	pub fn status_line(&self) -> String
+	{
+		match self.status {
+			RunStatus::Ok             => format!("Status: OK (exit code {} - everything succeeded)", self.exit_code),
+			RunStatus::OkWithWarnings => format!("Status: OK with warnings (exit code {} - finished, but check the problems listed above)", self.exit_code),
+			RunStatus::Failed         => format!("Status: FAILED (exit code {0} - {1})", self.exit_code, exit_code_meaning(self.exit_code)),
+		}
+	}
+
+	/* Write the summary as JSON (for "--report=<file.json>")
+	 * NOTE: The file gets replaced in one step, so scripts never see half a report
+	 */
+	pub fn write_json_file(&self, path: &Path) -> Result<(), String>
+	{
+		let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+
+		let temp_path = path.with_extension("json.tmp");
+		File::create(&temp_path)
+			.and_then(|mut f| f.write_all(json.as_bytes()))
+			.and_then(|_| fs::rename(&temp_path, path))
+			.map_err(|e| format!("Couldn't write report to {0:?} - {1}", path, e))
+	}
+
+	/* Write the summary block (nothing but the status line when there weren't any problems) */
+	pub fn write_text(&self, out: &mut dyn Write) -> io::Result<()>
+	{
+		if !self.categories.is_empty() {
+			let width = self.categories.iter().map(|c| c.category.len()).max().unwrap_or(0);
+			writeln!(out, "Problems:")?;
+			for c in self.categories.iter() {
+				let plural = if c.count == 1 { "" } else { "s" };
+				writeln!(out, "    {0:<width$}  {1:>5} {2}{3}", sanitize(&c.category), c.count, c.severity, plural, width = width)?;
+			}
+		}
+		writeln!(out, "{}", self.status_line())
+	}
+}
+
+/* ------------------------------------------- */
+/* Current Run
+ *
+ * Problems get counted from wherever they're noticed, so (like the journal) the tally
+ * for the current run is kept here instead of being passed down through all the modes.
+ */
+
+lazy_static! {
+	static ref RUN_OUTCOME: Mutex<RunOutcome> = Mutex::new(RunOutcome::default());
+}
+
+/* Count the given diagnostics (after they've been shown), and record them in the journal */
+pub fn record_diagnostics(diagnostics: &Diagnostics)
+{
+	if let Ok(mut outcome) = RUN_OUTCOME.lock() {
+		outcome.add_diagnostics(diagnostics);
+	}
+	run_journal::record_diagnostics(diagnostics);
+}
+
+/* Count a failure of the given category (e.g. COPY_FAILED_CATEGORY) */
+pub fn record_failure(category: &str)
+{
+	if let Ok(mut outcome) = RUN_OUTCOME.lock() {
+		outcome.add(Severity::Error, category);
+	}
+}
+
+/* Note how the tracks of a copy/convert/move run went (for the summary, and the "--report" file) */
+pub fn record_track_results(results: TrackResults)
+{
+	if let Ok(mut outcome) = RUN_OUTCOME.lock() {
+		outcome.set_track_results(results);
+	}
+}
+
+/* Count a problem that didn't stop the run (e.g. SKIPPED_CATEGORY) */
+pub fn record_warning(category: &str)
+{
+	if let Ok(mut outcome) = RUN_OUTCOME.lock() {
+		outcome.add(Severity::Warning, category);
+	}
+}
+
+/* Summarise the current run (which is ending with the given exit code) on stderr, and in the journal
+ * (and as JSON in report_path, if given)
+ * NOTE: With "quiet", nothing gets printed unless there were problems
+ * > returns the exit code to use
+ */
+pub fn finish(exit_code: i32, quiet: bool, report_path: Option<&Path>) -> i32
+{
+	let report = match RUN_OUTCOME.lock() {
+		Ok(outcome) => outcome.report(exit_code),
+		Err(_)      => RunOutcome::default().report(exit_code)
+	};
+
+	if !(quiet && report.is_clean()) {
+		eprintln!();
+		let _ = report.write_text(&mut io::stderr());
+	}
+	if let Some(path) = report_path {
+		if let Err(e) = report.write_json_file(path) {
+			eprintln!("WARNING: {}", e);
+		}
+	}
+	run_journal::record(JournalEntry::Outcome(report.clone()));
+	report.exit_code
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use std::path::Path;
+	use test_utils::TempDir;
+	use xspf_parser::{parse_xspf, ParseOptions};
+
+	/* Copy the playlist's tracks into "dst_dir", tallying the problems along the way (like "copy" does) */
+	fn simulate_copy(playlist: &Path, dst_dir: &Path) -> OutcomeReport
+	{
+		let mut outcome = RunOutcome::default();
+		let xspf = match parse_xspf(playlist.to_str().unwrap(), &ParseOptions::default()) {
+			Ok(xspf) => xspf,
+			Err(_) => {
+				outcome.add(Severity::Error, UNREADABLE_PLAYLIST_CATEGORY);
+				return outcome.report(EXIT_OK);
+			}
+		};
+		outcome.add_diagnostics(&xspf.diagnostics);
+
+		fs::create_dir_all(dst_dir).unwrap();
+		let mut results = TrackResults::default();
+		for track in xspf.tracks.iter() {
+			if fs::copy(track.file_path(), dst_dir.join(&track.filename)).is_ok() {
+				results.ok += 1;
+			}
+			else {
+				outcome.add(Severity::Error, COPY_FAILED_CATEGORY);
+				results.add_failed(track.file_path());
+			}
+		}
+		outcome.set_track_results(results);
+		outcome.report(EXIT_OK)
+	}
+
+	fn summary_text(report: &OutcomeReport) -> String
+	{
+		let mut out: Vec<u8> = Vec::new();
+		report.write_text(&mut out).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	/* A copy with paths that aren't UTF-8, and files that can't be copied */
+	#[test]
+	fn test_copy_with_problems()
+	{
+		let dir = TempDir::new("outcome-problems");
+		dir.write_file("20170802/v01-tranquil.mp3", b"ok");
+		let playlist = dir.write_file("playlist.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>20170802/v01-tranquil.mp3</location></track>\
+			<track><location>20170802/v02-caf%E9.mp3</location></track>\
+			<track><location>20170802/v03-gone.mp3</location></track>\
+			<track><location>20170802/v04-na%EFve.mp3</location></track>\
+			</trackList></playlist>");
+
+		let report = simulate_copy(&playlist, &dir.path().join("out"));
+		assert_eq!(vec![("non-utf8-path", Severity::Warning, 2), (COPY_FAILED_CATEGORY, Severity::Error, 3)],
+		           report.categories.iter().map(|c| (c.category.as_str(), c.severity, c.count)).collect::<Vec<_>>());
+		assert_eq!((RunStatus::Failed, EXIT_TRACKS_FAILED), (report.status, report.exit_code));
+
+		assert_eq!("Problems:\n\
+		            \x20   non-utf8-path      2 warnings\n\
+		            \x20   copy-failed        3 errors\n\
+		            Status: FAILED (exit code 3 - some tracks failed, see the errors above)\n",
+		           summary_text(&report));
+	}
+
+	/* A playlist that doesn't exist can't be copied at all */
+	#[test]
+	fn test_missing_playlist()
+	{
+		let dir = TempDir::new("outcome-missing-playlist");
+		let report = simulate_copy(&dir.path().join("nowhere.xspf"), &dir.path().join("out"));
+		assert_eq!((RunStatus::Failed, EXIT_UNREADABLE_PLAYLIST, None), (report.status, report.exit_code, report.tracks.clone()));
+		assert!(summary_text(&report).ends_with("Status: FAILED (exit code 2 - the playlist couldn't be read)\n"));
+	}
+
+	/* One track that doesn't exist - the rest still get copied, and the report names the one that failed */
+	#[test]
+	fn test_copy_with_missing_track()
+	{
+		let dir = TempDir::new("outcome-missing-track");
+		dir.write_file("20170802/v01-tranquil.mp3", b"ok");
+		dir.write_file("20170802/v03-moose.mp3", b"ok");
+		let playlist = dir.write_file("playlist.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>20170802/v01-tranquil.mp3</location></track>\
+			<track><location>20170802/v02-gone.mp3</location></track>\
+			<track><location>20170802/v03-moose.mp3</location></track>\
+			</trackList></playlist>");
+
+		let report = simulate_copy(&playlist, &dir.path().join("out"));
+		assert_eq!((RunStatus::Failed, EXIT_TRACKS_FAILED), (report.status, report.exit_code));
+
+		let tracks = report.tracks.clone().unwrap();
+		assert_eq!((2, 1, 0), (tracks.ok, tracks.failed, tracks.skipped));
+		assert_eq!(1, tracks.failed_files.len());
+		assert!(tracks.failed_files[0].ends_with("v02-gone.mp3"));
+
+		/* The same goes into the report file */
+		let report_path = dir.path().join("report.json");
+		report.write_json_file(&report_path).unwrap();
+		let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+		assert_eq!((3, "failed", 1), (json["exit_code"].as_i64().unwrap(), json["status"].as_str().unwrap(),
+		                              json["tracks"]["failed"].as_u64().unwrap()));
+		assert_eq!(tracks.failed_files[0], json["tracks"]["failed_files"][0].as_str().unwrap());
+		assert!(!dir.path().join("report.json.tmp").exists());
+	}
+
+	/* A clean run only gets the status line */
+	#[test]
+	fn test_clean_copy()
+	{
+		let dir = TempDir::new("outcome-clean");
+		dir.write_file("20170802/v01-tranquil.mp3", b"ok");
+		dir.write_file("20170802/v02-moose.mp3", b"ok");
+		let playlist = dir.write_file("playlist.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>20170802/v01-tranquil.mp3</location></track>\
+			<track><location>20170802/v02-moose.mp3</location></track>\
+			</trackList></playlist>");
+
+		let report = simulate_copy(&playlist, &dir.path().join("out"));
+		assert!(report.is_clean());
+		assert_eq!("Status: OK (exit code 0 - everything succeeded)\n", summary_text(&report));
+	}
+
+	#[test]
+	fn test_status()
+	{
+		let mut outcome = RunOutcome::default();
+		outcome.add(Severity::Warning, "rating");
+		assert_eq!((RunStatus::OkWithWarnings, EXIT_OK), { let r = outcome.report(EXIT_OK); (r.status, r.exit_code) });
+
+		/* Stopping early counts as failing, even without any errors counted */
+		assert_eq!((RunStatus::Failed, EXIT_FAILED), { let r = outcome.report(EXIT_FAILED); (r.status, r.exit_code) });
+
+		/* Categories keep the most serious severity seen */
+		outcome.add(Severity::Error, "rating");
+		outcome.add(Severity::Warning, "rating");
+		assert_eq!(vec![CategoryCount { category: "rating".to_string(), severity: Severity::Error, count: 3 }],
+		           outcome.report(EXIT_OK).categories);
+		assert_eq!(EXIT_FAILED, outcome.report(EXIT_OK).exit_code);
+
+		/* Tracks that failed take over from other errors, and an unreadable playlist from those */
+		outcome.add(Severity::Warning, MOVE_FAILED_CATEGORY);
+		assert_eq!(EXIT_FAILED, outcome.report(EXIT_FAILED).exit_code);
+		outcome.add(Severity::Error, MOVE_FAILED_CATEGORY);
+		assert_eq!(EXIT_TRACKS_FAILED, outcome.report(EXIT_FAILED).exit_code);
+		outcome.add(Severity::Error, UNREADABLE_PLAYLIST_CATEGORY);
+		assert_eq!(EXIT_UNREADABLE_PLAYLIST, outcome.report(EXIT_OK).exit_code);
+	}
+}
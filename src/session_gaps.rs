@@ -0,0 +1,226 @@
+/* Session gap check
+ *
+ * Within each session (i.e. the tracks of one type filed under one date), the take numbers
+ * should run on without any breaks (v01, v02, ... v07). Gaps usually mean a file got lost, or
+ * was never copied off the recorder, while the same number being used for takes with different
+ * names usually means something got misnumbered.
+ */
+use std::collections::BTreeMap;
+
+use diagnostics::{Diagnostics, Severity};
+use track_name_info::TrackType;
+use xspf_parser::XspfPlaylist;
+
+/* Category used for the diagnostics generated here */
+pub const CATEGORY: &'static str = "gaps";
+
+/* One take in a session - (index, variant, name) */
+pub type Take<'a> = (i32, Option<&'a str>, &'a str);
+
+/* *************************************************** */
+
+/* Problems found in the numbering of one session's takes */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct SequenceFindings {
+	/* Indices that were used (in order, each only listed once) */
+	pub present: Vec<i32>,
+	/* Indices between the first and last ones used that don't appear */
+	pub missing: Vec<i32>,
+	/* Indices used for takes with different names (along with those names, in order of appearance) */
+	pub duplicates: Vec<(i32, Vec<String>)>,
+}
+
+impl SequenceFindings {
+	/* Was everything in order? */
+	pub fn is_empty(&self) -> bool
+	{
+		self.missing.is_empty() && self.duplicates.is_empty()
+	}
+}
+
+/* Find the gaps and duplicates in the numbering of a session's takes
+ * - takes: (index, variant, name) for each take, in any order
+ *
+ * NOTE: Variants of a take (e.g. "01a" and "01b") all count as that index, so they don't leave gaps,
+ *       and can have different names. Only takes sharing both the index and the variant are duplicates
+ *       (unless they have the same name too, e.g. the same take exported in several formats).
+ *       The numbering is only checked from the first index used, not from 1, as earlier takes may
+ *       have been left out on purpose.
+ */
+pub fn find_sequence_problems(takes: &[Take]) -> SequenceFindings
+{
+	/* Distinct names used for each index + variant */
+	let mut names: BTreeMap<(i32, Option<&str>), Vec<&str>> = BTreeMap::new();
+	for &(index, variant, name) in takes.iter() {
+		let entry = names.entry((index, variant)).or_default();
+		if !entry.contains(&name) {
+			entry.push(name);
+		}
+	}
+
+	let mut present: Vec<i32> = names.keys().map(|k| k.0).collect();
+	present.dedup();
+
+	let missing = match (present.first(), present.last()) {
+		(Some(&first), Some(&last)) => (first ..= last).filter(|i| present.binary_search(i).is_err()).collect(),
+		_ => Vec::new()
+	};
+
+	let duplicates = names.iter()
+	                      .filter(|&(_, n)| n.len() > 1)
+	                      .map(|(k, n)| (k.0, n.iter().map(|s| s.to_string()).collect()))
+	                      .collect();
+
+	SequenceFindings {
+		present: present,
+		missing: missing,
+		duplicates: duplicates,
+	}
+}
+
+/* Format a list of indices as "1,2,3" */
+fn join_indices(indices: &[i32]) -> String
+{
+	indices.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/* *************************************************** */
+
+/* Summary of a gap check over a whole playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct GapsReport {
+	/* Number of sessions (date + track type) that were checked */
+	pub sessions: usize,
+	/* Number of tracks skipped as their filenames don't have take numbers */
+	pub unchecked: usize,
+
+	/* Problems found */
+	pub diagnostics: Diagnostics,
+}
+
+/* Run the gap check over all the sessions in the playlist */
+pub fn check_playlist(xspf: &XspfPlaylist) -> GapsReport
+{
+	let mut report = GapsReport {
+		sessions: 0,
+		unchecked: 0,
+		diagnostics: Diagnostics::new(),
+	};
+
+	/* Group the takes by session (sorted by date, then type) */
+	let mut sessions: BTreeMap<(&str, String), Vec<Take>> = BTreeMap::new();
+	for track in xspf.tracks.iter() {
+		if track.info().track_type == TrackType::UnknownType {
+			report.unchecked += 1;
+			continue;
+		}
+		sessions.entry((track.date.as_str(), track.info().track_type.shortname()))
+		        .or_default()
+		        .push((track.info().index, track.info().variant.as_deref(), track.info().name.as_str()));
+	}
+
+	for (&(date, ref track_type), takes) in sessions.iter() {
+		let findings = find_sequence_problems(takes);
+		report.sessions += 1;
+		if findings.is_empty() {
+			continue;
+		}
+
+		if !findings.missing.is_empty() {
+			report.diagnostics.add(Severity::Warning, CATEGORY, None,
+				format!("{0} {1}: have {2} - missing {3}",
+				        date, track_type, join_indices(&findings.present), join_indices(&findings.missing)));
+		}
+		for &(index, ref names) in findings.duplicates.iter() {
+			report.diagnostics.add(Severity::Warning, CATEGORY, None,
+				format!("{0} {1}: take {2} used for several takes - '{3}' (possibly misnumbered)",
+				        date, track_type, index, names.join("', '")));
+		}
+	}
+
+	report
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use xspf_parser::Track;
+
+	#[test]
+	fn test_gaps()
+	{
+		let findings = find_sequence_problems(&[(7, None, "dusk"), (1, None, "tranquil"), (2, None, "moose"),
+		                                        (3, None, "celestial"), (5, None, "winds")]);
+		assert_eq!(vec![1, 2, 3, 5, 7], findings.present);
+		assert_eq!(vec![4, 6], findings.missing);
+		assert!(findings.duplicates.is_empty());
+
+		/* Only counted from the first take used */
+		let findings = find_sequence_problems(&[(3, None, "celestial"), (4, None, "winds")]);
+		assert!(findings.is_empty());
+	}
+
+	#[test]
+	fn test_variants()
+	{
+		/* Variants count as their index, and can have their own names */
+		let findings = find_sequence_problems(&[(1, Some("a"), "outcrop"), (1, Some("b"), "outcrop_again"), (2, None, "dawn"),
+		                                        (3, Some("a"), "dusk"), (4, None, "night")]);
+		assert_eq!(vec![1, 2, 3, 4], findings.present);
+		assert!(findings.is_empty());
+
+		let findings = find_sequence_problems(&[(1, None, "tranquil"), (3, Some("b"), "dusk")]);
+		assert_eq!(vec![2], findings.missing);
+	}
+
+	#[test]
+	fn test_duplicates()
+	{
+		let findings = find_sequence_problems(&[(1, None, "tranquil"), (2, None, "moose"), (2, None, "celestial"),
+		                                        (3, None, "dusk"), (2, None, "moose")]);
+		assert!(findings.missing.is_empty());
+		assert_eq!(vec![(2, vec!["moose".to_string(), "celestial".to_string()])], findings.duplicates);
+
+		/* Same take in several formats isn't a duplicate */
+		let findings = find_sequence_problems(&[(1, None, "tranquil"), (1, None, "tranquil"), (2, None, "moose")]);
+		assert!(findings.is_empty());
+	}
+
+	#[test]
+	fn test_single_take()
+	{
+		assert!(find_sequence_problems(&[(1, None, "tranquil")]).is_empty());
+		assert!(find_sequence_problems(&[(5, Some("c"), "winds")]).is_empty());
+		assert!(find_sequence_problems(&[]).is_empty());
+	}
+
+	#[test]
+	fn test_check_playlist()
+	{
+		let paths = ["music/20170802/v01-tranquil.mp3", "music/20170802/v03-celestial.mp3",
+		             "music/20170802/v01a-tranquil.flac", "music/20170803/v01-dawn.mp3",
+		             "music/20170803/v02-dusk.mp3", "music/20170803/v02-night.mp3",
+		             "music/20170803/notes.txt"];
+		let xspf = XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+
+		let report = check_playlist(&xspf);
+		assert_eq!((2, 1), (report.sessions, report.unchecked));
+		assert_eq!(vec!["20170802 VL: have 1,3 - missing 2",
+		                "20170803 VL: take 2 used for several takes - 'dusk', 'night' (possibly misnumbered)"],
+		           report.diagnostics.entries.iter().map(|d| d.message.as_str()).collect::<Vec<_>>());
+	}
+}
@@ -0,0 +1,583 @@
+/* Estimating the size of the files that convert would produce
+ *
+ * Lossy formats are estimated from the duration of each track and the (nominal) bitrate
+ * they'll be encoded at. FLAC is variable bitrate, so it's estimated from the source size instead.
+ *
+ * Also, how much space the tracks' files currently take up (see XspfPlaylist::disk_usage()), e.g. to check
+ * whether a playlist will fit on a device before copying it there.
+ */
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+
+use ffmpeg_command::AudioQuality;
+use track_name_info::TrackExtension;
+use xspf_parser::XspfPlaylist;
+
+/* Bitrates (in kbit/s) ffmpeg uses when it isn't given any quality settings (see quality_bitrate_kbps() for convert's)
+ * - mp3: libmp3lame defaults to 128k CBR
+ * - ogg: libvorbis defaults to quality 3, which works out at roughly 112k
+ */
+pub const DEFAULT_MP3_BITRATE_KBPS: u32 = 128;
+pub const DEFAULT_OGG_BITRATE_KBPS: u32 = 112;
+
+/* FLAC output is taken to be roughly the same size as the source file
+ * NOTE: This is only a rough guide - lossless sources shrink a little (or stay the same if already FLAC),
+ *       while lossy sources (decoded back to full PCM) can end up several times bigger.
+ */
+pub const FLAC_SOURCE_SIZE_FACTOR: f64 = 1.0;
+
+/* Number of files listed in DiskUsage.largest */
+pub const LARGEST_FILES_COUNT: usize = 10;
+
+/* *************************************************** */
+/* Estimation Math */
+
+/* Nominal bitrate (in kbit/s) used for the given output format, when none is specified
+ * > returns None for formats without a fixed bitrate (i.e. FLAC), or that convert doesn't support
+ */
+pub fn default_bitrate_kbps(format: &TrackExtension) -> Option<u32>
+{
+	match *format {
+		TrackExtension::mp3 => Some(DEFAULT_MP3_BITRATE_KBPS),
+		TrackExtension::ogg => Some(DEFAULT_OGG_BITRATE_KBPS),
+		_                   => None
+	}
+}
+
+/* Typical bitrate (in kbit/s) that convert's quality settings work out at (see ffmpeg_command::encoder_settings())
+ * NOTE: These are variable bitrate, so the actual sizes depend on the material
+ * > returns None for formats without a fixed bitrate (i.e. FLAC), or that convert doesn't support
+ */
+pub fn quality_bitrate_kbps(format: &TrackExtension, quality: AudioQuality) -> Option<u32>
+{
+	match (format, quality) {
+		(&TrackExtension::mp3, AudioQuality::High)   => Some(245),
+		(&TrackExtension::mp3, AudioQuality::Medium) => Some(165),
+		(&TrackExtension::mp3, AudioQuality::Low)    => Some(100),
+		(&TrackExtension::ogg, AudioQuality::High)   => Some(256),
+		(&TrackExtension::ogg, AudioQuality::Medium) => Some(160),
+		(&TrackExtension::ogg, AudioQuality::Low)    => Some(112),
+		_                                            => None
+	}
+}
+
+/* Parse a bitrate as given to ffmpeg (e.g. "192k", "192000", "1.5M") into kbit/s */
+pub fn parse_bitrate_kbps(s: &str) -> Result<u32, String>
+{
+	let s = s.trim();
+	let (number, scale) = match s.chars().last() {
+		Some('k') | Some('K') => (&s[.. s.len() - 1], 1.0),
+		Some('m') | Some('M') => (&s[.. s.len() - 1], 1000.0),
+		_                     => (s, 0.001),
+	};
+
+	match number.parse::<f64>() {
+		Ok(x) if x > 0.0 => Ok((x * scale).round() as u32),
+		_                => Err(format!("Invalid bitrate - '{}'", s))
+	}
+}
+
+/* Estimate the size (in bytes) of a converted track
+ * - format: Output format
+ * - bitrate_kbps: Bitrate to encode at (None = the default for the format)
+ * - duration_ms: Length of the track
+ * - source_bytes: Size of the source file (needed for FLAC)
+ *
+ * > returns None if there isn't enough info to make an estimate
+ */
+pub fn estimate_bytes(format: &TrackExtension, bitrate_kbps: Option<u32>,
+                      duration_ms: Option<i64>, source_bytes: Option<u64>)
+	-> Option<u64>
+{
+	if *format == TrackExtension::flac {
+		return source_bytes.map(|size| (size as f64 * FLAC_SOURCE_SIZE_FACTOR).round() as u64);
+	}
+
+	let kbps = bitrate_kbps.or_else(|| default_bitrate_kbps(format))?;
+	Some(bytes_at_bitrate(kbps, duration_ms?))
+}
+
+/* Size (in bytes) of the given length of audio at the given bitrate */
+pub fn bytes_at_bitrate(bitrate_kbps: u32, duration_ms: i64) -> u64
+{
+	/* kbit/s * ms = bits; / 8 = bytes */
+	bitrate_kbps as u64 * duration_ms.max(0) as u64 / 8
+}
+
+/* *************************************************** */
+/* Playlist Estimate */
+
+/* Estimate for a single track */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct TrackEstimate {
+	/* Index (0-based) of the track in the playlist */
+	pub track_index: usize,
+	pub filename: String,
+
+	/* Duration used for the estimate */
+	pub duration_ms: Option<i64>,
+	/* Was the duration unknown (i.e. the average of the known ones got used instead)? */
+	pub duration_assumed: bool,
+
+	/* Size of the source file (if statting was enabled, and it exists) */
+	pub source_bytes: Option<u64>,
+	/* Estimated size of the output file */
+	pub estimated_bytes: Option<u64>,
+}
+
+/* Estimate for a whole playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct SizeEstimate {
+	pub format: String,
+	/* Bitrate used for the estimate (None for FLAC) */
+	pub bitrate_kbps: Option<u32>,
+
+	pub tracks: Vec<TrackEstimate>,
+
+	/* Total of all the track estimates */
+	pub total_bytes: u64,
+	/* Total size of the source files (only if all of them could be checked) */
+	pub source_total_bytes: Option<u64>,
+
+	/* Number of tracks whose duration was unknown, and were estimated using the average instead */
+	pub uncertain: usize,
+	/* Number of tracks which couldn't be estimated at all (not included in the total) */
+	pub unestimated: usize,
+}
+
+/* Estimate the output sizes for converting the playlist
+ * - stat_files: Check the sizes of the source files (always done for FLAC, as it's needed for the estimate)
+ */
+pub fn estimate_playlist(xspf: &XspfPlaylist, format: &TrackExtension, bitrate_kbps: Option<u32>, stat_files: bool)
+	-> SizeEstimate
+{
+	let stat_files = stat_files || (*format == TrackExtension::flac);
+	let bitrate_kbps = if *format == TrackExtension::flac { None } else { bitrate_kbps.or_else(|| default_bitrate_kbps(format)) };
+
+	/* Average of the known durations, for use when a track's duration is unknown */
+	let known: Vec<i64> = xspf.tracks.iter().filter_map(|t| t.duration.as_ref().map(|d| d.0)).collect();
+	let average_ms = if known.is_empty() { None } else { Some(known.iter().sum::<i64>() / known.len() as i64) };
+
+	let mut estimate = SizeEstimate {
+		format: format.to_string(),
+		bitrate_kbps: bitrate_kbps,
+		tracks: Vec::new(),
+		total_bytes: 0,
+		source_total_bytes: if stat_files { Some(0) } else { None },
+		uncertain: 0,
+		unestimated: 0,
+	};
+
+	for (i, track) in xspf.tracks.iter().enumerate() {
+		let (duration_ms, duration_assumed) = match track.duration {
+			Some(ref d) => (Some(d.0), false),
+			None        => (average_ms, true)
+		};
+
+		let source_bytes = if stat_files { fs::metadata(track.file_path()).ok().map(|m| m.len()) } else { None };
+
+		/* Files already in the output format just get copied (see convert_files_mode()) */
+		let copied = (track.info().extn == *format) && source_bytes.is_some();
+		let estimated_bytes = if copied {
+			source_bytes
+		}
+		else {
+			estimate_bytes(format, bitrate_kbps, duration_ms, source_bytes)
+		};
+
+		match estimated_bytes {
+			Some(size) => {
+				estimate.total_bytes += size;
+
+				/* Only estimates based on the duration are affected by it being unknown */
+				if duration_assumed && !copied && (*format != TrackExtension::flac) {
+					estimate.uncertain += 1;
+				}
+			},
+			None => estimate.unestimated += 1
+		}
+		estimate.source_total_bytes = match (estimate.source_total_bytes, source_bytes) {
+			(Some(total), Some(size)) => Some(total + size),
+			_                         => None
+		};
+
+		estimate.tracks.push(TrackEstimate {
+			track_index: i,
+			filename: track.filename.clone(),
+			duration_ms: duration_ms,
+			duration_assumed: duration_assumed,
+			source_bytes: source_bytes,
+			estimated_bytes: estimated_bytes,
+		});
+	}
+
+	estimate
+}
+
+/* *************************************************** */
+/* Disk Usage */
+
+/* Size of one track's file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct FileSize {
+	/* Index (0-based) of the track in the playlist */
+	pub track_index: usize,
+	pub path: String,
+	pub bytes: u64,
+}
+
+/* Total size of the files with one extension (e.g. all the flac files) */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ExtensionUsage {
+	/* Extension of the files (empty for files without one) */
+	pub extension: String,
+	pub files: usize,
+	pub bytes: u64,
+}
+
+/* A track whose file couldn't be checked (e.g. because it doesn't exist) */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct UnreadableFile {
+	pub track_index: usize,
+	pub path: String,
+	pub reason: String,
+}
+
+/* How much space the tracks' files take up */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct DiskUsage {
+	/* Total size of all the files that could be checked */
+	pub total_bytes: u64,
+	/* Number of files included in the total */
+	pub files: usize,
+
+	/* Totals for each extension (largest first) */
+	pub by_extension: Vec<ExtensionUsage>,
+	/* The largest files (largest first - at most LARGEST_FILES_COUNT of them) */
+	pub largest: Vec<FileSize>,
+
+	/* Tracks whose files couldn't be checked (not included in any of the above) */
+	pub unreadable: Vec<UnreadableFile>,
+}
+
+impl XspfPlaylist {
+	/* How much space the tracks' files take up on disk (as they are now), from the size of each file
+	 * NOTE: Tracks included more than once get counted each time (as copying the playlist copies them each time)
+	 */
+	pub fn disk_usage(&self) -> DiskUsage
+	{
+		let mut sizes: Vec<FileSize> = Vec::new();
+		let mut unreadable: Vec<UnreadableFile> = Vec::new();
+		let mut per_extension: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+
+		for (i, track) in self.tracks.iter().enumerate() {
+			match fs::metadata(track.file_path()) {
+				Ok(ref metadata) if metadata.is_file() => {
+					let totals = per_extension.entry(track.info().extn.to_string()).or_insert((0, 0));
+					totals.0 += 1;
+					totals.1 += metadata.len();
+
+					sizes.push(FileSize {
+						track_index: i,
+						path: track.path.to_string(),
+						bytes: metadata.len(),
+					});
+				},
+				Ok(_) => {
+					unreadable.push(UnreadableFile { track_index: i, path: track.path.to_string(), reason: "not a file".to_string() });
+				},
+				Err(e) => {
+					unreadable.push(UnreadableFile { track_index: i, path: track.path.to_string(), reason: e.to_string() });
+				}
+			}
+		}
+
+		let mut by_extension: Vec<ExtensionUsage> = per_extension.into_iter()
+			.map(|(extension, (files, bytes))| ExtensionUsage { extension: extension, files: files, bytes: bytes })
+			.collect();
+		by_extension.sort_by_key(|x| Reverse(x.bytes));
+
+		let total_bytes: u64 = sizes.iter().map(|f| f.bytes).sum();
+		let files = sizes.len();
+
+		/* NOTE: The sort is stable, so files of the same size stay in playlist order */
+		sizes.sort_by_key(|f| Reverse(f.bytes));
+		sizes.truncate(LARGEST_FILES_COUNT);
+
+		DiskUsage {
+			total_bytes: total_bytes,
+			files: files,
+			by_extension: by_extension,
+			largest: sizes,
+			unreadable: unreadable,
+		}
+	}
+}
+
+/* Bitrate (in kbit/s) assumed for each format when estimating from durations alone (see estimate_converted_size())
+ * - mp3: 320k, the highest there is (so the estimate errs on the side of not fitting)
+ * - ogg: 256k, as for convert's high quality
+ * - flac: Roughly what CD-quality stereo compresses to (though this varies a lot with the material)
+ * > returns None for formats that convert doesn't support
+ */
+pub fn assumed_bitrate_kbps(format: &TrackExtension) -> Option<u32>
+{
+	match *format {
+		TrackExtension::mp3  => Some(320),
+		TrackExtension::ogg  => Some(256),
+		TrackExtension::flac => Some(1000),
+		_                    => None
+	}
+}
+
+/* Rough size of the files that converting the playlist would produce, from the durations alone */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ConvertedSize {
+	pub format: String,
+	/* Bitrate assumed for every track */
+	pub bitrate_kbps: u32,
+
+	/* Estimated total (for the tracks with known durations) */
+	pub total_bytes: u64,
+	/* Number of tracks with unknown durations (not included in the total) */
+	pub unknown_durations: usize,
+}
+
+/* Estimate the size of the converted files from each track's duration, assuming they're all at the same bitrate
+ * (unlike estimate_playlist(), this doesn't need the source files - so it's quick, but rougher)
+ * - bitrate_kbps: Bitrate to assume (None = assumed_bitrate_kbps() for the format)
+ *
+ * > returns None if there's no bitrate to assume for the format
+ */
+pub fn estimate_converted_size(xspf: &XspfPlaylist, format: &TrackExtension, bitrate_kbps: Option<u32>) -> Option<ConvertedSize>
+{
+	let kbps = bitrate_kbps.or_else(|| assumed_bitrate_kbps(format))?;
+
+	let mut estimate = ConvertedSize {
+		format: format.to_string(),
+		bitrate_kbps: kbps,
+		total_bytes: 0,
+		unknown_durations: 0,
+	};
+	for track in xspf.tracks.iter() {
+		match track.duration {
+			Some(ref d) => estimate.total_bytes += bytes_at_bitrate(kbps, d.0),
+			None        => estimate.unknown_durations += 1
+		}
+	}
+	Some(estimate)
+}
+
+/* *************************************************** */
+/* Formatting */
+
+/* Human-readable version of a size in bytes (e.g. "12.3 MB") */
+pub fn format_size(bytes: u64) -> String
+{
+	const UNITS: [&'static str; 4] = ["KB", "MB", "GB", "TB"];
+
+	if bytes < 1024 {
+		return format!("{} B", bytes);
+	}
+
+	let mut size = bytes as f64 / 1024.0;
+	let mut unit = 0;
+	while (size >= 1024.0) && (unit + 1 < UNITS.len()) {
+		size /= 1024.0;
+		unit += 1;
+	}
+	format!("{0:.1} {1}", size, UNITS[unit])
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use diagnostics::Diagnostics;
+	use track_duration::TrackDuration;
+	use test_utils::TempDir;
+	use xspf_parser::Track;
+
+	#[test]
+	fn test_parse_bitrate()
+	{
+		assert_eq!(Ok(192), parse_bitrate_kbps("192k"));
+		assert_eq!(Ok(192), parse_bitrate_kbps("192000"));
+		assert_eq!(Ok(1500), parse_bitrate_kbps("1.5M"));
+		assert!(parse_bitrate_kbps("fast").is_err());
+		assert!(parse_bitrate_kbps("-128k").is_err());
+	}
+
+	#[test]
+	fn test_estimate_bytes()
+	{
+		/* 60 seconds at 128 kbit/s = 960,000 bytes */
+		assert_eq!(Some(960_000), estimate_bytes(&TrackExtension::mp3, None, Some(60_000), None));
+		assert_eq!(Some(1_440_000), estimate_bytes(&TrackExtension::mp3, Some(192), Some(60_000), None));
+		assert_eq!(Some(840_000), estimate_bytes(&TrackExtension::ogg, None, Some(60_000), None));
+
+		/* Unknown duration */
+		assert_eq!(None, estimate_bytes(&TrackExtension::mp3, Some(192), None, Some(5_000_000)));
+
+		/* FLAC - Based on the source size, regardless of bitrate */
+		assert_eq!(Some(5_000_000), estimate_bytes(&TrackExtension::flac, Some(192), Some(60_000), Some(5_000_000)));
+		assert_eq!(None, estimate_bytes(&TrackExtension::flac, None, Some(60_000), None));
+
+		/* Unsupported formats */
+		assert_eq!(None, estimate_bytes(&TrackExtension::mkv, None, Some(60_000), None));
+	}
+
+	#[test]
+	fn test_quality_bitrate()
+	{
+		assert_eq!(Some(165), quality_bitrate_kbps(&TrackExtension::mp3, AudioQuality::Medium));
+		assert_eq!(Some(256), quality_bitrate_kbps(&TrackExtension::ogg, AudioQuality::High));
+		assert_eq!(None, quality_bitrate_kbps(&TrackExtension::flac, AudioQuality::High));
+	}
+
+	/* Tracks with unknown durations use the average, and get counted as uncertain */
+	#[test]
+	fn test_estimate_playlist()
+	{
+		let mut tracks: Vec<Track> = ["/nonexistent/20170403/v01-tranquil.flac",
+		                              "/nonexistent/20170403/v02-celestial.flac",
+		                              "/nonexistent/20170403/v03-moose.flac"]
+		                              .iter().map(|p| Track::from_filepath(p).unwrap()).collect();
+		tracks[0].duration = Some(TrackDuration(60_000));
+		tracks[2].duration = Some(TrackDuration(120_000));
+
+		let xspf = XspfPlaylist {
+			tracks: tracks,
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+
+		let estimate = estimate_playlist(&xspf, &TrackExtension::mp3, None, false);
+		assert_eq!(Some(128), estimate.bitrate_kbps);
+		assert_eq!(Some(90_000), estimate.tracks[1].duration_ms);
+		assert!(estimate.tracks[1].duration_assumed);
+		assert_eq!((1, 0), (estimate.uncertain, estimate.unestimated));
+		assert_eq!(960_000 + 1_440_000 + 1_920_000, estimate.total_bytes);
+		assert_eq!(None, estimate.source_total_bytes);
+
+		/* Files already in the output format get copied, so their real size gets used */
+		let dir = TempDir::new("size-estimate");
+		let path = dir.write_file("20170404/v01-dawn.mp3", &[0u8; 1000]);
+		let xspf_copied = XspfPlaylist {
+			tracks: vec![Track::from_path(&path).unwrap()],
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		let estimate_copied = estimate_playlist(&xspf_copied, &TrackExtension::mp3, None, true);
+		assert_eq!(Some(1000), estimate_copied.tracks[0].estimated_bytes);
+		assert_eq!(Some(1000), estimate_copied.source_total_bytes);
+		assert_eq!((0, 0), (estimate_copied.uncertain, estimate_copied.unestimated));
+
+		/* FLAC needs the source files, which don't exist here */
+		let estimate = estimate_playlist(&xspf, &TrackExtension::flac, Some(192), false);
+		assert_eq!(None, estimate.bitrate_kbps);
+		assert_eq!((0, 3), (estimate.uncertain, estimate.unestimated));
+		assert_eq!(0, estimate.total_bytes);
+	}
+
+	#[test]
+	fn test_disk_usage()
+	{
+		let dir = TempDir::new("disk-usage");
+		let mut paths = vec![
+			dir.write_file("20170403/v01-tranquil.flac", &[0u8; 3000]),
+			dir.write_file("20170403/v02-celestial.mp3", &[0u8; 1000]),
+			dir.write_file("20170404/v01-dawn.flac", &[0u8; 5000]),
+			dir.write_file("20170404/v02-moose.mp3", &[0u8; 1000]),
+		];
+		paths.push(dir.path().join("20170405/v01-missing.mp3"));
+		for i in 0 .. 12 {
+			paths.push(dir.write_file(&format!("20170406/v{:02}-small.ogg", i), &[0u8; 10]));
+		}
+
+		let xspf = XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_path(p).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+		let usage = xspf.disk_usage();
+
+		assert_eq!(10_000 + 12 * 10, usage.total_bytes);
+		assert_eq!(16, usage.files);
+		assert_eq!(vec![("flac", 2, 8000), ("mp3", 2, 2000), ("ogg", 12, 120)],
+		           usage.by_extension.iter().map(|e| (e.extension.as_str(), e.files, e.bytes)).collect::<Vec<_>>());
+
+		/* Only the largest get listed (with ties in playlist order) */
+		assert_eq!(LARGEST_FILES_COUNT, usage.largest.len());
+		assert_eq!(vec![2, 0, 1, 3, 5], usage.largest.iter().take(5).map(|f| f.track_index).collect::<Vec<_>>());
+		assert_eq!(5000, usage.largest[0].bytes);
+
+		assert_eq!(vec![4], usage.unreadable.iter().map(|f| f.track_index).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_estimate_converted_size()
+	{
+		let mut tracks: Vec<Track> = ["/nonexistent/20170403/v01-tranquil.flac",
+		                              "/nonexistent/20170403/v02-celestial.flac",
+		                              "/nonexistent/20170403/v03-moose.flac"]
+		                              .iter().map(|p| Track::from_filepath(p).unwrap()).collect();
+		tracks[0].duration = Some(TrackDuration(60_000));
+		tracks[2].duration = Some(TrackDuration(120_000));
+
+		let xspf = XspfPlaylist {
+			tracks: tracks,
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+
+		/* 3 minutes at 320 kbit/s = 7,200,000 bytes (the track without a duration is left out) */
+		let estimate = estimate_converted_size(&xspf, &TrackExtension::mp3, None).unwrap();
+		assert_eq!((320, 7_200_000, 1), (estimate.bitrate_kbps, estimate.total_bytes, estimate.unknown_durations));
+
+		/* FLAC doesn't need the source files here */
+		let estimate = estimate_converted_size(&xspf, &TrackExtension::flac, None).unwrap();
+		assert_eq!(22_500_000, estimate.total_bytes);
+
+		assert_eq!(1_440_000, estimate_converted_size(&xspf, &TrackExtension::ogg, Some(64)).unwrap().total_bytes);
+		assert!(estimate_converted_size(&xspf, &TrackExtension::mkv, None).is_none());
+	}
+
+	#[test]
+	fn test_format_size()
+	{
+		assert_eq!("512 B", format_size(512));
+		assert_eq!("1.5 KB", format_size(1536));
+		assert_eq!("2.0 MB", format_size(2 * 1024 * 1024));
+	}
+}
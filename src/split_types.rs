@@ -0,0 +1,253 @@
+/* Splitting a playlist into separate playlists for each type of track
+ *
+ * e.g. "all.xspf" -> "all.VL.xspf", "all.MS.xspf", "all.misc.xspf", etc.
+ * Each one only contains the tracks of that type (in their original order).
+ */
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use diagnostics::Diagnostics;
+use playlist_order::type_rank;
+use track_duration::TrackDuration;
+use track_name_info::TrackType;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Tag used in the filenames for tracks of unknown type */
+pub const MISC_TAG: &'static str = "misc";
+
+/* Number of different track types (i.e. number of possible splits) */
+const NUM_TYPES: usize = 5;
+
+/* *************************************************** */
+/* Splitting */
+
+/* Tag identifying the type in the filenames of the split playlists */
+pub fn type_tag(track_type: &TrackType) -> String
+{
+	match *track_type {
+		TrackType::UnknownType => MISC_TAG.to_string(),
+		_                      => track_type.shortname_safe(),
+	}
+}
+
+/* Tracks of a single type, split out of the original playlist */
+#[derive(Debug)]
+pub struct TypeSplit {
+	/* Tag for the type (e.g. "VL", or "misc" for unknown types) */
+	pub tag: String,
+	/* Playlist containing only the tracks of this type */
+	pub playlist: XspfPlaylist,
+}
+
+/* Split the tracks into a playlist per type
+ * - title: Title of the original playlist (each split gets this + its tag)
+ *
+ * > returns the splits in type order (as for playlist_order), skipping types without any tracks
+ */
+pub fn split_by_type(tracks: Vec<Track>, title: Option<&str>) -> Vec<TypeSplit>
+{
+	let mut groups: Vec<(String, Vec<Track>)> = (0 .. NUM_TYPES).map(|_| (String::new(), Vec::new())).collect();
+	for track in tracks.into_iter() {
+		let group = &mut groups[type_rank(&track.info().track_type) as usize];
+		if group.1.is_empty() {
+			group.0 = type_tag(&track.info().track_type);
+		}
+		group.1.push(track);
+	}
+
+	groups.into_iter()
+	      .filter(|&(_, ref tracks)| !tracks.is_empty())
+	      .map(|(tag, tracks)| {
+	          let title = match title {
+	              Some(title) => format!("{0} ({1})", title, tag),
+	              None        => tag.clone(),
+	          };
+	          TypeSplit {
+	              tag: tag,
+	              playlist: XspfPlaylist {
+	                  tracks: tracks,
+	                  title: Some(title),
+	                  creator: None,
+	                  annotation: None,
+	                  date: None,
+	                  extensions: Vec::new(),
+	                  diagnostics: Diagnostics::new(),
+	                  skipped: Vec::new(),
+	              },
+	          }
+	      })
+	      .collect()
+}
+
+/* *************************************************** */
+/* Writing */
+
+/* Problems writing out the split playlists */
+#[derive(Debug)]
+pub enum SplitError {
+	/* Output files that already exist (and weren't allowed to be overwritten) */
+	AlreadyExists(Vec<PathBuf>),
+	IoError(PathBuf, io::Error),
+}
+
+impl fmt::Display for SplitError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			SplitError::AlreadyExists(ref paths) => {
+				let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+				write!(f, "Output files already exist (use --force to overwrite them) - {}", names.join(", "))
+			},
+			SplitError::IoError(ref path, ref e) => write!(f, "Couldn't write {0:?} - {1}", path, e),
+		}
+	}
+}
+
+/* Details of a split playlist that got written */
+#[derive(Debug)]
+pub struct SplitFile {
+	pub path: PathBuf,
+	pub tracks: usize,
+	/* Total duration of the tracks (and number of tracks without a known duration) */
+	pub duration: TrackDuration,
+	pub uncounted: usize,
+}
+
+/* Path of the file that a split gets written to - "<out_dir>/<stem>.<tag>.<ext>" */
+pub fn split_file_path(out_dir: &Path, stem: &str, tag: &str, extension: &str) -> PathBuf
+{
+	out_dir.join(format!("{0}.{1}.{2}", stem, tag, extension))
+}
+
+/* Write each of the splits out as XSPF (plus JSON if "json" is set)
+ * - stem: Name the output files are based on (i.e. the original playlist's filename, minus extension)
+ * - force: Overwrite any existing files (otherwise nothing gets written if any of them exist)
+ *
+ * > returns details of the files written (in the same order as the splits, with the XSPF before the JSON)
+ */
+pub fn write_splits(splits: &[TypeSplit], out_dir: &Path, stem: &str, json: bool, force: bool)
+	-> Result<Vec<SplitFile>, SplitError>
+{
+	let extensions: &[&str] = if json { &["xspf", "json"] } else { &["xspf"] };
+
+	/* Check for collisions first, so that it's all or nothing */
+	if !force {
+		let existing: Vec<PathBuf> = splits.iter()
+		                                   .flat_map(|s| extensions.iter().map(move |ext| split_file_path(out_dir, stem, &s.tag, ext)))
+		                                   .filter(|p| p.exists())
+		                                   .collect();
+		if !existing.is_empty() {
+			return Err(SplitError::AlreadyExists(existing));
+		}
+	}
+
+	let mut written = Vec::new();
+	for split in splits.iter() {
+		let tally = split.playlist.total_duration();
+
+		for ext in extensions.iter() {
+			let path = split_file_path(out_dir, stem, &split.tag, ext);
+			let result = match *ext {
+				"json" => {
+					serde_json::to_string_pretty(&split.playlist)
+						.map_err(io::Error::other)
+						.and_then(|s| fs::write(&path, s))
+				},
+				_ => split.playlist.save_xspf(&path),
+			};
+			if let Err(e) = result {
+				return Err(SplitError::IoError(path, e));
+			}
+
+			written.push(SplitFile {
+				path: path,
+				tracks: split.playlist.len(),
+				duration: tally.duration,
+				uncounted: tally.uncounted,
+			});
+		}
+	}
+	Ok(written)
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+	use xspf_parser::{self, ParseOptions};
+
+	fn make_tracks(paths: &[&str]) -> Vec<Track>
+	{
+		paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect()
+	}
+
+	const FIXTURE: [&'static str; 6] = [
+		"music/20170403/20170403-01-sonata.mp3",
+		"music/20170403/v01-tranquil.mp3",
+		"music/misc/intro.mp3",
+		"music/20170403/v02-celestial.mp3",
+		"music/20170404/20170404-02-etude.mp3",
+		"music/20170405/v03-moose.mp3",
+	];
+
+	#[test]
+	fn test_split_by_type()
+	{
+		let splits = split_by_type(make_tracks(&FIXTURE), Some("Sessions"));
+
+		/* Only the types that are present, in type order */
+		let tags: Vec<&str> = splits.iter().map(|s| s.tag.as_str()).collect();
+		assert_eq!(vec!["VL", "MS", "misc"], tags);
+
+		/* Original order is kept within each */
+		let vl: Vec<&str> = splits[0].playlist.tracks.iter().map(|t| t.filename.as_str()).collect();
+		assert_eq!(vec!["v01-tranquil.mp3", "v02-celestial.mp3", "v03-moose.mp3"], vl);
+		assert_eq!(2, splits[1].playlist.len());
+		assert_eq!("intro.mp3", splits[2].playlist.tracks[0].filename);
+
+		assert_eq!(Some("Sessions (MS)".to_string()), splits[1].playlist.title);
+	}
+
+	#[test]
+	fn test_write_splits()
+	{
+		let dir = TempDir::new("split-types");
+
+		let mut tracks = make_tracks(&FIXTURE);
+		tracks[1].duration = Some(TrackDuration(60_000));
+		tracks[3].duration = Some(TrackDuration(90_000));
+		let splits = split_by_type(tracks, None);
+
+		let written = write_splits(&splits, dir.path(), "all", true, false).unwrap();
+		assert_eq!(6, written.len());
+		assert_eq!(6, fs::read_dir(dir.path()).unwrap().count());
+
+		assert_eq!(dir.path().join("all.VL.xspf"), written[0].path);
+		assert_eq!(dir.path().join("all.VL.json"), written[1].path);
+		assert_eq!((3, 150_000, 1), (written[0].tracks, written[0].duration.0, written[0].uncounted));
+
+		/* Contents can be read back in */
+		let expected = [("VL", 3), ("MS", 2), ("misc", 1)];
+		for &(tag, count) in expected.iter() {
+			let path = dir.path().join(format!("all.{}.xspf", tag));
+			let xspf = xspf_parser::parse_xspf(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+			assert_eq!(count, xspf.len(), "{}", tag);
+		}
+		let misc: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.path().join("all.misc.json")).unwrap()).unwrap();
+		assert_eq!("intro.mp3", misc["tracks"][0]["filename"]);
+
+		/* Existing files need --force */
+		match write_splits(&splits, dir.path(), "all", false, false) {
+			Err(SplitError::AlreadyExists(ref paths)) => assert_eq!(3, paths.len()),
+			ref other => panic!("Expected collision, got {:?}", other),
+		}
+		assert!(write_splits(&splits, dir.path(), "all", false, true).is_ok());
+	}
+}
@@ -0,0 +1,538 @@
+/* Minimal reader/writer for (uncompressed) ustar archives
+ *
+ * Only the parts of the format needed for bundling up a playlist are supported:
+ * regular files and directories, with long names handled via the "prefix" field.
+ * Reference: POSIX.1-1988 "ustar" interchange format.
+ */
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/* Size of header and data blocks */
+pub const BLOCK_SIZE: usize = 512;
+
+/* Largest file that can be stored (the size field is 11 octal digits) */
+pub const MAX_ENTRY_SIZE: u64 = 0o77777777777;
+
+/* Limits on the name fields */
+const NAME_LEN: usize = 100;
+const PREFIX_LEN: usize = 155;
+
+/* *************************************************** */
+/* Errors */
+
+#[derive(Debug)]
+pub enum TarError {
+	/* Problem reading/writing the underlying stream */
+	Io(io::Error),
+	/* Name can't be stored in a ustar header */
+	NameTooLong(String),
+	/* Entry exceeds MAX_ENTRY_SIZE */
+	TooLarge(String, u64),
+	/* Header is corrupt (e.g. checksum mismatch, or not a ustar archive) */
+	BadHeader(String),
+	/* Entry would be extracted outside of the destination (absolute path, or ".." components) */
+	UnsafePath(String),
+	/* Archive ended in the middle of an entry */
+	Truncated,
+}
+
+impl fmt::Display for TarError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			TarError::Io(ref e)              => write!(f, "{}", e),
+			TarError::NameTooLong(ref name)  => write!(f, "Name too long for archive - '{}'", name),
+			TarError::TooLarge(ref name, sz) => write!(f, "'{0}' is too large for archive ({1} bytes, max {2})", name, sz, MAX_ENTRY_SIZE),
+			TarError::BadHeader(ref msg)     => write!(f, "Invalid archive header - {}", msg),
+			TarError::UnsafePath(ref name)   => write!(f, "Refusing to extract entry with unsafe path - '{}'", name),
+			TarError::Truncated              => write!(f, "Archive is truncated"),
+		}
+	}
+}
+
+impl From<io::Error> for TarError {
+	fn from(e: io::Error) -> TarError
+	{
+		TarError::Io(e)
+	}
+}
+
+/* *************************************************** */
+/* Headers */
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum EntryType {
+	File,
+	Directory,
+	/* Anything else (links, devices, etc.) - These get skipped when extracting */
+	Other(u8),
+}
+
+impl EntryType {
+	fn typeflag(&self) -> u8
+	{
+		match *self {
+			EntryType::File      => b'0',
+			EntryType::Directory => b'5',
+			EntryType::Other(c)  => c,
+		}
+	}
+
+	fn from_typeflag(c: u8) -> EntryType
+	{
+		match c {
+			b'0' | 0 => EntryType::File,  /* NUL is used by old archivers for regular files */
+			b'5'     => EntryType::Directory,
+			_        => EntryType::Other(c),
+		}
+	}
+}
+
+/* The details about an entry that get stored in its header */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct TarHeader {
+	/* Path of the entry within the archive (using '/' separators) */
+	pub name: String,
+	/* Unix permissions */
+	pub mode: u32,
+	/* Size of the data (in bytes) */
+	pub size: u64,
+	/* Modification time (seconds since Unix epoch) */
+	pub mtime: u64,
+	pub entry_type: EntryType,
+}
+
+impl TarHeader {
+	/* Header for a regular file */
+	pub fn file(name: &str, size: u64, mtime: u64) -> TarHeader
+	{
+		TarHeader {
+			name: name.to_string(),
+			mode: 0o644,
+			size: size,
+			mtime: mtime,
+			entry_type: EntryType::File,
+		}
+	}
+}
+
+/* Write a number as a zero-padded octal string, followed by a NUL */
+fn encode_octal(field: &mut [u8], value: u64)
+{
+	let digits = field.len() - 1;
+	let s = format!("{:0width$o}", value, width=digits);
+	field[.. digits].copy_from_slice(s.as_bytes());
+	field[digits] = 0;
+}
+
+/* Read an octal number (ignoring leading spaces, and trailing NULs/spaces) */
+fn decode_octal(field: &[u8], field_name: &str) -> Result<u64, TarError>
+{
+	let text: String = field.iter()
+	                        .take_while(|&&c| c != 0)
+	                        .map(|&c| c as char)
+	                        .collect();
+	let text = text.trim();
+	if text.is_empty() {
+		return Ok(0);
+	}
+	u64::from_str_radix(text, 8).map_err(|_| TarError::BadHeader(format!("Invalid {0} field - '{1}'", field_name, text)))
+}
+
+/* Read a NUL-terminated string */
+fn decode_string(field: &[u8]) -> String
+{
+	let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+	String::from_utf8_lossy(&field[.. end]).into_owned()
+}
+
+/* Split a long name into the "prefix" and "name" parts
+ * > returns (prefix, name), where prefix may be empty
+ */
+fn split_long_name(name: &str) -> Result<(&str, &str), TarError>
+{
+	if name.len() <= NAME_LEN {
+		return Ok(("", name));
+	}
+
+	/* Split at the first '/' where both parts fit (i.e. keeping the prefix as short as possible, like other tar tools do) */
+	let bytes = name.as_bytes();
+	for i in 1 .. bytes.len() {
+		if bytes[i] == b'/' && i <= PREFIX_LEN && (bytes.len() - i - 1) <= NAME_LEN {
+			return Ok((&name[.. i], &name[i + 1 ..]));
+		}
+	}
+	Err(TarError::NameTooLong(name.to_string()))
+}
+
+/* Sum of all bytes in the header, with the checksum field itself counted as spaces */
+fn header_checksum(block: &[u8; BLOCK_SIZE]) -> u64
+{
+	block.iter()
+	     .enumerate()
+	     .map(|(i, &c)| if (148 .. 156).contains(&i) { b' ' as u64 } else { c as u64 })
+	     .sum()
+}
+
+/* Encode the header for an entry */
+pub fn encode_header(header: &TarHeader) -> Result<[u8; BLOCK_SIZE], TarError>
+{
+	if header.size > MAX_ENTRY_SIZE {
+		return Err(TarError::TooLarge(header.name.clone(), header.size));
+	}
+	let (prefix, name) = split_long_name(&header.name)?;
+
+	let mut block = [0u8; BLOCK_SIZE];
+	block[0 .. name.len()].copy_from_slice(name.as_bytes());
+	encode_octal(&mut block[100 .. 108], header.mode as u64);
+	encode_octal(&mut block[108 .. 116], 0);  /* uid */
+	encode_octal(&mut block[116 .. 124], 0);  /* gid */
+	encode_octal(&mut block[124 .. 136], header.size);
+	encode_octal(&mut block[136 .. 148], header.mtime);
+	block[156] = header.entry_type.typeflag();
+	block[257 .. 263].copy_from_slice(b"ustar\0");
+	block[263 .. 265].copy_from_slice(b"00");
+	block[345 .. 345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+	/* Checksum - 6 octal digits, then NUL and space */
+	let checksum = header_checksum(&block);
+	encode_octal(&mut block[148 .. 155], checksum);
+	block[155] = b' ';
+
+	Ok(block)
+}
+
+/* Decode the header for an entry
+ * > returns None for an all-zeros block (i.e. the end of the archive)
+ */
+pub fn decode_header(block: &[u8; BLOCK_SIZE]) -> Result<Option<TarHeader>, TarError>
+{
+	if block.iter().all(|&c| c == 0) {
+		return Ok(None);
+	}
+
+	let stored_checksum = decode_octal(&block[148 .. 156], "checksum")?;
+	let actual_checksum = header_checksum(block);
+	if stored_checksum != actual_checksum {
+		return Err(TarError::BadHeader(format!("Checksum mismatch (stored {0:o}, computed {1:o})",
+		                                       stored_checksum, actual_checksum)));
+	}
+
+	if &block[257 .. 262] != b"ustar" {
+		return Err(TarError::BadHeader("Not a ustar archive".to_string()));
+	}
+
+	let name = decode_string(&block[0 .. 100]);
+	let prefix = decode_string(&block[345 .. 500]);
+
+	Ok(Some(TarHeader {
+		name: if prefix.is_empty() { name } else { format!("{0}/{1}", prefix, name) },
+		mode: decode_octal(&block[100 .. 108], "mode")? as u32,
+		size: decode_octal(&block[124 .. 136], "size")?,
+		mtime: decode_octal(&block[136 .. 148], "mtime")?,
+		entry_type: EntryType::from_typeflag(block[156]),
+	}))
+}
+
+/* Number of padding bytes needed after an entry's data to fill up the last block */
+fn padding_for(size: u64) -> usize
+{
+	let remainder = (size % BLOCK_SIZE as u64) as usize;
+	if remainder == 0 { 0 } else { BLOCK_SIZE - remainder }
+}
+
+/* *************************************************** */
+/* Writing */
+
+pub struct TarWriter<W: Write> {
+	out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+	pub fn new(out: W) -> TarWriter<W>
+	{
+		TarWriter { out: out }
+	}
+
+	/* Add an entry with the given contents */
+	pub fn append_data(&mut self, name: &str, data: &[u8], mtime: u64) -> Result<(), TarError>
+	{
+		let header = TarHeader::file(name, data.len() as u64, mtime);
+		self.out.write_all(&encode_header(&header)?)?;
+		self.out.write_all(data)?;
+		self.out.write_all(&[0u8; BLOCK_SIZE][.. padding_for(header.size)])?;
+		Ok(())
+	}
+
+	/* Add an entry with the contents of the given file
+	 * > returns the number of bytes added
+	 */
+	pub fn append_file(&mut self, name: &str, path: &Path) -> Result<u64, TarError>
+	{
+		let file = File::open(path)?;
+		let metadata = file.metadata()?;
+		let mtime = metadata.modified()
+		                    .ok()
+		                    .and_then(|t| t.duration_since(::std::time::UNIX_EPOCH).ok())
+		                    .map(|d| d.as_secs())
+		                    .unwrap_or(0);
+
+		let header = TarHeader::file(name, metadata.len(), mtime);
+		self.out.write_all(&encode_header(&header)?)?;
+
+		/* Copy exactly as many bytes as the header claims (in case the file changes under us) */
+		let copied = io::copy(&mut file.take(header.size), &mut self.out)?;
+		if copied != header.size {
+			return Err(TarError::Truncated);
+		}
+		self.out.write_all(&[0u8; BLOCK_SIZE][.. padding_for(header.size)])?;
+
+		Ok(header.size)
+	}
+
+	/* Write the end-of-archive marker (two zero blocks), returning the underlying stream */
+	pub fn finish(mut self) -> Result<W, TarError>
+	{
+		self.out.write_all(&[0u8; BLOCK_SIZE * 2])?;
+		self.out.flush()?;
+		Ok(self.out)
+	}
+}
+
+/* *************************************************** */
+/* Reading */
+
+/* Check that an entry's name is safe to extract, returning the relative path to extract it to */
+pub fn safe_entry_path(name: &str) -> Result<PathBuf, TarError>
+{
+	let path = Path::new(name);
+	let mut result = PathBuf::new();
+
+	for component in path.components() {
+		match component {
+			Component::Normal(part) => result.push(part),
+			Component::CurDir       => { /* skip */ },
+			_                       => return Err(TarError::UnsafePath(name.to_string())),
+		}
+	}
+
+	if result.as_os_str().is_empty() {
+		return Err(TarError::UnsafePath(name.to_string()));
+	}
+	Ok(result)
+}
+
+/* Read exactly one block, returning false if the stream ended cleanly beforehand */
+fn read_block<R: Read>(input: &mut R, block: &mut [u8; BLOCK_SIZE]) -> Result<bool, TarError>
+{
+	let mut filled = 0;
+	while filled < BLOCK_SIZE {
+		match input.read(&mut block[filled ..])? {
+			0 if filled == 0 => return Ok(false),
+			0                => return Err(TarError::Truncated),
+			n                => filled += n,
+		}
+	}
+	Ok(true)
+}
+
+/* Extract all entries from the archive into out_dir
+ * - progress is called before each entry is extracted, with the (0-based) index and header.
+ * - Entries that aren't files or directories are skipped.
+ * > returns the headers of all entries extracted
+ */
+pub fn extract_all<R: Read>(input: &mut R, out_dir: &Path, progress: &mut dyn FnMut(usize, &TarHeader))
+	-> Result<Vec<TarHeader>, TarError>
+{
+	let mut extracted = Vec::new();
+	let mut block = [0u8; BLOCK_SIZE];
+
+	let mut index = 0;
+	while read_block(input, &mut block)? {
+		let header = match decode_header(&block)? {
+			Some(h) => h,
+			None    => break  /* End of archive */
+		};
+		let dst_path = out_dir.join(safe_entry_path(&header.name)?);
+		progress(index, &header);
+
+		match header.entry_type {
+			EntryType::Directory => {
+				fs::create_dir_all(&dst_path)?;
+			},
+			EntryType::File => {
+				if let Some(parent) = dst_path.parent() {
+					fs::create_dir_all(parent)?;
+				}
+				let mut f = File::create(&dst_path)?;
+				let copied = io::copy(&mut input.take(header.size), &mut f)?;
+				if copied != header.size {
+					return Err(TarError::Truncated);
+				}
+			},
+			EntryType::Other(_) => {
+				/* Skip over data */
+				let skipped = io::copy(&mut input.take(header.size), &mut io::sink())?;
+				if skipped != header.size {
+					return Err(TarError::Truncated);
+				}
+			}
+		}
+
+		/* Skip padding */
+		let padding = padding_for(header.size);
+		if padding > 0 {
+			input.read_exact(&mut block[.. padding]).map_err(|_| TarError::Truncated)?;
+		}
+
+		match header.entry_type {
+			EntryType::Other(_) => { /* skipped */ },
+			_                   => extracted.push(header),
+		}
+		index += 1;
+	}
+
+	Ok(extracted)
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	/* Reference header for "hello.txt" (5 bytes, mode 644, mtime 1500000000),
+	 * as produced by Python's tarfile module (format=USTAR_FORMAT)
+	 */
+	fn reference_header() -> [u8; BLOCK_SIZE]
+	{
+		let mut block = [0u8; BLOCK_SIZE];
+		block[0 .. 9].copy_from_slice(b"hello.txt");
+		block[100 .. 108].copy_from_slice(b"0000644\0");
+		block[108 .. 116].copy_from_slice(b"0000000\0");
+		block[116 .. 124].copy_from_slice(b"0000000\0");
+		block[124 .. 136].copy_from_slice(b"00000000005\0");
+		block[136 .. 148].copy_from_slice(b"13132027400\0");
+		block[148 .. 156].copy_from_slice(b"007633\0 ");
+		block[156] = b'0';
+		block[257 .. 265].copy_from_slice(b"ustar\x0000");
+		block
+	}
+
+	#[test]
+	fn test_encode_header()
+	{
+		let header = TarHeader::file("hello.txt", 5, 1500000000);
+		assert_eq!(&reference_header()[..], &encode_header(&header).unwrap()[..]);
+	}
+
+	#[test]
+	fn test_decode_header()
+	{
+		let header = decode_header(&reference_header()).unwrap().unwrap();
+		assert_eq!(TarHeader::file("hello.txt", 5, 1500000000), header);
+
+		/* End of archive */
+		assert!(decode_header(&[0u8; BLOCK_SIZE]).unwrap().is_none());
+
+		/* Corruption gets noticed */
+		let mut corrupt = reference_header();
+		corrupt[0] = b'j';
+		match decode_header(&corrupt) {
+			Err(TarError::BadHeader(_)) => {},
+			other => panic!("Expected checksum error, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn test_long_names()
+	{
+		/* 134 chars - Needs to be split between the prefix and name fields */
+		let name = format!("music/{0}/{1}/v01-tranquil.mp3", "a".repeat(60), "b".repeat(50));
+		let block = encode_header(&TarHeader::file(&name, 0, 0)).unwrap();
+
+		/* Compare against the split + checksum that Python's tarfile module produces */
+		assert_eq!(format!("music/{}", "a".repeat(60)), decode_string(&block[345 .. 500]));
+		assert_eq!(format!("{}/v01-tranquil.mp3", "b".repeat(50)), decode_string(&block[0 .. 100]));
+		assert_eq!(b"036756\0 ", &block[148 .. 156]);
+
+		assert_eq!(name, decode_header(&block).unwrap().unwrap().name);
+
+		/* No way to split this one */
+		let too_long = "x".repeat(120);
+		match encode_header(&TarHeader::file(&too_long, 0, 0)) {
+			Err(TarError::NameTooLong(_)) => {},
+			other => panic!("Expected name too long error, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn test_safe_entry_path()
+	{
+		assert_eq!(PathBuf::from("a/b.mp3"), safe_entry_path("a/b.mp3").unwrap());
+		assert_eq!(PathBuf::from("a/b.mp3"), safe_entry_path("./a/b.mp3").unwrap());
+
+		assert!(safe_entry_path("/etc/passwd").is_err());
+		assert!(safe_entry_path("../escape.mp3").is_err());
+		assert!(safe_entry_path("a/../../escape.mp3").is_err());
+		assert!(safe_entry_path(".").is_err());
+	}
+
+	#[test]
+	fn test_round_trip()
+	{
+		let src = TempDir::new("tar-round-trip-src");
+		let dst = TempDir::new("tar-round-trip-dst");
+
+		/* Fixtures - Including sizes that are/aren't multiples of the block size */
+		let fixtures: Vec<(String, Vec<u8>)> = vec![
+			("Track_01-20170403-VL01_Tranquil.mp3".to_string(), (0 .. 1500u32).map(|i| (i % 251) as u8).collect()),
+			("Track_02-20170404-P02_Moose.flac".to_string(), vec![0xAB; BLOCK_SIZE * 2]),
+			("empty.txt".to_string(), Vec::new()),
+		];
+
+		let mut writer = TarWriter::new(Vec::new());
+		for &(ref name, ref data) in fixtures.iter() {
+			let path = src.write_file(name, data);
+			assert_eq!(data.len() as u64, writer.append_file(name, &path).unwrap());
+		}
+		writer.append_data("playlist.m3u8", b"#EXTM3U\n\nTrack_01-20170403-VL01_Tranquil.mp3\n", 0).unwrap();
+		let archive = writer.finish().unwrap();
+		assert_eq!(0, archive.len() % BLOCK_SIZE);
+
+		let mut seen = Vec::new();
+		let extracted = extract_all(&mut &archive[..], dst.path(), &mut |i, h| seen.push((i, h.name.clone()))).unwrap();
+		assert_eq!(4, extracted.len());
+		assert_eq!((3, "playlist.m3u8".to_string()), seen[3]);
+
+		for &(ref name, ref data) in fixtures.iter() {
+			assert_eq!(*data, fs::read(dst.path().join(name)).unwrap());
+		}
+		assert_eq!(b"#EXTM3U\n\nTrack_01-20170403-VL01_Tranquil.mp3\n".to_vec(),
+		           fs::read(dst.path().join("playlist.m3u8")).unwrap());
+	}
+
+	#[test]
+	fn test_refuses_unsafe_entries()
+	{
+		let dst = TempDir::new("tar-unsafe");
+
+		let mut writer = TarWriter::new(Vec::new());
+		writer.append_data("../escape.txt", b"gotcha", 0).unwrap();
+		let archive = writer.finish().unwrap();
+
+		match extract_all(&mut &archive[..], dst.path(), &mut |_, _| {}) {
+			Err(TarError::UnsafePath(_)) => {},
+			other => panic!("Expected unsafe path error, got {:?}", other),
+		}
+		assert!(!dst.path().join("../escape.txt").exists());
+	}
+}
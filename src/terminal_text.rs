@@ -0,0 +1,89 @@
+/* Making text from playlists safe to print to a terminal
+ *
+ * Track names, titles, and paths come from the playlist (or the filesystem), so they can contain anything -
+ * including control characters and ANSI escape sequences, which the terminal would act on instead of showing
+ * (e.g. changing colours, moving the cursor to overwrite earlier lines, or ringing the bell). Before such text
+ * gets printed, those characters get replaced by visible escapes (e.g. ESC becomes "\x1b", so a colour code
+ * shows up as "\x1b[31m").
+ *
+ * NOTE: This is only for text meant for the terminal. Outputs written for other programs to read
+ *       (JSON, XSPF, m3u, list) have their own escaping rules, and are left alone.
+ */
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/* Does the character need escaping? (C0 and C1 control characters, and DEL) */
+fn is_unsafe(c: char) -> bool
+{
+	c.is_control()
+}
+
+/* Replace any control characters in the text with visible escapes
+ * - C0 controls (and DEL) become "\xNN" (apart from tabs and newlines, which become "\t", "\n", and "\r")
+ * - C1 controls (U+0080 - U+009F) become "\u{NN}"
+ *
+ * Everything else (including all other non-ASCII characters, e.g. accents and emoji) is left as-is.
+ * > returns the text unchanged (without copying it) if there was nothing to escape
+ */
+pub fn sanitize(s: &str) -> Cow<'_, str>
+{
+	if !s.chars().any(is_unsafe) {
+		return Cow::Borrowed(s);
+	}
+
+	let mut result = String::with_capacity(s.len() + 8);
+	for c in s.chars() {
+		match c {
+			'\t' => result.push_str("\\t"),
+			'\n' => result.push_str("\\n"),
+			'\r' => result.push_str("\\r"),
+			c if (c as u32) < 0x80 && is_unsafe(c) => { let _ = write!(result, "\\x{:02x}", c as u32); },
+			c if is_unsafe(c)                      => { let _ = write!(result, "\\u{{{:x}}}", c as u32); },
+			c                                      => result.push(c),
+		}
+	}
+	Cow::Owned(result)
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sanitize_escape_sequences()
+	{
+		/* Colours, cursor movement, and a whole OSC sequence (setting the window title) */
+		assert_eq!("\\x1b[31mred\\x1b[0m", sanitize("\x1b[31mred\x1b[0m"));
+		assert_eq!("a\\x1b[2Ab", sanitize("a\x1b[2Ab"));
+		assert_eq!("\\x1b]0;pwned\\x07", sanitize("\x1b]0;pwned\x07"));
+
+		/* 8-bit CSI (C1 control) */
+		assert_eq!("\\u{9b}31m", sanitize("\u{9b}31m"));
+	}
+
+	#[test]
+	fn test_sanitize_control_characters()
+	{
+		assert_eq!("ding\\x07", sanitize("ding\x07"));
+		assert_eq!("abc\\x08\\x08xy", sanitize("abc\x08\x08xy"));
+		assert_eq!("two\\nlines\\r", sanitize("two\nlines\r"));
+		assert_eq!("tab\\tbed", sanitize("tab\tbed"));
+		assert_eq!("nul\\x00 del\\x7f", sanitize("nul\0 del\x7f"));
+	}
+
+	#[test]
+	fn test_sanitize_unicode()
+	{
+		/* Legitimate non-ASCII text passes through untouched (and isn't copied) */
+		for s in ["🎻 tranquil", "Rêverie d'été", "月の光", "👩‍👩‍👧 family", ""].iter() {
+			assert_eq!(*s, sanitize(s));
+			assert!(match sanitize(s) { Cow::Borrowed(_) => true, Cow::Owned(_) => false });
+		}
+
+		/* Multi-byte characters right next to escapes stay whole */
+		assert_eq!("🎻\\x1b[1m🎻\\u{85}é", sanitize("🎻\x1b[1m🎻\u{85}é"));
+	}
+}
@@ -0,0 +1,282 @@
+/* Fitting text output (i.e. tables) to the width of the terminal
+ *
+ * The width comes from (in order): the "--width=<N>" option, the XSPF_TOOLS_WIDTH environment variable,
+ * or the size of the terminal that stdout is connected to. When stdout isn't a terminal (e.g. piped
+ * into another program or a file), a fixed width is used instead, so the output doesn't depend on
+ * whichever terminal the command happened to be run from.
+ */
+use std::env;
+
+/* Environment variable overriding the detected width */
+pub const WIDTH_ENV_VAR: &'static str = "XSPF_TOOLS_WIDTH";
+
+/* Width used when stdout isn't a terminal */
+pub const PIPED_WIDTH: usize = 120;
+
+/* Narrowest width that output gets fitted to (anything narrower just wraps) */
+pub const MIN_WIDTH: usize = 40;
+
+/* *************************************************** */
+/* Detection */
+
+/* Width of the terminal that stdout is connected to (None if it isn't a terminal) */
+#[cfg(unix)]
+fn stdout_terminal_width() -> Option<usize>
+{
+	use std::os::raw::{c_int, c_ulong, c_ushort};
+
+	#[repr(C)]
+	struct WinSize {
+		ws_row: c_ushort,
+		ws_col: c_ushort,
+		ws_xpixel: c_ushort,
+		ws_ypixel: c_ushort,
+	}
+
+	#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+	const TIOCGWINSZ: c_ulong = 0x4008_7468;
+	#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd")))]
+	const TIOCGWINSZ: c_ulong = 0x5413;
+
+	extern "C" {
+		fn isatty(fd: c_int) -> c_int;
+		fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+	}
+
+	let mut size = WinSize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+
+	/* Safe: The struct is valid for the duration of the call, and only gets written to by it */
+	let ok = unsafe { isatty(1) == 1 && ioctl(1, TIOCGWINSZ, &mut size as *mut WinSize) == 0 };
+	if ok && size.ws_col > 0 { Some(size.ws_col as usize) } else { None }
+}
+
+/* Width of the console window that stdout is connected to (None if it isn't a console) */
+#[cfg(windows)]
+fn stdout_terminal_width() -> Option<usize>
+{
+	use std::os::raw::{c_int, c_short, c_ushort, c_void};
+
+	#[repr(C)]
+	struct Coord {
+		x: c_short,
+		y: c_short,
+	}
+
+	#[repr(C)]
+	struct SmallRect {
+		left: c_short,
+		top: c_short,
+		right: c_short,
+		bottom: c_short,
+	}
+
+	#[repr(C)]
+	struct ConsoleScreenBufferInfo {
+		size: Coord,
+		cursor_position: Coord,
+		attributes: c_ushort,
+		window: SmallRect,
+		maximum_window_size: Coord,
+	}
+
+	const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+
+	extern "system" {
+		fn GetStdHandle(std_handle: u32) -> *mut c_void;
+		fn GetConsoleScreenBufferInfo(console: *mut c_void, info: *mut ConsoleScreenBufferInfo) -> c_int;
+	}
+
+	let mut info = ConsoleScreenBufferInfo {
+		size: Coord { x: 0, y: 0 },
+		cursor_position: Coord { x: 0, y: 0 },
+		attributes: 0,
+		window: SmallRect { left: 0, top: 0, right: 0, bottom: 0 },
+		maximum_window_size: Coord { x: 0, y: 0 },
+	};
+
+	/* Safe: The struct is valid for the duration of the call (which fails for anything that isn't a console) */
+	let ok = unsafe { GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) != 0 };
+	let width = info.window.right as i64 - info.window.left as i64 + 1;
+	if ok && width > 0 { Some(width as usize) } else { None }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stdout_terminal_width() -> Option<usize>
+{
+	None
+}
+
+/* Work out the width to use
+ * - flag: Value given for "--width"
+ * - env_value: Value of the XSPF_TOOLS_WIDTH environment variable
+ * - terminal: Width of the terminal (None if stdout isn't a terminal)
+ *
+ * NOTE: Invalid values for the environment variable are ignored (as it may have been set for
+ *       some other run), while an invalid "--width" is an error.
+ */
+pub fn resolve_width(flag: Option<&str>, env_value: Option<&str>, terminal: Option<usize>) -> Result<usize, String>
+{
+	let width = match flag {
+		Some(value) => match value.trim().parse::<usize>() {
+			Ok(width) if width > 0 => width,
+			_ => return Err(format!("Invalid value for --width - '{}' (expected a number of columns)", value))
+		},
+		None => env_value.and_then(|v| v.trim().parse::<usize>().ok())
+		                 .filter(|&w| w > 0)
+		                 .or(terminal)
+		                 .unwrap_or(PIPED_WIDTH)
+	};
+	Ok(width.max(MIN_WIDTH))
+}
+
+/* Width to fit output to, given the value of "--width" (if any) */
+pub fn output_width(flag: Option<&str>) -> Result<usize, String>
+{
+	let env_value = env::var(WIDTH_ENV_VAR).ok();
+	resolve_width(flag, env_value.as_deref(), stdout_terminal_width())
+}
+
+/* *************************************************** */
+/* Columns */
+
+/* Constraints on the width of a column in a table */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub struct Column {
+	/* Narrowest the column can be made */
+	pub min: usize,
+	/* Width needed to show everything in the column */
+	pub preferred: usize,
+	/* Order that flexible columns get narrowed in when there isn't enough room (lowest first) */
+	pub shrink_rank: usize,
+}
+
+impl Column {
+	/* Column that's always the same width (e.g. for numbers and dates) */
+	pub fn fixed(width: usize) -> Column
+	{
+		Column { min: width, preferred: width, shrink_rank: 0 }
+	}
+
+	/* Column that can be narrowed (down to "min") when there isn't enough room (e.g. for names and paths) */
+	pub fn flexible(min: usize, preferred: usize, shrink_rank: usize) -> Column
+	{
+		Column { min: min, preferred: preferred.max(min), shrink_rank: shrink_rank }
+	}
+}
+
+/* Share out the available width between the columns of a table
+ * - total: Width available for the table
+ * - spacing: Width of the gap between each pair of columns
+ *
+ * Every column gets its preferred width if there's room for it. Otherwise, the flexible columns get narrowed
+ * (in order of their shrink ranks), each down to its minimum before the next one gets touched. If even that
+ * isn't enough, everything stays at its minimum width (and the lines end up longer than "total").
+ *
+ * > returns the width for each column
+ */
+pub fn allocate_widths(columns: &[Column], total: usize, spacing: usize) -> Vec<usize>
+{
+	let mut widths: Vec<usize> = columns.iter().map(|c| c.preferred).collect();
+	let needed = widths.iter().sum::<usize>() + spacing * columns.len().saturating_sub(1);
+	let mut excess = needed.saturating_sub(total);
+
+	let mut order: Vec<usize> = (0 .. columns.len()).collect();
+	order.sort_by_key(|&i| columns[i].shrink_rank);
+
+	for i in order {
+		if excess == 0 {
+			break;
+		}
+		let shrink = excess.min(widths[i] - columns[i].min);
+		widths[i] -= shrink;
+		excess -= shrink;
+	}
+	widths
+}
+
+/* Shorten text to at most "width" characters, ending with "..." if anything had to be cut off */
+pub fn truncate_end(s: &str, width: usize) -> String
+{
+	const ELLIPSIS: &'static str = "...";
+
+	if s.chars().count() <= width {
+		s.to_string()
+	}
+	else if width <= ELLIPSIS.len() {
+		s.chars().take(width).collect()
+	}
+	else {
+		let kept: String = s.chars().take(width - ELLIPSIS.len()).collect();
+		kept + ELLIPSIS
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/* Typical table - "#", name, path, date (with names narrowed before paths) */
+	fn columns() -> Vec<Column>
+	{
+		vec![Column::fixed(3),
+		     Column::flexible(10, 50, 0),
+		     Column::flexible(20, 70, 1),
+		     Column::fixed(10)]
+	}
+
+	#[test]
+	fn test_allocate_widths_wide()
+	{
+		/* Plenty of room - everything at its preferred width (without being stretched) */
+		assert_eq!(vec![3, 50, 70, 10], allocate_widths(&columns(), 200, 2));
+	}
+
+	#[test]
+	fn test_allocate_widths_medium()
+	{
+		/* 139 needed, 100 available - only the name column gets narrowed */
+		let widths = allocate_widths(&columns(), 100, 2);
+		assert_eq!(vec![3, 11, 70, 10], widths);
+		assert_eq!(100, widths.iter().sum::<usize>() + 2 * 3);
+	}
+
+	#[test]
+	fn test_allocate_widths_narrow()
+	{
+		/* Name column at its minimum, and the path column takes up the rest of the shortfall */
+		let widths = allocate_widths(&columns(), 60, 2);
+		assert_eq!(vec![3, 10, 31, 10], widths);
+		assert_eq!(60, widths.iter().sum::<usize>() + 2 * 3);
+
+		/* Not enough room even at the minimums */
+		assert_eq!(vec![3, 10, 20, 10], allocate_widths(&columns(), 30, 2));
+	}
+
+	#[test]
+	fn test_resolve_width()
+	{
+		/* Flag > environment > terminal > fixed width for piped output */
+		assert_eq!(Ok(100), resolve_width(Some("100"), Some("90"), Some(80)));
+		assert_eq!(Ok(90), resolve_width(None, Some("90"), Some(80)));
+		assert_eq!(Ok(80), resolve_width(None, Some("wide"), Some(80)));
+		assert_eq!(Ok(PIPED_WIDTH), resolve_width(None, None, None));
+
+		/* Never narrower than the minimum */
+		assert_eq!(Ok(MIN_WIDTH), resolve_width(Some("10"), None, None));
+		assert!(resolve_width(Some("0"), None, None).is_err());
+		assert!(resolve_width(Some("wide"), None, None).is_err());
+	}
+
+	#[test]
+	fn test_truncate_end()
+	{
+		assert_eq!("tranquil", truncate_end("tranquil", 8));
+		assert_eq!("tran...", truncate_end("tranquil", 7));
+		assert_eq!("tr", truncate_end("tranquil", 2));
+		assert_eq!("Rêve...", truncate_end("Rêverie d'été", 7));
+	}
+}
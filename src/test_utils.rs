@@ -0,0 +1,50 @@
+/* Shared helpers for unit tests */
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static TEMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/* Temporary directory that gets removed again when dropped */
+pub struct TempDir {
+	path: PathBuf,
+}
+
+impl TempDir {
+	/* Create a new empty directory, unique to this test */
+	pub fn new(name: &str) -> TempDir
+	{
+		let count = TEMP_DIR_COUNTER.fetch_add(1, Ordering::SeqCst);
+		let path = env::temp_dir().join(format!("xspf_tools-test-{0}-{1}-{2}", process::id(), count, name));
+
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).expect("Couldn't create temp dir for test");
+
+		TempDir { path: path }
+	}
+
+	pub fn path(&self) -> &Path
+	{
+		&self.path
+	}
+
+	/* Create a file (and any parent dirs) within the temp dir, returning its full path */
+	pub fn write_file(&self, relative_path: &str, contents: &[u8]) -> PathBuf
+	{
+		let path = self.path.join(relative_path);
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent).expect("Couldn't create dir for test file");
+		}
+		fs::write(&path, contents).expect("Couldn't write test file");
+		path
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self)
+	{
+		let _ = fs::remove_dir_all(&self.path);
+	}
+}
@@ -0,0 +1,154 @@
+/* Numbering the titles of tracks (e.g. "01. tranquil", "02. moose")
+ *
+ * Some players (e.g. car head units) ignore the playlist order, and just sort by title instead.
+ * Prefixing the titles with their position keeps them in order there too.
+ */
+extern crate regex;
+use self::regex::Regex;
+
+use xspf_parser::Track;
+
+/* *************************************************** */
+/* Prefixes */
+
+/* Remove any number prefixes (e.g. "01. ") from the start of a title
+ * (Repeated prefixes all get removed, e.g. from titles numbered by other tools too)
+ */
+pub fn strip_number_prefix(title: &str) -> &str
+{
+	lazy_static! {
+		static ref RE_NUMBER_PREFIX : Regex = Regex::new(r"^(?:\d+\.\s+)+").unwrap();
+	}
+	match RE_NUMBER_PREFIX.find(title) {
+		Some(m) => &title[m.end() ..],
+		None    => title
+	}
+}
+
+/* Title for the track at the given position ("NN. <title>")
+ * - existing: The track's current title (which only gets replaced if "replace" is set)
+ * - pretty_name: Name to use when there's no title (or it's being replaced)
+ * - width: Number of digits to pad the number to
+ *
+ * NOTE: Any existing number prefix gets replaced, so numbering again doesn't result in "01. 01. name"
+ */
+pub fn numbered_title(existing: Option<&str>, pretty_name: &str, number: usize, width: usize, replace: bool) -> String
+{
+	let base = match existing.map(strip_number_prefix) {
+		Some(title) if !replace && !title.is_empty() => title,
+		_                                            => strip_number_prefix(pretty_name)
+	};
+	format!("{0:01$}. {2}", number, width, base)
+}
+
+/* *************************************************** */
+/* Playlists */
+
+/* Number of digits needed for the track numbers
+ * (as for XspfPlaylist::track_index_width(), but allowing for starting further along)
+ */
+pub fn number_width(num_tracks: usize, start_index: usize, min_width: usize) -> usize
+{
+	let last = (start_index + num_tracks).saturating_sub(1);
+	min_width.max(last.to_string().len())
+}
+
+/* Number the titles of all the tracks, starting from "start_index" */
+pub fn number_titles(tracks: &mut [Track], start_index: usize, width: usize, replace: bool)
+{
+	for (i, track) in tracks.iter_mut().enumerate() {
+		let title = numbered_title(track.title.as_deref(), &track.info().name,
+		                           start_index + i, width, replace);
+		track.title = Some(title);
+	}
+}
+
+/* Remove the number prefixes from the titles of all the tracks
+ * > returns the number of titles changed
+ */
+pub fn strip_titles(tracks: &mut [Track]) -> usize
+{
+	let mut changed = 0;
+	for track in tracks.iter_mut() {
+		let stripped = match track.title {
+			Some(ref title) if strip_number_prefix(title) != title => Some(strip_number_prefix(title).to_string()),
+			_                                                      => None
+		};
+		if let Some(stripped) = stripped {
+			track.title = if stripped.is_empty() { None } else { Some(stripped) };
+			changed += 1;
+		}
+	}
+	changed
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_strip_number_prefix()
+	{
+		assert_eq!("tranquil", strip_number_prefix("01. tranquil"));
+		assert_eq!("tranquil", strip_number_prefix("01. 01. tranquil"));
+		assert_eq!("tranquil", strip_number_prefix("123.\ttranquil"));
+
+		/* Not number prefixes */
+		assert_eq!("1.5 hours", strip_number_prefix("1.5 hours"));
+		assert_eq!("Op. 27 No. 2", strip_number_prefix("Op. 27 No. 2"));
+		assert_eq!("01 tranquil", strip_number_prefix("01 tranquil"));
+		assert_eq!("", strip_number_prefix(""));
+	}
+
+	#[test]
+	fn test_numbered_title()
+	{
+		assert_eq!("01. tranquil", numbered_title(None, "tranquil", 1, 2, false));
+		assert_eq!("007. tranquil", numbered_title(None, "tranquil", 7, 3, false));
+
+		/* Existing titles get the prefix prepended, unless replaced */
+		assert_eq!("03. Moonlight Sonata", numbered_title(Some("Moonlight Sonata"), "sonata", 3, 2, false));
+		assert_eq!("03. sonata", numbered_title(Some("Moonlight Sonata"), "sonata", 3, 2, true));
+
+		/* Already numbered - renumbered rather than numbered twice */
+		assert_eq!("01. tranquil", numbered_title(Some("01. tranquil"), "tranquil", 1, 2, false));
+		assert_eq!("05. Moonlight Sonata", numbered_title(Some("02. Moonlight Sonata"), "sonata", 5, 2, false));
+
+		/* Titles that were only a number */
+		assert_eq!("01. tranquil", numbered_title(Some("01. "), "tranquil", 1, 2, false));
+	}
+
+	#[test]
+	fn test_number_titles()
+	{
+		let mut tracks: Vec<Track> = ["music/20170403/v01-tranquil.mp3", "music/20170403/v02-moose.mp3"]
+			.iter().map(|p| Track::from_filepath(p).unwrap()).collect();
+		tracks[1].title = Some("The Moose".to_string());
+
+		number_titles(&mut tracks, 9, number_width(2, 9, 2), false);
+		let titles: Vec<Option<String>> = tracks.iter().map(|t| t.title.clone()).collect();
+		assert_eq!(vec![Some("09. tranquil".to_string()), Some("10. The Moose".to_string())], titles);
+
+		/* Applying it twice doesn't change anything */
+		number_titles(&mut tracks, 9, 2, false);
+		assert_eq!(titles, tracks.iter().map(|t| t.title.clone()).collect::<Vec<_>>());
+
+		/* Stripping gets back the titles (with ones that were made up still there) */
+		assert_eq!(2, strip_titles(&mut tracks));
+		assert_eq!(Some("The Moose".to_string()), tracks[1].title);
+		assert_eq!(Some("tranquil".to_string()), tracks[0].title);
+		assert_eq!(0, strip_titles(&mut tracks));
+	}
+
+	#[test]
+	fn test_number_width()
+	{
+		assert_eq!(2, number_width(12, 1, 2));
+		assert_eq!(3, number_width(12, 95, 2));
+		assert_eq!(3, number_width(100, 1, 3));
+		assert_eq!(2, number_width(0, 1, 2));
+	}
+}
@@ -0,0 +1,565 @@
+/* Types and Utilities for working with the session dates
+ * that tracks are filed under (i.e. their parent directory names)
+ */
+use std::fmt;
+
+/* *************************************************** */
+/* Session Date */
+
+/* Calendar date of a recording session */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct SessionDate {
+	pub year: i32,
+	pub month: u32,
+	pub day: u32,
+}
+
+impl SessionDate {
+	/* Construct a date, returning None if the day doesn't exist in that month */
+	pub fn new(year: i32, month: u32, day: u32) -> Option<SessionDate>
+	{
+		if !(1 ..= 12).contains(&month) || !(1 ..= days_in_month(year, month)).contains(&day) {
+			None
+		}
+		else {
+			Some(SessionDate { year: year, month: month, day: day })
+		}
+	}
+
+	/* Parse a directory name of the form "YYYYMMDD" or "YYYY-MM-DD" */
+	pub fn parse(s: &str) -> Option<SessionDate>
+	{
+		let digits: String = match s.len() {
+			8  => s.to_string(),
+			10 => {
+				let bytes = s.as_bytes();
+				if bytes[4] != b'-' || bytes[7] != b'-' {
+					return None;
+				}
+				format!("{}{}{}", &s[0..4], &s[5..7], &s[8..10])
+			},
+			_  => return None
+		};
+
+		if !digits.chars().all(|c| c.is_ascii_digit()) {
+			return None;
+		}
+
+		let year  = digits[0..4].parse::<i32>().ok()?;
+		let month = digits[4..6].parse::<u32>().ok()?;
+		let day   = digits[6..8].parse::<u32>().ok()?;
+
+		SessionDate::new(year, month, day)
+	}
+
+	/* Compact "YYYYMMDD" form (as used for directory names) */
+	pub fn normalised(&self) -> String
+	{
+		format!("{0:04}{1:02}{2:02}", self.year, self.month, self.day)
+	}
+
+	/* Number of days between 1970-01-01 and this date
+	 * (Uses the "days from civil" algorithm - see http://howardhinnant.github.io/date_algorithms.html)
+	 */
+	pub fn days_since_epoch(&self) -> i64
+	{
+		let y = if self.month <= 2 { self.year as i64 - 1 } else { self.year as i64 };
+		let era = if y >= 0 { y } else { y - 399 } / 400;
+		let yoe = y - era * 400;
+		let mp = (self.month as i64 + 9) % 12;  /* March = 0 */
+		let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+		let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+		era * 146097 + doe - 719468
+	}
+
+	/* Date that's the given number of days after 1970-01-01 (i.e. the reverse of days_since_epoch())
+	 * (Uses the "civil from days" algorithm - see http://howardhinnant.github.io/date_algorithms.html)
+	 */
+	pub fn from_days_since_epoch(days: i64) -> SessionDate
+	{
+		let z = days + 719468;
+		let era = if z >= 0 { z } else { z - 146096 } / 146097;
+		let doe = z - era * 146097;
+		let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+		let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+		let mp = (5 * doy + 2) / 153;  /* March = 0 */
+		let day = doy - (153 * mp + 2) / 5 + 1;
+		let month = if mp < 10 { mp + 3 } else { mp - 9 };
+		let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+		SessionDate { year: year as i32, month: month as u32, day: day as u32 }
+	}
+
+	/* Day of the week, counting from Monday = 0 to Sunday = 6 (1970-01-01 was a Thursday) */
+	pub fn weekday(&self) -> u32
+	{
+		(self.days_since_epoch() + 3).rem_euclid(7) as u32
+	}
+
+	/* Day of the year (1st January = 1) */
+	pub fn ordinal(&self) -> u32
+	{
+		let jan1 = SessionDate { year: self.year, month: 1, day: 1 };
+		(self.days_since_epoch() - jan1.days_since_epoch()) as u32 + 1
+	}
+
+	/* ISO-8601 week that this date falls in
+	 * Weeks start on Monday, and belong to the year that their Thursday is in. So the first
+	 * few days of January may be in week 52/53 of the previous year, and the last few days of
+	 * December may be in week 1 of the next year.
+	 */
+	pub fn iso_week(&self) -> WeekLabel
+	{
+		let days = self.days_since_epoch();
+		let thursday = SessionDate::from_days_since_epoch(days - self.weekday() as i64 + 3);
+		WeekLabel { year: thursday.year, week: (thursday.ordinal() - 1) / 7 + 1 }
+	}
+
+	/* Simple (non-ISO) week that this date falls in
+	 * Weeks start on the given day, with week 1 being the one containing 1st January
+	 * (so it may be a partial week), and every date stays in its own calendar year
+	 * (so there may be a week 53 or 54 at the end of the year).
+	 */
+	pub fn simple_week(&self, start: WeekStart) -> WeekLabel
+	{
+		let jan1 = SessionDate { year: self.year, month: 1, day: 1 };
+		let offset = (jan1.weekday() + 7 - start.weekday()) % 7;  /* days of week 1 before 1st January */
+		WeekLabel { year: self.year, week: (self.ordinal() - 1 + offset) / 7 + 1 }
+	}
+
+	/* Week that this date falls in, using the given week numbering scheme */
+	pub fn week(&self, scheme: WeekScheme) -> WeekLabel
+	{
+		match scheme {
+			WeekScheme::Iso           => self.iso_week(),
+			WeekScheme::Simple(start) => self.simple_week(start),
+		}
+	}
+}
+
+impl fmt::Display for SessionDate {
+	/* ISO-style "YYYY-MM-DD" */
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{0:04}-{1:02}-{2:02}", self.year, self.month, self.day)
+	}
+}
+
+/* Helper for SessionDate::new() */
+fn days_in_month(year: i32, month: u32) -> u32
+{
+	match month {
+		1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+		4 | 6 | 9 | 11              => 30,
+		2 => {
+			let leap = (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0);
+			if leap { 29 } else { 28 }
+		},
+		_ => 0
+	}
+}
+
+/* *************************************************** */
+/* Track Date */
+
+/* Session date that a track is filed under (i.e. its parent directory), if that is a date
+ * Directories named "YYYYMMDD", "YYYY-MM-DD", or "YYYY-MM-DD <description>" have dates.
+ * Anything else (e.g. "misc") doesn't, though the date may still be found elsewhere (see with_fallback()).
+ */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum TrackDate {
+	/* Date that could be worked out - normalised as "YYYYMMDD", along with the directory name it was filed under */
+	Known { date: String, raw: String },
+	/* Directory name that isn't a date */
+	Unknown { raw: String },
+}
+
+impl TrackDate {
+	/* Work out the date from a directory name */
+	pub fn parse(raw: &str) -> TrackDate
+	{
+		/* Anything after the date (e.g. "2017-08-02 Concert") is just a description */
+		let head = raw.trim().split(|c: char| c.is_whitespace() || c == '_').next().unwrap_or("");
+		match SessionDate::parse(head) {
+			Some(date) => TrackDate::Known { date: date.normalised(), raw: raw.to_string() },
+			None       => TrackDate::Unknown { raw: raw.to_string() }
+		}
+	}
+
+	/* Use the date from somewhere else (e.g. embedded in the filename - see track_name_info::embedded_date()),
+	 * if the directory name didn't have one
+	 */
+	pub fn with_fallback(self, fallback: Option<&str>) -> TrackDate
+	{
+		match (self, fallback.and_then(SessionDate::parse)) {
+			(TrackDate::Unknown { raw }, Some(date)) => TrackDate::Known { date: date.normalised(), raw: raw },
+			(date, _)                                => date
+		}
+	}
+
+	pub fn is_known(&self) -> bool
+	{
+		match *self {
+			TrackDate::Known { .. }   => true,
+			TrackDate::Unknown { .. } => false,
+		}
+	}
+
+	/* Normalised "YYYYMMDD" form - None if unknown */
+	pub fn normalised(&self) -> Option<&str>
+	{
+		match *self {
+			TrackDate::Known { ref date, .. } => Some(date.as_str()),
+			TrackDate::Unknown { .. }         => None,
+		}
+	}
+
+	/* Name of the directory the track was filed under */
+	pub fn raw(&self) -> &str
+	{
+		match *self {
+			TrackDate::Known { ref raw, .. } | TrackDate::Unknown { ref raw } => raw.as_str(),
+		}
+	}
+
+	/* Normalised form if known, otherwise the directory name (e.g. for grouping tracks by session) */
+	pub fn as_str(&self) -> &str
+	{
+		self.normalised().unwrap_or_else(|| self.raw())
+	}
+
+	/* Calendar date - None if unknown */
+	pub fn session_date(&self) -> Option<SessionDate>
+	{
+		self.normalised().and_then(SessionDate::parse)
+	}
+}
+
+impl fmt::Display for TrackDate {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.as_str())
+	}
+}
+
+/* Saved as the normalised form if known (otherwise the directory name)
+ * NOTE: The description part of the directory name doesn't get saved (it isn't needed after parsing)
+ */
+impl ::serde::Serialize for TrackDate {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where S: ::serde::Serializer
+	{
+		serializer.serialize_str(self.as_str())
+	}
+}
+
+impl<'de> ::serde::Deserialize<'de> for TrackDate {
+	fn deserialize<D>(deserializer: D) -> Result<TrackDate, D::Error>
+		where D: ::serde::Deserializer<'de>
+	{
+		let raw: String = ::serde::Deserialize::deserialize(deserializer)?;
+		Ok(TrackDate::parse(&raw))
+	}
+}
+
+/* *************************************************** */
+/* Weeks */
+
+/* A numbered week within a year, e.g. "2017-W31" */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct WeekLabel {
+	pub year: i32,
+	pub week: u32,
+}
+
+impl fmt::Display for WeekLabel {
+	/* ISO-style "YYYY-Www" */
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{0:04}-W{1:02}", self.year, self.week)
+	}
+}
+
+/* Day that simple (non-ISO) weeks start on */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum WeekStart {
+	Monday,
+	Sunday,
+}
+
+impl WeekStart {
+	/* Parse a "--week-start" value */
+	pub fn parse(s: &str) -> Option<WeekStart>
+	{
+		match s.to_lowercase().as_str() {
+			"monday" | "mon" => Some(WeekStart::Monday),
+			"sunday" | "sun" => Some(WeekStart::Sunday),
+			_                => None
+		}
+	}
+
+	/* Day of the week this is (see SessionDate::weekday()) */
+	fn weekday(&self) -> u32
+	{
+		match *self {
+			WeekStart::Monday => 0,
+			WeekStart::Sunday => 6,
+		}
+	}
+}
+
+/* How weeks get numbered
+ * NOTE: These only depend on the session date itself (never on the system locale),
+ *       so the same tracks always end up in the same weeks everywhere.
+ */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum WeekScheme {
+	/* ISO-8601 weeks (see SessionDate::iso_week()) */
+	Iso,
+	/* Simple calendar-year weeks starting on the given day (see SessionDate::simple_week()) */
+	Simple(WeekStart),
+}
+
+/* *************************************************** */
+/* Local Time */
+
+/* Offset (in seconds) of the local timezone from UTC at the given unix timestamp.
+ * This is needed to figure out when "midnight" happened for a session date.
+ *
+ * NOTE: Falls back to UTC on platforms where we don't know how to query this.
+ */
+#[cfg(unix)]
+pub fn local_utc_offset_secs(timestamp: i64) -> i64
+{
+	use std::os::raw::{c_char, c_int, c_long};
+
+	#[repr(C)]
+	struct Tm {
+		tm_sec: c_int,
+		tm_min: c_int,
+		tm_hour: c_int,
+		tm_mday: c_int,
+		tm_mon: c_int,
+		tm_year: c_int,
+		tm_wday: c_int,
+		tm_yday: c_int,
+		tm_isdst: c_int,
+		tm_gmtoff: c_long,
+		tm_zone: *const c_char,
+	}
+
+	extern "C" {
+		fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+	}
+
+	let t = timestamp as c_long;
+	let mut tm = Tm {
+		tm_sec: 0, tm_min: 0, tm_hour: 0, tm_mday: 0, tm_mon: 0, tm_year: 0,
+		tm_wday: 0, tm_yday: 0, tm_isdst: 0, tm_gmtoff: 0,
+		tm_zone: ::std::ptr::null(),
+	};
+
+	/* Safe: Both pointers are valid for the duration of the call, and localtime_r() is reentrant */
+	let result = unsafe { localtime_r(&t, &mut tm) };
+	if result.is_null() {
+		0
+	}
+	else {
+		tm.tm_gmtoff as i64
+	}
+}
+
+#[cfg(not(unix))]
+pub fn local_utc_offset_secs(_timestamp: i64) -> i64
+{
+	0
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/* Check that both supported directory-name styles parse */
+	#[test]
+	fn test_parse_session_date()
+	{
+		assert_eq!(SessionDate::new(2017, 4, 3), SessionDate::parse("20170403"));
+		assert_eq!(SessionDate::new(2017, 8, 2), SessionDate::parse("2017-08-02"));
+	}
+
+	/* Check that non-date directory names are rejected */
+	#[test]
+	fn test_parse_session_date_invalid()
+	{
+		assert_eq!(None, SessionDate::parse("misc"));
+		assert_eq!(None, SessionDate::parse("Scores"));
+		assert_eq!(None, SessionDate::parse("2017_08_02"));
+		assert_eq!(None, SessionDate::parse("20171302"));  /* month 13 */
+		assert_eq!(None, SessionDate::parse("20170230"));  /* 30th Feb */
+	}
+
+	/* Check leap year handling */
+	#[test]
+	fn test_leap_days()
+	{
+		assert!(SessionDate::parse("20160229").is_some());
+		assert!(SessionDate::parse("20170229").is_none());
+		assert!(SessionDate::parse("20000229").is_some());
+		assert!(SessionDate::parse("19000229").is_none());
+	}
+
+	/* Check the day-count conversion against some known values */
+	#[test]
+	fn test_days_since_epoch()
+	{
+		assert_eq!(0,     SessionDate::new(1970, 1, 1).unwrap().days_since_epoch());
+		assert_eq!(17259, SessionDate::new(2017, 4, 3).unwrap().days_since_epoch());
+		assert_eq!(-1,    SessionDate::new(1969, 12, 31).unwrap().days_since_epoch());
+	}
+
+	/* Converting back from day counts gives the same dates */
+	#[test]
+	fn test_from_days_since_epoch()
+	{
+		assert_eq!(SessionDate::new(1970, 1, 1), Some(SessionDate::from_days_since_epoch(0)));
+		assert_eq!(SessionDate::new(1969, 12, 31), Some(SessionDate::from_days_since_epoch(-1)));
+
+		for &(y, m, d) in [(2000, 2, 29), (2017, 4, 3), (2100, 3, 1), (1900, 2, 28)].iter() {
+			let date = SessionDate::new(y, m, d).unwrap();
+			assert_eq!(date, SessionDate::from_days_since_epoch(date.days_since_epoch()));
+		}
+	}
+
+	/* Check that dates display in ISO form */
+	#[test]
+	fn test_session_date_display()
+	{
+		assert_eq!("2017-04-03", SessionDate::new(2017, 4, 3).unwrap().to_string());
+	}
+
+	/* Directory names that do/don't have dates */
+	#[test]
+	fn test_track_date_parse()
+	{
+		let known = |raw: &str| (TrackDate::parse(raw).normalised().map(|d| d.to_string()), TrackDate::parse(raw).raw().to_string());
+
+		assert_eq!((Some("20170802".to_string()), "2017-08-02".to_string()), known("2017-08-02"));
+		assert_eq!((Some("20170802".to_string()), "20170802".to_string()), known("20170802"));
+		assert_eq!((Some("20170802".to_string()), "2017-08-02 Concert rehearsal".to_string()), known("2017-08-02 Concert rehearsal"));
+
+		/* Not dates */
+		for raw in ["misc", "", "2017-08", "20170231", "2017-08-02x", "Concert 2017-08-02"] {
+			let date = TrackDate::parse(raw);
+			assert_eq!(TrackDate::Unknown { raw: raw.to_string() }, date);
+			assert_eq!((None, raw), (date.normalised(), date.as_str()));
+			assert_eq!(None, date.session_date());
+		}
+
+		assert_eq!(SessionDate::new(2017, 8, 2), TrackDate::parse("2017-08-02 Concert").session_date());
+		assert_eq!("20170802", TrackDate::parse("2017-08-02 Concert").to_string());
+	}
+
+	#[test]
+	fn test_track_date_fallback()
+	{
+		let date = TrackDate::parse("misc").with_fallback(Some("20170802"));
+		assert_eq!(TrackDate::Known { date: "20170802".to_string(), raw: "misc".to_string() }, date);
+
+		/* The directory wins, and bad fallbacks are ignored */
+		assert_eq!(Some("20170403"), TrackDate::parse("20170403").with_fallback(Some("20170802")).normalised());
+		assert!(!TrackDate::parse("misc").with_fallback(Some("20171340")).is_known());
+		assert!(!TrackDate::parse("misc").with_fallback(None).is_known());
+	}
+
+	#[test]
+	fn test_weekday_and_ordinal()
+	{
+		assert_eq!(3, SessionDate::new(1970, 1, 1).unwrap().weekday());   /* Thursday */
+		assert_eq!(0, SessionDate::new(2017, 4, 3).unwrap().weekday());   /* Monday */
+		assert_eq!(6, SessionDate::new(1969, 12, 28).unwrap().weekday()); /* Sunday */
+
+		assert_eq!(1, SessionDate::new(2017, 1, 1).unwrap().ordinal());
+		assert_eq!(366, SessionDate::new(2016, 12, 31).unwrap().ordinal());
+	}
+
+	/* ISO weeks, particularly around the start/end of years */
+	#[test]
+	fn test_iso_week()
+	{
+		let cases = [
+			/* Year ending in week 53 */
+			((2015, 12, 28), "2015-W53"),
+			((2016,  1,  1), "2015-W53"),
+			((2016,  1,  3), "2015-W53"),
+			((2016,  1,  4), "2016-W01"),
+			/* Sunday 1st January - still in the previous year's last week */
+			((2017,  1,  1), "2016-W52"),
+			((2017,  1,  2), "2017-W01"),
+			((2017,  8,  2), "2017-W31"),
+			/* Last days of December in week 1 of the next year */
+			((2018, 12, 31), "2019-W01"),
+			((2019, 12, 30), "2020-W01"),
+			((2020, 12, 31), "2020-W53"),
+			((2021,  1,  3), "2020-W53"),
+			/* Week 1 starting on 1st January / 29th December */
+			((2024,  1,  1), "2024-W01"),
+			((2014, 12, 29), "2015-W01"),
+			/* Leap years */
+			((2004, 12, 31), "2004-W53"),
+			((2008, 12, 28), "2008-W52"),
+			((2008, 12, 29), "2009-W01"),
+		];
+		for &((y, m, d), expected) in cases.iter() {
+			let date = SessionDate::new(y, m, d).unwrap();
+			assert_eq!(expected, date.iso_week().to_string(), "for {}", date);
+			assert_eq!(expected, date.week(WeekScheme::Iso).to_string(), "for {}", date);
+		}
+	}
+
+	/* Simple weeks, starting on either Monday or Sunday */
+	#[test]
+	fn test_simple_week()
+	{
+		let monday = WeekScheme::Simple(WeekStart::Monday);
+		let sunday = WeekScheme::Simple(WeekStart::Sunday);
+		let week = |y, m, d, scheme| SessionDate::new(y, m, d).unwrap().week(scheme).to_string();
+
+		/* 1st January 2016 is a Friday - partial week 1 */
+		assert_eq!("2016-W01", week(2016, 1, 1, monday));
+		assert_eq!("2016-W01", week(2016, 1, 3, monday));
+		assert_eq!("2016-W02", week(2016, 1, 4, monday));
+		assert_eq!("2016-W01", week(2016, 1, 2, sunday));
+		assert_eq!("2016-W02", week(2016, 1, 3, sunday));
+
+		/* 1st January 2017 is a Sunday */
+		assert_eq!("2017-W01", week(2017, 1, 1, monday));
+		assert_eq!("2017-W02", week(2017, 1, 2, monday));
+		assert_eq!("2017-W01", week(2017, 1, 7, sunday));
+		assert_eq!("2017-W02", week(2017, 1, 8, sunday));
+
+		/* Dates never move to a different year (unlike ISO weeks) */
+		assert_eq!("2018-W53", week(2018, 12, 31, monday));
+		assert_eq!("2000-W54", week(2000, 12, 31, sunday));
+
+		assert_eq!(Some(WeekStart::Sunday), WeekStart::parse("Sunday"));
+		assert_eq!(Some(WeekStart::Monday), WeekStart::parse("mon"));
+		assert_eq!(None, WeekStart::parse("friday"));
+	}
+}
+
+/* *************************************************** */
@@ -0,0 +1,211 @@
+/* Field-level differences between two tracks
+ *
+ * Used to show exactly what differs between two versions of a track
+ * (e.g. the same take in two copies of a playlist), as an aligned old -> new listing.
+ */
+use std::io::{self, Write};
+
+use terminal_text::sanitize;
+use track_field::TrackField;
+use xspf_parser::Track;
+
+/* Fields that get compared (in the order they're listed) */
+pub const DIFF_FIELDS: [TrackField; 6] = [
+	TrackField::Path,
+	TrackField::Duration,
+	TrackField::Date,
+	TrackField::Name,
+	TrackField::Type,
+	TrackField::Extension,
+];
+
+/* Values longer than this (in characters) get shortened when displayed */
+pub const MAX_VALUE_WIDTH: usize = 60;
+
+/* Placeholder shown in place of values that are missing (e.g. unknown durations) */
+const EMPTY_VALUE: &'static str = "(none)";
+
+/* *************************************************** */
+/* Comparison */
+
+/* A field that has a different value in the two tracks */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct FieldChange {
+	pub field: TrackField,
+	pub old: String,
+	pub new: String,
+}
+
+/* Compare the fields of two tracks
+ * > returns the fields that differ (in the order of DIFF_FIELDS), or an empty list if they're the same
+ */
+pub fn track_field_diff(a: &Track, b: &Track) -> Vec<FieldChange>
+{
+	DIFF_FIELDS.iter()
+	           .filter_map(|field| {
+	               let old = field.value_string(a);
+	               let new = field.value_string(b);
+	               if old != new {
+	                   Some(FieldChange { field: *field, old: old, new: new })
+	               }
+	               else {
+	                   None
+	               }
+	           })
+	           .collect()
+}
+
+/* *************************************************** */
+/* Display */
+
+/* Shorten a string to at most "max_width" characters, by replacing the middle with "..."
+ * (keeping the start and end, which are the most identifying parts of paths)
+ */
+pub fn truncate_middle(s: &str, max_width: usize) -> String
+{
+	const ELLIPSIS: &'static str = "...";
+
+	let chars: Vec<char> = s.chars().collect();
+	if chars.len() <= max_width {
+		return s.to_string();
+	}
+	if max_width <= ELLIPSIS.len() {
+		return chars[.. max_width].iter().collect();
+	}
+
+	/* Give any odd character to the tail, as that's where the filename is */
+	let keep = max_width - ELLIPSIS.len();
+	let head = keep / 2;
+	let tail = keep - head;
+
+	let mut result: String = chars[.. head].iter().collect();
+	result.push_str(ELLIPSIS);
+	result.extend(chars[chars.len() - tail ..].iter());
+	result
+}
+
+/* Value as displayed in the listing */
+fn display_value(value: &str) -> String
+{
+	if value.is_empty() {
+		EMPTY_VALUE.to_string()
+	}
+	else {
+		truncate_middle(value, MAX_VALUE_WIDTH)
+	}
+}
+
+/* Write an "old -> new" line for each change, with the field names, and arrows lined up
+ * - indent: Prefix for each line
+ */
+pub fn write_field_changes(out: &mut dyn Write, changes: &[FieldChange], indent: &str) -> io::Result<()>
+{
+	let rows: Vec<(&str, String, String)> = changes.iter()
+	                                               .map(|c| (c.field.name(), sanitize(&display_value(&c.old)).into_owned(),
+	                                                     sanitize(&display_value(&c.new)).into_owned()))
+	                                               .collect();
+
+	let name_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+	let old_width = rows.iter().map(|r| r.1.chars().count()).max().unwrap_or(0);
+
+	for &(name, ref old, ref new) in rows.iter() {
+		writeln!(out, "{0}{1:<2$}  {3:<4$} -> {5}", indent, name, name_width, old, old_width, new)?;
+	}
+	Ok(())
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use track_duration::TrackDuration;
+
+	fn make_track(path: &str, duration_ms: Option<i64>) -> Track
+	{
+		let mut track = Track::from_filepath(path).unwrap();
+		track.duration = duration_ms.map(TrackDuration);
+		track
+	}
+
+	#[test]
+	fn test_identical_tracks()
+	{
+		let a = make_track("/music/20170403/v01-tranquil.mp3", Some(60_000));
+		let b = make_track("/music/20170403/v01-tranquil.mp3", Some(60_000));
+		assert!(track_field_diff(&a, &b).is_empty());
+	}
+
+	#[test]
+	fn test_duration_change()
+	{
+		let a = make_track("/music/20170403/v01-tranquil.mp3", Some(60_000));
+		let b = make_track("/music/20170403/v01-tranquil.mp3", Some(75_500));
+
+		let changes = track_field_diff(&a, &b);
+		assert_eq!(1, changes.len());
+		assert_eq!(TrackField::Duration, changes[0].field);
+		assert_eq!(TrackDuration(60_000).to_timecode(), changes[0].old);
+		assert_eq!(TrackDuration(75_500).to_timecode(), changes[0].new);
+
+		/* Duration going missing shows as "(none)" */
+		let c = make_track("/music/20170403/v01-tranquil.mp3", None);
+		let mut out = Vec::new();
+		write_field_changes(&mut out, &track_field_diff(&a, &c), "  ").unwrap();
+		let text = String::from_utf8(out).unwrap();
+		assert!(text.starts_with("  duration  "), "{}", text);
+		assert!(text.trim_end().ends_with("-> (none)"), "{}", text);
+	}
+
+	/* Arrows line up, regardless of the lengths of the names/values */
+	#[test]
+	fn test_aligned_arrows()
+	{
+		let a = make_track("/music/20170403/v01-tranquil.mp3", Some(60_000));
+		let b = make_track("/music/20170405/v02-celestial_dawn.ogg", Some(60_000));
+
+		let changes = track_field_diff(&a, &b);
+		let fields: Vec<TrackField> = changes.iter().map(|c| c.field).collect();
+		assert_eq!(vec![TrackField::Path, TrackField::Date, TrackField::Name, TrackField::Extension], fields);
+
+		let mut out = Vec::new();
+		write_field_changes(&mut out, &changes, "").unwrap();
+		let text = String::from_utf8(out).unwrap();
+
+		let arrow_columns: Vec<usize> = text.lines().map(|l| l.find(" -> ").unwrap()).collect();
+		assert_eq!(4, arrow_columns.len());
+		assert!(arrow_columns.iter().all(|&c| c == arrow_columns[0]), "{}", text);
+	}
+
+	#[test]
+	fn test_truncate_middle()
+	{
+		/* Short enough - untouched */
+		assert_eq!("v01-tranquil.mp3", truncate_middle("v01-tranquil.mp3", 20));
+
+		/* Keeps the start and end */
+		let path = "/home/user/music/recordings/2017/20170403/v01-tranquil.mp3";
+		let short = truncate_middle(path, 30);
+		assert_eq!(30, short.chars().count());
+		assert_eq!("/home/user/mu...1-tranquil.mp3", short);
+
+		/* Counts characters, not bytes */
+		let accented = truncate_middle("ééééééééééééééééééééé", 9);
+		assert_eq!("ééé...ééé", accented);
+
+		/* Long values get shortened in the listing */
+		let long_path = format!("/music/{}/v01-tranquil.mp3", "x".repeat(100));
+		let a = make_track(&long_path, None);
+		let b = make_track("/music/20170403/v01-tranquil.mp3", None);
+		let mut out = Vec::new();
+		write_field_changes(&mut out, &track_field_diff(&a, &b), "").unwrap();
+		let text = String::from_utf8(out).unwrap();
+		let first_line = text.lines().next().unwrap();
+		assert!(first_line.starts_with("path  /music/xxx"), "{}", first_line);
+		assert!(first_line.contains("xxx...xxx"), "{}", first_line);
+		assert!(first_line.contains("xxx/v01-tranquil.mp3 -> /music/20170403/"), "{}", first_line);
+	}
+}
@@ -1,13 +1,71 @@
 /* Implementation of "Track Duration" type */
 use std::fmt;
-use std::ops::{Add, AddAssign};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
 
 /* Track Duration */
 #[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
 pub struct TrackDuration(pub i64);
 
 #[allow(dead_code)]
 impl TrackDuration {
+	/* Create from a number of seconds (rounded to the nearest millisecond) */
+	pub fn from_secs(secs: f64) -> TrackDuration
+	{
+		TrackDuration((secs * 1000.0_f64).round() as i64)
+	}
+	
+	/* Create from a "mm:ss" or "h:mm:ss" timecode string (as given by to_timecode())
+	 * NOTE: The leading component can be as large as needed (e.g. "75:32"), but
+	 *       the others must be valid numbers of minutes/seconds.
+	 *       Negative durations (e.g. differences between durations) have a single "-" in front of the whole timecode.
+	 */
+	pub fn from_timecode(timecode: &str) -> Result<TrackDuration, String>
+	{
+		let trimmed = timecode.trim();
+		let (negative, unsigned) = match trimmed.strip_prefix('-') {
+			Some(rest) => (true, rest),
+			None       => (false, trimmed)
+		};
+		
+		let parts: Vec<&str> = unsigned.split(':').collect();
+		if parts.len() < 2 || parts.len() > 3 {
+			return Err(format!("Invalid timecode '{}' - expected mm:ss or h:mm:ss", timecode));
+		}
+		
+		let mut total_secs: i64 = 0;
+		for (i, part) in parts.iter().enumerate() {
+			/* Only plain digits - so no signs (other than the one in front), spaces, or decimal points */
+			if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+				return Err(format!("Invalid timecode '{0}' - '{1}' isn't a number", timecode, part));
+			}
+			let value = match part.parse::<i64>() {
+				Ok(value) => value,
+				Err(_) => return Err(format!("Invalid timecode '{0}' - '{1}' is too large", timecode, part))
+			};
+			
+			if i > 0 && value >= 60 {
+				return Err(format!("Invalid timecode '{0}' - '{1}' is out of range (should be under 60)", timecode, part));
+			}
+			total_secs = match total_secs.checked_mul(60).and_then(|secs| secs.checked_add(value)) {
+				Some(secs) => secs,
+				None       => return Err(format!("Invalid timecode '{0}' - '{1}' is too large", timecode, parts[0]))
+			};
+		}
+		
+		match total_secs.checked_mul(1000) {
+			Some(ms) => Ok(TrackDuration(if negative { -ms } else { ms })),
+			None     => Err(format!("Invalid timecode '{0}' - '{1}' is too large", timecode, parts[0]))
+		}
+	}
+	
+	/* Number of milliseconds */
+	pub fn as_millis(&self) -> i64
+	{
+		self.0
+	}
+	
 	/* Convert from milliseconds to seconds */
 	pub fn to_secs(&self) -> f64
 	{
@@ -22,18 +80,29 @@ impl TrackDuration {
 		secs / 60.0_f64
 	}
 	
-	/* Convert from milliseconds to "mins:secs" timecode string */
+	/* Convert from milliseconds to "mins:secs" timecode string
+	 * (or "hours:mins:secs" once it gets to an hour or more),
+	 * with a "-" in front for negative durations (e.g. "-01:05")
+	 */
 	pub fn to_timecode(&self) -> String
 	{
 		/* Total seconds - We don't care about the leftover milliseconds */
-		let total_secs = self.to_secs() as i64;
+		let total_secs = self.0 / 1000;
+		let sign = if total_secs < 0 { "-" } else { "" };
+		let total_secs = total_secs.abs();
 		
-		/* mins:secs */
-		let mins: i64 = total_secs / 60;
+		/* hours:mins:secs */
+		let hours: i64 = total_secs / 3600;
+		let mins: i64 = (total_secs / 60) % 60;
 		let secs: i64 = total_secs % 60;
 		
 		/* output string */
-		format!("{0:02}:{1:02}", mins, secs)
+		if hours > 0 {
+			format!("{0}{1}:{2:02}:{3:02}", sign, hours, mins, secs)
+		}
+		else {
+			format!("{0}{1:02}:{2:02}", sign, total_secs / 60, secs)
+		}
 	}
 }
 
@@ -59,6 +128,26 @@ impl AddAssign for TrackDuration {
 }
 
 
+impl Sub for TrackDuration {
+	type Output = TrackDuration;
+	fn sub(self, other: TrackDuration) -> TrackDuration
+	{
+		let TrackDuration(x) = self;
+		let TrackDuration(y) = other;
+		TrackDuration(x - y)
+	}
+}
+impl SubAssign for TrackDuration {
+	fn sub_assign(&mut self, other: TrackDuration)
+	{
+		let TrackDuration(x) = *self;
+		let TrackDuration(y) = other;
+		
+		*self = TrackDuration(x - y);
+	}
+}
+
+
 /* Operator Overrides - The useful cases */
 impl Add<i64> for TrackDuration {
 	type Output = TrackDuration;
@@ -95,3 +184,94 @@ impl fmt::Debug for TrackDuration {
 	}
 }
 
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn test_timecode_round_trip()
+	{
+		for &(timecode, ms) in [("00:00", 0), ("01:01", 61_000), ("59:59", 3_599_000),
+		                        ("1:00:00", 3_600_000), ("1:15:32", 4_532_000), ("12:03:09", 43_389_000)].iter() {
+			let duration = TrackDuration::from_timecode(timecode).unwrap();
+			assert_eq!(ms, duration.as_millis());
+			assert_eq!(timecode, duration.to_timecode());
+		}
+		
+		/* Minutes past the hour (as older versions wrote), and unpadded values, are still fine */
+		assert_eq!(TrackDuration(4_532_000), TrackDuration::from_timecode("75:32").unwrap());
+		assert_eq!(TrackDuration(4_532_000), TrackDuration::from_timecode("1:15:32").unwrap());
+		assert_eq!(TrackDuration(65_000), TrackDuration::from_timecode("1:05").unwrap());
+		
+		/* Leftover milliseconds get dropped from the timecode */
+		assert_eq!("01:01", TrackDuration(61_999).to_timecode());
+		assert_eq!("1:15:32", format!("{}", TrackDuration(4_532_400)));
+	}
+	
+	/* Negative durations (e.g. from subtracting a longer duration) */
+	#[test]
+	fn test_negative_timecode_round_trip()
+	{
+		for &(timecode, ms) in [("-00:01", -1_000), ("-01:05", -65_000), ("-1:15:32", -4_532_000)].iter() {
+			let duration = TrackDuration::from_timecode(timecode).unwrap();
+			assert_eq!(ms, duration.as_millis());
+			assert_eq!(timecode, duration.to_timecode());
+		}
+		assert_eq!("-01:05", (TrackDuration(30_000) - TrackDuration(95_000)).to_timecode());
+		
+		/* Leftover milliseconds get dropped (i.e. rounded towards zero) */
+		assert_eq!("-01:01", TrackDuration(-61_999).to_timecode());
+		assert_eq!("00:00", TrackDuration(-999).to_timecode());
+	}
+	
+	#[test]
+	fn test_invalid_timecodes()
+	{
+		for &timecode in ["", "90", "1:2:3:4", "--1:30", "- 1:30", "-", "1:-30", "1:30.5", "ab:cd", "1::30", ":30", "1:60", "1:60:00", "+1:30"].iter() {
+			assert!(TrackDuration::from_timecode(timecode).is_err(), "'{}' should be rejected", timecode);
+		}
+		
+		/* Too large to be held (instead of overflowing) */
+		for &timecode in ["9999999999999999:00", "-9999999999999999:00", "999999999999999999:00:00", "99999999999999999999:00"].iter() {
+			match TrackDuration::from_timecode(timecode) {
+				Err(e) => assert!(e.contains("too large"), "{}", e),
+				Ok(d) => panic!("'{0}' should be rejected, got {1}ms", timecode, d.as_millis())
+			}
+		}
+	}
+	
+	#[test]
+	fn test_from_secs()
+	{
+		assert_eq!(61_000, TrackDuration::from_secs(61.0).as_millis());
+		assert_eq!(1_235, TrackDuration::from_secs(1.2345).as_millis());
+		assert_eq!("1:01:01", TrackDuration::from_secs(3661.0).to_timecode());
+	}
+	
+	#[test]
+	fn test_operators()
+	{
+		let mut d = TrackDuration(90_000) - TrackDuration(30_000);
+		assert_eq!(TrackDuration(60_000), d);
+		
+		d -= TrackDuration(15_000);
+		assert_eq!(TrackDuration(45_000), d);
+		
+		d += TrackDuration(5_000);
+		assert_eq!(50_000, d.as_millis());
+		
+		/* Copy, so the original can still be used afterwards */
+		let e = d;
+		assert_eq!(d, e);
+		
+		let mut durations = vec![TrackDuration(61_000), TrackDuration(5_000), TrackDuration(3_600_000)];
+		durations.sort();
+		assert_eq!(vec![TrackDuration(5_000), TrackDuration(61_000), TrackDuration(3_600_000)], durations);
+		assert!(TrackDuration(5_000) < TrackDuration(61_000));
+		assert_eq!(Some(&TrackDuration(3_600_000)), durations.iter().max());
+	}
+}
@@ -0,0 +1,114 @@
+/* Named fields of a track
+ *
+ * Provides a common vocabulary for referring to parts of a track by name
+ * (e.g. from the command-line), along with ways of getting at their values.
+ */
+use std::fmt;
+
+use xspf_parser::Track;
+
+/* Kinds of values that fields hold (which determines how they can be compared) */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum FieldKind {
+	Text,
+	Number,
+	Duration,
+	Date,
+}
+
+/* The fields of a track that can be referred to by name */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum TrackField {
+	Path,
+	Filename,
+	Date,
+	Duration,
+	Type,
+	Index,
+	Name,
+	Extension,
+	SourcePlaylist,
+	Rating,
+}
+
+impl TrackField {
+	/* Look up a field by name (case insensitive) */
+	pub fn from_name(name: &str) -> Option<TrackField>
+	{
+		match name.to_lowercase().as_ref() {
+			"path"                => Some(TrackField::Path),
+			"filename"            => Some(TrackField::Filename),
+			"date"                => Some(TrackField::Date),
+			"duration"            => Some(TrackField::Duration),
+			"type" | "track_type" => Some(TrackField::Type),
+			"index"               => Some(TrackField::Index),
+			"name"                => Some(TrackField::Name),
+			"ext" | "extension"   => Some(TrackField::Extension),
+			"source_playlist"     => Some(TrackField::SourcePlaylist),
+			"rating" | "stars"    => Some(TrackField::Rating),
+			_                     => None
+		}
+	}
+
+	/* Canonical name of the field */
+	pub fn name(&self) -> &'static str
+	{
+		match *self {
+			TrackField::Path      => "path",
+			TrackField::Filename  => "filename",
+			TrackField::Date      => "date",
+			TrackField::Duration  => "duration",
+			TrackField::Type      => "type",
+			TrackField::Index     => "index",
+			TrackField::Name      => "name",
+			TrackField::Extension => "ext",
+			TrackField::SourcePlaylist => "source_playlist",
+			TrackField::Rating    => "rating",
+		}
+	}
+
+	/* What kind of value this field holds */
+	pub fn kind(&self) -> FieldKind
+	{
+		match *self {
+			TrackField::Duration => FieldKind::Duration,
+			TrackField::Date     => FieldKind::Date,
+			TrackField::Index    => FieldKind::Number,
+			TrackField::Rating   => FieldKind::Number,
+			_                    => FieldKind::Text,
+		}
+	}
+
+	/* Get the value of this field for the given track, as a display string */
+	pub fn value_string(&self, track: &Track) -> String
+	{
+		match *self {
+			TrackField::Path      => track.path.to_string(),
+			TrackField::Filename  => track.filename.clone(),
+			TrackField::Date      => track.date.to_string(),
+			TrackField::Duration  => {
+				match track.duration {
+					Some(ref d) => d.to_timecode(),
+					None        => String::new()
+				}
+			},
+			TrackField::Type      => track.info().track_type.shortname(),
+			TrackField::Index     => track.info().index.to_string(),
+			TrackField::Name      => track.info().name.clone(),
+			TrackField::Extension => track.info().extn.to_string(),
+			TrackField::SourcePlaylist => track.source_playlist.clone().unwrap_or_default(),
+			TrackField::Rating    => track.rating.map(|r| r.to_string()).unwrap_or_default(),
+		}
+	}
+}
+
+impl fmt::Display for TrackField {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.name())
+	}
+}
@@ -0,0 +1,246 @@
+/* Filename convention check
+ *
+ * The info about each track (type, take number, name, date) all comes from its filename, so a file
+ * that's been named by hand with a typo quietly ends up as an "unknown" track, or in the wrong session.
+ * This runs a set of rules over each track's filename (and where it's filed), flagging any that don't
+ * match what the rest of the tools expect - so they can be renamed before they cause trouble elsewhere.
+ */
+use diagnostics::{Diagnostics, Severity};
+use track_date::TrackDate;
+use track_name_info::{TrackExtension, TrackType};
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Characters that can't be used in the names of the files written by copy/convert
+ * (see dest_naming - "{name}" gets put into the output filename as it is)
+ */
+const UNSAFE_NAME_CHARS: &'static str = "<>:\"/\\|?*";
+
+/* *************************************************** */
+/* Rules */
+
+/* A single check on a track */
+pub struct Rule {
+	/* Short identifier for the rule (used as the category of its diagnostics) */
+	pub id: &'static str,
+	/* Check the track, returning a description of the problem if it breaks the rule */
+	pub check: fn(&Track) -> Option<String>,
+}
+
+/* All the rules, in the order they get checked */
+pub const RULES: [Rule; 5] = [
+	Rule { id: "unknown-type",      check: check_unknown_type },
+	Rule { id: "date-mismatch",     check: check_date_mismatch },
+	Rule { id: "zero-index",        check: check_zero_index },
+	Rule { id: "unsafe-name",       check: check_unsafe_name },
+	Rule { id: "unknown-extension", check: check_unknown_extension },
+];
+
+/* Filename doesn't match any of the naming conventions */
+fn check_unknown_type(track: &Track) -> Option<String>
+{
+	if track.info().track_type == TrackType::UnknownType {
+		Some("doesn't follow any of the naming conventions (type unknown)".to_string())
+	}
+	else {
+		None
+	}
+}
+
+/* MuseScore file filed under a different date from the one in its name */
+fn check_date_mismatch(track: &Track) -> Option<String>
+{
+	if track.info().track_type != TrackType::MuseScore {
+		return None;
+	}
+
+	/* NOTE: track.date falls back to the date in the filename, so the directory needs checking separately */
+	let dir_date = TrackDate::parse(&track.path.parent_name());
+	match (track.info().date.as_ref(), dir_date.normalised()) {
+		(Some(name_date), Some(dir_date)) if name_date != dir_date => {
+			Some(format!("dated {0} in its name, but filed under {1}", name_date, dir_date))
+		},
+		_ => None
+	}
+}
+
+/* Take number of 0 (takes are numbered from 1) */
+fn check_zero_index(track: &Track) -> Option<String>
+{
+	let info = track.info();
+	if info.track_type != TrackType::UnknownType && info.index == 0 {
+		Some("take number is 0".to_string())
+	}
+	else {
+		None
+	}
+}
+
+/* Name has characters that can't be used when renaming the file for copy/convert */
+fn check_unsafe_name(track: &Track) -> Option<String>
+{
+	let mut unsafe_chars: Vec<char> = track.info().name.chars()
+	                                       .filter(|&c| UNSAFE_NAME_CHARS.contains(c) || c.is_control())
+	                                       .collect();
+	unsafe_chars.sort();
+	unsafe_chars.dedup();
+
+	if unsafe_chars.is_empty() {
+		None
+	}
+	else {
+		Some(format!("name has characters that can't be used in output filenames - {:?}",
+		             unsafe_chars.iter().collect::<String>()))
+	}
+}
+
+/* Extension isn't one of the known audio/video formats */
+fn check_unknown_extension(track: &Track) -> Option<String>
+{
+	match track.info().extn {
+		TrackExtension::Unknown(ref extn) => Some(format!("unknown extension '.{}'", extn)),
+		_ => None
+	}
+}
+
+/* *************************************************** */
+
+/* Summary of the check over a whole playlist */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct LintReport {
+	/* Number of tracks checked */
+	pub tracks: usize,
+	/* Number of tracks with at least one problem */
+	pub flagged_tracks: usize,
+
+	/* Problems found (with the rule id as the category) */
+	pub diagnostics: Diagnostics,
+}
+
+/* Run all the rules over a single track, returning the ids of the rules broken along with the problems */
+pub fn check_track(track: &Track) -> Vec<(&'static str, String)>
+{
+	RULES.iter()
+	     .filter_map(|rule| (rule.check)(track).map(|problem| (rule.id, problem)))
+	     .collect()
+}
+
+/* Run all the rules over every track in the playlist */
+pub fn check_playlist(xspf: &XspfPlaylist) -> LintReport
+{
+	let mut report = LintReport {
+		tracks: xspf.tracks.len(),
+		flagged_tracks: 0,
+		diagnostics: Diagnostics::new(),
+	};
+
+	for (idx, track) in xspf.tracks.iter().enumerate() {
+		let problems = check_track(track);
+		if !problems.is_empty() {
+			report.flagged_tracks += 1;
+		}
+		for (id, problem) in problems {
+			report.diagnostics.add(Severity::Warning, id, Some(idx),
+			                       format!("{0} - {1}", track.filename, problem));
+		}
+	}
+
+	report
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/* Ids of the rules broken by the track at the given path */
+	fn broken_rules(path: &str) -> Vec<&'static str>
+	{
+		check_track(&Track::from_filepath(path).unwrap()).into_iter().map(|(id, _)| id).collect()
+	}
+
+	#[test]
+	fn test_good_names()
+	{
+		let empty: Vec<&str> = Vec::new();
+		assert_eq!(empty, broken_rules("music/20170403/v01b-tranquil.mp3"));
+		assert_eq!(empty, broken_rules("music/20170802/20170802-02-TouchedByAnAngel.flac"));
+		assert_eq!(empty, broken_rules("music/20170802/p03-evening_rain.ogg"));
+
+		/* MuseScore files don't have to be filed under a date */
+		assert_eq!(empty, broken_rules("music/exports/20170802-02-TouchedByAnAngel.mp3"));
+	}
+
+	#[test]
+	fn test_unknown_type()
+	{
+		assert_eq!(vec!["unknown-type"], broken_rules("music/20170403/tranquil.mp3"));
+		assert_eq!(vec!["unknown-type"], broken_rules("music/20170403/v-tranquil.mp3"));
+	}
+
+	#[test]
+	fn test_date_mismatch()
+	{
+		assert_eq!(vec!["date-mismatch"], broken_rules("music/20170803/20170802-02-TouchedByAnAngel.mp3"));
+		assert_eq!(vec!["date-mismatch"], broken_rules("music/2017-08-03/20170802-02-TouchedByAnAngel.mp3"));
+
+		/* Same date, written differently */
+		assert!(broken_rules("music/2017-08-02/20170802-02-TouchedByAnAngel.mp3").is_empty());
+
+		let track = Track::from_filepath("music/20170803/20170802-02-TouchedByAnAngel.mp3").unwrap();
+		assert_eq!(Some("dated 20170802 in its name, but filed under 20170803".to_string()), check_date_mismatch(&track));
+	}
+
+	#[test]
+	fn test_zero_index()
+	{
+		assert_eq!(vec!["zero-index"], broken_rules("music/20170403/v00-tranquil.mp3"));
+		assert_eq!(vec!["zero-index"], broken_rules("music/20170802/20170802-00-TouchedByAnAngel.mp3"));
+	}
+
+	#[test]
+	fn test_unsafe_name()
+	{
+		assert_eq!(vec!["unsafe-name"], broken_rules("music/20170403/v01-what?.mp3"));
+		assert_eq!(vec!["unsafe-name"], broken_rules("music/20170403/v01-a:b|c.mp3"));
+
+		let track = Track::from_filepath("music/20170403/v01-a:b|c:d.mp3").unwrap();
+		assert_eq!(Some("name has characters that can't be used in output filenames - \":|\"".to_string()),
+		           check_unsafe_name(&track));
+	}
+
+	#[test]
+	fn test_unknown_extension()
+	{
+		assert_eq!(vec!["unknown-extension"], broken_rules("music/20170403/v01-tranquil.aac"));
+
+		/* Several problems with the same track all get reported */
+		assert_eq!(vec!["unknown-type", "unknown-extension"], broken_rules("music/20170403/notes.txt"));
+	}
+
+	#[test]
+	fn test_check_playlist()
+	{
+		let paths = ["music/20170403/v01-tranquil.mp3", "music/20170403/v00-moose.mp3",
+		             "music/20170403/notes.txt", "music/20170403/v02-celestial.flac"];
+		let xspf = XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		};
+
+		let report = check_playlist(&xspf);
+		assert_eq!((4, 2), (report.tracks, report.flagged_tracks));
+		assert_eq!(vec!["[zero-index] warning | track 2: v00-moose.mp3 - take number is 0",
+		                "[unknown-type] warning | track 3: notes.txt - doesn't follow any of the naming conventions (type unknown)",
+		                "[unknown-extension] warning | track 3: notes.txt - unknown extension '.txt'"],
+		           report.diagnostics.entries.iter().map(|d| d.to_string()).collect::<Vec<_>>());
+	}
+}
@@ -0,0 +1,643 @@
+/* Moving track files to another folder (e.g. when migrating a collection to a new drive)
+ *
+ * Each file goes through these states, with each step being recorded in a journal in the
+ * destination folder (MOVE_JOURNAL_FILENAME) as soon as it's been done:
+ *   Pending -> Copied -> Verified -> SourceDeleted
+ * The source file only ever gets deleted once the copy has been verified (same size and hash).
+ * So if a run gets interrupted, running it again picks up where it left off - copies that were
+ * verified don't get redone, and anything in-between gets checked again first.
+ *
+ * When the source file is on the same filesystem as the destination, it just gets renamed instead
+ * (which is instant, and can't leave a half-moved file behind).
+ */
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use content_dupes::file_fingerprint;
+use convert_resume;
+use file_timestamps;
+
+/* Name of the file (in the destination folder) that the journal gets saved to */
+pub const MOVE_JOURNAL_FILENAME: &'static str = ".xspf_tools-move.json";
+
+/* Version of the journal file format */
+const MOVE_JOURNAL_FORMAT_VERSION: u32 = 1;
+
+/* *************************************************** */
+/* States */
+
+/* How far a file has got through being moved */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveState {
+	/* Nothing done yet (or a copy that didn't verify, which needs redoing) */
+	Pending,
+	/* The destination file has been written (but not checked yet) */
+	Copied,
+	/* The destination file matches the source */
+	Verified,
+	/* The source file has been deleted - The move is complete */
+	SourceDeleted,
+}
+
+impl MoveState {
+	/* State that follows this one (None once the move is complete) */
+	pub fn next(&self) -> Option<MoveState>
+	{
+		match *self {
+			MoveState::Pending       => Some(MoveState::Copied),
+			MoveState::Copied        => Some(MoveState::Verified),
+			MoveState::Verified      => Some(MoveState::SourceDeleted),
+			MoveState::SourceDeleted => None,
+		}
+	}
+
+	/* Can a file go from this state to the given one?
+	 * Files only move forward one step at a time, except for going back to Pending
+	 * to redo a copy (which can't happen once the source has been deleted)
+	 */
+	pub fn can_become(&self, to: MoveState) -> bool
+	{
+		(self.next() == Some(to)) || (to == MoveState::Pending && *self != MoveState::SourceDeleted)
+	}
+
+	/* Is there nothing more to do for a file in this state?
+	 * - delete_sources: Whether sources get deleted (otherwise, files are done once they're verified)
+	 */
+	pub fn is_finished(&self, delete_sources: bool) -> bool
+	{
+		match *self {
+			MoveState::SourceDeleted => true,
+			MoveState::Verified      => !delete_sources,
+			_                        => false,
+		}
+	}
+}
+
+impl fmt::Display for MoveState {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		let name = match *self {
+			MoveState::Pending       => "pending",
+			MoveState::Copied        => "copied",
+			MoveState::Verified      => "verified",
+			MoveState::SourceDeleted => "source deleted",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/* How a file gets moved */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveMethod {
+	/* Copied, verified, then the source deleted (for moving between filesystems) */
+	Copy,
+	/* Renamed (when the source is on the same filesystem as the destination) */
+	Rename,
+}
+
+/* *************************************************** */
+/* Journal */
+
+/* What's been done for one destination file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct MoveRecord {
+	/* Path of the source file (as it was given) */
+	pub source: String,
+	pub method: MoveMethod,
+	pub state: MoveState,
+
+	/* Size of the source file (once known) */
+	pub size: Option<u64>,
+	/* Hash of the source file's contents (only for copies) */
+	pub hash: Option<String>,
+}
+
+impl MoveRecord {
+	fn new(source: &Path, method: MoveMethod) -> MoveRecord
+	{
+		MoveRecord {
+			source: source.display().to_string(),
+			method: method,
+			state: MoveState::Pending,
+			size: None,
+			hash: None,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct MoveJournalFile {
+	version: u32,
+	/* Destination filename -> Record */
+	entries: BTreeMap<String, MoveRecord>,
+}
+
+/* Record of the files moved into a destination folder */
+#[derive(Debug)]
+pub struct MoveJournal {
+	path: PathBuf,
+	entries: BTreeMap<String, MoveRecord>,
+}
+
+impl MoveJournal {
+	/* Load the journal for the given destination folder (which is empty if there isn't one yet)
+	 * NOTE: Unlike the convert stamps, an unreadable journal is an error - guessing what's been moved isn't safe
+	 */
+	pub fn load(out_dir: &Path) -> Result<MoveJournal, String>
+	{
+		let path = out_dir.join(MOVE_JOURNAL_FILENAME);
+		let entries = match fs::read_to_string(&path) {
+			Ok(s) => {
+				let file: MoveJournalFile = serde_json::from_str(&s)
+					.map_err(|e| format!("Couldn't read the move journal {0:?} - {1}", path, e))?;
+				if file.version != MOVE_JOURNAL_FORMAT_VERSION {
+					return Err(format!("Move journal {0:?} has an unsupported version ({1})", path, file.version));
+				}
+				file.entries
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+			Err(e) => return Err(format!("Couldn't read the move journal {0:?} - {1}", path, e))
+		};
+
+		Ok(MoveJournal {
+			path: path,
+			entries: entries,
+		})
+	}
+
+	/* Where the journal gets saved */
+	pub fn path(&self) -> &Path
+	{
+		&self.path
+	}
+
+	/* Record for the given destination file (if it's been started on) */
+	pub fn get(&self, dst_filename: &str) -> Option<&MoveRecord>
+	{
+		self.entries.get(dst_filename)
+	}
+
+	/* Update the record for the given destination file, saving the journal straight away */
+	fn update(&mut self, dst_filename: &str, record: MoveRecord) -> Result<(), String>
+	{
+		self.entries.insert(dst_filename.to_string(), record);
+		self.save().map_err(|e| format!("Couldn't save the move journal {0:?} - {1}", self.path, e))
+	}
+
+	/* Write the journal out (via a temp file, so it's never left half-written) */
+	fn save(&self) -> io::Result<()>
+	{
+		let journal_file = MoveJournalFile {
+			version: MOVE_JOURNAL_FORMAT_VERSION,
+			entries: self.entries.clone(),
+		};
+		let json = serde_json::to_string_pretty(&journal_file).map_err(io::Error::other)?;
+
+		let temp_path = self.path.with_extension("json.tmp");
+		File::create(&temp_path)
+			.and_then(|mut f| f.write_all(json.as_bytes()).and_then(|_| f.sync_all()))
+			.and_then(|_| fs::rename(&temp_path, &self.path))
+	}
+}
+
+/* *************************************************** */
+/* Moving */
+
+/* Settings for moving files */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct MoveOptions {
+	/* Delete the source files once they've been verified (i.e. false for "--no-delete") */
+	pub delete_sources: bool,
+}
+
+/* Is the file on the same filesystem as the given folder? (None if it couldn't be told) */
+#[cfg(unix)]
+pub fn same_filesystem(file: &Path, dir: &Path) -> Option<bool>
+{
+	use std::os::unix::fs::MetadataExt;
+
+	let file_dev = fs::metadata(file).ok()?.dev();
+	let dir_dev = fs::metadata(dir).ok()?.dev();
+	Some(file_dev == dir_dev)
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_file: &Path, _dir: &Path) -> Option<bool>
+{
+	None
+}
+
+/* How a file will be moved (renaming is only safe when the source goes away anyway) */
+pub fn choose_method(src: &Path, dst: &Path, options: &MoveOptions) -> MoveMethod
+{
+	let dst_dir = dst.parent().unwrap_or(Path::new("."));
+	if options.delete_sources && same_filesystem(src, dst_dir) == Some(true) {
+		MoveMethod::Rename
+	}
+	else {
+		MoveMethod::Copy
+	}
+}
+
+/* Size of a file (if it exists) */
+fn file_size(path: &Path) -> Option<u64>
+{
+	fs::metadata(path).ok().filter(|m| m.is_file()).map(|m| m.len())
+}
+
+/* Copy the source to the destination (via a temp file), keeping its modification time
+ * > returns the source's size and hash
+ */
+fn copy_file(src: &Path, dst: &Path) -> Result<(u64, String), String>
+{
+	let (size, hash) = file_fingerprint(src).map_err(|e| format!("Couldn't read {0:?} - {1}", src, e))?;
+
+	let result = fs::copy(src, convert_resume::partial_path(dst))
+		.and_then(|_| convert_resume::finish_partial(dst));
+	if let Err(e) = result {
+		convert_resume::discard_partial(dst);
+		return Err(format!("Couldn't copy {0:?} to {1:?} - {2}", src, dst, e));
+	}
+
+	if let Ok(mtime) = fs::metadata(src).and_then(|m| m.modified()) {
+		let _ = file_timestamps::set_mtime(dst, mtime);
+	}
+	Ok((size, hash))
+}
+
+/* Does the destination file match the source file? */
+fn copy_matches(src: &Path, dst: &Path) -> Result<bool, String>
+{
+	let src_fingerprint = file_fingerprint(src).map_err(|e| format!("Couldn't read {0:?} - {1}", src, e))?;
+	match file_fingerprint(dst) {
+		Ok(dst_fingerprint) => Ok(src_fingerprint == dst_fingerprint),
+		Err(_)              => Ok(false)
+	}
+}
+
+/* Take the next step in moving a file, recording it in the journal
+ * - dst_filename: Name of the destination file (which the journal is keyed by)
+ * > returns the state the file is now in
+ */
+pub fn advance(journal: &mut MoveJournal, dst_filename: &str, src: &Path, dst: &Path, options: &MoveOptions)
+	-> Result<MoveState, String>
+{
+	let mut record = match journal.get(dst_filename) {
+		Some(record) if record.source == src.display().to_string() => record.clone(),
+		Some(record) if record.state != MoveState::Pending => {
+			return Err(format!("{0:?} was already moved there from {1:?} (according to the move journal)", dst, record.source));
+		},
+		_ => MoveRecord::new(src, choose_method(src, dst, options))
+	};
+
+	let to = match record.state {
+		MoveState::Pending => {
+			if file_size(src).is_none() {
+				/* Interrupted after moving it, but before that was recorded? */
+				match (record.size, file_size(dst)) {
+					(Some(size), Some(dst_size)) if size == dst_size => MoveState::Copied,
+					_ => return Err(format!("Source file {:?} doesn't exist", src))
+				}
+			}
+			else {
+				match record.method {
+					MoveMethod::Rename => {
+						/* Note the size before the file gets renamed (so it can be checked afterwards) */
+						record.size = file_size(src);
+						journal.update(dst_filename, record.clone())?;
+
+						fs::rename(src, dst).map_err(|e| format!("Couldn't rename {0:?} to {1:?} - {2}", src, dst, e))?;
+					},
+					MoveMethod::Copy => {
+						let (size, hash) = copy_file(src, dst)?;
+						record.size = Some(size);
+						record.hash = Some(hash);
+					}
+				}
+				MoveState::Copied
+			}
+		},
+
+		MoveState::Copied => {
+			match record.method {
+				MoveMethod::Rename => {
+					if file_size(dst) != record.size {
+						return Err(format!("Renamed file {0:?} isn't the size it was before ({1:?} bytes)", dst, record.size));
+					}
+					MoveState::Verified
+				},
+				MoveMethod::Copy => {
+					if copy_matches(src, dst)? {
+						MoveState::Verified
+					}
+					else {
+						/* Bad copy - Throw it away and start again */
+						fs::remove_file(dst).map_err(|e| format!("Couldn't remove bad copy {0:?} - {1}", dst, e))?;
+						MoveState::Pending
+					}
+				}
+			}
+		},
+
+		MoveState::Verified => {
+			if !options.delete_sources {
+				return Ok(MoveState::Verified);
+			}
+
+			/* The copy was verified, but might have been touched since (e.g. if this run is resuming)
+			 * NOTE: Only the sizes get checked again, as re-reading everything would double the time taken
+			 */
+			match (file_size(src), file_size(dst)) {
+				(None, Some(_)) => { /* Already gone */ },
+				(Some(src_size), Some(dst_size)) if (src_size == dst_size) && (record.size == Some(src_size)) => {
+					fs::remove_file(src).map_err(|e| format!("Couldn't delete source file {0:?} - {1}", src, e))?;
+				},
+				(Some(_), _) => {
+					/* Copy has gone missing/changed - The source is still there, so copy it again */
+					record.state = MoveState::Pending;
+					journal.update(dst_filename, record)?;
+					return Ok(MoveState::Pending);
+				},
+				(None, None) => {
+					return Err(format!("Neither the source file {0:?} nor its copy {1:?} exist any more", src, dst));
+				}
+			}
+			MoveState::SourceDeleted
+		},
+
+		MoveState::SourceDeleted => {
+			return Ok(MoveState::SourceDeleted);
+		}
+	};
+
+	debug_assert!(record.state.can_become(to), "{0} -> {1}", record.state, to);
+	record.state = to;
+	journal.update(dst_filename, record)?;
+	Ok(to)
+}
+
+/* Result of moving a file */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct MoveOutcome {
+	pub method: MoveMethod,
+	/* State the file ended up in */
+	pub state: MoveState,
+	/* State it was in at the start of the run (if it had been started on before) */
+	pub resumed_from: Option<MoveState>,
+}
+
+/* Maximum number of times a file gets copied, when the copies don't verify */
+const MAX_COPY_ATTEMPTS: usize = 2;
+
+/* Move a file, picking up from wherever an earlier (interrupted) run got to
+ * - dst_filename: Name of the destination file (which the journal is keyed by)
+ */
+pub fn move_file(journal: &mut MoveJournal, dst_filename: &str, src: &Path, dst: &Path, options: &MoveOptions)
+	-> Result<MoveOutcome, String>
+{
+	let resumed_from = journal.get(dst_filename)
+	                          .filter(|r| r.source == src.display().to_string())
+	                          .map(|r| r.state);
+
+	let mut attempts = 0;
+	loop {
+		let before = journal.get(dst_filename).map(|r| r.state);
+		let state = advance(journal, dst_filename, src, dst, options)?;
+
+		if state.is_finished(options.delete_sources) {
+			let method = journal.get(dst_filename).map_or(MoveMethod::Copy, |r| r.method);
+			return Ok(MoveOutcome { method: method, state: state, resumed_from: resumed_from });
+		}
+		if state == MoveState::Pending && before.is_some() && before != Some(MoveState::Pending) {
+			attempts += 1;
+			if attempts >= MAX_COPY_ATTEMPTS {
+				return Err(format!("Copy of {0:?} didn't match the original after {1} attempts", src, MAX_COPY_ATTEMPTS));
+			}
+		}
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_utils::TempDir;
+
+	const CONTENT: &'static [u8] = b"ID3 - not really an mp3, but it'll do";
+
+	fn move_options(delete_sources: bool) -> MoveOptions
+	{
+		MoveOptions { delete_sources: delete_sources }
+	}
+
+	#[test]
+	fn test_state_transitions()
+	{
+		use self::MoveState::*;
+
+		assert_eq!(Some(Copied), Pending.next());
+		assert_eq!(Some(Verified), Copied.next());
+		assert_eq!(Some(SourceDeleted), Verified.next());
+		assert_eq!(None, SourceDeleted.next());
+
+		/* Forwards one step at a time */
+		assert!(Pending.can_become(Copied));
+		assert!(!Pending.can_become(Verified));
+		assert!(!Copied.can_become(SourceDeleted));
+		assert!(!Verified.can_become(Copied));
+
+		/* Copies can be redone, until the source is gone */
+		assert!(Copied.can_become(Pending));
+		assert!(Verified.can_become(Pending));
+		assert!(!SourceDeleted.can_become(Pending));
+
+		assert!(SourceDeleted.is_finished(true));
+		assert!(!Verified.is_finished(true));
+		assert!(Verified.is_finished(false));
+		assert!(!Copied.is_finished(false));
+
+		assert_eq!("\"source_deleted\"", serde_json::to_string(&SourceDeleted).unwrap());
+	}
+
+	#[test]
+	fn test_move_file()
+	{
+		let dir = TempDir::new("move-file");
+		let src = dir.write_file("old/20170403/v01-tranquil.mp3", CONTENT);
+		fs::create_dir_all(dir.path().join("new")).unwrap();
+		let dst = dir.path().join("new/Track_01.mp3");
+
+		let mut journal = MoveJournal::load(&dir.path().join("new")).unwrap();
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &move_options(true)).unwrap();
+		assert_eq!((MoveState::SourceDeleted, None), (outcome.state, outcome.resumed_from));
+
+		assert!(!src.exists());
+		assert_eq!(CONTENT, &fs::read(&dst).unwrap()[..]);
+
+		/* The journal got saved, and running again has nothing left to do */
+		let mut journal = MoveJournal::load(&dir.path().join("new")).unwrap();
+		assert_eq!(Some(CONTENT.len() as u64), journal.get("Track_01.mp3").unwrap().size);
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &move_options(true)).unwrap();
+		assert_eq!((MoveState::SourceDeleted, Some(MoveState::SourceDeleted)), (outcome.state, outcome.resumed_from));
+	}
+
+	/* "--no-delete" leaves the sources alone (never renaming them), and a later run finishes the job */
+	#[test]
+	fn test_no_delete()
+	{
+		let dir = TempDir::new("move-no-delete");
+		let src = dir.write_file("old/v01-tranquil.mp3", CONTENT);
+		let dst = dir.path().join("Track_01.mp3");
+
+		let mut journal = MoveJournal::load(dir.path()).unwrap();
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &move_options(false)).unwrap();
+		assert_eq!(MoveOutcome { method: MoveMethod::Copy, state: MoveState::Verified, resumed_from: None }, outcome);
+		assert!(src.exists() && dst.exists());
+
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &move_options(true)).unwrap();
+		assert_eq!((MoveMethod::Copy, MoveState::SourceDeleted), (outcome.method, outcome.state));
+		assert!(!src.exists());
+	}
+
+	/* Interrupted after the copy was verified, but before the source was deleted */
+	#[test]
+	fn test_resume_after_verified()
+	{
+		let dir = TempDir::new("move-resume");
+		let src = dir.write_file("old/v01-tranquil.mp3", CONTENT);
+		let dst = dir.path().join("Track_01.mp3");
+		let options = move_options(true);
+
+		{
+			/* Force a copy (the temp dir is on one filesystem, so it'd be renamed otherwise) */
+			let mut journal = MoveJournal::load(dir.path()).unwrap();
+			journal.update("Track_01.mp3", MoveRecord::new(&src, MoveMethod::Copy)).unwrap();
+
+			assert_eq!(MoveState::Copied, advance(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap());
+			assert_eq!(MoveState::Verified, advance(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap());
+			/* ... and the process dies here */
+		}
+		assert!(src.exists() && dst.exists());
+
+		let mut journal = MoveJournal::load(dir.path()).unwrap();
+		assert_eq!(MoveState::Verified, journal.get("Track_01.mp3").unwrap().state);
+
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap();
+		assert_eq!(MoveOutcome { method: MoveMethod::Copy, state: MoveState::SourceDeleted, resumed_from: Some(MoveState::Verified) },
+		           outcome);
+
+		/* Nothing lost */
+		assert!(!src.exists());
+		assert_eq!(CONTENT, &fs::read(&dst).unwrap()[..]);
+		assert!(!convert_resume::partial_path(&dst).exists());
+	}
+
+	/* Copies that don't match (or went missing) get redone, rather than the source being deleted */
+	#[test]
+	fn test_bad_copy_redone()
+	{
+		let dir = TempDir::new("move-bad-copy");
+		let src = dir.write_file("old/v01-tranquil.mp3", CONTENT);
+		let dst = dir.path().join("Track_01.mp3");
+		let options = move_options(true);
+
+		let mut journal = MoveJournal::load(dir.path()).unwrap();
+		journal.update("Track_01.mp3", MoveRecord::new(&src, MoveMethod::Copy)).unwrap();
+		advance(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap();
+
+		/* Corrupted before being verified */
+		fs::write(&dst, b"ID3 - not really an mp3, but it'll dx").unwrap();
+		assert_eq!(MoveState::Pending, advance(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap());
+		assert!(!dst.exists() && src.exists());
+
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap();
+		assert_eq!(MoveState::SourceDeleted, outcome.state);
+		assert_eq!(CONTENT, &fs::read(&dst).unwrap()[..]);
+
+		/* Removed after being verified (while the source is still there) */
+		let src = dir.write_file("old/v02-moose.mp3", CONTENT);
+		let dst = dir.path().join("Track_02.mp3");
+		journal.update("Track_02.mp3", MoveRecord::new(&src, MoveMethod::Copy)).unwrap();
+		advance(&mut journal, "Track_02.mp3", &src, &dst, &move_options(false)).unwrap();
+		advance(&mut journal, "Track_02.mp3", &src, &dst, &move_options(false)).unwrap();
+		fs::remove_file(&dst).unwrap();
+
+		assert_eq!(MoveState::Pending, advance(&mut journal, "Track_02.mp3", &src, &dst, &options).unwrap());
+		assert!(src.exists());
+	}
+
+	/* Files on the same filesystem get renamed, and a rename that wasn't recorded gets picked up */
+	#[cfg(unix)]
+	#[test]
+	fn test_rename()
+	{
+		let dir = TempDir::new("move-rename");
+		let src = dir.write_file("old/v01-tranquil.mp3", CONTENT);
+		let dst = dir.path().join("Track_01.mp3");
+		let options = move_options(true);
+
+		assert_eq!(Some(true), same_filesystem(&src, dir.path()));
+		assert_eq!(MoveMethod::Rename, choose_method(&src, &dst, &options));
+		assert_eq!(MoveMethod::Copy, choose_method(&src, &dst, &move_options(false)));
+
+		let mut journal = MoveJournal::load(dir.path()).unwrap();
+		let outcome = move_file(&mut journal, "Track_01.mp3", &src, &dst, &options).unwrap();
+		assert_eq!((MoveMethod::Rename, MoveState::SourceDeleted), (outcome.method, outcome.state));
+		assert_eq!(CONTENT, &fs::read(&dst).unwrap()[..]);
+
+		/* Renamed, but interrupted before that was recorded */
+		let src = dir.write_file("old/v02-moose.mp3", CONTENT);
+		let dst = dir.path().join("Track_02.mp3");
+		let mut record = MoveRecord::new(&src, MoveMethod::Rename);
+		record.size = Some(CONTENT.len() as u64);
+		journal.update("Track_02.mp3", record).unwrap();
+		fs::rename(&src, &dst).unwrap();
+
+		let outcome = move_file(&mut journal, "Track_02.mp3", &src, &dst, &options).unwrap();
+		assert_eq!((MoveState::SourceDeleted, Some(MoveState::Pending)), (outcome.state, outcome.resumed_from));
+	}
+
+	#[test]
+	fn test_journal_errors()
+	{
+		let dir = TempDir::new("move-journal");
+		let src = dir.write_file("old/v01-tranquil.mp3", CONTENT);
+		let other = dir.write_file("old/v01-other.mp3", CONTENT);
+		let dst = dir.path().join("Track_01.mp3");
+
+		/* Missing sources */
+		let mut journal = MoveJournal::load(dir.path()).unwrap();
+		let e = move_file(&mut journal, "Track_01.mp3", &dir.path().join("old/missing.mp3"), &dst, &move_options(true)).unwrap_err();
+		assert!(e.contains("doesn't exist"), "{}", e);
+
+		/* Destinations that already hold another file */
+		move_file(&mut journal, "Track_01.mp3", &src, &dst, &move_options(false)).unwrap();
+		let e = move_file(&mut journal, "Track_01.mp3", &other, &dst, &move_options(true)).unwrap_err();
+		assert!(e.contains("was already moved there"), "{}", e);
+
+		/* Unreadable journals aren't ignored */
+		fs::write(dir.path().join(MOVE_JOURNAL_FILENAME), b"{ not json").unwrap();
+		assert!(MoveJournal::load(dir.path()).unwrap_err().contains("Couldn't read the move journal"));
+	}
+}
@@ -3,17 +3,23 @@
  */
 //#[macro_use] extern crate lazy_static;
 extern crate regex;
-use self::regex::Regex;
+use self::regex::{Captures, Regex};
 
 use std::fmt;
 use std::str::FromStr;
 use std::path::Path;
 
+use serde_json::Value;
+
+#[cfg(test)]
+use std::cell::Cell;
+
 /* *************************************************** */
 /* Track Types */
 #[derive(Serialize, Deserialize)]
 #[derive(PartialEq)]
 #[derive(Debug)]
+#[derive(Clone, Copy)]
 pub enum TrackType {
 	UnknownType,
 	ViolinLayering,
@@ -48,6 +54,32 @@ impl TrackType {
 	}
 }
 
+/* All the track types (in the order they're defined) */
+pub const TRACK_TYPES: [TrackType; 5] = [
+	TrackType::UnknownType,
+	TrackType::ViolinLayering,
+	TrackType::MuseScore,
+	TrackType::Piano,
+	TrackType::Voice,
+];
+
+/* Usage: "ViolinLayering".parse::<TrackType>() (or its shortname, e.g. "VL" - case insensitive) */
+impl FromStr for TrackType {
+	type Err = String;
+	
+	fn from_str(s: &str) -> Result<TrackType, Self::Err>
+	{
+		let name = s.trim().to_lowercase();
+		match TRACK_TYPES.iter().find(|t| format!("{:?}", t).to_lowercase() == name || t.shortname().to_lowercase() == name) {
+			Some(t) => Ok(*t),
+			None => {
+				let names: Vec<String> = TRACK_TYPES.iter().map(|t| format!("{0:?} ({1})", t, t.shortname())).collect();
+				Err(format!("Unknown track type '{0}' (expected one of: {1})", s, names.join(", ")))
+			}
+		}
+	}
+}
+
 /* *************************************************** */
 /* Filename Extension */
 #[derive(Serialize, Deserialize)]
@@ -62,7 +94,9 @@ pub enum TrackExtension {
 	/* Unknown Type - The full extension string is kept, so it can be queried/changed later */
 	Unknown(String),
 	
-	// TODO: Does "no extension" warrant an entry?
+	/* No Extension - e.g. "README", or a directory that ended up in the playlist */
+	#[serde(rename = "")]
+	None,
 	
 	mp3,
 	flac,
@@ -88,21 +122,22 @@ impl FromStr for TrackExtension {
 			"mp4"  => Ok(TrackExtension::mp4),
 			"mkv"  => Ok(TrackExtension::mkv),
 			
-			""     => Err("No Extension?"), // Does this case ever happen? This dummy case is so that Unknown() can catch everything else
+			""     => Ok(TrackExtension::None),
 			_      => Ok(TrackExtension::Unknown(s.to_string())),
 		}
 	}
 }
 
-impl ToString for TrackExtension {
-	fn to_string(&self) -> String
+impl fmt::Display for TrackExtension {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
 	{
 		match self {
 			/* Special Cases */
-			TrackExtension::Unknown(s) => s.to_string(),
+			TrackExtension::Unknown(s) => write!(f, "{}", s),
+			TrackExtension::None       => Ok(()),
 			
 			/* Standard Case - Use derived solution */
-			_ => format!("{:?}", self).to_string()
+			_ => write!(f, "{:?}", self)
 		}
 	}
 }
@@ -114,121 +149,302 @@ impl ToString for TrackExtension {
  * contained within track filenames
  */
 #[derive(Serialize, Deserialize)]
+#[derive(Clone)]
 pub struct FilenameInfoComponents {
 	/* Track Type */
 	pub track_type : TrackType,
 	/* Sequence Index in that day's sessions */
 	pub index : i32,
+	/* Variant letter following the index (e.g. the "b" in "v03b") - or the date for MuseScore - if there is one */
+	#[serde(default)]
+	pub variant : Option<String>,
 	
 	/* Descriptive name (all underscores/symbols get normalised out) */
 	pub name: String,
 	
+	/* Revision marker following the name (e.g. the "v2" in "20170821-03-MajesticSerenade-v2") - only for MuseScore */
+	#[serde(default)]
+	pub revision : Option<String>,
+	
+	/* Date embedded in the filename, as "YYYYMMDD" (e.g. the "20170802" in "20170802-02-TouchedByAnAngel") - only for MuseScore */
+	#[serde(default)]
+	pub date : Option<String>,
+	
 	/* filename extension */
 	pub extn : TrackExtension
 }
 
+/* JSON Schema for FilenameInfoComponents (see json_schema) */
+pub fn info_schema() -> Value
+{
+	json!({
+		"type": "object",
+		"properties": {
+			"track_type": { "enum": ["UnknownType", "ViolinLayering", "MuseScore", "Piano", "Voice"] },
+			"index": { "type": "integer" },
+			"variant": { "type": ["string", "null"] },
+			"name": { "type": "string" },
+			"revision": { "type": ["string", "null"] },
+			"date": { "description": "YYYYMMDD", "type": ["string", "null"] },
+			"extn": {
+				"oneOf": [
+					{ "enum": ["Placeholder", "", "mp3", "flac", "ogg", "m4a", "mp4", "mkv"] },
+					{
+						"description": "Any other extension",
+						"type": "object",
+						"properties": { "Unknown": { "type": "string" } },
+						"required": ["Unknown"],
+						"additionalProperties": false
+					}
+				]
+			}
+		},
+		"required": ["track_type", "index", "variant", "name", "revision", "date", "extn"],
+		"additionalProperties": false
+	})
+}
+
+/* Number of filenames analysed so far on this thread (so that tests can check when it happens) */
+#[cfg(test)]
+thread_local! {
+	pub static ANALYSIS_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+/* *************************************************** */
+/* Filename Rules
+ *
+ * Each style of filename we know about gets a rule, saying how to recognise it,
+ * and how to get the info out of it. Rules get tried in priority order (highest first),
+ * with the first one to match winning - so rules for more specific patterns need
+ * higher priorities than any more general ones that could also match them.
+ */
+struct NameRule {
+	/* Name of the rule (only needed by the tests, to check which rule each filename is handled by) */
+	#[cfg(test)]
+	name: &'static str,
+	/* Rules with higher priorities get tried first */
+	priority: i32,
+	/* Pattern that filenames (minus the extension) must match */
+	regex: Regex,
+	/* Fill out the stub instance from the captures of a match */
+	extract: fn(&Captures) -> FilenameInfoComponents,
+}
+
+/* Violin Layering - e.g. "v02-celestial", "v03b-outcrop", "vln_improv_04-mystique" */
+fn extract_violin_layering(caps: &Captures) -> FilenameInfoComponents
+{
+	extract_numbered_take(caps, TrackType::ViolinLayering)
+}
+
+/* Piano - e.g. "p03-evening_rain", "p01b-drift", "piano_improv_02-nocturne" */
+fn extract_piano(caps: &Captures) -> FilenameInfoComponents
+{
+	extract_numbered_take(caps, TrackType::Piano)
+}
+
+/* Voice - e.g. "vc01-warmup", "voice_02-harmony_test" */
+fn extract_voice(caps: &Captures) -> FilenameInfoComponents
+{
+	extract_numbered_take(caps, TrackType::Voice)
+}
+
+/* Shared by the styles of filename that are "<prefix><index><variant>-<title>" (with the title being optional) */
+fn extract_numbered_take(caps: &Captures, track_type: TrackType) -> FilenameInfoComponents
+{
+	let index = caps["index"].parse::<i32>()
+	                         .unwrap_or_default();
+	
+	let name : &str =   if let Some(x) = caps.name("id") {
+	                        x.as_str() // XXX: Prettify
+	                    }
+	                    else {
+	                        "<Untitled>"
+	                    };
+	
+	FilenameInfoComponents {
+		track_type : track_type,
+		index : index,
+		variant : caps.name("variant").map(|x| x.as_str().to_string()),
+		name : name.to_string(),
+		revision : None,
+		date : None,
+		extn : TrackExtension::Placeholder,
+	}
+}
+
+/* MuseScore - e.g. "20170802-02-TouchedByAnAngel", "20170821-03-MajesticSerenade-v2", "20170801-01" */
+fn extract_muse_score(caps: &Captures) -> FilenameInfoComponents
+{
+	let index = caps["index"].parse::<i32>()
+	                         .unwrap_or_default();
+	let name  = caps.name("id").map_or("<Untitled>", |x| x.as_str()); // XX: Prettify
+	
+	FilenameInfoComponents {
+		track_type : TrackType::MuseScore,
+		index : index,
+		variant : caps.name("variant").map(|x| x.as_str().to_string()), /* NOTE: This follows the date (e.g. "20170802b"), not the index */
+		name : name.to_string(),
+		revision : caps.name("revision").map(|x| x.as_str().to_string()),
+		date : Some(caps["date"].to_string()),
+		extn : TrackExtension::Placeholder,
+	}
+}
+
+lazy_static! {
+	/* All the built-in rules, in the order they get tried
+	 * - Multi-line expression strings with (?:x) will ignore all whitespace
+	 *   (which is useful, as those are the most complex)
+	 */
+	static ref NAME_RULES : Vec<NameRule> = {
+		let mut rules = vec![
+			NameRule {
+				#[cfg(test)]
+				name: "violin-layering",
+				priority: 20,
+				regex: Regex::new(r"(?x)                                            # Ignore whitespace
+				                    ^
+				                    (?: v |                                         # Non-Capturing; Modern style prefix, OR
+				                            (?: (?: vln_layering | vln_improv)      # Non-Capturing;   Old long-style prefixes
+				                                (?: - | _) ))                       # Non-Capturing;       vln_improv uses '_', while everyone else uses '-'
+				                    (?P<index>\d+)(?P<variant>[[:alpha:]])?         # e.g. 02, 03b, etc.
+				                    
+				                    (?: (?:                                         # Optional Non-Capturing Group - Optional as titles may be missing. Usually exists though.
+				                             -                                      #     Separator before title
+				                             (?P<id>.+)                             #     Track Title - e.g. 'celestial', 'the_last_moose'
+				                         )? )                                       # Mark the previous two elements as being part of a single optional group
+				                    $").unwrap(),
+				extract: extract_violin_layering,
+			},
+			NameRule {
+				#[cfg(test)]
+				name: "piano",
+				priority: 20,
+				regex: Regex::new(r"(?x)                                            # Ignore whitespace
+				                    ^
+				                    (?: p |                                         # Non-Capturing; Modern style prefix, OR
+				                            (?: piano_improv_ | piano- ))           # Non-Capturing;   Old long-style prefixes
+				                    (?P<index>\d+)(?P<variant>[[:alpha:]])?         # e.g. 02, 03b, etc.
+				                    (?: - (?P<id>.+) )?                             # Optional Non-Capturing Group - Separator + Track Title (may be missing)
+				                    $").unwrap(),
+				extract: extract_piano,
+			},
+			NameRule {
+				#[cfg(test)]
+				name: "voice",
+				priority: 20,
+				regex: Regex::new(r"(?x)                                            # Ignore whitespace
+				                    ^
+				                    (?: vc |                                        # Non-Capturing; Modern style prefix, OR
+				                            (?: voice_ | voice- ))                  # Non-Capturing;   Old long-style prefixes
+				                    (?P<index>\d+)(?P<variant>[[:alpha:]])?         # e.g. 02, 03b, etc.
+				                    (?: - (?P<id>.+) )?                             # Optional Non-Capturing Group - Separator + Track Title (may be missing)
+				                    $").unwrap(),
+				extract: extract_voice,
+			},
+			NameRule {
+				#[cfg(test)]
+				name: "musescore",
+				priority: 10,
+				regex: Regex::new(r"(?x)                                            # Ignore whitespace
+				                    ^
+				                    (?P<date>\d{8})(?P<variant>[[:alpha:]])?        # e.g. 20170802, 20170802b
+				                    -(?P<index>\d+)                                 # e.g. -02
+				                    (?: - (?P<id>.+?) )?                            # Optional Non-Capturing Group - Separator + Title (may be missing)
+				                    (?: - (?P<revision>(?i: v\d+ | wip | final | take\d+ )) )?   # Optional Revision Postfix - e.g. -v2, -WIP, -final, -take3
+				                    $").unwrap(),
+				extract: extract_muse_score,
+			},
+		];
+		
+		/* Highest priority first (keeping the order above for any with the same priority) */
+		rules.sort_by_key(|rule| -rule.priority);
+		rules
+	};
+}
+
+/* Find the rule that the given filename (minus the extension) gets handled by */
+fn find_rule<'t>(filename: &'t str) -> Option<(&'static NameRule, Captures<'t>)>
+{
+	NAME_RULES.iter()
+	          .find_map(|rule| rule.regex.captures(filename).map(|caps| (rule, caps)))
+}
+
+/* Date embedded in the filename (e.g. the "20170802" in MuseScore's "20170802-02-TouchedByAnAngel.mp3"), if it has one
+ * NOTE: This only runs the rules (not the full analysis done by FilenameInfoComponents::new())
+ */
+pub fn embedded_date(filename: &str) -> Option<String>
+{
+	let stem = Path::new(filename).file_stem()?.to_string_lossy();
+	find_rule(&stem).and_then(|(_, caps)| caps.name("date").map(|m| m.as_str().to_string()))
+}
+
 impl FilenameInfoComponents {
-	/* Internal-Use Constructor - Run regexes on a name string (minus the extension)
+	/* Internal-Use Constructor - Run the rules on a name string (minus the extension)
 	 * and generate a stub instance with the affected fields filled out
 	 */
 	fn from_file_stem(filename: &str) -> Self
 	{
-		/* Defines for the regex expressions to use
-		 * - All get initialised on first run, then can be accessed readily later
-		 * - Multi-line expression strings with (?:x) will ignore all whitespace
-		 *   (which is useful, as those are the most complex)
-		 */
-		lazy_static! {
-			/* Violin Layering */
-			static ref RE_VIOLIN_LAYERING : Regex   = Regex::new(r"(?x)                                            # Ignore whitespace
-			                                                       ^
-			                                                       (?: v |                                         # Non-Capturing; Modern style prefix, OR
-			                                                               (?: (?: vln_layering | vln_improv)      # Non-Capturing;   Old long-style prefixes
-			                                                                   (?: - | _) ))                       # Non-Capturing;       vln_improv uses '_', while everyone else uses '-'
-			                                                       (?P<index>\d+)(?P<variant>[[:alpha:]]?)         # e.g. 02, 03b, etc.
-			                                                       
-			                                                       (?: (?:                                         # Optional Non-Capturing Group - Optional as titles may be missing. Usually exists though.
-			                                                                -                                      #     Separator before title
-			                                                                (?P<id>.+)                             #     Track Title - e.g. 'celestial', 'the_last_moose'
-			                                                            )? )                                       # Mark the previous two elements as being part of a single optional group
-			                                                       $").unwrap();
-			
-			
-			/* Muse Score */
-			static ref RE_MUSE_SCORE : Regex        = Regex::new(r"^(?P<date>\d{8})(?P<variant>[[:alpha:]]?)-(?P<index>\d+)-(?P<id>.+)$").unwrap();
+		match find_rule(filename) {
+			Some((rule, caps)) => (rule.extract)(&caps),
+			None               => Self::unknown(filename)
 		}
-		
-		/* Try each of the regex'es to find a match */
-		if let Some(vcap) = RE_VIOLIN_LAYERING.captures(filename) {
-			/* return Violin Layering case */
-			let index = vcap["index"].parse::<i32>()
-									 .unwrap_or_default();
-			
-			let name : &str =   if let Some(x) = vcap.name("id") {
-									x.as_str() // XXX: Prettify
-								}
-								else {
-									"<Untitled>"
-								};
-			
-			FilenameInfoComponents {
-				track_type : TrackType::ViolinLayering,
-				index : index,
-				name : name.to_string(),
-				extn : TrackExtension::Placeholder,
-			}
-		}
-		else if let Some(mcap) = RE_MUSE_SCORE.captures(filename) {
-			/* return MuseScore case */
-			let index = mcap["index"].parse::<i32>()
-									 .unwrap_or_default();
-			let name  = mcap["id"].to_string(); // XX: Prettify
-			
-			FilenameInfoComponents {
-				track_type : TrackType::MuseScore,
-				index : index,
-				name : name,
-				extn : TrackExtension::Placeholder,
-			}
-		}
-		else {
-			let track_type = TrackType::UnknownType;
-			let index = 0;
-			let name = filename.to_string(); //String::new();
-			
-			/* Return new instance */
-			FilenameInfoComponents {
-				track_type : track_type,
-				index : index,
-				name : name.to_string(),
-				extn : TrackExtension::Placeholder,
-			}
+	}
+	
+	/* Unknown - Nothing could be learned, so the name is everything */
+	pub fn unknown(name: &str) -> Self
+	{
+		FilenameInfoComponents {
+			track_type : TrackType::UnknownType,
+			index : 0,
+			variant : None,
+			name : name.to_string(),
+			revision : None,
+			date : None,
+			extn : TrackExtension::None,
 		}
 	}
 	
 	
-	/* Constructor from filename */
-	pub fn new(filename: &str) -> Self
+	/* Constructor from filename
+	 * > returns an error for names that don't have a filename in them at all (e.g. "", "..", or "/")
+	 */
+	pub fn new(filename: &str) -> Result<Self, String>
 	{
+		#[cfg(test)]
+		ANALYSIS_COUNT.with(|count| count.set(count.get() + 1));
+		
 		/* Use Path to split the "name" portion from the extension */
 		let path = Path::new(filename);
-		let name_part: &str = path.file_stem().unwrap()  /* OsString - This should be ok to unwrap like this */
-								  .to_str().unwrap();    /* &str - Need to unwrap the converted version to get what we need */
+		let name_part = match path.file_stem() {
+			Some(stem) => stem.to_string_lossy(),    /* Cow<str> - Any invalid bytes get replaced */
+			None       => return Err(format!("'{}' doesn't have a filename to parse", filename))
+		};
 		
 		/* Generate the stub instance, with all the name-parts filled out */
-		let mut fic = Self::from_file_stem(name_part);
+		let mut fic = Self::from_file_stem(&name_part);
 		
-		/* Extract the extension info */
-		let extn_str = path.extension().unwrap()    /* get OsString */
-						   .to_str().unwrap();      /* get &str - Need to unwrap the converted version */
-		let extn = extn_str.parse::<TrackExtension>()
-						   .unwrap();               /* get contents of mandatory Result */
+		/* Extract the extension info (files without one get TrackExtension::None) */
+		let extn = match path.extension() {
+			Some(extn_str) => extn_str.to_string_lossy()       /* get Cow<str> - Any invalid bytes get replaced */
+			                          .parse::<TrackExtension>()
+			                          .map_err(|e| format!("Invalid extension in '{0}' - {1}", filename, e))?,
+			None           => TrackExtension::None
+		};
 		
 		/* ... and set extension now */
 		fic.extn = extn;
 		
 		/* Return new instance */
-		fic
+		Ok(fic)
+	}
+	
+	/* Name along with any revision marker (e.g. "MajesticSerenade-v2"), so that different renders of a piece can be told apart */
+	pub fn name_with_revision(&self) -> String
+	{
+		match self.revision {
+			Some(ref revision) => format!("{0}-{1}", self.name, revision),
+			None               => self.name.clone()
+		}
 	}
 }
 
@@ -236,11 +452,18 @@ impl fmt::Debug for FilenameInfoComponents {
 	/* Display key info from FilenameInfoComponents */
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
 	{
-		write!(f, r"[{0}]  idx={1}, n='{2}', ext={3:?}",
+		/* Files without an extension show it as empty, instead of as "None" */
+		let extn = match self.extn {
+			TrackExtension::None => "''".to_string(),
+			ref extn             => format!("{:?}", extn),
+		};
+		
+		write!(f, r"[{0}]  idx={1}{2}, n='{3}', ext={4}",
 			   self.track_type.shortname(),
 			   self.index,
+			   self.variant.as_deref().unwrap_or(""),
 			   self.name,
-			   self.extn)
+			   extn)
 	}
 }
 
@@ -281,8 +504,8 @@ mod tests {
 		assert_eq!(TrackExtension::Unknown("aac".to_string()),       "aac".parse::<TrackExtension>().unwrap());
 		assert_eq!(TrackExtension::Unknown("apple_pie".to_string()), "apple_pie".parse::<TrackExtension>().unwrap());
 		
-		/* For the dummy "Err" case that Result<T, E> must have for the FromStr trait */
-		assert_eq!(Err("No Extension?"),                             "".parse::<TrackExtension>());
+		/* No extension at all */
+		assert_eq!(TrackExtension::None,                             "".parse::<TrackExtension>().unwrap());
 	}
 	
 	/* Check TrackExtension enum->string conversion works as intended */
@@ -295,6 +518,47 @@ mod tests {
 		assert_eq!("m4a",   TrackExtension::m4a.to_string());
 		assert_eq!("mkv",   TrackExtension::mkv.to_string());
 		assert_eq!("mp4",   TrackExtension::mp4.to_string());
+		assert_eq!("",      TrackExtension::None.to_string());
+	}
+	
+	/* Files without extensions don't cause any problems */
+	#[test]
+	fn test_no_filename_extension()
+	{
+		let info = FilenameInfoComponents::new("README").unwrap();
+		assert_eq!((TrackType::UnknownType, "README"), (info.track_type, info.name.as_str()));
+		assert_eq!(TrackExtension::None, info.extn);
+		assert_eq!("[?]  idx=0, n='README', ext=''", format!("{:?}", info));
+		
+		let info = FilenameInfoComponents::new("v02-celestial").unwrap();
+		assert_eq!((TrackType::ViolinLayering, 2, TrackExtension::None), (info.track_type, info.index, info.extn));
+	}
+	
+	/* Names without a filename in them are refused (instead of panicking) */
+	#[test]
+	fn test_no_filename()
+	{
+		for &name in ["", "..", "/", "music/.."].iter() {
+			assert!(FilenameInfoComponents::new(name).is_err(), "{:?}", name);
+		}
+		
+		/* Only the last part of a path gets looked at */
+		let info = FilenameInfoComponents::new("music/20170403/v05L-wild_west.mp3").unwrap();
+		assert_eq!((TrackType::ViolinLayering, 5, "wild_west"), (info.track_type, info.index, info.name.as_str()));
+	}
+	
+	/* Track types can be given by name or shortname */
+	#[test]
+	fn test_tracktype_from_str()
+	{
+		assert_eq!(Ok(TrackType::Voice),          "Voice".parse::<TrackType>());
+		assert_eq!(Ok(TrackType::ViolinLayering), "violinlayering".parse::<TrackType>());
+		assert_eq!(Ok(TrackType::ViolinLayering), "VL".parse::<TrackType>());
+		assert_eq!(Ok(TrackType::MuseScore),      " ms ".parse::<TrackType>());
+		assert_eq!(Ok(TrackType::UnknownType),    "?".parse::<TrackType>());
+		
+		let e = "Violin".parse::<TrackType>().unwrap_err();
+		assert!(e.contains("'Violin'") && e.contains("ViolinLayering (VL)"), "{}", e);
 	}
 	
 	/* Check that the TrackType shortname stuff works as expected */
@@ -317,36 +581,148 @@ mod tests {
 		assert_eq!("V",   TrackType::Voice.shortname_safe());
 	}
 	
+	/* Check that the rules are tried in the right order --------------------------------- */
+	
+	/* Representative filenames for each rule (or None for those that shouldn't match any)
+	 * NOTE: Add examples here whenever a rule gets added, so that any rules shadowing others get caught
+	 */
+	const RULE_CORPUS: &[(&str, Option<&str>)] = &[
+		("v01-tranquil",                    Some("violin-layering")),
+		("v01a-outcrop",                    Some("violin-layering")),
+		("v05L-wild_west",                  Some("violin-layering")),
+		("v02",                             Some("violin-layering")),
+		("vln_improv_04-mystique",          Some("violin-layering")),
+		("vln_improv_01",                   Some("violin-layering")),
+		("vln_layering-05-the_last_moose",  Some("violin-layering")),
+		
+		("20170802-02-TouchedByAnAngel",    Some("musescore")),
+		("20170802b-03-Lullaby",            Some("musescore")),
+		("20170821-03-MajesticSerenade-v2", Some("musescore")),
+		("20170801-01-Patterns-WIP",        Some("musescore")),
+		("20170801-04",                     Some("musescore")),
+		
+		("p03-evening_rain",                Some("piano")),
+		("p01b-drift",                      Some("piano")),
+		("piano_improv_02-nocturne",        Some("piano")),
+		("piano-04",                        Some("piano")),
+		
+		("vc01-warmup",                     Some("voice")),
+		("voice_02-harmony_test",           Some("voice")),
+		("voice-03b",                       Some("voice")),
+		
+		("holiday_recording",               None),
+		("pianola-rolls",                   None),
+		("voice_memo",                      None),
+		("vivaldi-spring",                  None),
+		("2017-08-02-notes",                None),
+	];
+	
+	#[test]
+	fn test_rule_corpus()
+	{
+		for &(stem, expected) in RULE_CORPUS.iter() {
+			let found = find_rule(stem).map(|(rule, _)| rule.name);
+			assert_eq!(expected, found, "'{}' matched the wrong rule", stem);
+		}
+	}
+	
+	/* Every rule needs a unique name, and should have examples in the corpus */
+	#[test]
+	fn test_rules_covered()
+	{
+		for (i, rule) in NAME_RULES.iter().enumerate() {
+			assert!(NAME_RULES.iter().skip(i + 1).all(|other| other.name != rule.name), "Duplicate rule '{}'", rule.name);
+			assert!(RULE_CORPUS.iter().any(|&(_, expected)| expected == Some(rule.name)), "No examples for rule '{}'", rule.name);
+		}
+		
+		/* Highest priority first */
+		assert!(NAME_RULES.windows(2).all(|pair| pair[0].priority >= pair[1].priority));
+	}
+	
 	/* Check that violin-layering filenames parse correctly ----------------------------- */
 	
 	/* Check that simple violin-layering filenames parse correctly */
 	#[test]
 	fn test_violin_basic()
 	{
-		let v1 = FilenameInfoComponents::new("v01-tranquil.mp3");
+		let v1 = FilenameInfoComponents::new("v01-tranquil.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v1.track_type);
 		assert_eq!(1, v1.index);
+		assert_eq!(None, v1.variant.as_deref());
 		assert_eq!("tranquil", v1.name);
 		assert_eq!(TrackExtension::mp3, v1.extn);
 		
-		let v2 = FilenameInfoComponents::new("v02-celestial.mp3");
+		let v2 = FilenameInfoComponents::new("v02-celestial.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v2.track_type);
 		assert_eq!(2, v2.index);
 		assert_eq!("celestial", v2.name);
 		assert_eq!(TrackExtension::mp3, v2.extn);
 		
-		let v3 = FilenameInfoComponents::new("v03-spectral.mp3");
+		let v3 = FilenameInfoComponents::new("v03-spectral.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v3.track_type);
 		assert_eq!(3, v3.index);
 		assert_eq!("spectral", v3.name);
 		assert_eq!(TrackExtension::mp3, v3.extn);
 	}
 	
+	/* Check that basic piano filenames parse correctly */
+	#[test]
+	fn test_piano_basic()
+	{
+		let p1 = FilenameInfoComponents::new("p03-evening_rain.mp3").unwrap();
+		assert_eq!(TrackType::Piano, p1.track_type);
+		assert_eq!(3, p1.index);
+		assert_eq!(None, p1.variant.as_deref());
+		assert_eq!("evening_rain", p1.name);
+		assert_eq!(TrackExtension::mp3, p1.extn);
+		
+		let p2 = FilenameInfoComponents::new("p01b-drift.flac").unwrap();
+		assert_eq!(TrackType::Piano, p2.track_type);
+		assert_eq!(1, p2.index);
+		assert_eq!(Some("b"), p2.variant.as_deref());
+		assert_eq!("drift", p2.name);
+		assert_eq!(TrackExtension::flac, p2.extn);
+		
+		let p3 = FilenameInfoComponents::new("piano_improv_02-nocturne.mp3").unwrap();
+		assert_eq!(TrackType::Piano, p3.track_type);
+		assert_eq!(2, p3.index);
+		assert_eq!("nocturne", p3.name);
+		
+		let p4 = FilenameInfoComponents::new("piano-04.mp3").unwrap();
+		assert_eq!(TrackType::Piano, p4.track_type);
+		assert_eq!(4, p4.index);
+		assert_eq!("<Untitled>", p4.name);
+	}
+	
+	/* Check that basic voice filenames parse correctly */
+	#[test]
+	fn test_voice_basic()
+	{
+		let v1 = FilenameInfoComponents::new("voice_02-harmony_test.flac").unwrap();
+		assert_eq!(TrackType::Voice, v1.track_type);
+		assert_eq!(2, v1.index);
+		assert_eq!(None, v1.variant.as_deref());
+		assert_eq!("harmony_test", v1.name);
+		assert_eq!(TrackExtension::flac, v1.extn);
+		
+		let v2 = FilenameInfoComponents::new("vc01a-warmup.mp3").unwrap();
+		assert_eq!(TrackType::Voice, v2.track_type);
+		assert_eq!(1, v2.index);
+		assert_eq!(Some("a"), v2.variant.as_deref());
+		assert_eq!("warmup", v2.name);
+		assert_eq!(TrackExtension::mp3, v2.extn);
+		
+		let v3 = FilenameInfoComponents::new("voice-03.mp3").unwrap();
+		assert_eq!(TrackType::Voice, v3.track_type);
+		assert_eq!(3, v3.index);
+		assert_eq!("<Untitled>", v3.name);
+	}
+	
 	/* Check that multiword violin layering filenames parse correctly */
 	#[test]
 	fn test_violin_multiword()
 	{
-		let v1 = FilenameInfoComponents::new("v02-winds_of_flutter.mp3");
+		let v1 = FilenameInfoComponents::new("v02-winds_of_flutter.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v1.track_type);
 		assert_eq!(2, v1.index);
 		assert_eq!("winds_of_flutter", v1.name);
@@ -357,17 +733,17 @@ mod tests {
 	#[test]
 	fn test_violin_multiversion()
 	{
-		let v1 = FilenameInfoComponents::new("v01a-outcrop.mp3");
+		let v1 = FilenameInfoComponents::new("v01a-outcrop.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v1.track_type);
 		assert_eq!(1, v1.index);
-		// XXX: Variant numbers are not currently extracted and stored
+		assert_eq!(Some("a"), v1.variant.as_deref());
 		assert_eq!("outcrop", v1.name);
 		assert_eq!(TrackExtension::mp3, v1.extn);
 		
-		let v2 = FilenameInfoComponents::new("v05L-wild_west.mp3");
+		let v2 = FilenameInfoComponents::new("v05L-wild_west.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v2.track_type);
 		assert_eq!(5, v2.index);
-		// XXX: Variant numbers are not currently extracted and stored
+		assert_eq!(Some("L"), v2.variant.as_deref());
 		assert_eq!("wild_west", v2.name);
 		assert_eq!(TrackExtension::mp3, v2.extn);
 	}
@@ -376,7 +752,7 @@ mod tests {
 	#[test]
 	fn test_vln_improv()
 	{
-		let v1 = FilenameInfoComponents::new("vln_improv_04-mystique.mp3");
+		let v1 = FilenameInfoComponents::new("vln_improv_04-mystique.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v1.track_type);
 		assert_eq!(4, v1.index);
 		assert_eq!("mystique", v1.name);
@@ -386,7 +762,7 @@ mod tests {
 	#[test]
 	fn test_vln_improv_no_name()
 	{
-		let v1 = FilenameInfoComponents::new("vln_improv_01.mp3");
+		let v1 = FilenameInfoComponents::new("vln_improv_01.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v1.track_type);
 		assert_eq!(1, v1.index);
 		assert_eq!("<Untitled>", v1.name);
@@ -396,13 +772,13 @@ mod tests {
 	#[test]
 	fn test_vln_layering()
 	{
-		let v1 = FilenameInfoComponents::new("vln_layering-05-the_last_moose.mp3");
+		let v1 = FilenameInfoComponents::new("vln_layering-05-the_last_moose.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v1.track_type);
 		assert_eq!(5, v1.index);
 		assert_eq!("the_last_moose", v1.name);
 		assert_eq!(TrackExtension::mp3, v1.extn);
 		
-		let v2 = FilenameInfoComponents::new("vln_layering-03-delicate.mp3");
+		let v2 = FilenameInfoComponents::new("vln_layering-03-delicate.mp3").unwrap();
 		assert_eq!(TrackType::ViolinLayering, v2.track_type);
 		assert_eq!(3, v2.index);
 		assert_eq!("delicate", v2.name);
@@ -414,31 +790,99 @@ mod tests {
 	#[test]
 	fn test_ms_basic()
 	{
+		let m1 = FilenameInfoComponents::new("20170803-01-Prelude.mp3").unwrap();
+		assert_eq!(TrackType::MuseScore, m1.track_type);
+		assert_eq!(1, m1.index);
+		assert_eq!(None, m1.variant.as_deref());
+		assert_eq!("Prelude", m1.name);
+		assert_eq!(None, m1.revision);
+		assert_eq!(Some("20170803".to_string()), m1.date);
+		assert_eq!(TrackExtension::mp3, m1.extn);
+		
+		/* No name */
+		let m2 = FilenameInfoComponents::new("20170801-04.flac").unwrap();
+		assert_eq!(TrackType::MuseScore, m2.track_type);
+		assert_eq!(4, m2.index);
+		assert_eq!("<Untitled>", m2.name);
+		assert_eq!(None, m2.revision);
+		assert_eq!(Some("20170801".to_string()), m2.date);
 		
+		/* Other types don't have dates in their names */
+		assert_eq!(None, FilenameInfoComponents::new("v01-tranquil.mp3").unwrap().date);
 	}
 	
 	#[test]
 	fn test_ms_multiword()
 	{
-		let m1 = FilenameInfoComponents::new("20170802-02-TouchedByAnAngel.flac");
+		let m1 = FilenameInfoComponents::new("20170802-02-TouchedByAnAngel.flac").unwrap();
 		assert_eq!(TrackType::MuseScore, m1.track_type);
 		assert_eq!(2, m1.index);
 		assert_eq!("TouchedByAnAngel", m1.name);
 		assert_eq!(TrackExtension::flac, m1.extn);
 		
-		let m2 = FilenameInfoComponents::new("20170815-05-CanadianBeauty.flac");
+		let m2 = FilenameInfoComponents::new("20170815-05-CanadianBeauty.flac").unwrap();
 		assert_eq!(TrackType::MuseScore, m2.track_type);
 		assert_eq!(5, m2.index);
 		assert_eq!("CanadianBeauty", m2.name);
 		assert_eq!(TrackExtension::flac, m2.extn);
 	}
 	
+	#[test]
+	fn test_ms_multiversion()
+	{
+		let m1 = FilenameInfoComponents::new("20170802b-03-Lullaby.flac").unwrap();
+		assert_eq!(TrackType::MuseScore, m1.track_type);
+		assert_eq!(3, m1.index);
+		assert_eq!(Some("b"), m1.variant.as_deref());
+		assert_eq!("Lullaby", m1.name);
+		
+		let m2 = FilenameInfoComponents::new("20170802-03-Lullaby.flac").unwrap();
+		assert_eq!(None, m2.variant.as_deref());
+	}
+	
+	#[test]
+	fn test_ms_embedded_date()
+	{
+		assert_eq!(Some("20170802".to_string()), embedded_date("20170802b-03-Lullaby.flac"));
+		assert_eq!(Some("20170815".to_string()), embedded_date("20170815-05-CanadianBeauty.mp3"));
+		
+		/* Other types don't have dates in their names */
+		assert_eq!(None, embedded_date("v01-tranquil.mp3"));
+		assert_eq!(None, embedded_date("intro.mp3"));
+	}
+	
 	#[test]
 	fn test_ms_multiversion_postfix()
 	{
 		/* Names where the version is included in a postfix after the name */
-		//"20170821-03-MajesticSerenade-v2.flac"
-		//"20170801-01-Patterns-WIP"
+		let m1 = FilenameInfoComponents::new("20170821-03-MajesticSerenade-v2.flac").unwrap();
+		assert_eq!(TrackType::MuseScore, m1.track_type);
+		assert_eq!(3, m1.index);
+		assert_eq!("MajesticSerenade", m1.name);
+		assert_eq!(Some("v2".to_string()), m1.revision);
+		assert_eq!(Some("20170821".to_string()), m1.date);
+		assert_eq!("MajesticSerenade-v2", m1.name_with_revision());
+		
+		let m2 = FilenameInfoComponents::new("20170801-01-Patterns-WIP.mp3").unwrap();
+		assert_eq!("Patterns", m2.name);
+		assert_eq!(Some("WIP".to_string()), m2.revision);
+		
+		for &(filename, name, revision) in [("20170801b-02-Patterns-final.mp3",  "Patterns",        "final"),
+		                                    ("20170801-02-Patterns-take12.mp3",  "Patterns",        "take12"),
+		                                    ("20170801-02-Two-Part-Song-V10.mp3", "Two-Part-Song",  "V10")].iter() {
+			let info = FilenameInfoComponents::new(filename).unwrap();
+			assert_eq!((name, Some(revision.to_string())), (info.name.as_str(), info.revision), "{}", filename);
+		}
+		
+		/* Only recognised markers count - anything else is part of the name */
+		for &(filename, name) in [("20170801-02-Patterns-v.mp3",       "Patterns-v"),
+		                          ("20170801-02-Patterns-version2.mp3", "Patterns-version2"),
+		                          ("20170801-02-Patterns_v2.mp3",       "Patterns_v2"),
+		                          ("20170801-02-WIP.mp3",               "WIP")].iter() {
+			let info = FilenameInfoComponents::new(filename).unwrap();
+			assert_eq!((name, None), (info.name.as_str(), info.revision.as_deref()), "{}", filename);
+			assert_eq!(name, info.name_with_revision());
+		}
 	}
 }
 
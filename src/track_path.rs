@@ -0,0 +1,815 @@
+/* Locations of tracks, handled the same way on every platform
+ *
+ * Playlists get written on one platform and used on another (e.g. made on Windows with "D:\Music\..." locations,
+ * then copied from on Linux), so the paths in them can't just be handed to the OS as they are. Instead, each
+ * location gets decoded once into a normalized form - the path's bytes, with "/" as the separator - which all
+ * the platform-independent logic (working out the filename and date, resolving relative locations, writing URIs)
+ * works on. Only accessing the file itself uses the native form (see TrackPath::native()).
+ *
+ * NOTE: Paths decoded from "file:///" URIs don't keep their leading slash (e.g. "file:///music/..." becomes
+ *       "music/..."), which is the form that's always been shown in reports and JSON output.
+ */
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/* Prefix of the URIs that tracks can be located with */
+pub const FILE_URI_PREFIX: &'static str = "file:///";
+
+/* Prefix of URIs naming the local machine as the host (same as FILE_URI_PREFIX) */
+const LOCALHOST_URI_PREFIX: &str = "file://localhost/";
+
+/* *************************************************** */
+/* Path Styles */
+
+/* Conventions for writing native paths */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum PathStyle {
+	/* "/" separators only ("\" is just part of a name) */
+	Unix,
+	/* "\" separators (with "/" also accepted), drive letters, and UNC shares */
+	Windows,
+}
+
+impl PathStyle {
+	/* Style used by the platform this is running on */
+	pub fn host() -> PathStyle
+	{
+		if cfg!(windows) { PathStyle::Windows } else { PathStyle::Unix }
+	}
+}
+
+/* What a path starts from */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum PathRoot {
+	/* Relative path - e.g. "2018/v01-take.mp3" */
+	Relative,
+	/* Root directory - e.g. "/srv/music/..." */
+	Root,
+	/* Drive letter - e.g. "D:/Music/..." */
+	Drive(char),
+	/* Network share - e.g. "//server/share/..." */
+	Unc(String, String),
+}
+
+impl PathRoot {
+	/* Work out what a normalized path (i.e. with "/" separators) starts from
+	 * > returns the root, along with the length of the prefix naming it
+	 */
+	fn parse(bytes: &[u8]) -> (PathRoot, usize)
+	{
+		if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && (bytes.len() == 2 || bytes[2] == b'/') {
+			(PathRoot::Drive(bytes[0].to_ascii_uppercase() as char), 2)
+		}
+		else if bytes.starts_with(b"//") && bytes.get(2).is_some_and(|&b| b != b'/') {
+			let rest = &bytes[2 ..];
+			let server_len = rest.iter().position(|&b| b == b'/').unwrap_or(rest.len());
+			let share = if server_len < rest.len() { &rest[server_len + 1 ..] } else { &[][..] };
+			let share_len = share.iter().position(|&b| b == b'/').unwrap_or(share.len());
+
+			let server = String::from_utf8_lossy(&rest[.. server_len]).into_owned();
+			let share_name = String::from_utf8_lossy(&share[.. share_len]).into_owned();
+			let prefix_len = 2 + server_len + if server_len < rest.len() { 1 + share_len } else { 0 };
+			(PathRoot::Unc(server, share_name), prefix_len)
+		}
+		else if bytes.starts_with(b"/") {
+			(PathRoot::Root, 0)
+		}
+		else {
+			(PathRoot::Relative, 0)
+		}
+	}
+
+	pub fn is_absolute(&self) -> bool
+	{
+		*self != PathRoot::Relative
+	}
+}
+
+/* *************************************************** */
+/* Location Forms */
+
+/* Ways that track locations get written in playlists */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum LocationForm {
+	/* "file:///..." URI */
+	AbsoluteUri,
+	/* Plain absolute path - e.g. "/music/...", "D:/Music/...", or "\\server\share\..." */
+	AbsolutePath,
+	/* Path relative to the playlist - e.g. "2018/v01-take.mp3" */
+	RelativePath,
+}
+
+impl LocationForm {
+	/* Work out which form a location is written in
+	 * > returns None for URIs that aren't supported (i.e. anything other than "file:")
+	 */
+	pub fn classify(location: &str) -> Option<LocationForm>
+	{
+		if location.starts_with("file:") {
+			Some(LocationForm::AbsoluteUri)
+		}
+		else if location.contains("://") {
+			None
+		}
+		else if PathRoot::parse(&normalize_separators(location.as_bytes(), PathStyle::Windows)).0.is_absolute() {
+			Some(LocationForm::AbsolutePath)
+		}
+		else {
+			Some(LocationForm::RelativePath)
+		}
+	}
+
+	/* Description of locations in this form (for reports) */
+	pub fn description(&self) -> &'static str
+	{
+		match *self {
+			LocationForm::AbsoluteUri  => "file:/// URIs",
+			LocationForm::AbsolutePath => "absolute paths",
+			LocationForm::RelativePath => "relative paths",
+		}
+	}
+}
+
+/* *************************************************** */
+/* Encoding Helpers */
+
+/* Convert the separators in a path written in the given style to "/" */
+fn normalize_separators(bytes: &[u8], style: PathStyle) -> Vec<u8>
+{
+	match style {
+		PathStyle::Unix    => bytes.to_vec(),
+		PathStyle::Windows => bytes.iter().map(|&b| if b == b'\\' { b'/' } else { b }).collect(),
+	}
+}
+
+/* Decode the "%xx" escapes in a path from a URI
+ * NOTE: The decoded bytes are not guaranteed to be valid UTF-8 (e.g. old files with latin-1 names),
+ *       which is why this doesn't just produce a String.
+ */
+pub fn unquote_path_bytes(s: &str) -> Vec<u8>
+{
+	let bytes = s.as_bytes();
+	let mut result: Vec<u8> = Vec::with_capacity(bytes.len());
+
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' && i + 2 < bytes.len() {
+			let hi = (bytes[i + 1] as char).to_digit(16);
+			let lo = (bytes[i + 2] as char).to_digit(16);
+			if let (Some(hi), Some(lo)) = (hi, lo) {
+				result.push((hi * 16 + lo) as u8);
+				i += 3;
+				continue;
+			}
+		}
+		result.push(bytes[i]);
+		i += 1;
+	}
+	result
+}
+
+/* Apply "%xx" escapes to a path for use in a URI (i.e. the reverse of unquote_path_bytes()) */
+pub fn quote_path_bytes(bytes: &[u8]) -> String
+{
+	let mut result = String::with_capacity(bytes.len());
+	for &b in bytes.iter() {
+		match b {
+			b'A' ..= b'Z' | b'a' ..= b'z' | b'0' ..= b'9' |
+			b'-' | b'.' | b'_' | b'~' | b'/' | b':' => result.push(b as char),
+			_ => result.push_str(&format!("%{:02X}", b)),
+		}
+	}
+	result
+}
+
+/* Bytes of a native path */
+#[cfg(unix)]
+fn native_bytes(path: &Path) -> Vec<u8>
+{
+	use std::os::unix::ffi::OsStrExt;
+	path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn native_bytes(path: &Path) -> Vec<u8>
+{
+	path.to_string_lossy().into_owned().into_bytes()
+}
+
+/* Native path for the bytes of a normalized path */
+#[cfg(unix)]
+fn native_path(bytes: &[u8]) -> PathBuf
+{
+	use std::ffi::OsString;
+	use std::os::unix::ffi::OsStringExt;
+	PathBuf::from(OsString::from_vec(bytes.to_vec()))
+}
+
+#[cfg(not(unix))]
+fn native_path(bytes: &[u8]) -> PathBuf
+{
+	PathBuf::from(native_form(bytes, PathStyle::Windows))
+}
+
+/* Text of a normalized path, written in the given style
+ * NOTE: Native Unix paths are made from the bytes directly (see native_path()), so this is only needed for Windows
+ */
+#[cfg(any(test, not(unix)))]
+fn native_form(bytes: &[u8], style: PathStyle) -> String
+{
+	let text = String::from_utf8_lossy(bytes);
+	match style {
+		PathStyle::Unix    => text.into_owned(),
+		PathStyle::Windows => text.replace('/', "\\"),
+	}
+}
+
+/* *************************************************** */
+/* Track Paths */
+
+/* Location of a track's file */
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct TrackPath {
+	/* Location as written in the playlist (only set for paths parsed from one) */
+	location: Option<String>,
+	/* Decoded path, with "/" separators (may not be valid UTF-8) */
+	bytes: Vec<u8>,
+	/* Lossy UTF-8 version of the path - i.e. the form shown in reports and JSON */
+	text: String,
+	/* Path to use when accessing the file on this platform */
+	native: PathBuf,
+}
+
+impl TrackPath {
+	fn new(bytes: Vec<u8>, location: Option<String>) -> TrackPath
+	{
+		TrackPath {
+			location: location,
+			text: String::from_utf8_lossy(&bytes).into_owned(),
+			native: native_path(&bytes),
+			bytes: bytes,
+		}
+	}
+
+	/* Decode a location from a playlist (a "file:" URI, or a plain path - see LocationForm)
+	 * NOTE: Backslashes get treated as separators (for playlists written on Windows), while escaped ones ("%5C") are kept
+	 */
+	pub fn from_location(location: &str) -> Result<TrackPath, &'static str>
+	{
+		let path = match LocationForm::classify(location) {
+			Some(LocationForm::AbsoluteUri) => TrackPath::from_uri(location)?,
+			Some(_)                         => TrackPath::from_escaped(location),
+			None                            => return Err("Unsupported URI - Only local files ('file:') can be used")
+		};
+		Ok(TrackPath { location: Some(location.to_string()), ..path })
+	}
+
+	/* Decode a "file:" URI
+	 * - "file:///<path>" - Local file (without the leading slash, see the note at the top)
+	 * - "file://localhost/<path>" or "file:/<path>" - Local file (other ways of writing the above)
+	 * - "file:////server/share/<path>" or "file://server/share/<path>" - File on a network share
+	 */
+	pub fn from_uri(uri: &str) -> Result<TrackPath, &'static str>
+	{
+		if let Some(share_path) = uri.strip_prefix("file:////") {
+			Ok(TrackPath::from_escaped(&format!("//{}", share_path)))
+		}
+		else if let Some(path) = uri.strip_prefix(FILE_URI_PREFIX) {
+			Ok(TrackPath::from_uri_path(path))
+		}
+		else if uri.len() > LOCALHOST_URI_PREFIX.len() &&
+		        uri.get(.. LOCALHOST_URI_PREFIX.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(LOCALHOST_URI_PREFIX))
+		{
+			Ok(TrackPath::from_uri_path(&uri[LOCALHOST_URI_PREFIX.len() ..]))
+		}
+		else if uri.starts_with("file:/") && !uri.starts_with("file://") && uri.len() > "file:/".len() {
+			Ok(TrackPath::from_uri_path(&uri["file:/".len() ..]))
+		}
+		else if uri.starts_with("file://") && uri.len() > "file://".len() {
+			Ok(TrackPath::from_escaped(&format!("//{}", &uri["file://".len() ..])))
+		}
+		else {
+			Err("Unsupported URI - Must start with 'file:///'")
+		}
+	}
+
+	/* Decode a path written with "%xx" escapes (as found in URIs) */
+	pub fn from_escaped(path: &str) -> TrackPath
+	{
+		TrackPath::new(unquote_path_bytes(&path.replace('\\', "/")), None)
+	}
+
+	/* Decode the path from a local "file:" URI
+	 * NOTE: Old tools wrote drive letters with a "|" (e.g. "file:///C|/Music/..."), which is the same as "C:"
+	 */
+	fn from_uri_path(path: &str) -> TrackPath
+	{
+		let mut bytes = unquote_path_bytes(&path.replace('\\', "/"));
+		if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b'|' && bytes.get(2).is_none_or(|&b| b == b'/') {
+			bytes[1] = b':';
+		}
+		TrackPath::new(bytes, None)
+	}
+
+	/* Path of a file on this platform */
+	pub fn from_native(path: &Path) -> TrackPath
+	{
+		TrackPath::from_native_bytes(&native_bytes(path), PathStyle::host())
+	}
+
+	/* Path written in the given style (i.e. as it would be on that platform) */
+	pub fn from_native_bytes(bytes: &[u8], style: PathStyle) -> TrackPath
+	{
+		TrackPath::new(normalize_separators(bytes, style), None)
+	}
+
+	/* Path that's already normalized (e.g. the text form, as saved in JSON) */
+	pub fn from_normalized(text: &str) -> TrackPath
+	{
+		TrackPath::new(text.as_bytes().to_vec(), None)
+	}
+
+	/* ................................ */
+
+	/* Normalized form of the path (with any non-UTF-8 bytes replaced) */
+	pub fn as_str(&self) -> &str
+	{
+		&self.text
+	}
+
+	/* Path to use when accessing the file on this platform */
+	pub fn native(&self) -> &Path
+	{
+		&self.native
+	}
+
+	/* How the location was written in the playlist (if it came from one) */
+	pub fn location_form(&self) -> Option<LocationForm>
+	{
+		self.location.as_ref().and_then(|location| LocationForm::classify(location))
+	}
+
+	/* Did the path contain bytes that aren't valid UTF-8? (i.e. as_str() is a lossy version of the real path) */
+	pub fn is_lossy(&self) -> bool
+	{
+		::std::str::from_utf8(&self.bytes).is_err()
+	}
+
+	/* What the path starts from (e.g. a drive letter) */
+	pub fn root(&self) -> PathRoot
+	{
+		PathRoot::parse(&self.bytes).0
+	}
+
+	pub fn is_absolute(&self) -> bool
+	{
+		self.root().is_absolute()
+	}
+
+	/* Names of the directories and file (after the root) */
+	fn components(&self) -> Vec<&[u8]>
+	{
+		let (_, prefix_len) = PathRoot::parse(&self.bytes);
+		self.bytes[prefix_len ..].split(|&b| b == b'/')
+		                         .filter(|c| !c.is_empty() && *c != b".")
+		                         .collect()
+	}
+
+	/* Name of the file (with any non-UTF-8 bytes replaced)
+	 * > returns None if the path doesn't end with a name (e.g. it's a root, or ends with "..")
+	 */
+	pub fn file_name(&self) -> Option<String>
+	{
+		match self.components().last() {
+			Some(&name) if name != b".." => Some(String::from_utf8_lossy(name).into_owned()),
+			_                            => None
+		}
+	}
+
+	/* Name of the directory the file is in (or "" if it's at the root, or the path has no directories) */
+	pub fn parent_name(&self) -> String
+	{
+		let components = self.components();
+		match components.len() {
+			n if n >= 2 => String::from_utf8_lossy(components[n - 2]).into_owned(),
+			_           => String::new()
+		}
+	}
+
+	/* Normalized form of the directory the file is in (or "" if there isn't one) */
+	pub fn parent(&self) -> String
+	{
+		match self.text.rfind('/') {
+			Some(0)   => "/".to_string(),
+			Some(pos) => self.text[.. pos].to_string(),
+			None      => String::new()
+		}
+	}
+
+	/* Path of a file alongside this one (i.e. with the file name replaced) */
+	pub fn with_file_name(&self, name: &str) -> TrackPath
+	{
+		let dir_len = self.bytes.iter().rposition(|&b| b == b'/').map_or(0, |pos| pos + 1);
+		let mut bytes = self.bytes[.. dir_len].to_vec();
+		bytes.extend_from_slice(name.as_bytes());
+		TrackPath::new(bytes, None)
+	}
+
+	/* Path of something inside this directory (e.g. a file to write) */
+	pub fn join(&self, name: &str) -> TrackPath
+	{
+		let other = TrackPath::from_normalized(name);
+		other.resolve_against(self)
+	}
+
+	/* Resolve the path against a base directory, if it's relative (absolute paths are never changed)
+	 * NOTE: The location it came from is kept (so it still counts as a relative location)
+	 */
+	pub fn resolve_against(&self, base: &TrackPath) -> TrackPath
+	{
+		if self.is_absolute() || base.bytes.is_empty() {
+			return self.clone();
+		}
+
+		let mut bytes = base.bytes.clone();
+		if !bytes.ends_with(b"/") {
+			bytes.push(b'/');
+		}
+		let mut rest = &self.bytes[..];
+		while rest.starts_with(b"./") {
+			rest = &rest[2 ..];
+		}
+		bytes.extend_from_slice(rest);
+
+		TrackPath::new(bytes, self.location.clone())
+	}
+
+	/* Names of the directories and file after the root, with any ".." resolved (without looking at the filesystem)
+	 * NOTE: ".." can't go above the root of an absolute path, so these get dropped there (as the OS does)
+	 */
+	fn resolved_components(&self) -> Vec<&[u8]>
+	{
+		let absolute = self.is_absolute();
+		let mut resolved: Vec<&[u8]> = Vec::new();
+		for component in self.components() {
+			if component != b".." {
+				resolved.push(component);
+			}
+			else if resolved.last().is_some_and(|&last| last != b"..") {
+				resolved.pop();
+			}
+			else if !absolute {
+				resolved.push(component);
+			}
+		}
+		resolved
+	}
+
+	/* Path of this one relative to the base directory (e.g. for listing it in a playlist kept in base)
+	 * NOTE: Both paths should be absolute (or both relative to the same place). Any ".." get resolved first,
+	 *       and drive letters (and network server/share names) are compared regardless of case.
+	 * > returns None if there's no way to get from one to the other (i.e. they're on different drives or shares)
+	 */
+	pub fn relative_to(&self, base: &TrackPath) -> Option<String>
+	{
+		let same_root = match (self.root(), base.root()) {
+			(PathRoot::Unc(server, share), PathRoot::Unc(base_server, base_share)) => {
+				server.eq_ignore_ascii_case(&base_server) && share.eq_ignore_ascii_case(&base_share)
+			},
+			(root, base_root) => root == base_root
+		};
+		if !same_root {
+			return None;
+		}
+
+		let components = self.resolved_components();
+		let base_components = base.resolved_components();
+		let common = components.iter().zip(base_components.iter()).take_while(|&(a, b)| a == b).count();
+
+		/* Relative paths can't go back up through any ".." left at the start of the base */
+		if base_components[common ..].iter().any(|&c| c == b"..") {
+			return None;
+		}
+
+		let mut bytes: Vec<u8> = Vec::new();
+		for _ in common .. base_components.len() {
+			bytes.extend_from_slice(b"../");
+		}
+		for component in components[common ..].iter() {
+			bytes.extend_from_slice(component);
+			bytes.push(b'/');
+		}
+		bytes.pop();
+
+		if bytes.is_empty() {
+			Some(".".to_string())
+		}
+		else {
+			Some(String::from_utf8_lossy(&bytes).into_owned())
+		}
+	}
+
+	/* Decode any "%xx" escapes still left in the path (i.e. for locations that got escaped twice, e.g. "%2520" for a space)
+	 * NOTE: The location it came from is kept, as for resolve_against()
+	 * > returns None if there's nothing left to decode (or the path isn't valid UTF-8, so it can't have been escaped twice)
+	 */
+	pub fn decode_again(&self) -> Option<TrackPath>
+	{
+		if self.is_lossy() {
+			return None;
+		}
+
+		let bytes = unquote_path_bytes(&self.text);
+		if bytes == self.bytes {
+			None
+		}
+		else {
+			Some(TrackPath::new(bytes, self.location.clone()))
+		}
+	}
+
+	/* URI to use for this path in a playlist (i.e. the reverse of from_uri())
+	 * NOTE: Paths decoded from "file:///" URIs don't keep their leading slash, so only one gets added back here
+	 */
+	pub fn to_uri(&self) -> String
+	{
+		if let PathRoot::Unc(_, _) = self.root() {
+			return format!("file:{}", quote_path_bytes(&self.bytes));
+		}
+		let relative = if self.bytes.first() == Some(&b'/') { &self.bytes[1 ..] } else { &self.bytes[..] };
+		format!("{0}{1}", FILE_URI_PREFIX, quote_path_bytes(relative))
+	}
+}
+
+impl fmt::Display for TrackPath {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		f.write_str(&self.text)
+	}
+}
+
+/* Two paths are the same if they lead to the same file (regardless of how they were written in a playlist) */
+impl PartialEq for TrackPath {
+	fn eq(&self, other: &TrackPath) -> bool
+	{
+		self.bytes == other.bytes
+	}
+}
+
+/* Saved as the normalized text form */
+impl ::serde::Serialize for TrackPath {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where S: ::serde::Serializer
+	{
+		serializer.serialize_str(&self.text)
+	}
+}
+
+impl<'de> ::serde::Deserialize<'de> for TrackPath {
+	fn deserialize<D>(deserializer: D) -> Result<TrackPath, D::Error>
+		where D: ::serde::Deserializer<'de>
+	{
+		let text: String = ::serde::Deserialize::deserialize(deserializer)?;
+		Ok(TrackPath::from_normalized(&text))
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/* Windows-style locations behave the same whatever platform is running the tests */
+	#[test]
+	fn test_windows_locations()
+	{
+		let path = TrackPath::from_location("D:\\Music\\20170403\\v01-my%20track.mp3").unwrap();
+		assert_eq!("D:/Music/20170403/v01-my track.mp3", path.as_str());
+		assert_eq!(PathRoot::Drive('D'), path.root());
+		assert_eq!((Some("v01-my track.mp3".to_string()), "20170403".to_string()), (path.file_name(), path.parent_name()));
+		assert_eq!("D:\\Music\\20170403\\v01-my track.mp3", native_form(&path.bytes, PathStyle::Windows));
+		assert_eq!("file:///D:/Music/20170403/v01-my%20track.mp3", path.to_uri());
+		assert_eq!(Some(LocationForm::AbsolutePath), path.location_form());
+
+		/* Files directly on a drive aren't in a session folder */
+		let path = TrackPath::from_location("file:///c:/v01-tranquil.mp3").unwrap();
+		assert_eq!((PathRoot::Drive('C'), String::new()), (path.root(), path.parent_name()));
+
+		/* Old-style drive letters, and backslashes in URIs */
+		for location in ["file:///C|/Users/me/Music/2017-08-02/v01-tranquil.mp3", "file:///C%7C/Users/me/Music/2017-08-02/v01-tranquil.mp3",
+		                 "file:///C:\\Users\\me\\Music\\2017-08-02\\v01-tranquil.mp3"].iter() {
+			let path = TrackPath::from_location(location).unwrap();
+			assert_eq!("C:/Users/me/Music/2017-08-02/v01-tranquil.mp3", path.as_str(), "{}", location);
+			assert_eq!(PathRoot::Drive('C'), path.root());
+			assert_eq!("C:\\Users\\me\\Music\\2017-08-02\\v01-tranquil.mp3", native_form(&path.bytes, PathStyle::Windows));
+		}
+		/* ... but only where the drive letter goes */
+		assert_eq!("music/a|b/v01-tranquil.mp3", TrackPath::from_location("file:///music/a|b/v01-tranquil.mp3").unwrap().as_str());
+
+		/* Native Windows paths */
+		let path = TrackPath::from_native_bytes(b"D:\\Music\\20170404\\v02-moose.flac", PathStyle::Windows);
+		assert_eq!("D:/Music/20170404/v02-moose.flac", path.as_str());
+		assert_eq!(TrackPath::from_location("file:///D:/Music/20170404/v02-moose.flac").unwrap(), path);
+	}
+
+	#[test]
+	fn test_local_uri_forms()
+	{
+		let forms = ["file:///music/20170403/v01-tranquil%20take.mp3", "file://localhost/music/20170403/v01-tranquil%20take.mp3",
+		             "file://LocalHost/music/20170403/v01-tranquil%20take.mp3", "file:/music/20170403/v01-tranquil%20take.mp3"];
+		for location in forms.iter() {
+			let path = TrackPath::from_location(location).unwrap();
+			assert_eq!("music/20170403/v01-tranquil take.mp3", path.as_str(), "{}", location);
+			assert_eq!(Some(LocationForm::AbsoluteUri), path.location_form());
+		}
+
+		/* Nothing to go on */
+		assert!(TrackPath::from_location("file:").is_err());
+		assert!(TrackPath::from_location("file:/").is_err());
+		assert!(TrackPath::from_location("file://").is_err());
+		assert!(TrackPath::from_location("http://example.com/v01-tranquil.mp3").is_err());
+	}
+
+	#[test]
+	fn test_unc_locations()
+	{
+		let forms = ["\\\\nas\\music\\20170403\\v01-tranquil.mp3", "//nas/music/20170403/v01-tranquil.mp3",
+		             "file:////nas/music/20170403/v01-tranquil.mp3", "file://nas/music/20170403/v01-tranquil.mp3"];
+		for location in forms.iter() {
+			let path = TrackPath::from_location(location).unwrap();
+			assert_eq!("//nas/music/20170403/v01-tranquil.mp3", path.as_str(), "{}", location);
+			assert_eq!(PathRoot::Unc("nas".to_string(), "music".to_string()), path.root());
+			assert_eq!("20170403", path.parent_name());
+		}
+
+		let path = TrackPath::from_location(forms[0]).unwrap();
+		assert_eq!(forms[0], native_form(&path.bytes, PathStyle::Windows));
+		assert_eq!("file://nas/music/20170403/v01-tranquil.mp3", path.to_uri());
+		assert_eq!(path, TrackPath::from_uri(&path.to_uri()).unwrap());
+
+		/* Files at the top of a share aren't in a session folder */
+		assert_eq!("", TrackPath::from_location("//nas/music/v01-tranquil.mp3").unwrap().parent_name());
+	}
+
+	/* Unix paths keep backslashes as part of the names (and vice versa for escaped ones in locations) */
+	#[test]
+	fn test_unix_paths()
+	{
+		let path = TrackPath::from_native_bytes(b"/srv/music/20170403/v01-a\\b.mp3", PathStyle::Unix);
+		assert_eq!((PathRoot::Root, Some("v01-a\\b.mp3".to_string())), (path.root(), path.file_name()));
+		assert_eq!("/srv/music/20170403/v01-a\\b.mp3", native_form(&path.bytes, PathStyle::Unix));
+		assert_eq!("file:///srv/music/20170403/v01-a%5Cb.mp3", path.to_uri());
+
+		let path = TrackPath::from_location("file:///srv/music/20170403/v01-a%5Cb.mp3").unwrap();
+		assert_eq!(Some("v01-a\\b.mp3".to_string()), path.file_name());
+
+		/* URIs lose their leading slash (the form shown in reports and JSON), but write back out the same */
+		let path = TrackPath::from_location("file:///music/20170403/v01-tranquil.mp3").unwrap();
+		assert_eq!("music/20170403/v01-tranquil.mp3", path.as_str());
+		assert_eq!("file:///music/20170403/v01-tranquil.mp3", path.to_uri());
+		assert_eq!("music\\20170403\\v01-tranquil.mp3", native_form(&path.bytes, PathStyle::Windows));
+	}
+
+	#[test]
+	fn test_resolve_relative()
+	{
+		let relative = TrackPath::from_location("2018\\v01-take.mp3").unwrap();
+		assert!(!relative.is_absolute());
+		assert_eq!(Some(LocationForm::RelativePath), relative.location_form());
+
+		let base = TrackPath::from_native_bytes(b"C:\\Users\\me\\Playlists\\", PathStyle::Windows);
+		let resolved = relative.resolve_against(&base);
+		assert_eq!("C:/Users/me/Playlists/2018/v01-take.mp3", resolved.as_str());
+		assert_eq!(Some(LocationForm::RelativePath), resolved.location_form());
+
+		let base = TrackPath::from_native_bytes(b"/home/user/playlists", PathStyle::Unix);
+		assert_eq!("/home/user/playlists/2018/v01-take.mp3", relative.resolve_against(&base).as_str());
+		assert_eq!("/home/user/playlists/v02-b.mp3", TrackPath::from_location("./v02-b.mp3").unwrap().resolve_against(&base).as_str());
+
+		/* Absolute paths are never changed */
+		for location in ["D:/Music/v01-a.mp3", "/srv/v01-a.mp3", "\\\\nas\\music\\v01-a.mp3"].iter() {
+			let path = TrackPath::from_location(location).unwrap();
+			assert_eq!(path, path.resolve_against(&base));
+		}
+
+		/* Joining output names onto a directory */
+		let joined = TrackPath::from_native_bytes(b"D:\\Out", PathStyle::Windows).join("Track_01.mp3");
+		assert_eq!("D:\\Out\\Track_01.mp3", native_form(&joined.bytes, PathStyle::Windows));
+	}
+
+	#[test]
+	fn test_relative_to()
+	{
+		let relative = |path: &str, base: &str| {
+			TrackPath::from_native_bytes(path.as_bytes(), PathStyle::Windows)
+				.relative_to(&TrackPath::from_native_bytes(base.as_bytes(), PathStyle::Windows))
+		};
+
+		assert_eq!(Some("20170403/v01-a.mp3".to_string()), relative("/srv/music/20170403/v01-a.mp3", "/srv/music"));
+		assert_eq!(Some("20170403/v01-a.mp3".to_string()), relative("/srv/music/20170403/v01-a.mp3", "/srv/music/"));
+		assert_eq!(Some("../../music/v01-a.mp3".to_string()), relative("/srv/music/v01-a.mp3", "/srv/out/phone/"));
+		assert_eq!(Some(".".to_string()), relative("/srv/music/", "/srv/music"));
+
+		/* ".." and "." get resolved first */
+		assert_eq!(Some("v01-a.mp3".to_string()), relative("/srv/other/../music/./v01-a.mp3", "/srv/music"));
+		assert_eq!(Some("../music/v01-a.mp3".to_string()), relative("/srv/music/v01-a.mp3", "/srv/out/sub/.."));
+		assert_eq!(Some("v01-a.mp3".to_string()), relative("/../srv/v01-a.mp3", "/srv"));
+
+		/* Drive letters and shares don't care about case, but can't be crossed */
+		assert_eq!(Some("Music/v01-a.mp3".to_string()), relative("d:\\Music\\v01-a.mp3", "D:\\"));
+		assert_eq!(Some("../Music/v01-a.mp3".to_string()), relative("D:/Music/v01-a.mp3", "d:/Out"));
+		assert_eq!(None, relative("C:/Music/v01-a.mp3", "D:/Out"));
+		assert_eq!(Some("v01-a.mp3".to_string()), relative("\\\\NAS\\Music\\v01-a.mp3", "//nas/music"));
+		assert_eq!(None, relative("//nas/music/v01-a.mp3", "//nas/backup"));
+		assert_eq!(None, relative("/srv/music/v01-a.mp3", "D:/Out"));
+
+		/* Relative paths work too, as long as the base doesn't start above where they do */
+		assert_eq!(Some("../v01-a.mp3".to_string()), relative("music/v01-a.mp3", "music/out"));
+		assert_eq!(None, relative("music/v01-a.mp3", "../out"));
+	}
+
+	#[test]
+	fn test_file_names()
+	{
+		let path = TrackPath::from_location("music/20170403/v01-tranquil.mp3").unwrap();
+		assert_eq!("music/20170403", path.parent());
+		assert_eq!("music/20170403/v01-calm.mp3", path.with_file_name("v01-calm.mp3").as_str());
+		assert_eq!("", TrackPath::from_location("v01-tranquil.mp3").unwrap().parent_name());
+
+		assert_eq!(None, TrackPath::from_location("/").unwrap().file_name());
+		assert_eq!(None, TrackPath::from_location("D:\\").unwrap().file_name());
+		assert_eq!(None, TrackPath::from_location("music/..").unwrap().file_name());
+
+		assert!(TrackPath::from_location("http://example.com/v01-a.mp3").is_err());
+	}
+
+	#[test]
+	fn test_unquote_path_bytes()
+	{
+		assert_eq!(b"music/20170403/v01-my track.mp3".to_vec(), unquote_path_bytes("music/20170403/v01-my%20track.mp3"));
+		assert_eq!(b"caf\xe9".to_vec(), unquote_path_bytes("caf%E9"));
+		assert_eq!(b"100%-done%2".to_vec(), unquote_path_bytes("100%-done%2"));
+	}
+
+	/* Any escapes get decoded (including multi-byte UTF-8), while anything that isn't one is left as it is */
+	#[test]
+	fn test_decode_uri_locations()
+	{
+		let cases = [
+			("file:///music/20170403/v01-take%20%231.mp3",          "music/20170403/v01-take #1.mp3"),
+			("file:///music/20170403/v02-sch%C3%B6n.mp3",           "music/20170403/v02-schön.mp3"),
+			("file:///music/20170403/v03-intro%E2%80%93outro.mp3",  "music/20170403/v03-intro–outro.mp3"),
+			("file:///music/20170403/v04-a+b.mp3",                  "music/20170403/v04-a+b.mp3"),   /* "+" is only a space in queries */
+			("file:///music/20170403/v05-a%2Bb.mp3",                "music/20170403/v05-a+b.mp3"),
+			("file:///music/20170403/v06-100%25.mp3",               "music/20170403/v06-100%.mp3"),
+			("file:///music/20170403/v07-100%.mp3",                 "music/20170403/v07-100%.mp3"),  /* not a valid escape */
+			("file:///music/20170403/v08-50%zz.mp3",                "music/20170403/v08-50%zz.mp3"),
+		];
+		for &(location, expected) in cases.iter() {
+			let path = TrackPath::from_location(location).unwrap();
+			assert_eq!(expected, path.as_str(), "{}", location);
+			assert!(!path.is_lossy());
+
+			/* Written back out, these decode to the same path again */
+			assert_eq!(path, TrackPath::from_location(&path.to_uri()).unwrap());
+		}
+
+		/* Paths that were never escaped are left alone */
+		assert_eq!("/music/20170403/v09-a b#c+d ö.mp3", TrackPath::from_location("/music/20170403/v09-a b#c+d ö.mp3").unwrap().as_str());
+	}
+
+	#[test]
+	fn test_decode_again()
+	{
+		let path = TrackPath::from_location("file:///music/20170403/v01-my%2520track.mp3").unwrap();
+		assert_eq!("music/20170403/v01-my%20track.mp3", path.as_str());
+
+		let decoded = path.decode_again().unwrap();
+		assert_eq!("music/20170403/v01-my track.mp3", decoded.as_str());
+		assert_eq!(Some(LocationForm::AbsoluteUri), decoded.location_form());
+
+		/* Nothing left to decode */
+		assert_eq!(None, decoded.decode_again());
+		assert_eq!(None, TrackPath::from_location("music/100%-done.mp3").unwrap().decode_again());
+	}
+
+	/* JSON keeps the same text form as before */
+	#[test]
+	fn test_serialize()
+	{
+		use serde_json;
+
+		let path = TrackPath::from_location("file:///D:/Music/20170403/v01-tranquil.mp3").unwrap();
+		let json = serde_json::to_string(&path).unwrap();
+		assert_eq!("\"D:/Music/20170403/v01-tranquil.mp3\"", json);
+		assert_eq!(path, serde_json::from_str::<TrackPath>(&json).unwrap());
+	}
+}
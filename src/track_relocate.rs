@@ -0,0 +1,289 @@
+/* Finding tracks that have moved, by searching a music folder for them
+ *
+ * Older playlists often point at a drive or directory layout that no longer exists, while the files
+ * themselves are still around somewhere under the music folder, with the same names. So for each track
+ * whose file doesn't exist, this looks for files with the same name under a search root ("--search-root").
+ *
+ * The whole search root gets scanned once, into an index of files by name, so that finding each track
+ * is just a lookup (instead of scanning it all again for every track).
+ */
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use track_date::TrackDate;
+use xspf_parser::{Track, XspfPlaylist};
+
+/* Diagnostic category for tracks that were found somewhere else */
+pub const RELOCATED_CATEGORY: &'static str = "relocated";
+
+/* Diagnostic category for tracks that turned up in several places (so were left as they were) */
+pub const AMBIGUOUS_CATEGORY: &'static str = "ambiguous-relocation";
+
+/* *************************************************** */
+/* Index */
+
+/* All the files under the search root, by filename */
+pub struct FileIndex {
+	/* Directory that was scanned */
+	pub root: PathBuf,
+	/* Number of files found */
+	pub file_count: usize,
+
+	/* Full paths of the files with each name (in sorted order) */
+	files: HashMap<String, Vec<PathBuf>>,
+}
+
+impl FileIndex {
+	/* Scan everything under the given directory
+	 * NOTE: Subdirectories that can't be read get skipped (only failing to read the root itself is an error).
+	 *       Symlinks to directories don't get followed (so loops can't happen), but symlinks to files are included.
+	 */
+	pub fn build(root: &Path) -> io::Result<FileIndex>
+	{
+		let mut index = FileIndex {
+			root: root.to_path_buf(),
+			file_count: 0,
+			files: HashMap::new(),
+		};
+
+		let mut pending = vec![root.to_path_buf()];
+		let mut is_root = true;
+		while let Some(dir) = pending.pop() {
+			let entries = match fs::read_dir(&dir) {
+				Ok(entries) => entries,
+				Err(e) => {
+					if is_root {
+						return Err(e);
+					}
+					continue;
+				}
+			};
+			is_root = false;
+
+			for entry in entries.filter_map(|e| e.ok()) {
+				let path = entry.path();
+				let file_type = match entry.file_type() {
+					Ok(file_type) => file_type,
+					Err(_)        => continue
+				};
+
+				if file_type.is_dir() {
+					pending.push(path);
+				}
+				else if file_type.is_file() || (file_type.is_symlink() && path.is_file()) {
+					let name = entry.file_name().to_string_lossy().into_owned();
+					index.files.entry(name).or_default().push(path);
+					index.file_count += 1;
+				}
+			}
+		}
+
+		for paths in index.files.values_mut() {
+			paths.sort();
+		}
+		Ok(index)
+	}
+
+	/* Where the given track's file might have ended up
+	 * When there are several files with its name, the one filed under the track's session date (if any) gets used
+	 */
+	pub fn locate(&self, track: &Track) -> Relocation
+	{
+		let candidates = match self.files.get(&track.filename) {
+			Some(candidates) => candidates,
+			None             => return Relocation::NotFound
+		};
+		if candidates.len() == 1 {
+			return Relocation::Found(candidates[0].clone());
+		}
+
+		let dated: Vec<&PathBuf> = match track.date.normalised() {
+			Some(date) => candidates.iter().filter(|path| dir_date(path).as_deref() == Some(date)).collect(),
+			None       => Vec::new()
+		};
+		if dated.len() == 1 {
+			Relocation::Found(dated[0].clone())
+		}
+		else {
+			Relocation::Ambiguous(candidates.clone())
+		}
+	}
+}
+
+/* Session date of the directory the file is in (normalised), if it's named as one */
+fn dir_date(path: &Path) -> Option<String>
+{
+	let dir_name = path.parent()?.file_name()?.to_string_lossy().into_owned();
+	TrackDate::parse(&dir_name).normalised().map(|d| d.to_string())
+}
+
+/* *************************************************** */
+/* Relocating */
+
+/* What was found for a track */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Relocation {
+	/* The file it now is */
+	Found(PathBuf),
+	/* Several files that it could be (none of which could be picked over the others) */
+	Ambiguous(Vec<PathBuf>),
+	/* Nothing with its name */
+	NotFound,
+}
+
+/* A track that was missing, and what was found for it */
+#[derive(Debug)]
+pub struct TrackRelocation {
+	pub track_index: usize,
+	pub filename: String,
+	/* Where the playlist said the track was */
+	pub old_path: String,
+	pub relocation: Relocation,
+}
+
+/* Look for the tracks whose files don't exist, moving those that were found to where they are now
+ * (noting each one in the playlist's diagnostics, along with any that turned up in several places)
+ * > returns what was found for each missing track
+ */
+pub fn relocate_missing(xspf: &mut XspfPlaylist, index: &FileIndex) -> Vec<TrackRelocation>
+{
+	let mut results = Vec::new();
+	for (i, track) in xspf.tracks.iter_mut().enumerate() {
+		if track.file_path().exists() {
+			continue;
+		}
+
+		let old_path = track.path.to_string();
+		let relocation = index.locate(track);
+		match relocation {
+			Relocation::Found(ref path) => {
+				xspf.diagnostics.warn_track(RELOCATED_CATEGORY, i,
+					format!("relocated - '{0}' is now '{1}'", old_path, path.display()));
+				track.relocate_to(path);
+			},
+			Relocation::Ambiguous(ref paths) => {
+				let candidates: Vec<String> = paths.iter().map(|p| format!("'{}'", p.display())).collect();
+				xspf.diagnostics.warn_track(AMBIGUOUS_CATEGORY, i,
+					format!("'{0}' could be any of {1} files (left as it is) - {2}", track.filename, paths.len(), candidates.join(", ")));
+			},
+			Relocation::NotFound => {}
+		}
+
+		results.push(TrackRelocation {
+			track_index: i,
+			filename: track.filename.clone(),
+			old_path: old_path,
+			relocation: relocation,
+		});
+	}
+	results
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use diagnostics::Diagnostics;
+	use test_utils::TempDir;
+
+	fn playlist(paths: &[&str]) -> XspfPlaylist
+	{
+		XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_path(Path::new(p)).unwrap()).collect(),
+			title: None,
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_build_index()
+	{
+		let dir = TempDir::new("relocate-index");
+		dir.write_file("20170403/v01-tranquil.mp3", b"a");
+		dir.write_file("old/20170403/v01-tranquil.mp3", b"b");
+		dir.write_file("misc/notes.txt", b"c");
+
+		let index = FileIndex::build(dir.path()).unwrap();
+		assert_eq!(3, index.file_count);
+		assert_eq!(vec![dir.path().join("20170403/v01-tranquil.mp3"), dir.path().join("old/20170403/v01-tranquil.mp3")],
+		           index.files["v01-tranquil.mp3"]);
+
+		assert!(FileIndex::build(&dir.path().join("nonexistent")).is_err());
+	}
+
+	#[test]
+	fn test_locate()
+	{
+		let dir = TempDir::new("relocate-locate");
+		dir.write_file("2017/20170403/v01-tranquil.mp3", b"a");
+		dir.write_file("backup/20170404/v01-tranquil.mp3", b"b");
+		dir.write_file("misc/v01-tranquil.mp3", b"c");
+		dir.write_file("2017/20170802/20170802-02-TouchedByAnAngel.flac", b"d");
+		dir.write_file("a/v02-moose.mp3", b"e");
+		dir.write_file("b/v02-moose.mp3", b"f");
+		let index = FileIndex::build(dir.path()).unwrap();
+
+		/* Only one file with the name */
+		let track = Track::from_path(Path::new("/gone/20170802/20170802-02-TouchedByAnAngel.flac")).unwrap();
+		assert_eq!(Relocation::Found(dir.path().join("2017/20170802/20170802-02-TouchedByAnAngel.flac")), index.locate(&track));
+
+		/* Several, but only one filed under the track's date (written differently) */
+		let track = Track::from_path(Path::new("D:/music/2017-04-04/v01-tranquil.mp3")).unwrap();
+		assert_eq!(Relocation::Found(dir.path().join("backup/20170404/v01-tranquil.mp3")), index.locate(&track));
+
+		/* Several, with nothing to choose between them */
+		let track = Track::from_path(Path::new("/gone/20170405/v01-tranquil.mp3")).unwrap();
+		match index.locate(&track) {
+			Relocation::Ambiguous(paths) => assert_eq!(3, paths.len()),
+			other => panic!("expected Ambiguous, got {:?}", other)
+		}
+		let track = Track::from_path(Path::new("/gone/20170405/v02-moose.mp3")).unwrap();
+		assert_eq!(Relocation::Ambiguous(vec![dir.path().join("a/v02-moose.mp3"), dir.path().join("b/v02-moose.mp3")]),
+		           index.locate(&track));
+
+		let track = Track::from_path(Path::new("/gone/20170405/v03-dawn.mp3")).unwrap();
+		assert_eq!(Relocation::NotFound, index.locate(&track));
+	}
+
+	#[test]
+	fn test_relocate_missing()
+	{
+		let dir = TempDir::new("relocate-missing");
+		let present = dir.write_file("music/20170403/v01-tranquil.mp3", b"a");
+		dir.write_file("new/20170404/v02-moose.mp3", b"b");
+		dir.write_file("new/a/v03-dawn.mp3", b"c");
+		dir.write_file("new/b/v03-dawn.mp3", b"d");
+		let index = FileIndex::build(&dir.path().join("new")).unwrap();
+
+		let mut xspf = playlist(&[present.to_str().unwrap(), "/gone/20170404/v02-moose.mp3",
+		                          "/gone/20170405/v03-dawn.mp3", "/gone/20170406/v04-dusk.mp3"]);
+		let results = relocate_missing(&mut xspf, &index);
+
+		/* Only the missing tracks get looked for */
+		assert_eq!(vec![1, 2, 3], results.iter().map(|r| r.track_index).collect::<Vec<_>>());
+		assert_eq!("/gone/20170404/v02-moose.mp3", results[0].old_path);
+
+		/* Found tracks get moved (and filed under the date of where they are now) */
+		assert_eq!(dir.path().join("new/20170404/v02-moose.mp3"), xspf.tracks[1].file_path());
+		assert_eq!("20170404", xspf.tracks[1].date.as_str());
+
+		/* ... while ambiguous ones are left where they were */
+		assert_eq!(Path::new("/gone/20170405/v03-dawn.mp3"), xspf.tracks[2].file_path());
+
+		let categories: Vec<(&str, Option<usize>)> = xspf.diagnostics.entries.iter()
+			.map(|d| (d.category.as_str(), d.track_index))
+			.collect();
+		assert_eq!(vec![(RELOCATED_CATEGORY, Some(1)), (AMBIGUOUS_CATEGORY, Some(2))], categories);
+	}
+}
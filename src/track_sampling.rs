@@ -0,0 +1,232 @@
+/* Picking a small, representative sample of the tracks in a playlist (e.g. for spot-checking conversions)
+ *
+ * Rather than picking purely at random, the tracks get grouped into strata (by session date and type),
+ * and the sample is shared out between the strata in proportion to their sizes. So the sample ends up
+ * covering as many different sessions/types as possible. The choices that do need to be made at random
+ * (which tracks to take from each stratum, and who gets the leftovers) are driven by a seed, so the
+ * same seed always gives the same sample.
+ */
+use std::collections::HashMap;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use xspf_parser::Track;
+
+/* Number of tracks sampled when no count is given */
+pub const DEFAULT_SAMPLE_COUNT: usize = 5;
+
+/* *************************************************** */
+/* Random Numbers */
+
+/* Small deterministic random number generator (SplitMix64) - so that samples are reproducible for a given seed */
+pub struct SampleRng {
+	state: u64,
+}
+
+impl SampleRng {
+	pub fn new(seed: u64) -> SampleRng
+	{
+		SampleRng { state: seed }
+	}
+
+	pub fn next_u64(&mut self) -> u64
+	{
+		self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+
+	/* Random number in the range [0, 1) */
+	pub fn next_f64(&mut self) -> f64
+	{
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/* Shuffle the items in place (Fisher-Yates) */
+	pub fn shuffle<T>(&mut self, items: &mut [T])
+	{
+		for i in (1 .. items.len()).rev() {
+			let j = (self.next_u64() % (i as u64 + 1)) as usize;
+			items.swap(i, j);
+		}
+	}
+}
+
+/* Seed to use when none was given - different for every run (so it should be reported, for the run to be repeatable) */
+pub fn random_seed() -> u64
+{
+	let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+	SampleRng::new(nanos ^ ((process::id() as u64) << 32)).next_u64()
+}
+
+/* *************************************************** */
+/* Strata */
+
+/* Stratum that a track belongs to - (session date, type) */
+pub fn stratum_key(track: &Track) -> (String, String)
+{
+	(track.date.to_string(), track.info().track_type.shortname())
+}
+
+/* Group the items into strata by their keys
+ * > returns the indices of the items in each stratum (with the strata in order of first appearance)
+ */
+fn group_strata<K: Eq + ::std::hash::Hash>(keys: &[K]) -> Vec<Vec<usize>>
+{
+	let mut strata: Vec<Vec<usize>> = Vec::new();
+	let mut lookup: HashMap<&K, usize> = HashMap::new();
+
+	for (i, key) in keys.iter().enumerate() {
+		let stratum = *lookup.entry(key).or_insert_with(|| {
+			strata.push(Vec::new());
+			strata.len() - 1
+		});
+		strata[stratum].push(i);
+	}
+	strata
+}
+
+/* *************************************************** */
+/* Allocation */
+
+/* Share out "count" samples between strata of the given sizes, in proportion to their sizes
+ *
+ * Each stratum first gets the whole part of its share. The leftovers then go one each to the strata
+ * with the largest fractional parts (with ties broken at random, based on the seed). So no stratum
+ * ever gets more than its size, and when there are more strata than samples, the largest get picked.
+ *
+ * > returns the number of samples to take from each stratum (taking everything if count >= total)
+ */
+pub fn allocate(sizes: &[usize], count: usize, seed: u64) -> Vec<usize>
+{
+	let total: usize = sizes.iter().sum();
+	if count >= total {
+		return sizes.to_vec();
+	}
+
+	/* Proportional share = size * count / total (kept as whole part + remainder, to avoid rounding issues) */
+	let mut allocation: Vec<usize> = sizes.iter().map(|&size| size * count / total).collect();
+	let remainders: Vec<usize> = sizes.iter().map(|&size| size * count % total).collect();
+	let leftover = count - allocation.iter().sum::<usize>();
+
+	/* Shuffle first, so that the (stable) sort leaves ties in a random order */
+	let mut order: Vec<usize> = (0 .. sizes.len()).collect();
+	SampleRng::new(seed).shuffle(&mut order);
+	order.sort_by(|&a, &b| remainders[b].cmp(&remainders[a]));
+
+	for &i in order.iter().take(leftover) {
+		allocation[i] += 1;
+	}
+	allocation
+}
+
+/* *************************************************** */
+/* Sampling */
+
+/* Pick a sample of "count" tracks, spread across the (date, type) strata of the playlist
+ * > returns the indices of the tracks picked (in playlist order)
+ */
+pub fn select_sample(tracks: &[Track], count: usize, seed: u64) -> Vec<usize>
+{
+	let keys: Vec<(String, String)> = tracks.iter().map(stratum_key).collect();
+	let strata = group_strata(&keys);
+
+	let sizes: Vec<usize> = strata.iter().map(|s| s.len()).collect();
+	let allocation = allocate(&sizes, count, seed);
+
+	/* Pick which tracks to take from each stratum */
+	let mut rng = SampleRng::new(seed.wrapping_add(1));
+	let mut picked: Vec<usize> = Vec::new();
+	for (mut members, n) in strata.into_iter().zip(allocation) {
+		rng.shuffle(&mut members);
+		picked.extend(members.into_iter().take(n));
+	}
+
+	picked.sort();
+	picked
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_allocate_fewer_strata()
+	{
+		/* 3 strata, 5 samples - 3 / 1.5 / 0.5 */
+		let sizes = [6, 3, 1];
+		for seed in 0 .. 10 {
+			let allocation = allocate(&sizes, 5, seed);
+			assert_eq!(5, allocation.iter().sum::<usize>());
+			assert_eq!(3, allocation[0]);
+			assert!(allocation[1] >= 1);
+			assert!(allocation.iter().zip(sizes.iter()).all(|(a, s)| a <= s));
+		}
+
+		/* Exact shares don't need any leftovers sharing out */
+		assert_eq!(vec![4, 2, 0], allocate(&[8, 4, 0], 6, 7));
+
+		/* Asking for more than there are takes everything */
+		assert_eq!(vec![2, 1], allocate(&[2, 1], 5, 0));
+	}
+
+	#[test]
+	fn test_allocate_more_strata()
+	{
+		/* 8 strata of 1, 3 samples - only 3 strata get one each */
+		let sizes = [1; 8];
+		let allocation = allocate(&sizes, 3, 42);
+		assert_eq!(3, allocation.iter().sum::<usize>());
+		assert!(allocation.iter().all(|&a| a <= 1));
+
+		/* Same seed = same result, while other seeds pick other strata */
+		assert_eq!(allocation, allocate(&sizes, 3, 42));
+		assert!((0 .. 20).any(|seed| allocate(&sizes, 3, seed) != allocation));
+
+		/* Larger strata are preferred for the leftovers */
+		assert_eq!(vec![0, 1, 0, 1], allocate(&[1, 3, 1, 3], 2, 5));
+	}
+
+	#[test]
+	fn test_allocate_single_stratum()
+	{
+		assert_eq!(vec![5], allocate(&[12], 5, 0));
+		assert_eq!(vec![0], allocate(&[12], 0, 0));
+		assert_eq!(Vec::<usize>::new(), allocate(&[], 5, 0));
+	}
+
+	#[test]
+	fn test_select_sample()
+	{
+		let paths = ["music/20170403/v01-tranquil.mp3",
+		             "music/20170403/v02-celestial.mp3",
+		             "music/20170403/v03-dawn.mp3",
+		             "music/20170403/v04-dusk.mp3",
+		             "music/20170403/20170403-01-sonata.mp3",
+		             "music/20170404/v01-moose.mp3",
+		             "music/20170404/v02-goose.mp3",
+		             "music/misc/intro.mp3"];
+		let tracks: Vec<Track> = paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect();
+
+		/* 4 samples from strata of 4/1/2/1 - half from the largest, and one each from two others */
+		let picked = select_sample(&tracks, 4, 3);
+		assert_eq!(4, picked.len());
+		assert_eq!(2, picked.iter().filter(|&&i| i < 4).count());
+		assert_eq!(1, picked.iter().filter(|&&i| i == 5 || i == 6).count());
+
+		let mut strata: Vec<(String, String)> = picked.iter().map(|&i| stratum_key(&tracks[i])).collect();
+		strata.dedup();
+		assert_eq!(3, strata.len());
+
+		/* Deterministic, and in playlist order */
+		assert_eq!(picked, select_sample(&tracks, 4, 3));
+		assert!(picked.windows(2).all(|w| w[0] < w[1]));
+
+		assert_eq!((0 .. 8).collect::<Vec<_>>(), select_sample(&tracks, 20, 0));
+	}
+}
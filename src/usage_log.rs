@@ -0,0 +1,396 @@
+/* Usage log - Running totals of how the tool gets used
+ *
+ * With "--usage-log=<path>", each run adds its counters (the mode that was run, the number of tracks
+ * processed, bytes copied/converted, and the time taken) to the totals kept in the given file, so that
+ * it's possible to see where the time goes over many runs. Everything stays in that one local file.
+ *
+ * The file gets updated under a lock (see output_lock), and written out via a temp file that replaces
+ * it in one go, so concurrent runs can't clobber each other's counts, and a crash can't leave a half
+ * written file behind. Like the journal, this is purely a side record - problems with it only get warnings.
+ */
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use output_lock::{LockError, OutputLock, SystemProcessChecker};
+
+/* Version of the usage file format */
+const USAGE_FORMAT_VERSION: u32 = 1;
+
+/* How long to wait for another run to finish updating the file */
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(25);
+
+/* *************************************************** */
+/* Counters */
+
+/* Totals for one mode */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub struct ModeUsage {
+	/* Number of times the mode was run (to completion) */
+	pub runs: u64,
+	/* Number of tracks in the playlists that were processed */
+	pub tracks: u64,
+	/* Size of the files produced by copying/converting/bundling */
+	pub bytes: u64,
+	/* Time taken by all those runs */
+	pub wall_ms: u64,
+}
+
+impl ModeUsage {
+	/* Add another set of counts to these */
+	pub fn add(&mut self, other: &ModeUsage)
+	{
+		self.runs = self.runs.saturating_add(other.runs);
+		self.tracks = self.tracks.saturating_add(other.tracks);
+		self.bytes = self.bytes.saturating_add(other.bytes);
+		self.wall_ms = self.wall_ms.saturating_add(other.wall_ms);
+	}
+}
+
+/* Contents of the usage file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct UsageStats {
+	pub version: u32,
+
+	/* When the earliest/latest of the runs counted here happened (milliseconds since the unix epoch) */
+	pub first_run_ms: Option<u64>,
+	pub last_run_ms: Option<u64>,
+
+	/* Totals for each mode */
+	pub modes: BTreeMap<String, ModeUsage>,
+}
+
+impl Default for UsageStats {
+	fn default() -> UsageStats
+	{
+		UsageStats {
+			version: USAGE_FORMAT_VERSION,
+			first_run_ms: None,
+			last_run_ms: None,
+			modes: BTreeMap::new(),
+		}
+	}
+}
+
+impl UsageStats {
+	/* Counts for a single run of a mode (finishing at the given time) */
+	pub fn for_run(mode: &str, usage: ModeUsage, finished_ms: u64) -> UsageStats
+	{
+		let mut modes = BTreeMap::new();
+		modes.insert(mode.to_string(), usage);
+
+		UsageStats {
+			version: USAGE_FORMAT_VERSION,
+			first_run_ms: Some(finished_ms),
+			last_run_ms: Some(finished_ms),
+			modes: modes,
+		}
+	}
+
+	/* Add the counts from another set of stats (e.g. a run) to these
+	 * NOTE: The order that stats get merged in doesn't matter
+	 */
+	pub fn merge(&mut self, other: &UsageStats)
+	{
+		for (mode, usage) in other.modes.iter() {
+			self.modes.entry(mode.clone()).or_default().add(usage);
+		}
+
+		self.first_run_ms = match (self.first_run_ms, other.first_run_ms) {
+			(Some(a), Some(b)) => Some(a.min(b)),
+			(a, b)             => a.or(b),
+		};
+		self.last_run_ms = self.last_run_ms.max(other.last_run_ms);
+	}
+
+	/* Totals over all the modes */
+	pub fn totals(&self) -> ModeUsage
+	{
+		let mut totals = ModeUsage::default();
+		for usage in self.modes.values() {
+			totals.add(usage);
+		}
+		totals
+	}
+
+	/* Modes in order of the time spent on them (most first) */
+	pub fn modes_by_time(&self) -> Vec<(&str, &ModeUsage)>
+	{
+		let mut modes: Vec<(&str, &ModeUsage)> = self.modes.iter().map(|(m, u)| (m.as_str(), u)).collect();
+		modes.sort_by(|a, b| b.1.wall_ms.cmp(&a.1.wall_ms).then(a.0.cmp(b.0)));
+		modes
+	}
+}
+
+/* *************************************************** */
+/* Usage File */
+
+/* Expand a leading "~" in the path to the home directory (as the shell doesn't do this for "--usage-log=~/...") */
+pub fn expand_home(path: &str) -> PathBuf
+{
+	let rest = if path == "~" {
+		Some("")
+	}
+	else if path.starts_with("~/") || path.starts_with("~\\") {
+		Some(&path[2 ..])
+	}
+	else {
+		None
+	};
+
+	match (rest, env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))) {
+		(Some(rest), Some(home)) => Path::new(&home).join(rest),
+		_                        => PathBuf::from(path)
+	}
+}
+
+/* Path with a suffix added to the filename (e.g. "<path>.lock") */
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf
+{
+	let mut name: OsString = path.as_os_str().to_os_string();
+	name.push(suffix);
+	PathBuf::from(name)
+}
+
+/* Read the stats from the usage file (empty stats if there isn't one yet)
+ * > returns an error if the file couldn't be read, or isn't a valid usage file
+ */
+pub fn load(path: &Path) -> Result<UsageStats, String>
+{
+	read_contents(path)?.map_or(Ok(UsageStats::default()), |contents| parse(path, &contents))
+}
+
+/* Contents of the usage file (None if there isn't one yet) */
+fn read_contents(path: &Path) -> Result<Option<String>, String>
+{
+	match fs::read_to_string(path) {
+		Ok(contents) => Ok(Some(contents)),
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(format!("Couldn't read usage log {0:?} - {1}", path, e))
+	}
+}
+
+fn parse(path: &Path, contents: &str) -> Result<UsageStats, String>
+{
+	serde_json::from_str::<UsageStats>(contents).map_err(|e| format!("Usage log {0:?} is corrupt - {1}", path, e))
+}
+
+/* Write the stats to the usage file (via a temp file, which then replaces it) */
+fn save(path: &Path, stats: &UsageStats) -> Result<(), String>
+{
+	let json = serde_json::to_string_pretty(stats).map_err(|e| e.to_string())?;
+	let temp_path = with_suffix(path, &format!(".{}.tmp", ::std::process::id()));
+
+	fs::write(&temp_path, json)
+		.and_then(|_| fs::rename(&temp_path, path))
+		.map_err(|e| {
+			let _ = fs::remove_file(&temp_path);
+			format!("Couldn't write usage log {0:?} - {1}", path, e)
+		})
+}
+
+/* Take the lock on the usage file, waiting a while for any other run that's updating it */
+fn lock(path: &Path) -> Result<OutputLock, String>
+{
+	let lock_path = with_suffix(path, ".lock");
+	let start = Instant::now();
+	loop {
+		match OutputLock::acquire_file(&lock_path, false, &SystemProcessChecker) {
+			Ok(lock) => return Ok(lock),
+			Err(LockError::Held { .. }) if start.elapsed() < LOCK_WAIT => thread::sleep(LOCK_RETRY_INTERVAL),
+			Err(LockError::Held { info, .. }) => {
+				return Err(format!("Usage log {0:?} is being updated by another run (PID {1})", path, info.pid));
+			},
+			Err(LockError::IoError(e)) => return Err(format!("Couldn't lock usage log {0:?} - {1}", path, e)),
+		}
+	}
+}
+
+/* Add the given counts to the totals in the usage file
+ * NOTE: If the existing file can't be parsed, it gets moved aside (to "<path>.corrupt") and the totals start again
+ */
+pub fn update(path: &Path, delta: &UsageStats) -> Result<(), String>
+{
+	let _lock = lock(path)?;
+
+	let contents = read_contents(path)?;
+	let mut stats = match contents.as_ref().map(|contents| parse(path, contents)) {
+		None => UsageStats::default(),
+		Some(Ok(stats)) => stats,
+		Some(Err(e)) => {
+			let corrupt_path = with_suffix(path, ".corrupt");
+			eprintln!("WARNING: {0} (moved to {1:?}, starting again from zero)", e, corrupt_path);
+			let _ = fs::rename(path, &corrupt_path);
+			UsageStats::default()
+		}
+	};
+	stats.merge(delta);
+	save(path, &stats)
+}
+
+/* ------------------------------------------- */
+/* Current Run
+ *
+ * Counts for the current run get added from wherever the work happens, so (like the journal)
+ * they're kept here instead of being passed down through all the modes.
+ */
+
+lazy_static! {
+	static ref RUN_USAGE: Mutex<ModeUsage> = Mutex::new(ModeUsage::default());
+}
+
+/* Note tracks processed by the current run */
+pub fn add_tracks(count: usize)
+{
+	if let Ok(mut usage) = RUN_USAGE.lock() {
+		usage.tracks = usage.tracks.saturating_add(count as u64);
+	}
+}
+
+/* Note bytes produced by the current run */
+pub fn add_bytes(bytes: u64)
+{
+	if let Ok(mut usage) = RUN_USAGE.lock() {
+		usage.bytes = usage.bytes.saturating_add(bytes);
+	}
+}
+
+/* Add the current run (of the given mode, which took "elapsed") to the usage file */
+pub fn record_run(path: &Path, mode: &str, elapsed: Duration)
+{
+	let mut usage = RUN_USAGE.lock().map(|u| *u).unwrap_or_default();
+	usage.runs = 1;
+	usage.wall_ms = elapsed.as_millis() as u64;
+
+	let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+	if let Err(e) = update(path, &UsageStats::for_run(mode, usage, now_ms)) {
+		eprintln!("WARNING: {} (this run wasn't counted)", e);
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+	use test_utils::TempDir;
+
+	fn usage(runs: u64, tracks: u64, bytes: u64, wall_ms: u64) -> ModeUsage
+	{
+		ModeUsage { runs: runs, tracks: tracks, bytes: bytes, wall_ms: wall_ms }
+	}
+
+	#[test]
+	fn test_merge_runs()
+	{
+		let mut stats = UsageStats::default();
+		stats.merge(&UsageStats::for_run("copy", usage(1, 10, 5000, 1200), 2000));
+		stats.merge(&UsageStats::for_run("dump", usage(1, 4, 0, 15), 1000));
+		stats.merge(&UsageStats::for_run("copy", usage(1, 3, 700, 300), 3000));
+
+		assert_eq!(Some(&usage(2, 13, 5700, 1500)), stats.modes.get("copy"));
+		assert_eq!(Some(&usage(1, 4, 0, 15)), stats.modes.get("dump"));
+		assert_eq!(usage(3, 17, 5700, 1515), stats.totals());
+		assert_eq!((Some(1000), Some(3000)), (stats.first_run_ms, stats.last_run_ms));
+
+		assert_eq!(vec!["copy", "dump"], stats.modes_by_time().iter().map(|m| m.0).collect::<Vec<_>>());
+	}
+
+	/* Deltas from two runs that finished at the same time end up the same whichever gets merged first */
+	#[test]
+	fn test_merge_concurrent_deltas()
+	{
+		let mut base = UsageStats::default();
+		base.merge(&UsageStats::for_run("convert", usage(4, 40, 1 << 30, 60_000), 1000));
+
+		let a = UsageStats::for_run("convert", usage(1, 12, 1 << 20, 9_000), 5000);
+		let b = UsageStats::for_run("bundle", usage(1, 12, 1 << 21, 2_000), 4000);
+
+		let mut a_then_b = base.clone();
+		a_then_b.merge(&a);
+		a_then_b.merge(&b);
+
+		let mut b_then_a = base.clone();
+		b_then_a.merge(&b);
+		b_then_a.merge(&a);
+
+		assert_eq!(a_then_b, b_then_a);
+		assert_eq!(Some(&usage(5, 52, (1 << 30) + (1 << 20), 69_000)), a_then_b.modes.get("convert"));
+		assert_eq!(Some(&usage(1, 12, 1 << 21, 2_000)), a_then_b.modes.get("bundle"));
+		assert_eq!((Some(1000), Some(5000)), (a_then_b.first_run_ms, a_then_b.last_run_ms));
+	}
+
+	#[test]
+	fn test_counts_saturate()
+	{
+		let mut total = usage(1, 0, u64::MAX - 1, 0);
+		total.add(&usage(1, 0, 10, 0));
+		assert_eq!(usage(2, 0, u64::MAX, 0), total);
+	}
+
+	/* Runs updating the same file at the same time all get counted */
+	#[test]
+	fn test_concurrent_updates()
+	{
+		let dir = TempDir::new("usage-log-concurrent");
+		let path = Arc::new(dir.path().join("usage.json"));
+
+		let workers: Vec<_> = (0 .. 4).map(|i| {
+			let path = path.clone();
+			thread::spawn(move || {
+				for _ in 0 .. 10 {
+					update(&path, &UsageStats::for_run(if i % 2 == 0 { "copy" } else { "dump" }, usage(1, 2, 100, 5), 1000)).unwrap();
+				}
+			})
+		}).collect();
+		for worker in workers {
+			worker.join().unwrap();
+		}
+
+		let stats = load(&path).unwrap();
+		assert_eq!(usage(40, 80, 4000, 200), stats.totals());
+		assert_eq!(20, stats.modes["copy"].runs);
+		assert!(!with_suffix(&path, ".lock").exists());
+	}
+
+	/* Corrupt files get moved aside, and counting starts again */
+	#[test]
+	fn test_corrupt_file()
+	{
+		let dir = TempDir::new("usage-log-corrupt");
+		let path = dir.write_file("usage.json", b"{\"version\": 1, \"modes\": {\"copy\": ");
+		assert!(load(&path).is_err());
+
+		update(&path, &UsageStats::for_run("copy", usage(1, 2, 100, 5), 1000)).unwrap();
+		assert_eq!(usage(1, 2, 100, 5), load(&path).unwrap().totals());
+		assert!(with_suffix(&path, ".corrupt").exists());
+	}
+
+	#[test]
+	fn test_expand_home()
+	{
+		assert_eq!(PathBuf::from("/tmp/usage.json"), expand_home("/tmp/usage.json"));
+		assert_eq!(PathBuf::from("~user/usage.json"), expand_home("~user/usage.json"));
+		if let Some(home) = env::var_os("HOME") {
+			assert_eq!(Path::new(&home).join(".xspf_tools_usage.json"), expand_home("~/.xspf_tools_usage.json"));
+		}
+	}
+}
@@ -0,0 +1,380 @@
+/* Single-file HTML player for a playlist
+ *
+ * "webplayer" writes a standalone HTML page that lists the playlist's tracks and plays them in the
+ * browser (with a plain <audio> element - no external scripts or stylesheets needed). It's meant to sit
+ * next to (or in) a folder of files made by copy/convert, so each track links to the file there
+ * (the media base URL, plus the file's percent-encoded name).
+ *
+ * The playlist details get embedded in the page as JSON, inside a <script> element. Names can contain
+ * anything, so the JSON gets escaped to make sure nothing in it can end the script early (e.g. "</script>").
+ */
+use std::collections::HashMap;
+use std::path::Path;
+
+use m3u_parser::M3uPlaylist;
+use serde_json;
+use track_path::quote_path_bytes;
+use xml_writer::escape_text;
+
+/* *************************************************** */
+/* Player Data */
+
+/* Details of a track, as given to the player */
+#[derive(Serialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct PlayerTrack {
+	/* Position in the playlist (1-based) */
+	pub number: usize,
+	pub title: String,
+	/* Session date (if known) */
+	pub date: Option<String>,
+	/* Duration as a timecode (if known) */
+	pub duration: Option<String>,
+	/* Rating (0-5 stars, if rated) - 5-star tracks get marked as favorites */
+	pub rating: Option<u8>,
+
+	/* URL of the file to play */
+	pub url: String,
+}
+
+/* Details of the playlist, as given to the player */
+#[derive(Serialize)]
+#[derive(Debug)]
+pub struct PlayerData {
+	pub title: String,
+	pub tracks: Vec<PlayerTrack>,
+}
+
+/* *************************************************** */
+/* Escaping */
+
+/* Percent-encode a filename for use in a URL
+ * (Unlike for paths, "/" and ":" get encoded too, as they'd change what the URL points to)
+ */
+pub fn encode_filename(filename: &str) -> String
+{
+	quote_path_bytes(filename.as_bytes()).replace('/', "%2F").replace(':', "%3A")
+}
+
+/* URL of a file in the media folder
+ * - media_base: URL of the folder (e.g. "./files/"), which a "/" gets added to if it doesn't have one
+ */
+pub fn media_url(media_base: &str, filename: &str) -> String
+{
+	if media_base.is_empty() || media_base.ends_with('/') {
+		format!("{0}{1}", media_base, encode_filename(filename))
+	}
+	else {
+		format!("{0}/{1}", media_base, encode_filename(filename))
+	}
+}
+
+/* Make JSON safe to embed in a <script> element
+ * Escaping "<", ">", and "&" means nothing inside can close the element (or start a comment), while still
+ * being the same JSON. U+2028/U+2029 get escaped too, for older browsers that didn't allow them in scripts.
+ */
+pub fn escape_script_json(json: &str) -> String
+{
+	json.replace('<', "\\u003c")
+	    .replace('>', "\\u003e")
+	    .replace('&', "\\u0026")
+	    .replace('\u{2028}', "\\u2028")
+	    .replace('\u{2029}', "\\u2029")
+}
+
+/* *************************************************** */
+/* Copy Manifests */
+
+/* Name of a file without its extension (as convert changes the extensions) */
+fn file_stem(filename: &str) -> &str
+{
+	let name = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+	match name.rfind('.') {
+		Some(pos) if pos > 0 => &name[.. pos],
+		_                    => name
+	}
+}
+
+/* Work out the actual filenames of the tracks, from the manifest written by copy/convert
+ * - expected: Name each track would have been given by copy (see plan_destination_filenames())
+ * > returns the name listed in the manifest for each track, or None if it isn't listed (e.g. it failed to copy)
+ *
+ * NOTE: Tracks get matched up by name, ignoring the extension (as convert changes it)
+ */
+pub fn map_manifest_names(expected: &[String], manifest: &M3uPlaylist) -> Vec<Option<String>>
+{
+	let mut listed: HashMap<&str, &str> = HashMap::new();
+	for entry in manifest.entries.iter() {
+		let name = entry.location.rsplit(['/', '\\']).next().unwrap_or(&entry.location);
+		listed.entry(file_stem(name)).or_insert(name);
+	}
+
+	expected.iter()
+	        .map(|name| listed.get(file_stem(name)).map(|listed_name| listed_name.to_string()))
+	        .collect()
+}
+
+/* *************************************************** */
+/* Page */
+
+/* Markers in the template that get replaced */
+const TITLE_MARKER: &'static str = "{{TITLE}}";
+const DATA_MARKER: &'static str = "{{PLAYLIST_JSON}}";
+
+/* Page to write, with the markers above to be filled in */
+const PLAYER_TEMPLATE: &'static str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{TITLE}}</title>
+<style>
+	body { font-family: sans-serif; margin: 0 auto; max-width: 60em; padding: 1em; color: #222; }
+	h1 { font-size: 1.4em; }
+	#now-playing { font-weight: bold; min-height: 1.5em; }
+	audio { width: 100%; margin: 0.5em 0 1em; }
+	table { border-collapse: collapse; width: 100%; }
+	td, th { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #ddd; }
+	td.num, td.duration { text-align: right; font-variant-numeric: tabular-nums; }
+	tbody tr { cursor: pointer; }
+	tbody tr:hover { background: #f2f6fa; }
+	tbody tr.playing { background: #dbe9f5; }
+	td.rating { color: #c90; white-space: nowrap; }
+	tr.favorite td.title { font-weight: bold; }
+</style>
+</head>
+<body>
+<h1>{{TITLE}}</h1>
+<div id="now-playing"></div>
+<audio id="player" controls preload="none"></audio>
+<table>
+	<thead><tr><th>#</th><th>Title</th><th>Date</th><th>Duration</th><th>Rating</th></tr></thead>
+	<tbody id="tracks"></tbody>
+</table>
+<script type="application/json" id="playlist-data">{{PLAYLIST_JSON}}</script>
+<script>
+(function () {
+	"use strict";
+	var data = JSON.parse(document.getElementById("playlist-data").textContent);
+	var player = document.getElementById("player");
+	var nowPlaying = document.getElementById("now-playing");
+	var rows = [];
+	var current = -1;
+
+	function cell(row, text, className) {
+		var td = document.createElement("td");
+		td.textContent = (text === null || text === undefined) ? "" : String(text);
+		if (className) {
+			td.className = className;
+		}
+		row.appendChild(td);
+	}
+
+	function play(index) {
+		if (index < 0 || index >= data.tracks.length) {
+			return;
+		}
+		if (current >= 0) {
+			rows[current].classList.remove("playing");
+		}
+		current = index;
+		rows[current].classList.add("playing");
+
+		var track = data.tracks[index];
+		nowPlaying.textContent = track.number + ". " + track.title;
+		player.src = track.url;
+		player.play();
+	}
+
+	function stars(rating) {
+		if (rating === null || rating === undefined) {
+			return "";
+		}
+		return "\u2605".repeat(rating) + "\u2606".repeat(5 - rating);
+	}
+
+	data.tracks.forEach(function (track, index) {
+		var row = document.createElement("tr");
+		var favorite = (track.rating === 5);
+		if (favorite) {
+			row.className = "favorite";
+		}
+		cell(row, track.number, "num");
+		cell(row, (favorite ? "\u2605 " : "") + track.title, "title");
+		cell(row, track.date);
+		cell(row, track.duration, "duration");
+		cell(row, stars(track.rating), "rating");
+		row.addEventListener("click", function () { play(index); });
+		document.getElementById("tracks").appendChild(row);
+		rows.push(row);
+	});
+
+	/* Carry on with the next track */
+	player.addEventListener("ended", function () { play(current + 1); });
+})();
+</script>
+</body>
+</html>
+"#;
+
+/* Replace the markers in a template (in a single pass, so nothing substituted can be mistaken for a marker) */
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String
+{
+	let mut result = String::with_capacity(template.len());
+	let mut rest = template;
+
+	loop {
+		let next = values.iter()
+		                 .filter_map(|&(marker, value)| rest.find(marker).map(|pos| (pos, marker, value)))
+		                 .min_by_key(|&(pos, _, _)| pos);
+		match next {
+			Some((pos, marker, value)) => {
+				result.push_str(&rest[.. pos]);
+				result.push_str(value);
+				rest = &rest[pos + marker.len() ..];
+			},
+			None => {
+				result.push_str(rest);
+				return result;
+			}
+		}
+	}
+}
+
+/* Generate the player page for a playlist */
+pub fn player_html(data: &PlayerData) -> Result<String, String>
+{
+	let json = serde_json::to_string(data).map_err(|e| format!("Couldn't convert the playlist data to JSON - {}", e))?;
+	Ok(fill_template(PLAYER_TEMPLATE, &[(TITLE_MARKER, &escape_text(&data.title)),
+	                                    (DATA_MARKER, &escape_script_json(&json))]))
+}
+
+/* Title for the page (the playlist's own title, or the name of the playlist file) */
+pub fn page_title(playlist_title: Option<&str>, in_file: &str) -> String
+{
+	match playlist_title {
+		Some(title) if !title.trim().is_empty() => title.to_string(),
+		_ => Path::new(in_file).file_stem().map_or_else(|| in_file.to_string(), |s| s.to_string_lossy().into_owned())
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use m3u_parser::parse_m3u;
+
+	fn fixture_data() -> PlayerData
+	{
+		PlayerData {
+			title: "Sessions <2017> & more".to_string(),
+			tracks: vec![
+				PlayerTrack {
+					number: 1,
+					title: "tranquil".to_string(),
+					date: Some("2017-04-03".to_string()),
+					duration: Some("01:01".to_string()),
+					rating: Some(5),
+					url: media_url("./files/", "Track_01-20170403-VL01_tranquil.mp3"),
+				},
+				PlayerTrack {
+					number: 2,
+					title: "</script><script>alert(1)</script>".to_string(),
+					date: None,
+					duration: None,
+					rating: None,
+					url: media_url("./files", "Track_02-misc-VL02_a b#c.mp3"),
+				},
+			],
+		}
+	}
+
+	#[test]
+	fn test_media_urls()
+	{
+		assert_eq!("./files/Track_01-20170403-VL01_tranquil.mp3", media_url("./files/", "Track_01-20170403-VL01_tranquil.mp3"));
+		assert_eq!("./files/a%20b%23c%3F.mp3", media_url("./files", "a b#c?.mp3"));
+		assert_eq!("x%3Ay%2Fz%25.mp3", media_url("", "x:y/z%.mp3"));
+		assert_eq!("https://host/m/%C3%A9t%C3%A9.ogg", media_url("https://host/m/", "été.ogg"));
+	}
+
+	#[test]
+	fn test_escaping()
+	{
+		let html = player_html(&fixture_data()).unwrap();
+
+		/* Nothing in the data can end the script element early */
+		assert_eq!(2, html.matches("</script>").count());
+		assert!(!html.contains("</script><script>alert"));
+		assert!(html.contains("\\u003c/script\\u003e\\u003cscript\\u003ealert(1)\\u003c/script\\u003e"));
+
+		/* The title gets escaped as HTML */
+		assert!(html.contains("<title>Sessions &lt;2017&gt; &amp; more</title>"));
+		assert!(html.contains("<h1>Sessions &lt;2017&gt; &amp; more</h1>"));
+
+		/* ... and is still the same JSON */
+		let start = html.find("id=\"playlist-data\">").unwrap() + "id=\"playlist-data\">".len();
+		let end = start + html[start ..].find("</script>").unwrap();
+		let value: serde_json::Value = serde_json::from_str(&html[start .. end]).unwrap();
+		assert_eq!("</script><script>alert(1)</script>", value["tracks"][1]["title"]);
+		assert_eq!("Sessions <2017> & more", value["title"]);
+		assert_eq!("./files/Track_02-misc-VL02_a%20b%23c.mp3", value["tracks"][1]["url"]);
+	}
+
+	#[test]
+	fn test_one_entry_per_track()
+	{
+		let mut data = fixture_data();
+		for i in 3 ..= 12 {
+			let mut track = data.tracks[0].clone();
+			track.number = i;
+			data.tracks.push(track);
+		}
+
+		let html = player_html(&data).unwrap();
+		let start = html.find("id=\"playlist-data\">").unwrap() + "id=\"playlist-data\">".len();
+		let end = start + html[start ..].find("</script>").unwrap();
+		let value: serde_json::Value = serde_json::from_str(&html[start .. end]).unwrap();
+
+		let numbers: Vec<u64> = value["tracks"].as_array().unwrap().iter().map(|t| t["number"].as_u64().unwrap()).collect();
+		assert_eq!((1 ..= 12).collect::<Vec<u64>>(), numbers);
+		assert!(value["tracks"][1]["duration"].is_null());
+
+		/* Ratings are passed along for marking the favorites */
+		assert_eq!(5, value["tracks"][0]["rating"]);
+		assert!(value["tracks"][1]["rating"].is_null());
+	}
+
+	#[test]
+	fn test_fill_template()
+	{
+		/* Values that look like markers don't get replaced again */
+		assert_eq!("<b>{{PLAYLIST_JSON}}</b> - [1]",
+		           fill_template("<b>{{TITLE}}</b> - {{PLAYLIST_JSON}}", &[(TITLE_MARKER, "{{PLAYLIST_JSON}}"), (DATA_MARKER, "[1]")]));
+	}
+
+	#[test]
+	fn test_manifest_names()
+	{
+		let expected: Vec<String> = ["Track_01-20170403-VL01_tranquil.mp3",
+		                             "Track_02-20170403-VL02_stormy.flac",
+		                             "Track_03-20170404-VL01_gone.mp3"]
+			.iter().map(|s| s.to_string()).collect();
+
+		/* Converted to ogg, with the 3rd track having failed */
+		let manifest = parse_m3u("#EXTM3U\n\n\
+		                          Track_01-20170403-VL01_tranquil.ogg\n\
+		                          Track_02-20170403-VL02_stormy.ogg\n");
+		assert_eq!(vec![Some("Track_01-20170403-VL01_tranquil.ogg".to_string()),
+		                Some("Track_02-20170403-VL02_stormy.ogg".to_string()),
+		                None],
+		           map_manifest_names(&expected, &manifest));
+
+		/* Paths in the manifest only count by their filenames */
+		let manifest = parse_m3u("sub/Track_03-20170404-VL01_gone.mp3\n");
+		assert_eq!(Some("Track_03-20170404-VL01_gone.mp3".to_string()), map_manifest_names(&expected, &manifest)[2]);
+	}
+}
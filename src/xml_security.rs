@@ -0,0 +1,469 @@
+/* Pre-parse scan of XML documents for suspicious markup
+ *
+ * Playlists sometimes come from other people, so rather than relying on the XML library
+ * to do the right thing with them, documents containing DTD-related declarations
+ * (<!DOCTYPE, <!ENTITY, etc.) or processing instructions (other than the <?xml ...?>
+ * declaration at the start) get refused outright.
+ *
+ * NOTE: This walks over the markup (skipping comments, CDATA sections, and quoted attribute values)
+ *       instead of just searching for the strings, so that they can still be mentioned in
+ *       comments/text without tripping it. It isn't a full XML parser though - anything malformed
+ *       is left for the real parser to complain about.
+ *
+ * Documents can either be scanned all at once (scan_document()), or as they get read in (ScanningReader),
+ * for when they get parsed without ever being loaded all at once.
+ */
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+/* *************************************************** */
+/* Findings */
+
+/* Kinds of suspicious markup */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum MarkupKind {
+	/* <!DOCTYPE ...> */
+	Doctype,
+	/* <!ENTITY ...> */
+	Entity,
+	/* Any other "<!" declaration (e.g. <!ELEMENT>, <!ATTLIST>) */
+	Declaration,
+	/* <?target ...?> that isn't the XML declaration */
+	ProcessingInstruction,
+}
+
+/* Suspicious markup found in a document */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct SuspiciousMarkup {
+	pub kind: MarkupKind,
+	/* Line number (1-based) that it starts on */
+	pub line: usize,
+	/* Start of the markup, for identifying it (e.g. "<!DOCTYPE", "<?php") */
+	pub snippet: String,
+}
+
+impl fmt::Display for SuspiciousMarkup {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		let what = match self.kind {
+			MarkupKind::Doctype               => "DOCTYPE declaration",
+			MarkupKind::Entity                => "ENTITY declaration",
+			MarkupKind::Declaration           => "markup declaration",
+			MarkupKind::ProcessingInstruction => "processing instruction",
+		};
+		write!(f, "{0} '{1}' on line {2}", what, self.snippet, self.line)
+	}
+}
+
+/* *************************************************** */
+/* Scanning */
+
+/* Position just after the end of the given terminator (searching from "start")
+ * > returns None if the terminator never appears
+ */
+fn skip_past(xml: &[u8], start: usize, terminator: &[u8]) -> Option<usize>
+{
+	xml[start ..].windows(terminator.len())
+	             .position(|w| w == terminator)
+	             .map(|offset| start + offset + terminator.len())
+}
+
+/* Position just after the '>' ending the tag starting at "start" (ignoring any '>' in quoted attribute values) */
+fn skip_tag(xml: &[u8], start: usize) -> Option<usize>
+{
+	let mut quote: Option<u8> = None;
+	for (offset, &b) in xml[start ..].iter().enumerate() {
+		match (quote, b) {
+			(None, b'"') | (None, b'\'') => quote = Some(b),
+			(None, b'>')                 => return Some(start + offset + 1),
+			(Some(q), _) if q == b       => quote = None,
+			_                            => {}
+		}
+	}
+	None
+}
+
+/* Name following a "<!" or "<?" (i.e. the declaration keyword, or processing instruction target)
+ * > returns None if the name might carry on past the end of what's been read so far
+ */
+fn markup_name(xml: &[u8], start: usize, at_end: bool) -> Option<String>
+{
+	let rest = String::from_utf8_lossy(&xml[start ..]);
+	match rest.find(|c: char| c.is_whitespace() || c == '>' || c == '?' || c == '[' || c == '<') {
+		Some(end)          => Some(rest[.. end].to_string()),
+		None if at_end     => Some(rest.into_owned()),
+		None               => None
+	}
+}
+
+/* Byte order mark that documents may start with */
+const BOM: &'static [u8] = b"\xEF\xBB\xBF";
+
+/* Longest opening that needs to be seen to tell what kind of markup something is (i.e. "<![CDATA[") */
+const LONGEST_OPENING: usize = 9;
+
+/* Scans a document for DTD declarations and processing instructions, a piece at a time
+ * (e.g. as it gets read in, for documents that never get loaded all at once)
+ *
+ * Markup that's split across pieces is held back until the rest of it arrives.
+ */
+pub struct MarkupScanner {
+	/* Bytes that haven't been scanned yet (i.e. the start of some unfinished markup) */
+	pending: Vec<u8>,
+	/* Number of bytes scanned before the pending ones */
+	offset: usize,
+	/* Line that the pending bytes start on */
+	line: usize,
+
+	/* Where the document starts (after any byte order mark), once that's known */
+	doc_start: Option<usize>,
+
+	/* First suspicious thing found */
+	finding: Option<SuspiciousMarkup>,
+	/* Stopped scanning (something was found, or the markup was unterminated) */
+	stopped: bool,
+}
+
+impl MarkupScanner {
+	pub fn new() -> MarkupScanner
+	{
+		MarkupScanner {
+			pending: Vec::new(),
+			offset: 0,
+			line: 1,
+			doc_start: None,
+			finding: None,
+			stopped: false,
+		}
+	}
+
+	/* Scan the next piece of the document
+	 * > returns the first suspicious thing found (once it's been found)
+	 */
+	pub fn feed(&mut self, bytes: &[u8]) -> Option<&SuspiciousMarkup>
+	{
+		if !self.stopped {
+			self.pending.extend_from_slice(bytes);
+			self.scan(false);
+		}
+		self.finding.as_ref()
+	}
+
+	/* Scan whatever is left, now that the end of the document has been reached
+	 * > returns the first suspicious thing found (or None if the document looks safe)
+	 */
+	pub fn finish(&mut self) -> Option<&SuspiciousMarkup>
+	{
+		if !self.stopped {
+			self.scan(true);
+			self.stopped = true;
+		}
+		self.finding.as_ref()
+	}
+
+	/* Scan as much of the pending bytes as possible
+	 * - at_end: There won't be any more (so anything unfinished is as complete as it'll get)
+	 */
+	fn scan(&mut self, at_end: bool)
+	{
+		let xml = &self.pending;
+
+		/* The XML declaration is only allowed at the very start (after an optional byte order mark) */
+		let doc_start = match self.doc_start {
+			Some(doc_start) => doc_start,
+			None if !at_end && xml.len() < BOM.len() && BOM.starts_with(xml) => return,
+			None => {
+				let doc_start = if xml.starts_with(BOM) { BOM.len() } else { 0 };
+				self.doc_start = Some(doc_start);
+				doc_start
+			}
+		};
+
+		let mut pos = doc_start.saturating_sub(self.offset);
+		let mut finding: Option<(MarkupKind, usize, &str, String)> = None;
+		let mut stop = false;
+
+		while let Some(offset) = xml[pos ..].iter().position(|&b| b == b'<') {
+			let start = pos + offset;
+			let rest = &xml[start ..];
+
+			/* Wait for enough to tell what it is */
+			if !at_end && rest.len() < LONGEST_OPENING {
+				pos = start;
+				break;
+			}
+
+			let next = if rest.starts_with(b"<!--") {
+				skip_past(xml, start + 4, b"-->")
+			}
+			else if rest.starts_with(b"<![CDATA[") {
+				skip_past(xml, start + 9, b"]]>")
+			}
+			else if rest.starts_with(b"<!") {
+				match markup_name(xml, start + 2, at_end) {
+					Some(name) => {
+						let kind = match name.to_uppercase().as_ref() {
+							"DOCTYPE" => MarkupKind::Doctype,
+							"ENTITY"  => MarkupKind::Entity,
+							_         => MarkupKind::Declaration,
+						};
+						finding = Some((kind, start, "<!", name));
+						break;
+					},
+					None => None
+				}
+			}
+			else if rest.starts_with(b"<?") {
+				match markup_name(xml, start + 2, at_end) {
+					Some(name) => {
+						let is_xml_declaration = (self.offset + start == doc_start) && (name == "xml");
+						if !is_xml_declaration {
+							finding = Some((MarkupKind::ProcessingInstruction, start, "<?", name));
+							break;
+						}
+						skip_past(xml, start + 2, b"?>")
+					},
+					None => None
+				}
+			}
+			else {
+				skip_tag(xml, start + 1)
+			};
+
+			match next {
+				Some(next) => pos = next,
+				None => {
+					/* Unterminated - wait for the rest, or at the end, let the XML parser report it */
+					stop = at_end;
+					pos = start;
+					break;
+				}
+			}
+		}
+
+		if let Some((kind, start, prefix, name)) = finding {
+			self.finding = Some(SuspiciousMarkup {
+				kind: kind,
+				line: self.line + xml[.. start].iter().filter(|&&b| b == b'\n').count(),
+				snippet: format!("{0}{1}", prefix, name),
+			});
+			self.stopped = true;
+			return;
+		}
+		if stop {
+			self.stopped = true;
+			return;
+		}
+
+		/* Text up to the next markup (or everything, if there isn't any) doesn't need keeping */
+		let scanned = if xml[pos ..].contains(&b'<') || pos > xml.len() { pos } else { xml.len() };
+		self.line += xml[.. scanned].iter().filter(|&&b| b == b'\n').count();
+		self.offset += scanned;
+		self.pending.drain(.. scanned);
+	}
+}
+
+/* Scan the document for DTD declarations and processing instructions
+ * > returns the first suspicious thing found (or None if the document looks safe)
+ */
+pub fn scan_document(xml: &str) -> Option<SuspiciousMarkup>
+{
+	let mut scanner = MarkupScanner::new();
+	scanner.feed(xml.as_bytes());
+	scanner.finish().cloned()
+}
+
+/* *************************************************** */
+/* Scanning Reader */
+
+/* Passes a document through to the XML parser, scanning it on the way (see MarkupScanner)
+ * Reading fails as soon as anything suspicious turns up, before the parser gets to see it.
+ */
+pub struct ScanningReader<R: BufRead> {
+	inner: R,
+	scanner: MarkupScanner,
+	/* Number of bytes at the start of the inner reader's buffer that have already been scanned */
+	scanned: usize,
+}
+
+impl<R: BufRead> ScanningReader<R> {
+	pub fn new(inner: R) -> ScanningReader<R>
+	{
+		ScanningReader {
+			inner: inner,
+			scanner: MarkupScanner::new(),
+			scanned: 0,
+		}
+	}
+
+	/* Suspicious thing that stopped the reading (if any) */
+	pub fn finding(&self) -> Option<&SuspiciousMarkup>
+	{
+		self.scanner.finding.as_ref()
+	}
+}
+
+impl<R: BufRead> Read for ScanningReader<R> {
+	fn read(&mut self, out: &mut [u8]) -> io::Result<usize>
+	{
+		let n = {
+			let available = self.fill_buf()?;
+			let n = available.len().min(out.len());
+			out[.. n].copy_from_slice(&available[.. n]);
+			n
+		};
+		self.consume(n);
+		Ok(n)
+	}
+}
+
+impl<R: BufRead> BufRead for ScanningReader<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]>
+	{
+		let buf = self.inner.fill_buf()?;
+		let found = if buf.is_empty() {
+			self.scanner.finish().is_some()
+		}
+		else if buf.len() > self.scanned {
+			let found = self.scanner.feed(&buf[self.scanned ..]).is_some();
+			self.scanned = buf.len();
+			found
+		}
+		else {
+			self.scanner.finding.is_some()
+		};
+
+		if found {
+			Err(io::Error::new(io::ErrorKind::InvalidData, "refused to read past suspicious markup"))
+		}
+		else {
+			Ok(buf)
+		}
+	}
+
+	fn consume(&mut self, amt: usize)
+	{
+		self.inner.consume(amt);
+		self.scanned = self.scanned.saturating_sub(amt);
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DECLARATION: &'static str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+
+	fn playlist(body: &str) -> String
+	{
+		format!("{0}<playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\">\n{1}\n</playlist>\n", DECLARATION, body)
+	}
+
+	#[test]
+	fn test_safe_documents()
+	{
+		assert_eq!(None, scan_document(&playlist("<title>Sessions</title>")));
+		assert_eq!(None, scan_document("<playlist/>"));
+		assert_eq!(None, scan_document(&format!("\u{FEFF}{}", playlist(""))));
+
+		/* Mentioned in comments, CDATA, and attribute values */
+		assert_eq!(None, scan_document(&playlist("<title>Mix <!-- not a <!DOCTYPE html> or <?php ?> --></title>")));
+		assert_eq!(None, scan_document(&playlist("<annotation><![CDATA[<!ENTITY x SYSTEM \"file:///etc/passwd\">]]></annotation>")));
+		assert_eq!(None, scan_document(&playlist("<meta rel=\"<!DOCTYPE>\">x</meta>")));
+	}
+
+	#[test]
+	fn test_doctype()
+	{
+		let xml = format!("{0}<!DOCTYPE playlist [\n<!ENTITY xxe SYSTEM \"file:///etc/passwd\">\n]>\n<playlist/>", DECLARATION);
+		let found = scan_document(&xml).unwrap();
+		assert_eq!(MarkupKind::Doctype, found.kind);
+		assert_eq!(2, found.line);
+		assert_eq!("DOCTYPE declaration '<!DOCTYPE' on line 2", found.to_string());
+
+		/* Entities declared on their own (e.g. inside the playlist) */
+		let found = scan_document(&playlist("<title>x</title>\n<!ENTITY lol \"lol\">")).unwrap();
+		assert_eq!(MarkupKind::Entity, found.kind);
+		assert_eq!(4, found.line);
+
+		let found = scan_document(&playlist("<!ELEMENT title ANY>")).unwrap();
+		assert_eq!(MarkupKind::Declaration, found.kind);
+	}
+
+	#[test]
+	fn test_processing_instructions()
+	{
+		let found = scan_document(&playlist("<?xml-stylesheet href=\"evil.xsl\"?>")).unwrap();
+		assert_eq!(MarkupKind::ProcessingInstruction, found.kind);
+		assert_eq!("<?xml-stylesheet", found.snippet);
+
+		/* The XML declaration anywhere other than the start */
+		let found = scan_document(&format!("\n{}", playlist(""))).unwrap();
+		assert_eq!(MarkupKind::ProcessingInstruction, found.kind);
+		assert_eq!("<?xml", found.snippet);
+	}
+
+	/* Scan the document a few bytes at a time (splitting any markup across pieces) */
+	fn scan_in_pieces(xml: &str, piece_size: usize) -> Option<SuspiciousMarkup>
+	{
+		let mut scanner = MarkupScanner::new();
+		for piece in xml.as_bytes().chunks(piece_size) {
+			if scanner.feed(piece).is_some() {
+				break;
+			}
+		}
+		scanner.finish().cloned()
+	}
+
+	#[test]
+	fn test_scan_in_pieces()
+	{
+		let documents = vec![
+			playlist("<title>Sessions</title>"),
+			format!("\u{FEFF}{}", playlist("")),
+			playlist("<title>Mix <!-- not a <!DOCTYPE html> or <?php ?> --></title>"),
+			playlist("<annotation><![CDATA[<!ENTITY x SYSTEM \"file:///etc/passwd\">]]></annotation>"),
+			playlist("<meta rel=\"<!DOCTYPE>\">x</meta>"),
+			format!("{0}<!DOCTYPE playlist [\n<!ENTITY xxe SYSTEM \"file:///etc/passwd\">\n]>\n<playlist/>", DECLARATION),
+			playlist("<title>x</title>\n<!ENTITY lol \"lol\">"),
+			playlist("<?xml-stylesheet href=\"evil.xsl\"?>"),
+			format!("\n{}", playlist("")),
+			"<playlist><title>Unterminated <!-- comment".to_string(),
+		];
+		for xml in documents.iter() {
+			let expected = scan_document(xml);
+			for piece_size in 1 .. 12 {
+				assert_eq!(expected, scan_in_pieces(xml, piece_size), "{:?} in pieces of {}", xml, piece_size);
+			}
+		}
+	}
+
+	#[test]
+	fn test_scanning_reader()
+	{
+		use std::io::BufReader;
+
+		/* Safe documents get passed through unchanged */
+		let xml = playlist("<title>Mix <!-- <!DOCTYPE> --></title>");
+		let mut reader = ScanningReader::new(BufReader::with_capacity(7, xml.as_bytes()));
+		let mut contents = String::new();
+		reader.read_to_string(&mut contents).unwrap();
+		assert_eq!(xml, contents);
+		assert_eq!(None, reader.finding());
+
+		/* Reading stops before all of anything suspicious gets through */
+		let xml = playlist(&format!("<title>x</title>\n<!ENTITY lol \"lol\">\n{}", "<!-- padding -->".repeat(100)));
+		let mut reader = ScanningReader::new(BufReader::with_capacity(16, xml.as_bytes()));
+		let mut contents = String::new();
+		assert!(reader.read_to_string(&mut contents).is_err());
+		assert!(!contents.contains("<!ENTITY"), "{}", contents);
+		assert_eq!(scan_document(&xml).as_ref(), reader.finding());
+	}
+}
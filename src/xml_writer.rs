@@ -0,0 +1,178 @@
+/* Writing simple XML documents (e.g. XSPF playlists, and .nfo sidecars)
+ *
+ * The documents written by this tool are small and flat, so they're just built up as text, one element
+ * per line (indented with tabs). This takes care of the escaping, and of keeping the tags balanced.
+ */
+
+/* Declaration at the start of every document */
+const XML_DECLARATION: &'static str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>";
+
+/* *************************************************** */
+/* Escaping */
+
+/* Escape text for use in element content */
+pub fn escape_text(s: &str) -> String
+{
+	s.replace('&', "&amp;")
+	 .replace('<', "&lt;")
+	 .replace('>', "&gt;")
+}
+
+/* Escape text for use in a (double-quoted) attribute value */
+pub fn escape_attr(s: &str) -> String
+{
+	escape_text(s).replace('"', "&quot;")
+}
+
+/* Make text safe to put inside a comment
+ * Comments can't contain "--" (or end with "-"), and there's no way to escape it, so a space gets added between them
+ */
+pub fn escape_comment(s: &str) -> String
+{
+	let mut result = String::with_capacity(s.len());
+	for c in s.chars() {
+		if c == '-' && result.ends_with('-') {
+			result.push(' ');
+		}
+		result.push(c);
+	}
+	if result.ends_with('-') {
+		result.push(' ');
+	}
+	result
+}
+
+/* *************************************************** */
+/* Writer */
+
+/* Builds up an XML document, one line at a time */
+pub struct XmlWriter {
+	xml: String,
+	/* Elements that have been started but not ended yet (innermost last) */
+	open: Vec<String>,
+}
+
+impl XmlWriter {
+	/* Start a new document (with the XML declaration) */
+	pub fn new() -> XmlWriter
+	{
+		XmlWriter {
+			xml: format!("{}\n", XML_DECLARATION),
+			open: Vec::new(),
+		}
+	}
+
+	fn indent(&mut self)
+	{
+		for _ in 0 .. self.open.len() {
+			self.xml.push('\t');
+		}
+	}
+
+	/* Start an element that will contain other elements - <tag attr="value" ...> */
+	pub fn start(&mut self, tag: &str, attrs: &[(&str, &str)])
+	{
+		self.indent();
+		self.xml.push('<');
+		self.xml.push_str(tag);
+		for &(name, value) in attrs.iter() {
+			self.xml.push_str(&format!(" {0}=\"{1}\"", name, escape_attr(value)));
+		}
+		self.xml.push_str(">\n");
+		self.open.push(tag.to_string());
+	}
+
+	/* End the innermost element that was started */
+	pub fn end(&mut self)
+	{
+		if let Some(tag) = self.open.pop() {
+			self.indent();
+			self.xml.push_str(&format!("</{}>\n", tag));
+		}
+	}
+
+	/* Add an element containing only text - <tag>text</tag> */
+	pub fn text_element(&mut self, tag: &str, text: &str)
+	{
+		self.indent();
+		self.xml.push_str(&format!("<{0}>{1}</{0}>\n", tag, escape_text(text)));
+	}
+
+	/* Add an element with attributes, and either some text or nothing inside it - <tag attr="value">text</tag> or <tag attr="value"/> */
+	pub fn element(&mut self, tag: &str, attrs: &[(&str, &str)], text: Option<&str>)
+	{
+		self.indent();
+		self.xml.push('<');
+		self.xml.push_str(tag);
+		for &(name, value) in attrs.iter() {
+			self.xml.push_str(&format!(" {0}=\"{1}\"", name, escape_attr(value)));
+		}
+		match text {
+			Some(text) => self.xml.push_str(&format!(">{0}</{1}>\n", escape_text(text), tag)),
+			None       => self.xml.push_str("/>\n")
+		}
+	}
+
+	/* Add some text on its own line (inside the current element) */
+	pub fn text(&mut self, text: &str)
+	{
+		self.indent();
+		self.xml.push_str(&escape_text(text));
+		self.xml.push('\n');
+	}
+
+	/* Add a comment - <!-- text --> */
+	pub fn comment(&mut self, text: &str)
+	{
+		self.indent();
+		self.xml.push_str(&format!("<!-- {} -->\n", escape_comment(text)));
+	}
+
+	/* Finish the document (ending any elements still open), and get its text */
+	pub fn finish(mut self) -> String
+	{
+		while !self.open.is_empty() {
+			self.end();
+		}
+		self.xml
+	}
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_escaping()
+	{
+		assert_eq!("Sessions &lt;2017&gt; &amp; more", escape_text("Sessions <2017> & more"));
+		assert_eq!("say &quot;hi&quot; &amp; go", escape_attr("say \"hi\" & go"));
+		assert_eq!("say \"hi\"", escape_text("say \"hi\""));
+
+		/* Comments can't contain "--", or end with "-" */
+		assert_eq!("a- -b- - -c- ", escape_comment("a--b---c-"));
+		assert_eq!("/music/v01-a-b.mp3", escape_comment("/music/v01-a-b.mp3"));
+	}
+
+	#[test]
+	fn test_writer()
+	{
+		let mut w = XmlWriter::new();
+		w.start("playlist", &[("version", "1")]);
+		w.comment("made by --tests");
+		w.start("trackList", &[]);
+		w.text_element("title", "A & B");
+		let xml = w.finish();
+
+		assert_eq!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		            <playlist version=\"1\">\n\
+		            \t<!-- made by - -tests -->\n\
+		            \t<trackList>\n\
+		            \t\t<title>A &amp; B</title>\n\
+		            \t</trackList>\n\
+		            </playlist>\n", xml);
+	}
+}
@@ -0,0 +1,306 @@
+/* Other applications' <extension> blocks in XSPF playlists
+ *
+ * Players stash their own data in <extension application="..."> blocks, both on the playlist and on its
+ * tracks - e.g. VLC gives each track an id (<vlc:id>), and lists the order it shows them in at the end of
+ * the playlist (<vlc:item tid="..."/>). None of that means anything to us, but it shouldn't get lost when
+ * the playlist gets written back out, so the contents of each block get kept as they were found (as a tree
+ * of elements), to be written out again as they were.
+ *
+ * NOTE: Our own extension (see xspf_parser::EXTENSION_APPLICATION) isn't kept like this, as its details get
+ *       read into the tracks themselves.
+ */
+extern crate minidom;
+use self::minidom::Element;
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use xml_writer::XmlWriter;
+
+/* *************************************************** */
+/* Types */
+
+/* An element inside an extension block (along with everything inside it) */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct ExtensionNode {
+	/* Name of the element, including any namespace prefix (e.g. "vlc:id") */
+	pub name: String,
+
+	#[serde(default)]
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub attrs: BTreeMap<String, String>,
+
+	/* Text directly inside the element (trimmed) - if there's any */
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub text: Option<String>,
+
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub children: Vec<ExtensionNode>,
+}
+
+/* An <extension> block, as given by another application */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct ExtensionBlock {
+	/* URI identifying the application (i.e. the block's "application" attribute) */
+	pub application: String,
+
+	/* Namespaces of the prefixed elements in the block, by prefix (e.g. "vlc" -> "http://www.videolan.org/vlc/playlist/ns/0/")
+	 * NOTE: These usually get declared on the playlist itself, so they get declared on the block when it's written out
+	 */
+	#[serde(default)]
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub namespaces: BTreeMap<String, String>,
+
+	/* Elements inside the block */
+	pub nodes: Vec<ExtensionNode>,
+}
+
+/* *************************************************** */
+/* Reading */
+
+/* Name of the element as written in the document, with its prefix (if any) */
+fn qualified_name(elem: &Element) -> String
+{
+	match elem.prefix() {
+		Some(prefix) => format!("{0}:{1}", prefix, elem.name()),
+		None         => elem.name().to_string()
+	}
+}
+
+impl ExtensionNode {
+	/* Node for the given element, noting the namespace of any prefixed elements in "namespaces" */
+	fn from_element(elem: &Element, namespaces: &mut BTreeMap<String, String>) -> ExtensionNode
+	{
+		if let (Some(prefix), Some(ns)) = (elem.prefix(), elem.ns()) {
+			namespaces.entry(prefix.to_string()).or_insert(ns);
+		}
+
+		let text = elem.text().trim().to_string();
+		ExtensionNode {
+			name: qualified_name(elem),
+			attrs: elem.attrs().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+			text: if text.is_empty() { None } else { Some(text) },
+			children: elem.children().map(|child| ExtensionNode::from_element(child, namespaces)).collect(),
+		}
+	}
+}
+
+impl ExtensionBlock {
+	/* Block for the given <extension> element
+	 * > returns None if it doesn't say which application it's from (as there's no way to tell what it is then)
+	 */
+	pub fn from_element(e_extension: &Element) -> Option<ExtensionBlock>
+	{
+		let application = e_extension.attr("application")?.to_string();
+		let mut namespaces = BTreeMap::new();
+		let nodes = e_extension.children()
+		                       .map(|child| ExtensionNode::from_element(child, &mut namespaces))
+		                       .collect();
+		Some(ExtensionBlock {
+			application: application,
+			namespaces: namespaces,
+			nodes: nodes,
+		})
+	}
+}
+
+/* *************************************************** */
+/* Writing */
+
+impl ExtensionNode {
+	fn write(&self, xml: &mut XmlWriter)
+	{
+		let attrs: Vec<(&str, &str)> = self.attrs.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+		if self.children.is_empty() {
+			xml.element(&self.name, &attrs, self.text.as_deref());
+		}
+		else {
+			xml.start(&self.name, &attrs);
+			if let Some(ref text) = self.text {
+				xml.text(text);
+			}
+			for child in self.children.iter() {
+				child.write(xml);
+			}
+			xml.end();
+		}
+	}
+
+	/* Add a line describing this node (and more for each of its children, indented further) */
+	fn describe(&self, depth: usize, lines: &mut Vec<String>)
+	{
+		let mut line = format!("{0}{1}", "  ".repeat(depth), self.name);
+		for (name, value) in self.attrs.iter() {
+			line.push_str(&format!(" {0}=\"{1}\"", name, value));
+		}
+		if let Some(ref text) = self.text {
+			line.push_str(&format!(" = '{}'", text));
+		}
+		lines.push(line);
+
+		for child in self.children.iter() {
+			child.describe(depth + 1, lines);
+		}
+	}
+}
+
+impl ExtensionBlock {
+	/* Write the block out as an <extension> element (as it was when read in) */
+	pub fn write(&self, xml: &mut XmlWriter)
+	{
+		let declarations: Vec<(String, &str)> = self.namespaces.iter()
+		                                            .map(|(prefix, ns)| (format!("xmlns:{}", prefix), ns.as_str()))
+		                                            .collect();
+		let mut attrs = vec![("application", self.application.as_str())];
+		attrs.extend(declarations.iter().map(|&(ref name, ns)| (name.as_str(), ns)));
+
+		xml.start("extension", &attrs);
+		for node in self.nodes.iter() {
+			node.write(xml);
+		}
+		xml.end();
+	}
+
+	/* Outline of the elements in the block, one per line (indented by how deeply they're nested) - e.g. for "dump --verbose" */
+	pub fn describe(&self) -> Vec<String>
+	{
+		let mut lines = Vec::new();
+		for node in self.nodes.iter() {
+			node.describe(0, &mut lines);
+		}
+		lines
+	}
+}
+
+/* *************************************************** */
+/* Schema */
+
+/* JSON Schema for a list of extension blocks (as included in XspfPlaylist, and each Track)
+ * NOTE: Nodes nested inside others aren't described any further, as the schemas can't refer back to themselves
+ */
+pub fn extensions_schema() -> Value
+{
+	let node_schema = json!({
+		"type": "object",
+		"properties": {
+			"name": { "type": "string" },
+			"attrs": { "type": "object", "additionalProperties": { "type": "string" } },
+			"text": { "type": "string" },
+			"children": { "type": "array", "items": { "type": "object" } }
+		},
+		"required": ["name"],
+		"additionalProperties": false
+	});
+
+	json!({
+		"description": "Other applications' <extension> blocks, kept as given (only present if there are any)",
+		"type": "array",
+		"items": {
+			"type": "object",
+			"properties": {
+				"application": { "type": "string" },
+				"namespaces": { "type": "object", "additionalProperties": { "type": "string" } },
+				"nodes": { "type": "array", "items": node_schema }
+			},
+			"required": ["application", "nodes"],
+			"additionalProperties": false
+		}
+	})
+}
+
+/* *************************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const VLC_NS: &'static str = "http://www.videolan.org/vlc/playlist/ns/0/";
+	const VLC_APPLICATION: &'static str = "http://www.videolan.org/vlc/playlist/0";
+
+	/* The <extension> element in the given document (nested somewhere under the root) */
+	fn find_extension(elem: &Element) -> Option<&Element>
+	{
+		if elem.name() == "extension" {
+			Some(elem)
+		}
+		else {
+			elem.children().filter_map(find_extension).next()
+		}
+	}
+
+	fn parse_block(xml: &str) -> Option<ExtensionBlock>
+	{
+		let root: Element = xml.parse().unwrap();
+		find_extension(&root).and_then(ExtensionBlock::from_element)
+	}
+
+	#[test]
+	fn test_from_element()
+	{
+		let xml = format!("<track xmlns=\"http://xspf.org/ns/0/\" xmlns:vlc=\"{0}\">\
+		                   <extension application=\"{1}\">\
+		                   <vlc:id>3</vlc:id>\
+		                   <vlc:option>start-time=5</vlc:option>\
+		                   </extension></track>", VLC_NS, VLC_APPLICATION);
+		let block = parse_block(&xml).unwrap();
+
+		assert_eq!(VLC_APPLICATION, block.application);
+		assert_eq!(Some(VLC_NS), block.namespaces.get("vlc").map(|ns| ns.as_str()));
+		assert_eq!(vec![("vlc:id", Some("3")), ("vlc:option", Some("start-time=5"))],
+		           block.nodes.iter().map(|n| (n.name.as_str(), n.text.as_deref())).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_nested_nodes()
+	{
+		let xml = format!("<playlist xmlns:vlc=\"{0}\">\
+		                   <extension application=\"{1}\">\
+		                   <vlc:node title=\"Violin\"><vlc:item tid=\"0\"/><vlc:item tid=\"2\"/></vlc:node>\
+		                   <vlc:item tid=\"1\"/>\
+		                   </extension></playlist>", VLC_NS, VLC_APPLICATION);
+		let block = parse_block(&xml).unwrap();
+
+		assert_eq!(vec!["vlc:node title=\"Violin\"",
+		                "  vlc:item tid=\"0\"",
+		                "  vlc:item tid=\"2\"",
+		                "vlc:item tid=\"1\""],
+		           block.describe());
+	}
+
+	#[test]
+	fn test_no_application()
+	{
+		assert!(parse_block("<track><extension><id>3</id></extension></track>").is_none());
+	}
+
+	#[test]
+	fn test_write()
+	{
+		let xml = format!("<track xmlns:vlc=\"{0}\">\
+		                   <extension application=\"{1}\">\
+		                   <vlc:id>3</vlc:id>\
+		                   <vlc:node title=\"A &amp; B\"><vlc:item tid=\"0\"/></vlc:node>\
+		                   </extension></track>", VLC_NS, VLC_APPLICATION);
+		let block = parse_block(&xml).unwrap();
+
+		let mut w = XmlWriter::new();
+		block.write(&mut w);
+		let written = w.finish();
+		assert!(written.contains(&format!("<extension application=\"{0}\" xmlns:vlc=\"{1}\">", VLC_APPLICATION, VLC_NS)));
+		assert!(written.contains("\t<vlc:id>3</vlc:id>\n"));
+		assert!(written.contains("\t<vlc:node title=\"A &amp; B\">\n\t\t<vlc:item tid=\"0\"/>\n\t</vlc:node>\n"));
+
+		/* Reading it back in gives the same block */
+		assert_eq!(Some(block), parse_block(&written));
+	}
+}
@@ -4,97 +4,358 @@
  * so that we can just abstract out the bits we want to expose.
  */
 extern crate minidom;
+extern crate quick_xml;
+#[cfg(feature = "mmap")]
+extern crate memmap;
 use self::minidom::Element;
+use self::quick_xml::Reader as XmlReader;
+use self::quick_xml::events::{BytesStart, Event};
+#[cfg(feature = "mmap")]
+use self::memmap::Mmap;
 
-extern crate urlparse;
-use self::urlparse::unquote;
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use std::fs::File;
-use std::io::prelude::*;
+use serde_json::Value;
 
+use diagnostics::{Diagnostics, Severity};
+use duration_source::DurationSource;
+use playlist_input;
+use playlist_stats::StatsSummary;
+use track_date::TrackDate;
 use track_duration::TrackDuration;
-use track_name_info::FilenameInfoComponents;
+use track_name_info::{self, FilenameInfoComponents, TrackType, TRACK_TYPES};
+use track_path::TrackPath;
+use xml_security;
+use xml_writer::XmlWriter;
+use xspf_extension::{self, ExtensionBlock};
+
+pub use track_path::LocationForm;
 
 /* ********************************************** */
 /* Playlist Types */
 
 /* A track listing in the playlist */
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 #[derive(Debug)]
+#[derive(Clone)]
 pub struct Track {
-	/* Full path (extracted from the file) */
-	pub path: String,
+	/* Full path (extracted from the file)
+	 * NOTE: Shown with any non-UTF-8 bytes replaced (see TrackPath). Use file_path() when accessing the file itself.
+	 */
+	pub path: TrackPath,
 	
 	/* Full name of the track itself (v<num>_<name>.<mp3/flac>) */
 	pub filename: String,
-	/* Date string of the track (i.e. parent directory) */
-	pub date: String,
+	/* Session date of the track (i.e. parent directory - or for MuseScore files, the date in the filename if that isn't a date) */
+	pub date: TrackDate,
 	
 	/* Duration (in ms) of the track - as stored in the file */
 	pub duration: Option<TrackDuration>,
+	/* Where the duration came from (see duration_source::resolve_duration()) */
+	pub duration_source: DurationSource,
+	
+	/* Title given to the track in the playlist (if any) */
+	pub title: Option<String>,
+	/* Other details given for the track in the playlist (if any) - <creator>, <album>, and <annotation> (i.e. notes) */
+	#[serde(default)]
+	pub creator: Option<String>,
+	#[serde(default)]
+	pub album: Option<String>,
+	#[serde(default)]
+	pub annotation: Option<String>,
+	
+	/* Playlist the track was taken from, when it's been merged into another one (see XspfPlaylist::merge()) */
+	#[serde(default)]
+	pub source_playlist: Option<String>,
+	
+	/* Position of the track's entry in the playlist it was read from (0-based, counting the entries that got skipped too),
+	 * so it can still be matched up with what players show after the tracks have been filtered, sorted, or merged
+	 * NOTE: Tracks that weren't read from a playlist (e.g. added by "edit insert") have 0
+	 */
+	#[serde(default)]
+	pub original_index: usize,
+	
+	/* Rating given to the track (0-5 stars), taken from its extension properties (see XspfPlaylist::apply_ratings()) */
+	#[serde(default)]
+	pub rating: Option<u8>,
 	
-	/* FileInfo */
-	pub info : FilenameInfoComponents
+	/* Values from <extension> blocks added by other applications (e.g. "rating" -> "4"), by element name */
+	#[serde(default)]
+	pub properties: BTreeMap<String, String>,
+	/* Other applications' <extension> blocks, as they were given (so they can be written back out) */
+	#[serde(default)]
+	pub extensions: Vec<ExtensionBlock>,
+	
+	/* FileInfo - Only worked out from the filename when first needed (see info()) */
+	#[serde(default, deserialize_with = "deserialize_info")]
+	info : OnceCell<FilenameInfoComponents>
+}
+
+/* Session date for a track at the given location - from its directory name, or failing that, the date in its filename */
+fn date_for(path: &TrackPath, filename: &str) -> TrackDate
+{
+	let date = TrackDate::parse(&path.parent_name());
+	if date.is_known() {
+		date
+	}
+	else {
+		date.with_fallback(track_name_info::embedded_date(filename).as_deref())
+	}
+}
+
+/* Load any "info" that was saved along with the track (otherwise it gets worked out again when needed) */
+fn deserialize_info<'de, D>(deserializer: D) -> Result<OnceCell<FilenameInfoComponents>, D::Error>
+	where D: ::serde::Deserializer<'de>
+{
+	let info: FilenameInfoComponents = ::serde::Deserialize::deserialize(deserializer)?;
+	Ok(OnceCell::from(info))
+}
+
+/* Serialised form of a track (with the info worked out, if it wasn't already) */
+#[derive(Serialize)]
+struct TrackRepr<'a> {
+	path: &'a TrackPath,
+	filename: &'a str,
+	date: &'a TrackDate,
+	duration: &'a Option<TrackDuration>,
+	duration_source: DurationSource,
+	title: &'a Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	creator: &'a Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	album: &'a Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	annotation: &'a Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	source_playlist: &'a Option<String>,
+	original_index: usize,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	rating: Option<u8>,
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	properties: &'a BTreeMap<String, String>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	extensions: &'a Vec<ExtensionBlock>,
+	info: &'a FilenameInfoComponents,
 }
 
-const FILE_URI_PREFIX: &'static str = "file:///";
+impl ::serde::Serialize for Track {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where S: ::serde::Serializer
+	{
+		TrackRepr {
+			path: &self.path,
+			filename: &self.filename,
+			date: &self.date,
+			duration: &self.duration,
+			duration_source: self.duration_source,
+			title: &self.title,
+			creator: &self.creator,
+			album: &self.album,
+			annotation: &self.annotation,
+			source_playlist: &self.source_playlist,
+			original_index: self.original_index,
+			rating: self.rating,
+			properties: &self.properties,
+			extensions: &self.extensions,
+			info: self.info(),
+		}.serialize(serializer)
+	}
+}
 
 impl Track {
-	/* Generate a track element from a file path */
+	/* Generate a track element from a file path (with "%xx" escapes, as found in URIs)
+	 * NOTE: Only used for test fixtures - locations in playlists go through TrackPath::from_location()
+	 */
+	#[cfg(test)]
 	pub fn from_filepath(path: &str) -> Result<Track, &'static str>
 	{
-		/* Full "unmodfied" path (with the symbols replaced, so that we can find the files) */
-		let fullpath = unquote(path).ok().unwrap();
-		
-		/* extra filename and date from the last parts of the path 
-		 * WARNING: We're extracting these in reverse order! So first filename, then date!
+		Track::from_track_path(TrackPath::from_escaped(path))
+	}
+	
+	/* Generate a track element from the path to the file */
+	pub fn from_path(fullpath: &Path) -> Result<Track, &'static str>
+	{
+		Track::from_track_path(TrackPath::from_native(fullpath))
+	}
+	
+	/* Generate a track element for the given location */
+	pub fn from_track_path(path: TrackPath) -> Result<Track, &'static str>
+	{
+		/* extract filename and date from the last parts of the path
+		 * NOTE: Non UTF-8 bytes get replaced here (see has_lossy_path())
 		 */
-		// TODO: Sanity checking!
-		let mut path_elems : Vec<&str> = fullpath.split("/").collect();
-		
-		let filename = path_elems.pop().unwrap().to_string();
-		let date = path_elems.pop().unwrap().to_string();
+		let filename = match path.file_name() {
+			Some(f) => f,
+			None    => return Err("Path doesn't include a filename")
+		};
+		let date = date_for(&path, &filename);
 		
 		/* Construct and return a track */
 		Ok(Track {
-			path: fullpath.clone(),
-			filename: filename.clone(),
-			date: date.clone(),
+			path: path,
+			filename: filename,
+			date: date,
 			duration: None,  /* Currently unknown */
-			info: FilenameInfoComponents::new(filename.as_ref()),
+			duration_source: DurationSource::Unknown,
+			title: None,
+			creator: None,
+			album: None,
+			annotation: None,
+			source_playlist: None,
+			original_index: 0,
+			rating: None,
+			properties: BTreeMap::new(),
+			extensions: Vec::new(),
+			info: OnceCell::new(),
 		})
 	}
 	
-	/* Generate a track element from a URI */
-	pub fn from_uri(uri: &str) -> Result<Track, &'static str>
+	/* Details extracted from the filename (type of track, index, name, etc.)
+	 * NOTE: These only get worked out on first use, as it's quite expensive (and not every mode needs them)
+	 */
+	pub fn info(&self) -> &FilenameInfoComponents
+	{
+		/* Names that can't be parsed at all get treated as just a name (without any details) */
+		self.info.get_or_init(|| FilenameInfoComponents::new(&self.filename).unwrap_or_else(|_| FilenameInfoComponents::unknown(&self.filename)))
+	}
+	
+	/* Name to show for the track - its title (if it has one), otherwise the name from the filename */
+	pub fn display_name(&self) -> &str
+	{
+		match self.title {
+			Some(ref title) => title,
+			None            => &self.info().name
+		}
+	}
+	
+	/* Path to use when accessing the file itself */
+	pub fn file_path(&self) -> &Path
+	{
+		self.path.native()
+	}
+	
+	/* Did the path contain bytes that aren't valid UTF-8? (i.e. "path" is a lossy version of the real path) */
+	pub fn has_lossy_path(&self) -> bool
+	{
+		self.path.is_lossy()
+	}
+	
+	/* How the location was written in the playlist (only known for tracks parsed from one) */
+	pub fn location_form(&self) -> Option<LocationForm>
+	{
+		self.path.location_form()
+	}
+	
+	
+	/* Move the track to another location (keeping everything else about it, e.g. its duration and title) */
+	fn relocate(&mut self, path: TrackPath)
+	{
+		self.date = date_for(&path, &self.filename);
+		self.path = path;
+	}
+	
+	/* Move the track to a file with the same name somewhere else (e.g. when it's been found under a search root - see track_relocate) */
+	pub fn relocate_to(&mut self, path: &Path)
+	{
+		self.relocate(TrackPath::from_native(path));
+	}
+	
+	/* Resolve the track's location against the given directory, if it's a relative one (see XspfPlaylist::resolve_relative_locations())
+	 * > returns whether it was
+	 */
+	fn resolve_relative_location(&mut self, base: &TrackPath) -> bool
 	{
-		if uri.starts_with(FILE_URI_PREFIX) {
-			// TODO: optimise this prefix stripping
-			let filename = uri[FILE_URI_PREFIX.len() ..].to_string();
-			Track::from_filepath(&filename)
+		if self.location_form() == Some(LocationForm::RelativePath) {
+			let fullpath = self.path.resolve_against(base);
+			self.relocate(fullpath);
+			true
 		}
 		else {
-			/* Unsupported URI */
-			Err("Unsupported URI - Must start with 'file:///'")
+			false
 		}
 	}
 	
+	/* Take the track's rating from the given extension property (see XspfPlaylist::apply_ratings())
+	 * > returns the rating, or why the value given isn't one (it's left in the properties then) - or None if there's no value
+	 */
+	fn take_rating(&mut self, key: &str) -> Option<Result<u8, String>>
+	{
+		let value = self.properties.get(key)?.clone();
+		match value.parse::<u8>() {
+			Ok(rating) if rating <= MAX_RATING => {
+				self.properties.remove(key);
+				self.rating = Some(rating);
+				Some(Ok(rating))
+			},
+			_ => Some(Err(format!("'{0}' isn't a valid rating (expected 0-{1}) - '{2}'", value, MAX_RATING, self.filename)))
+		}
+	}
 	
-	/* Generate & populate track's details, given the element describing a track */
+	/* Move the track to a location that may have a different filename (so the info from it needs working out again) */
+	fn relocate_renamed(&mut self, path: TrackPath, filename: String)
+	{
+		self.filename = filename;
+		self.info = OnceCell::new();
+		self.relocate(path);
+	}
+	
+	/* Generate & populate track's details, given the element describing a track
+	 * NOTE: Relative locations are left as-is here (see XspfPlaylist::resolve_relative_locations())
+	 */
 	pub fn from_xml_elem(e_track: &Element) -> Result<Track, &'static str>
 	{
-		let e_location = e_track.children().find(|&& ref x| x.name() == "location");
-		let e_duration = e_track.children().find(|&& ref x| x.name() == "duration");
+		let ns = e_track.ns();
+		let e_location = e_track.children().find(|&& ref x| is_xspf_elem(x, "location", &ns));
+		let e_duration = e_track.children().find(|&& ref x| is_xspf_elem(x, "duration", &ns));
+		let e_detail = |name: &str| e_track.children().find(|&& ref x| is_xspf_elem(x, name, &ns)).and_then(non_empty_text);
+		let e_extension = e_track.children().find(|&& ref x| is_xspf_elem(x, "extension", &ns) && x.attr("application") == Some(EXTENSION_APPLICATION));
+		let e_foreign_extensions = e_track.children().filter(|&& ref x| is_xspf_elem(x, "extension", &ns) && x.attr("application") != Some(EXTENSION_APPLICATION));
 		
-		if e_location.is_some() {
-			let track = Track::from_uri(e_location.unwrap().text().as_ref());
-			match track {
+		if let Some(e_location) = e_location {
+			let location = e_location.text().trim().to_string();
+			match TrackPath::from_location(&location).and_then(Track::from_track_path) {
 				Ok(mut t) => {
 					/* Try to add duration to the track */
-					if e_duration.is_some() {
-						let duration_str = e_duration.unwrap().text();
+					if let Some(e_duration) = e_duration {
+						let duration_str = e_duration.text();
 						if let Ok(duration) = duration_str.parse::<i64>() {
 							t.duration = Some(TrackDuration(duration));
+							t.duration_source = DurationSource::Playlist;
+						}
+					}
+					
+					/* Keep the title, etc. (if it has them) */
+					t.title = e_detail("title");
+					t.creator = e_detail("creator");
+					t.album = e_detail("album");
+					t.annotation = e_detail("annotation");
+					
+					/* Details only we know about */
+					if let Some(e_extension) = e_extension {
+						t.source_playlist = e_extension.children()
+						                               .find(|&& ref x| x.name() == "source_playlist")
+						                               .and_then(non_empty_text);
+					}
+					
+					/* Details other applications have added (where the first one given wins),
+					 * keeping the blocks themselves too so they don't get lost when the playlist is saved
+					 */
+					for e_foreign in e_foreign_extensions {
+						for e_property in e_foreign.children().filter(|x| x.children().next().is_none()) {
+							if let Some(value) = non_empty_text(e_property) {
+								t.properties.entry(e_property.name().to_string()).or_insert(value);
+							}
+						}
+						if let Some(block) = ExtensionBlock::from_element(e_foreign) {
+							t.extensions.push(block);
 						}
 					}
 					
@@ -114,16 +375,210 @@ impl Track {
 	}
 }
 
+/* Track for the given <track> element, or why it had to be skipped
+ * - index: Position of the element among all the playlist's <track> elements (see Track.original_index)
+ */
+fn track_entry(e_track: &Element, index: usize) -> Result<Track, SkippedTrack>
+{
+	Track::from_xml_elem(e_track).map(|mut track| {
+		track.original_index = index;
+		track
+	}).map_err(|reason| {
+		let ns = e_track.ns();
+		let location = e_track.children().find(|&& ref x| is_xspf_elem(x, "location", &ns))
+		                                 .map(|x| x.text().trim().to_string());
+		SkippedTrack { location: location, reason: reason.to_string() }
+	})
+}
+
 /* ------------------------------------------- */
 
+/* Whether the element is the given XSPF element - i.e. has that (local) name, whatever prefix it was written with,
+ * and is in the same namespace as the playlist's own elements ("ns", as found on its parent)
+ * NOTE: This keeps other vocabularies' elements (e.g. <dc:title>) from being mistaken for ours
+ */
+fn is_xspf_elem(elem: &Element, name: &str, ns: &Option<String>) -> bool
+{
+	elem.name() == name && elem.ns() == *ns
+}
+
+/* Text of the element (trimmed), if there's any */
+fn non_empty_text(elem: &Element) -> Option<String>
+{
+	let text = elem.text().trim().to_string();
+	if text.is_empty() { None } else { Some(text) }
+}
+
+/* Application URI for the <extension> element holding details that XSPF has no place for (e.g. Track.source_playlist) */
+pub const EXTENSION_APPLICATION: &'static str = "https://github.com/Aligorith/xspf_parser_jsl-rs";
+
 /* Container for everything about the playlist */
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 pub struct XspfPlaylist {
 	pub tracks : Vec<Track>,
-	pub title : Option<String>
+	pub title : Option<String>,
+	
+	/* Other metadata about the playlist itself (as given in the playlist) */
+	pub creator : Option<String>,
+	pub annotation : Option<String>,
+	pub date : Option<String>,
+	
+	/* Other applications' <extension> blocks on the playlist itself (e.g. VLC's track ordering), as they were given */
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub extensions : Vec<ExtensionBlock>,
+	
+	/* Problems noticed while parsing the playlist */
+	#[serde(skip)]
+	pub diagnostics : Diagnostics,
+	
+	/* Entries in the playlist that couldn't be used as tracks (e.g. "http://" streams)
+	 * NOTE: These aren't included in len(), or any of the totals
+	 */
+	#[serde(skip)]
+	pub skipped : Vec<SkippedTrack>
+}
+
+/* An entry in the playlist that got left out, as it doesn't refer to a local file */
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct SkippedTrack {
+	/* Location as given in the playlist (if there was one) */
+	pub location : Option<String>,
+	/* Why it was skipped */
+	pub reason : String,
+}
+
+/* JSON Schema for XspfPlaylist, as written by "verify-order --fix" and in bundles (see json_schema)
+ * NOTE: The "json" mode writes a JsonPlaylistExport instead (see json_export)
+ */
+pub fn playlist_schema() -> Value
+{
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "raw-playlist",
+		"type": "object",
+		"properties": {
+			"tracks": { "type": "array", "items": track_schema() },
+			"title": { "type": ["string", "null"] },
+			"creator": { "type": ["string", "null"] },
+			"annotation": { "type": ["string", "null"] },
+			"date": { "type": ["string", "null"] },
+			"extensions": xspf_extension::extensions_schema()
+		},
+		"required": ["tracks", "title", "creator", "annotation", "date"],
+		"additionalProperties": false
+	})
 }
 
+/* JSON Schema for Track (i.e. TrackRepr) */
+fn track_schema() -> Value
+{
+	json!({
+		"type": "object",
+		"properties": {
+			"path": { "type": "string" },
+			"filename": { "type": "string" },
+			"date": { "type": "string" },
+			"duration": { "description": "Duration in ms", "type": ["integer", "null"] },
+			"duration_source": { "enum": ["playlist", "probed", "unknown"] },
+			"title": { "type": ["string", "null"] },
+			"creator": { "description": "Only present if given in the playlist", "type": "string" },
+			"album": { "description": "Only present if given in the playlist", "type": "string" },
+			"annotation": { "description": "Only present if given in the playlist", "type": "string" },
+			"source_playlist": { "description": "Only present for merged tracks", "type": "string" },
+			"original_index": {
+				"description": "Position of the track's entry in the playlist it was read from (0-based, counting skipped entries)",
+				"type": "integer"
+			},
+			"rating": { "description": "Stars (0-5) - Only present for rated tracks", "enum": [0, 1, 2, 3, 4, 5] },
+			"properties": {
+				"description": "Values from other applications' extensions (only present if there are any)",
+				"type": "object",
+				"additionalProperties": { "type": "string" }
+			},
+			"extensions": xspf_extension::extensions_schema(),
+			"info": track_name_info::info_schema()
+		},
+		"required": ["path", "filename", "date", "duration", "duration_source", "title", "original_index", "info"],
+		"additionalProperties": false
+	})
+}
+
+/* Namespace of XSPF documents */
+pub const XSPF_NAMESPACE: &'static str = "http://xspf.org/ns/0/";
+
+/* Diagnostic category for playlists in some namespace other than XSPF's (which still get read, as if they were in it) */
+pub const NAMESPACE_CATEGORY: &'static str = "namespace";
+
+/* Diagnostic category for playlists with no <trackList> at all (as opposed to an empty one) */
+pub const NO_TRACKLIST_CATEGORY: &'static str = "no-tracklist";
+
+/* Note anything about the playlist as a whole suggesting it isn't what it seems
+ * - namespace: Namespace of the <playlist> element (if it's in one)
+ * - tracklists: Number of <trackList> elements found in it
+ */
+fn check_playlist_structure(diagnostics: &mut Diagnostics, namespace: Option<&str>, tracklists: usize)
+{
+	match namespace {
+		Some(ns) if !ns.is_empty() && ns != XSPF_NAMESPACE => {
+			diagnostics.add(Severity::Warning, NAMESPACE_CATEGORY, None,
+				format!("playlist is in the namespace '{0}' instead of '{1}' (read as XSPF anyway)", ns, XSPF_NAMESPACE));
+		},
+		_ => {}
+	}
+	if tracklists == 0 {
+		diagnostics.add(Severity::Error, NO_TRACKLIST_CATEGORY, None,
+			"playlist has no <trackList> (so there's nothing in it to read)".to_string());
+	}
+}
+
+/* Diagnostic category for tracks with paths that couldn't be represented as UTF-8 */
+pub const NON_UTF8_PATH_CATEGORY: &'static str = "non-utf8-path";
+
+/* Diagnostic category for ratings that couldn't be understood (i.e. not a whole number of stars from 0 to 5) */
+pub const RATING_CATEGORY: &'static str = "rating";
+
+/* Extension property ratings are taken from by default */
+pub const DEFAULT_RATING_KEY: &'static str = "rating";
+
+/* Highest rating a track can have */
+pub const MAX_RATING: u8 = 5;
+
+/* Rating shown as stars, e.g. "★★★☆☆" for 3 */
+pub fn rating_stars(rating: u8) -> String
+{
+	let filled = rating.min(MAX_RATING) as usize;
+	format!("{0}{1}", "★".repeat(filled), "☆".repeat(MAX_RATING as usize - filled))
+}
+
+/* Diagnostic category for playlists mixing different forms of track locations (e.g. URIs and relative paths) */
+pub const MIXED_LOCATIONS_CATEGORY: &'static str = "mixed-locations";
+
+/* Warning for playlists mixing different forms of track locations (given the form of each track's location), if this one does */
+fn mixed_locations_message(forms: &[Option<LocationForm>], base_dir: &Path) -> Option<String>
+{
+	let counts: Vec<(LocationForm, usize)> = [LocationForm::AbsoluteUri, LocationForm::AbsolutePath, LocationForm::RelativePath].iter()
+		.map(|&form| (form, forms.iter().filter(|&&f| f == Some(form)).count()))
+		.filter(|&(_, count)| count > 0)
+		.collect();
+	
+	if counts.len() > 1 {
+		let mix: Vec<String> = counts.iter().map(|&(form, count)| format!("{0} {1}", count, form.description())).collect();
+		Some(format!("playlist mixes different forms of track locations ({0}) - relative paths were resolved against {1:?}",
+		             mix.join(", "), base_dir))
+	}
+	else {
+		None
+	}
+}
+
+/* Diagnostic category for track locations that had been escaped twice (see XspfPlaylist::repair_double_encoded_locations()) */
+pub const DOUBLE_ENCODED_CATEGORY: &'static str = "double-encoded-location";
+
 /* Helper for XspfPlaylist.total_duration() */
 #[derive(Debug)]
 pub struct XspfDurationTallyResult {
@@ -131,26 +586,80 @@ pub struct XspfDurationTallyResult {
 	pub uncounted : usize              /* Number of tracks that couldn't be counted (i.e. missing durations) */
 }
 
+/* Helper for XspfPlaylist.duration_stats() - Total duration of one type of track */
+#[derive(Debug)]
+pub struct TypeDurationTally {
+	pub track_type : TrackType,
+	pub duration : TrackDuration,      /* Total duration of the tracks of this type that have durations */
+	pub counted : usize,               /* Number of tracks of this type that have durations */
+	pub uncounted : usize              /* Number of tracks of this type that don't */
+}
+
+/* Helper for XspfPlaylist.duration_stats() - Spread of track durations
+ * NOTE: Tracks without durations only show up in the "uncounted" counts
+ */
+#[derive(Debug)]
+pub struct DurationStats {
+	pub total : XspfDurationTallyResult,
+	pub counted : usize,               /* Number of tracks that have durations */
+	
+	/* Indices of the shortest/longest tracks (the first one, if several are the same length) - if any have durations */
+	pub shortest : Option<usize>,
+	pub longest : Option<usize>,
+	
+	/* Totals for each type of track in the playlist (in the order the types are defined) */
+	pub per_type : Vec<TypeDurationTally>,
+}
+
+impl DurationStats {
+	/* Average duration of the tracks that have durations */
+	pub fn average(&self) -> Option<TrackDuration>
+	{
+		match self.counted {
+			0 => None,
+			n => Some(TrackDuration(self.total.duration.0 / n as i64))
+		}
+	}
+}
+
 /* API for XspfPlaylist */
 impl XspfPlaylist {
 	/* Generate & populate playlist, given the root element of the */
 	pub fn from_xml_tree(root: Element, filename: &str) -> XspfPlaylist
 	{
 		let mut tracklist : Vec<Track> = Vec::new();
+		let mut skipped : Vec<SkippedTrack> = Vec::new();
 		let mut title = None;
+		let mut creator = None;
+		let mut annotation = None;
+		let mut date = None;
+		let mut extensions = Vec::new();
+		let mut tracklists = 0;
+		let mut entries = 0;
 		
-		/* Go over DOM, pulling out what we need */
-		for e_section in root.children() {
-			match e_section.name().as_ref() {
+		/* Go over DOM, pulling out what we need (skipping anything from other vocabularies) */
+		let ns = root.ns();
+		for e_section in root.children().filter(|&& ref x| x.ns() == ns) {
+			match e_section.name() {
 				"title" => {
 					let title_text = format!("{0} - {1}", e_section.text(), filename);
 					title = Some(title_text.to_string());
 				},
 				
+				"creator"    => { creator = non_empty_text(e_section); },
+				"annotation" => { annotation = non_empty_text(e_section); },
+				"date"       => { date = non_empty_text(e_section); },
+				
 				"trackList" => {
-					for e_track in e_section.children() {
-						if let Ok(track) = Track::from_xml_elem(e_track) {
-							tracklist.push(track);
+					tracklists += 1;
+					
+					/* NOTE: Entries are counted across all the <trackList> sections (as when streaming - see StreamState.entries) */
+					for e_track in e_section.children().filter(|&& ref x| x.name() == "track") {
+						let index = entries;
+						entries += 1;
+						match track_entry(e_track, index) {
+							Ok(track) => tracklist.push(track),
+							Err(entry) => skipped.push(entry)
 						}
 					}
 					
@@ -159,15 +668,111 @@ impl XspfPlaylist {
 					//          .collect();
 				},
 				
+				"extension" if e_section.attr("application") != Some(EXTENSION_APPLICATION) => {
+					extensions.extend(ExtensionBlock::from_element(e_section));
+				},
+				
 				_ => { /* Unhandled */ }
 			}
 		}
 		
+		let mut diagnostics = Diagnostics::new();
+		check_playlist_structure(&mut diagnostics, ns.as_deref(), tracklists);
+		
+		/* Note any tracks whose paths got mangled */
+		for (i, track) in tracklist.iter().enumerate() {
+			if track.has_lossy_path() {
+				diagnostics.warn_track(NON_UTF8_PATH_CATEGORY, i,
+					format!("path contains non-UTF-8 bytes (lossy converted) - '{}'", track.path));
+			}
+		}
+		
 		/* Return playlist instance populated with this info */
 		XspfPlaylist {
 			tracks: tracklist,
-			title: title
+			title: title,
+			creator: creator,
+			annotation: annotation,
+			date: date,
+			extensions: extensions,
+			diagnostics: diagnostics,
+			skipped: skipped
+		}
+	}
+	
+	/* Resolve the tracks with relative locations against the given directory
+	 * (i.e. the playlist's directory, or where it's been moved from), noting if the
+	 * playlist mixes different forms of locations.
+	 *
+	 * NOTE: Tracks with absolute locations (URIs or paths) are never changed
+	 * > returns the number of tracks resolved
+	 */
+	pub fn resolve_relative_locations(&mut self, base_dir: &Path) -> usize
+	{
+		let forms: Vec<Option<LocationForm>> = self.tracks.iter().map(|t| t.location_form()).collect();
+		if let Some(message) = mixed_locations_message(&forms, base_dir) {
+			self.diagnostics.add(Severity::Warning, MIXED_LOCATIONS_CATEGORY, None, message);
+		}
+		
+		let base = TrackPath::from_native(base_dir);
+		let mut resolved = 0;
+		for track in self.tracks.iter_mut() {
+			if track.resolve_relative_location(&base) {
+				resolved += 1;
+			}
+		}
+		resolved
+	}
+	
+	/* Repair the locations of tracks that were escaped twice (e.g. "%2520" for a space) - i.e. tracks whose files
+	 * don't exist at the decoded location, but do once it's been decoded again. Each repair gets noted.
+	 *
+	 * NOTE: This checks which files exist, so it's only done by modes that access the files (never when just parsing).
+	 *       Files whose names really do contain escapes (e.g. "100%20.mp3") are left alone, as they exist as they are.
+	 * > returns the number of tracks repaired
+	 */
+	pub fn repair_double_encoded_locations(&mut self) -> usize
+	{
+		let mut repaired = 0;
+		for (i, track) in self.tracks.iter_mut().enumerate() {
+			if track.file_path().exists() {
+				continue;
+			}
+			let path = match track.path.decode_again() {
+				Some(path) => path,
+				None       => continue
+			};
+			let filename = match path.file_name() {
+				Some(filename) => filename,
+				None           => continue
+			};
+			if !path.native().exists() {
+				continue;
+			}
+			
+			self.diagnostics.warn_track(DOUBLE_ENCODED_CATEGORY, i,
+				format!("double-encoded location repaired - '{0}' is '{1}'", track.path, path));
+			track.relocate_renamed(path, filename);
+			repaired += 1;
 		}
+		repaired
+	}
+	
+	/* Take each track's rating from the given extension property, noting any that aren't valid ratings
+	 * NOTE: Invalid values are left in the track's properties (so they're still shown as given)
+	 * > returns the number of tracks that have a rating
+	 */
+	pub fn apply_ratings(&mut self, key: &str) -> usize
+	{
+		let mut rated = 0;
+		for (i, track) in self.tracks.iter_mut().enumerate() {
+			match track.take_rating(key) {
+				Some(Ok(_))       => rated += 1,
+				Some(Err(reason)) => self.diagnostics.warn_track(RATING_CATEGORY, i, reason),
+				None              => {}
+			}
+		}
+		rated
 	}
 	
 	/* Utility - Number of tracks in playlist */
@@ -176,6 +781,12 @@ impl XspfPlaylist {
 		self.tracks.len()
 	}
 	
+	/* Utility - Does the playlist have any tracks? */
+	pub fn is_empty(&self) -> bool
+	{
+		self.tracks.is_empty()
+	}
+	
 	/* Utility - Number of digits required for padding track numbers
 	 * so all filenames will have the same length for the track-number
 	 * prefix.
@@ -211,46 +822,2453 @@ impl XspfPlaylist {
 		
 		result
 	}
+	
+	/* Utility - Spread of track durations (shortest, longest, average), and totals per type of track */
+	pub fn duration_stats(&self) -> DurationStats
+	{
+		let timed: Vec<(usize, i64)> = self.tracks.iter().enumerate()
+			.filter_map(|(i, track)| track.duration.as_ref().map(|d| (i, d.0)))
+			.collect();
+		
+		/* NOTE: min_by_key()/max_by_key() disagree on which of several equal ones to pick, so the index breaks ties */
+		let shortest = timed.iter().min_by_key(|&&(i, ms)| (ms, i)).map(|&(i, _)| i);
+		let longest  = timed.iter().max_by_key(|&&(i, ms)| (ms, -(i as i64))).map(|&(i, _)| i);
+		
+		let per_type: Vec<TypeDurationTally> = TRACK_TYPES.iter()
+			.map(|&track_type| {
+				let mut tally = TypeDurationTally { track_type: track_type, duration: TrackDuration(0), counted: 0, uncounted: 0 };
+				for track in self.tracks.iter().filter(|t| t.info().track_type == track_type) {
+					match track.duration {
+						Some(ref d) => { tally.duration += d.0; tally.counted += 1; },
+						None        => { tally.uncounted += 1; }
+					}
+				}
+				tally
+			})
+			.filter(|tally| tally.counted + tally.uncounted > 0)
+			.collect();
+		
+		DurationStats {
+			total: self.total_duration(),
+			counted: timed.len(),
+			shortest: shortest,
+			longest: longest,
+			per_type: per_type,
+		}
+	}
+	
+	/* Utility - Number of tracks and total duration, overall and per type, session date, and file extension */
+	pub fn stats(&self) -> StatsSummary
+	{
+		StatsSummary::from_tracks(self.tracks.iter())
+	}
 }
 
-/* ********************************************** */
-/* Parsing API */
+/* ------------------------------------------- */
+/* Track Selection */
 
-/* Read the file into a string, for easier processing
- *
- * FIXME: It's not nice having the entire file loaded in memory like this
- *        especially on large files. That said, most playlists should be small.
+/* Simple criteria for picking out tracks (i.e. the "--type", "--date", and "--name" options)
+ * NOTE: Tracks have to meet all the criteria given. For anything more involved, see filter_expr.
  */
-fn parse_file(filename: &str) -> String
-{
-	let mut f = File::open(filename).expect("ERROR: File not found");
+#[derive(Debug)]
+#[derive(Default)]
+pub struct TrackFilter {
+	/* Type of track (as worked out from its filename) */
+	pub track_type : Option<TrackType>,
+	/* Start of the track's date (e.g. "2017", or "2017-06") - Any dashes are ignored, as track dates don't have them */
+	pub date_prefix : Option<String>,
+	/* Text appearing somewhere in the track's filename (case insensitive) */
+	pub name_contains : Option<String>,
+}
+
+impl TrackFilter {
+	/* Are there no criteria (i.e. every track matches)? */
+	pub fn is_empty(&self) -> bool
+	{
+		self.track_type.is_none() && self.date_prefix.is_none() && self.name_contains.is_none()
+	}
 	
-	let mut contents = String::new();
-	f.read_to_string(&mut contents)
-	 .expect("ERROR: Something went wrong reading the file");
-	 
-	/* Return the string. The program will have "panic()'d if anything went wrong,
-	 * so this function will always just return a string
+	/* Does the given track meet all the criteria? */
+	pub fn matches(&self, track: &Track) -> bool
+	{
+		let type_ok = self.track_type.is_none_or(|t| track.info().track_type == t);
+		let date_ok = self.date_prefix.as_ref().is_none_or(|prefix| track.date.as_str().starts_with(&prefix.replace('-', "")));
+		let name_ok = self.name_contains.as_ref().is_none_or(|text| track.filename.to_lowercase().contains(&text.to_lowercase()));
+		
+		type_ok && date_ok && name_ok
+	}
+}
+
+impl XspfPlaylist {
+	/* Drop all the tracks that don't match the filter
+	 * > returns the number of tracks that were dropped
 	 */
-	contents
+	pub fn filter_tracks(&mut self, filter: &TrackFilter) -> usize
+	{
+		let old_len = self.tracks.len();
+		self.tracks.retain(|track| filter.matches(track));
+		old_len - self.tracks.len()
+	}
 }
 
+/* ------------------------------------------- */
+/* Editing API
+ *
+ * NOTE: Positions here are 1-based (i.e. as shown to users), unlike the 0-based track indices
+ *       used elsewhere, so that errors can refer to them exactly as they were given.
+ */
 
-/* Process the XML Tree */
-pub fn parse_xspf(filename: &str) -> Option<XspfPlaylist>
-{
-	/* 1) Read contents of file to a string */
-	let xml_file = parse_file(filename);
+/* A position that doesn't refer to a valid place in the playlist */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct PositionError {
+	/* The (1-based) position that was given */
+	pub position: usize,
+	/* Number of tracks in the playlist */
+	pub len: usize,
+}
+
+impl fmt::Display for PositionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "Position {0} is out of range (playlist has {1} tracks)", self.position, self.len)
+	}
+}
+
+impl XspfPlaylist {
+	/* Check that the position refers to an existing track (or, if allow_end is set, the slot after the last one) */
+	fn check_position(&self, position: usize, allow_end: bool) -> Result<(), PositionError>
+	{
+		let max = if allow_end { self.len() + 1 } else { self.len() };
+		if position >= 1 && position <= max {
+			Ok(())
+		}
+		else {
+			Err(PositionError { position: position, len: self.len() })
+		}
+	}
+	
+	/* Remove the tracks at the given positions
+	 * All positions are checked before anything gets removed, so nothing changes if any are invalid.
+	 * Duplicate positions are only removed once.
+	 * > returns the removed tracks, in playlist order
+	 */
+	pub fn remove_tracks(&mut self, positions: &[usize]) -> Result<Vec<Track>, PositionError>
+	{
+		for &position in positions.iter() {
+			self.check_position(position, false)?;
+		}
+		
+		let mut indices: Vec<usize> = positions.iter().map(|p| p - 1).collect();
+		indices.sort();
+		indices.dedup();
+		
+		let mut removed: Vec<Track> = indices.iter().rev().map(|&i| self.tracks.remove(i)).collect();
+		removed.reverse();
+		Ok(removed)
+	}
 	
-	/* 2) Parse the file into a DOM tree*/
-	// FIXME: properly handle the parsing failures here
-	let root: Element = xml_file.parse().unwrap();
+	/* Move the track at position "from", so that it ends up at position "to" */
+	pub fn move_track(&mut self, from: usize, to: usize) -> Result<(), PositionError>
+	{
+		self.check_position(from, false)?;
+		self.check_position(to, false)?;
+		
+		let track = self.tracks.remove(from - 1);
+		self.tracks.insert(to - 1, track);
+		Ok(())
+	}
 	
-	/* 3) Create and return new playlist object from the DOM */
-	let playlist = XspfPlaylist::from_xml_tree(root, filename);
-	Some(playlist)
+	/* Insert a track so that it ends up at the given position (len + 1 appends it to the end) */
+	pub fn insert_track(&mut self, position: usize, track: Track) -> Result<(), PositionError>
+	{
+		self.check_position(position, true)?;
+		
+		self.tracks.insert(position - 1, track);
+		Ok(())
+	}
 }
 
-/* ********************************************** */
+/* ------------------------------------------- */
+/* Truncation API */
+
+/* Result of cutting a run of tracks down to a duration limit */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct DurationCut {
+	/* Number of tracks kept */
+	pub kept: usize,
+	/* Total duration of the tracks kept (in ms) */
+	pub total_ms: i64,
+	/* Tracks kept whose durations weren't known, and so got counted as zero (0-based indices, in playlist order) */
+	pub unknown: Vec<usize>,
+}
 
+/* Work out how many tracks (from the start of the given durations) fit within the limit
+ * - include_crossing: Whether the track that takes the total over the limit gets kept too
+ *
+ * NOTE: Unknown durations count as zero (the positions of these are noted). Once the limit has been
+ *       reached exactly, nothing more gets kept (not even tracks of zero/unknown duration).
+ */
+pub fn cut_by_duration(durations: &[Option<i64>], limit_ms: i64, include_crossing: bool) -> DurationCut
+{
+	let mut cut = DurationCut { kept: 0, total_ms: 0, unknown: Vec::new() };
+	
+	for (i, duration) in durations.iter().enumerate() {
+		if cut.total_ms >= limit_ms {
+			break;
+		}
+		
+		let ms = duration.unwrap_or(0).max(0);
+		let crosses = cut.total_ms + ms > limit_ms;
+		if crosses && !include_crossing {
+			break;
+		}
+		
+		if duration.is_none() {
+			cut.unknown.push(i);
+		}
+		cut.kept += 1;
+		cut.total_ms += ms;
+		
+		if crosses {
+			break;
+		}
+	}
+	cut
+}
 
+impl XspfPlaylist {
+	/* Durations of all the tracks (in ms) */
+	fn durations_ms(&self) -> Vec<Option<i64>>
+	{
+		self.tracks.iter().map(|t| t.duration.as_ref().map(|d| d.0)).collect()
+	}
+	
+	/* Keep only the first "count" tracks
+	 * > returns the number of tracks removed
+	 */
+	pub fn head_by_count(&mut self, count: usize) -> usize
+	{
+		let removed = self.len().saturating_sub(count);
+		self.tracks.truncate(count);
+		removed
+	}
+	
+	/* Keep only the last "count" tracks
+	 * > returns the number of tracks removed
+	 */
+	pub fn tail_by_count(&mut self, count: usize) -> usize
+	{
+		let removed = self.len().saturating_sub(count);
+		self.tracks.drain(.. removed);
+		removed
+	}
+	
+	/* Keep tracks from the start, until their total duration reaches the limit (see cut_by_duration()) */
+	pub fn head_by_duration(&mut self, limit_ms: i64, include_crossing: bool) -> DurationCut
+	{
+		let cut = cut_by_duration(&self.durations_ms(), limit_ms, include_crossing);
+		self.tracks.truncate(cut.kept);
+		cut
+	}
+	
+	/* Keep tracks from the end, until their total duration reaches the limit (see cut_by_duration())
+	 * The durations get added up backwards from the last track, so the tracks kept are the last ones
+	 */
+	pub fn tail_by_duration(&mut self, limit_ms: i64, include_crossing: bool) -> DurationCut
+	{
+		let mut durations = self.durations_ms();
+		durations.reverse();
+		
+		let mut cut = cut_by_duration(&durations, limit_ms, include_crossing);
+		let len = self.len();
+		cut.unknown = cut.unknown.iter().rev().map(|&i| len - 1 - i).collect();
+		
+		self.tracks.drain(.. len - cut.kept);
+		cut
+	}
+}
+
+/* ------------------------------------------- */
+/* Splitting API */
+
+/* Work out how to split a run of tracks into parts, each with a total duration within the limit
+ * (filling each part in turn, in playlist order)
+ * - assumed_ms: Duration to count for tracks whose durations aren't known
+ *
+ * > returns the number of tracks in each part
+ *
+ * NOTE: Tracks never get split, so a track that's longer than the limit by itself gets a part of its own.
+ */
+pub fn split_sizes_by_duration(durations: &[Option<i64>], max_ms: i64, assumed_ms: i64) -> Vec<usize>
+{
+	let mut sizes: Vec<usize> = Vec::new();
+	let mut total_ms = 0;
+	
+	for duration in durations.iter() {
+		let ms = duration.unwrap_or(assumed_ms).max(0);
+		let parts = sizes.len();
+		if parts > 0 && total_ms + ms <= max_ms {
+			sizes[parts - 1] += 1;
+			total_ms += ms;
+		}
+		else {
+			sizes.push(1);
+			total_ms = ms;
+		}
+	}
+	sizes
+}
+
+impl XspfPlaylist {
+	/* Split the playlist into parts whose tracks add up to no more than "max" each (see split_sizes_by_duration()),
+	 * counting tracks whose durations aren't known as zero
+	 */
+	pub fn split_by_duration(&self, max: TrackDuration) -> Vec<XspfPlaylist>
+	{
+		self.split_by_duration_assuming(max, TrackDuration(0))
+	}
+	
+	/* As for split_by_duration(), counting tracks whose durations aren't known as "assumed" instead */
+	pub fn split_by_duration_assuming(&self, max: TrackDuration, assumed: TrackDuration) -> Vec<XspfPlaylist>
+	{
+		let sizes = split_sizes_by_duration(&self.durations_ms(), max.0, assumed.0);
+		self.split_into_parts(&sizes)
+	}
+	
+	/* Split the playlist into parts of (at most) "max_tracks" tracks each */
+	pub fn split_by_count(&self, max_tracks: usize) -> Vec<XspfPlaylist>
+	{
+		let max_tracks = max_tracks.max(1);
+		let sizes: Vec<usize> = (0 .. self.len()).step_by(max_tracks)
+		                                         .map(|start| max_tracks.min(self.len() - start))
+		                                         .collect();
+		self.split_into_parts(&sizes)
+	}
+	
+	/* Copies of the playlist, each with the next "size" tracks in turn
+	 * Each part's title notes which part it is (e.g. "Sessions (Part 2 of 3)").
+	 *
+	 * NOTE: The playlist's own extension blocks get left out, as they refer to the tracks of the whole
+	 *       playlist (e.g. VLC's track ordering). The tracks keep theirs.
+	 */
+	fn split_into_parts(&self, sizes: &[usize]) -> Vec<XspfPlaylist>
+	{
+		let count = sizes.len();
+		let mut start = 0;
+		
+		sizes.iter().enumerate().map(|(i, &size)| {
+			let tracks = self.tracks[start .. start + size].to_vec();
+			start += size;
+			
+			let part = format!("Part {0} of {1}", i + 1, count);
+			XspfPlaylist {
+				tracks: tracks,
+				title: Some(match self.title {
+					Some(ref title) => format!("{0} ({1})", title, part),
+					None            => part
+				}),
+				creator: self.creator.clone(),
+				annotation: self.annotation.clone(),
+				date: self.date.clone(),
+				extensions: Vec::new(),
+				diagnostics: Diagnostics::new(),
+				skipped: Vec::new(),
+			}
+		}).collect()
+	}
+}
+
+/* ********************************************** */
+/* Parsing API */
+
+/* Problems that can prevent a playlist from being read */
+#[derive(Debug)]
+pub enum XspfError {
+	/* File couldn't be opened/read */
+	IoError(io::Error),
+	/* File isn't valid XML */
+	XmlParseError(String),
+	/* File is XML, but the root element isn't <playlist> */
+	NotAPlaylist,
+	/* Reading the file took too long (e.g. a hung network share) */
+	Timeout(Duration),
+	/* File contains markup that isn't allowed (e.g. <!DOCTYPE), and was refused without parsing it */
+	SecurityError(String),
+}
+
+impl fmt::Display for XspfError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match *self {
+			XspfError::IoError(ref e)       => write!(f, "Couldn't read file - {}", e),
+			XspfError::XmlParseError(ref e) => write!(f, "Invalid XML - {}", e),
+			XspfError::NotAPlaylist         => write!(f, "Not an XSPF playlist (root element should be <playlist>)"),
+			XspfError::Timeout(ref t)       => write!(f, "Timed out after {:.1} seconds", t.as_secs() as f64 + t.subsec_nanos() as f64 * 1e-9),
+			XspfError::SecurityError(ref e) => write!(f, "Refused to parse file containing {} (use --allow-doctype to parse it anyway)", e),
+		}
+	}
+}
+
+impl From<io::Error> for XspfError {
+	fn from(e: io::Error) -> XspfError
+	{
+		XspfError::IoError(e)
+	}
+}
+
+/* Read the playlist into a string, for easier processing
+ *
+ * FIXME: It's not nice having the entire file loaded in memory like this
+ *        especially on large files. That said, most playlists should be small.
+ */
+fn parse_file<R: Read>(mut source: R) -> Result<String, XspfError>
+{
+	let mut contents = String::new();
+	source.read_to_string(&mut contents)?;
+	
+	Ok(contents)
+}
+
+
+/* Size of the read buffer used when parsing straight from the file */
+const BUFFERED_READ_CAPACITY: usize = 64 * 1024;
+
+/* Read the playlist through a buffer, straight into the XML parser (without loading it all into a string first)
+ * The document gets scanned for suspicious markup on the way in (unless "allow_doctype" is set).
+ */
+fn parse_buffered<R: Read>(source: R, allow_doctype: bool) -> Result<Element, XspfError>
+{
+	let f = BufReader::with_capacity(BUFFERED_READ_CAPACITY, source);
+	
+	if allow_doctype {
+		read_element(f)
+	}
+	else {
+		let mut scanner = xml_security::ScanningReader::new(f);
+		let result = read_element(&mut scanner);
+		
+		/* The scanner stops the reading as soon as anything turns up, which the parser just sees as a read error */
+		if let Some(found) = scanner.finding() {
+			return Err(XspfError::SecurityError(found.to_string()));
+		}
+		result
+	}
+}
+
+/* Build the DOM tree from the document, one event at a time
+ * > returns an error if the document ends before its root element does
+ *
+ * NOTE: minidom's own reader takes running out of document as the end of it (handing back whichever
+ *       element was still open), so truncated files would get read as if there was nothing more to them.
+ *       Instead, the elements still open are kept track of here, and appended to their parents as they end.
+ */
+fn read_element<R: BufRead>(source: R) -> Result<Element, XspfError>
+{
+	let mut reader = XmlReader::from_reader(source);
+	
+	/* Elements that have been started but not ended yet (root first),
+	 * along with the namespaces (by prefix) in scope for each of them
+	 */
+	let mut open_elems: Vec<Element> = Vec::new();
+	let mut open_namespaces: Vec<BTreeMap<Option<String>, String>> = Vec::new();
+	
+	let mut buf: Vec<u8> = Vec::new();
+	loop {
+		let ended = match reader.read_event(&mut buf).map_err(xml_error)? {
+			Event::Start(ref e) => {
+				let (elem, namespaces) = start_element(&reader, e, open_namespaces.last())?;
+				open_elems.push(elem);
+				open_namespaces.push(namespaces);
+				None
+			},
+			Event::Empty(ref e) => {
+				let (elem, _) = start_element(&reader, e, open_namespaces.last())?;
+				Some(elem)
+			},
+			Event::End(_) => {
+				open_namespaces.pop();
+				open_elems.pop()
+			},
+			Event::Text(ref e) => {
+				let text = e.unescape_and_decode(&reader).map_err(xml_error)?;
+				if let (Some(elem), false) = (open_elems.last_mut(), text.is_empty()) {
+					elem.append_text_node(text);
+				}
+				None
+			},
+			Event::CData(ref e) => {
+				if let Some(elem) = open_elems.last_mut() {
+					elem.append_text_node(String::from_utf8_lossy(e).into_owned());
+				}
+				None
+			},
+			Event::Comment(ref e) => {
+				if let Some(elem) = open_elems.last_mut() {
+					elem.append_comment_node(String::from_utf8_lossy(e).into_owned());
+				}
+				None
+			},
+			Event::Eof => {
+				let message = match open_elems.last() {
+					Some(elem) => format!("document ended before </{}>", elem.name()),
+					None       => "document has no root element".to_string()
+				};
+				return Err(XspfError::XmlParseError(message));
+			},
+			_ => { /* Declarations, processing instructions, etc. */ None }
+		};
+		
+		/* Ended elements belong to the one enclosing them, until the root element ends (which is the end of the document) */
+		if let Some(elem) = ended {
+			match open_elems.last_mut() {
+				Some(parent) => { parent.append_child(elem); },
+				None         => return Ok(elem)
+			}
+		}
+		buf.clear();
+	}
+}
+
+/* Element for the given start tag (with its attributes, but no children yet)
+ * > returns the element, and the namespaces in scope for it (i.e. those from its parent, plus any it declares)
+ *
+ * NOTE: Namespace declarations aren't kept as attributes. Instead, the element gets the namespace its prefix
+ *       refers to (or the default one, if it doesn't have a prefix).
+ */
+fn start_element<B: BufRead>(reader: &XmlReader<B>, e: &BytesStart, parent_namespaces: Option<&BTreeMap<Option<String>, String>>)
+	-> Result<(Element, BTreeMap<Option<String>, String>), XspfError>
+{
+	let name = String::from_utf8_lossy(e.name()).into_owned();
+	if name.matches(':').count() > 1 {
+		return Err(XspfError::XmlParseError(format!("malformed element name '{}'", name)));
+	}
+	let prefix = name.find(':').map(|i| name[.. i].to_string());
+	
+	let mut namespaces = parent_namespaces.cloned().unwrap_or_default();
+	let mut attributes = Vec::new();
+	for attr in e.attributes() {
+		let attr = attr.map_err(xml_error)?;
+		let key = String::from_utf8_lossy(attr.key).into_owned();
+		let value = attr.unescape_and_decode_value(reader).map_err(xml_error)?;
+		
+		if key == "xmlns" {
+			namespaces.insert(None, value);
+		}
+		else if let Some(ns_prefix) = key.strip_prefix("xmlns:") {
+			namespaces.insert(Some(ns_prefix.to_string()), value);
+		}
+		else {
+			attributes.push((key, value));
+		}
+	}
+	
+	let mut builder = Element::builder(name);
+	if let Some(ns) = namespaces.get(&prefix) {
+		builder = builder.ns(ns.clone());
+	}
+	for (key, value) in attributes {
+		builder = builder.attr(key, value);
+	}
+	Ok((builder.build(), namespaces))
+}
+
+/* Problem reported by the XML reader */
+fn xml_error<E: fmt::Display>(e: E) -> XspfError
+{
+	XspfError::XmlParseError(format!("{}", e))
+}
+
+/* Read the file by memory-mapping it
+ *
+ * The mapped bytes get copied out straight away, and the file's size is checked again afterwards,
+ * so that files being truncated/extended by something else while being read gives an error
+ * (instead of reading past the end of the mapping, or a half-written document).
+ *
+ * NOTE: There's still a window where a file shrinking and growing back again between the checks
+ *       won't be noticed. Reading files that are actively being written to isn't supported anyway.
+ */
+#[cfg(feature = "mmap")]
+fn read_mapped(filename: &str) -> Result<Vec<u8>, XspfError>
+{
+	let f = File::open(filename)?;
+	let len = f.metadata()?.len();
+	if len == 0 {
+		/* Empty files can't be mapped */
+		return Ok(Vec::new());
+	}
+	
+	let bytes = {
+		let map = unsafe { Mmap::map(&f)? };
+		if map.len() as u64 != len {
+			return Err(XspfError::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, "file changed size while being read")));
+		}
+		map.to_vec()
+	};
+	if f.metadata()?.len() != len {
+		return Err(XspfError::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, "file changed size while being read")));
+	}
+	
+	Ok(bytes)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_mapped(_filename: &str) -> Result<Vec<u8>, XspfError>
+{
+	Err(XspfError::IoError(io::Error::other("memory-mapped reading isn't available (built without the 'mmap' feature)")))
+}
+
+
+/* How playlist files get read in for parsing
+ * NOTE: This is mainly for comparing them (see "--io-strategy"/"--profile"). They all give the same result.
+ */
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub enum IoStrategy {
+	/* Read the whole file into a string, then parse that */
+	#[default]
+	String,
+	/* Parse straight from the file, through a read buffer */
+	Buffered,
+	/* Memory-map the file, then parse that (needs the "mmap" feature)
+	 * NOTE: Playlists read from stdin can't be mapped, so get read into a string instead
+	 */
+	Mmap,
+}
+
+impl IoStrategy {
+	pub fn name(&self) -> &'static str
+	{
+		match *self {
+			IoStrategy::String   => "string",
+			IoStrategy::Buffered => "buffered",
+			IoStrategy::Mmap     => "mmap",
+		}
+	}
+}
+
+impl FromStr for IoStrategy {
+	type Err = String;
+	
+	fn from_str(s: &str) -> Result<IoStrategy, String>
+	{
+		match s {
+			"string"   => Ok(IoStrategy::String),
+			"buffered" => Ok(IoStrategy::Buffered),
+			"mmap"     => Ok(IoStrategy::Mmap),
+			_          => Err(format!("Unknown I/O strategy '{}' (expected string, buffered, or mmap)", s))
+		}
+	}
+}
+
+
+/* Settings for how strictly playlists get checked when parsing */
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct ParseOptions {
+	/* Parse documents containing DTD declarations/processing instructions, instead of refusing them */
+	pub allow_doctype: bool,
+	
+	/* Directory to resolve relative track locations against, instead of the playlist's directory
+	 * (e.g. for playlists that have been moved away from their tracks)
+	 */
+	pub rebase: Option<PathBuf>,
+	
+	/* How the file gets read in */
+	pub io_strategy: IoStrategy,
+	
+	/* Extension property to take track ratings from (instead of DEFAULT_RATING_KEY) */
+	pub rating_key: Option<String>,
+}
+
+/* Make a path absolute (relative to the current directory), without needing it to exist */
+fn absolute_path(path: &Path) -> PathBuf
+{
+	if path.is_absolute() {
+		path.to_path_buf()
+	}
+	else {
+		env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf())
+	}
+}
+
+/* Process the XML Tree, returning an error if the file couldn't be read, or isn't an XSPF playlist
+ * NOTE: "-" reads the playlist from stdin (see playlist_input), with relative track locations resolved against
+ *       the current directory (unless it's been rebased). Gzip-compressed playlists get decompressed as they're read.
+ */
+pub fn parse_xspf(filename: &str, options: &ParseOptions) -> Result<XspfPlaylist, XspfError>
+{
+	/* 1-3) Read the file, refusing anything suspicious before the XML library gets to see it,
+	 *      and parse it into a DOM tree
+	 */
+	let root: Element = match options.io_strategy {
+		IoStrategy::Buffered => parse_buffered(playlist_input::open(filename)?, options.allow_doctype)?,
+		strategy => {
+			let xml_file = match strategy {
+				IoStrategy::Mmap if !playlist_input::is_stdin(filename) => {
+					let bytes = read_mapped(filename)?;
+					parse_file(playlist_input::decompressed(io::Cursor::new(bytes), playlist_input::has_gzip_extension(filename))?)?
+				},
+				_ => parse_file(playlist_input::open(filename)?)?
+			};
+			
+			parse_document(&xml_file, options.allow_doctype)?
+		}
+	};
+	
+	/* 4-6) Create new playlist object from the DOM, resolving relative locations against the playlist's directory */
+	let base_dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+	playlist_from_root(root, playlist_input::display_name(filename), base_dir, options)
+}
+
+/* Process an XSPF document read from the given source (e.g. a pipe), which is decompressed first if needed
+ * "name" takes the place of the filename in the playlist's title. Relative track locations get resolved
+ * against the current directory (unless it's been rebased).
+ */
+pub fn parse_xspf_reader<R: Read + 'static>(source: R, name: &str, options: &ParseOptions) -> Result<XspfPlaylist, XspfError>
+{
+	let xml = parse_file(playlist_input::decompressed(source, false)?)?;
+	parse_xspf_str(&xml, name, options)
+}
+
+/* Process an XSPF document that's already been read in (e.g. from somewhere other than a file)
+ * "name" takes the place of the filename in the playlist's title. Relative track locations get resolved
+ * against the current directory (unless it's been rebased).
+ */
+pub fn parse_xspf_str(xml: &str, name: &str, options: &ParseOptions) -> Result<XspfPlaylist, XspfError>
+{
+	let root = parse_document(xml, options.allow_doctype)?;
+	playlist_from_root(root, name, Path::new(""), options)
+}
+
+/* Parse the XML, refusing anything suspicious before the XML library gets to see it */
+fn parse_document(xml: &str, allow_doctype: bool) -> Result<Element, XspfError>
+{
+	if !allow_doctype {
+		if let Some(found) = xml_security::scan_document(xml) {
+			return Err(XspfError::SecurityError(found.to_string()));
+		}
+	}
+	read_element(xml.as_bytes())
+}
+
+/* Steps shared by all the parsing methods, once the DOM tree has been built */
+fn playlist_from_root(root: Element, filename: &str, base_dir: &Path, options: &ParseOptions) -> Result<XspfPlaylist, XspfError>
+{
+	if root.name() != "playlist" {
+		return Err(XspfError::NotAPlaylist);
+	}
+	
+	/* 4) Create new playlist object from the DOM */
+	let mut xspf = XspfPlaylist::from_xml_tree(root, filename);
+	
+	/* 5) Resolve relative locations against the playlist's directory (unless it's been rebased) */
+	let base_dir = options.rebase.as_ref().map_or(base_dir, |dir| dir.as_path());
+	xspf.resolve_relative_locations(&absolute_path(base_dir));
+	
+	/* 6) Pick out the ratings from the extension properties */
+	xspf.apply_ratings(options.rating_key.as_ref().map_or(DEFAULT_RATING_KEY, |k| k.as_str()));
+	
+	Ok(xspf)
+}
+
+/* ********************************************** */
+/* Streaming API
+ *
+ * For very large playlists, building the whole DOM tree (as parse_xspf() does) before any tracks can be
+ * used is slow and takes a lot of memory. Instead, the document can be read a piece at a time, with each
+ * track handed over as soon as its element has been read (only that element gets built as a tree, so the
+ * same rules apply to it as when parsing the whole document).
+ */
+
+/* Name of an element without any namespace prefix (i.e. as given by Element::name()) */
+fn local_name(name: &[u8]) -> String
+{
+	let name = String::from_utf8_lossy(name);
+	match name.rfind(':') {
+		Some(i) => name[i + 1 ..].to_string(),
+		None    => name.into_owned()
+	}
+}
+
+/* Fill in one of the playlist's own details (e.g. its <title>), from the text of its element */
+fn set_playlist_detail(xspf: &mut XspfPlaylist, name: &str, text: &str, filename: &str)
+{
+	let value = || {
+		let text = text.trim();
+		if text.is_empty() { None } else { Some(text.to_string()) }
+	};
+	match name {
+		"title"      => { xspf.title = Some(format!("{0} - {1}", text, filename)); },
+		"creator"    => { xspf.creator = value(); },
+		"annotation" => { xspf.annotation = value(); },
+		"date"       => { xspf.date = value(); },
+		_            => { /* Unhandled */ }
+	}
+}
+
+/* Where the reading of the document has got to (see stream_elements()) */
+struct StreamState {
+	/* Names of the elements enclosing the current position (starting with the root) */
+	open_names: Vec<String>,
+	/* The <track> element being read in, along with any of its children that haven't ended yet */
+	open_elems: Vec<Element>,
+	/* Namespaces (by prefix) in scope for each of the open elements */
+	open_namespaces: Vec<BTreeMap<Option<String>, String>>,
+	/* Text of the current element, when it's one of the playlist's own details (e.g. <title>) */
+	text: String,
+	/* Number of <track> elements read so far (including any that got skipped) */
+	entries: usize,
+	/* Namespace of the <playlist> element (if it's in one) */
+	namespace: Option<String>,
+	/* Number of <trackList> elements found in the playlist */
+	tracklists: usize,
+}
+
+impl StreamState {
+	/* An element started */
+	fn start<B: BufRead>(&mut self, reader: &XmlReader<B>, e: &BytesStart) -> Result<(), XspfError>
+	{
+		let mut name = local_name(e.name());
+		if self.open_names.is_empty() && name != "playlist" {
+			return Err(XspfError::NotAPlaylist);
+		}
+		
+		let (elem, namespaces) = start_element(reader, e, self.open_namespaces.last())?;
+		let ns = elem.ns();
+		if self.open_names.is_empty() {
+			self.namespace = ns;
+		}
+		else if self.open_names.len() == 1 {
+			/* Sections from other vocabularies (e.g. <dc:title>) get named so they can't be mistaken for ours (as "{namespace}name") */
+			if ns != self.namespace {
+				name = format!("{{{0}}}{1}", ns.unwrap_or_default(), name);
+			}
+			else if name == "trackList" {
+				self.tracklists += 1;
+			}
+		}
+		
+		let in_tracklist = self.open_names.len() == 2 && self.open_names[1] == "trackList";
+		let playlist_extension = self.open_names.len() == 1 && name == "extension";
+		if !self.open_elems.is_empty() || (in_tracklist && name == "track") || playlist_extension {
+			self.open_elems.push(elem);
+		}
+		self.open_namespaces.push(namespaces);
+		if self.open_names.len() == 1 {
+			self.text.clear();
+		}
+		self.open_names.push(name);
+		Ok(())
+	}
+	
+	/* Some text was found in the current element */
+	fn add_text(&mut self, content: String)
+	{
+		if let Some(elem) = self.open_elems.last_mut() {
+			elem.append_text_node(content);
+		}
+		else if self.open_names.len() == 2 {
+			self.text.push_str(&content);
+		}
+	}
+	
+	/* The current element ended - adding it to the <track> being read in (or handing that over if it's the <track> itself),
+	 * or filling in the playlist's details from it (including any of its <extension> blocks)
+	 */
+	fn end(&mut self, xspf: &mut XspfPlaylist, filename: &str, on_track: &mut dyn FnMut(Track))
+	{
+		let name = self.open_names.pop();
+		self.open_namespaces.pop();
+		if let Some(elem) = self.open_elems.pop() {
+			match self.open_elems.last_mut() {
+				Some(parent) => { parent.append_child(elem); },
+				None if elem.name() == "extension" => {
+					if elem.attr("application") != Some(EXTENSION_APPLICATION) {
+						xspf.extensions.extend(ExtensionBlock::from_element(&elem));
+					}
+				},
+				None => {
+					let index = self.entries;
+					self.entries += 1;
+					match track_entry(&elem, index) {
+						Ok(track)  => on_track(track),
+						Err(entry) => xspf.skipped.push(entry)
+					}
+				}
+			}
+		}
+		else if let (1, Some(name)) = (self.open_names.len(), name) {
+			set_playlist_detail(xspf, &name, &self.text, filename);
+		}
+	}
+}
+
+/* Read the document's elements as they come in, handing over each track once its element ends
+ * > returns the rest of the playlist (i.e. with no tracks, but with any entries that were skipped)
+ */
+fn stream_elements<B: BufRead>(reader: &mut XmlReader<B>, filename: &str, on_track: &mut dyn FnMut(Track))
+	-> Result<XspfPlaylist, XspfError>
+{
+	let mut xspf = XspfPlaylist {
+		tracks: Vec::new(),
+		title: None,
+		creator: None,
+		annotation: None,
+		date: None,
+		extensions: Vec::new(),
+		diagnostics: Diagnostics::new(),
+		skipped: Vec::new(),
+	};
+	let mut state = StreamState {
+		open_names: Vec::new(),
+		open_elems: Vec::new(),
+		open_namespaces: Vec::new(),
+		text: String::new(),
+		entries: 0,
+		namespace: None,
+		tracklists: 0,
+	};
+	let mut seen_root = false;
+	
+	let mut buf: Vec<u8> = Vec::new();
+	loop {
+		match reader.read_event(&mut buf).map_err(xml_error)? {
+			Event::Start(ref e) => {
+				state.start(reader, e)?;
+				seen_root = true;
+			},
+			Event::Empty(ref e) => {
+				state.start(reader, e)?;
+				state.end(&mut xspf, filename, on_track);
+				seen_root = true;
+			},
+			Event::End(_) => {
+				state.end(&mut xspf, filename, on_track);
+			},
+			Event::Text(ref e) => {
+				state.add_text(e.unescape_and_decode(reader).map_err(xml_error)?);
+			},
+			Event::CData(ref e) => {
+				state.add_text(String::from_utf8_lossy(e).into_owned());
+			},
+			Event::Eof => break,
+			_ => { /* Comments, etc. */ }
+		}
+		buf.clear();
+	}
+	
+	if !seen_root {
+		return Err(XspfError::XmlParseError("document has no root element".to_string()));
+	}
+	if let Some(name) = state.open_names.last() {
+		return Err(XspfError::XmlParseError(format!("document ended before </{}>", name)));
+	}
+	check_playlist_structure(&mut xspf.diagnostics, state.namespace.as_deref(), state.tracklists);
+	Ok(xspf)
+}
+
+/* Parse the playlist a piece at a time, handing each track to "on_track" as soon as it's been read
+ * (with the same processing as parse_xspf() - e.g. resolving relative locations, and picking out ratings)
+ * > returns everything else about the playlist - i.e. its details, and any entries that were skipped.
+ *   The tracks are NOT kept in it (so it has none), and any diagnostics refer to them by the order they were handed over in.
+ *
+ * NOTE: The file is read through a buffer (as with IoStrategy::Buffered), refusing anything suspicious on the way in
+ *       (unless "allow_doctype" is set). Tracks before whatever turned up will already have been handed over by then.
+ */
+pub fn parse_xspf_streaming<F>(filename: &str, options: &ParseOptions, mut on_track: F) -> Result<XspfPlaylist, XspfError>
+	where F: FnMut(Track)
+{
+	let f = BufReader::with_capacity(BUFFERED_READ_CAPACITY, playlist_input::open(filename)?);
+	let name = playlist_input::display_name(filename);
+	
+	/* Relative locations get resolved against the playlist's directory (unless it's been rebased) */
+	let base_dir = match options.rebase {
+		Some(ref dir) => absolute_path(dir),
+		None          => absolute_path(Path::new(filename).parent().unwrap_or(Path::new("")))
+	};
+	let base = TrackPath::from_native(&base_dir);
+	let rating_key = options.rating_key.as_ref().map_or(DEFAULT_RATING_KEY, |k| k.as_str());
+	
+	/* Everything that gets done to the tracks after building them from the DOM (see playlist_from_root()) */
+	let mut diagnostics = Diagnostics::new();
+	let mut forms: Vec<Option<LocationForm>> = Vec::new();
+	let result = {
+		let mut handle_track = |mut track: Track| {
+			let index = forms.len();
+			if track.has_lossy_path() {
+				diagnostics.warn_track(NON_UTF8_PATH_CATEGORY, index,
+					format!("path contains non-UTF-8 bytes (lossy converted) - '{}'", track.path));
+			}
+			forms.push(track.location_form());
+			track.resolve_relative_location(&base);
+			if let Some(Err(reason)) = track.take_rating(rating_key) {
+				diagnostics.warn_track(RATING_CATEGORY, index, reason);
+			}
+			on_track(track);
+		};
+		
+		if options.allow_doctype {
+			stream_elements(&mut XmlReader::from_reader(f), name, &mut handle_track)
+		}
+		else {
+			let mut scanner = xml_security::ScanningReader::new(f);
+			let result = stream_elements(&mut XmlReader::from_reader(&mut scanner), name, &mut handle_track);
+			
+			/* The scanner stops the reading as soon as anything turns up, which the reader just sees as a read error */
+			if let Some(found) = scanner.finding() {
+				return Err(XspfError::SecurityError(found.to_string()));
+			}
+			result
+		}
+	};
+	
+	let mut xspf = result?;
+	if let Some(message) = mixed_locations_message(&forms, &base_dir) {
+		diagnostics.add(Severity::Warning, MIXED_LOCATIONS_CATEGORY, None, message);
+	}
+	
+	/* Anything about the playlist as a whole comes before what was found for its tracks */
+	xspf.diagnostics.entries.extend(diagnostics.entries);
+	Ok(xspf)
+}
+
+/* ********************************************** */
+/* Writing API */
+
+impl Track {
+	/* URI to use for this track in a playlist
+	 * NOTE: Paths parsed from "file:///" URIs don't keep their leading slash (see TrackPath::from_uri()),
+	 *       so only one gets added back here.
+	 */
+	pub fn to_uri(&self) -> String
+	{
+		self.path.to_uri()
+	}
+}
+
+impl XspfPlaylist {
+	/* Generate an XSPF document for the playlist */
+	pub fn to_xspf_string(&self) -> String
+	{
+		let mut xml = XmlWriter::new();
+		xml.start("playlist", &[("xmlns", XSPF_NAMESPACE), ("version", "1")]);
+		
+		if let Some(ref title) = self.title {
+			xml.text_element("title", title);
+		}
+		if let Some(ref creator) = self.creator {
+			xml.text_element("creator", creator);
+		}
+		if let Some(ref annotation) = self.annotation {
+			xml.text_element("annotation", annotation);
+		}
+		if let Some(ref date) = self.date {
+			xml.text_element("date", date);
+		}
+		for extension in self.extensions.iter() {
+			extension.write(&mut xml);
+		}
+		
+		xml.start("trackList", &[]);
+		for track in self.tracks.iter() {
+			xml.start("track", &[]);
+			xml.text_element("location", &track.to_uri());
+			/* NOTE: These go in the order the XSPF spec lists them in */
+			if let Some(ref title) = track.title {
+				xml.text_element("title", title);
+			}
+			if let Some(ref creator) = track.creator {
+				xml.text_element("creator", creator);
+			}
+			if let Some(ref annotation) = track.annotation {
+				xml.text_element("annotation", annotation);
+			}
+			if let Some(ref album) = track.album {
+				xml.text_element("album", album);
+			}
+			if let Some(TrackDuration(ms)) = track.duration {
+				xml.text_element("duration", &ms.to_string());
+			}
+			if let Some(ref source_playlist) = track.source_playlist {
+				xml.start("extension", &[("application", EXTENSION_APPLICATION)]);
+				xml.text_element("source_playlist", source_playlist);
+				xml.end();
+			}
+			for extension in track.extensions.iter() {
+				extension.write(&mut xml);
+			}
+			xml.end();
+		}
+		xml.end();
+		
+		xml.finish()
+	}
+	
+	/* Write the playlist to an XSPF file
+	 * The document gets written to a temp file alongside the destination first, which then replaces it,
+	 * so the destination is never left half-written (i.e. it's safe to use this to overwrite the input).
+	 */
+	pub fn save_xspf(&self, path: &Path) -> io::Result<()>
+	{
+		let filename = match path.file_name() {
+			Some(f) => f.to_string_lossy().into_owned(),
+			None    => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Output path doesn't include a filename"))
+		};
+		let temp_path = path.with_file_name(format!(".{}.tmp", filename));
+		
+		let result = File::create(&temp_path)
+		                 .and_then(|mut f| f.write_all(self.to_xspf_string().as_bytes()).and_then(|_| f.sync_all()))
+		                 .and_then(|_| fs::rename(&temp_path, path));
+		if result.is_err() {
+			let _ = fs::remove_file(&temp_path);
+		}
+		result
+	}
+}
+
+/* ********************************************** */
+/* Unit Tests */
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[cfg(unix)]
+	#[test]
+	fn test_non_utf8_path()
+	{
+		use std::ffi::OsStr;
+		use std::fs;
+		use std::os::unix::ffi::OsStrExt;
+		use test_utils::TempDir;
+		
+		/* Parsing survives, and gets flagged */
+		let root: Element = r#"<playlist xmlns="http://xspf.org/ns/0/">
+		                         <trackList>
+		                           <track><location>file:///music/20170403/v01-tranquil.mp3</location></track>
+		                           <track><location>file:///music/20170403/v02-caf%E9_au_lait.mp3</location></track>
+		                         </trackList>
+		                       </playlist>"#.parse().unwrap();
+		let xspf = XspfPlaylist::from_xml_tree(root, "test.xspf");
+		
+		assert_eq!(2, xspf.len());
+		assert_eq!("music/20170403/v02-caf\u{FFFD}_au_lait.mp3", xspf.tracks[1].path.as_str());
+		assert_eq!("20170403", xspf.tracks[1].date.as_str());
+		assert_eq!(2, xspf.tracks[1].info().index);
+		
+		assert_eq!(1, xspf.diagnostics.len());
+		assert_eq!(NON_UTF8_PATH_CATEGORY, xspf.diagnostics.entries[0].category);
+		assert_eq!(Some(1), xspf.diagnostics.entries[0].track_index);
+		
+		/* The real file can still be accessed */
+		let dir = TempDir::new("non-utf8-path");
+		let session_dir = dir.path().join("20170403");
+		fs::create_dir_all(&session_dir).unwrap();
+		
+		let raw_path = session_dir.join(OsStr::from_bytes(b"v02-caf\xe9_au_lait.mp3"));
+		fs::write(&raw_path, b"audio data").unwrap();
+		
+		let track = Track::from_path(&raw_path).unwrap();
+		assert!(track.has_lossy_path());
+		assert_eq!("v02-caf\u{FFFD}_au_lait.mp3", track.filename);
+		
+		let dst_path = dir.path().join(format!("Track_01-{0}-VL{1:02}_{2}.mp3", track.date, track.info().index, track.info().name));
+		fs::copy(track.file_path(), &dst_path).unwrap();
+		assert_eq!(b"audio data".to_vec(), fs::read(&dst_path).unwrap());
+	}
+	
+	/* ............................................ */
+	
+	fn make_playlist(paths: &[&str]) -> XspfPlaylist
+	{
+		XspfPlaylist {
+			tracks: paths.iter().map(|p| Track::from_filepath(p).unwrap()).collect(),
+			title: Some("Test".to_string()),
+			creator: None,
+			annotation: None,
+			date: None,
+			extensions: Vec::new(),
+			diagnostics: Diagnostics::new(),
+			skipped: Vec::new(),
+		}
+	}
+	
+	fn filenames(xspf: &XspfPlaylist) -> Vec<&str>
+	{
+		xspf.tracks.iter().map(|t| t.filename.as_str()).collect()
+	}
+	
+	const EDIT_FIXTURE: [&'static str; 5] = [
+		"music/20170403/v01-a.mp3",
+		"music/20170403/v02-b.mp3",
+		"music/20170403/v03-c.mp3",
+		"music/20170403/v04-d.mp3",
+		"music/20170403/v05-e.mp3",
+	];
+	
+	#[test]
+	fn test_remove_tracks()
+	{
+		let mut xspf = make_playlist(&EDIT_FIXTURE);
+		
+		/* Range + single position (with an overlap) */
+		let removed = xspf.remove_tracks(&[2, 3, 4, 4]).unwrap();
+		assert_eq!(vec!["v02-b.mp3", "v03-c.mp3", "v04-d.mp3"], removed.iter().map(|t| t.filename.as_str()).collect::<Vec<_>>());
+		assert_eq!(vec!["v01-a.mp3", "v05-e.mp3"], filenames(&xspf));
+		
+		/* Invalid positions don't remove anything */
+		assert_eq!(Err(PositionError { position: 3, len: 2 }), xspf.remove_tracks(&[1, 3]).map(|_| ()));
+		assert_eq!(Err(PositionError { position: 0, len: 2 }), xspf.remove_tracks(&[0]).map(|_| ()));
+		assert_eq!(2, xspf.len());
+		
+		assert_eq!("Position 3 is out of range (playlist has 2 tracks)", PositionError { position: 3, len: 2 }.to_string());
+	}
+	
+	#[test]
+	fn test_move_track()
+	{
+		let mut xspf = make_playlist(&EDIT_FIXTURE);
+		
+		/* Same position - No change */
+		xspf.move_track(3, 3).unwrap();
+		assert_eq!(EDIT_FIXTURE.iter().map(|p| &p[15 ..]).collect::<Vec<_>>(), filenames(&xspf));
+		
+		xspf.move_track(2, 4).unwrap();
+		assert_eq!(vec!["v01-a.mp3", "v03-c.mp3", "v04-d.mp3", "v02-b.mp3", "v05-e.mp3"], filenames(&xspf));
+		
+		xspf.move_track(5, 1).unwrap();
+		assert_eq!(vec!["v05-e.mp3", "v01-a.mp3", "v03-c.mp3", "v04-d.mp3", "v02-b.mp3"], filenames(&xspf));
+		
+		assert_eq!(Err(PositionError { position: 6, len: 5 }), xspf.move_track(1, 6));
+	}
+	
+	#[test]
+	fn test_insert_track()
+	{
+		let mut xspf = make_playlist(&EDIT_FIXTURE[.. 2]);
+		
+		/* At the end */
+		xspf.insert_track(3, Track::from_filepath("music/20170404/v01-new.mp3").unwrap()).unwrap();
+		assert_eq!(vec!["v01-a.mp3", "v02-b.mp3", "v01-new.mp3"], filenames(&xspf));
+		
+		/* At the start */
+		xspf.insert_track(1, Track::from_filepath("music/20170402/v09-old.mp3").unwrap()).unwrap();
+		assert_eq!("v09-old.mp3", xspf.tracks[0].filename);
+		
+		assert_eq!(Err(PositionError { position: 6, len: 4 }),
+		           xspf.insert_track(6, Track::from_filepath("music/x/y.mp3").unwrap()));
+	}
+	
+	#[test]
+	fn test_to_xspf_string()
+	{
+		let mut xspf = make_playlist(&["music/20170403/v01-my%20track.mp3", "music/20170404/v02-a&b.flac"]);
+		xspf.title = Some("Sessions <2017>".to_string());
+		xspf.creator = Some("Aligorith".to_string());
+		xspf.tracks[0].duration = Some(TrackDuration(61000));
+		xspf.tracks[1].title = Some("A & B".to_string());
+		
+		let xml = xspf.to_xspf_string();
+		assert!(xml.contains("<title>Sessions &lt;2017&gt;</title>\n\t<creator>Aligorith</creator>\n\t<trackList>"));
+		assert!(xml.contains("<location>file:///music/20170403/v01-my%20track.mp3</location>\n\t\t\t<duration>61000</duration>"));
+		assert!(xml.contains("<location>file:///music/20170404/v02-a%26b.flac</location>\n\t\t\t<title>A &amp; B</title>\n\t\t</track>"));
+		
+		/* Can be read back in */
+		let root: Element = xml.parse().unwrap();
+		let reparsed = XspfPlaylist::from_xml_tree(root, "test.xspf");
+		assert_eq!(vec!["music/20170403/v01-my track.mp3", "music/20170404/v02-a&b.flac"],
+		           reparsed.tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>());
+		assert_eq!(vec![None, Some("A & B".to_string())],
+		           reparsed.tracks.iter().map(|t| t.title.clone()).collect::<Vec<_>>());
+		assert_eq!((Some("Aligorith".to_string()), None, None), (reparsed.creator, reparsed.annotation, reparsed.date));
+	}
+	
+	/* Titles/creators/albums/notes given for tracks get kept (but not empty ones) */
+	#[test]
+	fn test_track_details()
+	{
+		use serde_json;
+		
+		let xml = "<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>file:///music/20170403/v01b-tranquil.mp3</location>\
+			  <title>  Tranquil Waters </title><creator>Aligorith</creator><album>Sessions</album>\
+			  <annotation>Retake the ending</annotation></track>\
+			<track><location>file:///music/20170404/v02-moose.mp3</location><title> </title><creator></creator></track>\
+			</trackList></playlist>";
+		let xspf = parse_xspf_str(xml, "details.xspf", &ParseOptions::default()).unwrap();
+		
+		let t = &xspf.tracks[0];
+		assert_eq!((Some("Tranquil Waters"), Some("Aligorith"), Some("Sessions"), Some("Retake the ending")),
+		           (t.title.as_deref(), t.creator.as_deref(), t.album.as_deref(), t.annotation.as_deref()));
+		assert_eq!("Tranquil Waters", t.display_name());
+		
+		let t = &xspf.tracks[1];
+		assert_eq!((None, None, None, None), (t.title.as_deref(), t.creator.as_deref(), t.album.as_deref(), t.annotation.as_deref()));
+		assert_eq!("moose", t.display_name());
+		
+		/* Kept through serialising (and only included when given) */
+		let json = serde_json::to_value(&xspf.tracks).unwrap();
+		assert_eq!("Sessions", json[0]["album"]);
+		assert!(json[1].get("creator").is_none());
+		let loaded: Vec<Track> = serde_json::from_value(json).unwrap();
+		assert_eq!((Some("Aligorith"), Some("Retake the ending")), (loaded[0].creator.as_deref(), loaded[0].annotation.as_deref()));
+		
+		/* ... and writing back out */
+		let xml = xspf.to_xspf_string();
+		assert!(xml.contains("<title>Tranquil Waters</title>\n\t\t\t<creator>Aligorith</creator>\n\t\t\t\
+		                      <annotation>Retake the ending</annotation>\n\t\t\t<album>Sessions</album>"));
+		let reparsed = parse_xspf_str(&xml, "details.xspf", &ParseOptions::default()).unwrap();
+		assert_eq!(Some("Sessions".to_string()), reparsed.tracks[0].album);
+	}
+	
+	/* Tracks without extensions (e.g. a stray README) don't stop the rest of the playlist being read */
+	#[test]
+	fn test_track_without_extension()
+	{
+		use json_export::JsonTrackExport;
+		use track_name_info::TrackExtension;
+
+		let xml = "<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>file:///music/2017-08-02/README</location></track>\
+			<track><location>file:///music/20170403/v01b-tranquil.mp3</location></track>\
+			</trackList></playlist>";
+		let xspf = parse_xspf_str(xml, "no-extension.xspf", &ParseOptions::default()).unwrap();
+		assert_eq!(2, xspf.len());
+
+		let t = &xspf.tracks[0];
+		assert_eq!(("README", "20170802"), (t.filename.as_str(), t.date.as_str()));
+		assert_eq!(TrackExtension::None, t.info().extn);
+		assert_eq!(TrackExtension::mp3, xspf.tracks[1].info().extn);
+
+		/* Shown as an empty extension in the JSON output */
+		assert_eq!("", JsonTrackExport::from_track(t).extension);
+	}
+
+	/* Parsing what was written gives the same tracks back */
+	#[test]
+	fn test_write_round_trip()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-round-trip");
+		let path = dir.write_file("in.xspf",
+			"<playlist xmlns=\"http://xspf.org/ns/0/\"><title>Sessions</title><trackList>\
+			   <track><location>file:///music/20170403/v01-my%20track.mp3</location><duration>61000</duration></track>\
+			   <track><location>file:///music/20170404/v02b-caf%C3%A9_%E2%99%AB.flac</location></track>\
+			   <track><location>file:///music/2017%2004%2005/v03-spaced%20out.ogg</location><duration>5</duration></track>\
+			 </trackList></playlist>".as_bytes());
+		let xspf = parse_xspf(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+		
+		let out_path = dir.path().join("out.xspf");
+		xspf.save_xspf(&out_path).unwrap();
+		let reparsed = parse_xspf(out_path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+		
+		assert_eq!(3, reparsed.len());
+		for (a, b) in xspf.tracks.iter().zip(reparsed.tracks.iter()) {
+			assert_eq!(a.path, b.path);
+			assert_eq!(a.filename, b.filename);
+			assert_eq!(a.date, b.date);
+			assert_eq!(a.duration.as_ref().map(|d| d.0), b.duration.as_ref().map(|d| d.0));
+		}
+		assert_eq!("v02b-café_♫.flac", reparsed.tracks[1].filename);
+		assert_eq!("2017 04 05", reparsed.tracks[2].date.raw());
+		assert!(!reparsed.tracks[2].date.is_known());
+		assert_eq!(Some(61000), reparsed.tracks[0].duration.as_ref().map(|d| d.0));
+		
+		/* Writing it again gives the same tracks (the titles differ, as they get the filename added) */
+		let (mut xspf, mut reparsed) = (xspf, reparsed);
+		xspf.title = None;
+		reparsed.title = None;
+		assert_eq!(xspf.to_xspf_string(), reparsed.to_xspf_string());
+	}
+	
+	/* Other applications' extensions (e.g. VLC's) are kept as given, and survive being written back out */
+	#[test]
+	fn test_extension_blocks()
+	{
+		use json_export::JsonPlaylistExport;
+		use json_schema::{assert_matches_schema, OutputKind};
+		use serde_json;
+		
+		let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+			<playlist xmlns=\"http://xspf.org/ns/0/\" xmlns:vlc=\"http://www.videolan.org/vlc/playlist/ns/0/\" version=\"1\">\
+			<title>Sessions</title><trackList>\
+			<track><location>file:///music/20170403/v01b-tranquil.mp3</location>\
+			  <extension application=\"http://www.videolan.org/vlc/playlist/0\"><vlc:id>1</vlc:id>\
+			  <vlc:option>start-time=5</vlc:option></extension></track>\
+			<track><location>file:///music/20170404/v02-moose.mp3</location>\
+			  <extension application=\"http://www.videolan.org/vlc/playlist/0\"><vlc:id>0</vlc:id></extension></track>\
+			</trackList>\
+			<extension application=\"http://www.videolan.org/vlc/playlist/0\"><vlc:item tid=\"1\"/><vlc:item tid=\"0\"/></extension>\
+			</playlist>";
+		let xspf = parse_xspf_str(xml, "vlc.xspf", &ParseOptions::default()).unwrap();
+		
+		assert_eq!(1, xspf.extensions.len());
+		assert_eq!(vec!["vlc:item tid=\"1\"", "vlc:item tid=\"0\""], xspf.extensions[0].describe());
+		assert_eq!(vec!["vlc:id = '1'", "vlc:option = 'start-time=5'"], xspf.tracks[0].extensions[0].describe());
+		assert_eq!(Some("http://www.videolan.org/vlc/playlist/ns/0/"),
+		           xspf.tracks[0].extensions[0].namespaces.get("vlc").map(|ns| ns.as_str()));
+		
+		/* Leaf values still get picked out as properties (by their name without the prefix) */
+		assert_eq!(Some("0"), xspf.tracks[1].properties.get("id").map(|v| v.as_str()));
+		
+		/* Included in the JSON output */
+		let json = serde_json::to_value(&xspf).unwrap();
+		assert_eq!("vlc:id", json["tracks"][1]["extensions"][0]["nodes"][0]["name"]);
+		assert_eq!("0", json["extensions"][0]["nodes"][1]["attrs"]["tid"]);
+		assert_matches_schema(OutputKind::RawPlaylist, &json);
+		assert_matches_schema(OutputKind::Playlist, &serde_json::to_value(JsonPlaylistExport::from_playlist(&xspf)).unwrap());
+		
+		/* Written back out as they were (after the playlist's details, and each track's own details) */
+		let written = xspf.to_xspf_string();
+		assert!(written.contains("\t<extension application=\"http://www.videolan.org/vlc/playlist/0\" \
+		                          xmlns:vlc=\"http://www.videolan.org/vlc/playlist/ns/0/\">\n\
+		                          \t\t<vlc:item tid=\"1\"/>\n\t\t<vlc:item tid=\"0\"/>\n\t</extension>\n\t<trackList>"));
+		let reparsed = parse_xspf_str(&written, "vlc.xspf", &ParseOptions::default()).unwrap();
+		assert_eq!(xspf.extensions, reparsed.extensions);
+		for (a, b) in xspf.tracks.iter().zip(reparsed.tracks.iter()) {
+			assert_eq!(a.extensions, b.extensions);
+		}
+	}
+	
+	/* Saving replaces the file in one go, and leaves the original alone if anything fails */
+	#[test]
+	fn test_save_xspf_in_place()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("save-xspf");
+		let path = dir.write_file("playlist.xspf", b"original");
+		
+		let xspf = make_playlist(&EDIT_FIXTURE);
+		xspf.save_xspf(&path).unwrap();
+		assert_eq!(xspf.to_xspf_string(), fs::read_to_string(&path).unwrap());
+		assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());  /* No temp file left behind */
+		
+		/* Failure to write the temp file (blocked here by a dir with the same name) */
+		let temp_path = dir.path().join(".playlist.xspf.tmp");
+		fs::create_dir(&temp_path).unwrap();
+		
+		let smaller = make_playlist(&EDIT_FIXTURE[.. 1]);
+		assert!(smaller.save_xspf(&path).is_err());
+		assert_eq!(xspf.to_xspf_string(), fs::read_to_string(&path).unwrap());
+	}
+	
+	/* ............................................ */
+	
+	/* Documents with a DOCTYPE get refused, unless explicitly allowed */
+	#[test]
+	fn test_doctype_refused()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-doctype");
+		let path = dir.write_file("doctype.xspf",
+			b"<?xml version=\"1.0\"?>\n<!DOCTYPE playlist>\n<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			  <track><location>file:///music/20170403/v01-tranquil.mp3</location></track>\
+			  </trackList></playlist>");
+		let path = path.to_str().unwrap();
+		
+		match parse_xspf(path, &ParseOptions::default()) {
+			Err(XspfError::SecurityError(ref e)) => assert!(e.contains("DOCTYPE"), "{}", e),
+			ref other => panic!("Expected security error, got {:?}", other),
+		}
+		
+		let xspf = parse_xspf(path, &ParseOptions { allow_doctype: true, ..ParseOptions::default() }).unwrap();
+		assert_eq!(1, xspf.len());
+	}
+	
+	/* Mentioning "<!DOCTYPE" in a comment is fine */
+	#[test]
+	fn test_doctype_in_comment()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-doctype-comment");
+		let path = dir.write_file("comment.xspf",
+			b"<?xml version=\"1.0\"?>\n<playlist xmlns=\"http://xspf.org/ns/0/\">\
+			  <title>Mix<!-- copied from <!DOCTYPE html> page --></title><trackList>\
+			  <track><location>file:///music/20170403/v01-tranquil.mp3</location></track>\
+			  </trackList></playlist>");
+		
+		let xspf = parse_xspf(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+		assert_eq!(1, xspf.len());
+		assert!(xspf.title.unwrap().starts_with("Mix"));
+	}
+	
+	/* ............................................ */
+	
+	#[test]
+	fn test_location_form_classify()
+	{
+		assert_eq!(Some(LocationForm::AbsoluteUri), LocationForm::classify("file:///D:/Music/2017/v01-a.mp3"));
+		assert_eq!(Some(LocationForm::AbsolutePath), LocationForm::classify("/srv/music/2017/v01-a.mp3"));
+		assert_eq!(Some(LocationForm::AbsolutePath), LocationForm::classify("D:\\Music\\v01-a.mp3"));
+		assert_eq!(Some(LocationForm::AbsolutePath), LocationForm::classify("d:/Music/v01-a.mp3"));
+		assert_eq!(Some(LocationForm::RelativePath), LocationForm::classify("2018/v01-take.mp3"));
+		assert_eq!(Some(LocationForm::RelativePath), LocationForm::classify("../2018/v01-take.mp3"));
+		assert_eq!(None, LocationForm::classify("http://example.com/v01-take.mp3"));
+	}
+	
+	const MIXED_LOCATIONS_FIXTURE: &'static str = "<playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\"><trackList>\
+		<track><location>file:///D:/Music/20170403/v01-tranquil.mp3</location></track>\
+		<track><location>20180102/v01-take.mp3</location><duration>61000</duration></track>\
+		<track><location>/srv/music/20170404/v02-moose.mp3</location></track>\
+		<track><location>20180103/v02-second%20take.mp3</location></track>\
+		<track><location>http://example.com/v03-remote.mp3</location></track>\
+		</trackList></playlist>";
+	
+	/* Filenames/dates come out the same however the location was written (on any platform) */
+	#[test]
+	fn test_track_location_forms()
+	{
+		let cases = [
+			("file:///C:/Users/me/Music/2017-08-02/v01-tranquil.mp3",   "C:/Users/me/Music/2017-08-02/v01-tranquil.mp3"),
+			("file:///C|/Users/me/Music/2017-08-02/v01-tranquil.mp3",   "C:/Users/me/Music/2017-08-02/v01-tranquil.mp3"),
+			("C:\\Users\\me\\Music\\2017-08-02\\v01-tranquil.mp3",       "C:/Users/me/Music/2017-08-02/v01-tranquil.mp3"),
+			("file://server/share/2017-08-02/v01-tranquil.mp3",         "//server/share/2017-08-02/v01-tranquil.mp3"),
+			("file:///home/me/Music/2017-08-02/v01-tranquil.mp3",       "home/me/Music/2017-08-02/v01-tranquil.mp3"),
+		];
+		for &(location, expected) in cases.iter() {
+			let track = Track::from_track_path(TrackPath::from_location(location).unwrap()).unwrap();
+			assert_eq!(expected, track.path.as_str(), "{}", location);
+			assert_eq!("v01-tranquil.mp3", track.filename, "{}", location);
+			assert_eq!(Some("20170802"), track.date.normalised(), "{}", location);
+			assert_eq!("2017-08-02", track.date.raw(), "{}", location);
+			assert_eq!(TrackType::ViolinLayering, track.info().track_type);
+		}
+	}
+	
+	/* Dates come from the directory, or failing that, the date in MuseScore filenames */
+	#[test]
+	fn test_track_dates()
+	{
+		let date = |path: &str| Track::from_filepath(path).unwrap().date;
+		
+		assert_eq!(Some("20170403"), date("music/20170403/v01-tranquil.mp3").normalised());
+		assert_eq!(Some("20170403"), date("music/2017-04-03/v01-tranquil.mp3").normalised());
+		assert_eq!(Some("20170403"), date("music/2017-04-03 Rehearsal/v01-tranquil.mp3").normalised());
+		
+		assert_eq!(TrackDate::Unknown { raw: "misc".to_string() }, date("music/misc/v01-tranquil.mp3"));
+		assert_eq!(TrackDate::Known { date: "20170802".to_string(), raw: "misc".to_string() },
+		           date("music/misc/20170802-02-TouchedByAnAngel.flac"));
+		
+		/* Relocated tracks get their dates worked out again */
+		let mut track = Track::from_filepath("music/misc/v01-tranquil.mp3").unwrap();
+		track.relocate(TrackPath::from_escaped("music/2018-01-02/v01-tranquil.mp3"));
+		assert_eq!(Some("20180102"), track.date.normalised());
+	}
+	
+	#[test]
+	fn test_skipped_entries()
+	{
+		let root: Element = "<playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\"><trackList>\
+			<track><location>file://localhost/music/20170403/v01-tranquil.mp3</location><duration>61000</duration></track>\
+			<track><location>http://radio.example.com/live</location><duration>3600000</duration></track>\
+			<track><title>Nowhere</title></track>\
+			<track><location>file:/music/20170403/v02-moose.mp3</location><duration>30000</duration></track>\
+			</trackList></playlist>".parse().unwrap();
+		let xspf = XspfPlaylist::from_xml_tree(root, "vlc.xspf");
+		
+		/* Local files, however they're written */
+		assert_eq!(vec!["music/20170403/v01-tranquil.mp3", "music/20170403/v02-moose.mp3"],
+		           xspf.tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>());
+		
+		/* Everything else gets noted, without counting towards the totals */
+		assert_eq!(vec![Some("http://radio.example.com/live".to_string()), None],
+		           xspf.skipped.iter().map(|s| s.location.clone()).collect::<Vec<_>>());
+		assert_eq!("Element skipped as no location info found", xspf.skipped[1].reason);
+		assert_eq!(2, xspf.len());
+		
+		/* Tracks know where they were in the playlist, counting the skipped entries (as players do) */
+		assert_eq!(vec![0, 3], xspf.tracks.iter().map(|t| t.original_index).collect::<Vec<_>>());
+		assert_eq!((91_000, 0), (xspf.total_duration().duration.0, xspf.total_duration().uncounted));
+	}
+	
+	#[test]
+	fn test_mixed_locations()
+	{
+		let root: Element = MIXED_LOCATIONS_FIXTURE.parse().unwrap();
+		let mut xspf = XspfPlaylist::from_xml_tree(root, "mixed.xspf");
+		
+		/* Unsupported URIs still get skipped (but noted) */
+		assert_eq!(4, xspf.len());
+		assert_eq!(1, xspf.skipped.len());
+		assert_eq!(Some("http://example.com/v03-remote.mp3".to_string()), xspf.skipped[0].location);
+		assert!(xspf.skipped[0].reason.starts_with("Unsupported URI"));
+		assert_eq!(2, xspf.resolve_relative_locations(Path::new("/home/user/playlists")));
+		
+		assert_eq!(vec!["D:/Music/20170403/v01-tranquil.mp3",
+		                "/home/user/playlists/20180102/v01-take.mp3",
+		                "/srv/music/20170404/v02-moose.mp3",
+		                "/home/user/playlists/20180103/v02-second take.mp3"],
+		           xspf.tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>());
+		
+		/* Everything else about the resolved tracks is kept */
+		assert_eq!("20180102", xspf.tracks[1].date.as_str());
+		assert_eq!(Some(61000), xspf.tracks[1].duration.as_ref().map(|d| d.0));
+		assert_eq!(Some(LocationForm::RelativePath), xspf.tracks[1].location_form());
+		
+		/* The mix gets reported */
+		assert_eq!(1, xspf.diagnostics.len());
+		let report = &xspf.diagnostics.entries[0];
+		assert_eq!(MIXED_LOCATIONS_CATEGORY, report.category);
+		assert!(report.message.contains("1 file:/// URIs, 1 absolute paths, 2 relative paths"), "{}", report.message);
+		
+		/* Consistent playlists aren't reported */
+		let root: Element = "<playlist><trackList><track><location>2018/v01-a.mp3</location></track></trackList></playlist>".parse().unwrap();
+		let mut xspf = XspfPlaylist::from_xml_tree(root, "relative.xspf");
+		assert_eq!(1, xspf.resolve_relative_locations(Path::new("/music")));
+		assert!(xspf.diagnostics.is_empty());
+	}
+	
+	/* Relative locations get resolved against the playlist's directory (or the rebase dir instead) */
+	#[test]
+	fn test_rebase_relative_locations()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-rebase");
+		let path = dir.write_file("playlists/mixed.xspf", MIXED_LOCATIONS_FIXTURE.as_bytes());
+		let path = path.to_str().unwrap();
+		
+		let xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+		assert_eq!(dir.path().join("playlists/20180102/v01-take.mp3"), xspf.tracks[1].file_path());
+		
+		let options = ParseOptions { rebase: Some(dir.path().join("moved")), ..ParseOptions::default() };
+		let rebased = parse_xspf(path, &options).unwrap();
+		assert_eq!(dir.path().join("moved/20180102/v01-take.mp3"), rebased.tracks[1].file_path());
+		assert_eq!(dir.path().join("moved/20180103/v02-second take.mp3"), rebased.tracks[3].file_path());
+		
+		/* Absolute entries are never rebased */
+		assert_eq!("D:/Music/20170403/v01-tranquil.mp3", rebased.tracks[0].path.as_str());
+		assert_eq!("/srv/music/20170404/v02-moose.mp3", rebased.tracks[2].path.as_str());
+		
+		/* Written back out as absolute URIs */
+		assert!(rebased.to_xspf_string().contains(&format!("<location>file://{}/moved/20180102/v01-take.mp3</location>",
+		                                                   dir.path().display())));
+	}
+	
+	/* Locations escaped twice get repaired, but only when that's where the files really are */
+	#[test]
+	fn test_repair_double_encoded_locations()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-double-encoded");
+		dir.write_file("20170802/v01-second take.mp3", b"repaired");
+		dir.write_file("20170802/v02-100%20pure.mp3", b"literal");
+		dir.write_file("20170802/v02-100 pure.mp3", b"not this one");
+		let path = dir.write_file("playlist.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>20170802/v01-second%2520take.mp3</location><title>Second</title></track>\
+			<track><location>20170802/v02-100%2520pure.mp3</location></track>\
+			<track><location>20170802/v03-gone%2520away.mp3</location></track>\
+			<track><location>20170802/v04-plain.mp3</location></track>\
+			</trackList></playlist>");
+		let path = path.to_str().unwrap();
+		
+		/* Parsing alone never changes anything */
+		let mut xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+		assert_eq!("v01-second%20take.mp3", xspf.tracks[0].filename);
+		assert!(xspf.diagnostics.is_empty());
+		
+		assert_eq!(1, xspf.repair_double_encoded_locations());
+		assert_eq!(dir.path().join("20170802/v01-second take.mp3"), xspf.tracks[0].file_path());
+		assert_eq!("v01-second take.mp3", xspf.tracks[0].filename);
+		assert_eq!("second take", xspf.tracks[0].info().name);
+		assert_eq!(Some("Second".to_string()), xspf.tracks[0].title);
+		
+		/* The file with a literal "%20" in its name exists as it is, and neither version of the missing one exists */
+		assert_eq!(dir.path().join("20170802/v02-100%20pure.mp3"), xspf.tracks[1].file_path());
+		assert_eq!("v03-gone%20away.mp3", xspf.tracks[2].filename);
+		
+		assert_eq!(1, xspf.diagnostics.len());
+		assert_eq!(DOUBLE_ENCODED_CATEGORY, xspf.diagnostics.entries[0].category);
+		assert_eq!(Some(0), xspf.diagnostics.entries[0].track_index);
+		assert!(xspf.diagnostics.entries[0].message.starts_with("double-encoded location repaired"));
+		
+		/* Nothing more to do */
+		assert_eq!(0, xspf.repair_double_encoded_locations());
+	}
+	
+	/* Playlist with ratings (and other details) given by another application */
+	const RATED_FIXTURE: &'static str = "<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+	                                     <track><location>file:///music/20170403/v01-tranquil.mp3</location>\
+	                                       <extension application=\"http://example.com/player\"><rating>5</rating><mood>calm</mood></extension></track>\
+	                                     <track><location>file:///music/20170403/v02-moose.mp3</location>\
+	                                       <extension application=\"http://example.com/player\"><rating> 0 </rating><stars>3</stars></extension></track>\
+	                                     <track><location>file:///music/20170404/v01-dawn.mp3</location>\
+	                                       <extension application=\"http://example.com/player\"><rating>6</rating></extension></track>\
+	                                     <track><location>file:///music/20170404/v02-dusk.mp3</location>\
+	                                       <extension application=\"http://example.com/player\"><rating>great</rating></extension>\
+	                                       <extension application=\"http://example.com/other\"><rating>4</rating></extension></track>\
+	                                     <track><location>file:///music/20170405/v01-noon.mp3</location></track>\
+	                                     </trackList></playlist>";
+	
+	/* Extension properties get picked up, and the ratings taken from them */
+	#[test]
+	fn test_ratings()
+	{
+		let root: Element = RATED_FIXTURE.parse().unwrap();
+		let mut xspf = XspfPlaylist::from_xml_tree(root, "rated.xspf");
+		assert_eq!(Some("calm"), xspf.tracks[0].properties.get("mood").map(|v| v.as_str()));
+		
+		assert_eq!(2, xspf.apply_ratings(DEFAULT_RATING_KEY));
+		assert_eq!(vec![Some(5), Some(0), None, None, None], xspf.tracks.iter().map(|t| t.rating).collect::<Vec<_>>());
+		
+		/* Valid ratings move out of the properties, while invalid ones are kept as given */
+		assert!(!xspf.tracks[0].properties.contains_key("rating"));
+		assert_eq!(Some("6"), xspf.tracks[2].properties.get("rating").map(|v| v.as_str()));
+		assert_eq!(Some("great"), xspf.tracks[3].properties.get("rating").map(|v| v.as_str()));
+		assert!(xspf.tracks[4].properties.is_empty());
+		
+		/* ... and get reported */
+		let reported: Vec<(Option<usize>, &str)> = xspf.diagnostics.entries.iter().map(|d| (d.track_index, d.category.as_str())).collect();
+		assert_eq!(vec![(Some(2), RATING_CATEGORY), (Some(3), RATING_CATEGORY)], reported);
+		assert!(xspf.diagnostics.entries[1].message.contains("'great' isn't a valid rating"), "{}", xspf.diagnostics.entries[1].message);
+		
+		/* Another key */
+		let root: Element = RATED_FIXTURE.parse().unwrap();
+		let mut xspf = XspfPlaylist::from_xml_tree(root, "rated.xspf");
+		assert_eq!(1, xspf.apply_ratings("stars"));
+		assert_eq!(Some(3), xspf.tracks[1].rating);
+		assert!(xspf.diagnostics.is_empty());
+		
+		/* Key missing altogether - nothing's rated, and nothing's wrong */
+		let mut xspf = make_playlist(&["/music/20170403/v01-tranquil.mp3"]);
+		assert_eq!(0, xspf.apply_ratings(DEFAULT_RATING_KEY));
+		assert_eq!(None, xspf.tracks[0].rating);
+		assert!(xspf.diagnostics.is_empty());
+	}
+	
+	/* The key to use comes from the parse options */
+	#[test]
+	fn test_ratings_parse_option()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-ratings");
+		let path = dir.write_file("rated.xspf", RATED_FIXTURE.as_bytes());
+		let path = path.to_str().unwrap();
+		
+		let xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+		assert_eq!(Some(5), xspf.tracks[0].rating);
+		assert_eq!(2, xspf.diagnostics.len());
+		
+		let options = ParseOptions { rating_key: Some("stars".to_string()), ..ParseOptions::default() };
+		let xspf = parse_xspf(path, &options).unwrap();
+		assert_eq!(vec![None, Some(3), None, None, None], xspf.tracks.iter().map(|t| t.rating).collect::<Vec<_>>());
+	}
+	
+	/* Playlist with tracks of the given durations (in seconds, None = unknown) */
+	fn timed_playlist(durations: &[Option<i64>]) -> XspfPlaylist
+	{
+		let paths: Vec<String> = (0 .. durations.len()).map(|i| format!("music/20170403/v{:02}-take.mp3", i + 1)).collect();
+		let mut xspf = make_playlist(&paths.iter().map(|p| p.as_str()).collect::<Vec<_>>());
+		for (track, duration) in xspf.tracks.iter_mut().zip(durations.iter()) {
+			track.duration = duration.map(|secs| TrackDuration(secs * 1000));
+		}
+		xspf
+	}
+	
+	#[test]
+	fn test_duration_stats()
+	{
+		let mut xspf = timed_playlist(&[Some(90), None, Some(30), Some(240), Some(30), None]);
+		xspf.tracks.push(Track::from_filepath("music/20170410/20170410-01-Prelude.flac").unwrap());
+		xspf.tracks.push(Track::from_filepath("music/20170410/notes.mp3").unwrap());
+		xspf.tracks[6].duration = Some(TrackDuration(120_000));
+		xspf.tracks[7].duration = Some(TrackDuration(240_000));
+		
+		let stats = xspf.duration_stats();
+		assert_eq!(750_000, stats.total.duration.0);
+		assert_eq!((6, 2), (stats.counted, stats.total.uncounted));
+		
+		/* Ties go to the first track with that duration */
+		assert_eq!(Some(2), stats.shortest);
+		assert_eq!(Some(3), stats.longest);
+		assert_eq!(Some(125_000), stats.average().map(|d| d.0));
+		assert_eq!("02:05", stats.average().unwrap().to_timecode());
+		
+		let per_type: Vec<(TrackType, i64, usize, usize)> = stats.per_type.iter()
+			.map(|t| (t.track_type, t.duration.0, t.counted, t.uncounted))
+			.collect();
+		assert_eq!(vec![(TrackType::UnknownType, 240_000, 1, 0),
+		                (TrackType::ViolinLayering, 390_000, 4, 2),
+		                (TrackType::MuseScore, 120_000, 1, 0)],
+		           per_type);
+		
+		/* Nothing to go on */
+		let stats = timed_playlist(&[None, None]).duration_stats();
+		assert_eq!((None, None, None), (stats.shortest, stats.longest, stats.average().map(|d| d.0)));
+		assert_eq!(2, stats.total.uncounted);
+	}
+	
+	#[test]
+	fn test_filter_tracks()
+	{
+		let paths = ["music/20170403/v01-tranquil.mp3",
+		             "music/20170403/20170403-01-Prelude.mp3",
+		             "music/20170612/v02-the_last_moose.mp3",
+		             "music/20180105/v03-Moose_Returns.flac",
+		             "music/20180105/holiday_recording.mp3"];
+		let filter_names = |filter: TrackFilter| -> Vec<String> {
+			let mut xspf = make_playlist(&paths);
+			xspf.filter_tracks(&filter);
+			filenames(&xspf).iter().map(|f| f.to_string()).collect()
+		};
+		
+		/* Nothing to filter on */
+		assert!(TrackFilter::default().is_empty());
+		assert_eq!(5, filter_names(TrackFilter::default()).len());
+		
+		assert_eq!(vec!["v01-tranquil.mp3", "v02-the_last_moose.mp3", "v03-Moose_Returns.flac"],
+		           filter_names(TrackFilter { track_type: Some(TrackType::ViolinLayering), ..TrackFilter::default() }));
+		assert_eq!(vec!["v01-tranquil.mp3", "20170403-01-Prelude.mp3", "v02-the_last_moose.mp3"],
+		           filter_names(TrackFilter { date_prefix: Some("2017".to_string()), ..TrackFilter::default() }));
+		assert_eq!(vec!["v02-the_last_moose.mp3"],
+		           filter_names(TrackFilter { date_prefix: Some("2017-06".to_string()), ..TrackFilter::default() }));
+		assert_eq!(vec!["v02-the_last_moose.mp3", "v03-Moose_Returns.flac"],
+		           filter_names(TrackFilter { name_contains: Some("MOOSE".to_string()), ..TrackFilter::default() }));
+		
+		/* Everything given has to match */
+		let filter = TrackFilter { track_type: Some(TrackType::ViolinLayering),
+		                           date_prefix: Some("2018".to_string()),
+		                           name_contains: Some("moose".to_string()) };
+		assert!(!filter.is_empty());
+		assert_eq!(vec!["v03-Moose_Returns.flac"], filter_names(filter));
+		
+		let mut xspf = make_playlist(&paths);
+		assert_eq!(4, xspf.filter_tracks(&TrackFilter { track_type: Some(TrackType::MuseScore), ..TrackFilter::default() }));
+	}
+	
+	#[test]
+	fn test_truncate_by_count()
+	{
+		let mut xspf = timed_playlist(&[Some(60); 5]);
+		assert_eq!(3, xspf.head_by_count(2));
+		assert_eq!(vec!["v01-take.mp3", "v02-take.mp3"], filenames(&xspf));
+		
+		let mut xspf = timed_playlist(&[Some(60); 5]);
+		assert_eq!(3, xspf.tail_by_count(2));
+		assert_eq!(vec!["v04-take.mp3", "v05-take.mp3"], filenames(&xspf));
+		
+		/* Asking for more than there are keeps everything */
+		assert_eq!(0, xspf.tail_by_count(10));
+		assert_eq!(0, xspf.head_by_count(10));
+		assert_eq!(2, xspf.len());
+	}
+	
+	/* The track taking the total over the limit is kept, unless the limit is strict */
+	#[test]
+	fn test_cut_crossing_track()
+	{
+		let durations = [Some(60_000), Some(90_000), Some(120_000), Some(30_000)];
+		assert_eq!(DurationCut { kept: 3, total_ms: 270_000, unknown: vec![] }, cut_by_duration(&durations, 200_000, true));
+		assert_eq!(DurationCut { kept: 2, total_ms: 150_000, unknown: vec![] }, cut_by_duration(&durations, 200_000, false));
+		
+		/* Reaching the limit exactly doesn't count as crossing it */
+		assert_eq!(2, cut_by_duration(&durations, 150_000, true).kept);
+		assert_eq!(2, cut_by_duration(&durations, 150_000, false).kept);
+		
+		/* A first track that's already too long */
+		assert_eq!(1, cut_by_duration(&durations, 10_000, true).kept);
+		assert_eq!(0, cut_by_duration(&durations, 10_000, false).kept);
+		
+		/* Unknown durations count as zero */
+		let cut = cut_by_duration(&[Some(60_000), None, Some(60_000), None], 120_000, true);
+		assert_eq!((3, vec![1]), (cut.kept, cut.unknown));
+	}
+	
+	#[test]
+	fn test_truncate_by_duration()
+	{
+		let mut xspf = timed_playlist(&[Some(60), Some(90), Some(120), Some(30)]);
+		let cut = xspf.head_by_duration(100_000, true);
+		assert_eq!((2, 150_000), (cut.kept, cut.total_ms));
+		assert_eq!(vec!["v01-take.mp3", "v02-take.mp3"], filenames(&xspf));
+		
+		/* Tail adds up from the end - 30, then 120 crosses 100 */
+		let mut xspf = timed_playlist(&[Some(60), Some(90), Some(120), Some(30)]);
+		let cut = xspf.tail_by_duration(100_000, true);
+		assert_eq!((2, 150_000), (cut.kept, cut.total_ms));
+		assert_eq!(vec!["v03-take.mp3", "v04-take.mp3"], filenames(&xspf));
+		
+		let mut xspf = timed_playlist(&[Some(60), Some(90), Some(120), Some(30)]);
+		xspf.tail_by_duration(100_000, false);
+		assert_eq!(vec!["v04-take.mp3"], filenames(&xspf));
+		
+		/* Unknown durations are reported by their index in the original playlist (in playlist order) */
+		let mut xspf = timed_playlist(&[Some(60), None, Some(30), None, Some(30)]);
+		let cut = xspf.tail_by_duration(90_000, false);
+		assert_eq!((4, vec![1, 3]), (cut.kept, cut.unknown));
+		assert_eq!(vec!["v02-take.mp3", "v03-take.mp3", "v04-take.mp3", "v05-take.mp3"], filenames(&xspf));
+	}
+	
+	/* Parts get filled in turn, without splitting any tracks */
+	#[test]
+	fn test_split_sizes_by_duration()
+	{
+		let durations = [Some(60_000), Some(90_000), Some(120_000), Some(30_000), Some(50_000)];
+		assert_eq!(vec![2, 2, 1], split_sizes_by_duration(&durations, 150_000, 0));
+		assert_eq!(vec![5], split_sizes_by_duration(&durations, 400_000, 0));
+		
+		/* Tracks longer than the limit get a part of their own */
+		assert_eq!(vec![1, 1, 1, 2], split_sizes_by_duration(&durations, 80_000, 0));
+		
+		/* Unknown durations count as zero, or the assumed duration */
+		let durations = [Some(60_000), None, Some(60_000), None, Some(60_000)];
+		assert_eq!(vec![4, 1], split_sizes_by_duration(&durations, 120_000, 0));
+		assert_eq!(vec![2, 2, 1], split_sizes_by_duration(&durations, 120_000, 60_000));
+		
+		assert!(split_sizes_by_duration(&[], 120_000, 0).is_empty());
+	}
+	
+	#[test]
+	fn test_split_playlist()
+	{
+		let mut xspf = timed_playlist(&[Some(60), Some(90), None, Some(120), Some(30)]);
+		xspf.title = Some("Sessions".to_string());
+		
+		let parts = xspf.split_by_duration(TrackDuration(150_000));
+		assert_eq!(vec![vec!["v01-take.mp3", "v02-take.mp3", "v03-take.mp3"], vec!["v04-take.mp3", "v05-take.mp3"]],
+		           parts.iter().map(filenames).collect::<Vec<_>>());
+		assert_eq!(Some("Sessions (Part 2 of 2)".to_string()), parts[1].title);
+		assert_eq!(Some(TrackDuration(120_000)), parts[1].tracks[0].duration);
+		
+		let parts = xspf.split_by_duration_assuming(TrackDuration(150_000), TrackDuration(60_000));
+		assert_eq!(vec![2, 1, 2], parts.iter().map(|p| p.len()).collect::<Vec<_>>());
+		
+		let parts = xspf.split_by_count(2);
+		assert_eq!(vec![2, 2, 1], parts.iter().map(|p| p.len()).collect::<Vec<_>>());
+		assert_eq!(vec!["v05-take.mp3"], filenames(&parts[2]));
+		
+		/* The original playlist is left as it was */
+		assert_eq!(5, xspf.len());
+	}
+	
+	/* Track info only gets worked out when it's used (e.g. not at all for listing the paths) */
+	#[test]
+	fn test_lazy_track_info()
+	{
+		use serde_json;
+		use test_utils::TempDir;
+		use track_name_info::ANALYSIS_COUNT;
+		
+		let analysed = || ANALYSIS_COUNT.with(|count| count.get());
+		
+		let dir = TempDir::new("xspf-lazy-info");
+		let path = dir.write_file("playlists/mixed.xspf", MIXED_LOCATIONS_FIXTURE.as_bytes());
+		let before = analysed();
+		
+		/* Parsing, then listing (as for "list" mode) */
+		let xspf = parse_xspf(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+		let paths: Vec<&str> = xspf.tracks.iter().map(|t| t.path.as_str()).collect();
+		assert_eq!(4, paths.len());
+		assert_eq!(before, analysed());
+		
+		/* Only worked out once per track */
+		assert_eq!(1, xspf.tracks[1].info().index);
+		assert_eq!("take", xspf.tracks[1].info().name);
+		assert_eq!(before + 1, analysed());
+		
+		/* Serialising fills in the rest (in the same shape as before) */
+		let json = serde_json::to_value(&xspf.tracks).unwrap();
+		assert_eq!(before + 4, analysed());
+		assert_eq!(1, json[1]["info"]["index"]);
+		assert_eq!("take", json[1]["info"]["name"]);
+		assert_eq!(vec!["date", "duration", "duration_source", "filename", "info", "original_index", "path", "title"],
+		           json[0].as_object().unwrap().keys().map(|k| k.as_str()).collect::<Vec<_>>());
+		
+		/* Info loaded back in from JSON doesn't need working out again */
+		let loaded: Vec<Track> = serde_json::from_value(json).unwrap();
+		assert_eq!("take", loaded[1].info().name);
+		assert_eq!(before + 4, analysed());
+	}
+	
+	/* ............................................ */
+	
+	/* Playlist with lots of tracks (and some of the awkward bits), for comparing the ways of reading it */
+	fn large_playlist_fixture(num_tracks: usize) -> String
+	{
+		let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		                            <playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\">\n\
+		                            <title>Sessions &amp; Takes <!-- not a <!DOCTYPE> --></title>\n<trackList>\n");
+		for i in 0 .. num_tracks {
+			xml.push_str(&format!("<track><location>file:///music/2017{0:04}/v{1:02}-caf%C3%A9_take_{2}.mp3</location>\
+			                       <title>Caf\u{e9} take {2} &lt;{1}&gt;</title><duration>{3}</duration></track>\n",
+			                      i % 10000, i % 100, i, 1000 + i * 7));
+		}
+		xml.push_str("</trackList>\n</playlist>\n");
+		xml
+	}
+	
+	/* Strategies that can be used in this build */
+	fn available_io_strategies() -> Vec<IoStrategy>
+	{
+		if cfg!(feature = "mmap") {
+			vec![IoStrategy::String, IoStrategy::Buffered, IoStrategy::Mmap]
+		}
+		else {
+			vec![IoStrategy::String, IoStrategy::Buffered]
+		}
+	}
+	
+	/* All the ways of reading the file give the same playlist */
+	#[test]
+	fn test_io_strategies_identical()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-io-strategies");
+		let path = dir.write_file("large.xspf", large_playlist_fixture(2000).as_bytes());
+		let path = path.to_str().unwrap();
+		
+		let parse_with = |io_strategy: IoStrategy| {
+			let xspf = parse_xspf(path, &ParseOptions { io_strategy: io_strategy, ..ParseOptions::default() }).unwrap();
+			(xspf.title.clone(), serde_json::to_value(&xspf.tracks).unwrap(), xspf.to_xspf_string())
+		};
+		let expected = parse_with(IoStrategy::String);
+		assert_eq!(2000, expected.1.as_array().unwrap().len());
+		
+		for io_strategy in available_io_strategies() {
+			assert!(expected == parse_with(io_strategy), "{:?} gave a different playlist", io_strategy);
+		}
+	}
+	
+	/* Files that can't be parsed as playlists give errors (instead of panicking) */
+	#[test]
+	fn test_parse_errors()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-errors");
+		let parse = |path: &Path| parse_xspf(path.to_str().unwrap(), &ParseOptions::default());
+		
+		/* Missing file */
+		match parse(&dir.path().join("nonexistent.xspf")) {
+			Err(XspfError::IoError(ref e)) => assert_eq!(io::ErrorKind::NotFound, e.kind()),
+			other => panic!("expected an IoError, got {:?}", other.map(|x| x.len()))
+		}
+		
+		/* Truncated XML */
+		let truncated = dir.write_file("truncated.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>file:///music/20170403/v01-tranq");
+		match parse(&truncated) {
+			Err(XspfError::XmlParseError(_)) => {},
+			other => panic!("expected an XmlParseError, got {:?}", other.map(|x| x.len()))
+		}
+		
+		/* ... including when it stops between elements (but not when there's nothing left to close) */
+		let between = dir.write_file("between.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList><track/>");
+		match parse(&between) {
+			Err(XspfError::XmlParseError(ref e)) => assert_eq!("document ended before </trackList>", e),
+			other => panic!("expected an XmlParseError, got {:?}", other.map(|x| x.len()))
+		}
+		let buffered = ParseOptions { io_strategy: IoStrategy::Buffered, ..ParseOptions::default() };
+		match parse_xspf(between.to_str().unwrap(), &buffered) {
+			Err(XspfError::XmlParseError(ref e)) => assert_eq!("document ended before </trackList>", e),
+			other => panic!("expected an XmlParseError, got {:?}", other.map(|x| x.len()))
+		}
+		let empty = dir.write_file("empty.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\"/>\n");
+		assert_eq!(0, parse(&empty).unwrap().len());
+		
+		/* Valid XML, but not a playlist */
+		let not_playlist = dir.write_file("feed.xspf", b"<rss version=\"2.0\"><channel><title>Feed</title></channel></rss>");
+		match parse(&not_playlist) {
+			Err(XspfError::NotAPlaylist) => {},
+			other => panic!("expected NotAPlaylist, got {:?}", other.map(|x| x.len()))
+		}
+		
+		/* The messages say what went wrong */
+		assert!(parse(&not_playlist).unwrap_err().to_string().contains("root element should be <playlist>"));
+		assert!(parse(&truncated).unwrap_err().to_string().starts_with("Invalid XML"));
+	}
+	
+	/* Documents that have already been read in give the same playlist as reading the file */
+	#[test]
+	fn test_parse_xspf_str()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-parse-str");
+		let xml = large_playlist_fixture(20);
+		let path = dir.write_file("tracks.xspf", xml.as_bytes());
+		
+		let path = path.to_str().unwrap();
+		let options = ParseOptions { rebase: Some(dir.path().to_path_buf()), ..ParseOptions::default() };
+		let from_file = parse_xspf(path, &options).unwrap();
+		let from_str = parse_xspf_str(&xml, path, &options).unwrap();
+		assert_eq!(from_file.title, from_str.title);
+		assert_eq!(serde_json::to_value(&from_file.tracks).unwrap(), serde_json::to_value(&from_str.tracks).unwrap());
+		
+		/* Same errors too */
+		match parse_xspf_str("<rss version=\"2.0\"></rss>", "feed", &ParseOptions::default()) {
+			Err(XspfError::NotAPlaylist) => {},
+			other => panic!("expected NotAPlaylist, got {:?}", other.map(|x| x.len()))
+		}
+		match parse_xspf_str("<!DOCTYPE playlist><playlist/>", "doctype", &ParseOptions::default()) {
+			Err(XspfError::SecurityError(ref e)) => assert!(e.contains("DOCTYPE"), "{}", e),
+			other => panic!("expected a SecurityError, got {:?}", other.map(|x| x.len()))
+		}
+	}
+	
+	/* Gzip-compressed playlists get decompressed, whichever way they're read (and whatever they're named) */
+	#[test]
+	fn test_gzipped_playlist()
+	{
+		use test_utils::TempDir;
+		
+		let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/compos-20170403-melodrama.xspf");
+		let expected = parse_xspf(fixture, &ParseOptions::default()).unwrap();
+		assert!(!expected.is_empty());
+		
+		let dir = TempDir::new("xspf-gzipped");
+		let unnamed = dir.write_file("melodrama.xspf", include_bytes!("../test_files/compos-20170403-melodrama.xspf.gz"));
+		let gz_fixture = format!("{}.gz", fixture);
+		for path in [gz_fixture.as_str(), unnamed.to_str().unwrap()] {
+			for io_strategy in available_io_strategies() {
+				let xspf = parse_xspf(path, &ParseOptions { io_strategy: io_strategy, ..ParseOptions::default() }).unwrap();
+				assert_eq!(serde_json::to_value(&expected.tracks).unwrap(), serde_json::to_value(&xspf.tracks).unwrap(),
+				           "{:?} gave a different playlist for {}", io_strategy, path);
+			}
+			
+			let mut streamed: Vec<Track> = Vec::new();
+			parse_xspf_streaming(path, &ParseOptions::default(), |track| streamed.push(track)).unwrap();
+			assert_eq!(serde_json::to_value(&expected.tracks).unwrap(), serde_json::to_value(&streamed).unwrap());
+		}
+		
+		/* Files named as compressed have to be */
+		let wrongly_named = dir.write_file("plain.xspf.gz", b"<playlist xmlns=\"http://xspf.org/ns/0/\"/>");
+		assert!(parse_xspf(wrongly_named.to_str().unwrap(), &ParseOptions::default()).is_err());
+	}
+	
+	/* Playlists piped in (i.e. as stdin is read for "-") get named as such, and resolved against the current directory */
+	#[test]
+	fn test_parse_xspf_reader()
+	{
+		let xml = "<playlist xmlns=\"http://xspf.org/ns/0/\"><title>Piped</title><trackList>\
+			<track><location>20170403/v01-tranquil.mp3</location></track>\
+			</trackList></playlist>";
+		let xspf = parse_xspf_reader(io::Cursor::new(xml.as_bytes().to_vec()), playlist_input::display_name("-"),
+		                             &ParseOptions::default()).unwrap();
+		assert_eq!(Some("Piped - <stdin>".to_string()), xspf.title);
+		assert_eq!(env::current_dir().unwrap().join("20170403").join("v01-tranquil.mp3"), xspf.tracks[0].file_path());
+		
+		/* Compressed ones too */
+		let compressed = include_bytes!("../test_files/compos-20170403-melodrama.xspf.gz").to_vec();
+		let xspf = parse_xspf_reader(io::Cursor::new(compressed), "<stdin>", &ParseOptions::default()).unwrap();
+		assert_eq!(Some("Playlist - <stdin>".to_string()), xspf.title);
+		assert!(!xspf.is_empty());
+	}
+	
+	/* Streaming the tracks gives the same playlist as parsing the whole document */
+	#[test]
+	fn test_parse_streaming()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-streaming");
+		let large = dir.write_file("large.xspf", large_playlist_fixture(2000).as_bytes());
+		let mixed = dir.write_file("mixed.xspf", b"<?xml version=\"1.0\"?>\n\
+			<playlist xmlns=\"http://xspf.org/ns/0/\"><title>Mixed &lt;bag&gt;</title><creator> Me </creator><trackList>\
+			<track><location>20170403/v01-tranquil.mp3</location><title>Tranquil</title><album>Sessions</album></track>\
+			<track><location>file:///music/20170404/v02-moose.mp3</location>\
+			  <extension application=\"http://example.com/player\"><rating>4</rating><plays>12</plays></extension></track>\
+			<track><location>http://example.com/stream</location></track>\
+			<track/>\
+			<track><location>/music/20170405/v03-dawn.mp3</location>\
+			  <extension application=\"http://example.com/player\"><rating>11</rating></extension></track>\
+			</trackList><date>2017-04-05</date>\
+			<extension application=\"http://example.com/player\"><queue><item ref=\"2\"/></queue></extension></playlist>");
+		
+		for path in [large, mixed] {
+			let path = path.to_str().unwrap();
+			let expected = parse_xspf(path, &ParseOptions::default()).unwrap();
+			
+			let mut tracks: Vec<Track> = Vec::new();
+			let streamed = parse_xspf_streaming(path, &ParseOptions::default(), |track| tracks.push(track)).unwrap();
+			assert!(streamed.tracks.is_empty());
+			
+			assert_eq!(serde_json::to_value(&expected.tracks).unwrap(), serde_json::to_value(&tracks).unwrap());
+			assert_eq!((&expected.title, &expected.creator, &expected.annotation, &expected.date),
+			           (&streamed.title, &streamed.creator, &streamed.annotation, &streamed.date));
+			assert_eq!(expected.skipped, streamed.skipped);
+			assert_eq!(expected.extensions, streamed.extensions);
+			
+			let findings = |xspf: &XspfPlaylist| {
+				let mut found: Vec<(String, Option<usize>)> = xspf.diagnostics.entries.iter()
+					.map(|d| (d.category.to_string(), d.track_index))
+					.collect();
+				found.sort();
+				found
+			};
+			assert_eq!(findings(&expected), findings(&streamed));
+		}
+		
+		/* Relative locations, ratings, skipped entries, etc. all got handled */
+		let mut tracks: Vec<Track> = Vec::new();
+		let streamed = parse_xspf_streaming(dir.path().join("mixed.xspf").to_str().unwrap(), &ParseOptions::default(),
+		                                    |track| tracks.push(track)).unwrap();
+		assert_eq!(dir.path().join("20170403/v01-tranquil.mp3"), tracks[0].file_path());
+		assert_eq!((Some("Tranquil"), Some("Sessions")), (tracks[0].title.as_deref(), tracks[0].album.as_deref()));
+		assert_eq!((Some(4), Some("12")), (tracks[1].rating, tracks[1].properties.get("plays").map(|s| s.as_str())));
+		assert!(streamed.title.unwrap().starts_with("Mixed <bag> - "));
+		assert_eq!((Some("Me"), Some("2017-04-05")), (streamed.creator.as_deref(), streamed.date.as_deref()));
+		assert_eq!(vec![Some("http://example.com/stream".to_string()), None],
+		           streamed.skipped.iter().map(|s| s.location.clone()).collect::<Vec<_>>());
+		
+		/* Same errors as parsing the whole document */
+		let not_playlist = dir.write_file("feed.xspf", b"<rss version=\"2.0\"><channel><title>Feed</title></channel></rss>");
+		let truncated = dir.write_file("truncated.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>file:///music/20170403/v01-tranq");
+		let doctype = dir.write_file("doctype.xspf", large_playlist_fixture(10).replacen("<trackList>", "<trackList><!DOCTYPE x>", 1).as_bytes());
+		let stream = |path: &Path| parse_xspf_streaming(path.to_str().unwrap(), &ParseOptions::default(), |_| {});
+		match stream(&not_playlist) {
+			Err(XspfError::NotAPlaylist) => {},
+			other => panic!("expected NotAPlaylist, got {:?}", other.map(|x| x.len()))
+		}
+		match stream(&truncated) {
+			Err(XspfError::XmlParseError(_)) => {},
+			other => panic!("expected an XmlParseError, got {:?}", other.map(|x| x.len()))
+		}
+		match stream(&doctype) {
+			Err(XspfError::SecurityError(ref e)) => assert!(e.contains("DOCTYPE"), "{}", e),
+			other => panic!("expected a SecurityError, got {:?}", other.map(|x| x.len()))
+		}
+		match stream(&dir.path().join("nonexistent.xspf")) {
+			Err(XspfError::IoError(ref e)) => assert_eq!(io::ErrorKind::NotFound, e.kind()),
+			other => panic!("expected an IoError, got {:?}", other.map(|x| x.len()))
+		}
+	}
+	
+	/* Playlists get read the same whether their elements are in the XSPF namespace or not, and whatever prefix is used for it */
+	#[test]
+	fn test_namespaces()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-namespaces");
+		let fixtures = [
+			dir.write_file("plain.xspf", b"<playlist version=\"1\"><title>Sessions</title><trackList>\
+				<track><location>file:///music/20170403/v01-tranquil.mp3</location><duration>1000</duration><title>Tranquil</title></track>\
+				<track><location>file:///music/20170404/v02-moose.mp3</location></track>\
+				</trackList></playlist>"),
+			dir.write_file("default.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\" version=\"1\"><title>Sessions</title><trackList>\
+				<track><location>file:///music/20170403/v01-tranquil.mp3</location><duration>1000</duration><title>Tranquil</title></track>\
+				<track><location>file:///music/20170404/v02-moose.mp3</location></track>\
+				</trackList></playlist>"),
+			dir.write_file("prefixed.xspf", b"<ns0:playlist xmlns:ns0=\"http://xspf.org/ns/0/\" version=\"1\"><ns0:title>Sessions</ns0:title><ns0:trackList>\
+				<ns0:track><ns0:location>file:///music/20170403/v01-tranquil.mp3</ns0:location><ns0:duration>1000</ns0:duration>\
+				<ns0:title>Tranquil</ns0:title></ns0:track>\
+				<ns0:track><ns0:location>file:///music/20170404/v02-moose.mp3</ns0:location></ns0:track>\
+				</ns0:trackList></ns0:playlist>"),
+		];
+		
+		let expected = parse_xspf(fixtures[0].to_str().unwrap(), &ParseOptions::default()).unwrap();
+		assert_eq!(2, expected.len());
+		assert_eq!((Some(TrackDuration(1000)), Some("Tranquil")), (expected.tracks[0].duration, expected.tracks[0].title.as_deref()));
+		
+		for path in fixtures.iter() {
+			let path = path.to_str().unwrap();
+			let xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+			let mut tracks: Vec<Track> = Vec::new();
+			let streamed = parse_xspf_streaming(path, &ParseOptions::default(), |track| tracks.push(track)).unwrap();
+			
+			for &(found, found_tracks) in [(&xspf, &xspf.tracks), (&streamed, &tracks)].iter() {
+				assert_eq!(serde_json::to_value(&expected.tracks).unwrap(), serde_json::to_value(found_tracks).unwrap(), "{}", path);
+				assert!(found.title.as_ref().unwrap().starts_with("Sessions - "), "{}", path);
+				assert!(found.diagnostics.is_empty(), "{}", path);
+			}
+		}
+	}
+	
+	/* Elements from other vocabularies don't get mistaken for ours, even when they share a name */
+	#[test]
+	fn test_foreign_namespaces()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-foreign-namespaces");
+		let path = dir.write_file("foreign.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\
+			<dc:title>Not the title</dc:title><trackList>\
+			<track><dc:location>file:///elsewhere/v00-decoy.mp3</dc:location><location>file:///music/20170403/v01-tranquil.mp3</location>\
+			  <dc:title>Not this either</dc:title><title xmlns=\"http://example.com/other\">Nor this</title></track>\
+			</trackList></playlist>");
+		let path = path.to_str().unwrap();
+		
+		let xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+		let mut tracks: Vec<Track> = Vec::new();
+		let streamed = parse_xspf_streaming(path, &ParseOptions::default(), |track| tracks.push(track)).unwrap();
+		
+		for &(found, found_tracks) in [(&xspf, &xspf.tracks), (&streamed, &tracks)].iter() {
+			assert_eq!(None, found.title);
+			assert_eq!(1, found_tracks.len());
+			assert_eq!(Path::new("music/20170403/v01-tranquil.mp3"), found_tracks[0].file_path());
+			assert_eq!(None, found_tracks[0].title);
+		}
+		
+		/* Playlists in some other namespace still get read, but get flagged */
+		let other = dir.write_file("other.xspf", b"<playlist xmlns=\"http://example.com/not-xspf\"><trackList>\
+			<track><location>file:///music/20170403/v01-tranquil.mp3</location></track>\
+			</trackList></playlist>");
+		let other = other.to_str().unwrap();
+		let xspf = parse_xspf(other, &ParseOptions::default()).unwrap();
+		let streamed = parse_xspf_streaming(other, &ParseOptions::default(), |_| {}).unwrap();
+		assert_eq!(1, xspf.len());
+		for found in [&xspf, &streamed].iter() {
+			assert_eq!(1, found.diagnostics.len());
+			assert_eq!(NAMESPACE_CATEGORY, found.diagnostics.entries[0].category);
+			assert_eq!(Severity::Warning, found.diagnostics.entries[0].severity);
+			assert!(found.diagnostics.entries[0].message.contains("http://example.com/not-xspf"));
+		}
+	}
+	
+	/* A <playlist> without any <trackList> gets flagged, instead of passing as an empty playlist */
+	#[test]
+	fn test_no_tracklist()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-no-tracklist");
+		let missing = dir.write_file("missing.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><title>Sessions</title>\
+			<track><location>file:///music/20170403/v01-tranquil.mp3</location></track></playlist>");
+		let mismatched = dir.write_file("mismatched.xspf", b"<ns0:playlist xmlns:ns0=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>file:///music/20170403/v01-tranquil.mp3</location></track></trackList></ns0:playlist>");
+		let empty = dir.write_file("empty.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList/></playlist>");
+		
+		for path in [missing, mismatched] {
+			let path = path.to_str().unwrap();
+			let xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+			let streamed = parse_xspf_streaming(path, &ParseOptions::default(), |_| {}).unwrap();
+			for found in [&xspf, &streamed].iter() {
+				assert_eq!(vec![(NO_TRACKLIST_CATEGORY, Severity::Error)],
+				           found.diagnostics.entries.iter().map(|d| (d.category.as_str(), d.severity)).collect::<Vec<_>>(), "{}", path);
+			}
+			assert!(xspf.is_empty());
+		}
+		
+		/* ... though an empty one is fine */
+		let xspf = parse_xspf(empty.to_str().unwrap(), &ParseOptions::default()).unwrap();
+		assert!(xspf.is_empty());
+		assert!(xspf.diagnostics.is_empty());
+	}
+	
+	/* Tracks' original positions count every <track> entry in the playlist, across all its <trackList> sections */
+	#[test]
+	fn test_original_index_across_tracklists()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-original-index");
+		let path = dir.write_file("split.xspf", b"<playlist xmlns=\"http://xspf.org/ns/0/\"><trackList>\
+			<track><location>file:///music/20170403/v01-tranquil.mp3</location></track>\
+			<track><location>http://example.com/stream</location></track>\
+			<track><location>file:///music/20170403/v02-moose.mp3</location></track>\
+			</trackList><trackList>\
+			<track><location>file:///music/20170404/v01-dawn.mp3</location></track>\
+			<track/>\
+			<track><location>file:///music/20170404/v02-dusk.mp3</location></track>\
+			</trackList></playlist>");
+		let path = path.to_str().unwrap();
+		
+		let xspf = parse_xspf(path, &ParseOptions::default()).unwrap();
+		assert_eq!(vec![0, 2, 3, 5], xspf.tracks.iter().map(|t| t.original_index).collect::<Vec<_>>());
+		assert_eq!(2, xspf.skipped.len());
+		
+		let mut tracks: Vec<Track> = Vec::new();
+		parse_xspf_streaming(path, &ParseOptions::default(), |track| tracks.push(track)).unwrap();
+		assert_eq!(vec![0, 2, 3, 5], tracks.iter().map(|t| t.original_index).collect::<Vec<_>>());
+	}
+	
+	/* Suspicious documents get refused whichever way they're read */
+	#[test]
+	fn test_io_strategies_refuse_doctype()
+	{
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-io-doctype");
+		let xml = large_playlist_fixture(500).replacen("<trackList>", "<trackList><!ENTITY xxe SYSTEM \"file:///etc/passwd\">", 1);
+		let path = dir.write_file("doctype.xspf", xml.as_bytes());
+		let path = path.to_str().unwrap();
+		
+		for io_strategy in available_io_strategies() {
+			match parse_xspf(path, &ParseOptions { io_strategy: io_strategy, ..ParseOptions::default() }) {
+				Err(XspfError::SecurityError(ref e)) => assert!(e.contains("ENTITY") && e.contains("line 4"), "{}", e),
+				ref other => panic!("Expected security error from {:?}, got {:?}", io_strategy, other),
+			}
+		}
+	}
+	
+	#[test]
+	fn test_io_strategy_names()
+	{
+		for io_strategy in [IoStrategy::String, IoStrategy::Buffered, IoStrategy::Mmap] {
+			assert_eq!(Ok(io_strategy), io_strategy.name().parse());
+		}
+		assert!("memory".parse::<IoStrategy>().is_err());
+		
+		if !cfg!(feature = "mmap") {
+			match parse_xspf("missing.xspf", &ParseOptions { io_strategy: IoStrategy::Mmap, ..ParseOptions::default() }) {
+				Err(XspfError::IoError(ref e)) => assert!(e.to_string().contains("'mmap' feature"), "{}", e),
+				ref other => panic!("Expected I/O error, got {:?}", other),
+			}
+		}
+	}
+	
+	/* The playlist's JSON matches its schema (whatever's known about the tracks) */
+	#[test]
+	fn test_playlist_schema()
+	{
+		use json_schema::{assert_matches_schema, OutputKind};
+		use serde_json;
+		
+		let root: Element = "<playlist xmlns=\"http://xspf.org/ns/0/\"><title>Sessions</title><creator>Me</creator><trackList>\
+		                     <track><location>file:///music/20170403/v01b-tranquil.mp3</location><title>Tranquil</title><duration>61000</duration></track>\
+		                     <track><location>file:///music/misc/intro.wav</location></track>\
+		                     <track><location>file:///music/20170404/p02-moose.flac</location></track>\
+		                     </trackList></playlist>".parse().unwrap();
+		let mut xspf = XspfPlaylist::from_xml_tree(root, "sessions.xspf");
+		xspf.tracks[2].source_playlist = Some("april.xspf".to_string());
+		xspf.tracks[2].rating = Some(5);
+		xspf.tracks[2].properties.insert("mood".to_string(), "calm".to_string());
+		
+		let json = serde_json::to_value(&xspf).unwrap();
+		assert_eq!(json!({ "Unknown": "wav" }), json["tracks"][1]["info"]["extn"]);
+		assert_matches_schema(OutputKind::RawPlaylist, &json);
+		
+		assert_matches_schema(OutputKind::RawPlaylist, &serde_json::to_value(make_playlist(&[])).unwrap());
+	}
+	
+	/* Rough comparison of how long each way of reading takes (run with "cargo test --release -- --ignored --nocapture") */
+	#[test]
+	#[ignore]
+	fn bench_io_strategies()
+	{
+		use std::time::Instant;
+		use test_utils::TempDir;
+		
+		let dir = TempDir::new("xspf-io-bench");
+		let xml = large_playlist_fixture(50000);
+		let path = dir.write_file("bench.xspf", xml.as_bytes());
+		let path = path.to_str().unwrap();
+		
+		println!("\nParsing {0} tracks ({1:.1} MB):", 50000, xml.len() as f64 / (1024.0 * 1024.0));
+		for io_strategy in available_io_strategies() {
+			let options = ParseOptions { io_strategy: io_strategy, ..ParseOptions::default() };
+			let runs = 3;
+			let start_time = Instant::now();
+			for _ in 0 .. runs {
+				assert_eq!(50000, parse_xspf(path, &options).unwrap().len());
+			}
+			let elapsed = start_time.elapsed();
+			println!("   {0:<10} {1:>10.1} ms/run", io_strategy.name(),
+			         (elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 * 1e-6) / runs as f64);
+		}
+	}
+}